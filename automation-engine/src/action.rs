@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use log::{error, info};
+use paho_mqtt::{AsyncClient, Message};
+use serde::Deserialize;
+
+use crate::webhook::WebhookConfig;
+
+/// Something done when a rule runs.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ActionConfig {
+    #[serde(flatten)]
+    pub kind: ActionKind,
+    /// Time to wait after the previous action.
+    #[serde(default)]
+    pub delay_secs: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ActionKind {
+    Publish {
+        topic: String,
+        /// `{{payload}}` and `{{topic}}` are replaced by the payload and topic of the trigger.
+        payload: String,
+        #[serde(default)]
+        retain: bool,
+    },
+    Webhook {
+        webhook: WebhookConfig,
+    },
+}
+
+/// The message that triggered a rule.
+#[derive(Debug, Clone, Default)]
+pub struct TriggerContext {
+    pub topic: String,
+    pub payload: String,
+}
+
+pub fn render(template: &str, context: &TriggerContext) -> String {
+    template.replace("{{payload}}", context.payload.trim()).replace("{{topic}}", &context.topic)
+}
+
+/// Runs the actions of a rule in order, waiting for their delays.
+pub async fn run_actions(client: AsyncClient, http: reqwest::Client, rule: String, actions: Vec<ActionConfig>, context: TriggerContext) {
+    for action in actions {
+        if action.delay_secs > 0 {
+            tokio::time::sleep(Duration::from_secs(action.delay_secs)).await;
+        }
+
+        match action.kind {
+            ActionKind::Publish { topic, payload, retain } => {
+                let payload = render(&payload, &context);
+                info!("[{}] Publishing '{}' to {}", rule, payload, topic);
+
+                let message = match retain {
+                    true => Message::new_retained(topic.as_str(), payload, 1),
+                    false => Message::new(topic.as_str(), payload, 1),
+                };
+
+                if let Err(e) = client.publish(message).await {
+                    error!("[{}] Failed to publish to {}: {}", rule, topic, e);
+                }
+            }
+            ActionKind::Webhook { webhook } => {
+                info!("[{}] Sending webhook to {}", rule, webhook.url);
+
+                if let Err(e) = webhook.send(&http, &context).await {
+                    error!("[{}] Failed to send webhook to {}: {}", rule, webhook.url, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action::{render, ActionConfig, ActionKind, TriggerContext};
+
+    #[test]
+    fn test_render() {
+        let context = TriggerContext { topic: "smart-home-system/zigbee/desk/brightness".into(), payload: " 42\n".into() };
+
+        assert_eq!(render("{{payload}}", &context), "42");
+        assert_eq!(render("{\"source\":\"{{topic}}\"}", &context), "{\"source\":\"smart-home-system/zigbee/desk/brightness\"}");
+        assert_eq!(render("on", &context), "on");
+    }
+
+    #[test]
+    fn test_action_kinds() {
+        let actions: Vec<ActionConfig> = serde_yaml::from_str(r#"
+            - topic: smart-home-system/hue/hallway/power/set
+              payload: "on"
+            - webhook:
+                url: https://ntfy.sh/home-alerts
+                body: "{{payload}}"
+              delay_secs: 5
+        "#).unwrap();
+
+        assert!(matches!(&actions[0].kind, ActionKind::Publish { topic, retain: false, .. } if topic == "smart-home-system/hue/hallway/power/set"));
+        assert!(matches!(&actions[1].kind, ActionKind::Webhook { webhook } if webhook.url == "https://ntfy.sh/home-alerts"));
+        assert_eq!(actions[1].delay_secs, 5);
+    }
+}
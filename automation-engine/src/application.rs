@@ -0,0 +1,833 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Timelike, Utc};
+use domain_state::error;
+use log::{debug, error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+use rhai::AST;
+use tokio::task::JoinHandle;
+
+use crate::action::{run_actions, TriggerContext};
+use crate::audit::{self, Audit, AuditEvent};
+use crate::auto_off::AutoOff;
+use crate::{MQTT_AUDIT_TOPIC, MQTT_AUTO_OFF_TOPIC_PREFIX, MQTT_CIRCADIAN_TOPIC_PREFIX, MQTT_ERROR_TOPIC, MQTT_GROUP_TOPIC_PREFIX, MQTT_PRESENCE_TOPIC_PREFIX,
+    MQTT_VACATION_TOPIC_PREFIX, MQTT_WAKE_UP_TOPIC_PREFIX, MQTT_SCENE_TOPIC_PREFIX, MQTT_SCHEDULE_TOPIC_PREFIX,
+    MQTT_TIMER_TOPIC_PREFIX};
+use crate::circadian::Circadian;
+use crate::config::{Config, RuleConfig, RunMode, ScheduleConfig};
+use crate::group::{aggregate, GroupConfig};
+use crate::history::{self, History};
+use crate::presence::{Presence, PresenceEvent};
+use crate::scene::{SceneConfig, SceneValue};
+use crate::script::ScriptEngine;
+use crate::state::StateStore;
+use crate::sun::{sun_elevation, Location};
+use crate::timer::Timers;
+use crate::vacation::VacationConfig;
+use crate::wake_up::WakeUpConfig;
+use crate::webhook;
+
+struct Rule {
+    config: RuleConfig,
+    condition_script: Option<AST>,
+    script: Option<AST>,
+    last_fired: Option<Instant>,
+    running: Option<JoinHandle<()>>,
+}
+
+impl Rule {
+    fn is_running(&self) -> bool {
+        self.running.as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+
+    fn in_cooldown(&self, now: Instant) -> bool {
+        self.last_fired.is_some_and(|last_fired| now.duration_since(last_fired) < Duration::from_secs(self.config.cooldown_secs))
+    }
+}
+
+struct Schedule {
+    config: ScheduleConfig,
+    enabled: bool,
+}
+
+struct WakeUp {
+    config: WakeUpConfig,
+    enabled: bool,
+    /// When the running fade ends.
+    running_until: Option<Instant>,
+}
+
+/// A `<prefix>/<name>/enabled[/<action>]` topic, to enable or disable a schedule, wake-up routine or circadian lighting.
+struct EnabledTopic<'a> {
+    name: &'a str,
+    action: Option<&'a str>,
+}
+
+/// What a message on an enabled topic asks for.
+enum EnabledRequest {
+    Get,
+    Set(bool),
+    /// The retained state from before a restart.
+    Restore(bool),
+}
+
+impl<'a> EnabledTopic<'a> {
+    fn parse(prefix: &str, topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(prefix)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let name = parts.next()?;
+        if parts.next()? != "enabled" {
+            return None;
+        }
+        let action = parts.next();
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Self { name, action }),
+        }
+    }
+
+    fn request(&self, client: &AsyncClient, message: &Message) -> Option<EnabledRequest> {
+        if self.action == Some("get") {
+            return Some(EnabledRequest::Get);
+        }
+
+        let payload = message.payload_str();
+        let enabled = match payload.trim().to_ascii_lowercase().as_str() {
+            "on" => true,
+            "off" => false,
+            _ => {
+                error::publish_invalid_payload(client, MQTT_ERROR_TOPIC, message, "Expected on or off");
+                return None;
+            }
+        };
+
+        match self.action {
+            None => Some(EnabledRequest::Restore(enabled)),
+            Some("set") => Some(EnabledRequest::Set(enabled)),
+            _ => {
+                error!("Received message for unknown topic: {}", message.topic());
+                None
+            }
+        }
+    }
+}
+
+struct Scene {
+    config: SceneConfig,
+    /// The values from before the scene was activated.
+    snapshot: Option<Vec<SceneValue>>,
+}
+
+/// A `smart-home-system/scene/<name>/<action>` topic.
+struct SceneTopic<'a> {
+    name: &'a str,
+    action: &'a str,
+}
+
+impl<'a> SceneTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_SCENE_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let (name, action) = rest.split_once('/')?;
+
+        match action.contains('/') {
+            true => None,
+            false => Some(Self { name, action }),
+        }
+    }
+}
+
+/// A `smart-home-system/automation/auto-off/<name>/<action>` topic.
+struct AutoOffTopic<'a> {
+    name: &'a str,
+    action: &'a str,
+}
+
+impl<'a> AutoOffTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_AUTO_OFF_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let (name, action) = rest.split_once('/')?;
+        Some(Self { name, action })
+    }
+}
+
+/// A `smart-home-system/automation/timer/<name>/<action>` topic.
+struct TimerTopic<'a> {
+    name: &'a str,
+    action: &'a str,
+}
+
+impl<'a> TimerTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TIMER_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let (name, action) = rest.split_once('/')?;
+        Some(Self { name, action })
+    }
+}
+
+/// A `smart-home-system/group/<name>/<attribute>/<action>` topic.
+struct GroupTopic<'a> {
+    name: &'a str,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> GroupTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_GROUP_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { name: parts.next()?, attribute: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+pub struct Application {
+    client: AsyncClient,
+    /// Sends the webhooks of the actions.
+    http: reqwest::Client,
+    rules: Vec<Rule>,
+    schedules: Vec<Schedule>,
+    location: Option<Location>,
+    scenes: Vec<Scene>,
+    /// The last payload of every topic received.
+    states: StateStore,
+    scripts: ScriptEngine,
+    /// The last minute the schedules were checked in.
+    last_minute: Option<DateTime<Local>>,
+    circadian: Option<Circadian>,
+    last_circadian_update: Option<Instant>,
+    auto_off: AutoOff,
+    timers: Timers,
+    presence: Option<Presence>,
+    wake_ups: Vec<WakeUp>,
+    vacation: Option<VacationConfig>,
+    vacation_enabled: bool,
+    /// When the vacation events happen today.
+    vacation_plan: Option<(NaiveDate, Vec<NaiveDateTime>)>,
+    groups: Vec<GroupConfig>,
+    /// The last published state of every group attribute.
+    group_states: HashMap<String, String>,
+    history: Option<History>,
+    audit: Option<Audit>,
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, config: Config) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(webhook::REQUEST_TIMEOUT)
+            .build()?;
+
+        let states = StateStore::default();
+        let scripts = ScriptEngine::new(states.clone());
+
+        let compile = |script: &Option<String>| script.as_ref().map(|script| scripts.compile(script).expect("Scripts are validated when loading the config"));
+        let rules = config.rules.into_iter()
+            .map(|config| Rule { condition_script: compile(&config.condition_script), script: compile(&config.script), config, last_fired: None, running: None })
+            .collect();
+        let schedules = config.schedules.into_iter().map(|config| Schedule { enabled: config.enabled, config }).collect();
+        let scenes = config.scenes.into_iter().map(|config| Scene { config, snapshot: None }).collect();
+
+        let circadian = config.circadian.map(Circadian::new);
+
+        Ok(Self {
+            client,
+            http,
+            rules,
+            schedules,
+            location: config.location,
+            scenes,
+            states,
+            scripts,
+            last_minute: None,
+            circadian,
+            last_circadian_update: None,
+            auto_off: AutoOff::new(config.auto_off),
+            timers: Timers::new(config.timers),
+            presence: config.presence.map(Presence::new),
+            wake_ups: config.wake_up.into_iter().map(|config| WakeUp { enabled: config.enabled, config, running_until: None }).collect(),
+            vacation: config.vacation,
+            vacation_enabled: false,
+            vacation_plan: None,
+            groups: config.groups,
+            group_states: HashMap::new(),
+            history: config.history.map(History::new),
+            audit: config.audit.map(Audit::new),
+        })
+    }
+
+    /// Runs the schedules matching the current minute, updates circadian lighting, turns off
+    /// the devices whose auto off timer ran out, counts down the timers and writes the recorded history.
+    pub fn tick(&mut self, now: DateTime<Local>) {
+        self.update_circadian();
+        self.run_schedules(now);
+
+        let messages = self.auto_off.expired(Instant::now());
+        self.publish_messages(messages);
+
+        self.update_timers();
+        self.update_presence();
+        self.write_history();
+    }
+
+    fn log_audit_event(&self, event: AuditEvent) {
+        let line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("[{}] Failed to serialize the audit event: {}", event.topic, e);
+                return;
+            }
+        };
+
+        if let Some(path) = self.audit.as_ref().and_then(|audit| audit.file()) {
+            if let Err(e) = audit::append(path, &line) {
+                error!("[{}] Failed to log the audit event: {:#}", event.topic, e);
+            }
+        }
+
+        self.client.publish(Message::new(MQTT_AUDIT_TOPIC, line, 1));
+    }
+
+    fn write_history(&mut self) {
+        let Some(history) = &mut self.history else { return };
+        let Some(lines) = history.take(Instant::now()) else { return };
+
+        let http = self.http.clone();
+        let sink = history.sink().clone();
+        tokio::spawn(async move {
+            let count = lines.len();
+            if let Err(e) = history::write(&http, &sink, lines).await {
+                error!("Failed to write {} states to the history: {:#}", count, e);
+            }
+        });
+    }
+
+    fn update_timers(&mut self) {
+        let (messages, finished) = self.timers.tick(Instant::now());
+        self.publish_timer_messages(messages);
+
+        for name in finished {
+            let actions = self.timers.on_finished(&name).to_vec();
+            if !actions.is_empty() {
+                let topic = format!("{}/{}/running", MQTT_TIMER_TOPIC_PREFIX, name);
+                let context = TriggerContext { topic, payload: "off".into() };
+                tokio::spawn(run_actions(self.client.clone(), self.http.clone(), format!("timer {}", name), actions, context));
+            }
+        }
+    }
+
+    fn publish_timer_messages(&self, messages: Vec<(String, String)>) {
+        for (topic, payload) in messages {
+            debug!("Publishing '{}' to {}", payload, topic);
+            self.client.publish(Message::new_retained(topic, payload, 1));
+        }
+    }
+
+    fn handle_timer_message(&mut self, topic: TimerTopic, message: &Message) {
+        let Some(running) = self.timers.is_running(topic.name) else {
+            error!("[{}] Unknown timer {}", message.topic(), topic.name);
+            return;
+        };
+
+        let payload = message.payload_str();
+        let payload = payload.trim();
+
+        // Our own state, or the retained state from before a restart when the timer is no longer running
+        if topic.action == "running" {
+            if payload != if running { "on" } else { "off" } {
+                let messages = self.timers.state(topic.name, Instant::now()).unwrap_or_default();
+                self.publish_timer_messages(messages);
+            }
+            return;
+        }
+
+        if topic.action == "remaining" {
+            return;
+        }
+
+        // Commands from before a restart
+        if message.retained() {
+            return;
+        }
+
+        let now = Instant::now();
+
+        let messages = match (topic.action, payload) {
+            ("start", "") | ("running/set", "on") => self.timers.start(topic.name, None, now),
+            ("start", minutes) => match minutes.parse::<u64>() {
+                Ok(minutes) if minutes > 0 => self.timers.start(topic.name, Some(minutes), now),
+                _ => return error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a number of minutes greater than 0"),
+            },
+            ("cancel", _) | ("running/set", "off") => self.timers.cancel(topic.name),
+            ("running/set", _) => return error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected on or off"),
+            ("running/get" | "remaining/get", _) => self.timers.state(topic.name, now),
+            _ => return error!("Received message for unknown topic: {}", message.topic()),
+        };
+
+        self.publish_timer_messages(messages.unwrap_or_default());
+    }
+
+    fn update_presence(&mut self) {
+        let Some(presence) = &mut self.presence else { return };
+        let events = presence.tick(Instant::now());
+
+        for event in &events {
+            let (topic, payload) = match event {
+                PresenceEvent::Arrived(name) => (format!("{}/{}", MQTT_PRESENCE_TOPIC_PREFIX, name), "home"),
+                PresenceEvent::Departed(name) => (format!("{}/{}", MQTT_PRESENCE_TOPIC_PREFIX, name), "away"),
+                PresenceEvent::FirstArrival => (format!("{}/anyone", MQTT_PRESENCE_TOPIC_PREFIX), "on"),
+                PresenceEvent::EveryoneLeft => (format!("{}/anyone", MQTT_PRESENCE_TOPIC_PREFIX), "off"),
+            };
+
+            info!("[{}] Presence changed to {}", topic, payload);
+            self.client.publish(Message::new_retained(topic.as_str(), payload, 1));
+
+            let actions = self.presence.as_ref().map(|presence| presence.actions(event).to_vec()).unwrap_or_default();
+            if !actions.is_empty() {
+                let context = TriggerContext { topic, payload: payload.to_string() };
+                tokio::spawn(run_actions(self.client.clone(), self.http.clone(), format!("presence {:?}", event), actions, context));
+            }
+        }
+    }
+
+    fn update_circadian(&mut self) {
+        let Some(circadian) = &self.circadian else { return };
+
+        let interval = Duration::from_secs(circadian.interval_secs());
+        if self.last_circadian_update.is_some_and(|last_update| last_update.elapsed() < interval) {
+            return;
+        }
+        self.last_circadian_update = Some(Instant::now());
+
+        let elevation = self.sun_elevation();
+        let messages = self.circadian.as_mut().map(|circadian| circadian.update(elevation)).unwrap_or_default();
+        self.publish_messages(messages);
+    }
+
+    /// Each minute is only checked once.
+    fn run_schedules(&mut self, now: DateTime<Local>) {
+        let Some(minute) = now.with_second(0).and_then(|time| time.with_nanosecond(0)) else { return };
+
+        if self.last_minute == Some(minute) {
+            return;
+        }
+        self.last_minute = Some(minute);
+
+        for schedule in &self.schedules {
+            if !schedule.enabled || !schedule.config.time.matches(&minute, self.location) {
+                continue;
+            }
+
+            info!("Running schedule {}", schedule.config.name);
+
+            tokio::spawn(run_actions(
+                self.client.clone(),
+                self.http.clone(),
+                schedule.config.name.clone(),
+                schedule.config.actions.clone(),
+                TriggerContext::default(),
+            ));
+        }
+
+        for wake_up in &mut self.wake_ups {
+            if !wake_up.enabled || !wake_up.config.time.matches(&minute, self.location) {
+                continue;
+            }
+
+            info!("Starting wake-up routine {}", wake_up.config.name);
+
+            let (topic, payload) = wake_up.config.start_message();
+            self.client.publish(Message::new(topic, payload, 1));
+            wake_up.running_until = Some(Instant::now() + Duration::from_secs(wake_up.config.duration_minutes * 60));
+        }
+
+        self.run_vacation(minute.naive_local());
+    }
+
+    fn run_vacation(&mut self, minute: NaiveDateTime) {
+        let Some(vacation) = &self.vacation else { return };
+        if !self.vacation_enabled {
+            return;
+        }
+
+        let date = minute.date();
+        if self.vacation_plan.as_ref().map(|(plan_date, _)| *plan_date) != Some(date) {
+            let plan = vacation.plan(date, &mut rand::thread_rng());
+            debug!("Vacation mode plan for {}: {:?}", date, plan);
+            self.vacation_plan = Some((date, plan));
+        }
+
+        let Some((_, plan)) = &self.vacation_plan else { return };
+
+        for (event, _) in vacation.events.iter().zip(plan).filter(|(_, time)| **time == minute) {
+            info!("Vacation mode publishing '{}' to {}", event.payload, event.topic);
+            self.client.publish(Message::new(event.topic.as_str(), event.payload.as_str(), 1));
+        }
+    }
+
+    fn handle_vacation_message(&mut self, topic: EnabledTopic, message: &Message) {
+        if self.vacation.is_none() {
+            error!("[{}] Vacation mode is not configured", message.topic());
+            return;
+        }
+
+        match topic.request(&self.client, message) {
+            Some(EnabledRequest::Get) => self.publish_enabled(MQTT_VACATION_TOPIC_PREFIX, topic.name, self.vacation_enabled),
+            Some(EnabledRequest::Set(enabled)) => {
+                info!("[{}] Vacation mode is now {}", message.topic(), if enabled { "enabled" } else { "disabled" });
+                self.vacation_enabled = enabled;
+                self.publish_enabled(MQTT_VACATION_TOPIC_PREFIX, topic.name, enabled);
+            }
+            Some(EnabledRequest::Restore(enabled)) => self.vacation_enabled = enabled,
+            None => {}
+        }
+    }
+
+    fn handle_wake_up_message(&mut self, name: &str, action: &str, message: &Message) {
+        let Some(index) = self.wake_ups.iter().position(|wake_up| wake_up.config.name == name) else {
+            error!("[{}] Unknown wake-up routine {}", message.topic(), name);
+            return;
+        };
+
+        if action == "cancel" {
+            let wake_up = &mut self.wake_ups[index];
+
+            if wake_up.running_until.take().is_some_and(|running_until| running_until > Instant::now()) {
+                info!("[{}] Cancelling wake-up routine {}", message.topic(), name);
+                let (topic, payload) = wake_up.config.cancel_message();
+                self.client.publish(Message::new(topic, payload, 1));
+            } else {
+                warn!("[{}] Wake-up routine {} is not running", message.topic(), name);
+            }
+            return;
+        }
+
+        let Some(topic) = EnabledTopic::parse(MQTT_WAKE_UP_TOPIC_PREFIX, message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        match topic.request(&self.client, message) {
+            Some(EnabledRequest::Get) => self.publish_enabled(MQTT_WAKE_UP_TOPIC_PREFIX, name, self.wake_ups[index].enabled),
+            Some(EnabledRequest::Set(enabled)) => {
+                info!("[{}] Wake-up routine {} is now {}", message.topic(), name, if enabled { "enabled" } else { "disabled" });
+                self.wake_ups[index].enabled = enabled;
+                self.publish_enabled(MQTT_WAKE_UP_TOPIC_PREFIX, name, enabled);
+            }
+            Some(EnabledRequest::Restore(enabled)) => self.wake_ups[index].enabled = enabled,
+            None => {}
+        }
+    }
+
+    fn publish_enabled(&self, prefix: &str, name: &str, enabled: bool) {
+        let topic = format!("{}/{}/enabled", prefix, name);
+        let payload = if enabled { "on" } else { "off" };
+        self.client.publish(Message::new_retained(topic, payload, 1));
+    }
+
+    fn handle_schedule_message(&mut self, topic: EnabledTopic, message: &Message) {
+        let Some(index) = self.schedules.iter().position(|schedule| schedule.config.name == topic.name) else {
+            error!("[{}] Unknown schedule {}", message.topic(), topic.name);
+            return;
+        };
+
+        match topic.request(&self.client, message) {
+            Some(EnabledRequest::Get) => self.publish_enabled(MQTT_SCHEDULE_TOPIC_PREFIX, topic.name, self.schedules[index].enabled),
+            Some(EnabledRequest::Set(enabled)) => {
+                info!("[{}] Schedule {} is now {}", message.topic(), topic.name, if enabled { "enabled" } else { "disabled" });
+                self.schedules[index].enabled = enabled;
+                self.publish_enabled(MQTT_SCHEDULE_TOPIC_PREFIX, topic.name, enabled);
+            }
+            Some(EnabledRequest::Restore(enabled)) => self.schedules[index].enabled = enabled,
+            None => {}
+        }
+    }
+
+    fn handle_circadian_message(&mut self, topic: EnabledTopic, message: &Message) {
+        let Some(circadian) = &self.circadian else { return };
+
+        let Some(enabled) = circadian.is_enabled(topic.name) else {
+            error!("[{}] Unknown circadian light {}", message.topic(), topic.name);
+            return;
+        };
+
+        let enabled = match topic.request(&self.client, message) {
+            Some(EnabledRequest::Get) => {
+                self.publish_enabled(MQTT_CIRCADIAN_TOPIC_PREFIX, topic.name, enabled);
+                return;
+            }
+            Some(EnabledRequest::Set(enabled)) => {
+                info!("[{}] Circadian lighting for {} is now {}", message.topic(), topic.name, if enabled { "enabled" } else { "disabled" });
+                self.publish_enabled(MQTT_CIRCADIAN_TOPIC_PREFIX, topic.name, enabled);
+                enabled
+            }
+            Some(EnabledRequest::Restore(enabled)) => enabled,
+            None => return,
+        };
+
+        let elevation = self.sun_elevation();
+        if let Some(messages) = self.circadian.as_mut().and_then(|circadian| circadian.set_enabled(topic.name, enabled, elevation)) {
+            self.publish_messages(messages);
+        }
+    }
+
+    fn sun_elevation(&self) -> f64 {
+        self.location.map_or(0.0, |location| sun_elevation(Utc::now(), location))
+    }
+
+    fn publish_messages(&self, messages: Vec<(String, String)>) {
+        for (topic, payload) in messages {
+            debug!("Publishing '{}' to {}", payload, topic);
+            self.client.publish(Message::new(topic, payload, 1));
+        }
+    }
+
+    fn publish_auto_off_minutes(&self, name: &str, minutes: u64) {
+        let topic = format!("{}/{}/minutes", MQTT_AUTO_OFF_TOPIC_PREFIX, name);
+        self.client.publish(Message::new_retained(topic, minutes.to_string(), 1));
+    }
+
+    fn handle_auto_off_message(&mut self, topic: AutoOffTopic, message: &Message) {
+        let Some(minutes) = self.auto_off.minutes(topic.name) else {
+            error!("[{}] Unknown auto off timer {}", message.topic(), topic.name);
+            return;
+        };
+
+        match topic.action {
+            "minutes/get" => return self.publish_auto_off_minutes(topic.name, minutes),
+            "cancel" => {
+                if self.auto_off.cancel(topic.name) == Some(true) {
+                    info!("[{}] Cancelled auto off timer {} until it's turned on again", message.topic(), topic.name);
+                }
+                return;
+            }
+            "minutes" | "minutes/set" => {}
+            _ => return error!("Received message for unknown topic: {}", message.topic()),
+        }
+
+        let payload = message.payload_str();
+        let Ok(minutes) = payload.trim().parse::<u64>() else {
+            error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a number of minutes");
+            return;
+        };
+
+        self.auto_off.set_minutes(topic.name, minutes, Instant::now());
+
+        // Messages on the state topic are the retained value from before a restart
+        if topic.action == "minutes/set" {
+            info!("[{}] Auto off timer {} is now {} minutes", message.topic(), topic.name, minutes);
+            self.publish_auto_off_minutes(topic.name, minutes);
+        }
+    }
+
+    fn publish_scene_values(&self, scene: &str, values: &[SceneValue]) {
+        for value in values {
+            info!("[{}] Publishing '{}' to {}", scene, value.payload, value.topic);
+            self.client.publish(Message::new(value.topic.as_str(), value.payload.as_str(), 1));
+        }
+    }
+
+    fn handle_scene_message(&mut self, topic: SceneTopic, message: &Message) {
+        let Some(index) = self.scenes.iter().position(|scene| scene.config.name == topic.name) else {
+            error!("[{}] Unknown scene {}", message.topic(), topic.name);
+            return;
+        };
+
+        match topic.action {
+            "activate" => {
+                info!("[{}] Activating scene {}", message.topic(), topic.name);
+
+                let scene = &self.scenes[index];
+                // Activating the scene again keeps the values from before the first activation
+                let snapshot = scene.snapshot.clone().unwrap_or_else(|| scene.config.snapshot(&self.states.read()));
+
+                self.publish_scene_values(topic.name, &scene.config.values);
+                self.scenes[index].snapshot = Some(snapshot);
+            }
+            "restore" => {
+                let Some(snapshot) = self.scenes[index].snapshot.take() else {
+                    warn!("[{}] Scene {} has nothing to restore", message.topic(), topic.name);
+                    return;
+                };
+
+                info!("[{}] Restoring the values from before scene {}", message.topic(), topic.name);
+                self.publish_scene_values(topic.name, &snapshot);
+            }
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    fn handle_group_message(&self, topic: GroupTopic, message: &Message) {
+        let Some(group) = self.groups.iter().find(|group| group.name == topic.name) else {
+            error!("[{}] Unknown group {}", message.topic(), topic.name);
+            return;
+        };
+
+        info!("[{}] Forwarding '{}' to the members of group {}", message.topic(), message.payload_str(), topic.name);
+
+        for member_topic in group.fan_out(topic.attribute, topic.action) {
+            self.client.publish(Message::new(member_topic, message.payload(), 1));
+        }
+    }
+
+    /// Publishes the state of the groups `topic` is a member attribute of, when it changed.
+    fn update_groups(&mut self, topic: &str) {
+        for group in &self.groups {
+            let Some(attribute) = group.member_attribute(topic) else { continue };
+
+            let member_topics = group.member_topics(attribute);
+            let states = self.states.read();
+            let values: Vec<&str> = member_topics.iter().filter_map(|topic| states.get(topic).map(String::as_str)).collect();
+            let Some(state) = aggregate(attribute, &values) else { continue };
+
+            let group_topic = format!("{}/{}/{}", MQTT_GROUP_TOPIC_PREFIX, group.name, attribute);
+            if self.group_states.get(&group_topic) != Some(&state) {
+                debug!("[{}] Group {} {} is now {}", topic, group.name, attribute, state);
+                self.client.publish(Message::new_retained(group_topic.as_str(), state.as_str(), 1));
+                self.group_states.insert(group_topic, state);
+            }
+        }
+    }
+
+    pub fn handle_mqtt_message(&mut self, message: &Message) {
+        self.states.set(message.topic(), message.payload_str().trim());
+
+        if let Some(history) = &mut self.history {
+            history.record(message.topic(), &message.payload_str(), message.retained(), SystemTime::now());
+        }
+
+        if let Some(event) = self.audit.as_mut().and_then(|audit| audit.event(message.topic(), &message.payload_str(), message.retained(), Local::now())) {
+            self.log_audit_event(event);
+        }
+
+        self.update_groups(message.topic());
+
+        if let Some(topic) = GroupTopic::parse(message.topic()) {
+            self.handle_group_message(topic, message);
+        }
+
+        if let Some(topic) = EnabledTopic::parse(MQTT_SCHEDULE_TOPIC_PREFIX, message.topic()) {
+            self.handle_schedule_message(topic, message);
+        }
+
+        if let Some(topic) = EnabledTopic::parse(MQTT_CIRCADIAN_TOPIC_PREFIX, message.topic()) {
+            self.handle_circadian_message(topic, message);
+        }
+
+        if let Some(topic) = EnabledTopic::parse(MQTT_VACATION_TOPIC_PREFIX, message.topic()).filter(|topic| topic.name == "vacation") {
+            self.handle_vacation_message(topic, message);
+        }
+
+        if let Some((name, action)) = message.topic().strip_prefix(MQTT_WAKE_UP_TOPIC_PREFIX)
+            .and_then(|rest| rest.strip_prefix('/')?.split_once('/')) {
+            self.handle_wake_up_message(name, action, message);
+        }
+
+        if let Some(topic) = AutoOffTopic::parse(message.topic()) {
+            self.handle_auto_off_message(topic, message);
+        }
+
+        if let Some(topic) = TimerTopic::parse(message.topic()) {
+            self.handle_timer_message(topic, message);
+        }
+
+        self.auto_off.handle_power(message.topic(), &message.payload_str(), Instant::now());
+
+        if let Some(presence) = &mut self.presence {
+            presence.handle_message(message.topic(), &message.payload_str(), Instant::now());
+        }
+
+        if self.circadian.is_some() {
+            let elevation = self.sun_elevation();
+            let messages = self.circadian.as_mut()
+                .map(|circadian| circadian.handle_message(message.topic(), &message.payload_str(), elevation))
+                .unwrap_or_default();
+            self.publish_messages(messages);
+        }
+
+        if let Some(topic) = SceneTopic::parse(message.topic()) {
+            self.handle_scene_message(topic, message);
+        }
+
+        // Retained messages describe the state from before the engine started, not an event.
+        if message.retained() {
+            return;
+        }
+
+        let context = TriggerContext { topic: message.topic().to_string(), payload: message.payload_str().to_string() };
+        let now = Instant::now();
+
+        for rule in &mut self.rules {
+            if !rule.config.trigger.matches(&context.topic, &context.payload) {
+                continue;
+            }
+
+            if let Some(condition) = rule.config.conditions.iter().find(|condition| !condition.holds(&self.states.read())) {
+                debug!("[{}] Condition on {} of rule {} doesn't hold, ignoring trigger", context.topic, condition.topic, rule.config.name);
+                continue;
+            }
+
+            if let Some(script) = &rule.condition_script {
+                match self.scripts.check(script, &context) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!("[{}] Condition script of rule {} returned false, ignoring trigger", context.topic, rule.config.name);
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("[{}] Condition script of rule {} failed: {}", context.topic, rule.config.name, e);
+                        continue;
+                    }
+                }
+            }
+
+            if rule.in_cooldown(now) {
+                debug!("[{}] Rule {} is in cooldown, ignoring trigger", context.topic, rule.config.name);
+                continue;
+            }
+
+            if rule.is_running() {
+                match rule.config.mode {
+                    RunMode::Single => {
+                        debug!("[{}] Rule {} is already running, ignoring trigger", context.topic, rule.config.name);
+                        continue;
+                    }
+                    RunMode::Restart => {
+                        info!("[{}] Restarting rule {}", context.topic, rule.config.name);
+                        if let Some(handle) = rule.running.take() {
+                            handle.abort();
+                        }
+                    }
+                    RunMode::Parallel => {}
+                }
+            }
+
+            info!("[{}] Running rule {}", context.topic, rule.config.name);
+
+            if let Some(script) = &rule.script {
+                match self.scripts.execute(script, &context) {
+                    Ok(messages) => for (topic, payload) in messages {
+                        info!("[{}] Publishing '{}' to {}", rule.config.name, payload, topic);
+                        self.client.publish(Message::new(topic, payload, 1));
+                    },
+                    Err(e) => error!("[{}] Script of rule {} failed: {}", context.topic, rule.config.name, e),
+                }
+            }
+
+            rule.last_fired = Some(now);
+            rule.running = Some(tokio::spawn(run_actions(
+                self.client.clone(),
+                self.http.clone(),
+                rule.config.name.clone(),
+                rule.config.actions.clone(),
+                context.clone(),
+            )));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::MQTT_AUDIT_TOPIC;
+use crate::rule::{is_command, topic_matches};
+
+/// Logs the commands and state changes of these topics, to find out what changed a device and when.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuditConfig {
+    /// Topic filters, may contain the `+` and `#` wildcards.
+    pub topics: Vec<String>,
+    /// Also appends the events to this file, one json object per line.
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditKind {
+    Command,
+    State,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    pub timestamp: String,
+    pub kind: AuditKind,
+    pub topic: String,
+    /// The previous payload of a state, if it was known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    pub new: String,
+}
+
+pub struct Audit {
+    config: AuditConfig,
+    /// The last payload of every state topic, to only log changes.
+    states: HashMap<String, String>,
+}
+
+impl Audit {
+    pub fn new(config: AuditConfig) -> Self {
+        Self { config, states: HashMap::new() }
+    }
+
+    pub fn file(&self) -> Option<&Path> {
+        self.config.file.as_deref()
+    }
+
+    /// The event of a message, if it's a command or changes a state on one of the topics.
+    /// Retained messages only set the starting state, as they happened before the engine started.
+    pub fn event(&mut self, topic: &str, payload: &str, retained: bool, timestamp: DateTime<Local>) -> Option<AuditEvent> {
+        if topic == MQTT_AUDIT_TOPIC || !self.config.topics.iter().any(|filter| topic_matches(filter, topic)) {
+            return None;
+        }
+
+        let payload = payload.trim();
+        let event = |kind, old| AuditEvent { timestamp: timestamp.to_rfc3339(), kind, topic: topic.to_string(), old, new: payload.to_string() };
+
+        if is_command(topic) {
+            return (!retained).then(|| event(AuditKind::Command, None));
+        }
+
+        let old = self.states.insert(topic.to_string(), payload.to_string());
+        if retained || old.as_deref() == Some(payload) {
+            return None;
+        }
+
+        Some(event(AuditKind::State, old))
+    }
+}
+
+pub fn append(path: &Path, line: &str) -> anyhow::Result<()> {
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)
+        .context(format!("Failed to open {:?}", path))?;
+    writeln!(file, "{}", line).context(format!("Failed to write to {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Local, TimeZone};
+
+    use crate::audit::{Audit, AuditConfig, AuditKind};
+
+    #[test]
+    fn test_events() {
+        let mut audit = Audit::new(AuditConfig { topics: vec!["smart-home-system/hue/#".into()], file: None });
+        let now = Local.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap();
+
+        assert_eq!(audit.event("smart-home-system/hue/living-room/power", "off", true, now), None);
+        assert_eq!(audit.event("smart-home-system/hue/living-room/power", "off", false, now), None);
+        assert_eq!(audit.event("smart-home-system/yeelight/power/set", "on", false, now), None);
+
+        let command = audit.event("smart-home-system/hue/living-room/power/set", "on", false, now).unwrap();
+        assert_eq!(command.kind, AuditKind::Command);
+        assert_eq!(serde_json::to_string(&command).unwrap(), format!(
+            r#"{{"timestamp":"{}","kind":"command","topic":"smart-home-system/hue/living-room/power/set","new":"on"}}"#,
+            now.to_rfc3339()));
+
+        let state = audit.event("smart-home-system/hue/living-room/power", "on", false, now).unwrap();
+        assert_eq!(state.kind, AuditKind::State);
+        assert_eq!(state.old.as_deref(), Some("off"));
+        assert_eq!(state.new, "on");
+
+        let state = audit.event("smart-home-system/hue/living-room/brightness", "80", false, now).unwrap();
+        assert_eq!(state.old, None);
+    }
+}
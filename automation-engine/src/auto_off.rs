@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AutoOffConfig {
+    pub name: String,
+    /// Topic prefix of the device, e.g. `smart-home-system/yeelight`.
+    pub topic: String,
+    /// Minutes the device can stay on. 0 never turns it off.
+    pub minutes: u64,
+}
+
+struct Timer {
+    config: AutoOffConfig,
+    minutes: u64,
+    /// When the device gets turned off, while it's on.
+    deadline: Option<Instant>,
+}
+
+/// Turns devices off after they have been on for a while.
+///
+/// Every method returns the `(topic, payload)` messages to publish.
+pub struct AutoOff {
+    timers: Vec<Timer>,
+}
+
+impl AutoOff {
+    pub fn new(config: Vec<AutoOffConfig>) -> Self {
+        let timers = config.into_iter().map(|config| Timer { minutes: config.minutes, config, deadline: None }).collect();
+        Self { timers }
+    }
+
+    fn deadline(minutes: u64, now: Instant) -> Option<Instant> {
+        match minutes {
+            0 => None,
+            _ => Some(now + Duration::from_secs(minutes * 60)),
+        }
+    }
+
+    /// Starts the timer of a device when it's turned on and stops it when it's turned off.
+    pub fn handle_power(&mut self, topic: &str, payload: &str, now: Instant) {
+        for timer in &mut self.timers {
+            if topic.strip_prefix(timer.config.topic.as_str()) != Some("/power") {
+                continue;
+            }
+
+            match payload.trim() {
+                "on" if timer.deadline.is_none() => timer.deadline = Self::deadline(timer.minutes, now),
+                "off" => timer.deadline = None,
+                _ => {}
+            }
+        }
+    }
+
+    pub fn minutes(&self, name: &str) -> Option<u64> {
+        self.timers.iter().find(|timer| timer.config.name == name).map(|timer| timer.minutes)
+    }
+
+    /// Changes the minutes of a timer, counting from now if it's running. `None` if there is no timer named `name`.
+    pub fn set_minutes(&mut self, name: &str, minutes: u64, now: Instant) -> Option<()> {
+        let timer = self.timers.iter_mut().find(|timer| timer.config.name == name)?;
+
+        timer.minutes = minutes;
+        if timer.deadline.is_some() {
+            timer.deadline = Self::deadline(minutes, now);
+        }
+
+        Some(())
+    }
+
+    /// Keeps the device on until it's turned off and on again. `None` if there is no timer named `name`.
+    pub fn cancel(&mut self, name: &str) -> Option<bool> {
+        let timer = self.timers.iter_mut().find(|timer| timer.config.name == name)?;
+        Some(timer.deadline.take().is_some())
+    }
+
+    /// Turns off the devices whose timer ran out.
+    pub fn expired(&mut self, now: Instant) -> Vec<(String, String)> {
+        let mut messages = vec![];
+
+        for timer in &mut self.timers {
+            if timer.deadline.is_some_and(|deadline| deadline <= now) {
+                info!("{} has been on for {} minutes, turning it off", timer.config.name, timer.minutes);
+
+                // Stays unset until the device reports it's on again
+                timer.deadline = None;
+                messages.push((format!("{}/power/set", timer.config.topic), "off".to_string()));
+            }
+        }
+
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::auto_off::{AutoOff, AutoOffConfig};
+
+    fn auto_off() -> AutoOff {
+        AutoOff::new(vec![AutoOffConfig { name: "bathroom".into(), topic: "smart-home-system/zigbee/bathroom-light".into(), minutes: 15 }])
+    }
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_turns_off() {
+        let mut auto_off = auto_off();
+        let start = Instant::now();
+
+        auto_off.handle_power("smart-home-system/zigbee/bathroom-light/power", "on", start);
+        // being reported on again doesn't restart the timer
+        auto_off.handle_power("smart-home-system/zigbee/bathroom-light/power", "on", start + 5 * MINUTE);
+
+        assert_eq!(auto_off.expired(start + 14 * MINUTE), []);
+        assert_eq!(auto_off.expired(start + 15 * MINUTE), [
+            ("smart-home-system/zigbee/bathroom-light/power/set".to_string(), "off".to_string()),
+        ]);
+        assert_eq!(auto_off.expired(start + 16 * MINUTE), []);
+    }
+
+    #[test]
+    fn test_cancel_and_set_minutes() {
+        let mut auto_off = auto_off();
+        let start = Instant::now();
+
+        auto_off.handle_power("smart-home-system/zigbee/bathroom-light/power", "on", start);
+        assert_eq!(auto_off.cancel("bathroom"), Some(true));
+        assert_eq!(auto_off.expired(start + 20 * MINUTE), []);
+
+        auto_off.handle_power("smart-home-system/zigbee/bathroom-light/power", "off", start + 20 * MINUTE);
+        auto_off.handle_power("smart-home-system/zigbee/bathroom-light/power", "on", start + 21 * MINUTE);
+        auto_off.set_minutes("bathroom", 2, start + 22 * MINUTE);
+        assert_eq!(auto_off.expired(start + 24 * MINUTE).len(), 1);
+
+        assert_eq!(auto_off.set_minutes("kitchen", 2, start), None);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use log::info;
+use serde::Deserialize;
+
+/// Sun elevation, in degrees, at and below which lights are at their warmest and dimmest (civil twilight).
+const NIGHT_ELEVATION: f64 = -6.0;
+/// Sun elevation, in degrees, at and above which lights are at their coldest and brightest.
+const DAY_ELEVATION: f64 = 30.0;
+
+/// The attributes circadian lighting adjusts, as published under the light topic.
+const BRIGHTNESS: &str = "brightness";
+const COLOR_TEMPERATURE: &str = "color_temperature";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CircadianConfig {
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// Brightness in percent.
+    #[serde(default = "default_min_brightness")]
+    pub min_brightness: u8,
+    #[serde(default = "default_max_brightness")]
+    pub max_brightness: u8,
+    /// Color temperature in Kelvin.
+    #[serde(default = "default_min_color_temperature")]
+    pub min_color_temperature: u32,
+    #[serde(default = "default_max_color_temperature")]
+    pub max_color_temperature: u32,
+    pub lights: Vec<CircadianLightConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CircadianLightConfig {
+    pub name: String,
+    /// Topic prefix of the light, e.g. `smart-home-system/hue/living-room`.
+    pub topic: String,
+    #[serde(default = "default_true")]
+    pub brightness: bool,
+    #[serde(default = "default_true")]
+    pub color_temperature: bool,
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_min_brightness() -> u8 {
+    30
+}
+
+fn default_max_brightness() -> u8 {
+    100
+}
+
+fn default_min_color_temperature() -> u32 {
+    2200
+}
+
+fn default_max_color_temperature() -> u32 {
+    5000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl CircadianConfig {
+    /// The brightness and color temperature for a sun elevation.
+    pub fn target(&self, elevation: f64) -> (u8, u32) {
+        let factor = ((elevation - NIGHT_ELEVATION) / (DAY_ELEVATION - NIGHT_ELEVATION)).clamp(0.0, 1.0);
+        let lerp = |min: f64, max: f64| min + (max - min) * factor;
+
+        let brightness = lerp(self.min_brightness as f64, self.max_brightness as f64).round() as u8;
+        // Rounded so lights aren't updated for changes no one can see
+        let color_temperature = (lerp(self.min_color_temperature as f64, self.max_color_temperature as f64) / 10.0).round() as u32 * 10;
+
+        (brightness, color_temperature)
+    }
+
+    pub fn subscribe_topics(&self) -> Vec<String> {
+        self.lights.iter()
+            .flat_map(|light| [
+                format!("{}/power", light.topic),
+                format!("{}/{}/set", light.topic, BRIGHTNESS),
+                format!("{}/{}/set", light.topic, COLOR_TEMPERATURE),
+            ])
+            .collect()
+    }
+}
+
+struct Light {
+    config: CircadianLightConfig,
+    enabled: bool,
+    on: bool,
+    /// Set when someone else changed the light. Cleared when it's turned off.
+    overridden: bool,
+    /// The last payload published for each attribute.
+    published: HashMap<&'static str, String>,
+}
+
+/// Adjusts the brightness and color temperature of lights that are on to follow the sun.
+///
+/// Every method returns the `(topic, payload)` messages to publish.
+pub struct Circadian {
+    config: CircadianConfig,
+    lights: Vec<Light>,
+}
+
+impl Circadian {
+    pub fn new(config: CircadianConfig) -> Self {
+        let lights = config.lights.iter()
+            .map(|light| Light { config: light.clone(), enabled: true, on: false, overridden: false, published: HashMap::new() })
+            .collect();
+
+        Self { config, lights }
+    }
+
+    pub fn interval_secs(&self) -> u64 {
+        self.config.interval_secs
+    }
+
+    fn apply(config: &CircadianConfig, light: &mut Light, elevation: f64, force: bool) -> Vec<(String, String)> {
+        if !light.enabled || !light.on || light.overridden {
+            return vec![];
+        }
+
+        let (brightness, color_temperature) = config.target(elevation);
+        let mut values = vec![];
+
+        if light.config.brightness {
+            values.push((BRIGHTNESS, brightness.to_string()));
+        }
+        if light.config.color_temperature {
+            values.push((COLOR_TEMPERATURE, color_temperature.to_string()));
+        }
+
+        let mut messages = vec![];
+
+        for (attribute, payload) in values {
+            if force || light.published.get(attribute) != Some(&payload) {
+                messages.push((format!("{}/{}/set", light.config.topic, attribute), payload.clone()));
+                light.published.insert(attribute, payload);
+            }
+        }
+
+        messages
+    }
+
+    pub fn update(&mut self, elevation: f64) -> Vec<(String, String)> {
+        let config = &self.config;
+        self.lights.iter_mut().flat_map(|light| Self::apply(config, light, elevation, false)).collect()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.lights.iter().find(|light| light.config.name == name).map(|light| light.enabled)
+    }
+
+    /// `None` if there is no light named `name`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool, elevation: f64) -> Option<Vec<(String, String)>> {
+        let light = self.lights.iter_mut().find(|light| light.config.name == name)?;
+
+        light.enabled = enabled;
+        light.overridden = false;
+
+        Some(Self::apply(&self.config, light, elevation, true))
+    }
+
+    /// Follows the power state of the lights and detects manual changes.
+    pub fn handle_message(&mut self, topic: &str, payload: &str, elevation: f64) -> Vec<(String, String)> {
+        let light = self.lights.iter_mut().find_map(|light| {
+            let attribute = topic.strip_prefix(light.config.topic.as_str())?.strip_prefix('/')?;
+            Some((light, attribute))
+        });
+        let Some((light, attribute)) = light else { return vec![] };
+
+        match attribute {
+            "power" => match payload.trim() {
+                "on" if !light.on => {
+                    light.on = true;
+                    Self::apply(&self.config, light, elevation, true)
+                }
+                "off" => {
+                    if light.overridden {
+                        info!("[{}] Resuming circadian lighting for {}", topic, light.config.name);
+                    }
+                    light.on = false;
+                    light.overridden = false;
+                    light.published.clear();
+                    vec![]
+                }
+                _ => vec![],
+            },
+            _ => {
+                let Some(attribute) = [BRIGHTNESS, COLOR_TEMPERATURE].into_iter().find(|name| attribute == format!("{}/set", name)) else {
+                    return vec![];
+                };
+
+                // Our own messages come back with the payload we published
+                if light.enabled && !light.overridden && light.published.get(attribute).map(String::as_str) != Some(payload.trim()) {
+                    info!("[{}] {} was changed manually, pausing circadian lighting until it's turned off", topic, light.config.name);
+                    light.overridden = true;
+                }
+
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circadian::{Circadian, CircadianConfig, CircadianLightConfig};
+
+    fn config() -> CircadianConfig {
+        CircadianConfig {
+            interval_secs: 60,
+            min_brightness: 30,
+            max_brightness: 100,
+            min_color_temperature: 2200,
+            max_color_temperature: 5000,
+            lights: vec![CircadianLightConfig {
+                name: "desk".into(),
+                topic: "smart-home-system/hue/desk".into(),
+                brightness: true,
+                color_temperature: true,
+            }],
+        }
+    }
+
+    fn message(topic: &str, payload: &str) -> (String, String) {
+        (topic.to_string(), payload.to_string())
+    }
+
+    #[test]
+    fn test_target() {
+        let config = config();
+        assert_eq!(config.target(-20.0), (30, 2200));
+        assert_eq!(config.target(12.0), (65, 3600));
+        assert_eq!(config.target(60.0), (100, 5000));
+    }
+
+    #[test]
+    fn test_follows_power() {
+        let mut circadian = Circadian::new(config());
+
+        assert_eq!(circadian.update(60.0), []);
+
+        assert_eq!(circadian.handle_message("smart-home-system/hue/desk/power", "on", 60.0), [
+            message("smart-home-system/hue/desk/brightness/set", "100"),
+            message("smart-home-system/hue/desk/color_temperature/set", "5000"),
+        ]);
+
+        assert_eq!(circadian.update(60.0), []);
+        assert_eq!(circadian.update(-20.0), [
+            message("smart-home-system/hue/desk/brightness/set", "30"),
+            message("smart-home-system/hue/desk/color_temperature/set", "2200"),
+        ]);
+
+        circadian.handle_message("smart-home-system/hue/desk/power", "off", 60.0);
+        assert_eq!(circadian.update(60.0), []);
+    }
+
+    #[test]
+    fn test_manual_override() {
+        let mut circadian = Circadian::new(config());
+        circadian.handle_message("smart-home-system/hue/desk/power", "on", 60.0);
+
+        // the messages published by circadian lighting itself
+        circadian.handle_message("smart-home-system/hue/desk/brightness/set", "100", 60.0);
+        assert_eq!(circadian.update(-20.0).len(), 2);
+
+        circadian.handle_message("smart-home-system/hue/desk/brightness/set", "80", -20.0);
+        assert_eq!(circadian.update(60.0), []);
+
+        circadian.handle_message("smart-home-system/hue/desk/power", "off", 60.0);
+        assert_eq!(circadian.handle_message("smart-home-system/hue/desk/power", "on", 60.0).len(), 2);
+    }
+}
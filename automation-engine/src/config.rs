@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::action::ActionConfig;
+use crate::audit::AuditConfig;
+use crate::auto_off::AutoOffConfig;
+use crate::circadian::CircadianConfig;
+use crate::group::GroupConfig;
+use crate::history::HistoryConfig;
+use crate::presence::PresenceConfig;
+use crate::rule::{StateConditionConfig, TriggerConfig};
+use crate::scene::SceneConfig;
+use crate::schedule::ScheduleTime;
+use crate::script;
+use crate::sun::Location;
+use crate::timer::TimerConfig;
+use crate::vacation::VacationConfig;
+use crate::wake_up::WakeUpConfig;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    #[serde(default)]
+    pub schedules: Vec<ScheduleConfig>,
+    #[serde(default)]
+    pub scenes: Vec<SceneConfig>,
+    pub circadian: Option<CircadianConfig>,
+    #[serde(default)]
+    pub auto_off: Vec<AutoOffConfig>,
+    pub presence: Option<PresenceConfig>,
+    #[serde(default)]
+    pub groups: Vec<GroupConfig>,
+    #[serde(default)]
+    pub wake_up: Vec<WakeUpConfig>,
+    pub vacation: Option<VacationConfig>,
+    #[serde(default)]
+    pub timers: Vec<TimerConfig>,
+    pub history: Option<HistoryConfig>,
+    pub audit: Option<AuditConfig>,
+    /// Used to compute the sunrise and sunset of sun schedules and the sun position for circadian lighting.
+    pub location: Option<Location>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RuleConfig {
+    pub name: String,
+    pub trigger: TriggerConfig,
+    /// Checks on the current state of other topics. The rule only runs if all of them hold.
+    #[serde(default)]
+    pub conditions: Vec<StateConditionConfig>,
+    /// A rhai script that must evaluate to `true` for the rule to run.
+    pub condition_script: Option<String>,
+    /// A rhai script run before the actions, which can publish messages.
+    pub script: Option<String>,
+    #[serde(default)]
+    pub actions: Vec<ActionConfig>,
+    /// Minimum time between two runs of the rule. Triggers in between are ignored.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    #[serde(default)]
+    pub mode: RunMode,
+}
+
+/// What happens when a rule triggers while its delayed actions are still running.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    /// Cancel the running actions and start over, e.g. to extend a motion light's timeout.
+    #[default]
+    Restart,
+    /// Ignore the trigger.
+    Single,
+    /// Run both.
+    Parallel,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScheduleConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub time: ScheduleTime,
+    pub actions: Vec<ActionConfig>,
+    /// Whether the schedule runs until it's disabled over mqtt.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        let config: Config = serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))?;
+
+        let mut names = HashSet::new();
+        for rule in &config.rules {
+            if !names.insert(&rule.name) {
+                anyhow::bail!("Rule {} is defined more than once", rule.name);
+            }
+
+            for source in rule.condition_script.iter().chain(&rule.script) {
+                script::validate(source).context(format!("Invalid script in rule {}", rule.name))?;
+            }
+        }
+
+        let mut names = HashSet::new();
+        for schedule in &config.schedules {
+            if !names.insert(&schedule.name) {
+                anyhow::bail!("Schedule {} is defined more than once", schedule.name);
+            }
+
+            if matches!(schedule.time, ScheduleTime::Sun(_)) && config.location.is_none() {
+                anyhow::bail!("Schedule {} follows the sun but no location is configured", schedule.name);
+            }
+        }
+
+        let mut names = HashSet::new();
+        for scene in &config.scenes {
+            if !names.insert(&scene.name) {
+                anyhow::bail!("Scene {} is defined more than once", scene.name);
+            }
+        }
+
+        let mut names = HashSet::new();
+        for timer in &config.auto_off {
+            if !names.insert(&timer.name) {
+                anyhow::bail!("Auto off timer {} is defined more than once", timer.name);
+            }
+        }
+
+        let mut names = HashSet::new();
+        for group in &config.groups {
+            if !names.insert(&group.name) {
+                anyhow::bail!("Group {} is defined more than once", group.name);
+            }
+        }
+
+        let mut names = HashSet::new();
+        for wake_up in &config.wake_up {
+            if !names.insert(&wake_up.name) {
+                anyhow::bail!("Wake-up routine {} is defined more than once", wake_up.name);
+            }
+
+            if !(1..=100).contains(&wake_up.target_brightness) {
+                anyhow::bail!("Wake-up routine {} has an invalid target brightness {}", wake_up.name, wake_up.target_brightness);
+            }
+
+            if matches!(wake_up.time, ScheduleTime::Sun(_)) && config.location.is_none() {
+                anyhow::bail!("Wake-up routine {} follows the sun but no location is configured", wake_up.name);
+            }
+        }
+
+        let mut names = HashSet::new();
+        for timer in &config.timers {
+            if !names.insert(&timer.name) {
+                anyhow::bail!("Timer {} is defined more than once", timer.name);
+            }
+
+            if timer.minutes == 0 {
+                anyhow::bail!("Timer {} must run for at least a minute", timer.name);
+            }
+        }
+
+        if config.circadian.is_some() && config.location.is_none() {
+            anyhow::bail!("Circadian lighting follows the sun but no location is configured");
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,152 @@
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+/// A standard 5 field cron expression: `minute hour day-of-month month day-of-week`.
+///
+/// Fields accept `*`, values, ranges (`1-5`), lists (`1,15`) and steps (`*/15`, `8-18/2`).
+/// Day of week goes from 0 (sunday) to 7 (sunday again).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpression {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Like cron, when both days of month and days of week are restricted either of them can match.
+    restricted_days: bool,
+}
+
+/// Parses a field into a bitset of the allowed values.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut values = 0u64;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<u32>().ok().filter(|step| *step > 0)
+                    .ok_or_else(|| format!("Invalid step in {}", part))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let start = start.parse::<u32>().map_err(|_| format!("Invalid value in {}", part))?;
+                let end = end.parse::<u32>().map_err(|_| format!("Invalid value in {}", part))?;
+                // `5/15` means from 5 to the end of the range
+                let end = if step > 1 && !range.contains('-') { max } else { end };
+                (start, end)
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("{} is out of range {}-{}", part, min, max));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            values |= 1 << value;
+        }
+    }
+
+    Ok(values)
+}
+
+impl FromStr for CronExpression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(format!("Expected 5 fields in cron expression '{}'", s));
+        };
+
+        let mut days_of_week = parse_field(days_of_week, 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days_of_month: parse_field(days_of_month, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            days_of_week,
+            restricted_days: days_of_month != "*" && fields[4] != "*",
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for CronExpression {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl CronExpression {
+    /// Whether the expression fires in the minute of `time`.
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        let has = |values: u64, value: u32| values & (1 << value) != 0;
+
+        let day_of_month = has(self.days_of_month, time.day());
+        let day_of_week = has(self.days_of_week, time.weekday().num_days_from_sunday());
+        let day = match self.restricted_days {
+            true => day_of_month || day_of_week,
+            false => day_of_month && day_of_week,
+        };
+
+        day && has(self.minutes, time.minute()) && has(self.hours, time.hour()) && has(self.months, time.month())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::cron::CronExpression;
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::NaiveDateTime {
+        // 2024-01-01 is a monday
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let porch_on: CronExpression = "0 19 * * *".parse().unwrap();
+        assert!(porch_on.matches(&at(1, 19, 0)));
+        assert!(!porch_on.matches(&at(1, 19, 1)));
+
+        let porch_off: CronExpression = "30 23 * * *".parse().unwrap();
+        assert!(porch_off.matches(&at(5, 23, 30)));
+
+        let every_quarter: CronExpression = "*/15 8-18 * * 1-5".parse().unwrap();
+        assert!(every_quarter.matches(&at(2, 8, 45)));
+        assert!(!every_quarter.matches(&at(2, 8, 50)));
+        assert!(!every_quarter.matches(&at(6, 8, 45)));
+
+        let sundays: CronExpression = "0 9 * * 7".parse().unwrap();
+        assert!(sundays.matches(&at(7, 9, 0)));
+        assert!(!sundays.matches(&at(8, 9, 0)));
+    }
+
+    #[test]
+    fn test_restricted_days() {
+        // the 1st of the month or any friday
+        let expression: CronExpression = "0 12 1 * 5".parse().unwrap();
+        assert!(expression.matches(&at(1, 12, 0)));
+        assert!(expression.matches(&at(5, 12, 0)));
+        assert!(!expression.matches(&at(4, 12, 0)));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!("0 19 * *".parse::<CronExpression>().is_err());
+        assert!("60 19 * * *".parse::<CronExpression>().is_err());
+        assert!("0 19 * * mon".parse::<CronExpression>().is_err());
+        assert!("*/0 19 * * *".parse::<CronExpression>().is_err());
+    }
+}
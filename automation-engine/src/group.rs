@@ -0,0 +1,81 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GroupConfig {
+    pub name: String,
+    /// Topic prefixes of the member devices, e.g. `smart-home-system/yeelight`.
+    pub members: Vec<String>,
+}
+
+impl GroupConfig {
+    /// The attribute a member reports on `topic`, e.g. `power` for `smart-home-system/yeelight/power`.
+    pub fn member_attribute<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        self.members.iter()
+            .filter_map(|member| topic.strip_prefix(member.as_str())?.strip_prefix('/'))
+            .find(|attribute| !attribute.is_empty() && !attribute.contains('/'))
+    }
+
+    /// The topics a message to `<group>/<attribute>/<action>` is forwarded to.
+    pub fn fan_out(&self, attribute: &str, action: &str) -> Vec<String> {
+        self.members.iter().map(|member| format!("{}/{}/{}", member, attribute, action)).collect()
+    }
+
+    pub fn member_topics(&self, attribute: &str) -> Vec<String> {
+        self.members.iter().map(|member| format!("{}/{}", member, attribute)).collect()
+    }
+}
+
+/// Combines the values of the members into the value of the group: `on` if any member is on,
+/// the average of numbers, or the value all members agree on.
+pub fn aggregate(attribute: &str, values: &[&str]) -> Option<String> {
+    if values.is_empty() {
+        return None;
+    }
+
+    if attribute == "power" {
+        let any_on = values.iter().any(|value| value.eq_ignore_ascii_case("on"));
+        return Some(if any_on { "on" } else { "off" }.to_string());
+    }
+
+    if let Ok(numbers) = values.iter().map(|value| value.parse::<f64>()).collect::<Result<Vec<_>, _>>() {
+        let average = numbers.iter().sum::<f64>() / numbers.len() as f64;
+
+        return match values.iter().all(|value| !value.contains('.')) {
+            true => Some((average.round() as i64).to_string()),
+            false => Some(format!("{:.1}", average)),
+        };
+    }
+
+    values.iter().all(|value| value == &values[0]).then(|| values[0].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::group::{aggregate, GroupConfig};
+
+    #[test]
+    fn test_member_topics() {
+        let group = GroupConfig {
+            name: "livingroom".into(),
+            members: vec!["smart-home-system/yeelight".into(), "smart-home-system/hue/living-room".into()],
+        };
+
+        assert_eq!(group.member_attribute("smart-home-system/yeelight/power"), Some("power"));
+        assert_eq!(group.member_attribute("smart-home-system/hue/living-room/brightness"), Some("brightness"));
+        assert_eq!(group.member_attribute("smart-home-system/yeelight/power/set"), None);
+        assert_eq!(group.member_attribute("smart-home-system/hue/desk/power"), None);
+
+        assert_eq!(group.fan_out("power", "set"), ["smart-home-system/yeelight/power/set", "smart-home-system/hue/living-room/power/set"]);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        assert_eq!(aggregate("power", &["off", "on"]), Some("on".into()));
+        assert_eq!(aggregate("power", &["off", "off"]), Some("off".into()));
+        assert_eq!(aggregate("brightness", &["20", "45"]), Some("33".into()));
+        assert_eq!(aggregate("temperature", &["20.5", "21"]), Some("20.8".into()));
+        assert_eq!(aggregate("mode", &["auto", "auto"]), Some("auto".into()));
+        assert_eq!(aggregate("mode", &["auto", "manual"]), None);
+        assert_eq!(aggregate("power", &[]), None);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::rule::{is_command, topic_matches};
+
+/// How often the recorded states are written.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Writes sooner if this many states were recorded.
+const MAX_BUFFERED_LINES: usize = 500;
+
+/// Records state changes in the InfluxDB line protocol, e.g. to chart them in Grafana.
+#[derive(Deserialize, Debug, Clone)]
+pub struct HistoryConfig {
+    /// Topic filters of the recorded states, may contain the `+` and `#` wildcards.
+    pub topics: Vec<String>,
+    #[serde(flatten)]
+    pub sink: HistorySink,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum HistorySink {
+    Influxdb(InfluxConfig),
+    /// Appends the lines to a file.
+    File(PathBuf),
+}
+
+/// An InfluxDB 2 bucket.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InfluxConfig {
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: Option<String>,
+}
+
+pub struct History {
+    config: HistoryConfig,
+    /// The last payload of every recorded topic, to only record changes.
+    states: HashMap<String, String>,
+    lines: Vec<String>,
+    last_flush: Instant,
+}
+
+impl History {
+    pub fn new(config: HistoryConfig) -> Self {
+        Self { config, states: HashMap::new(), lines: Vec::new(), last_flush: Instant::now() }
+    }
+
+    pub fn sink(&self) -> &HistorySink {
+        &self.config.sink
+    }
+
+    /// Records the payload if it changed the state of one of the topics.
+    /// Retained messages only set the starting state, as they were already recorded before.
+    pub fn record(&mut self, topic: &str, payload: &str, retained: bool, time: SystemTime) {
+        if is_command(topic) || !self.config.topics.iter().any(|filter| topic_matches(filter, topic)) {
+            return;
+        }
+
+        let payload = payload.trim();
+        if self.states.get(topic).is_some_and(|last| last == payload) {
+            return;
+        }
+        self.states.insert(topic.to_string(), payload.to_string());
+
+        if !retained {
+            self.lines.push(line(topic, payload, time));
+        }
+    }
+
+    /// The recorded lines, if it's time to write them.
+    pub fn take(&mut self, now: Instant) -> Option<Vec<String>> {
+        if self.lines.is_empty() || (now - self.last_flush < FLUSH_INTERVAL && self.lines.len() < MAX_BUFFERED_LINES) {
+            return None;
+        }
+
+        self.last_flush = now;
+        Some(std::mem::take(&mut self.lines))
+    }
+}
+
+/// A state as a line in the InfluxDB line protocol. The measurement is the last topic level and the
+/// device the levels before it, e.g. `power,device=hue/living-room value=1` for
+/// `smart-home-system/hue/living-room/power`. Numbers and on/off are stored in the `value` field and
+/// anything else in the `state` field.
+pub fn line(topic: &str, payload: &str, time: SystemTime) -> String {
+    let topic = topic.strip_prefix("smart-home-system/").unwrap_or(topic);
+    let (device, measurement) = topic.rsplit_once('/').unwrap_or(("", topic));
+
+    let mut line = escape(measurement, &[',', ' ']);
+    if !device.is_empty() {
+        line.push_str(",device=");
+        line.push_str(&escape(device, &[',', '=', ' ']));
+    }
+
+    let value = match payload.to_ascii_lowercase().as_str() {
+        "on" | "true" => Some(1.0),
+        "off" | "false" => Some(0.0),
+        payload => payload.parse::<f64>().ok().filter(|value| value.is_finite()),
+    };
+
+    match value {
+        Some(value) => line.push_str(&format!(" value={}", value)),
+        None => line.push_str(&format!(" state=\"{}\"", escape(payload, &['"']))),
+    }
+
+    let nanos = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+    format!("{} {}", line, nanos)
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        if char == '\\' || special.contains(&char) {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
+
+pub async fn write(http: &reqwest::Client, sink: &HistorySink, lines: Vec<String>) -> anyhow::Result<()> {
+    let mut body = lines.join("\n");
+    body.push('\n');
+
+    match sink {
+        HistorySink::Influxdb(influx) => {
+            let mut request = http.post(format!("{}/api/v2/write", influx.url.trim_end_matches('/')))
+                .query(&[("org", influx.org.as_str()), ("bucket", influx.bucket.as_str()), ("precision", "ns")])
+                .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(body);
+
+            if let Some(token) = &influx.token {
+                request = request.header(reqwest::header::AUTHORIZATION, format!("Token {}", token));
+            }
+
+            request.send().await?.error_for_status()?;
+        }
+        HistorySink::File(path) => {
+            let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await
+                .context(format!("Failed to open {:?}", path))?;
+            file.write_all(body.as_bytes()).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use crate::history::{line, History, HistoryConfig, HistorySink, FLUSH_INTERVAL};
+
+    #[test]
+    fn test_line() {
+        let time = UNIX_EPOCH + Duration::from_secs(1700000000);
+
+        assert_eq!(line("smart-home-system/hue/living-room/power", "on", time), "power,device=hue/living-room value=1 1700000000000000000");
+        assert_eq!(line("smart-home-system/host/server/cpu_temperature", "45.5", time), "cpu_temperature,device=host/server value=45.5 1700000000000000000");
+        assert_eq!(line("smart-home-system/automation/presence/alice", "home", time), "alice,device=automation/presence state=\"home\" 1700000000000000000");
+        assert_eq!(line("smart-home-system/my room/mode", "say \"hi\"", time), "mode,device=my\\ room state=\"say \\\"hi\\\"\" 1700000000000000000");
+    }
+
+    #[test]
+    fn test_record_changes() {
+        let mut history = History::new(HistoryConfig {
+            topics: vec!["smart-home-system/yeelight/#".into()],
+            sink: HistorySink::File(PathBuf::from("history.lp")),
+        });
+        let now = SystemTime::now();
+
+        history.record("smart-home-system/yeelight/power", "off", true, now);
+        history.record("smart-home-system/yeelight/power", "off", false, now);
+        history.record("smart-home-system/yeelight/power/set", "on", false, now);
+        history.record("smart-home-system/yeelight/power", "on", false, now);
+        history.record("smart-home-system/yeelight/power", "on", false, now);
+        history.record("smart-home-system/yeelight/brightness", "40", false, now);
+        history.record("smart-home-system/hue/living-room/power", "on", false, now);
+
+        assert_eq!(history.take(Instant::now()), None, "waits for the flush interval");
+
+        let lines = history.take(Instant::now() + FLUSH_INTERVAL).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("power,device=yeelight value=1 "));
+        assert!(lines[1].starts_with("brightness,device=yeelight value=40 "));
+
+        assert_eq!(history.take(Instant::now() + FLUSH_INTERVAL * 2), None);
+    }
+}
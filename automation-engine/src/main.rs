@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::info;
+
+use crate::application::Application;
+use crate::config::Config;
+use crate::mqtt::connect_mqtt;
+
+mod action;
+mod application;
+mod audit;
+mod auto_off;
+mod circadian;
+mod config;
+mod cron;
+mod group;
+mod history;
+mod mqtt;
+mod presence;
+mod rule;
+mod scene;
+mod schedule;
+mod script;
+mod state;
+mod sun;
+mod timer;
+mod vacation;
+mod wake_up;
+mod webhook;
+
+const MQTT_SCHEDULE_TOPIC_PREFIX: &str = "smart-home-system/automation/schedule";
+const MQTT_SCHEDULE_STATE_TOPIC: &str = "smart-home-system/automation/schedule/+/enabled";
+const MQTT_SCHEDULE_SET_TOPIC: &str = "smart-home-system/automation/schedule/+/enabled/set";
+const MQTT_SCHEDULE_GET_TOPIC: &str = "smart-home-system/automation/schedule/+/enabled/get";
+
+const MQTT_CIRCADIAN_TOPIC_PREFIX: &str = "smart-home-system/automation/circadian";
+const MQTT_CIRCADIAN_STATE_TOPIC: &str = "smart-home-system/automation/circadian/+/enabled";
+const MQTT_CIRCADIAN_SET_TOPIC: &str = "smart-home-system/automation/circadian/+/enabled/set";
+const MQTT_CIRCADIAN_GET_TOPIC: &str = "smart-home-system/automation/circadian/+/enabled/get";
+
+const MQTT_AUTO_OFF_TOPIC_PREFIX: &str = "smart-home-system/automation/auto-off";
+const MQTT_AUTO_OFF_TOPIC: &str = "smart-home-system/automation/auto-off/#";
+
+const MQTT_WAKE_UP_TOPIC_PREFIX: &str = "smart-home-system/automation/wake-up";
+const MQTT_WAKE_UP_TOPIC: &str = "smart-home-system/automation/wake-up/#";
+
+const MQTT_TIMER_TOPIC_PREFIX: &str = "smart-home-system/automation/timer";
+const MQTT_TIMER_TOPIC: &str = "smart-home-system/automation/timer/#";
+
+/// Vacation mode is enabled with `smart-home-system/automation/vacation/enabled/set`.
+const MQTT_VACATION_TOPIC_PREFIX: &str = "smart-home-system/automation";
+const MQTT_VACATION_TOPIC: &str = "smart-home-system/automation/vacation/#";
+
+const MQTT_GROUP_TOPIC_PREFIX: &str = "smart-home-system/group";
+const MQTT_GROUP_TOPIC: &str = "smart-home-system/group/+/+/+";
+
+const MQTT_PRESENCE_TOPIC_PREFIX: &str = "smart-home-system/automation/presence";
+
+const MQTT_SCENE_TOPIC_PREFIX: &str = "smart-home-system/scene";
+const MQTT_SCENE_TOPIC: &str = "smart-home-system/scene/+/+";
+
+const MQTT_AUDIT_TOPIC: &str = "smart-home-system/audit";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/automation/error";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let config_path = PathBuf::from(std::env::var("AUTOMATION_CONFIG").unwrap_or_else(|_| "automations.yaml".into()));
+    let config = Config::load(&config_path)?;
+
+    let circadian_topics = config.circadian.as_ref().map(|circadian| circadian.subscribe_topics()).unwrap_or_default();
+    let group_member_topics: Vec<String> = config.groups.iter()
+        .flat_map(|group| group.members.iter().map(|member| format!("{}/+", member)))
+        .collect();
+    let auto_off_topics: Vec<String> = config.auto_off.iter().map(|timer| format!("{}/power", timer.topic)).collect();
+
+    let mut subscribe_topics: Vec<&str> = config.rules.iter().map(|rule| rule.trigger.topic.as_str()).collect();
+    // The current values checked by rule conditions
+    subscribe_topics.extend(config.rules.iter().flat_map(|rule| rule.conditions.iter().map(|condition| condition.topic.as_str())));
+    subscribe_topics.extend([MQTT_SCHEDULE_STATE_TOPIC, MQTT_SCHEDULE_SET_TOPIC, MQTT_SCHEDULE_GET_TOPIC, MQTT_SCENE_TOPIC]);
+    // The current values of the scene topics, to snapshot them
+    subscribe_topics.extend(config.scenes.iter().flat_map(|scene| scene.values.iter().filter_map(|value| value.state_topic())));
+    subscribe_topics.extend([MQTT_CIRCADIAN_STATE_TOPIC, MQTT_CIRCADIAN_SET_TOPIC, MQTT_CIRCADIAN_GET_TOPIC]);
+    subscribe_topics.extend(circadian_topics.iter().map(String::as_str));
+    subscribe_topics.push(MQTT_AUTO_OFF_TOPIC);
+    subscribe_topics.extend(auto_off_topics.iter().map(String::as_str));
+    subscribe_topics.push(MQTT_WAKE_UP_TOPIC);
+    subscribe_topics.push(MQTT_TIMER_TOPIC);
+    subscribe_topics.push(MQTT_VACATION_TOPIC);
+    subscribe_topics.push(MQTT_GROUP_TOPIC);
+    subscribe_topics.extend(group_member_topics.iter().map(String::as_str));
+    subscribe_topics.extend(config.presence.iter().flat_map(|presence| presence.people.iter().map(|person| person.topic.as_str())));
+    subscribe_topics.extend(config.history.iter().flat_map(|history| history.topics.iter().map(String::as_str)));
+    subscribe_topics.extend(config.audit.iter().flat_map(|audit| audit.topics.iter().map(String::as_str)));
+    subscribe_topics.sort();
+    subscribe_topics.dedup();
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting automation engine with {} rules, {} schedules and {} scenes", config.rules.len(), config.schedules.len(), config.scenes.len());
+
+    let mut application = Application::new(client, config).context("Failed to create the application")?;
+
+    info!("Waiting for mqtt messages...");
+
+    let mut schedule_interval = tokio::time::interval(Duration::from_secs(1));
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    application.handle_mqtt_message(&message);
+                }
+            }
+            _ = schedule_interval.tick() => application.tick(chrono::Local::now()),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::action::ActionConfig;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PresenceConfig {
+    /// Time someone has to be home before they count as arrived.
+    #[serde(default)]
+    pub arrival_delay_secs: u64,
+    /// Time someone has to be away before they count as left, so phones briefly dropping off the
+    /// network don't count as leaving.
+    #[serde(default = "default_departure_delay_secs")]
+    pub departure_delay_secs: u64,
+    pub people: Vec<PersonConfig>,
+    /// Run when someone arrives to an empty home.
+    #[serde(default)]
+    pub on_first_arrival: Vec<ActionConfig>,
+    /// Run when the last person leaves.
+    #[serde(default)]
+    pub on_everyone_left: Vec<ActionConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PersonConfig {
+    pub name: String,
+    /// Topic reporting whether the person is home, e.g. from a phone tracker.
+    pub topic: String,
+    /// Payload of the topic while the person is home. Anything else means away.
+    #[serde(default = "default_home_payload")]
+    pub home_payload: String,
+    #[serde(default)]
+    pub on_arrival: Vec<ActionConfig>,
+    #[serde(default)]
+    pub on_departure: Vec<ActionConfig>,
+}
+
+fn default_departure_delay_secs() -> u64 {
+    300
+}
+
+fn default_home_payload() -> String {
+    "home".into()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PresenceEvent {
+    Arrived(String),
+    Departed(String),
+    FirstArrival,
+    EveryoneLeft,
+}
+
+struct Person {
+    config: PersonConfig,
+    /// Unknown until the topic reports anything.
+    home: Option<bool>,
+    /// A change that is confirmed once it lasted until the deadline.
+    pending: Option<(bool, Instant)>,
+}
+
+/// Tracks who is home, debouncing the reports of each person.
+pub struct Presence {
+    config: PresenceConfig,
+    people: Vec<Person>,
+}
+
+impl Presence {
+    pub fn new(config: PresenceConfig) -> Self {
+        let people = config.people.iter().map(|person| Person { config: person.clone(), home: None, pending: None }).collect();
+        Self { config, people }
+    }
+
+    fn anyone_home(&self) -> bool {
+        self.people.iter().any(|person| person.home == Some(true))
+    }
+
+    pub fn handle_message(&mut self, topic: &str, payload: &str, now: Instant) {
+        for person in self.people.iter_mut().filter(|person| person.config.topic == topic) {
+            let home = payload.trim() == person.config.home_payload;
+
+            if person.home.is_none() {
+                // The state from before the engine started, e.g. a retained message
+                person.home = Some(home);
+            } else if person.home == Some(home) {
+                person.pending = None;
+            } else if person.pending.map(|(pending, _)| pending) != Some(home) {
+                let delay = if home { self.config.arrival_delay_secs } else { self.config.departure_delay_secs };
+                person.pending = Some((home, now + Duration::from_secs(delay)));
+            }
+        }
+    }
+
+    /// Confirms the changes that lasted long enough.
+    pub fn tick(&mut self, now: Instant) -> Vec<PresenceEvent> {
+        let anyone_home = self.anyone_home();
+        let mut events = vec![];
+
+        for person in &mut self.people {
+            let Some((home, deadline)) = person.pending else { continue };
+            if deadline > now {
+                continue;
+            }
+
+            person.home = Some(home);
+            person.pending = None;
+
+            events.push(match home {
+                true => PresenceEvent::Arrived(person.config.name.clone()),
+                false => PresenceEvent::Departed(person.config.name.clone()),
+            });
+        }
+
+        match (anyone_home, self.anyone_home()) {
+            (false, true) => events.push(PresenceEvent::FirstArrival),
+            (true, false) => events.push(PresenceEvent::EveryoneLeft),
+            _ => {}
+        }
+
+        events
+    }
+
+    pub fn actions(&self, event: &PresenceEvent) -> &[ActionConfig] {
+        let person = |name: &str| self.people.iter().find(|person| person.config.name == name).map(|person| &person.config);
+
+        match event {
+            PresenceEvent::Arrived(name) => person(name).map_or(&[], |person| &person.on_arrival),
+            PresenceEvent::Departed(name) => person(name).map_or(&[], |person| &person.on_departure),
+            PresenceEvent::FirstArrival => &self.config.on_first_arrival,
+            PresenceEvent::EveryoneLeft => &self.config.on_everyone_left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::presence::{PersonConfig, Presence, PresenceConfig, PresenceEvent};
+
+    fn person(name: &str) -> PersonConfig {
+        PersonConfig {
+            name: name.into(),
+            topic: format!("smart-home-system/phone/{}", name),
+            home_payload: "home".into(),
+            on_arrival: vec![],
+            on_departure: vec![],
+        }
+    }
+
+    fn presence() -> Presence {
+        Presence::new(PresenceConfig {
+            arrival_delay_secs: 0,
+            departure_delay_secs: 300,
+            people: vec![person("alice"), person("bob")],
+            on_first_arrival: vec![],
+            on_everyone_left: vec![],
+        })
+    }
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_debounced_departure() {
+        let mut presence = presence();
+        let start = Instant::now();
+
+        presence.handle_message("smart-home-system/phone/alice", "home", start);
+        presence.handle_message("smart-home-system/phone/bob", "not_home", start);
+        assert_eq!(presence.tick(start), []);
+
+        // alice's phone drops off the network for two minutes
+        presence.handle_message("smart-home-system/phone/alice", "not_home", start + MINUTE);
+        presence.handle_message("smart-home-system/phone/alice", "home", start + MINUTE * 3);
+        assert_eq!(presence.tick(start + MINUTE * 10), []);
+
+        presence.handle_message("smart-home-system/phone/alice", "not_home", start + MINUTE * 10);
+        assert_eq!(presence.tick(start + MINUTE * 14), []);
+        assert_eq!(presence.tick(start + MINUTE * 15), [PresenceEvent::Departed("alice".into()), PresenceEvent::EveryoneLeft]);
+    }
+
+    #[test]
+    fn test_arrivals() {
+        let mut presence = presence();
+        let start = Instant::now();
+
+        presence.handle_message("smart-home-system/phone/alice", "not_home", start);
+        presence.handle_message("smart-home-system/phone/bob", "not_home", start);
+
+        presence.handle_message("smart-home-system/phone/alice", "home", start + MINUTE);
+        assert_eq!(presence.tick(start + MINUTE), [PresenceEvent::Arrived("alice".into()), PresenceEvent::FirstArrival]);
+
+        presence.handle_message("smart-home-system/phone/bob", "home", start + MINUTE * 2);
+        assert_eq!(presence.tick(start + MINUTE * 2), [PresenceEvent::Arrived("bob".into())]);
+    }
+}
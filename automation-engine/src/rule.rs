@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// Checks on a payload. Every check that is set must hold.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PayloadCondition {
+    /// The payload must be exactly this.
+    pub payload: Option<String>,
+    /// The payload must be a number above this.
+    pub above: Option<f64>,
+    /// The payload must be a number below this.
+    pub below: Option<f64>,
+}
+
+impl PayloadCondition {
+    pub fn matches(&self, payload: &str) -> bool {
+        let payload = payload.trim();
+
+        if self.payload.as_ref().is_some_and(|expected| expected != payload) {
+            return false;
+        }
+
+        if self.above.is_none() && self.below.is_none() {
+            return true;
+        }
+
+        let Ok(value) = payload.parse::<f64>() else { return false };
+
+        self.above.iter().all(|above| value > *above) && self.below.iter().all(|below| value < *below)
+    }
+}
+
+/// A message that fires a rule.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TriggerConfig {
+    /// Topic filter, may contain the `+` and `#` wildcards.
+    pub topic: String,
+    #[serde(flatten)]
+    pub condition: PayloadCondition,
+}
+
+impl TriggerConfig {
+    pub fn matches(&self, topic: &str, payload: &str) -> bool {
+        topic_matches(&self.topic, topic) && self.condition.matches(payload)
+    }
+}
+
+/// A check on the current state of a topic, e.g. that a light is off, made when a rule triggers.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StateConditionConfig {
+    pub topic: String,
+    #[serde(flatten)]
+    pub condition: PayloadCondition,
+}
+
+impl StateConditionConfig {
+    /// Whether the last payload received on the topic passes the checks. Fails if nothing was received yet.
+    pub fn holds(&self, states: &HashMap<String, String>) -> bool {
+        states.get(&self.topic).is_some_and(|payload| self.condition.matches(payload))
+    }
+}
+
+/// Whether a topic matches an mqtt topic filter.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// The last topic level of messages that are commands, not states.
+const COMMAND_LEVELS: [&str; 8] = ["set", "get", "toggle", "fade", "activate", "restore", "start", "cancel"];
+
+/// Whether the topic is a command to a device or the engine, e.g. `smart-home-system/yeelight/power/set`.
+pub fn is_command(topic: &str) -> bool {
+    topic.rsplit('/').next().is_some_and(|level| COMMAND_LEVELS.contains(&level))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::rule::{topic_matches, PayloadCondition, StateConditionConfig, TriggerConfig};
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("smart-home-system/yeelight/power", "smart-home-system/yeelight/power"));
+        assert!(topic_matches("smart-home-system/+/power", "smart-home-system/yeelight/power"));
+        assert!(topic_matches("smart-home-system/#", "smart-home-system/zigbee/door/contact"));
+        assert!(!topic_matches("smart-home-system/+/power", "smart-home-system/zigbee/door/power"));
+        assert!(!topic_matches("smart-home-system/yeelight/power", "smart-home-system/yeelight"));
+    }
+
+    #[test]
+    fn test_trigger_matches() {
+        let trigger = TriggerConfig {
+            topic: "smart-home-system/zigbee/+/occupancy".into(),
+            condition: PayloadCondition { payload: Some("on".into()), ..Default::default() },
+        };
+
+        assert!(trigger.matches("smart-home-system/zigbee/hallway/occupancy", "on"));
+        assert!(!trigger.matches("smart-home-system/zigbee/hallway/occupancy", "off"));
+
+        let trigger = TriggerConfig {
+            topic: "smart-home-system/host/server/cpu_temperature".into(),
+            condition: PayloadCondition { above: Some(80.0), ..Default::default() },
+        };
+
+        assert!(trigger.matches("smart-home-system/host/server/cpu_temperature", "85.5"));
+        assert!(!trigger.matches("smart-home-system/host/server/cpu_temperature", "60.0"));
+        assert!(!trigger.matches("smart-home-system/host/server/cpu_temperature", "hot"));
+    }
+
+    #[test]
+    fn test_state_condition_holds() {
+        let dark = StateConditionConfig {
+            topic: "smart-home-system/hue/hallway-motion/illuminance".into(),
+            condition: PayloadCondition { below: Some(20.0), ..Default::default() },
+        };
+        let off = StateConditionConfig {
+            topic: "smart-home-system/hue/hallway/power".into(),
+            condition: PayloadCondition { payload: Some("off".into()), ..Default::default() },
+        };
+
+        let mut states = HashMap::from([("smart-home-system/hue/hallway-motion/illuminance".to_string(), "12".to_string())]);
+
+        assert!(dark.holds(&states));
+        assert!(!off.holds(&states), "unknown state never holds");
+
+        states.insert("smart-home-system/hue/hallway/power".into(), "on".into());
+        assert!(!off.holds(&states));
+
+        states.insert("smart-home-system/hue/hallway/power".into(), "off".into());
+        states.insert("smart-home-system/hue/hallway-motion/illuminance".into(), "250".into());
+        assert!(off.holds(&states));
+        assert!(!dark.holds(&states));
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SceneConfig {
+    pub name: String,
+    pub values: Vec<SceneValue>,
+}
+
+/// A payload published to a `/set` topic when the scene is activated.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SceneValue {
+    pub topic: String,
+    pub payload: String,
+}
+
+impl SceneValue {
+    /// The topic the device reports the current value on, e.g. `smart-home-system/yeelight/power`
+    /// for `smart-home-system/yeelight/power/set`.
+    pub fn state_topic(&self) -> Option<&str> {
+        self.topic.strip_suffix("/set")
+    }
+}
+
+impl SceneConfig {
+    /// The current values of the topics of the scene, to restore them after the scene was activated.
+    /// Topics whose value isn't known are left out.
+    pub fn snapshot(&self, states: &HashMap<String, String>) -> Vec<SceneValue> {
+        self.values.iter()
+            .filter_map(|value| {
+                let payload = states.get(value.state_topic()?)?;
+                Some(SceneValue { topic: value.topic.clone(), payload: payload.clone() })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::scene::{SceneConfig, SceneValue};
+
+    fn value(topic: &str, payload: &str) -> SceneValue {
+        SceneValue { topic: topic.into(), payload: payload.into() }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let scene = SceneConfig {
+            name: "movie".into(),
+            values: vec![
+                value("smart-home-system/yeelight/power/set", "on"),
+                value("smart-home-system/yeelight/brightness/set", "20"),
+                value("smart-home-system/chromecast/volume/set", "40"),
+                value("smart-home-system/http/projector", "on"),
+            ],
+        };
+
+        let states = HashMap::from([
+            ("smart-home-system/yeelight/power".to_string(), "off".to_string()),
+            ("smart-home-system/yeelight/brightness".to_string(), "100".to_string()),
+        ]);
+
+        assert_eq!(scene.snapshot(&states), [
+            value("smart-home-system/yeelight/power/set", "off"),
+            value("smart-home-system/yeelight/brightness/set", "100"),
+        ]);
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Duration, TimeZone, Timelike};
+use serde::Deserialize;
+
+use crate::cron::CronExpression;
+use crate::sun::{sun_event, Location, SunEvent};
+
+/// When a schedule runs.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum ScheduleTime {
+    Cron(CronExpression),
+    Sun(SunSchedule),
+}
+
+/// A time relative to the sunrise or sunset, e.g. 30 minutes before sunset.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SunSchedule {
+    pub event: SunEvent,
+    #[serde(default)]
+    pub offset_minutes: i64,
+}
+
+impl ScheduleTime {
+    /// Whether the schedule runs in `minute`, which must have no seconds.
+    pub fn matches<Tz: TimeZone>(&self, minute: &DateTime<Tz>, location: Option<Location>) -> bool {
+        match self {
+            ScheduleTime::Cron(cron) => cron.matches(&minute.naive_local()),
+            ScheduleTime::Sun(sun) => {
+                let Some(location) = location else { return false };
+                let offset = Duration::minutes(sun.offset_minutes);
+
+                // the day of the event, which isn't the day of the minute for offsets that cross midnight
+                let date = (minute.naive_local() - offset).date();
+
+                sun_event(sun.event, date, location)
+                    .and_then(|time| (time.with_timezone(&minute.timezone()) + offset).with_second(0))
+                    .and_then(|time| time.with_nanosecond(0))
+                    .is_some_and(|time| time == *minute)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::schedule::{ScheduleTime, SunSchedule};
+    use crate::sun::{Location, SunEvent};
+
+    const LISBON: Location = Location { latitude: 38.72, longitude: -9.14 };
+
+    #[test]
+    fn test_sun_schedule() {
+        // sunset in Lisbon on 2024-12-21 is at 17:18 UTC
+        let before_sunset = ScheduleTime::Sun(SunSchedule { event: SunEvent::Sunset, offset_minutes: -30 });
+
+        let matching: Vec<_> = (0..24 * 60)
+            .map(|minute| Utc.with_ymd_and_hms(2024, 12, 21, 0, 0, 0).unwrap() + chrono::Duration::minutes(minute))
+            .filter(|minute| before_sunset.matches(minute, Some(LISBON)))
+            .collect();
+
+        assert_eq!(matching, [Utc.with_ymd_and_hms(2024, 12, 21, 16, 48, 0).unwrap()]);
+        assert!(!before_sunset.matches(&matching[0], None));
+    }
+}
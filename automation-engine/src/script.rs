@@ -0,0 +1,151 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use log::info;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, Scope, AST};
+
+use crate::action::TriggerContext;
+use crate::state::StateStore;
+
+/// How long a script may run before it's stopped.
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Runs the rhai scripts of the rules. Scripts can read the trigger through the `topic` and
+/// `payload` variables, look up the last payload of any subscribed topic with `state(topic)` and
+/// publish messages with `publish(topic, payload)`. They can't access files or import modules and
+/// are stopped when they run for too long.
+pub struct ScriptEngine {
+    engine: Engine,
+    started: Arc<Mutex<Instant>>,
+    published: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+/// A sandboxed engine, without the functions that depend on the application state.
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(1_000_000);
+    engine.set_max_call_levels(16);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(10_000);
+    engine.set_max_array_size(1_000);
+    engine.set_max_map_size(1_000);
+    engine
+}
+
+/// Checks that a script compiles.
+pub fn validate(script: &str) -> anyhow::Result<()> {
+    sandboxed_engine().compile(script)?;
+    Ok(())
+}
+
+impl ScriptEngine {
+    pub fn new(states: StateStore) -> Self {
+        let started = Arc::new(Mutex::new(Instant::now()));
+        let published = Arc::new(Mutex::new(Vec::new()));
+
+        let mut engine = sandboxed_engine();
+
+        let deadline = started.clone();
+        engine.on_progress(move |_| {
+            let started = *deadline.lock().unwrap_or_else(PoisonError::into_inner);
+            (started.elapsed() > TIMEOUT).then(|| format!("Script ran for more than {:?}", TIMEOUT).into())
+        });
+
+        engine.register_fn("state", move |topic: &str| -> Dynamic {
+            states.get(topic).map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        });
+
+        let messages = published.clone();
+        engine.register_fn("publish", move |topic: &str, payload: Dynamic| {
+            messages.lock().unwrap_or_else(PoisonError::into_inner).push((topic.to_string(), payload.to_string()));
+        });
+
+        engine.on_print(|text| info!("[script] {}", text));
+
+        Self { engine, started, published }
+    }
+
+    pub fn compile(&self, script: &str) -> anyhow::Result<AST> {
+        Ok(self.engine.compile(script)?)
+    }
+
+    fn run(&self, script: &AST, context: &TriggerContext) -> Result<Dynamic, String> {
+        *self.started.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+        self.published.lock().unwrap_or_else(PoisonError::into_inner).clear();
+
+        let mut scope = Scope::new();
+        scope.push_constant("topic", context.topic.clone());
+        scope.push_constant("payload", context.payload.trim().to_string());
+
+        self.engine.eval_ast_with_scope::<Dynamic>(&mut scope, script).map_err(|e| e.to_string())
+    }
+
+    /// Runs a condition script, which must evaluate to a bool.
+    pub fn check(&self, script: &AST, context: &TriggerContext) -> Result<bool, String> {
+        let result = self.run(script, context)?;
+        result.as_bool().map_err(|type_name| format!("Condition script returned {} instead of a bool", type_name))
+    }
+
+    /// Runs an action script and returns the messages it published.
+    pub fn execute(&self, script: &AST, context: &TriggerContext) -> Result<Vec<(String, String)>, String> {
+        self.run(script, context)
+            .map(|_| std::mem::take(&mut *self.published.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::action::TriggerContext;
+    use crate::script::{validate, ScriptEngine};
+    use crate::state::StateStore;
+
+    #[test]
+    fn test_condition_and_actions() {
+        let states = StateStore::default();
+        states.set("smart-home-system/hue/hallway-motion/illuminance", "12");
+
+        let engine = ScriptEngine::new(states.clone());
+        let context = TriggerContext { topic: "smart-home-system/zigbee/desk/brightness".into(), payload: "42\n".into() };
+
+        let condition = engine.compile(r#"parse_int(state("smart-home-system/hue/hallway-motion/illuminance")) < 20"#).unwrap();
+        assert_eq!(engine.check(&condition, &context), Ok(true));
+
+        states.set("smart-home-system/hue/hallway-motion/illuminance", "250");
+        assert_eq!(engine.check(&condition, &context), Ok(false));
+
+        let unknown = engine.compile(r#"state("smart-home-system/unknown") == ()"#).unwrap();
+        assert_eq!(engine.check(&unknown, &context), Ok(true));
+
+        let script = engine.compile(r#"
+            let brightness = parse_int(payload);
+            publish("smart-home-system/hue/desk/brightness/set", brightness * 2);
+            if topic.ends_with("/brightness") { publish("smart-home-system/hue/desk/power/set", "on"); }
+        "#).unwrap();
+
+        assert_eq!(engine.execute(&script, &context), Ok(vec![
+            ("smart-home-system/hue/desk/brightness/set".to_string(), "84".to_string()),
+            ("smart-home-system/hue/desk/power/set".to_string(), "on".to_string()),
+        ]));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let engine = ScriptEngine::new(StateStore::default());
+        let context = TriggerContext::default();
+
+        let endless = engine.compile("loop {}").unwrap();
+        assert!(engine.check(&endless, &context).is_err());
+
+        let not_bool = engine.compile("42").unwrap();
+        assert!(engine.check(&not_bool, &context).is_err());
+
+        let import = engine.compile(r#"import "secrets" as secrets; true"#).unwrap();
+        assert!(engine.check(&import, &context).is_err());
+
+        assert!(validate("eval(\"true\")").is_err());
+        assert!(validate("let x = ").is_err());
+    }
+}
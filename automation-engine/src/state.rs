@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+/// The last payload received on every topic. Cloning it gives another handle to the same store,
+/// so scripts can look up states while the application keeps updating them.
+#[derive(Clone, Default)]
+pub struct StateStore(Arc<RwLock<HashMap<String, String>>>);
+
+impl StateStore {
+    pub fn get(&self, topic: &str) -> Option<String> {
+        self.read().get(topic).cloned()
+    }
+
+    pub fn set(&self, topic: &str, payload: &str) {
+        self.0.write().unwrap_or_else(PoisonError::into_inner).insert(topic.to_string(), payload.to_string());
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, HashMap<String, String>> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+
+/// Julian day of 2000-01-01 12:00 UTC.
+const J2000: f64 = 2451545.0;
+/// Julian day of 1970-01-01 00:00 UTC.
+const UNIX_EPOCH: f64 = 2440587.5;
+/// Tilt of the earth's axis, in degrees.
+const OBLIQUITY: f64 = 23.4397;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+fn sin(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn from_julian_day(julian_day: f64) -> Option<DateTime<Utc>> {
+    let millis = ((julian_day - UNIX_EPOCH) * 86_400_000.0).round() as i64;
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+/// The mean anomaly and ecliptic longitude of the sun, in degrees, `days` after J2000.
+fn sun_longitude(days: f64) -> (f64, f64) {
+    let mean_anomaly = (357.5291 + 0.98560028 * days).rem_euclid(360.0);
+    let center = 1.9148 * sin(mean_anomaly) + 0.02 * sin(2.0 * mean_anomaly) + 0.0003 * sin(3.0 * mean_anomaly);
+    let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    (mean_anomaly, ecliptic_longitude)
+}
+
+/// Angle of the sun above the horizon at `time`, in degrees. Negative while the sun is down.
+pub fn sun_elevation(time: DateTime<Utc>, location: Location) -> f64 {
+    let days = time.timestamp_millis() as f64 / 86_400_000.0 + UNIX_EPOCH - J2000;
+    let (_, ecliptic_longitude) = sun_longitude(days);
+
+    let declination = (sin(ecliptic_longitude) * sin(OBLIQUITY)).asin();
+    let right_ascension = (sin(ecliptic_longitude) * OBLIQUITY.to_radians().cos()).atan2(ecliptic_longitude.to_radians().cos());
+    let sidereal_time = (280.1470 + 360.9856235 * days + location.longitude).to_radians();
+    let hour_angle = sidereal_time - right_ascension;
+
+    let latitude = location.latitude.to_radians();
+    (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos()).asin().to_degrees()
+}
+
+/// Time of the sunrise or sunset on `date` at `location`, using the sunrise equation.
+/// Accurate to about a minute. `None` in polar day or night.
+pub fn sun_event(event: SunEvent, date: NaiveDate, location: Location) -> Option<DateTime<Utc>> {
+    let days = (date - NaiveDate::from_ymd_opt(2000, 1, 1)?).num_days() as f64;
+
+    // mean solar time
+    let mean_solar_noon = days - location.longitude / 360.0;
+    let (mean_anomaly, ecliptic_longitude) = sun_longitude(mean_solar_noon);
+    let solar_transit = J2000 + mean_solar_noon + 0.0053 * sin(mean_anomaly) - 0.0069 * sin(2.0 * ecliptic_longitude);
+
+    let declination = (sin(ecliptic_longitude) * sin(OBLIQUITY)).asin();
+    let latitude = location.latitude.to_radians();
+
+    // -0.833° accounts for refraction and the size of the sun's disc
+    let cos_hour_angle = (sin(-0.833) - latitude.sin() * declination.sin()) / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+
+    match event {
+        SunEvent::Sunrise => from_julian_day(solar_transit - hour_angle / 360.0),
+        SunEvent::Sunset => from_julian_day(solar_transit + hour_angle / 360.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    use crate::sun::{sun_elevation, sun_event, Location, SunEvent};
+
+    const LISBON: Location = Location { latitude: 38.72, longitude: -9.14 };
+
+    fn assert_close(actual: Option<chrono::DateTime<Utc>>, expected: chrono::DateTime<Utc>) {
+        let difference = (actual.unwrap() - expected).num_seconds().abs();
+        assert!(difference < 120, "{:?} is not close to {}", actual, expected);
+    }
+
+    #[test]
+    fn test_sun_event() {
+        let summer = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        assert_close(sun_event(SunEvent::Sunrise, summer, LISBON), Utc.with_ymd_and_hms(2024, 6, 21, 5, 12, 0).unwrap());
+        assert_close(sun_event(SunEvent::Sunset, summer, LISBON), Utc.with_ymd_and_hms(2024, 6, 21, 20, 5, 0).unwrap());
+
+        let winter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert_close(sun_event(SunEvent::Sunrise, winter, LISBON), Utc.with_ymd_and_hms(2024, 12, 21, 7, 51, 0).unwrap());
+        assert_close(sun_event(SunEvent::Sunset, winter, LISBON), Utc.with_ymd_and_hms(2024, 12, 21, 17, 18, 0).unwrap());
+    }
+
+    #[test]
+    fn test_sun_elevation() {
+        let noon = sun_elevation(Utc.with_ymd_and_hms(2024, 6, 21, 12, 38, 0).unwrap(), LISBON);
+        assert!((noon - 74.7).abs() < 0.5, "{}", noon);
+
+        let sunset = sun_elevation(Utc.with_ymd_and_hms(2024, 6, 21, 20, 5, 0).unwrap(), LISBON);
+        assert!((sunset + 0.833).abs() < 0.5, "{}", sunset);
+
+        assert!(sun_elevation(Utc.with_ymd_and_hms(2024, 6, 21, 0, 0, 0).unwrap(), LISBON) < -20.0);
+    }
+
+    #[test]
+    fn test_polar_night() {
+        let tromso = Location { latitude: 69.65, longitude: 18.96 };
+        let winter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert_eq!(sun_event(SunEvent::Sunrise, winter, tromso), None);
+    }
+}
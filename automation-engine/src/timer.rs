@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::action::ActionConfig;
+use crate::MQTT_TIMER_TOPIC_PREFIX;
+
+/// A countdown started and cancelled over mqtt, e.g. to be reminded when the laundry is done.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TimerConfig {
+    pub name: String,
+    /// Minutes the timer runs for when it's started without a duration.
+    pub minutes: u64,
+    /// Run when the timer runs out, not when it's cancelled.
+    #[serde(default)]
+    pub on_finished: Vec<ActionConfig>,
+}
+
+struct Timer {
+    config: TimerConfig,
+    /// When the timer runs out, while it's running.
+    deadline: Option<Instant>,
+    /// The last published remaining minutes.
+    remaining: Option<u64>,
+}
+
+impl Timer {
+    /// The retained `<name>/running` and `<name>/remaining` states, with the minutes left rounded up.
+    fn state(&self, remaining: u64) -> Vec<(String, String)> {
+        let running = if self.deadline.is_some() { "on" } else { "off" };
+
+        vec![
+            (format!("{}/{}/running", MQTT_TIMER_TOPIC_PREFIX, self.config.name), running.to_string()),
+            (format!("{}/{}/remaining", MQTT_TIMER_TOPIC_PREFIX, self.config.name), remaining.to_string()),
+        ]
+    }
+
+    fn remaining(&self, now: Instant) -> u64 {
+        self.deadline.map_or(0, |deadline| (deadline.saturating_duration_since(now).as_secs_f64() / 60.0).ceil() as u64)
+    }
+}
+
+/// Countdown timers.
+///
+/// Every method returns the `(topic, payload)` messages to publish, retained.
+pub struct Timers {
+    timers: Vec<Timer>,
+}
+
+impl Timers {
+    pub fn new(config: Vec<TimerConfig>) -> Self {
+        let timers = config.into_iter().map(|config| Timer { config, deadline: None, remaining: None }).collect();
+        Self { timers }
+    }
+
+    fn find(&mut self, name: &str) -> Option<&mut Timer> {
+        self.timers.iter_mut().find(|timer| timer.config.name == name)
+    }
+
+    pub fn is_running(&self, name: &str) -> Option<bool> {
+        self.timers.iter().find(|timer| timer.config.name == name).map(|timer| timer.deadline.is_some())
+    }
+
+    /// Starts or restarts a timer, for its configured minutes if `minutes` is `None`.
+    /// `None` if there is no timer named `name`.
+    pub fn start(&mut self, name: &str, minutes: Option<u64>, now: Instant) -> Option<Vec<(String, String)>> {
+        let timer = self.find(name)?;
+        let minutes = minutes.unwrap_or(timer.config.minutes);
+
+        info!("Starting timer {} for {} minutes", name, minutes);
+
+        timer.deadline = Some(now + Duration::from_secs(minutes * 60));
+        timer.remaining = Some(minutes);
+        Some(timer.state(minutes))
+    }
+
+    /// Stops a timer without running its actions. `None` if there is no timer named `name`.
+    pub fn cancel(&mut self, name: &str) -> Option<Vec<(String, String)>> {
+        let timer = self.find(name)?;
+
+        if timer.deadline.take().is_some() {
+            info!("Cancelled timer {}", name);
+        }
+
+        timer.remaining = Some(0);
+        Some(timer.state(0))
+    }
+
+    /// The current state of a timer. `None` if there is no timer named `name`.
+    pub fn state(&self, name: &str, now: Instant) -> Option<Vec<(String, String)>> {
+        let timer = self.timers.iter().find(|timer| timer.config.name == name)?;
+        Some(timer.state(timer.remaining(now)))
+    }
+
+    /// Publishes the remaining minutes of the running timers when they change and stops the timers
+    /// that ran out. Returns the messages and the names of the timers that finished.
+    pub fn tick(&mut self, now: Instant) -> (Vec<(String, String)>, Vec<String>) {
+        let mut messages = vec![];
+        let mut finished = vec![];
+
+        for timer in &mut self.timers {
+            let Some(deadline) = timer.deadline else { continue };
+
+            if deadline <= now {
+                info!("Timer {} finished", timer.config.name);
+
+                timer.deadline = None;
+                timer.remaining = Some(0);
+                messages.extend(timer.state(0));
+                finished.push(timer.config.name.clone());
+                continue;
+            }
+
+            let remaining = timer.remaining(now);
+            if timer.remaining != Some(remaining) {
+                timer.remaining = Some(remaining);
+                messages.extend(timer.state(remaining));
+            }
+        }
+
+        (messages, finished)
+    }
+
+    pub fn on_finished(&self, name: &str) -> &[ActionConfig] {
+        self.timers.iter().find(|timer| timer.config.name == name).map_or(&[], |timer| &timer.config.on_finished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::timer::{TimerConfig, Timers};
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn message(attribute: &str, payload: &str) -> (String, String) {
+        (format!("smart-home-system/automation/timer/laundry/{}", attribute), payload.to_string())
+    }
+
+    #[test]
+    fn test_countdown() {
+        let mut timers = Timers::new(vec![TimerConfig { name: "laundry".into(), minutes: 45, on_finished: vec![] }]);
+        let start = Instant::now();
+
+        assert_eq!(timers.start("laundry", Some(3), start), Some(vec![message("running", "on"), message("remaining", "3")]));
+        assert_eq!(timers.tick(start + Duration::from_secs(30)), (vec![], vec![]));
+        assert_eq!(timers.tick(start + MINUTE + Duration::from_secs(30)), (vec![message("running", "on"), message("remaining", "2")], vec![]));
+        assert_eq!(timers.is_running("laundry"), Some(true));
+
+        assert_eq!(timers.tick(start + 3 * MINUTE), (vec![message("running", "off"), message("remaining", "0")], vec!["laundry".to_string()]));
+        assert_eq!(timers.tick(start + 4 * MINUTE), (vec![], vec![]));
+        assert_eq!(timers.is_running("laundry"), Some(false));
+    }
+
+    #[test]
+    fn test_default_minutes_and_cancel() {
+        let mut timers = Timers::new(vec![TimerConfig { name: "laundry".into(), minutes: 45, on_finished: vec![] }]);
+        let start = Instant::now();
+
+        timers.start("laundry", None, start);
+        assert_eq!(timers.state("laundry", start + 10 * MINUTE), Some(vec![message("running", "on"), message("remaining", "35")]));
+
+        assert_eq!(timers.cancel("laundry"), Some(vec![message("running", "off"), message("remaining", "0")]));
+        assert_eq!(timers.tick(start + 45 * MINUTE), (vec![], vec![]));
+
+        assert_eq!(timers.start("dishes", None, start), None);
+    }
+}
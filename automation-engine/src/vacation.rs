@@ -0,0 +1,79 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use rand::Rng;
+use serde::{Deserialize, Deserializer};
+
+/// Replays typical light usage with random offsets while no one is home.
+#[derive(Deserialize, Debug, Clone)]
+pub struct VacationConfig {
+    /// Events happen up to this many minutes before or after their time, different every day.
+    #[serde(default = "default_max_offset_minutes")]
+    pub max_offset_minutes: i64,
+    pub events: Vec<VacationEvent>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct VacationEvent {
+    /// Local time, e.g. `19:30`.
+    #[serde(deserialize_with = "deserialize_time")]
+    pub time: NaiveTime,
+    pub topic: String,
+    pub payload: String,
+}
+
+fn default_max_offset_minutes() -> i64 {
+    20
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&s, "%H:%M").map_err(|_| serde::de::Error::custom(format!("Invalid time '{}', expected HH:MM", s)))
+}
+
+impl VacationConfig {
+    /// When each event happens on `date`, in the order of the events.
+    pub fn plan(&self, date: NaiveDate, rng: &mut impl Rng) -> Vec<NaiveDateTime> {
+        self.events.iter()
+            .map(|event| {
+                let offset = rng.gen_range(-self.max_offset_minutes..=self.max_offset_minutes);
+                date.and_time(event.time) + Duration::minutes(offset)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::vacation::VacationConfig;
+
+    #[test]
+    fn test_plan() {
+        let config: VacationConfig = serde_yaml::from_str("
+            max_offset_minutes: 15
+            events:
+              - time: '19:30'
+                topic: smart-home-system/hue/living-room/power/set
+                payload: 'on'
+              - time: '23:10'
+                topic: smart-home-system/hue/living-room/power/set
+                payload: 'off'
+        ").unwrap();
+
+        let date = NaiveDate::from_ymd_opt(2024, 8, 1).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+
+        for _ in 0..100 {
+            let plan = config.plan(date, &mut rng);
+
+            for (time, event) in plan.iter().zip(&config.events) {
+                let offset = *time - date.and_time(event.time);
+                assert!(offset.abs() <= Duration::minutes(15));
+            }
+        }
+
+        assert!(serde_yaml::from_str::<VacationConfig>("events: [{ time: '25:00', topic: a, payload: b }]").is_err());
+    }
+}
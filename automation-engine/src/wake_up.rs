@@ -0,0 +1,61 @@
+use serde::Deserialize;
+
+use crate::schedule::ScheduleTime;
+
+/// Turns a light on at the minimum brightness and fades it to the target brightness, like a sunrise.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WakeUpConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub time: ScheduleTime,
+    /// Topic prefix of the light, e.g. `smart-home-system/yeelight`. Its controller has to support `brightness/fade`.
+    pub light: String,
+    #[serde(default = "default_target_brightness")]
+    pub target_brightness: u8,
+    #[serde(default = "default_duration_minutes")]
+    pub duration_minutes: u64,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_target_brightness() -> u8 {
+    80
+}
+
+fn default_duration_minutes() -> u64 {
+    20
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl WakeUpConfig {
+    /// Starts the fade. Lights that are off are turned on at the minimum brightness by their controller.
+    pub fn start_message(&self) -> (String, String) {
+        (format!("{}/brightness/fade", self.light), format!("{},{}", self.target_brightness, self.duration_minutes * 60))
+    }
+
+    /// Turning the light off also aborts the fade.
+    pub fn cancel_message(&self) -> (String, String) {
+        (format!("{}/power/set", self.light), "off".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wake_up::WakeUpConfig;
+
+    #[test]
+    fn test_messages() {
+        let wake_up: WakeUpConfig = serde_yaml::from_str("
+            name: weekdays
+            cron: 30 6 * * 1-5
+            light: smart-home-system/yeelight
+            duration_minutes: 30
+        ").unwrap();
+
+        assert_eq!(wake_up.start_message(), ("smart-home-system/yeelight/brightness/fade".to_string(), "80,1800".to_string()));
+        assert_eq!(wake_up.cancel_message(), ("smart-home-system/yeelight/power/set".to_string(), "off".to_string()));
+    }
+}
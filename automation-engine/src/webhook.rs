@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::action::{render, TriggerContext};
+
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An http POST sent when a rule runs, e.g. to an ntfy topic or a Home Assistant webhook.
+/// `{{payload}}` and `{{topic}}` in the url, headers and body are replaced by the payload and topic of the trigger.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    /// How many times a failed request is retried, waiting twice as long before each retry.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_content_type() -> String {
+    "application/json".into()
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+/// The delay before the given retry, starting at 1.
+pub fn retry_delay(retry: u32) -> Duration {
+    Duration::from_secs(1 << (retry - 1).min(6))
+}
+
+impl WebhookConfig {
+    pub fn request(&self, http: &reqwest::Client, context: &TriggerContext) -> reqwest::RequestBuilder {
+        self.headers.iter()
+            .fold(http.post(render(&self.url, context)), |request, (name, value)| request.header(name, render(value, context)))
+            .header(reqwest::header::CONTENT_TYPE, &self.content_type)
+            .body(render(&self.body, context))
+    }
+
+    /// Sends the webhook, retrying when the request fails or the server answers with an error.
+    pub async fn send(&self, http: &reqwest::Client, context: &TriggerContext) -> Result<(), reqwest::Error> {
+        let mut retry = 0;
+
+        loop {
+            let result = async { self.request(http, context).send().await?.error_for_status() }.await;
+
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if retry >= self.retries => return Err(e),
+                Err(e) => {
+                    retry += 1;
+                    warn!("Webhook to {} failed: {}. Retrying in {:?}...", self.url, e, retry_delay(retry));
+                    tokio::time::sleep(retry_delay(retry)).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::action::TriggerContext;
+    use crate::webhook::{retry_delay, WebhookConfig};
+
+    #[test]
+    fn test_request() {
+        let webhook: WebhookConfig = serde_yaml::from_str(r#"
+            url: https://ntfy.sh/home-alerts
+            headers:
+              Title: "Leak at {{topic}}"
+            body: "Sensor reported {{payload}}"
+            content_type: text/plain
+        "#).unwrap();
+
+        let context = TriggerContext { topic: "smart-home-system/zigbee/sink-leak/water_leak".into(), payload: "on".into() };
+        let request = webhook.request(&reqwest::Client::new(), &context).build().unwrap();
+
+        assert_eq!(request.method(), reqwest::Method::POST);
+        assert_eq!(request.url().as_str(), "https://ntfy.sh/home-alerts");
+        assert_eq!(request.headers()["Title"], "Leak at smart-home-system/zigbee/sink-leak/water_leak");
+        assert_eq!(request.headers()["Content-Type"], "text/plain");
+        assert_eq!(request.body().and_then(|body| body.as_bytes()), Some("Sensor reported on".as_bytes()));
+        assert_eq!(webhook.retries, 3);
+
+        assert_eq!(retry_delay(1), Duration::from_secs(1));
+        assert_eq!(retry_delay(3), Duration::from_secs(4));
+        assert_eq!(retry_delay(20), Duration::from_secs(64));
+    }
+}
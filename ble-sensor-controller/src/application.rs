@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use log::{debug, info};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::MQTT_TOPIC_PREFIX;
+use crate::sensors::Reading;
+
+pub struct Application {
+    client: AsyncClient,
+    /// Maps upper-case sensor addresses to the topic segment used for them. When empty,
+    /// every decodable sensor in range is published under its address.
+    names: HashMap<String, String>,
+    last_readings: HashMap<String, Reading>,
+}
+
+/// Parses `A4:C1:38:01:02:03=bedroom,A4:C1:38:04:05:06=kitchen`.
+pub fn parse_names(names: &str) -> Result<HashMap<String, String>, String> {
+    names.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((address, name)) if !name.trim().is_empty() => Ok((address.trim().to_ascii_uppercase(), name.trim().to_string())),
+            _ => Err(format!("Invalid sensor name entry: '{}'. Expected <address>=<name>", entry)),
+        })
+        .collect()
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, names: HashMap<String, String>) -> Self {
+        Self { client, names, last_readings: HashMap::new() }
+    }
+
+    pub fn handle_reading(&mut self, address: &str, reading: Reading) {
+        let address = address.to_ascii_uppercase();
+
+        let name = match self.names.get(&address) {
+            Some(name) => name.clone(),
+            None if self.names.is_empty() => address.replace(':', "").to_ascii_lowercase(),
+            None => {
+                debug!("Ignoring reading from unconfigured sensor {}: {:?}", address, reading);
+                return;
+            }
+        };
+
+        let last = self.last_readings.get(&address);
+
+        if last.map(|last| format!("{:.1}", last.temperature)) != Some(format!("{:.1}", reading.temperature)) {
+            info!("Sensor {} temperature is: {:.1}", name, reading.temperature);
+            self.publish(&name, "temperature", format!("{:.1}", reading.temperature));
+        }
+
+        if last.map(|last| format!("{:.1}", last.humidity)) != Some(format!("{:.1}", reading.humidity)) {
+            info!("Sensor {} humidity is: {:.1}", name, reading.humidity);
+            self.publish(&name, "humidity", format!("{:.1}", reading.humidity));
+        }
+
+        if let Some(battery) = reading.battery.filter(|battery| last.and_then(|last| last.battery) != Some(*battery)) {
+            info!("Sensor {} battery is: {}", name, battery);
+            self.publish(&name, "battery", battery.to_string());
+        }
+
+        self.last_readings.insert(address, reading);
+    }
+
+    fn publish(&self, name: &str, attribute: &str, value: String) {
+        let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, name, attribute);
+        self.client.publish(Message::new_retained(topic, value, 1));
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter};
+use btleplug::platform::{Adapter, Manager, PeripheralId};
+use futures::StreamExt;
+use log::{info, warn};
+
+use crate::application::{parse_names, Application};
+use crate::mqtt::connect_mqtt;
+use crate::sensors::{decode_manufacturer_data, decode_service_data};
+
+mod sensors;
+mod application;
+mod mqtt;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/ble";
+
+async fn address_of(adapter: &Adapter, id: &PeripheralId) -> Option<String> {
+    match adapter.peripheral(id).await {
+        Ok(peripheral) => Some(peripheral.address().to_string()),
+        Err(e) => {
+            warn!("Failed to look up peripheral {:?}: {}", id, e);
+            None
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let names = match std::env::var("BLE_SENSOR_NAMES") {
+        Ok(names) => parse_names(&names).map_err(anyhow::Error::msg).context("Invalid BLE_SENSOR_NAMES")?,
+        Err(_) => HashMap::new(),
+    };
+
+    let (client, _stream) = connect_mqtt(
+        &[],
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting ble sensor controller");
+
+    let manager = Manager::new().await.context("Failed to access bluetooth")?;
+    let adapter = manager.adapters().await?
+        .into_iter()
+        .next()
+        .context("No bluetooth adapter found")?;
+
+    info!("Using bluetooth adapter {}", adapter.adapter_info().await.unwrap_or_default());
+
+    let mut application = Application::new(client, names);
+
+    let mut events = adapter.events().await?;
+    adapter.start_scan(ScanFilter::default()).await.context("Failed to start bluetooth scan")?;
+
+    info!("Listening for sensor advertisements...");
+
+    while let Some(event) = events.next().await {
+        match event {
+            CentralEvent::ServiceDataAdvertisement { id, service_data } => {
+                for (uuid, data) in service_data {
+                    if let Some(reading) = decode_service_data(&uuid, &data) {
+                        if let Some(address) = address_of(&adapter, &id).await {
+                            application.handle_reading(&address, reading);
+                        }
+                    }
+                }
+            }
+            CentralEvent::ManufacturerDataAdvertisement { id, manufacturer_data } => {
+                for (company_id, data) in manufacturer_data {
+                    if let Some(reading) = decode_manufacturer_data(company_id, &data) {
+                        if let Some(address) = address_of(&adapter, &id).await {
+                            application.handle_reading(&address, reading);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use uuid::Uuid;
+
+/// Environmental Sensing service, used by the ATC1441 and pvvx custom firmwares for the LYWSD03MMC.
+pub const ENVIRONMENTAL_SENSING_UUID: Uuid = Uuid::from_u128(0x0000181a_0000_1000_8000_00805f9b34fb);
+
+/// Bluetooth SIG company id used by Govee thermometers.
+pub const GOVEE_COMPANY_ID: u16 = 0xEC88;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    /// Degrees Celsius.
+    pub temperature: f32,
+    /// Relative humidity in percent.
+    pub humidity: f32,
+    /// Battery level in percent.
+    pub battery: Option<u8>,
+}
+
+/// Decodes the advertisements of a LYWSD03MMC running the ATC1441 or pvvx firmware.
+/// The stock firmware encrypts its advertisements and is not supported.
+pub fn decode_service_data(uuid: &Uuid, data: &[u8]) -> Option<Reading> {
+    if *uuid != ENVIRONMENTAL_SENSING_UUID {
+        return None;
+    }
+
+    match data.len() {
+        // ATC1441: mac[6] (big endian), temperature i16 BE (0.1 °C), humidity u8, battery u8, battery mV u16, counter u8
+        13 => Some(Reading {
+            temperature: i16::from_be_bytes([data[6], data[7]]) as f32 / 10.0,
+            humidity: data[8] as f32,
+            battery: Some(data[9].min(100)),
+        }),
+        // pvvx: mac[6] (little endian), temperature i16 LE (0.01 °C), humidity u16 LE (0.01 %), battery mV u16, battery u8, counter u8, flags u8
+        15 => Some(Reading {
+            temperature: i16::from_le_bytes([data[6], data[7]]) as f32 / 100.0,
+            humidity: u16::from_le_bytes([data[8], data[9]]) as f32 / 100.0,
+            battery: Some(data[12].min(100)),
+        }),
+        _ => None,
+    }
+}
+
+/// Decodes the manufacturer data of Govee H5075/H5072 (6 bytes) and H5074/H5051 (7 bytes) thermometers.
+pub fn decode_manufacturer_data(company_id: u16, data: &[u8]) -> Option<Reading> {
+    if company_id != GOVEE_COMPANY_ID {
+        return None;
+    }
+
+    match data.len() {
+        // 0x00, 24-bit packed temperature/humidity (sign in the top bit), battery
+        6 => {
+            let packed = u32::from_be_bytes([0, data[1], data[2], data[3]]);
+            let negative = packed & 0x800000 != 0;
+            let packed = packed & 0x7FFFFF;
+
+            let temperature = (packed / 1000) as f32 / 10.0;
+
+            Some(Reading {
+                temperature: if negative { -temperature } else { temperature },
+                humidity: (packed % 1000) as f32 / 10.0,
+                battery: Some(data[4].min(100)),
+            })
+        }
+        // 0x00, temperature i16 LE (0.01 °C), humidity u16 LE (0.01 %), battery
+        7 => Some(Reading {
+            temperature: i16::from_le_bytes([data[1], data[2]]) as f32 / 100.0,
+            humidity: u16::from_le_bytes([data[3], data[4]]) as f32 / 100.0,
+            battery: Some(data[5].min(100)),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::sensors::{decode_manufacturer_data, decode_service_data, ENVIRONMENTAL_SENSING_UUID, GOVEE_COMPANY_ID, Reading};
+
+    #[test]
+    fn test_decode_atc1441() {
+        let data = [0xA4, 0xC1, 0x38, 0x01, 0x02, 0x03, 0x00, 0xD7, 0x37, 0x5A, 0x0B, 0xB8, 0x01];
+
+        assert_eq!(decode_service_data(&ENVIRONMENTAL_SENSING_UUID, &data),
+                   Some(Reading { temperature: 21.5, humidity: 55.0, battery: Some(90) }));
+    }
+
+    #[test]
+    fn test_decode_pvvx() {
+        let data = [0x03, 0x02, 0x01, 0x38, 0xC1, 0xA4, 0x66, 0x08, 0x7C, 0x15, 0xB8, 0x0B, 0x5A, 0x01, 0x04];
+
+        assert_eq!(decode_service_data(&ENVIRONMENTAL_SENSING_UUID, &data),
+                   Some(Reading { temperature: 21.5, humidity: 55.0, battery: Some(90) }));
+    }
+
+    #[test]
+    fn test_decode_unknown_service_data() {
+        let data = [0; 13];
+
+        assert_eq!(decode_service_data(&Uuid::nil(), &data), None);
+        assert_eq!(decode_service_data(&ENVIRONMENTAL_SENSING_UUID, &data[..10]), None);
+    }
+
+    #[test]
+    fn test_decode_govee_h5075() {
+        // 215550 -> 21.5 °C, 55.0 %
+        let data = [0x00, 0x03, 0x49, 0xFE, 0x5A, 0x00];
+        assert_eq!(decode_manufacturer_data(GOVEE_COMPANY_ID, &data),
+                   Some(Reading { temperature: 21.5, humidity: 55.0, battery: Some(90) }));
+
+        // negative temperatures set the top bit: -5.2 °C, 40.0 %
+        let data = [0x00, 0x80, 0xCC, 0xB0, 0x50, 0x00];
+        assert_eq!(decode_manufacturer_data(GOVEE_COMPANY_ID, &data),
+                   Some(Reading { temperature: -5.2, humidity: 40.0, battery: Some(80) }));
+    }
+
+    #[test]
+    fn test_decode_govee_h5074() {
+        let data = [0x00, 0x66, 0x08, 0x7C, 0x15, 0x5A, 0x02];
+
+        assert_eq!(decode_manufacturer_data(GOVEE_COMPANY_ID, &data),
+                   Some(Reading { temperature: 21.5, humidity: 55.0, battery: Some(90) }));
+        assert_eq!(decode_manufacturer_data(0x004C, &data), None);
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use domain_state::error;
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::{discovery, MQTT_APP_PUBLISH_TOPIC, MQTT_ARTIST_PUBLISH_TOPIC, MQTT_ERROR_TOPIC, MQTT_MUTED_PUBLISH_TOPIC, MQTT_STATE_PUBLISH_TOPIC, MQTT_TITLE_PUBLISH_TOPIC, MQTT_VOLUME_PUBLISH_TOPIC};
+use crate::cast::{CastMessage, DEFAULT_RECEIVER_ID, MediaMetadata, MediaStatus, NAMESPACE_CONNECTION, NAMESPACE_HEARTBEAT, NAMESPACE_MEDIA, NAMESPACE_RECEIVER, ReceiverStatus};
+use crate::connection::Connection;
+
+pub struct Application {
+    client: AsyncClient,
+    filter: DeviceFilters,
+    connection: Connection,
+    messages: mpsc::Receiver<CastMessage>,
+    receiver_status: ReceiverStatus,
+    /// Transport id of the application whose media channel is open.
+    media_transport_id: Option<String>,
+    media_status: Option<MediaStatus>,
+    metadata: Option<MediaMetadata>,
+    published: HashMap<&'static str, String>,
+}
+
+#[derive(Debug)]
+pub struct DeviceFilters {
+    pub id: Option<String>,
+    pub name: Option<String>,
+}
+
+impl DeviceFilters {
+    fn matches(&self, device: &discovery::DiscoveryResponse) -> bool {
+        self.id.iter().all(|id| device.id == *id) &&
+            self.name.iter().all(|name| device.name == *name)
+    }
+}
+
+impl Application {
+    pub async fn new(client: AsyncClient, filter: DeviceFilters) -> Self {
+        let (connection, messages) = Self::connect(&filter).await;
+
+        Self {
+            client,
+            filter,
+            connection,
+            messages,
+            receiver_status: ReceiverStatus::default(),
+            media_transport_id: None,
+            media_status: None,
+            metadata: None,
+            published: HashMap::new(),
+        }
+    }
+
+    async fn connect(filter: &DeviceFilters) -> (Connection, mpsc::Receiver<CastMessage>) {
+        loop {
+            match Self::try_connect(filter).await {
+                Ok(Some(connection)) => return connection,
+                Ok(None) => warn!("No cast device found matching filter {filter:?}. Retrying in 30 seconds..."),
+                Err(e) => warn!("Failed to connect to cast device: {}. Retrying in 30 seconds...", e),
+            }
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+
+    async fn try_connect(filter: &DeviceFilters) -> anyhow::Result<Option<(Connection, mpsc::Receiver<CastMessage>)>> {
+        let discovery = discovery::discover(Duration::from_secs(3)).await?;
+
+        let Some(device) = discovery.into_iter().find(|device| filter.matches(device)) else {
+            return Ok(None);
+        };
+
+        info!("Using cast device {} at {}...", device.name, device.address);
+
+        let (mut connection, messages) = Connection::connect(&device.address).await?;
+        connection.open_channel(DEFAULT_RECEIVER_ID).await?;
+        connection.request(DEFAULT_RECEIVER_ID, NAMESPACE_RECEIVER, json!({ "type": "GET_STATUS" })).await?;
+
+        Ok(Some((connection, messages)))
+    }
+
+    pub async fn reconnect(&mut self) {
+        let (connection, messages) = Self::connect(&self.filter).await;
+
+        self.connection = connection;
+        self.messages = messages;
+        self.media_transport_id = None;
+        self.media_status = None;
+        self.metadata = None;
+    }
+
+    /// Waits for the next message from the device. Returns `None` once the connection is lost.
+    pub async fn next_cast_message(&mut self) -> Option<CastMessage> {
+        self.messages.recv().await
+    }
+
+    pub async fn handle_cast_message(&mut self, message: CastMessage) {
+        let Some(message_type) = message.message_type() else {
+            warn!("Received cast message without a type: {:?}", message);
+            return;
+        };
+
+        let result = match (message.namespace.as_str(), message_type.as_str()) {
+            (NAMESPACE_HEARTBEAT, "PING") =>
+                self.connection.send(&message.source_id, NAMESPACE_HEARTBEAT, json!({ "type": "PONG" })).await,
+            (NAMESPACE_HEARTBEAT, "PONG") => Ok(()),
+            (NAMESPACE_CONNECTION, "CLOSE") => {
+                if self.media_transport_id.as_deref() == Some(message.source_id.as_str()) {
+                    self.media_transport_id = None;
+                }
+                Ok(())
+            }
+            (NAMESPACE_RECEIVER, "RECEIVER_STATUS") => match ReceiverStatus::parse(&message.payload) {
+                Ok(status) => self.handle_receiver_status(status).await,
+                Err(e) => {
+                    error!("Received invalid receiver status: {}", e);
+                    Ok(())
+                }
+            },
+            (NAMESPACE_MEDIA, "MEDIA_STATUS") => {
+                match MediaStatus::parse(&message.payload) {
+                    Ok(status) => self.handle_media_status(status),
+                    Err(e) => error!("Received invalid media status: {}", e),
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to respond to cast device: {}", e);
+        }
+
+        self.publish_state(false);
+    }
+
+    async fn handle_receiver_status(&mut self, status: ReceiverStatus) -> anyhow::Result<()> {
+        let transport_id = status.active_application().map(|application| application.transport_id.clone());
+        self.receiver_status = status;
+
+        if transport_id == self.media_transport_id {
+            return Ok(());
+        }
+
+        self.media_transport_id = transport_id.clone();
+        self.media_status = None;
+        self.metadata = None;
+
+        if let Some(transport_id) = transport_id {
+            self.connection.open_channel(&transport_id).await?;
+            self.connection.request(&transport_id, NAMESPACE_MEDIA, json!({ "type": "GET_STATUS" })).await?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_media_status(&mut self, status: Option<MediaStatus>) {
+        if let Some(metadata) = status.as_ref().and_then(|status| status.media.as_ref()).and_then(|media| media.metadata.clone()) {
+            self.metadata = Some(metadata);
+        }
+
+        if status.is_none() {
+            self.metadata = None;
+        }
+
+        self.media_status = status;
+    }
+
+    pub async fn heartbeat(&mut self) {
+        if let Err(e) = self.connection.send(DEFAULT_RECEIVER_ID, NAMESPACE_HEARTBEAT, json!({ "type": "PING" })).await {
+            error!("Failed to send heartbeat to cast device: {}", e);
+        }
+    }
+
+    fn publish_state(&mut self, force: bool) {
+        let volume = &self.receiver_status.volume;
+        let application = self.receiver_status.active_application();
+
+        let state = self.media_status.as_ref()
+            .map(|status| status.player_state.to_ascii_lowercase())
+            .unwrap_or_else(|| "idle".into());
+
+        let values = [
+            (MQTT_STATE_PUBLISH_TOPIC, state),
+            (MQTT_VOLUME_PUBLISH_TOPIC, volume.level.map(|level| ((level * 100.0).round() as u8).to_string()).unwrap_or_default()),
+            (MQTT_MUTED_PUBLISH_TOPIC, if volume.muted.unwrap_or(false) { "on" } else { "off" }.to_string()),
+            (MQTT_APP_PUBLISH_TOPIC, application.map(|application| application.display_name.clone()).unwrap_or_default()),
+            (MQTT_TITLE_PUBLISH_TOPIC, self.metadata.as_ref().and_then(|metadata| metadata.title.clone()).unwrap_or_default()),
+            (MQTT_ARTIST_PUBLISH_TOPIC, self.metadata.as_ref()
+                .and_then(|metadata| metadata.artist.clone().or_else(|| metadata.series_title.clone()))
+                .unwrap_or_default()),
+        ];
+
+        for (topic, value) in values {
+            if !force && self.published.get(topic) == Some(&value) {
+                continue;
+            }
+
+            info!("[{}] Publishing '{}'", topic, value);
+            self.client.publish(Message::new_retained(topic, value.clone(), 1));
+            self.published.insert(topic, value);
+        }
+    }
+
+    pub async fn handle_mqtt_set_state(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        let command = match payload.trim() {
+            "play" => "PLAY",
+            "pause" => "PAUSE",
+            "stop" => "STOP",
+            _ => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected play, pause or stop");
+                return;
+            }
+        };
+
+        let (Some(transport_id), Some(status)) = (self.media_transport_id.clone(), self.media_status.as_ref()) else {
+            warn!("[{}] Nothing is playing on the cast device", message.topic());
+            return;
+        };
+
+        info!("[{}] Sending {} to cast device", message.topic(), command);
+
+        let payload = json!({ "type": command, "mediaSessionId": status.media_session_id });
+        if let Err(e) = self.connection.request(&transport_id, NAMESPACE_MEDIA, payload).await {
+            error!("[{}] Failed to send {} to cast device: {}", message.topic(), command, e);
+        }
+    }
+
+    pub async fn handle_mqtt_set_volume(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        let Some(volume) = payload.trim().parse::<u8>().ok().filter(|volume| *volume <= 100) else {
+            error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a volume from 0 to 100");
+            return;
+        };
+
+        info!("[{}] Setting volume to {}", message.topic(), volume);
+        self.set_volume(json!({ "level": volume as f32 / 100.0 }), message).await;
+    }
+
+    pub async fn handle_mqtt_set_muted(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        let muted = match payload.trim() {
+            "on" | "true" | "1" => true,
+            "off" | "false" | "0" => false,
+            _ => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected on or off");
+                return;
+            }
+        };
+
+        info!("[{}] Setting muted to {}", message.topic(), muted);
+        self.set_volume(json!({ "muted": muted }), message).await;
+    }
+
+    async fn set_volume(&mut self, volume: serde_json::Value, message: &Message) {
+        let payload = json!({ "type": "SET_VOLUME", "volume": volume });
+        if let Err(e) = self.connection.request(DEFAULT_RECEIVER_ID, NAMESPACE_RECEIVER, payload).await {
+            error!("[{}] Failed to set volume of cast device: {}", message.topic(), e);
+        }
+    }
+
+    pub async fn handle_mqtt_get_state(&mut self) {
+        if let Err(e) = self.connection.request(DEFAULT_RECEIVER_ID, NAMESPACE_RECEIVER, json!({ "type": "GET_STATUS" })).await {
+            error!("Failed to request status of cast device: {}", e);
+        }
+
+        self.publish_state(true);
+    }
+}
@@ -0,0 +1,266 @@
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+pub const NAMESPACE_CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+pub const NAMESPACE_HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+pub const NAMESPACE_RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+pub const NAMESPACE_MEDIA: &str = "urn:x-cast:com.google.cast.media";
+
+pub const DEFAULT_SENDER_ID: &str = "sender-0";
+pub const DEFAULT_RECEIVER_ID: &str = "receiver-0";
+
+/// The `CastMessage` protobuf exchanged with cast devices. Only utf-8 payloads are supported,
+/// which is all the connection, heartbeat, receiver and media namespaces use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastMessage {
+    pub source_id: String,
+    pub destination_id: String,
+    pub namespace: String,
+    pub payload: String,
+}
+
+const FIELD_PROTOCOL_VERSION: u64 = 1;
+const FIELD_SOURCE_ID: u64 = 2;
+const FIELD_DESTINATION_ID: u64 = 3;
+const FIELD_NAMESPACE: u64 = 4;
+const FIELD_PAYLOAD_TYPE: u64 = 5;
+const FIELD_PAYLOAD_UTF8: u64 = 6;
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_FIXED64: u64 = 1;
+const WIRE_TYPE_LENGTH_DELIMITED: u64 = 2;
+const WIRE_TYPE_FIXED32: u64 = 5;
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*position).context("Truncated varint")?;
+        *position += 1;
+
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("Varint is too long")
+}
+
+fn write_string(buffer: &mut Vec<u8>, field: u64, value: &str) {
+    write_varint(buffer, field << 3 | WIRE_TYPE_LENGTH_DELIMITED);
+    write_varint(buffer, value.len() as u64);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+impl CastMessage {
+    pub fn new(source_id: &str, destination_id: &str, namespace: &str, payload: String) -> Self {
+        Self {
+            source_id: source_id.to_string(),
+            destination_id: destination_id.to_string(),
+            namespace: namespace.to_string(),
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.payload.len() + 64);
+
+        // protocol_version and payload_type are required proto2 fields, so they're always written
+        write_varint(&mut buffer, FIELD_PROTOCOL_VERSION << 3 | WIRE_TYPE_VARINT);
+        write_varint(&mut buffer, 0); // CASTV2_1_0
+        write_string(&mut buffer, FIELD_SOURCE_ID, &self.source_id);
+        write_string(&mut buffer, FIELD_DESTINATION_ID, &self.destination_id);
+        write_string(&mut buffer, FIELD_NAMESPACE, &self.namespace);
+        write_varint(&mut buffer, FIELD_PAYLOAD_TYPE << 3 | WIRE_TYPE_VARINT);
+        write_varint(&mut buffer, 0); // STRING
+        write_string(&mut buffer, FIELD_PAYLOAD_UTF8, &self.payload);
+
+        buffer
+    }
+
+    pub fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        let mut message = CastMessage::new("", "", "", String::new());
+        let mut position = 0;
+
+        while position < data.len() {
+            let key = read_varint(data, &mut position)?;
+
+            match key & 0x07 {
+                WIRE_TYPE_VARINT => {
+                    read_varint(data, &mut position)?;
+                }
+                WIRE_TYPE_LENGTH_DELIMITED => {
+                    let length = read_varint(data, &mut position)? as usize;
+                    let value = data.get(position..position + length).context("Truncated field")?;
+                    position += length;
+
+                    let value = || String::from_utf8(value.to_vec()).context("Field is not valid utf-8");
+
+                    match key >> 3 {
+                        FIELD_SOURCE_ID => message.source_id = value()?,
+                        FIELD_DESTINATION_ID => message.destination_id = value()?,
+                        FIELD_NAMESPACE => message.namespace = value()?,
+                        FIELD_PAYLOAD_UTF8 => message.payload = value()?,
+                        _ => {}
+                    }
+                }
+                WIRE_TYPE_FIXED64 => position += 8,
+                WIRE_TYPE_FIXED32 => position += 4,
+                wire_type => bail!("Unsupported wire type {}", wire_type),
+            }
+        }
+
+        if position > data.len() {
+            bail!("Truncated field");
+        }
+
+        Ok(message)
+    }
+
+    pub fn message_type(&self) -> Option<String> {
+        #[derive(Deserialize)]
+        struct Payload {
+            #[serde(rename = "type")]
+            message_type: String,
+        }
+
+        serde_json::from_str::<Payload>(&self.payload).ok().map(|payload| payload.message_type)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Volume {
+    pub level: Option<f32>,
+    pub muted: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Application {
+    pub app_id: String,
+    pub display_name: String,
+    pub transport_id: String,
+    pub session_id: String,
+    #[serde(default)]
+    pub is_idle_screen: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ReceiverStatus {
+    #[serde(default)]
+    pub applications: Vec<Application>,
+    #[serde(default)]
+    pub volume: Volume,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReceiverStatusPayload {
+    status: ReceiverStatus,
+}
+
+impl ReceiverStatus {
+    pub fn parse(payload: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str::<ReceiverStatusPayload>(payload)?.status)
+    }
+
+    /// The application that's casting, ignoring the backdrop shown when nothing is.
+    pub fn active_application(&self) -> Option<&Application> {
+        self.applications.iter().find(|application| !application.is_idle_screen)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub series_title: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Media {
+    #[serde(default)]
+    pub metadata: Option<MediaMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaStatus {
+    pub media_session_id: i64,
+    pub player_state: String,
+    /// Only sent when the media changes, so it must be kept from earlier statuses.
+    pub media: Option<Media>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaStatusPayload {
+    status: Vec<MediaStatus>,
+}
+
+impl MediaStatus {
+    pub fn parse(payload: &str) -> anyhow::Result<Option<Self>> {
+        Ok(serde_json::from_str::<MediaStatusPayload>(payload)?.status.into_iter().next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cast::{CastMessage, MediaStatus, NAMESPACE_RECEIVER, ReceiverStatus, Volume};
+
+    #[test]
+    fn test_encode_decode() {
+        let message = CastMessage::new("sender-0", "receiver-0", NAMESPACE_RECEIVER,
+                                       r#"{"type":"GET_STATUS","requestId":1}"#.to_string());
+
+        let encoded = message.encode();
+
+        assert_eq!(&encoded[..4], &[0x08, 0x00, 0x12, 0x08]);
+        assert_eq!(CastMessage::decode(&encoded).unwrap(), message);
+    }
+
+    #[test]
+    fn test_decode_skips_unknown_fields() {
+        let mut data = CastMessage::new("a", "b", "c", "d".to_string()).encode();
+        // payload_binary (field 7), which is never set for utf-8 payloads
+        data.extend_from_slice(&[0x3A, 0x02, 0xFF, 0xFF]);
+
+        assert_eq!(CastMessage::decode(&data).unwrap(), CastMessage::new("a", "b", "c", "d".to_string()));
+        assert!(CastMessage::decode(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_receiver_status() {
+        let payload = r#"{"requestId":1,"status":{"applications":[{"appId":"CC1AD845","displayName":"Default Media Receiver",
+            "isIdleScreen":false,"sessionId":"7E2FF513","transportId":"7E2FF513","statusText":"Ready To Cast"}],
+            "volume":{"controlType":"attenuation","level":0.25,"muted":false,"stepInterval":0.05}},"type":"RECEIVER_STATUS"}"#;
+
+        let status = ReceiverStatus::parse(payload).unwrap();
+
+        assert_eq!(status.volume, Volume { level: Some(0.25), muted: Some(false) });
+        assert_eq!(status.active_application().unwrap().transport_id, "7E2FF513");
+        assert_eq!(CastMessage::new("", "", "", payload.to_string()).message_type().as_deref(), Some("RECEIVER_STATUS"));
+    }
+
+    #[test]
+    fn test_parse_media_status() {
+        let payload = r#"{"type":"MEDIA_STATUS","status":[{"mediaSessionId":1,"playerState":"PLAYING","currentTime":12.5,
+            "media":{"contentId":"x","metadata":{"metadataType":3,"title":"Song","artist":"Band"}}}],"requestId":0}"#;
+
+        let status = MediaStatus::parse(payload).unwrap().unwrap();
+
+        assert_eq!(status.media_session_id, 1);
+        assert_eq!(status.player_state, "PLAYING");
+        assert_eq!(status.media.unwrap().metadata.unwrap().title.as_deref(), Some("Song"));
+
+        assert_eq!(MediaStatus::parse(r#"{"type":"MEDIA_STATUS","status":[]}"#).unwrap(), None);
+    }
+}
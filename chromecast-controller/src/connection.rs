@@ -0,0 +1,100 @@
+use std::pin::Pin;
+
+use anyhow::Context;
+use log::{debug, warn};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_openssl::SslStream;
+
+use crate::cast::{CastMessage, DEFAULT_SENDER_ID, NAMESPACE_CONNECTION};
+
+/// Cast messages are limited to 64KiB by the protocol.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+pub struct Connection {
+    writer: WriteHalf<SslStream<TcpStream>>,
+    request_id: u64,
+}
+
+impl Connection {
+    /// Connects to the device and returns the channel where every message it sends is delivered.
+    /// The channel closes when the connection is lost.
+    pub async fn connect(address: &str) -> anyhow::Result<(Self, mpsc::Receiver<CastMessage>)> {
+        // Cast devices use self-signed certificates
+        let mut connector = SslConnector::builder(SslMethod::tls())?;
+        connector.set_verify(SslVerifyMode::NONE);
+
+        let ssl = connector.build().configure()?
+            .verify_hostname(false)
+            .use_server_name_indication(false)
+            .into_ssl("")?;
+
+        let stream = TcpStream::connect(address).await
+            .with_context(|| format!("Failed to connect to {}", address))?;
+
+        let mut stream = SslStream::new(ssl, stream)?;
+        Pin::new(&mut stream).connect().await.context("TLS handshake failed")?;
+
+        let (reader, writer) = tokio::io::split(stream);
+
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(Self::read_messages(reader, sender));
+
+        Ok((Self { writer, request_id: 0 }, receiver))
+    }
+
+    async fn read_messages(mut reader: ReadHalf<SslStream<TcpStream>>, sender: mpsc::Sender<CastMessage>) {
+        loop {
+            let message = async {
+                let length = reader.read_u32().await? as usize;
+                anyhow::ensure!(length <= MAX_MESSAGE_SIZE, "Message of {} bytes is too large", length);
+
+                let mut buffer = vec![0; length];
+                reader.read_exact(&mut buffer).await?;
+
+                CastMessage::decode(&buffer)
+            }.await;
+
+            match message {
+                Ok(message) => {
+                    debug!("Received: {:?}", message);
+                    if sender.send(message).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    warn!("Cast connection closed: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub async fn send(&mut self, destination_id: &str, namespace: &str, payload: Value) -> anyhow::Result<()> {
+        let message = CastMessage::new(DEFAULT_SENDER_ID, destination_id, namespace, payload.to_string());
+        debug!("Sending: {:?}", message);
+
+        let encoded = message.encode();
+        self.writer.write_u32(encoded.len() as u32).await?;
+        self.writer.write_all(&encoded).await?;
+        self.writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Sends a request carrying a fresh `requestId`, which the device echoes in its response.
+    pub async fn request(&mut self, destination_id: &str, namespace: &str, mut payload: Value) -> anyhow::Result<()> {
+        self.request_id += 1;
+        payload["requestId"] = json!(self.request_id);
+
+        self.send(destination_id, namespace, payload).await
+    }
+
+    /// Opens a virtual connection, which is needed before talking to the receiver or to an application.
+    pub async fn open_channel(&mut self, destination_id: &str) -> anyhow::Result<()> {
+        self.send(destination_id, NAMESPACE_CONNECTION, json!({ "type": "CONNECT" })).await
+    }
+}
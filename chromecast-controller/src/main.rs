@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info, warn};
+
+use crate::application::{Application, DeviceFilters};
+use crate::mqtt::connect_mqtt;
+
+mod cast;
+mod connection;
+mod application;
+mod mqtt;
+mod discovery;
+
+const MQTT_SET_STATE_TOPIC: &str = "smart-home-system/chromecast/state/set";
+const MQTT_GET_STATE_TOPIC: &str = "smart-home-system/chromecast/state/get";
+const MQTT_STATE_PUBLISH_TOPIC: &str = "smart-home-system/chromecast/state";
+const MQTT_SET_VOLUME_TOPIC: &str = "smart-home-system/chromecast/volume/set";
+const MQTT_GET_VOLUME_TOPIC: &str = "smart-home-system/chromecast/volume/get";
+const MQTT_VOLUME_PUBLISH_TOPIC: &str = "smart-home-system/chromecast/volume";
+const MQTT_SET_MUTED_TOPIC: &str = "smart-home-system/chromecast/muted/set";
+const MQTT_GET_MUTED_TOPIC: &str = "smart-home-system/chromecast/muted/get";
+const MQTT_MUTED_PUBLISH_TOPIC: &str = "smart-home-system/chromecast/muted";
+const MQTT_APP_PUBLISH_TOPIC: &str = "smart-home-system/chromecast/app";
+const MQTT_TITLE_PUBLISH_TOPIC: &str = "smart-home-system/chromecast/title";
+const MQTT_ARTIST_PUBLISH_TOPIC: &str = "smart-home-system/chromecast/artist";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/chromecast/error";
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [
+        MQTT_SET_STATE_TOPIC,
+        MQTT_SET_VOLUME_TOPIC,
+        MQTT_SET_MUTED_TOPIC,
+        MQTT_GET_STATE_TOPIC,
+        MQTT_GET_VOLUME_TOPIC,
+        MQTT_GET_MUTED_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting chromecast controller");
+
+    let mut application = Application::new(client, DeviceFilters {
+        id: std::env::var("CHROMECAST_ID").ok(),
+        name: std::env::var("CHROMECAST_NAME").ok(),
+    }).await;
+
+    info!("Connected to cast device.");
+
+    info!("Waiting for mqtt messages...");
+
+    let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    match message.topic() {
+                        MQTT_SET_STATE_TOPIC => application.handle_mqtt_set_state(&message).await,
+                        MQTT_SET_VOLUME_TOPIC => application.handle_mqtt_set_volume(&message).await,
+                        MQTT_SET_MUTED_TOPIC => application.handle_mqtt_set_muted(&message).await,
+                        MQTT_GET_STATE_TOPIC | MQTT_GET_VOLUME_TOPIC | MQTT_GET_MUTED_TOPIC =>
+                            application.handle_mqtt_get_state().await,
+                        _ => error!("Received message for unknown topic: {}", message.topic()),
+                    }
+                }
+            }
+            message = application.next_cast_message() => match message {
+                Some(message) => application.handle_cast_message(message).await,
+                None => {
+                    warn!("Lost connection to cast device. Reconnecting...");
+                    application.reconnect().await;
+                }
+            },
+            _ = heartbeat_interval.tick() => application.heartbeat().await,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use crossterm::event::KeyCode;
+
+use crate::devices::{Device, Devices, TOPIC_PREFIX};
+
+/// How much the brightness changes with each key press.
+const BRIGHTNESS_STEP: u8 = 10;
+
+#[derive(Default)]
+pub struct Dashboard {
+    pub devices: Devices,
+    pub selected: usize,
+}
+
+impl Dashboard {
+    pub fn selected_device(&self) -> Option<(&String, &Device)> {
+        self.devices.devices().nth(self.selected)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.devices.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The topic and payload of the command of the key for the selected device, if it's a command key.
+    pub fn command(&self, key: KeyCode) -> Option<(String, String)> {
+        let (name, device) = self.selected_device()?;
+        let property = |property: &str| device.properties.get(property).map(String::as_str);
+
+        match key {
+            KeyCode::Char(' ') | KeyCode::Char('t') => {
+                let power = if property("power") == Some("on") { "off" } else { "on" };
+                Some((format!("{}{}/power/set", TOPIC_PREFIX, name), power.to_string()))
+            }
+            KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char('-') => {
+                let brightness = property("brightness")?.parse::<u8>().ok()?;
+                let brightness = match key {
+                    KeyCode::Char('-') => brightness.saturating_sub(BRIGHTNESS_STEP),
+                    _ => brightness.saturating_add(BRIGHTNESS_STEP),
+                }.clamp(1, 100);
+                Some((format!("{}{}/brightness/set", TOPIC_PREFIX, name), brightness.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use chrono::Local;
+    use crossterm::event::KeyCode;
+
+    use crate::dashboard::Dashboard;
+
+    #[test]
+    fn test_commands() {
+        let mut dashboard = Dashboard::default();
+        dashboard.devices.record("smart-home-system/hue/desk/power", "on", Instant::now(), Local::now());
+        dashboard.devices.record("smart-home-system/yeelight/power", "off", Instant::now(), Local::now());
+        dashboard.devices.record("smart-home-system/yeelight/brightness", "95", Instant::now(), Local::now());
+
+        assert_eq!(dashboard.command(KeyCode::Char(' ')), Some(("smart-home-system/hue/desk/power/set".into(), "off".into())));
+        assert_eq!(dashboard.command(KeyCode::Char('+')), None);
+
+        dashboard.select_next();
+        dashboard.select_next();
+        assert_eq!(dashboard.command(KeyCode::Char('t')), Some(("smart-home-system/yeelight/power/set".into(), "on".into())));
+        assert_eq!(dashboard.command(KeyCode::Char('+')), Some(("smart-home-system/yeelight/brightness/set".into(), "100".into())));
+        assert_eq!(dashboard.command(KeyCode::Char('-')), Some(("smart-home-system/yeelight/brightness/set".into(), "85".into())));
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+pub const TOPIC_PREFIX: &str = "smart-home-system/";
+
+/// How many of the last messages are kept as events.
+const MAX_EVENTS: usize = 200;
+
+/// A controller is unavailable when it misses two heartbeats, which are published every 30 seconds.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The last levels of topics that are commands, e.g. `smart-home-system/yeelight/power/set`.
+const COMMAND_LEVELS: [&str; 8] = ["set", "get", "toggle", "fade", "activate", "restore", "start", "cancel"];
+
+/// The last levels of topics published by the controllers about themselves, not a device.
+const CONTROLLER_LEVELS: [&str; 5] = ["heartbeat", "stale", "diagnostics", "error", "devices"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Availability {
+    /// The controller doesn't publish heartbeats.
+    Unknown,
+    Online,
+    /// The controller missed its heartbeats or marked the state as stale.
+    Offline,
+}
+
+struct Controller {
+    last_heartbeat: Option<Instant>,
+    stale: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Device {
+    /// The last payload of every property, e.g. `power` to `on`.
+    pub properties: BTreeMap<String, String>,
+}
+
+pub struct Event {
+    pub time: DateTime<Local>,
+    pub topic: String,
+    pub payload: String,
+}
+
+/// The devices found in the messages published under `smart-home-system/`, where the topic of a property
+/// is `smart-home-system/<controller>[/<device>]/<property>`.
+#[derive(Default)]
+pub struct Devices {
+    /// By their topic without the prefix and the property, e.g. `hue/desk`.
+    devices: BTreeMap<String, Device>,
+    controllers: BTreeMap<String, Controller>,
+    events: VecDeque<Event>,
+}
+
+impl Devices {
+    pub fn record(&mut self, topic: &str, payload: &str, now: Instant, time: DateTime<Local>) {
+        let Some(path) = topic.strip_prefix(TOPIC_PREFIX) else { return };
+        let payload = payload.trim();
+
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_back();
+        }
+        self.events.push_front(Event { time, topic: path.to_string(), payload: payload.to_string() });
+
+        let Some((device, property)) = path.rsplit_once('/') else { return };
+        if COMMAND_LEVELS.contains(&property) {
+            return;
+        }
+
+        if CONTROLLER_LEVELS.contains(&property) {
+            let controller = self.controllers.entry(device.to_string())
+                .or_insert(Controller { last_heartbeat: None, stale: false });
+
+            match property {
+                "heartbeat" => controller.last_heartbeat = Some(now),
+                "stale" => controller.stale = payload == "true",
+                _ => {}
+            }
+            return;
+        }
+
+        self.devices.entry(device.to_string()).or_default()
+            .properties.insert(property.to_string(), payload.to_string());
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item=(&String, &Device)> {
+        self.devices.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    /// The newest first.
+    pub fn events(&self) -> impl Iterator<Item=&Event> {
+        self.events.iter()
+    }
+
+    /// The availability of the controller of the device, e.g. `hue` for `hue/desk`.
+    pub fn availability(&self, device: &str, now: Instant) -> Availability {
+        let controller = device.split('/').next().unwrap_or(device);
+
+        match self.controllers.get(controller) {
+            Some(Controller { stale: true, .. }) => Availability::Offline,
+            Some(Controller { last_heartbeat: Some(heartbeat), .. }) if now.duration_since(*heartbeat) < HEARTBEAT_TIMEOUT => Availability::Online,
+            Some(Controller { last_heartbeat: Some(_), .. }) => Availability::Offline,
+            _ => Availability::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chrono::Local;
+
+    use crate::devices::{Availability, Devices, HEARTBEAT_TIMEOUT};
+
+    #[test]
+    fn test_record() {
+        let mut devices = Devices::default();
+        let now = Instant::now();
+        let mut record = |topic: &str, payload: &str, now| devices.record(topic, payload, now, Local::now());
+
+        record("smart-home-system/yeelight/power", "on", now);
+        record("smart-home-system/yeelight/brightness", "80\n", now);
+        record("smart-home-system/yeelight/power/set", "off", now);
+        record("smart-home-system/yeelight/heartbeat", "{}", now);
+        record("smart-home-system/hue/desk/power", "off", now);
+        record("smart-home-system/zigbee/devices", "[]", now);
+        record("other/power", "on", now);
+
+        let names: Vec<_> = devices.devices().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["hue/desk", "yeelight"]);
+
+        let (_, yeelight) = devices.devices().find(|(name, _)| *name == "yeelight").unwrap();
+        assert_eq!(yeelight.properties["power"], "on");
+        assert_eq!(yeelight.properties["brightness"], "80");
+
+        assert_eq!(devices.events().count(), 6);
+        assert_eq!(devices.events().next().unwrap().topic, "zigbee/devices");
+
+        assert_eq!(devices.availability("yeelight", now), Availability::Online);
+        assert_eq!(devices.availability("yeelight", now + HEARTBEAT_TIMEOUT + Duration::from_secs(1)), Availability::Offline);
+        assert_eq!(devices.availability("hue/desk", now), Availability::Unknown);
+
+        devices.record("smart-home-system/yeelight/stale", "true", now, Local::now());
+        assert_eq!(devices.availability("yeelight", now), Availability::Offline);
+    }
+}
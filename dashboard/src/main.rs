@@ -0,0 +1,106 @@
+use std::io::Stdout;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chrono::Local;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use paho_mqtt::{AsyncClient, AsyncReceiver, Message};
+use ratatui::backend::CrosstermBackend;
+use ratatui::Terminal;
+use tokio::sync::mpsc;
+
+use crate::dashboard::Dashboard;
+use crate::mqtt::connect_mqtt;
+
+mod dashboard;
+mod devices;
+mod mqtt;
+mod ui;
+
+const MQTT_TOPICS: &str = "smart-home-system/#";
+
+/// How often the dashboard is redrawn without new messages, to update the availability.
+const REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+
+type DashboardTerminal = Terminal<CrosstermBackend<Stdout>>;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let (client, stream) = connect_mqtt(
+        &[MQTT_TOPICS],
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    enable_raw_mode().context("Failed to set up the terminal")?;
+    crossterm::execute!(std::io::stdout(), EnterAlternateScreen).context("Failed to set up the terminal")?;
+
+    let result = match Terminal::new(CrosstermBackend::new(std::io::stdout())) {
+        Ok(mut terminal) => run(&mut terminal, &client, stream).await,
+        Err(e) => Err(e.into()),
+    };
+
+    disable_raw_mode().context("Failed to restore the terminal")?;
+    crossterm::execute!(std::io::stdout(), LeaveAlternateScreen).context("Failed to restore the terminal")?;
+
+    result
+}
+
+/// Reads the keys pressed in a thread, as crossterm reads blocking.
+fn read_keys() -> mpsc::Receiver<KeyEvent> {
+    let (sender, receiver) = mpsc::channel(16);
+
+    std::thread::spawn(move || {
+        while let Ok(event) = crossterm::event::read() {
+            if let Event::Key(key) = event {
+                if key.kind == KeyEventKind::Press && sender.blocking_send(key).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+async fn run(terminal: &mut DashboardTerminal, client: &AsyncClient, stream: AsyncReceiver<Option<Message>>) -> anyhow::Result<()> {
+    let mut dashboard = Dashboard::default();
+    let mut keys = read_keys();
+    let mut redraw = tokio::time::interval(REDRAW_INTERVAL);
+
+    loop {
+        terminal.draw(|frame| ui::draw(frame, &dashboard, Instant::now())).context("Failed to draw the dashboard")?;
+
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                // None while reconnecting
+                if let Some(message) = message {
+                    dashboard.devices.record(message.topic(), &message.payload_str(), Instant::now(), Local::now());
+                }
+            }
+            Some(key) = keys.recv() => {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                    KeyCode::Up | KeyCode::Char('k') => dashboard.select_previous(),
+                    KeyCode::Down | KeyCode::Char('j') => dashboard.select_next(),
+                    code => {
+                        if let Some((topic, payload)) = dashboard.command(code) {
+                            client.publish(Message::new(topic, payload, 1));
+                        }
+                    }
+                }
+            }
+            _ = redraw.tick() => {}
+        }
+    }
+
+    Ok(())
+}
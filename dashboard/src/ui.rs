@@ -0,0 +1,58 @@
+use std::time::Instant;
+
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+
+use crate::dashboard::Dashboard;
+use crate::devices::Availability;
+
+const HELP: &str = "↑/↓ select  space toggle power  +/- brightness  q quit";
+
+pub fn draw(frame: &mut Frame, dashboard: &Dashboard, now: Instant) {
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Min(5), Constraint::Length(1)])
+        .split(frame.size());
+
+    let rows = dashboard.devices.devices().map(|(name, device)| {
+        let (availability, color) = match dashboard.devices.availability(name, now) {
+            Availability::Online => ("online", Color::Green),
+            Availability::Offline => ("offline", Color::Red),
+            Availability::Unknown => ("-", Color::DarkGray),
+        };
+
+        let property = |property: &str| device.properties.get(property).cloned().unwrap_or_else(|| "-".into());
+        let others = device.properties.iter()
+            .filter(|(property, _)| *property != "power" && *property != "brightness")
+            .map(|(property, value)| format!("{}={}", property, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Row::new(vec![
+            Cell::from(name.as_str()),
+            Cell::from(availability).style(Style::default().fg(color)),
+            Cell::from(property("power")),
+            Cell::from(property("brightness")),
+            Cell::from(others),
+        ])
+    });
+
+    let table = Table::new(rows)
+        .header(Row::new(vec!["Device", "Availability", "Power", "Brightness", "State"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title("Devices"))
+        .widths(&[Constraint::Length(30), Constraint::Length(12), Constraint::Length(6), Constraint::Length(10), Constraint::Min(10)])
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = TableState::default().with_selected(Some(dashboard.selected));
+    frame.render_stateful_widget(table, areas[0], &mut state);
+
+    let events: Vec<ListItem> = dashboard.devices.events()
+        .map(|event| ListItem::new(Line::from(format!("{} {} {}", event.time.format("%H:%M:%S"), event.topic, event.payload))))
+        .collect();
+    frame.render_widget(List::new(events).block(Block::default().borders(Borders::ALL).title("Events")), areas[1]);
+
+    frame.render_widget(Paragraph::new(HELP), areas[2]);
+}
@@ -0,0 +1,81 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ParseError;
+
+/// In percent, from 0 to 100.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub struct Brightness(u8);
+
+impl Brightness {
+    pub const MAX: Brightness = Brightness(100);
+
+    /// Clamped to 100, e.g. for a brightness read from a device. The payloads received are rejected instead.
+    pub fn new(percent: u8) -> Self {
+        Self(percent.min(100))
+    }
+
+    pub fn percent(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Brightness {
+    type Error = ParseError;
+
+    fn try_from(percent: u8) -> Result<Self, Self::Error> {
+        if percent > 100 {
+            return Err(ParseError(format!("Invalid brightness: {}, it's from 0 to 100", percent)));
+        }
+        Ok(Self(percent))
+    }
+}
+
+impl From<Brightness> for u8 {
+    fn from(brightness: Brightness) -> Self {
+        brightness.0
+    }
+}
+
+impl FromStr for Brightness {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent = s.trim().parse::<u8>().map_err(|_| ParseError(format!("Invalid brightness: {}", s)))?;
+        Self::try_from(percent)
+    }
+}
+
+impl Display for Brightness {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::Brightness;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Brightness::from_str("40"), Ok(Brightness::new(40)));
+        assert_eq!(Brightness::from_str(" 0\n"), Ok(Brightness::new(0)));
+        assert_eq!(Brightness::from_str("100"), Ok(Brightness::MAX));
+        assert_eq!(Brightness::from_str("150").unwrap_err().to_string(), "Invalid brightness: 150, it's from 0 to 100");
+        assert!(Brightness::from_str("300").is_err());
+        assert!(Brightness::from_str("-1").is_err());
+        assert!(Brightness::from_str("").is_err());
+        assert!(serde_json::from_str::<Brightness>("150").is_err());
+    }
+
+    #[test]
+    fn test_new_clamps() {
+        assert_eq!(Brightness::new(150), Brightness::MAX);
+        assert_eq!(Brightness::new(150).to_string(), "100");
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ParseError;
+
+/// In kelvin. The range a device supports is up to its controller, e.g. 1700 to 6500 for a yeelight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ColorTemperature(u16);
+
+impl ColorTemperature {
+    pub fn from_kelvin(kelvin: u16) -> Self {
+        Self(kelvin)
+    }
+
+    pub fn kelvin(self) -> u16 {
+        self.0
+    }
+}
+
+impl FromStr for ColorTemperature {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse::<u16>() {
+            Ok(kelvin) if kelvin > 0 => Ok(Self(kelvin)),
+            _ => Err(ParseError(format!("Invalid color temperature: {}", s))),
+        }
+    }
+}
+
+impl Display for ColorTemperature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::ColorTemperature;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ColorTemperature::from_str("2700"), Ok(ColorTemperature::from_kelvin(2700)));
+        assert!(ColorTemperature::from_str("0").is_err());
+        assert!(ColorTemperature::from_str("warm").is_err());
+        assert_eq!(serde_json::from_str::<ColorTemperature>("2700").unwrap().kelvin(), 2700);
+    }
+}
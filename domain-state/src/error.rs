@@ -0,0 +1,38 @@
+//! The errors the controllers publish to their error topic, e.g. `smart-home-system/hue/error`.
+
+/// The error published when the payload of a command is rejected, so the sender of the command can tell it was
+/// ignored.
+pub fn invalid_payload(topic: &str, payload: &str, reason: &str) -> String {
+    serde_json::json!({
+        "topic": topic,
+        "payload": payload,
+        "kind": "invalid_payload",
+        "error": reason,
+    }).to_string()
+}
+
+/// Logs why the payload of the message was rejected and publishes it to the error topic, see [`invalid_payload`].
+#[cfg(feature = "paho")]
+pub fn publish_invalid_payload(client: &paho_mqtt::AsyncClient, error_topic: &str, message: &paho_mqtt::Message, reason: &str) {
+    log::error!("[{}] Received invalid payload: '{}': {}", message.topic(), message.payload_str(), reason);
+
+    let payload = invalid_payload(message.topic(), &message.payload_str(), reason);
+    client.publish(paho_mqtt::Message::new(error_topic, payload, 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::invalid_payload;
+
+    #[test]
+    fn test_invalid_payload() {
+        let error: serde_json::Value = serde_json::from_str(&invalid_payload("smart-home-system/hue/desk/power/set", "dim", "Invalid power value: dim")).unwrap();
+
+        assert_eq!(error, serde_json::json!({
+            "topic": "smart-home-system/hue/desk/power/set",
+            "payload": "dim",
+            "kind": "invalid_payload",
+            "error": "Invalid power value: dim",
+        }));
+    }
+}
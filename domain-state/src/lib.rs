@@ -0,0 +1,29 @@
+//! The state of the devices as it's published on mqtt, and the topics it's published on, shared by the homekit
+//! bridge and the controllers so they accept and publish the same payloads.
+
+use std::fmt::{Display, Formatter};
+
+mod brightness;
+mod color_temperature;
+pub mod error;
+mod power;
+mod rgb;
+pub mod topic;
+
+pub use brightness::Brightness;
+pub use color_temperature::ColorTemperature;
+pub use power::Power;
+pub use rgb::Rgb;
+pub use topic::Topic;
+
+/// Why a payload isn't a valid value of the state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError(String);
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,84 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::ParseError;
+
+/// Published as `on` or `off`. `true` and `1`, or `false` and `0`, are accepted too, in any case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Power {
+    On,
+    Off,
+}
+
+impl Power {
+    pub fn is_on(self) -> bool {
+        self == Self::On
+    }
+}
+
+impl From<bool> for Power {
+    fn from(on: bool) -> Self {
+        if on { Self::On } else { Self::Off }
+    }
+}
+
+impl FromStr for Power {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "on" | "true" | "1" => Ok(Self::On),
+            "off" | "false" | "0" => Ok(Self::Off),
+            _ => Err(ParseError(format!("Invalid power value: {}", s))),
+        }
+    }
+}
+
+impl Display for Power {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Self::On => "on",
+            Self::Off => "off",
+        })
+    }
+}
+
+impl Serialize for Power {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Power {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::Power;
+
+    #[test]
+    fn test_parse() {
+        for payload in ["on", "ON", "true", "1", " on\n"] {
+            assert_eq!(Power::from_str(payload), Ok(Power::On), "{:?}", payload);
+        }
+        for payload in ["off", "Off", "false", "0"] {
+            assert_eq!(Power::from_str(payload), Ok(Power::Off), "{:?}", payload);
+        }
+        assert_eq!(Power::from_str("dim").unwrap_err().to_string(), "Invalid power value: dim");
+        assert!(Power::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        assert_eq!(serde_json::to_string(&Power::On).unwrap(), "\"on\"");
+        assert_eq!(serde_json::from_str::<Power>("\"OFF\"").unwrap(), Power::Off);
+        assert!(serde_json::from_str::<Power>("\"dim\"").is_err());
+    }
+}
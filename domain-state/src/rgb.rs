@@ -0,0 +1,112 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Brightness, ParseError};
+
+/// Published as `r,g,b`, accepted as `r,g,b` or `#rrggbb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const WHITE: Rgb = Rgb { red: 255, green: 255, blue: 255 };
+
+    pub fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    /// The strongest component, for the devices with no separate brightness channel.
+    pub fn brightness(self) -> Brightness {
+        let max = self.red.max(self.green).max(self.blue) as u16;
+        Brightness::new(((max * 100 + 127) / 255) as u8)
+    }
+
+    /// Scales the components so the strongest one is the brightness, white if the color is black.
+    pub fn with_brightness(self, brightness: Brightness) -> Rgb {
+        let current = self.red.max(self.green).max(self.blue) as u32;
+        let target = brightness.percent() as u32 * 255 / 100;
+
+        if current == 0 {
+            return Rgb::new(target as u8, target as u8, target as u8);
+        }
+
+        let scale = |component: u8| (component as u32 * target / current).min(255) as u8;
+        Rgb::new(scale(self.red), scale(self.green), scale(self.blue))
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || ParseError(format!("Invalid color: {}", s));
+
+        if let Some(hex) = s.strip_prefix('#') {
+            // Only hex digits, a non-ascii character would make the components split inside it and from_str_radix
+            // accepts a sign
+            if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+
+            let component = |range: std::ops::Range<usize>| u8::from_str_radix(&hex[range], 16).map_err(|_| invalid());
+            return Ok(Rgb::new(component(0..2)?, component(2..4)?, component(4..6)?));
+        }
+
+        let components: Vec<u8> = s.split(',')
+            .map(|component| component.trim().parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+
+        match components[..] {
+            [red, green, blue] => Ok(Rgb::new(red, green, blue)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Rgb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}", self.red, self.green, self.blue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{Brightness, Rgb};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Rgb::from_str("255, 128,0"), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!(Rgb::from_str("#ff8000"), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!(Rgb::from_str("#FF8000\n"), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!(Rgb::from_str("#ff80").unwrap_err().to_string(), "Invalid color: #ff80");
+        assert!(Rgb::from_str("256,0,0").is_err());
+        assert!(Rgb::from_str("#aéaaa").is_err());
+        assert!(Rgb::from_str("#+f+f+f").is_err());
+        assert!(Rgb::from_str("#gg0000").is_err());
+        assert!(Rgb::from_str("1,2").is_err());
+        assert!(Rgb::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Rgb::new(255, 128, 0).to_string(), "255,128,0");
+    }
+
+    #[test]
+    fn test_brightness() {
+        let color = Rgb::new(255, 128, 0);
+
+        assert_eq!(color.brightness(), Brightness::MAX);
+        assert_eq!(color.with_brightness(Brightness::new(50)), Rgb::new(127, 63, 0));
+        assert_eq!(Rgb::new(0, 0, 0).with_brightness(Brightness::MAX), Rgb::WHITE);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::ParseError;
+
+/// The start of every topic. The controllers and the bridge replace it on the broker if `MQTT_TOPIC_PREFIX` is set.
+pub const PREFIX: &str = "smart-home-system";
+
+/// A topic of a device, `smart-home-system/<device>[/<attribute>][/<action>]`, e.g.
+/// `Topic::device("yeelight").brightness().set()` for `smart-home-system/yeelight/brightness/set`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Topic {
+    device: String,
+    attribute: Option<Attribute>,
+    action: Option<Action>,
+}
+
+/// What of the device the topic is about. Without one, the topic is about the whole device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attribute {
+    Power,
+    Brightness,
+    ColorTemperature,
+    /// The watts the device is estimated to draw, for the devices that can't measure it.
+    PowerConsumption,
+    /// The on-time and switch cycles of the device on the last day.
+    Usage,
+    /// The on-time and switch cycles of the device since the counter was last reset.
+    UsageCounter,
+    /// The last changes of the state of the device.
+    History,
+    /// The model, firmware and id of the device, for the accessory information of the bridge.
+    Info,
+    Muted,
+    Volume,
+    /// `true` while the published state is the one stored before a restart and the device wasn't reached yet.
+    Stale,
+    Heartbeat,
+    /// The commands that failed.
+    Error,
+    Diagnostics,
+    Config,
+}
+
+/// What's asked of the attribute, or of the device. Without one, the topic is the state the device publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Set,
+    Get,
+    Fade,
+    Toggle,
+    Reloaded,
+    Reset,
+}
+
+impl Attribute {
+    const ALL: [Attribute; 15] = [
+        Self::Power, Self::Brightness, Self::ColorTemperature, Self::PowerConsumption, Self::Usage,
+        Self::UsageCounter, Self::History, Self::Info, Self::Muted, Self::Volume, Self::Stale, Self::Heartbeat,
+        Self::Error, Self::Diagnostics, Self::Config,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Power => "power",
+            Self::Brightness => "brightness",
+            Self::ColorTemperature => "color_temperature",
+            Self::PowerConsumption => "power_consumption",
+            Self::Usage => "usage",
+            Self::UsageCounter => "usage_counter",
+            Self::History => "history",
+            Self::Info => "info",
+            Self::Muted => "muted",
+            Self::Volume => "volume",
+            Self::Stale => "stale",
+            Self::Heartbeat => "heartbeat",
+            Self::Error => "error",
+            Self::Diagnostics => "diagnostics",
+            Self::Config => "config",
+        }
+    }
+}
+
+impl Action {
+    const ALL: [Action; 6] = [Self::Set, Self::Get, Self::Fade, Self::Toggle, Self::Reloaded, Self::Reset];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Set => "set",
+            Self::Get => "get",
+            Self::Fade => "fade",
+            Self::Toggle => "toggle",
+            Self::Reloaded => "reloaded",
+            Self::Reset => "reset",
+        }
+    }
+}
+
+impl Topic {
+    /// The topic the device publishes under, e.g. `yeelight` or `knx/living-room-light`.
+    pub fn device(device: impl Into<String>) -> Self {
+        Self { device: device.into(), attribute: None, action: None }
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device
+    }
+
+    pub fn attribute(&self) -> Option<Attribute> {
+        self.attribute
+    }
+
+    pub fn action(&self) -> Option<Action> {
+        self.action
+    }
+
+    fn with_attribute(self, attribute: Attribute) -> Self {
+        Self { attribute: Some(attribute), ..self }
+    }
+
+    fn with_action(self, action: Action) -> Self {
+        Self { action: Some(action), ..self }
+    }
+
+    pub fn power(self) -> Self {
+        self.with_attribute(Attribute::Power)
+    }
+
+    pub fn brightness(self) -> Self {
+        self.with_attribute(Attribute::Brightness)
+    }
+
+    pub fn color_temperature(self) -> Self {
+        self.with_attribute(Attribute::ColorTemperature)
+    }
+
+    pub fn power_consumption(self) -> Self {
+        self.with_attribute(Attribute::PowerConsumption)
+    }
+
+    pub fn usage(self) -> Self {
+        self.with_attribute(Attribute::Usage)
+    }
+
+    pub fn usage_counter(self) -> Self {
+        self.with_attribute(Attribute::UsageCounter)
+    }
+
+    pub fn history(self) -> Self {
+        self.with_attribute(Attribute::History)
+    }
+
+    pub fn info(self) -> Self {
+        self.with_attribute(Attribute::Info)
+    }
+
+    pub fn muted(self) -> Self {
+        self.with_attribute(Attribute::Muted)
+    }
+
+    pub fn volume(self) -> Self {
+        self.with_attribute(Attribute::Volume)
+    }
+
+    pub fn stale(self) -> Self {
+        self.with_attribute(Attribute::Stale)
+    }
+
+    pub fn heartbeat(self) -> Self {
+        self.with_attribute(Attribute::Heartbeat)
+    }
+
+    pub fn error(self) -> Self {
+        self.with_attribute(Attribute::Error)
+    }
+
+    pub fn diagnostics(self) -> Self {
+        self.with_attribute(Attribute::Diagnostics)
+    }
+
+    pub fn config(self) -> Self {
+        self.with_attribute(Attribute::Config)
+    }
+
+    pub fn set(self) -> Self {
+        self.with_action(Action::Set)
+    }
+
+    pub fn get(self) -> Self {
+        self.with_action(Action::Get)
+    }
+
+    pub fn fade(self) -> Self {
+        self.with_action(Action::Fade)
+    }
+
+    pub fn toggle(self) -> Self {
+        self.with_action(Action::Toggle)
+    }
+
+    pub fn reloaded(self) -> Self {
+        self.with_action(Action::Reloaded)
+    }
+
+    pub fn reset(self) -> Self {
+        self.with_action(Action::Reset)
+    }
+}
+
+impl FromStr for Topic {
+    type Err = ParseError;
+
+    /// Read from the end, so the device can have more than one level, e.g. `knx/living-room-light`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError(format!("Invalid topic: {}", s));
+
+        let rest = s.strip_prefix(PREFIX).and_then(|rest| rest.strip_prefix('/')).ok_or_else(invalid)?;
+        let mut levels: Vec<&str> = rest.split('/').collect();
+
+        let action = Action::ALL.into_iter().find(|action| levels.last() == Some(&action.name()));
+        if action.is_some() {
+            levels.pop();
+        }
+        let attribute = Attribute::ALL.into_iter().find(|attribute| levels.last() == Some(&attribute.name()));
+        if attribute.is_some() {
+            levels.pop();
+        }
+
+        if levels.is_empty() || levels.iter().any(|level| level.is_empty()) {
+            return Err(invalid());
+        }
+        Ok(Self { device: levels.join("/"), attribute, action })
+    }
+}
+
+impl Display for Topic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", PREFIX, self.device)?;
+        if let Some(attribute) = self.attribute {
+            write!(f, "/{}", attribute.name())?;
+        }
+        if let Some(action) = self.action {
+            write!(f, "/{}", action.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Topic> for String {
+    fn from(topic: Topic) -> Self {
+        topic.to_string()
+    }
+}
+
+impl PartialEq<str> for Topic {
+    fn eq(&self, other: &str) -> bool {
+        other.parse().is_ok_and(|topic: Topic| topic == *self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::topic::{Action, Attribute, Topic};
+
+    #[test]
+    fn test_render() {
+        assert_eq!(Topic::device("yeelight").to_string(), "smart-home-system/yeelight");
+        assert_eq!(Topic::device("yeelight").brightness().set().to_string(), "smart-home-system/yeelight/brightness/set");
+        assert_eq!(Topic::device("yeelight").toggle().to_string(), "smart-home-system/yeelight/toggle");
+        assert_eq!(Topic::device("yeelight").config().reloaded().to_string(), "smart-home-system/yeelight/config/reloaded");
+        assert_eq!(
+            Topic::device("knx/living-room-light").power().to_string(),
+            "smart-home-system/knx/living-room-light/power",
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let topic = Topic::from_str("smart-home-system/yeelight/brightness/fade").unwrap();
+        assert_eq!(topic.device_name(), "yeelight");
+        assert_eq!(topic.attribute(), Some(Attribute::Brightness));
+        assert_eq!(topic.action(), Some(Action::Fade));
+
+        assert_eq!(Topic::from_str("smart-home-system/yeelight/set"), Ok(Topic::device("yeelight").set()));
+        assert_eq!(
+            Topic::from_str("smart-home-system/knx/living-room-light/power/set"),
+            Ok(Topic::device("knx/living-room-light").power().set()),
+        );
+
+        assert!(Topic::from_str("smart-home-system").is_err());
+        assert!(Topic::from_str("smart-home-system/power/set").is_err());
+        assert!(Topic::from_str("smart-home-system//power").is_err());
+        assert!(Topic::from_str("smart-home-systems/yeelight").is_err());
+        assert!(Topic::from_str("homebridge/yeelight/setOn").is_err());
+    }
+
+    #[test]
+    fn test_render_then_parse() {
+        for attribute in Attribute::ALL.map(Some).into_iter().chain([None]) {
+            for action in Action::ALL.map(Some).into_iter().chain([None]) {
+                let topic = Topic { device: "yeelight".into(), attribute, action };
+                assert_eq!(Topic::from_str(&topic.to_string()), Ok(topic.clone()));
+                assert!(topic == *topic.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::config::Config;
+
+/// Exposes the devices of the mqtt server to HomeKit.
+#[derive(Parser, Debug, Clone)]
+#[command(version)]
+pub struct Args {
+    /// The config file, instead of env `HOMEKIT_BRIDGE_CONFIG` or `homekit-mqtt-bridge.yaml` if it exists.
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// The profile of the config to use, instead of env `CONFIG_PROFILE`.
+    #[arg(short, long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Which logs are written, as in `RUST_LOG`, instead of the log level of the config.
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Checks every setting of the config, prints a report and exits, without connecting to anything.
+    /// Exits with an error if the config has errors.
+    #[arg(long, visible_alias = "validate-config")]
+    pub check: bool,
+}
+
+impl Args {
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(Config::path)
+    }
+
+    pub fn profile(&self) -> Option<String> {
+        self.profile.clone().or_else(|| std::env::var("CONFIG_PROFILE").ok())
+    }
+
+    /// Loads the config, with the arguments taking precedence over the config file and the env vars.
+    pub fn read_config(&self) -> anyhow::Result<Config> {
+        let mut config = Config::load(self.config_path().as_deref(), self.profile().as_deref())?;
+
+        if let Some(log_level) = &self.log_level {
+            config.log_level = log_level.clone();
+        }
+
+        Ok(config)
+    }
+
+    /// Loads the config and validates it.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        let config = self.read_config()?;
+        config.validate()?;
+        Ok(config)
+    }
+}
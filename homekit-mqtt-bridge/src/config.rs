@@ -0,0 +1,266 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::check;
+use crate::remap::RemapRule;
+
+/// Where the config is read from, unless set with env `HOMEKIT_BRIDGE_CONFIG`. It's optional, the bridge
+/// can be configured with env vars only.
+const DEFAULT_CONFIG_PATH: &str = "homekit-mqtt-bridge.yaml";
+
+/// Reloaded while the bridge runs when the file changes, see [`crate::reload`]. The log settings are
+/// applied right away, the others on the next restart.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mqtt: MqttConfig,
+    /// Port of the metrics and health check endpoints.
+    pub http_port: u16,
+    /// Path of the unix socket of the admin console, only served if set.
+    pub admin_socket: Option<PathBuf>,
+    /// Which logs are written, as in `RUST_LOG`, e.g. `info,hap=debug`.
+    pub log_level: String,
+    /// How long repeats of a warning or error are dropped after it's logged.
+    pub log_throttle_secs: u64,
+    /// The setup code to pair with HomeKit, e.g. `111-22-333`.
+    pub pin: String,
+    /// Settings that replace the ones above when the profile is selected, e.g. to run a test instance against
+    /// the same broker without touching the topics of the real one.
+    pub profiles: BTreeMap<String, Profile>,
+    /// The name of the profile that was applied.
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// The env vars set to invalid values, reported with the other errors of the config.
+    #[serde(skip)]
+    pub env_errors: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub server_uri: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Replaces `smart-home-system` in every topic, the ones of the bridge and of the devices.
+    pub topic_prefix: String,
+    /// Only one client with an id can be connected to the broker.
+    pub client_id: String,
+    /// Rewrites the topics of the bridge to the ones of an existing topic scheme on the broker. The first
+    /// rule that matches a topic is used, the topics no rule matches get the topic prefix.
+    pub remap: Vec<RemapRule>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub mqtt: ProfileMqttConfig,
+}
+
+/// The mqtt settings of a profile, the ones that aren't set are kept. The client id is suffixed with the
+/// name of the profile if it isn't set, so the instances don't disconnect each other.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileMqttConfig {
+    pub server_uri: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: Option<String>,
+    pub client_id: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mqtt: MqttConfig::default(),
+            http_port: 9102,
+            admin_socket: None,
+            log_level: "info,hap=debug".into(),
+            log_throttle_secs: crate::throttle::DEFAULT_WINDOW.as_secs(),
+            pin: "111-22-333".into(),
+            profiles: BTreeMap::new(),
+            profile: None,
+            env_errors: Vec::new(),
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            server_uri: None,
+            username: None,
+            password: None,
+            topic_prefix: crate::mqtt::DEFAULT_TOPIC_PREFIX.into(),
+            client_id: "homekit-mqtt-bridge".into(),
+            remap: Vec::new(),
+        }
+    }
+}
+
+/// The content of the file at env `<name>_FILE`, e.g. a docker or kubernetes secret, or else the value of
+/// the env var, if either is set.
+fn env<T: FromStr>(name: &str) -> anyhow::Result<Option<T>>
+    where T::Err: std::error::Error + Send + Sync + 'static {
+    let file = format!("{}_FILE", name);
+
+    let value = match std::env::var(&file) {
+        Ok(path) => std::fs::read_to_string(&path)
+            .context(format!("Failed to read env {}: {}", file, path))?
+            .trim_end_matches(['\n', '\r'])
+            .to_string(),
+        Err(_) => match std::env::var(name) {
+            Ok(value) => value,
+            Err(_) => return Ok(None),
+        },
+    };
+
+    value.parse().map(Some).context(format!("Invalid env {}: {}", name, value))
+}
+
+impl Config {
+    /// The path of the config file, if there's one.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var("HOMEKIT_BRIDGE_CONFIG") {
+            Ok(path) => Some(path.into()),
+            Err(_) => Path::new(DEFAULT_CONFIG_PATH).exists().then(|| DEFAULT_CONFIG_PATH.into()),
+        }
+    }
+
+    /// Reads the config file, if there's one, and overrides it with the profile, if one is selected, and
+    /// then with the env vars that are set. It isn't validated yet, see [`Config::validate`], which also reports
+    /// the invalid env vars.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> anyhow::Result<Config> {
+        let mut config = match path {
+            Some(path) => Self::read(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(profile) = profile {
+            config.apply_profile(profile)?;
+        }
+        config.override_with_env();
+        Ok(config)
+    }
+
+    fn apply_profile(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            anyhow::bail!("Unknown profile {:?}, the config has the profiles {:?}", name, self.profiles.keys().collect::<Vec<_>>());
+        };
+
+        let mqtt = profile.mqtt;
+        if let Some(server_uri) = mqtt.server_uri {
+            self.mqtt.server_uri = Some(server_uri);
+        }
+        if let Some(username) = mqtt.username {
+            self.mqtt.username = Some(username);
+        }
+        if let Some(password) = mqtt.password {
+            self.mqtt.password = Some(password);
+        }
+        if let Some(topic_prefix) = mqtt.topic_prefix {
+            self.mqtt.topic_prefix = topic_prefix;
+        }
+        self.mqtt.client_id = mqtt.client_id.unwrap_or_else(|| format!("{}-{}", self.mqtt.client_id, name));
+
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))
+    }
+
+    /// Fails with the errors found by [`check::check_config`].
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let errors: Vec<String> = check::check_config(self).errors()
+            .map(|finding| format!("{}: {}", finding.setting, finding.message))
+            .collect();
+
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid config, {}", errors.join("; "));
+        }
+
+        Ok(())
+    }
+
+    /// The digits of the pin, if HomeKit accepts it. It can't be a sequence like `123-45-678` or
+    /// repeat a digit like `111-11-111`.
+    pub fn pin(&self) -> Result<[u8; 8], String> {
+        let digits: Vec<u8> = self.pin.chars()
+            .filter(|c| *c != '-')
+            .map(|c| c.to_digit(10).map(|digit| digit as u8))
+            .collect::<Option<_>>()
+            .ok_or(format!("{:?} should only have digits and dashes, e.g. 111-22-333", self.pin))?;
+
+        let digits: [u8; 8] = digits.try_into()
+            .map_err(|_| format!("{:?} should have 8 digits, e.g. 111-22-333", self.pin))?;
+
+        if digits.iter().all(|digit| *digit == digits[0]) || digits == [1, 2, 3, 4, 5, 6, 7, 8] || digits == [8, 7, 6, 5, 4, 3, 2, 1] {
+            return Err(format!("{:?} is too easy to guess and isn't accepted by HomeKit", self.pin));
+        }
+
+        Ok(digits)
+    }
+
+    /// The settings that changed in the other config and are only applied on a restart.
+    pub fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        [
+            ("mqtt", self.mqtt != other.mqtt),
+            ("http_port", self.http_port != other.http_port),
+            ("admin_socket", self.admin_socket != other.admin_socket),
+            ("pin", self.pin != other.pin),
+        ].into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// The env var, or `None` if it's invalid, with the error kept so every invalid env var is reported at once.
+    fn env<T: FromStr>(&mut self, name: &str) -> Option<T>
+        where T::Err: std::error::Error + Send + Sync + 'static {
+        env(name).unwrap_or_else(|e| {
+            self.env_errors.push(format!("{:#}, fix or unset it", e));
+            None
+        })
+    }
+
+    fn override_with_env(&mut self) {
+        if let Some(server_uri) = self.env("MQTT_SERVER_URI") {
+            self.mqtt.server_uri = Some(server_uri);
+        }
+        if let Some(username) = self.env("MQTT_USERNAME") {
+            self.mqtt.username = Some(username);
+        }
+        if let Some(password) = self.env("MQTT_PASSWORD") {
+            self.mqtt.password = Some(password);
+        }
+        if let Some(topic_prefix) = self.env("MQTT_TOPIC_PREFIX") {
+            self.mqtt.topic_prefix = topic_prefix;
+        }
+        if let Some(client_id) = self.env("MQTT_CLIENT_ID") {
+            self.mqtt.client_id = client_id;
+        }
+        if let Some(http_port) = self.env("HTTP_PORT") {
+            self.http_port = http_port;
+        }
+        if let Some(admin_socket) = self.env("ADMIN_SOCKET") {
+            self.admin_socket = Some(admin_socket);
+        }
+        if let Some(log_level) = self.env("RUST_LOG") {
+            self.log_level = log_level;
+        }
+        if let Some(secs) = self.env("LOG_THROTTLE_SECS") {
+            self.log_throttle_secs = secs;
+        }
+        if let Some(pin) = self.env("HOMEKIT_PIN") {
+            self.pin = pin;
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use mqtt_client::Message;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::{error, info, warn};
+
+use crate::health::Health;
+use crate::mqtt::MqttWrapper;
+
+const HELP: &str = "Commands:
+    state                       the health of the bridge
+    accessories                 the accessories exposed to HomeKit
+    callbacks                   the mqtt topics the bridge handles
+    inject <topic> [payload]    handles the message as if it was received from mqtt
+    quit";
+
+enum ConsoleCommand {
+    Help,
+    State,
+    Accessories,
+    Callbacks,
+    Inject { topic: String, payload: String },
+    Quit,
+}
+
+fn parse(line: &str) -> Result<ConsoleCommand, String> {
+    let line = line.trim();
+    let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+
+    match command {
+        "help" | "" => Ok(ConsoleCommand::Help),
+        "state" => Ok(ConsoleCommand::State),
+        "accessories" => Ok(ConsoleCommand::Accessories),
+        "callbacks" => Ok(ConsoleCommand::Callbacks),
+        "inject" => {
+            let arguments = arguments.trim();
+            let (topic, payload) = arguments.split_once(' ').unwrap_or((arguments, ""));
+            if topic.is_empty() {
+                return Err("Usage: inject <topic> [payload]".into());
+            }
+            Ok(ConsoleCommand::Inject { topic: topic.to_string(), payload: payload.trim().to_string() })
+        }
+        "quit" | "exit" => Ok(ConsoleCommand::Quit),
+        _ => Err(format!("Unknown command: {}. Type help for the commands.", command)),
+    }
+}
+
+/// A console on a unix socket to inspect the bridge and inject messages while debugging,
+/// e.g. with `socat - UNIX-CONNECT:<path>`.
+#[derive(Clone)]
+pub struct Console {
+    health: Health,
+    mqtt: MqttWrapper,
+    /// The id and name of every accessory.
+    accessories: Vec<(u64, String)>,
+}
+
+impl Console {
+    pub fn new(health: Health, mqtt: MqttWrapper, accessories: Vec<(u64, String)>) -> Self {
+        Self { health, mqtt, accessories }
+    }
+
+    async fn execute(&mut self, command: ConsoleCommand) -> String {
+        match command {
+            ConsoleCommand::Help => HELP.into(),
+            ConsoleCommand::State => self.health.report(),
+            ConsoleCommand::Accessories => self.accessories.iter()
+                .map(|(id, name)| format!("{}: {}", id, name))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ConsoleCommand::Callbacks => self.mqtt.topics().join("\n"),
+            ConsoleCommand::Inject { topic, payload } => match self.mqtt.inject(Message::new(topic, payload, 0)).await {
+                true => "Ok".into(),
+                false => "No callback for the topic".into(),
+            },
+            ConsoleCommand::Quit => String::new(),
+        }
+    }
+
+    async fn handle_connection(mut self, stream: UnixStream) -> std::io::Result<()> {
+        let (read_half, mut write_half) = stream.into_split();
+        let mut lines = BufReader::new(read_half).lines();
+
+        write_half.write_all(b"> ").await?;
+        while let Some(line) = lines.next_line().await? {
+            let output = match parse(&line) {
+                Ok(ConsoleCommand::Quit) => return Ok(()),
+                Ok(command) => self.execute(command).await,
+                Err(e) => e,
+            };
+
+            write_half.write_all(format!("{}\n> ", output.trim_end()).as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn serve(path: PathBuf, console: Console) {
+    // Left behind by a previous run
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => return error!("Failed to serve the admin console on {:?}: {}", path, e),
+    };
+
+    info!("Serving the admin console on {:?}", path);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let console = console.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = console.handle_connection(stream).await {
+                        warn!("Admin console connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => error!("Failed to accept an admin console connection: {}", e),
+        }
+    }
+}
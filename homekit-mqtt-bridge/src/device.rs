@@ -1,19 +1,55 @@
+// The characteristics are only used by the accessories the bridge is built with
+#![cfg_attr(
+    not(all(feature = "lightbulb", feature = "switch", feature = "scene", feature = "speaker", feature = "temperature-sensor", feature = "contact-sensor")),
+    allow(dead_code, unused_imports)
+)]
+
 use std::marker::PhantomData;
-use std::str::FromStr;
 use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use domain_state::{Brightness, Power};
 use hap::accessory::HapAccessory;
 use hap::characteristic::AsyncCharacteristicCallbacks;
 use hap::characteristic::brightness::BrightnessCharacteristic;
+use hap::characteristic::contact_sensor_state::ContactSensorStateCharacteristic;
+use hap::characteristic::current_temperature::CurrentTemperatureCharacteristic;
+use hap::characteristic::mute::MuteCharacteristic;
 use hap::characteristic::power_state::PowerStateCharacteristic;
+use hap::characteristic::status_low_battery::StatusLowBatteryCharacteristic;
+use hap::characteristic::volume::VolumeCharacteristic;
+use hap::futures::lock::{Mutex, MutexGuard};
 use hap::futures::FutureExt;
-use log::warn;
-use paho_mqtt::Message;
+use hap::HapType;
+use mqtt_client::Message;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, info_span, warn, Instrument};
 
+use crate::metrics;
 use crate::mqtt::MqttWrapper;
 
-pub mod yeelight_device;
+#[cfg(feature = "contact-sensor")]
+pub mod contact_sensor_device;
+#[cfg(feature = "lightbulb")]
+pub mod lightbulb_device;
+#[cfg(feature = "scene")]
+pub mod scene_device;
+#[cfg(feature = "speaker")]
+pub mod speaker_device;
+#[cfg(feature = "switch")]
+pub mod switch_device;
+#[cfg(feature = "temperature-sensor")]
+pub mod temperature_sensor_device;
+
+/// How long a message waits for the accessory, locked by the hap server or by the message before it, before
+/// it's dropped.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long setting a characteristic may keep the accessory locked, so a wedged hap call doesn't block the next
+/// messages forever.
+const SET_VALUE_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct InnerDevice<T, H> {
     pub name: String,
@@ -48,6 +84,10 @@ impl<D, H> Device<D, H> {
         self.inner.read().unwrap()
     }
 
+    pub fn name(&self) -> String {
+        self.get_inner().name.clone()
+    }
+
     pub fn get_inner_mut(&self) -> RwLockWriteGuard<'_, InnerDevice<D, H>> {
         self.inner.write().unwrap()
     }
@@ -83,11 +123,32 @@ impl<D: Send + Sync + 'static, H: Send + Sync + 'static> Device<D, H> {
             Box::new(move |message: Message| {
                 let mut self_clone = self.clone();
                 let lightbulb = lightbulb.clone();
+                let span = info_span!("device", device = %self_clone.name());
                 Box::pin(async move {
                     if let Err(str) = self_clone.handle_message::<A>(message, lightbulb).await {
                         warn!("Error handling message: {}", str);
+                        metrics::callback_error(&self_clone.name());
                     }
-                })
+                }.instrument(span))
+            }),
+        );
+    }
+
+    /// Updates the accessory information with the one published by the controller of the device on the topic.
+    pub fn setup_information(&self, topic: &str, mqtt_client: &mut MqttWrapper, accessory: HapRsAccessory) {
+        let device = self.clone();
+        mqtt_client.subscribe(
+            topic,
+            Box::new(move |message: Message| {
+                let name = device.name();
+                let accessory = accessory.clone();
+                let span = info_span!("device", device = %name);
+                Box::pin(async move {
+                    if let Err(str) = update_information(&message, accessory.as_ref()).await {
+                        warn!("Error handling message: {}", str);
+                        metrics::callback_error(&name);
+                    }
+                }.instrument(span))
             }),
         );
     }
@@ -105,11 +166,13 @@ impl<T, H> Device<T, H>
             let device = device.clone();
             let mqtt_client = mqtt_client.clone();
             async move {
-                println!("Read of the power state characteristic was triggered.");
+                metrics::characteristic_read(&device.name(), "power");
+                debug!(device = %device.name(), "Read of the power state characteristic was triggered.");
                 device.characteristic::<Power>(mqtt_client.clone()).await
-                    .map(|power| Some(power.0))
+                    .map(|power| Some(power.is_on()))
                     .or_else(|e| {
                         warn!("Read power error: {}", e);
+                        metrics::callback_error(&device.name());
                         Ok(None)
                     })
             }.boxed()
@@ -120,14 +183,16 @@ impl<T, H> Device<T, H>
         power_state_characteristic.on_update_async(Some(move |current_val: bool, new_val: bool| {
             let mqtt_client = mqtt_client.clone();
             let mut device = device.clone();
+            let span = info_span!("homekit_update", device = %device.name(), characteristic = "power");
             async move {
-                let power = Power(new_val);
+                let power = Power::from(new_val);
 
-                println!("The power state was updated from {} to {}.", current_val, new_val);
+                debug!(device = %device.name(), "The power state was updated from {} to {}.", current_val, new_val);
+                metrics::characteristic_update(&device.name(), "power");
                 device.set_characteristic::<Power>(power, mqtt_client.clone());
 
                 Ok(())
-            }.boxed()
+            }.instrument(span).boxed()
         }));
     }
 }
@@ -144,12 +209,14 @@ impl<T, H> Device<T, H>
             let device = device.clone();
             let mqtt_client = mqtt_client.clone();
             async move {
-                println!("Read of the brightness characteristic was triggered.");
+                metrics::characteristic_read(&device.name(), "brightness");
+                debug!(device = %device.name(), "Read of the brightness characteristic was triggered.");
 
                 device.characteristic::<Brightness>(mqtt_client.clone()).await
-                    .map(|brightness| Some(brightness.0 as i32))
+                    .map(|brightness| Some(brightness.percent() as i32))
                     .or_else(|e| {
                         warn!("Read brightness error: {}", e);
+                        metrics::callback_error(&device.name());
                         Ok(None)
                     })
             }.boxed()
@@ -160,13 +227,176 @@ impl<T, H> Device<T, H>
         brightness_characteristic.on_update_async(Some(move |current_val: i32, new_val: i32| {
             let mqtt_client = mqtt_client.clone();
             let mut device = device.clone();
+            let span = info_span!("homekit_update", device = %device.name(), characteristic = "brightness");
             async move {
-                let brightness = Brightness(new_val as u8);
+                let brightness = Brightness::new(new_val.clamp(0, 100) as u8);
 
-                println!("The brightness was updated from {} to {}.", current_val, new_val);
+                debug!(device = %device.name(), "The brightness was updated from {} to {}.", current_val, new_val);
+                metrics::characteristic_update(&device.name(), "brightness");
                 device.set_characteristic::<Brightness>(brightness, mqtt_client.clone());
 
                 Ok(())
+            }.instrument(span).boxed()
+        }));
+    }
+}
+
+impl<T, H> Device<T, H>
+    where Self: Characteristic<Temperature>, H: Send + Sync + 'static, T: Send + Sync + 'static {
+    pub fn setup_temperature(&self, mqtt_client: &MqttWrapper, temperature_characteristic: &mut CurrentTemperatureCharacteristic) {
+        Self::setup_temperature_read(self.clone(), mqtt_client.clone(), temperature_characteristic);
+    }
+
+    fn setup_temperature_read(device: Device<T, H>, mqtt_client: MqttWrapper, temperature_characteristic: &mut CurrentTemperatureCharacteristic) {
+        temperature_characteristic.on_read_async(Some(move || {
+            let device = device.clone();
+            let mqtt_client = mqtt_client.clone();
+            async move {
+                metrics::characteristic_read(&device.name(), "temperature");
+                debug!(device = %device.name(), "Read of the current temperature characteristic was triggered.");
+
+                device.characteristic::<Temperature>(mqtt_client.clone()).await
+                    .map(|temperature| Some(temperature.0))
+                    .or_else(|e| {
+                        warn!("Read temperature error: {}", e);
+                        metrics::callback_error(&device.name());
+                        Ok(None)
+                    })
+            }.boxed()
+        }));
+    }
+}
+
+impl<T, H> Device<T, H>
+    where Self: Characteristic<Mute>, H: Send + Sync + 'static, T: Send + Sync + 'static {
+    pub fn setup_mute(&self, mqtt_client: &MqttWrapper, mute_characteristic: &mut MuteCharacteristic) {
+        Self::setup_mute_update(self.clone(), mqtt_client.clone(), mute_characteristic);
+        Self::setup_mute_read(self.clone(), mqtt_client.clone(), mute_characteristic);
+    }
+
+    fn setup_mute_read(device: Device<T, H>, mqtt_client: MqttWrapper, mute_characteristic: &mut MuteCharacteristic) {
+        mute_characteristic.on_read_async(Some(move || {
+            let device = device.clone();
+            let mqtt_client = mqtt_client.clone();
+            async move {
+                metrics::characteristic_read(&device.name(), "mute");
+                debug!(device = %device.name(), "Read of the mute characteristic was triggered.");
+
+                device.characteristic::<Mute>(mqtt_client.clone()).await
+                    .map(|mute| Some(mute.0))
+                    .or_else(|e| {
+                        warn!("Read mute error: {}", e);
+                        metrics::callback_error(&device.name());
+                        Ok(None)
+                    })
+            }.boxed()
+        }));
+    }
+
+    fn setup_mute_update(device: Device<T, H>, mqtt_client: MqttWrapper, mute_characteristic: &mut MuteCharacteristic) {
+        mute_characteristic.on_update_async(Some(move |current_val: bool, new_val: bool| {
+            let mqtt_client = mqtt_client.clone();
+            let mut device = device.clone();
+            let span = info_span!("homekit_update", device = %device.name(), characteristic = "mute");
+            async move {
+                debug!(device = %device.name(), "The mute was updated from {} to {}.", current_val, new_val);
+                metrics::characteristic_update(&device.name(), "mute");
+                device.set_characteristic::<Mute>(Mute(new_val), mqtt_client.clone());
+
+                Ok(())
+            }.instrument(span).boxed()
+        }));
+    }
+}
+
+impl<T, H> Device<T, H>
+    where Self: Characteristic<Volume>, H: Send + Sync + 'static, T: Send + Sync + 'static {
+    pub fn setup_volume(&self, mqtt_client: &MqttWrapper, volume_characteristic: &mut VolumeCharacteristic) {
+        Self::setup_volume_update(self.clone(), mqtt_client.clone(), volume_characteristic);
+        Self::setup_volume_read(self.clone(), mqtt_client.clone(), volume_characteristic);
+    }
+
+    fn setup_volume_read(device: Device<T, H>, mqtt_client: MqttWrapper, volume_characteristic: &mut VolumeCharacteristic) {
+        volume_characteristic.on_read_async(Some(move || {
+            let device = device.clone();
+            let mqtt_client = mqtt_client.clone();
+            async move {
+                metrics::characteristic_read(&device.name(), "volume");
+                debug!(device = %device.name(), "Read of the volume characteristic was triggered.");
+
+                device.characteristic::<Volume>(mqtt_client.clone()).await
+                    .map(|volume| Some(volume.0))
+                    .or_else(|e| {
+                        warn!("Read volume error: {}", e);
+                        metrics::callback_error(&device.name());
+                        Ok(None)
+                    })
+            }.boxed()
+        }));
+    }
+
+    fn setup_volume_update(device: Device<T, H>, mqtt_client: MqttWrapper, volume_characteristic: &mut VolumeCharacteristic) {
+        volume_characteristic.on_update_async(Some(move |current_val: u8, new_val: u8| {
+            let mqtt_client = mqtt_client.clone();
+            let mut device = device.clone();
+            let span = info_span!("homekit_update", device = %device.name(), characteristic = "volume");
+            async move {
+                debug!(device = %device.name(), "The volume was updated from {} to {}.", current_val, new_val);
+                metrics::characteristic_update(&device.name(), "volume");
+                device.set_characteristic::<Volume>(Volume(new_val), mqtt_client.clone());
+
+                Ok(())
+            }.instrument(span).boxed()
+        }));
+    }
+}
+
+impl<T, H> Device<T, H>
+    where Self: Characteristic<Contact>, H: Send + Sync + 'static, T: Send + Sync + 'static {
+    pub fn setup_contact(&self, mqtt_client: &MqttWrapper, contact_characteristic: &mut ContactSensorStateCharacteristic) {
+        Self::setup_contact_read(self.clone(), mqtt_client.clone(), contact_characteristic);
+    }
+
+    fn setup_contact_read(device: Device<T, H>, mqtt_client: MqttWrapper, contact_characteristic: &mut ContactSensorStateCharacteristic) {
+        contact_characteristic.on_read_async(Some(move || {
+            let device = device.clone();
+            let mqtt_client = mqtt_client.clone();
+            async move {
+                metrics::characteristic_read(&device.name(), "contact");
+                debug!(device = %device.name(), "Read of the contact sensor state characteristic was triggered.");
+
+                device.characteristic::<Contact>(mqtt_client.clone()).await
+                    .map(|contact| Some(contact.state()))
+                    .or_else(|e| {
+                        warn!("Read contact error: {}", e);
+                        metrics::callback_error(&device.name());
+                        Ok(None)
+                    })
+            }.boxed()
+        }));
+    }
+}
+
+impl<T, H> Device<T, H>
+    where Self: Characteristic<LowBattery>, H: Send + Sync + 'static, T: Send + Sync + 'static {
+    pub fn setup_low_battery(&self, mqtt_client: &MqttWrapper, low_battery_characteristic: &mut StatusLowBatteryCharacteristic) {
+        Self::setup_low_battery_read(self.clone(), mqtt_client.clone(), low_battery_characteristic);
+    }
+
+    fn setup_low_battery_read(device: Device<T, H>, mqtt_client: MqttWrapper, low_battery_characteristic: &mut StatusLowBatteryCharacteristic) {
+        low_battery_characteristic.on_read_async(Some(move || {
+            let device = device.clone();
+            let mqtt_client = mqtt_client.clone();
+            async move {
+                metrics::characteristic_read(&device.name(), "low_battery");
+                debug!(device = %device.name(), "Read of the status low battery characteristic was triggered.");
+
+                device.characteristic::<LowBattery>(mqtt_client.clone()).await
+                    .map(|low_battery| Some(low_battery.0 as u8))
+                    .or_else(|e| {
+                        warn!("Read low battery error: {}", e);
+                        Ok(None)
+                    })
             }.boxed()
         }));
     }
@@ -179,37 +409,166 @@ pub trait Characteristic<T> {
     async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str>;
 }
 
+/// Temperature in degrees Celsius.
 #[derive(Clone, Debug)]
-pub struct Brightness(pub u8);
+pub struct Temperature(pub f32);
 
 #[derive(Clone, Debug)]
-pub struct Power(pub bool);
+pub struct Mute(pub bool);
 
-impl FromStr for Power {
-    type Err = &'static str;
+/// Volume in percent.
+#[derive(Clone, Debug)]
+pub struct Volume(pub u8);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "on" | "true" | "1" => Ok(Power(true)),
-            "off" | "false" | "0" => Ok(Power(false)),
-            _ => Err("Could not parse power state"),
-        }
+/// Whether contact is detected, e.g. a door is closed.
+#[derive(Clone, Debug)]
+pub struct Contact(pub bool);
+
+#[derive(Clone, Debug)]
+pub struct LowBattery(pub bool);
+
+impl Contact {
+    /// The HomeKit contact sensor state: 0 when contact is detected, 1 when it isn't.
+    pub fn state(&self) -> u8 {
+        if self.0 { 0 } else { 1 }
     }
 }
 
-impl ToString for Power {
-    fn to_string(&self) -> String {
-        match self.0 {
-            true => "on".into(),
-            false => "off".into(),
-        }
+/// The accessory information published by the controller of a device to `<topic>/info`, e.g.
+/// `{"manufacturer":"Yeelight","id":"0x1","model":"color","firmware":"18"}`, with any of the fields left out.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Information {
+    pub manufacturer: Option<String>,
+    /// Shown as the serial number.
+    pub id: Option<String>,
+    pub model: Option<String>,
+    pub firmware: Option<String>,
+}
+
+impl Information {
+    /// The characteristics of the accessory information service with their values, for the ones that are known.
+    fn characteristics(self) -> Vec<(HapType, Value)> {
+        [
+            (HapType::Manufacturer, self.manufacturer),
+            (HapType::SerialNumber, self.id),
+            (HapType::Model, self.model),
+            (HapType::FirmwareRevision, self.firmware),
+        ].into_iter()
+            .filter_map(|(characteristic, value)| Some((characteristic, Value::from(value?))))
+            .collect()
+    }
+}
+
+async fn update_information(message: &Message, accessory: &dyn AccessoryCharacteristics) -> Result<(), &'static str> {
+    let information: Information = serde_json::from_slice(message.payload()).map_err(|_| "Could not parse accessory information")?;
+    for (characteristic, value) in information.characteristics() {
+        accessory.set_value(HapType::AccessoryInformation, characteristic, value).await?;
+    }
+    Ok(())
+}
+
+/// The characteristics of an accessory the values received from mqtt are written to, implemented by the
+/// accessories added to the hap server and by [`FakeAccessory`] in the tests.
+#[async_trait]
+pub trait AccessoryCharacteristics: Send + Sync {
+    async fn set_value(&self, service: HapType, characteristic: HapType, value: Value) -> Result<(), &'static str>;
+}
+
+/// An accessory added to the hap server, or a fake one in the tests.
+pub type HapRsAccessory = Arc<dyn AccessoryCharacteristics>;
+
+/// Locks the mutex, or gives up after the timeout instead of waiting for a holder that may never release it.
+async fn lock_with_timeout<T: ?Sized>(mutex: &Mutex<T>, timeout: Duration) -> Result<MutexGuard<'_, T>, &'static str> {
+    tokio::time::timeout(timeout, mutex.lock()).await.map_err(|_| "Timed out waiting for the accessory lock")
+}
+
+#[async_trait]
+impl AccessoryCharacteristics for Mutex<Box<dyn HapAccessory>> {
+    async fn set_value(&self, service: HapType, characteristic: HapType, value: Value) -> Result<(), &'static str> {
+        let mut accessory = lock_with_timeout(self, LOCK_TIMEOUT).await?;
+        let characteristic = accessory.get_mut_service(service)
+            .ok_or("The accessory doesn't have the service")?
+            .get_mut_characteristic(characteristic)
+            .ok_or("The service doesn't have the characteristic")?;
+
+        // The lock is released when the timeout drops the call
+        tokio::time::timeout(SET_VALUE_TIMEOUT, characteristic.set_value(value)).await
+            .map_err(|_| "Timed out setting the value of the characteristic")?
+            .map_err(|_| "Could not set the value of the characteristic")
+    }
+}
+
+/// Keeps the values set on the characteristics in memory, instead of an accessory of the hap server.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeAccessory {
+    values: std::sync::Mutex<Vec<(HapType, HapType, Value)>>,
+    /// The characteristics that fail to be set, as when the accessory doesn't have them.
+    missing: Vec<HapType>,
+}
+
+#[cfg(test)]
+impl FakeAccessory {
+    pub fn without(missing: Vec<HapType>) -> Self {
+        Self { missing, ..Default::default() }
+    }
+
+    /// The services, characteristics and values set since the last call.
+    pub fn take_values(&self) -> Vec<(HapType, HapType, Value)> {
+        self.values.lock().unwrap().drain(..).collect()
     }
 }
 
-impl ToString for Brightness {
-    fn to_string(&self) -> String {
-        self.0.to_string()
+#[cfg(test)]
+#[async_trait]
+impl AccessoryCharacteristics for FakeAccessory {
+    async fn set_value(&self, service: HapType, characteristic: HapType, value: Value) -> Result<(), &'static str> {
+        if self.missing.contains(&characteristic) {
+            return Err("The service doesn't have the characteristic");
+        }
+        self.values.lock().unwrap().push((service, characteristic, value));
+        Ok(())
     }
 }
 
-type HapRsAccessory = Arc<hap::futures::lock::Mutex<Box<dyn HapAccessory>>>;
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hap::futures::lock::Mutex;
+    use hap::HapType;
+    use mqtt_client::Message;
+    use serde_json::json;
+
+    use crate::device::{lock_with_timeout, update_information, FakeAccessory};
+
+    #[tokio::test]
+    async fn test_lock_times_out() {
+        let mutex = Mutex::new(());
+
+        let guard = lock_with_timeout(&mutex, Duration::from_millis(50)).await.unwrap();
+        assert!(lock_with_timeout(&mutex, Duration::from_millis(50)).await.is_err());
+
+        drop(guard);
+        assert!(lock_with_timeout(&mutex, Duration::from_millis(50)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_update_information() {
+        let accessory = FakeAccessory::default();
+
+        let payload = json!({ "manufacturer": "Yeelight", "id": "0x1", "model": "color", "address": "192.168.1.10:55443" });
+        let message = Message::new("smart-home-system/yeelight/info", payload.to_string(), 1);
+        update_information(&message, &accessory).await.unwrap();
+
+        assert_eq!(accessory.take_values(), [
+            (HapType::AccessoryInformation, HapType::Manufacturer, json!("Yeelight")),
+            (HapType::AccessoryInformation, HapType::SerialNumber, json!("0x1")),
+            (HapType::AccessoryInformation, HapType::Model, json!("color")),
+        ]);
+
+        let message = Message::new("smart-home-system/yeelight/info", "color", 1);
+        assert!(update_information(&message, &accessory).await.is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use domain_state::Power;
+use hap::accessory::AccessoryInformation;
+use hap::accessory::contact_sensor::ContactSensorAccessory;
+use hap::HapType;
+use hap::server::{IpServer, Server};
+use mqtt_client::Message;
+
+use crate::device::{Characteristic, Contact, Device, HapRsAccessory, LowBattery};
+use crate::mqtt::MqttWrapper;
+
+pub struct ContactSensor {
+    pub contact: Contact,
+    pub low_battery: LowBattery,
+}
+
+pub type ContactSensorDevice = Device<ContactSensor, ContactSensorAccessory>;
+
+impl ContactSensorDevice {
+    pub fn new(name: String) -> Self {
+        Device::new_device(name, ContactSensor {
+            contact: Contact(true),
+            low_battery: LowBattery(false),
+        })
+    }
+
+    /// `open_topic` reports `on` while the sensor is open, e.g. while a UPS runs on battery, and
+    /// `low_battery_topic` reports `on` while its battery is low.
+    pub async fn setup(&mut self, id: u64, open_topic: &str, low_battery_topic: &str, mqtt_client: &mut MqttWrapper, ip_server: &IpServer) {
+        let mut sensor = ContactSensorAccessory::new(id, AccessoryInformation {
+            name: self.get_inner().name.to_string(),
+            ..Default::default()
+        }).expect("The contact sensor accessory should be created successfully.");
+
+        self.setup_contact(mqtt_client, &mut sensor.contact_sensor.contact_sensor_state);
+        self.setup_low_battery(mqtt_client, sensor.contact_sensor.status_low_battery.as_mut().expect("The status low battery characteristic should be created successfully."));
+
+        let accessory = ip_server.add_accessory(sensor).await.expect("The contact sensor accessory should be added successfully.");
+
+        self.clone().setup_pointer::<Contact>(open_topic, mqtt_client, accessory.clone());
+        self.clone().setup_pointer::<LowBattery>(low_battery_topic, mqtt_client, accessory.clone());
+    }
+}
+
+#[async_trait]
+impl Characteristic<Contact> for ContactSensorDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Contact> {
+        Ok(self.get_inner().device.contact.clone())
+    }
+
+    fn set_value(&mut self, value: Contact, _mqtt_client: MqttWrapper) {
+        self.get_inner_mut().device.contact = value;
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let open: Power = payload.parse().map_err(|_| "Could not parse power state")?;
+        let contact = Contact(!open.is_on());
+
+        self.get_inner_mut().device.contact = contact.clone();
+        accessory.set_value(HapType::ContactSensor, HapType::ContactSensorState, contact.state().into()).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Characteristic<LowBattery> for ContactSensorDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<LowBattery> {
+        Ok(self.get_inner().device.low_battery.clone())
+    }
+
+    fn set_value(&mut self, value: LowBattery, _mqtt_client: MqttWrapper) {
+        self.get_inner_mut().device.low_battery = value;
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let low: Power = payload.parse().map_err(|_| "Could not parse power state")?;
+        let low_battery = LowBattery(low.is_on());
+
+        self.get_inner_mut().device.low_battery = low_battery.clone();
+        accessory.set_value(HapType::ContactSensor, HapType::StatusLowBattery, (low_battery.0 as u8).into()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use domain_state::{Brightness, Power, Topic};
+use hap::accessory::AccessoryInformation;
+use hap::accessory::lightbulb::LightbulbAccessory;
+use hap::HapType;
+use hap::server::{IpServer, Server};
+use mqtt_client::Message;
+use serde_json::json;
+
+use crate::device::{Characteristic, Device, HapRsAccessory};
+use crate::mqtt::MqttWrapper;
+
+/// How long the changes made by homekit after the first one are waited for, so the ones of a scene are published
+/// together.
+const BATCH_WINDOW: Duration = Duration::from_millis(100);
+
+pub struct Lightbulb {
+    /// Topic of the light, e.g. `smart-home-system/yeelight`.
+    pub topic: Topic,
+    pub power_state: Power,
+    pub brightness: Brightness,
+    /// Whether the changes made together are published as one command to `<topic>/set`, for the controllers
+    /// that support it.
+    batched: bool,
+    /// The changes waiting for the batch window to end.
+    batch: Batch,
+}
+
+#[derive(Default)]
+struct Batch {
+    power: Option<Power>,
+    brightness: Option<Brightness>,
+}
+
+pub type LightbulbDevice = Device<Lightbulb, LightbulbAccessory>;
+
+impl LightbulbDevice {
+    pub fn new(name: String, topic: Topic) -> Self {
+        Device::new_device(name, Lightbulb {
+            topic,
+            power_state: Power::Off,
+            brightness: Brightness::new(0),
+            batched: false,
+            batch: Batch::default(),
+        })
+    }
+
+    /// Publishes the changes made within [`BATCH_WINDOW`] of each other as one command.
+    pub fn with_batched_updates(self) -> Self {
+        self.get_inner_mut().device.batched = true;
+        self
+    }
+
+    /// Adds the change to the batch, publishing it once the window started by the first change ends. Returns
+    /// false if the changes aren't batched.
+    fn batch(&self, change: impl FnOnce(&mut Batch), mqtt_client: &MqttWrapper) -> bool {
+        let first = {
+            let mut inner = self.get_inner_mut();
+            if !inner.device.batched {
+                return false;
+            }
+            let first = inner.device.batch.power.is_none() && inner.device.batch.brightness.is_none();
+            change(&mut inner.device.batch);
+            first
+        };
+
+        if first {
+            let device = self.clone();
+            let mqtt_client = mqtt_client.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(BATCH_WINDOW).await;
+                device.publish_batch(mqtt_client);
+            });
+        }
+        true
+    }
+
+    /// Publishes the batch, with a single change to its own topic as when the changes aren't batched.
+    fn publish_batch(&self, mut mqtt_client: MqttWrapper) {
+        let (topic, batch) = {
+            let mut inner = self.get_inner_mut();
+            (inner.device.topic.clone(), std::mem::take(&mut inner.device.batch))
+        };
+
+        match batch {
+            Batch { power: Some(power), brightness: None } => mqtt_client.publish(topic.power().set(), power.to_string()),
+            Batch { power: None, brightness: Some(brightness) } => mqtt_client.publish(topic.brightness().set(), brightness.to_string()),
+            Batch { power: Some(power), brightness: Some(brightness) } => {
+                let payload = json!({ "power": power, "brightness": brightness });
+                mqtt_client.publish(topic.set(), payload.to_string());
+            }
+            Batch { power: None, brightness: None } => {}
+        }
+    }
+
+    pub async fn setup(&mut self, id: u64, mqtt_client: &mut MqttWrapper, ip_server: &IpServer) {
+        let mut lightbulb = LightbulbAccessory::new(id, AccessoryInformation {
+            name: self.get_inner().name.to_string(),
+            ..Default::default()
+        }).expect("The lightbulb accessory should be created successfully.");
+
+        self.setup_power(mqtt_client, &mut lightbulb.lightbulb.power_state);
+        self.setup_brightness(mqtt_client, lightbulb.lightbulb.brightness.as_mut().expect("The brightness characteristic should be created successfully."));
+
+        let accessory = ip_server.add_accessory(lightbulb).await.expect("The lightbulb accessory should be added successfully.");
+
+        let topic = self.get_inner().device.topic.clone();
+        self.setup_information(&topic.clone().info().to_string(), mqtt_client, accessory.clone());
+        self.clone().setup_pointer::<Brightness>(&topic.clone().brightness().to_string(), mqtt_client, accessory.clone());
+        self.clone().setup_pointer::<Power>(&topic.power().to_string(), mqtt_client, accessory.clone());
+    }
+}
+
+#[async_trait]
+impl Characteristic<Brightness> for LightbulbDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Brightness> {
+        Ok(self.get_inner().device.brightness)
+    }
+
+    fn set_value(&mut self, value: Brightness, mut mqtt_client: MqttWrapper) {
+        if self.batch(|batch| batch.brightness = Some(value), &mqtt_client) {
+            self.get_inner_mut().device.brightness = value;
+            return;
+        }
+
+        let topic = {
+            let mut inner = self.get_inner_mut();
+            inner.device.brightness = value;
+            inner.device.topic.clone().brightness().set()
+        };
+        mqtt_client.publish(topic, value.to_string())
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let brightness: Brightness = payload.parse().map_err(|_| "Could not parse brightness")?;
+
+        self.get_inner_mut().device.brightness = brightness;
+        accessory.set_value(HapType::Lightbulb, HapType::Brightness, brightness.percent().into()).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Characteristic<Power> for LightbulbDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Power> {
+        Ok(self.get_inner().device.power_state)
+    }
+
+    fn set_value(&mut self, value: Power, mut mqtt_client: MqttWrapper) {
+        if self.batch(|batch| batch.power = Some(value), &mqtt_client) {
+            self.get_inner_mut().device.power_state = value;
+            return;
+        }
+
+        let topic = {
+            let mut inner = self.get_inner_mut();
+            inner.device.power_state = value;
+            inner.device.topic.clone().power().set()
+        };
+        mqtt_client.publish(topic, value.to_string());
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let power: Power = payload.parse().map_err(|_| "Could not parse power state")?;
+
+        self.get_inner_mut().device.power_state = power;
+        accessory.set_value(HapType::Lightbulb, HapType::PowerState, power.is_on().into()).await?;
+
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use domain_state::{Brightness, Power, Topic};
+    use hap::HapType;
+    use mqtt_client::Message;
+    use serde_json::json;
+
+    use crate::device::{FakeAccessory, HapRsAccessory};
+    use crate::device::lightbulb_device::{LightbulbDevice, BATCH_WINDOW};
+    use crate::mqtt::{FakeClient, MqttWrapper};
+
+    fn device() -> (LightbulbDevice, MqttWrapper) {
+        let mqtt = MqttWrapper::new(FakeClient::default());
+        (LightbulbDevice::new("yeelight".into(), Topic::device("yeelight")), mqtt)
+    }
+
+    fn message(topic: &str, payload: &str) -> Message {
+        Message::new(format!("smart-home-system/yeelight/{}", topic), payload, 1)
+    }
+
+    #[tokio::test]
+    async fn test_set_characteristic_publishes() {
+        let client = Arc::new(FakeClient::default());
+        let mqtt = MqttWrapper::new(client.clone());
+        let mut device = LightbulbDevice::new("yeelight".into(), Topic::device("yeelight"));
+
+        device.set_characteristic(Power::On, mqtt.clone());
+        device.set_characteristic(Brightness::new(40), mqtt.clone());
+
+        assert_eq!(client.take_published(), [
+            ("smart-home-system/yeelight/power/set".to_string(), "on".to_string()),
+            ("smart-home-system/yeelight/brightness/set".to_string(), "40".to_string()),
+        ]);
+        assert!(device.characteristic::<Power>(mqtt.clone()).await.unwrap().is_on());
+        assert_eq!(device.characteristic::<Brightness>(mqtt).await.unwrap().percent(), 40);
+    }
+
+    #[tokio::test]
+    async fn test_batched_updates_are_published_together() {
+        let client = Arc::new(FakeClient::default());
+        let mqtt = MqttWrapper::new(client.clone());
+        let mut device = LightbulbDevice::new("yeelight".into(), Topic::device("yeelight")).with_batched_updates();
+
+        // As when a homekit scene is activated
+        device.set_characteristic(Power::On, mqtt.clone());
+        device.set_characteristic(Brightness::new(40), mqtt.clone());
+        assert!(client.take_published().is_empty());
+        assert_eq!(device.characteristic::<Brightness>(mqtt.clone()).await.unwrap().percent(), 40);
+
+        tokio::time::sleep(BATCH_WINDOW * 2).await;
+        let published = client.take_published();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].0, "smart-home-system/yeelight/set");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&published[0].1).unwrap(), json!({ "power": "on", "brightness": 40 }));
+
+        // A single change is published to its own topic
+        device.set_characteristic(Brightness::new(60), mqtt.clone());
+        tokio::time::sleep(BATCH_WINDOW * 2).await;
+        assert_eq!(client.take_published(), [("smart-home-system/yeelight/brightness/set".to_string(), "60".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_mqtt_message_sets_characteristic() {
+        let (mut device, mqtt) = device();
+        let fake = Arc::new(FakeAccessory::default());
+        let accessory: HapRsAccessory = fake.clone();
+
+        device.handle_message::<Power>(message("power", "on"), accessory.clone()).await.unwrap();
+        device.handle_message::<Brightness>(message("brightness", "40"), accessory).await.unwrap();
+
+        assert_eq!(fake.take_values(), [
+            (HapType::Lightbulb, HapType::PowerState, json!(true)),
+            (HapType::Lightbulb, HapType::Brightness, json!(40)),
+        ]);
+        assert!(device.characteristic::<Power>(mqtt.clone()).await.unwrap().is_on());
+        assert_eq!(device.characteristic::<Brightness>(mqtt).await.unwrap().percent(), 40);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_mqtt_message_is_ignored() {
+        let (mut device, mqtt) = device();
+        let fake = Arc::new(FakeAccessory::default());
+        let accessory: HapRsAccessory = fake.clone();
+
+        assert!(device.handle_message::<Power>(message("power", "dim"), accessory.clone()).await.is_err());
+        assert!(device.handle_message::<Brightness>(message("brightness", "300"), accessory.clone()).await.is_err());
+        assert!(device.handle_message::<Brightness>(message("brightness", ""), accessory).await.is_err());
+
+        assert!(fake.take_values().is_empty());
+        assert!(!device.characteristic::<Power>(mqtt.clone()).await.unwrap().is_on());
+        assert_eq!(device.characteristic::<Brightness>(mqtt).await.unwrap().percent(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_missing_characteristic_is_an_error() {
+        let (mut device, _) = device();
+        let fake = Arc::new(FakeAccessory::without(vec![HapType::Brightness]));
+        let accessory: HapRsAccessory = fake.clone();
+
+        assert!(device.handle_message::<Brightness>(message("brightness", "40"), accessory.clone()).await.is_err());
+        device.handle_message::<Power>(message("power", "off"), accessory).await.unwrap();
+
+        assert_eq!(fake.take_values(), [(HapType::Lightbulb, HapType::PowerState, json!(false))]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use domain_state::Power;
+use hap::accessory::AccessoryInformation;
+use hap::accessory::switch::SwitchAccessory;
+use hap::HapType;
+use hap::server::{IpServer, Server};
+use mqtt_client::Message;
+use tracing::warn;
+
+use crate::device::{Characteristic, Device, HapRsAccessory};
+use crate::mqtt::MqttWrapper;
+
+/// Time the switch stays on after activating the scene.
+const RESET_DELAY: Duration = Duration::from_secs(1);
+
+pub struct Scene {
+    accessory: Option<HapRsAccessory>,
+}
+
+/// A switch that activates a scene of the automation engine and turns itself back off.
+pub type SceneDevice = Device<Scene, SwitchAccessory>;
+
+impl SceneDevice {
+    pub fn new(name: String) -> Self {
+        Device::new_device(name, Scene { accessory: None })
+    }
+
+    pub async fn setup(&mut self, id: u64, mqtt_client: &mut MqttWrapper, ip_server: &IpServer) {
+        let mut switch = SwitchAccessory::new(id, AccessoryInformation {
+            name: self.get_inner().name.to_string(),
+            ..Default::default()
+        }).expect("The switch accessory should be created successfully.");
+
+        self.setup_power(mqtt_client, &mut switch.switch.power_state);
+
+        let accessory: HapRsAccessory = ip_server.add_accessory(switch).await.expect("The switch accessory should be added successfully.");
+        self.get_inner_mut().device.accessory = Some(accessory);
+    }
+}
+
+#[async_trait]
+impl Characteristic<Power> for SceneDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Power> {
+        Ok(Power::Off)
+    }
+
+    fn set_value(&mut self, value: Power, mut mqtt_client: MqttWrapper) {
+        if !value.is_on() {
+            return;
+        }
+
+        let (topic, accessory) = {
+            let inner = self.get_inner();
+            (format!("smart-home-system/scene/{}/activate", inner.name), inner.device.accessory.clone())
+        };
+        mqtt_client.publish(topic, "");
+
+        let Some(accessory) = accessory else { return };
+        tokio::spawn(async move {
+            tokio::time::sleep(RESET_DELAY).await;
+
+            if let Err(e) = accessory.set_value(HapType::Switch, HapType::PowerState, false.into()).await {
+                warn!("Could not turn the scene switch back off: {}", e);
+            }
+        });
+    }
+
+    async fn handle_mqtt_message(&mut self, _message: Message, _accessory: HapRsAccessory) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
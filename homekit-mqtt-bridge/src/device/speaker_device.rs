@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use domain_state::Topic;
+use hap::accessory::AccessoryInformation;
+use hap::accessory::speaker::SpeakerAccessory;
+use hap::HapType;
+use hap::server::{IpServer, Server};
+use mqtt_client::Message;
+
+use crate::device::{Characteristic, Device, HapRsAccessory, Mute, Volume};
+use crate::mqtt::MqttWrapper;
+
+pub struct Speaker {
+    /// Topic of the controller, e.g. `smart-home-system/chromecast`.
+    pub topic: Topic,
+    pub mute: Mute,
+    pub volume: Volume,
+}
+
+pub type SpeakerDevice = Device<Speaker, SpeakerAccessory>;
+
+impl SpeakerDevice {
+    pub fn new(name: String, topic: Topic) -> Self {
+        Device::new_device(name, Speaker {
+            topic,
+            mute: Mute(false),
+            volume: Volume(0),
+        })
+    }
+
+    pub async fn setup(&mut self, id: u64, mqtt_client: &mut MqttWrapper, ip_server: &IpServer) {
+        let mut speaker = SpeakerAccessory::new(id, AccessoryInformation {
+            name: self.get_inner().name.to_string(),
+            ..Default::default()
+        }).expect("The speaker accessory should be created successfully.");
+
+        self.setup_mute(mqtt_client, &mut speaker.speaker.mute);
+        self.setup_volume(mqtt_client, speaker.speaker.volume.as_mut().expect("The volume characteristic should be created successfully."));
+
+        let accessory = ip_server.add_accessory(speaker).await.expect("The speaker accessory should be added successfully.");
+
+        let topic = self.get_inner().device.topic.clone();
+        self.clone().setup_pointer::<Mute>(&topic.clone().muted().to_string(), mqtt_client, accessory.clone());
+        self.clone().setup_pointer::<Volume>(&topic.volume().to_string(), mqtt_client, accessory.clone());
+    }
+}
+
+#[async_trait]
+impl Characteristic<Mute> for SpeakerDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Mute> {
+        Ok(self.get_inner().device.mute.clone())
+    }
+
+    fn set_value(&mut self, value: Mute, mut mqtt_client: MqttWrapper) {
+        let topic = {
+            let mut inner = self.get_inner_mut();
+            inner.device.mute = value.clone();
+            inner.device.topic.clone().muted().set()
+        };
+        mqtt_client.publish(topic, if value.0 { "on" } else { "off" });
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let mute = match message.payload_str().trim() {
+            "on" | "true" | "1" => Mute(true),
+            "off" | "false" | "0" => Mute(false),
+            _ => return Err("Could not parse mute"),
+        };
+
+        self.get_inner_mut().device.mute = mute.clone();
+        accessory.set_value(HapType::Speaker, HapType::Mute, mute.0.into()).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Characteristic<Volume> for SpeakerDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Volume> {
+        Ok(self.get_inner().device.volume.clone())
+    }
+
+    fn set_value(&mut self, value: Volume, mut mqtt_client: MqttWrapper) {
+        let topic = {
+            let mut inner = self.get_inner_mut();
+            inner.device.volume = value.clone();
+            inner.device.topic.clone().volume().set()
+        };
+        mqtt_client.publish(topic, value.0.to_string());
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let volume = Volume(payload.trim().parse::<u8>().map_err(|_| "Could not parse volume")?);
+
+        self.get_inner_mut().device.volume = volume.clone();
+        accessory.set_value(HapType::Speaker, HapType::Volume, volume.0.into()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use domain_state::Power;
+use hap::accessory::AccessoryInformation;
+use hap::accessory::switch::SwitchAccessory;
+use hap::HapType;
+use hap::server::{IpServer, Server};
+use mqtt_client::Message;
+
+use crate::device::{Characteristic, Device, HapRsAccessory};
+use crate::mqtt::MqttWrapper;
+
+pub struct Switch {
+    /// Topic reporting the state of the switch, e.g. `smart-home-system/automation/vacation/enabled`.
+    /// It's changed by publishing to `<topic>/set`.
+    pub topic: String,
+    pub power_state: Power,
+}
+
+pub type SwitchDevice = Device<Switch, SwitchAccessory>;
+
+impl SwitchDevice {
+    pub fn new(name: String, topic: String) -> Self {
+        Device::new_device(name, Switch {
+            topic,
+            power_state: Power::Off,
+        })
+    }
+
+    pub async fn setup(&mut self, id: u64, mqtt_client: &mut MqttWrapper, ip_server: &IpServer) {
+        let mut switch = SwitchAccessory::new(id, AccessoryInformation {
+            name: self.get_inner().name.to_string(),
+            ..Default::default()
+        }).expect("The switch accessory should be created successfully.");
+
+        self.setup_power(mqtt_client, &mut switch.switch.power_state);
+
+        let accessory = ip_server.add_accessory(switch).await.expect("The switch accessory should be added successfully.");
+
+        let topic = self.get_inner().device.topic.clone();
+        self.clone().setup_pointer::<Power>(&topic, mqtt_client, accessory.clone());
+    }
+}
+
+#[async_trait]
+impl Characteristic<Power> for SwitchDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Power> {
+        Ok(self.get_inner().device.power_state)
+    }
+
+    fn set_value(&mut self, value: Power, mut mqtt_client: MqttWrapper) {
+        let topic = {
+            let mut inner = self.get_inner_mut();
+            inner.device.power_state = value;
+            format!("{}/set", inner.device.topic)
+        };
+        mqtt_client.publish(topic, value.to_string());
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let power: Power = payload.parse().map_err(|_| "Could not parse power state")?;
+
+        self.get_inner_mut().device.power_state = power;
+        accessory.set_value(HapType::Switch, HapType::PowerState, power.is_on().into()).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use hap::accessory::AccessoryInformation;
+use hap::accessory::temperature_sensor::TemperatureSensorAccessory;
+use hap::HapType;
+use hap::server::{IpServer, Server};
+use mqtt_client::Message;
+
+use crate::device::{Characteristic, Device, HapRsAccessory, Temperature};
+use crate::mqtt::MqttWrapper;
+
+pub struct TemperatureSensor {
+    pub temperature: Temperature,
+}
+
+pub type TemperatureSensorDevice = Device<TemperatureSensor, TemperatureSensorAccessory>;
+
+impl TemperatureSensorDevice {
+    pub fn new(name: String) -> Self {
+        Device::new_device(name, TemperatureSensor {
+            temperature: Temperature(0.0),
+        })
+    }
+
+    pub async fn setup(&mut self, id: u64, topic: &str, mqtt_client: &mut MqttWrapper, ip_server: &IpServer) {
+        let mut sensor = TemperatureSensorAccessory::new(id, AccessoryInformation {
+            name: self.get_inner().name.to_string(),
+            ..Default::default()
+        }).expect("The temperature sensor accessory should be created successfully.");
+
+        self.setup_temperature(mqtt_client, &mut sensor.temperature_sensor.current_temperature);
+
+        let accessory = ip_server.add_accessory(sensor).await.expect("The temperature sensor accessory should be added successfully.");
+
+        self.clone().setup_pointer::<Temperature>(topic, mqtt_client, accessory.clone());
+    }
+}
+
+#[async_trait]
+impl Characteristic<Temperature> for TemperatureSensorDevice {
+    fn get_value(&self, _mqtt_client: MqttWrapper) -> anyhow::Result<Temperature> {
+        Ok(self.get_inner().device.temperature.clone())
+    }
+
+    fn set_value(&mut self, value: Temperature, _mqtt_client: MqttWrapper) {
+        self.get_inner_mut().device.temperature = value;
+    }
+
+    async fn handle_mqtt_message(&mut self, message: Message, accessory: HapRsAccessory) -> Result<(), &'static str> {
+        let payload = message.payload_str();
+        let temperature = Temperature(payload.trim().parse::<f32>().map_err(|_| "Could not parse temperature")?);
+
+        self.get_inner_mut().device.temperature = temperature.clone();
+        accessory.set_value(HapType::TemperatureSensor, HapType::CurrentTemperature, temperature.0.into()).await?;
+
+        Ok(())
+    }
+}
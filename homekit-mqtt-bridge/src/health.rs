@@ -0,0 +1,58 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::mqtt::MqttWrapper;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HapState {
+    Starting,
+    Running,
+    Stopped,
+}
+
+/// What the `/healthz` and `/readyz` endpoints report.
+#[derive(Clone)]
+pub struct Health {
+    mqtt: MqttWrapper,
+    hap: Arc<Mutex<HapState>>,
+}
+
+impl Health {
+    pub fn new(mqtt: MqttWrapper) -> Self {
+        Self { mqtt, hap: Arc::new(Mutex::new(HapState::Starting)) }
+    }
+
+    pub fn set_hap_state(&self, state: HapState) {
+        *self.hap.lock().unwrap() = state;
+    }
+
+    fn hap_state(&self) -> HapState {
+        *self.hap.lock().unwrap()
+    }
+
+    /// Whether the bridge works or is still starting. Unhealthy when the mqtt connection or the HomeKit server are gone.
+    pub fn is_healthy(&self) -> bool {
+        self.mqtt.is_connected() && self.hap_state() != HapState::Stopped
+    }
+
+    /// Whether the bridge is serving HomeKit controllers.
+    #[cfg(feature = "metrics-server")]
+    pub fn is_ready(&self) -> bool {
+        self.mqtt.is_connected() && self.hap_state() == HapState::Running
+    }
+
+    pub fn report(&self) -> String {
+        let ago = |instant: Option<Instant>| match instant {
+            Some(instant) => format!("{}s ago", instant.elapsed().as_secs()),
+            None => "never".to_string(),
+        };
+
+        let mut report = String::new();
+        let _ = writeln!(report, "mqtt: {}", if self.mqtt.is_connected() { "connected" } else { "disconnected" });
+        let _ = writeln!(report, "homekit server: {:?}", self.hap_state());
+        let _ = writeln!(report, "last command: {}", ago(self.mqtt.last_published()));
+        let _ = writeln!(report, "last message: {}", ago(self.mqtt.last_received()));
+        report
+    }
+}
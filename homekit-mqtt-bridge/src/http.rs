@@ -0,0 +1,69 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hap::storage::{FileStorage, Storage};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use tracing::{error, info, warn};
+
+use crate::health::Health;
+use crate::metrics;
+
+struct State {
+    storage: FileStorage,
+    health: Health,
+}
+
+fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle_request(request: Request<Body>, state: Arc<State>) -> Result<Response<Body>, Infallible> {
+    let response = match request.uri().path() {
+        "/metrics" => {
+            match state.storage.count_pairings().await {
+                Ok(count) => metrics::set_paired_controllers(count),
+                Err(e) => warn!("Failed to count the paired controllers: {}", e),
+            }
+
+            let mut response = response(StatusCode::OK, metrics::encode());
+            response.headers_mut().insert(hyper::header::CONTENT_TYPE, metrics::CONTENT_TYPE.parse().unwrap());
+            response
+        }
+        "/healthz" => match state.health.is_healthy() {
+            true => response(StatusCode::OK, state.health.report()),
+            false => response(StatusCode::SERVICE_UNAVAILABLE, state.health.report()),
+        },
+        "/readyz" => match state.health.is_ready() {
+            true => response(StatusCode::OK, state.health.report()),
+            false => response(StatusCode::SERVICE_UNAVAILABLE, state.health.report()),
+        },
+        _ => response(StatusCode::NOT_FOUND, "Not found"),
+    };
+
+    Ok(response)
+}
+
+/// Serves `/metrics`, `/healthz` and `/readyz`. The paired controllers are counted from `storage` on each scrape.
+pub async fn serve(address: SocketAddr, storage: FileStorage, health: Health) {
+    let state = Arc::new(State { storage, health });
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle_request(request, state.clone()))) }
+    });
+
+    let server = match Server::try_bind(&address) {
+        Ok(server) => server,
+        Err(e) => return error!("Failed to serve http on {}: {}", address, e),
+    };
+
+    info!("Serving metrics and health checks on http://{}", address);
+
+    if let Err(e) = server.serve(make_service).await {
+        error!("Http server failed: {}", e);
+    }
+}
@@ -0,0 +1,292 @@
+//! The HomeKit bridge, run by its own binary or next to the controllers by the launcher.
+
+#[cfg(feature = "metrics-server")]
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use hap::{accessory::{AccessoryCategory, AccessoryInformation}, MacAddress, Pin, Result, server::{IpServer, Server}, storage::{FileStorage, Storage}};
+use hap::accessory::bridge::BridgeAccessory;
+use domain_state::Topic;
+use hap::futures::future::join_all;
+use mqtt_client::{ConnectError, ConnectOptions, Message, Messages};
+use tracing::{info, warn};
+
+use crate::args::Args;
+use crate::config::MqttConfig;
+use crate::console::Console;
+use crate::health::{HapState, Health};
+use crate::heartbeat::HEARTBEAT_INTERVAL;
+use crate::logging::Logs;
+use crate::mqtt::MqttWrapper;
+use crate::reload::Reloadable;
+use crate::startup::StartupError;
+
+pub mod args;
+mod check;
+mod config;
+mod console;
+mod device;
+mod health;
+mod heartbeat;
+#[cfg(feature = "metrics-server")]
+mod http;
+pub mod logging;
+mod metrics;
+mod mqtt;
+mod remap;
+mod reload;
+pub mod startup;
+mod supervisor;
+mod systemd;
+mod telemetry;
+mod throttle;
+
+/// The device the topics of the bridge itself are under, e.g. `smart-home-system/homekit-mqtt-bridge/heartbeat`.
+const DEVICE: &str = "homekit-mqtt-bridge";
+
+fn topic() -> Topic {
+    Topic::device(DEVICE)
+}
+
+async fn load_hap_rs_config(storage: &mut FileStorage, pin: [u8; 8]) -> Result<hap::Config> {
+    let config = match storage.load_config().await {
+        Ok(mut config) => {
+            config.redetermine_local_ip();
+            // The pin of the config is used even after pairing
+            config.pin = Pin::new(pin)?;
+            storage.save_config(&config).await?;
+            config
+        }
+        Err(_) => {
+            let config = hap::Config {
+                pin: Pin::new(pin)?,
+                name: "smart-home-server-bridge".into(),
+                device_id: MacAddress::from_bytes(&[20u8, 20u8, 30u8, 40u8, 50u8, 60u8]).unwrap(),
+                category: AccessoryCategory::Bridge,
+                ..Default::default()
+            };
+            storage.save_config(&config).await?;
+            config
+        }
+    };
+    Ok(config)
+}
+
+/// The connection to the mqtt server with the messages received on it, shared by the launcher with the controllers
+/// it runs next to the bridge.
+pub struct MqttConnection {
+    pub client: mqtt_client::Client,
+    pub messages: Messages,
+}
+
+/// Connects to the mqtt server of the config of the arguments, reconnecting when the connection is lost as it's
+/// shared by the launcher with services that don't stop with it. The topics aren't subscribed to again.
+pub async fn connect_mqtt(args: &Args) -> std::result::Result<MqttConnection, StartupError> {
+    let config = args.load_config().map_err(StartupError::Config)?;
+    connect(&config.mqtt, Some((Duration::from_secs(1), Duration::from_secs(30)))).await
+}
+
+async fn connect(config: &MqttConfig, reconnect: Option<(Duration, Duration)>) -> std::result::Result<MqttConnection, StartupError> {
+    let options = ConnectOptions {
+        username: config.username.clone(),
+        password: config.password.clone(),
+        keep_alive: Duration::from_secs(20),
+        reconnect,
+        ..ConnectOptions::new(config.server_uri.clone().unwrap_or_default(), config.client_id.clone())
+    };
+
+    let (client, messages) = mqtt_client::connect(&options).await.map_err(|e| match e {
+        ConnectError::Options(e) => StartupError::Config(e),
+        ConnectError::Unavailable(e) => StartupError::Unavailable(e),
+    })?;
+
+    Ok(MqttConnection { client, messages })
+}
+
+/// Runs the bridge until the HomeKit server stops, or checks the config if the arguments ask for it, failing with
+/// [`StartupError::Config`] if a setting is invalid.
+pub async fn run(args: Args, logs: Logs) -> std::result::Result<(), StartupError> {
+    run_on(args, logs, None).await
+}
+
+/// As [`run`], on the connection to the mqtt server of the launcher instead of its own.
+pub async fn run_shared(args: Args, logs: Logs, connection: MqttConnection) -> std::result::Result<(), StartupError> {
+    run_on(args, logs, Some(connection)).await
+}
+
+async fn run_on(args: Args, logs: Logs, connection: Option<MqttConnection>) -> std::result::Result<(), StartupError> {
+    let started = Instant::now();
+    let last_error = logs.last_error;
+
+    if args.check {
+        match args.config_path() {
+            Some(path) => println!("Checking the config {:?} with the env vars", path),
+            None => println!("Checking the config of the env vars, there's no config file"),
+        }
+        let report = check::check(&args);
+        println!("{}", report);
+        return match report.has_errors() {
+            true => Err(StartupError::Config(anyhow!("{} settings are invalid, see the report above", report.errors().count()))),
+            false => Ok(()),
+        };
+    }
+
+    let config_path = args.config_path();
+    let config = args.load_config().map_err(StartupError::Config)?;
+
+    let reloadable = Reloadable { log_filter: logs.filter, throttle: logs.throttle };
+    reloadable.apply(&config).map_err(StartupError::Config)?;
+
+    if let Some(profile) = &config.profile {
+        info!("Using the {} profile", profile);
+    }
+    mqtt::set_topic_prefix(config.mqtt.topic_prefix.clone());
+    remap::enable(&config.mqtt.remap).map_err(StartupError::Config)?;
+
+    let MqttConnection { client, messages } = match connection {
+        Some(connection) => connection,
+        None => connect(&config.mqtt, None).await?,
+    };
+
+    let heartbeat_client = client.clone();
+    let heartbeat_storage = FileStorage::current_dir().await.map_err(storage_error)?;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let paired_controllers = heartbeat_storage.count_pairings().await.unwrap_or_else(|e| {
+                warn!("Failed to count the paired controllers: {}", e);
+                0
+            });
+            let payload = heartbeat::payload(started, paired_controllers, last_error.get());
+            mqtt::Publish::publish(&heartbeat_client, Message::new(mqtt::broker_topic(&topic().heartbeat().to_string()), payload, 0));
+        }
+    });
+
+    let mut mqtt_wrapper = MqttWrapper::new(client);
+    let mqtt_read_handle = mqtt_wrapper.start_reading(messages);
+
+    let diagnostics_mqtt = mqtt_wrapper.clone();
+    mqtt_wrapper.subscribe(topic().diagnostics().get(), Box::new(move |_| {
+        let mut mqtt = diagnostics_mqtt.clone();
+        Box::pin(async move { mqtt.publish(topic().diagnostics(), metrics::diagnostics()) })
+    }));
+
+    let bridge = BridgeAccessory::new(1, AccessoryInformation {
+        name: "smart-home-system bridge".into(),
+        ..Default::default()
+    }).map_err(|e| StartupError::Other(anyhow!("Failed to create the bridge accessory: {}", e)))?;
+
+    let mut storage = FileStorage::current_dir().await.map_err(storage_error)?;
+
+    let pin = config.pin().map_err(|e| StartupError::Config(anyhow!("Invalid pin: {}", e)))?;
+    let hap_config = load_hap_rs_config(&mut storage, pin).await.map_err(storage_error)?;
+
+    let server = IpServer::new(hap_config, storage).await.map_err(storage_error)?;
+    server.add_accessory(bridge).await
+        .map_err(|e| StartupError::Other(anyhow!("Failed to add the bridge accessory: {}", e)))?;
+
+    // The accessories listed by the admin console, with their aid, empty if the bridge is built without any
+    #[allow(unused_mut)]
+    let mut accessories = Vec::new();
+
+    #[cfg(feature = "lightbulb")]
+    {
+        // The yeelight controller turns the bulb on with the power and brightness of a scene at once
+        let mut device = device::lightbulb_device::LightbulbDevice::new("yeelight".into(), Topic::device("yeelight"))
+            .with_batched_updates();
+        device.setup(2, &mut mqtt_wrapper, &server).await;
+        accessories.push((2, device.name()));
+    }
+
+    #[cfg(feature = "temperature-sensor")]
+    {
+        let mut server_temperature = device::temperature_sensor_device::TemperatureSensorDevice::new("server".into());
+        server_temperature.setup(3, "smart-home-system/host/server/cpu_temperature", &mut mqtt_wrapper, &server).await;
+        accessories.push((3, server_temperature.name()));
+    }
+
+    #[cfg(feature = "speaker")]
+    {
+        let mut chromecast = device::speaker_device::SpeakerDevice::new("chromecast".into(), Topic::device("chromecast"));
+        chromecast.setup(4, &mut mqtt_wrapper, &server).await;
+        accessories.push((4, chromecast.name()));
+    }
+
+    #[cfg(feature = "lightbulb")]
+    {
+        let mut knx_light = device::lightbulb_device::LightbulbDevice::new("living-room-light".into(), Topic::device("knx/living-room-light"));
+        knx_light.setup(5, &mut mqtt_wrapper, &server).await;
+        accessories.push((5, knx_light.name()));
+    }
+
+    #[cfg(feature = "contact-sensor")]
+    {
+        let mut ups = device::contact_sensor_device::ContactSensorDevice::new("ups".into());
+        ups.setup(6, "smart-home-system/ups/on_battery", "smart-home-system/ups/low_battery", &mut mqtt_wrapper, &server).await;
+        accessories.push((6, ups.name()));
+    }
+
+    #[cfg(feature = "scene")]
+    {
+        let mut movie_scene = device::scene_device::SceneDevice::new("movie".into());
+        movie_scene.setup(7, &mut mqtt_wrapper, &server).await;
+        accessories.push((7, movie_scene.name()));
+    }
+
+    #[cfg(feature = "switch")]
+    {
+        let mut vacation_mode = device::switch_device::SwitchDevice::new("vacation-mode".into(), "smart-home-system/automation/vacation/enabled".into());
+        vacation_mode.setup(8, &mut mqtt_wrapper, &server).await;
+        accessories.push((8, vacation_mode.name()));
+
+        let mut laundry_timer = device::switch_device::SwitchDevice::new("laundry-timer".into(), "smart-home-system/automation/timer/laundry/running".into());
+        laundry_timer.setup(9, &mut mqtt_wrapper, &server).await;
+        accessories.push((9, laundry_timer.name()));
+    }
+
+    let health = Health::new(mqtt_wrapper.clone());
+
+    if let Some(path) = config.admin_socket.clone() {
+        tokio::spawn(console::serve(path, Console::new(health.clone(), mqtt_wrapper.clone(), accessories)));
+    }
+
+    #[cfg_attr(not(feature = "metrics-server"), allow(unused_mut))]
+    let mut handles = vec![mqtt_read_handle];
+
+    #[cfg(feature = "metrics-server")]
+    {
+        let http_storage = FileStorage::current_dir().await.map_err(storage_error)?;
+        handles.push(tokio::spawn(http::serve(SocketAddr::from(([0, 0, 0, 0], config.http_port)), http_storage, health.clone())));
+    }
+
+    if let Some(path) = config_path {
+        tokio::spawn(reload::watch(path, args, config.clone(), reloadable, mqtt_wrapper.clone()));
+    }
+
+    tokio::spawn(systemd::run_watchdog(health.clone()));
+
+    let hap_rs_handle = tokio::spawn(async move {
+        let handle = server.run_handle();
+        health.set_hap_state(HapState::Running);
+        systemd::notify_ready();
+        let result = handle.await;
+        health.set_hap_state(HapState::Stopped);
+        result
+    });
+
+    match hap_rs_handle.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(StartupError::Unavailable(anyhow!("The HomeKit server stopped: {}", e))),
+        Err(e) => return Err(StartupError::Other(anyhow!("The HomeKit server panicked: {}", e))),
+    }
+
+    join_all(handles).await;
+
+    Ok(())
+}
+
+fn storage_error(e: hap::Error) -> StartupError {
+    StartupError::Storage(anyhow!("Failed to access the HomeKit storage: {}", e))
+}
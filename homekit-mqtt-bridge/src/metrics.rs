@@ -0,0 +1,154 @@
+#[cfg(feature = "metrics")]
+use std::collections::BTreeMap;
+#[cfg(feature = "metrics")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "metrics")]
+use prometheus::core::Collector;
+#[cfg(feature = "metrics")]
+use prometheus::{IntCounterVec, IntGauge, Opts, Registry};
+#[cfg(feature = "metrics-server")]
+use prometheus::{Encoder, TextEncoder};
+#[cfg(feature = "metrics-server")]
+use tracing::error;
+
+// The registry and the paired controllers are only read when encoded for the metrics server
+#[cfg(feature = "metrics")]
+#[cfg_attr(not(feature = "metrics-server"), allow(dead_code))]
+struct Metrics {
+    registry: Registry,
+    characteristic_reads: IntCounterVec,
+    characteristic_updates: IntCounterVec,
+    callback_errors: IntCounterVec,
+    mqtt_received: IntCounterVec,
+    mqtt_published: IntCounterVec,
+    mqtt_publish_failures: IntCounterVec,
+    paired_controllers: IntGauge,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("homekit_mqtt_bridge".into()), None)
+            .expect("The metrics registry should be created successfully.");
+
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("The metric should be valid.");
+            registry.register(Box::new(counter.clone())).expect("The metric should be registered once.");
+            counter
+        };
+
+        let characteristic_reads = counter("characteristic_reads_total", "Characteristic reads by HomeKit controllers", &["accessory", "characteristic"]);
+        let characteristic_updates = counter("characteristic_updates_total", "Characteristic updates by HomeKit controllers", &["accessory", "characteristic"]);
+        let callback_errors = counter("callback_errors_total", "Errors reading a characteristic or handling an mqtt message", &["accessory"]);
+        let mqtt_received = counter("mqtt_messages_received_total", "Mqtt messages received", &["topic"]);
+        let mqtt_published = counter("mqtt_messages_published_total", "Mqtt messages published", &["topic"]);
+        let mqtt_publish_failures = counter("mqtt_publish_failures_total", "Mqtt messages the broker didn't acknowledge", &["topic"]);
+
+        let paired_controllers = IntGauge::new("paired_controllers", "HomeKit controllers paired with the bridge")
+            .expect("The metric should be valid.");
+        registry.register(Box::new(paired_controllers.clone())).expect("The metric should be registered once.");
+
+        Self { registry, characteristic_reads, characteristic_updates, callback_errors, mqtt_received, mqtt_published, mqtt_publish_failures, paired_controllers }
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[cfg(feature = "metrics")]
+pub fn characteristic_read(accessory: &str, characteristic: &str) {
+    metrics().characteristic_reads.with_label_values(&[accessory, characteristic]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn characteristic_update(accessory: &str, characteristic: &str) {
+    metrics().characteristic_updates.with_label_values(&[accessory, characteristic]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn callback_error(accessory: &str) {
+    metrics().callback_errors.with_label_values(&[accessory]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn mqtt_received(topic: &str) {
+    metrics().mqtt_received.with_label_values(&[topic]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn mqtt_published(topic: &str) {
+    metrics().mqtt_published.with_label_values(&[topic]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn mqtt_publish_failed(topic: &str) {
+    metrics().mqtt_publish_failures.with_label_values(&[topic]).inc();
+}
+
+#[cfg(feature = "metrics-server")]
+pub fn set_paired_controllers(count: usize) {
+    metrics().paired_controllers.set(count as i64);
+}
+
+/// The value of a counter for each topic it was incremented for.
+#[cfg(feature = "metrics")]
+fn per_topic(counter: &IntCounterVec) -> BTreeMap<String, u64> {
+    counter.collect().iter()
+        .flat_map(|family| family.get_metric())
+        .filter_map(|metric| {
+            let topic = metric.get_label().iter().find(|label| label.get_name() == "topic")?;
+            Some((topic.get_value().to_string(), metric.get_counter().get_value() as u64))
+        })
+        .collect()
+}
+
+/// The mqtt messages received, published and not acknowledged per topic as json, e.g. to spot a publisher
+/// spamming the broker.
+#[cfg(feature = "metrics")]
+pub fn diagnostics() -> String {
+    serde_json::json!({
+        "received": per_topic(&metrics().mqtt_received),
+        "published": per_topic(&metrics().mqtt_published),
+        "publish_failures": per_topic(&metrics().mqtt_publish_failures),
+    }).to_string()
+}
+
+/// Built without the metrics feature, nothing is counted and the diagnostics are empty.
+#[cfg(not(feature = "metrics"))]
+mod disabled {
+    pub fn characteristic_read(_accessory: &str, _characteristic: &str) {}
+
+    pub fn characteristic_update(_accessory: &str, _characteristic: &str) {}
+
+    pub fn callback_error(_accessory: &str) {}
+
+    pub fn mqtt_received(_topic: &str) {}
+
+    pub fn mqtt_published(_topic: &str) {}
+
+    pub fn mqtt_publish_failed(_topic: &str) {}
+
+    pub fn diagnostics() -> String {
+        "{}".into()
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub use disabled::*;
+
+#[cfg(feature = "metrics-server")]
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+/// Encodes every metric in the prometheus text format.
+#[cfg(feature = "metrics-server")]
+pub fn encode() -> Vec<u8> {
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&metrics().registry.gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", e);
+    }
+    buffer
+}
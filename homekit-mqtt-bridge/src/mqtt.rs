@@ -1,24 +1,153 @@
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
-use paho_mqtt::{AsyncClient, Message};
+use mqtt_client::{Message, Messages};
 use tokio::task::JoinHandle;
+use tracing::{debug, info_span, warn, Instrument};
+
+use crate::{metrics, remap, supervisor, telemetry};
+
+/// The root of the topics of the bridge and of the devices, replaced by the configured prefix on the broker.
+pub const DEFAULT_TOPIC_PREFIX: &str = domain_state::topic::PREFIX;
+
+static TOPIC_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// How long a state received right after a command was published to its `/set` topic is taken as the controller
+/// echoing the command if it has the same value, and ignored so it doesn't update the characteristic again.
+const ECHO_WINDOW: Duration = Duration::from_secs(2);
+
+/// How long the broker has to acknowledge a published message before it's counted as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Replaces `smart-home-system` in the topics published and subscribed to.
+pub fn set_topic_prefix(prefix: String) {
+    if prefix != DEFAULT_TOPIC_PREFIX {
+        let _ = TOPIC_PREFIX.set(prefix);
+    }
+}
+
+fn replace_prefix(topic: &str, from: &str, to: &str) -> Option<String> {
+    match topic.strip_prefix(from) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(format!("{}{}", to, rest)),
+        _ => None,
+    }
+}
+
+fn replace_topic_prefix(topic: &str) -> Option<String> {
+    replace_prefix(topic, DEFAULT_TOPIC_PREFIX, TOPIC_PREFIX.get()?)
+}
+
+/// The topic on the broker of a topic of the bridge.
+pub fn broker_topic(topic: &str) -> String {
+    remap::get().and_then(|remap| remap.outbound(topic))
+        .or_else(|| replace_topic_prefix(topic))
+        .unwrap_or_else(|| topic.to_string())
+}
+
+/// As [`broker_topic`], remembering the remapped topics to rewrite the messages received on them.
+fn subscribe_topic(topic: &str) -> String {
+    remap::get().and_then(|remap| remap.subscribe(topic))
+        .or_else(|| replace_topic_prefix(topic))
+        .unwrap_or_else(|| topic.to_string())
+}
+
+/// The topic of the bridge of a topic received from the broker.
+fn bridge_topic(topic: &str) -> String {
+    remap::get().and_then(|remap| remap.inbound(topic))
+        .or_else(|| TOPIC_PREFIX.get().and_then(|prefix| replace_prefix(topic, prefix, DEFAULT_TOPIC_PREFIX)))
+        .unwrap_or_else(|| topic.to_string())
+}
+
+/// Publishes messages to the broker, implemented by the mqtt client and by [`FakeClient`] in the tests.
+pub trait Publish: Send + Sync {
+    fn publish(&self, message: Message);
+}
+
+/// Subscribes to topics on the broker, implemented by the mqtt client and by [`FakeClient`] in the tests.
+pub trait Subscribe: Send + Sync {
+    fn subscribe(&self, topic: &str);
+}
+
+/// The client of [`MqttWrapper`].
+pub trait MqttClient: Publish + Subscribe {
+    fn is_connected(&self) -> bool;
+}
+
+impl Publish for mqtt_client::Client {
+    fn publish(&self, message: Message) {
+        let topic = message.topic().to_string();
+        let token = mqtt_client::Client::publish(self, message);
+
+        // The broker acknowledges the message in the background, a failure would otherwise lose it silently
+        tokio::spawn(async move {
+            let error = match tokio::time::timeout(DELIVERY_TIMEOUT, token).await {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => format!("not acknowledged in {:?}", DELIVERY_TIMEOUT),
+            };
+            warn!("Failed to publish to {}: {}", topic, error);
+            metrics::mqtt_publish_failed(&topic);
+        });
+    }
+}
+
+impl Subscribe for mqtt_client::Client {
+    fn subscribe(&self, topic: &str) {
+        // Acknowledged in the background
+        drop(mqtt_client::Client::subscribe(self, topic));
+    }
+}
+
+impl MqttClient for mqtt_client::Client {
+    fn is_connected(&self) -> bool {
+        mqtt_client::Client::is_connected(self)
+    }
+}
+
+impl<C: Publish + ?Sized> Publish for Arc<C> {
+    fn publish(&self, message: Message) {
+        (**self).publish(message);
+    }
+}
+
+impl<C: Subscribe + ?Sized> Subscribe for Arc<C> {
+    fn subscribe(&self, topic: &str) {
+        (**self).subscribe(topic);
+    }
+}
+
+impl<C: MqttClient + ?Sized> MqttClient for Arc<C> {
+    fn is_connected(&self) -> bool {
+        (**self).is_connected()
+    }
+}
 
 type Callback = Box<dyn Fn(Message) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
 
+/// The values published to a `/set` topic, with when.
+type Sent = Vec<(Vec<u8>, Instant)>;
+
 #[derive(Clone)]
 pub struct MqttWrapper {
-    client: AsyncClient,
+    client: Arc<dyn MqttClient>,
     callbacks: Arc<DashMap<String, Callback>>,
+    /// The values published to the `/set` topics in the echo window, by the topic of their state.
+    sent: Arc<DashMap<String, Sent>>,
+    last_published: Arc<Mutex<Option<Instant>>>,
+    last_received: Arc<Mutex<Option<Instant>>>,
 }
 
 impl MqttWrapper {
-    pub fn new(client: AsyncClient) -> MqttWrapper {
+    pub fn new(client: impl MqttClient + 'static) -> MqttWrapper {
         MqttWrapper {
-            client,
+            client: Arc::new(client),
             callbacks: Arc::new(DashMap::new()),
+            sent: Arc::new(DashMap::new()),
+            last_published: Arc::new(Mutex::new(None)),
+            last_received: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -26,36 +155,197 @@ impl MqttWrapper {
         where
             S: Into<String>,
             V: Into<Vec<u8>> {
-        let message = Message::new(topic, value, 1);
+        let topic = topic.into();
+        let value = value.into();
+        metrics::mqtt_published(&topic);
+        *self.last_published.lock().unwrap() = Some(Instant::now());
+
+        if let Some(state_topic) = topic.strip_suffix("/set") {
+            let now = Instant::now();
+            let mut sent = self.sent.entry(state_topic.to_string()).or_default();
+            sent.retain(|(_, at)| now.duration_since(*at) < ECHO_WINDOW);
+            sent.push((value.clone(), now));
+        }
+
+        let message = Message::new(broker_topic(&topic), value, 1).with_user_properties(telemetry::trace_properties());
         self.client.publish(message);
     }
 
+    pub fn is_connected(&self) -> bool {
+        self.client.is_connected()
+    }
+
+    pub fn last_published(&self) -> Option<Instant> {
+        *self.last_published.lock().unwrap()
+    }
+
+    pub fn last_received(&self) -> Option<Instant> {
+        *self.last_received.lock().unwrap()
+    }
+
     pub fn subscribe<S>(&mut self, topic: S, callback: Callback)
         where
             S: Into<String> {
         let topic = topic.into();
 
-        self.client.subscribe(topic.clone(), 1);
+        self.client.subscribe(&subscribe_topic(&topic));
         self.callbacks.insert(topic.clone(), callback);
     }
 
-    pub fn start_reading(&self) -> JoinHandle<()> {
-        let mut self_clone = self.clone();
-        tokio::spawn(async move {
-            let receiver = self_clone.client.get_stream(10);
-            while let Ok(message) = receiver.recv().await {
-                if let Some(message) = message {
-                    self_clone.handle_message(message).await;
+    /// The topics with a callback.
+    pub fn topics(&self) -> Vec<String> {
+        let mut topics: Vec<String> = self.callbacks.iter().map(|entry| entry.key().clone()).collect();
+        topics.sort();
+        topics
+    }
+
+    /// Handles the message as if it was received from mqtt, returning whether a callback handled it.
+    pub async fn inject(&mut self, message: Message) -> bool {
+        let handled = self.callbacks.contains_key(message.topic());
+        self.handle_message(message).await;
+        handled
+    }
+
+    /// Handles the messages received from mqtt on the stream of the client, restarted if a callback panics so
+    /// the next messages are still handled.
+    pub fn start_reading(&self, receiver: Messages) -> JoinHandle<()> {
+        let self_clone = self.clone();
+        supervisor::supervise("mqtt read", move || {
+            let mut self_clone = self_clone.clone();
+            let receiver = receiver.clone();
+            async move {
+                while let Ok(message) = receiver.recv().await {
+                    if let Some(message) = message {
+                        self_clone.handle_message(message).await;
+                    }
                 }
             }
         })
     }
 
+    /// Whether the payload was published to the `/set` topic of the state topic in the echo window.
+    fn is_echo(&self, topic: &str, payload: &[u8]) -> bool {
+        self.sent.get(topic)
+            .is_some_and(|sent| sent.iter().any(|(value, at)| value == payload && at.elapsed() < ECHO_WINDOW))
+    }
+
     async fn handle_message(&mut self, message: Message) {
-        let topic = message.topic();
+        let topic = bridge_topic(message.topic());
+        // e.g. the commands to the controllers, on the connection the launcher shares with them
+        if !self.callbacks.contains_key(&topic) {
+            return;
+        }
+        metrics::mqtt_received(&topic);
+        *self.last_received.lock().unwrap() = Some(Instant::now());
 
-        if let Some(sender) = self.callbacks.get(topic) {
-            sender(message).await;
+        if self.is_echo(&topic, message.payload()) {
+            debug!(topic, "Ignoring the state echoing the command just published");
+            return;
         }
+
+        if let Some(sender) = self.callbacks.get(&topic) {
+            let span = info_span!("mqtt_message", topic);
+            telemetry::set_parent(&span, &message);
+            sender(message).instrument(span).await;
+        }
+    }
+}
+
+/// Keeps the messages published and the topics subscribed to in memory, instead of sending them to a broker.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeClient {
+    published: Mutex<Vec<Message>>,
+    subscribed: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl FakeClient {
+    /// The topics and payloads of the messages published since the last call.
+    pub fn take_published(&self) -> Vec<(String, String)> {
+        self.published.lock().unwrap().drain(..)
+            .map(|message| (message.topic().to_string(), message.payload_str().to_string()))
+            .collect()
+    }
+
+    pub fn subscribed(&self) -> Vec<String> {
+        self.subscribed.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Publish for FakeClient {
+    fn publish(&self, message: Message) {
+        self.published.lock().unwrap().push(message);
+    }
+}
+
+#[cfg(test)]
+impl Subscribe for FakeClient {
+    fn subscribe(&self, topic: &str) {
+        self.subscribed.lock().unwrap().push(topic.to_string());
+    }
+}
+
+#[cfg(test)]
+impl MqttClient for FakeClient {
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use mqtt_client::Message;
+    use tokio::sync::mpsc;
+
+    use crate::mqtt::{FakeClient, MqttWrapper};
+
+    #[tokio::test]
+    async fn test_subscribe_and_inject() {
+        let client = Arc::new(FakeClient::default());
+        let mut mqtt = MqttWrapper::new(client.clone());
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        mqtt.subscribe("smart-home-system/yeelight/power", Box::new(move |message| {
+            let sender = sender.clone();
+            Box::pin(async move { sender.send(message.payload_str().to_string()).unwrap() })
+        }));
+        assert_eq!(client.subscribed(), ["smart-home-system/yeelight/power"]);
+
+        assert!(!mqtt.inject(Message::new("smart-home-system/yeelight/brightness", "40", 1)).await);
+        // Not counted, e.g. a message to a controller on the connection shared by the launcher
+        assert!(mqtt.last_received().is_none());
+        assert!(mqtt.inject(Message::new("smart-home-system/yeelight/power", "on", 1)).await);
+        assert_eq!(receiver.try_recv().as_deref(), Ok("on"));
+        assert!(mqtt.last_received().is_some());
+
+        mqtt.publish("smart-home-system/yeelight/power/set", "off");
+        assert_eq!(client.take_published(), [("smart-home-system/yeelight/power/set".to_string(), "off".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_echoed_state_is_ignored() {
+        let mut mqtt = MqttWrapper::new(FakeClient::default());
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        mqtt.subscribe("smart-home-system/yeelight/brightness", Box::new(move |message| {
+            let sender = sender.clone();
+            Box::pin(async move { sender.send(message.payload_str().to_string()).unwrap() })
+        }));
+
+        mqtt.publish("smart-home-system/yeelight/brightness/set", "40");
+        mqtt.publish("smart-home-system/yeelight/brightness/set", "50");
+
+        // The echoes of both commands, even the one of the command published before the last
+        mqtt.inject(Message::new("smart-home-system/yeelight/brightness", "40", 1)).await;
+        mqtt.inject(Message::new("smart-home-system/yeelight/brightness", "50", 1)).await;
+        // Changed by someone else
+        mqtt.inject(Message::new("smart-home-system/yeelight/brightness", "60", 1)).await;
+
+        assert_eq!(receiver.try_recv().as_deref(), Ok("60"));
+        assert!(receiver.try_recv().is_err());
     }
 }
@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use serde_json::json;
+use tracing::{error, info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::args::Args;
+use crate::config::Config;
+use crate::mqtt::MqttWrapper;
+use crate::throttle::Throttle;
+use crate::topic;
+
+/// How often the config file is checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type LogFilter = reload::Handle<EnvFilter, Registry>;
+
+/// The parts of the bridge that are changed when the config is reloaded.
+pub struct Reloadable {
+    pub log_filter: LogFilter,
+    pub throttle: Throttle,
+}
+
+impl Reloadable {
+    pub fn apply(&self, config: &Config) -> anyhow::Result<()> {
+        let filter = EnvFilter::try_new(&config.log_level).context(format!("Invalid log level {:?}", config.log_level))?;
+        self.log_filter.reload(filter).context("Failed to change the log level")?;
+        self.throttle.set_window(Duration::from_secs(config.log_throttle_secs));
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads the config when the file changes and applies the settings that don't need a restart. The others
+/// are only logged, and a `config/reloaded` event with them is published.
+pub async fn watch(path: PathBuf, args: Args, running: Config, reloadable: Reloadable, mut mqtt: MqttWrapper) {
+    let mut last_modified = modified(&path);
+    let mut config = running.clone();
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let modified = modified(&path);
+        if modified == last_modified {
+            continue;
+        }
+        last_modified = modified;
+
+        let reloaded = match args.load_config() {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                error!("Failed to reload the config, keeping the current one: {:#}", e);
+                continue;
+            }
+        };
+
+        // Saving the file without changing it
+        if reloaded == config {
+            continue;
+        }
+
+        if let Err(e) = reloadable.apply(&reloaded) {
+            error!("Failed to apply the reloaded config: {:#}", e);
+            continue;
+        }
+
+        let restart_required = running.restart_required(&reloaded);
+        for setting in &restart_required {
+            warn!("The {} setting changed, restart the bridge to apply it", setting);
+        }
+
+        info!("Reloaded the config from {:?}", path);
+        let payload = json!({ "path": path, "restart_required": restart_required });
+        mqtt.publish(topic().config().reloaded(), payload.to_string());
+
+        config = reloaded;
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::OnceLock;
+
+#[cfg(not(feature = "remap-regex"))]
+use anyhow::bail;
+#[cfg(feature = "remap-regex")]
+use anyhow::Context;
+use dashmap::DashMap;
+#[cfg(feature = "remap-regex")]
+use regex::Regex;
+use serde::Deserialize;
+
+/// A rule of the topic remapping table, rewriting a topic of the bridge to the topic used on the broker.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum RemapRule {
+    /// Replaces the start of the topics, e.g. `smart-home-system/yeelight/power` to `stat/bedroom-light/power`.
+    Prefix { prefix: String, to: String },
+    /// Replaces the topics matching the regex, with `$1` for its first group, e.g.
+    /// `^smart-home-system/yeelight/(.+)/set$` to `cmnd/bedroom-light/$1`. Needs the remap-regex feature.
+    Regex { regex: String, to: String },
+}
+
+enum Rule {
+    Prefix { prefix: String, to: String },
+    #[cfg(feature = "remap-regex")]
+    Regex { regex: Regex, to: String },
+}
+
+impl Rule {
+    fn new(rule: &RemapRule) -> anyhow::Result<Self> {
+        match rule {
+            RemapRule::Prefix { prefix, to } => Ok(Rule::Prefix { prefix: prefix.clone(), to: to.clone() }),
+            #[cfg(feature = "remap-regex")]
+            RemapRule::Regex { regex, to } => {
+                let regex = Regex::new(regex).with_context(|| format!("{:?} is an invalid regex", regex))?;
+                Ok(Rule::Regex { regex, to: to.clone() })
+            }
+            #[cfg(not(feature = "remap-regex"))]
+            RemapRule::Regex { regex, .. } => bail!("{:?} needs the bridge to be built with the remap-regex feature", regex),
+        }
+    }
+
+    fn apply(&self, topic: &str) -> Option<String> {
+        match self {
+            Rule::Prefix { prefix, to } => match topic.strip_prefix(prefix.as_str()) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(format!("{}{}", to, rest)),
+                _ => None,
+            },
+            #[cfg(feature = "remap-regex")]
+            Rule::Regex { regex, to } => regex.is_match(topic).then(|| regex.replace(topic, to.as_str()).into_owned()),
+        }
+    }
+}
+
+/// Rewrites the topics of the bridge with the first rule that matches them. The messages received are
+/// rewritten back with the topics that were subscribed to, as the regex rules can't be inverted.
+pub struct Remap {
+    rules: Vec<Rule>,
+    /// The topic of the bridge of every topic subscribed to on the broker.
+    subscribed: DashMap<String, String>,
+}
+
+static REMAP: OnceLock<Remap> = OnceLock::new();
+
+/// Rewrites the topics published and subscribed to with the rules.
+pub fn enable(rules: &[RemapRule]) -> anyhow::Result<()> {
+    if !rules.is_empty() {
+        let _ = REMAP.set(Remap::new(rules)?);
+    }
+    Ok(())
+}
+
+pub fn get() -> Option<&'static Remap> {
+    REMAP.get()
+}
+
+impl Remap {
+    pub fn new(rules: &[RemapRule]) -> anyhow::Result<Self> {
+        let rules = rules.iter().map(Rule::new).collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { rules, subscribed: DashMap::new() })
+    }
+
+    /// The topic on the broker of a topic of the bridge, if a rule matches it.
+    pub fn outbound(&self, topic: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| rule.apply(topic))
+    }
+
+    /// The topic to subscribe to on the broker, if a rule matches it, remembered to rewrite the messages
+    /// received on it.
+    pub fn subscribe(&self, topic: &str) -> Option<String> {
+        let broker_topic = self.outbound(topic)?;
+        self.subscribed.insert(broker_topic.clone(), topic.to_string());
+        Some(broker_topic)
+    }
+
+    /// The topic of the bridge of a topic received from the broker, if it was remapped.
+    pub fn inbound(&self, topic: &str) -> Option<String> {
+        self.subscribed.get(topic).map(|entry| entry.value().clone())
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt::{Display, Formatter};
+use std::process::ExitCode;
+
+/// Why the bridge stopped, telling the orchestrator running it whether restarting it can help.
+#[derive(Debug)]
+pub enum StartupError {
+    /// The config file or the env vars are invalid, the bridge fails the same way until they're fixed.
+    Config(anyhow::Error),
+    /// The pairings and the HomeKit config couldn't be read or written.
+    Storage(anyhow::Error),
+    /// The broker couldn't be reached or the HomeKit server stopped, e.g. as its port was taken. It may be back on
+    /// a restart.
+    Unavailable(anyhow::Error),
+    /// Anything else, e.g. the HomeKit server panicked.
+    Other(anyhow::Error),
+}
+
+impl StartupError {
+    /// Whether restarting the bridge without changing anything may get it running.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Unavailable(_))
+    }
+
+    /// The exit codes of sysexits.h, e.g. for `RestartPreventExitStatus=78 74` in a systemd unit.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Self::Config(_) => 78,
+            Self::Storage(_) => 74,
+            Self::Unavailable(_) => 75,
+            Self::Other(_) => 1,
+        })
+    }
+}
+
+impl Display for StartupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(e) => write!(f, "{:#}. Run with --check for a report of every setting.", e),
+            Self::Storage(e) | Self::Unavailable(e) | Self::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+/// Logs why the bridge stopped and returns its exit code, printing to stderr if the logs aren't set up yet.
+pub fn exit(error: StartupError) -> ExitCode {
+    if tracing::dispatcher::has_been_set() {
+        tracing::error!(retryable = error.is_retryable(), "{}", error);
+    } else {
+        eprintln!("{}", error);
+    }
+    error.exit_code()
+}
+
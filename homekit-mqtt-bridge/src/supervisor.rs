@@ -0,0 +1,60 @@
+use std::any::Any;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// How long to wait before restarting a task that panicked, doubled after every panic in a row.
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long a task has to run before a panic is no longer counted as one in a row.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Aborts the task when dropped, so aborting the supervisor stops the task it runs.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message,
+        _ => "unknown cause",
+    }
+}
+
+/// Runs the task started by `start`, starting it again with backoff whenever it panics, so a bug hit by one
+/// message doesn't leave the process running without it. A task that returns is done, e.g. its channel was
+/// closed, and isn't restarted. Aborting the handle stops the task.
+pub fn supervise<F, Fut>(name: &'static str, mut start: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let mut task = AbortOnDrop(tokio::spawn(start()));
+
+            let panic = match (&mut task.0).await {
+                Err(e) if e.is_panic() => e.into_panic(),
+                _ => return,
+            };
+
+            if started.elapsed() >= STABLE_AFTER {
+                backoff = MIN_BACKOFF;
+            }
+            error!("The {} task panicked: {}. Restarting it in {:?}...", name, panic_message(panic.as_ref()), backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    })
+}
+
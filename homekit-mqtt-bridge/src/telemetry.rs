@@ -0,0 +1,93 @@
+#[cfg(feature = "otel")]
+use std::collections::HashMap;
+#[cfg(not(feature = "otel"))]
+use std::convert::Infallible;
+
+#[cfg(feature = "otel")]
+use opentelemetry::global;
+#[cfg(feature = "otel")]
+use opentelemetry::propagation::Injector;
+#[cfg(feature = "otel")]
+use opentelemetry::trace::TraceError;
+#[cfg(feature = "otel")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "otel")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::trace::{self, Tracer};
+#[cfg(feature = "otel")]
+use opentelemetry_sdk::{runtime, Resource};
+use mqtt_client::Message;
+use tracing::Span;
+#[cfg(feature = "otel")]
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+#[cfg(not(feature = "otel"))]
+use tracing_subscriber::layer::Identity;
+#[cfg(feature = "otel")]
+use tracing_subscriber::registry::LookupSpan;
+
+#[cfg(feature = "otel")]
+const SERVICE_NAME: &str = "homekit-mqtt-bridge";
+
+/// Exports the spans to the OTLP collector at env `OTEL_EXPORTER_OTLP_ENDPOINT`, if it's set.
+#[cfg(feature = "otel")]
+pub fn layer<S>() -> Result<Option<OpenTelemetryLayer<S, Tracer>>, TraceError>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span> {
+    let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") else {
+        return Ok(None);
+    };
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", SERVICE_NAME)])))
+        .install_batch(runtime::Tokio)?;
+
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+#[cfg(feature = "otel")]
+struct PropertiesInjector<'a>(&'a mut Vec<(String, String)>);
+
+#[cfg(feature = "otel")]
+impl Injector for PropertiesInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.push((key.to_string(), value));
+    }
+}
+
+/// Mqtt v5 user properties carrying the trace context of the current span, so the receiver can continue the trace.
+#[cfg(feature = "otel")]
+pub fn trace_properties() -> Vec<(String, String)> {
+    let mut properties = Vec::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut PropertiesInjector(&mut properties)));
+    properties
+}
+
+/// Continues the trace of the sender of the message in `span`.
+#[cfg(feature = "otel")]
+pub fn set_parent(span: &Span, message: &Message) {
+    let properties: HashMap<String, String> = message.user_properties().iter().cloned().collect();
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&properties));
+    span.set_parent(context);
+}
+
+/// Built without the otel feature, the spans aren't exported even if env `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+#[cfg(not(feature = "otel"))]
+pub fn layer() -> Result<Option<Identity>, Infallible> {
+    Ok(None)
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn trace_properties() -> Vec<(String, String)> {
+    Vec::new()
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn set_parent(_span: &Span, _message: &Message) {}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::callsite::Identifier;
+use tracing::field::{Field, Visit};
+use tracing::{warn, Event, Level, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+
+/// How long repeats of a warning or error are dropped after it's logged, unless changed by the config.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// How often the dropped repeats are reported.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+const TARGET: &str = "log_throttle";
+
+struct Repeats {
+    since: Instant,
+    count: usize,
+}
+
+/// Drops the warnings and errors that repeat one logged less than a window ago, so a flapping device
+/// doesn't flood the log. The repeats are counted and reported by [`Throttle::flush`].
+#[derive(Clone)]
+pub struct Throttle {
+    messages: Arc<Mutex<HashMap<(Identifier, String), Repeats>>>,
+    /// In milliseconds, so it can be changed when the config is reloaded.
+    window: Arc<AtomicU64>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self { messages: Arc::default(), window: Arc::new(AtomicU64::new(DEFAULT_WINDOW.as_millis() as u64)) }
+    }
+}
+
+impl Throttle {
+    pub fn set_window(&self, window: Duration) {
+        self.window.store(window.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_millis(self.window.load(Ordering::Relaxed))
+    }
+
+    /// Logs how many times the messages whose window ended were repeated.
+    pub fn flush(&self, now: Instant) {
+        let mut repeated = vec![];
+        let window = self.window();
+
+        self.messages.lock().unwrap().retain(|(_, message), repeats| {
+            if now.duration_since(repeats.since) < window {
+                return true;
+            }
+            if repeats.count > 0 {
+                repeated.push((message.clone(), repeats.count));
+            }
+            false
+        });
+
+        for (message, count) in repeated {
+            warn!(target: TARGET, "{} (message repeated {} times)", message, count);
+        }
+    }
+
+    /// Reports the dropped repeats every [`FLUSH_INTERVAL`].
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.flush(Instant::now());
+        }
+    }
+}
+
+/// Writes the message and the other fields of an event, e.g. `Request failed device="desk"`.
+struct FieldsVisitor(String);
+
+impl Visit for FieldsVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S> Filter<S> for Throttle {
+    fn enabled(&self, _metadata: &Metadata<'_>, _context: &Context<'_, S>) -> bool {
+        true
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, _context: &Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::WARN || metadata.target() == TARGET {
+            return true;
+        }
+
+        let mut visitor = FieldsVisitor(String::new());
+        event.record(&mut visitor);
+
+        let window = self.window();
+        let mut messages = self.messages.lock().unwrap();
+        match messages.get_mut(&(metadata.callsite(), visitor.0.clone())) {
+            Some(repeats) if repeats.since.elapsed() < window => {
+                repeats.count += 1;
+                false
+            }
+            _ => {
+                messages.insert((metadata.callsite(), visitor.0), Repeats { since: Instant::now(), count: 0 });
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{debug, info};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::metrics::{Collector, Metrics};
+use crate::mqtt::connect_mqtt;
+
+mod metrics;
+mod mqtt;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/host";
+
+fn publish_metrics(client: &AsyncClient, name: &str, metrics: &Metrics) {
+    let publish = |metric: &str, value: String| {
+        let message = Message::new_retained(format!("{}/{}/{}", MQTT_TOPIC_PREFIX, name, metric), value, 1);
+        client.publish(message);
+    };
+
+    if let Some(temperature) = metrics.cpu_temperature {
+        publish("cpu_temperature", format!("{:.1}", temperature));
+    }
+
+    publish("load", format!("{:.2},{:.2},{:.2}", metrics.load[0], metrics.load[1], metrics.load[2]));
+    publish("memory_usage", format!("{:.1}", metrics.memory_usage));
+
+    if let Some(disk_usage) = metrics.disk_usage {
+        publish("disk_usage", format!("{:.1}", disk_usage));
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let name = std::env::var("HOST_METRICS_NAME").unwrap_or_else(|_| "server".into());
+
+    let interval = match std::env::var("HOST_METRICS_INTERVAL_SECS") {
+        Ok(interval) => interval.parse().context("HOST_METRICS_INTERVAL_SECS must be a number of seconds")?,
+        Err(_) => 30,
+    };
+
+    let disk_mount_point = std::env::var("HOST_METRICS_DISK").unwrap_or_else(|_| "/".into());
+
+    let (client, _stream) = connect_mqtt(
+        &[],
+        mqtt_server_uri,
+        format!("host-metrics-controller-{}", name),
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting host metrics controller for {} with interval {}s", name, interval);
+
+    let mut collector = Collector::new(disk_mount_point.into());
+    let mut interval = tokio::time::interval(Duration::from_secs(interval));
+
+    loop {
+        interval.tick().await;
+
+        let metrics = collector.collect();
+        debug!("Collected host metrics: {:?}", metrics);
+
+        publish_metrics(&client, &name, &metrics);
+    }
+}
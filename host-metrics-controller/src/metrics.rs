@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use sysinfo::{ComponentExt, DiskExt, System, SystemExt};
+
+/// Sensor labels that identify the CPU package temperature on common platforms
+/// (Intel coretemp, AMD k10temp, Raspberry Pi thermal zone).
+const CPU_SENSOR_LABELS: [&str; 4] = ["package id 0", "tctl", "cpu_thermal", "cpu"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    pub cpu_temperature: Option<f32>,
+    pub load: [f64; 3],
+    pub memory_usage: f64,
+    pub disk_usage: Option<f64>,
+}
+
+pub struct Collector {
+    system: System,
+    disk_mount_point: PathBuf,
+}
+
+impl Collector {
+    pub fn new(disk_mount_point: PathBuf) -> Self {
+        let mut system = System::new();
+        system.refresh_components_list();
+        system.refresh_disks_list();
+
+        Self { system, disk_mount_point }
+    }
+
+    pub fn collect(&mut self) -> Metrics {
+        self.system.refresh_memory();
+        self.system.refresh_components();
+        self.system.refresh_disks();
+
+        let load = self.system.load_average();
+
+        let readings = self.system.components().iter()
+            .map(|component| (component.label(), component.temperature()));
+
+        let disk_usage = self.system.disks().iter()
+            .find(|disk| disk.mount_point() == self.disk_mount_point)
+            .map(|disk| percent(disk.total_space() - disk.available_space(), disk.total_space()));
+
+        Metrics {
+            cpu_temperature: pick_cpu_temperature(readings),
+            load: [load.one, load.five, load.fifteen],
+            memory_usage: percent(self.system.total_memory() - self.system.available_memory(), self.system.total_memory()),
+            disk_usage,
+        }
+    }
+}
+
+/// Picks the reading of the most specific known CPU sensor, falling back to the hottest sensor.
+fn pick_cpu_temperature<'a>(readings: impl Iterator<Item=(&'a str, f32)>) -> Option<f32> {
+    let readings: Vec<(String, f32)> = readings
+        .filter(|(_, temperature)| temperature.is_finite())
+        .map(|(label, temperature)| (label.to_ascii_lowercase(), temperature))
+        .collect();
+
+    CPU_SENSOR_LABELS.iter()
+        .find_map(|wanted| readings.iter().find(|(label, _)| label.contains(wanted)))
+        .or_else(|| readings.iter().max_by(|a, b| a.1.total_cmp(&b.1)))
+        .map(|(_, temperature)| *temperature)
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    (used as f64 / total as f64 * 1000.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{percent, pick_cpu_temperature};
+
+    #[test]
+    fn test_pick_cpu_temperature() {
+        let readings = vec![("acpitz temp1", 30.0), ("coretemp Package id 0", 48.5), ("coretemp Core 0", 47.0)];
+        assert_eq!(pick_cpu_temperature(readings.into_iter()), Some(48.5));
+
+        let readings = vec![("nvme Composite", 38.0), ("cpu_thermal temp1", 52.1)];
+        assert_eq!(pick_cpu_temperature(readings.into_iter()), Some(52.1));
+
+        let readings = vec![("nvme Composite", 38.0), ("acpitz temp1", 41.0), ("broken", f32::NAN)];
+        assert_eq!(pick_cpu_temperature(readings.into_iter()), Some(41.0));
+
+        assert_eq!(pick_cpu_temperature(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(1, 3), 33.3);
+        assert_eq!(percent(0, 0), 0.0);
+        assert_eq!(percent(10, 10), 100.0);
+    }
+}
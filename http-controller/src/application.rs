@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use domain_state::error;
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::config::{Config, DeviceConfig};
+use crate::{MQTT_ERROR_TOPIC, MQTT_TOPIC_PREFIX};
+use crate::template::render;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct ManagedDevice {
+    config: DeviceConfig,
+    last_values: HashMap<String, String>,
+}
+
+pub struct Application {
+    client: AsyncClient,
+    http: reqwest::Client,
+    devices: HashMap<String, ManagedDevice>,
+}
+
+/// A `smart-home-system/http/<device>/<attribute>/<action>` topic.
+struct DeviceTopic<'a> {
+    device: &'a str,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { device: parts.next()?, attribute: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, config: Config) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        let mut devices = HashMap::new();
+
+        for device in config.devices {
+            for (name, command) in &device.commands {
+                Method::from_bytes(command.method.as_bytes())
+                    .with_context(|| format!("Invalid method '{}' for command {} of device {}", command.method, name, device.name))?;
+            }
+
+            info!("Loaded http device {} with {} attributes and {} commands", device.name,
+                device.state.as_ref().map_or(0, |state| state.attributes.len()), device.commands.len());
+
+            devices.insert(device.name.clone(), ManagedDevice { config: device, last_values: HashMap::new() });
+        }
+
+        Ok(Self { client, http, devices })
+    }
+
+    fn request(&self, device: &DeviceConfig, method: Method, url: &str) -> reqwest::RequestBuilder {
+        device.headers.iter().fold(self.http.request(method, url), |request, (name, value)| request.header(name, value))
+    }
+
+    /// Fetches the state of every device and publishes the values that changed since the last poll.
+    pub async fn poll(&mut self) {
+        let names: Vec<String> = self.devices.keys().cloned().collect();
+
+        for name in names {
+            self.poll_device(&name, false).await;
+        }
+    }
+
+    async fn poll_device(&mut self, name: &str, force: bool) {
+        let Some(managed) = self.devices.get(name) else { return };
+        let Some(state) = &managed.config.state else { return };
+
+        let response = async {
+            self.request(&managed.config, Method::GET, &state.url)
+                .send().await?
+                .error_for_status()?
+                .json::<Value>().await
+        }.await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Failed to fetch state of http device {}: {}", name, e);
+                return;
+            }
+        };
+
+        let mut values = Vec::new();
+
+        for (attribute, config) in &state.attributes {
+            let Some(value) = response.pointer(&config.pointer) else {
+                warn!("http device {} state has no value at {} for {}", name, config.pointer, attribute);
+                continue;
+            };
+
+            match config.value_type.format_value(value) {
+                Some(payload) => values.push((attribute.clone(), payload)),
+                None => warn!("http device {} {} is not a {:?}: {}", name, attribute, config.value_type, value),
+            }
+        }
+
+        let managed = self.devices.get_mut(name).unwrap();
+
+        for (attribute, payload) in values {
+            if !force && managed.last_values.get(&attribute) == Some(&payload) {
+                continue;
+            }
+
+            info!("http device {} {} is: {}", name, attribute, payload);
+
+            let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, name, attribute);
+            self.client.publish(Message::new_retained(topic, payload.clone(), 1));
+            managed.last_values.insert(attribute, payload);
+        }
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        if !self.devices.contains_key(topic.device) {
+            warn!("[{}] Unknown http device {}", message.topic(), topic.device);
+            return;
+        }
+
+        match topic.action {
+            "get" => self.poll_device(topic.device, true).await,
+            "set" => self.handle_set(&topic, message).await,
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    async fn handle_set(&mut self, topic: &DeviceTopic<'_>, message: &Message) {
+        let managed = &self.devices[topic.device];
+
+        let Some(command) = managed.config.commands.get(topic.attribute) else {
+            error!("[{}] Unknown command '{}' for http device {}", message.topic(), topic.attribute, topic.device);
+            return;
+        };
+
+        let payload = message.payload_str();
+        let value = match command.value_type.parse_payload(&payload) {
+            Ok(value) => value,
+            Err(e) => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string());
+                return;
+            }
+        };
+
+        info!("[{}] Setting http device {} {} to: {}", message.topic(), topic.device, topic.attribute, value);
+
+        // Validated when the config was loaded
+        let method = Method::from_bytes(command.method.as_bytes()).unwrap();
+        let mut request = self.request(&managed.config, method, &render(&command.url, &value, true));
+
+        if let Some(body) = &command.body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, &command.content_type)
+                .body(render(body, &value, false));
+        }
+
+        let result = async { request.send().await?.error_for_status() }.await;
+
+        if let Err(e) = result {
+            error!("Failed to set {} on http device {}: {}", topic.attribute, topic.device, e);
+            return;
+        }
+
+        self.poll_device(topic.device, false).await;
+    }
+}
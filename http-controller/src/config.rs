@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u64,
+    pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeviceConfig {
+    /// Used as the topic segment, e.g. `smart-home-system/http/<name>/power`.
+    pub name: String,
+    /// Sent with every request of the device, e.g. an `Authorization` header.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub state: Option<StateConfig>,
+    #[serde(default)]
+    pub commands: HashMap<String, CommandConfig>,
+}
+
+/// The request polled for the device state and where each attribute is found in its json response.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StateConfig {
+    pub url: String,
+    pub attributes: HashMap<String, AttributeConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AttributeConfig {
+    /// A JSON pointer (RFC 6901) into the state response, e.g. `/switch/0/output`.
+    pub pointer: String,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+}
+
+/// A request sent when a payload is published to `<attribute>/set`. `{{value}}` in the url and body
+/// is replaced by the payload, formatted for `type`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct CommandConfig {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub url: String,
+    pub body: Option<String>,
+    #[serde(default = "default_content_type")]
+    pub content_type: String,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    String,
+}
+
+fn default_poll_interval() -> u64 {
+    30
+}
+
+fn default_method() -> String {
+    "POST".into()
+}
+
+fn default_content_type() -> String {
+    "application/json".into()
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))
+    }
+}
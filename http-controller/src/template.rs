@@ -0,0 +1,107 @@
+use serde_json::Value;
+
+use crate::config::ValueType;
+
+const VALUE_PLACEHOLDER: &str = "{{value}}";
+
+impl ValueType {
+    /// Parses an mqtt payload into the value substituted into command templates.
+    pub fn parse_payload(self, payload: &str) -> Result<String, String> {
+        let payload = payload.trim();
+
+        match self {
+            ValueType::Bool => match payload.to_ascii_lowercase().as_str() {
+                "on" | "true" | "1" => Ok("true".into()),
+                "off" | "false" | "0" => Ok("false".into()),
+                _ => Err(format!("Invalid bool value: {}", payload)),
+            },
+            ValueType::Int => payload.parse::<i64>()
+                .map(|value| value.to_string())
+                .map_err(|_| format!("Invalid int value: {}", payload)),
+            ValueType::Float => payload.parse::<f64>()
+                .map(|value| value.to_string())
+                .map_err(|_| format!("Invalid float value: {}", payload)),
+            ValueType::String => Ok(payload.to_string()),
+        }
+    }
+
+    /// Formats a value extracted from a state response as an mqtt payload.
+    pub fn format_value(self, value: &Value) -> Option<String> {
+        match self {
+            ValueType::Bool => match value {
+                Value::Bool(value) => Some(if *value { "on" } else { "off" }.into()),
+                Value::Number(number) => Some(if number.as_f64()? != 0.0 { "on" } else { "off" }.into()),
+                Value::String(value) => self.parse_payload(value).ok()
+                    .map(|value| if value == "true" { "on" } else { "off" }.into()),
+                _ => None,
+            },
+            ValueType::Int => match value {
+                Value::Number(number) => number.as_i64().or_else(|| number.as_f64().map(|value| value.round() as i64)),
+                Value::String(value) => value.trim().parse().ok(),
+                _ => None,
+            }.map(|value| value.to_string()),
+            ValueType::Float => match value {
+                Value::Number(number) => number.as_f64(),
+                Value::String(value) => value.trim().parse().ok(),
+                _ => None,
+            }.map(|value| value.to_string()),
+            ValueType::String => match value {
+                Value::String(value) => Some(value.clone()),
+                Value::Null => None,
+                value => Some(value.to_string()),
+            },
+        }
+    }
+}
+
+/// Replaces `{{value}}` in a url or body template. Values placed in a url are percent-encoded.
+pub fn render(template: &str, value: &str, url: bool) -> String {
+    if url {
+        template.replace(VALUE_PLACEHOLDER, &encode_url_component(value))
+    } else {
+        template.replace(VALUE_PLACEHOLDER, value)
+    }
+}
+
+fn encode_url_component(value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::config::ValueType;
+    use crate::template::render;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("http://plug/rpc/Switch.Set?id=0&on={{value}}", "true", true),
+                   "http://plug/rpc/Switch.Set?id=0&on=true");
+        assert_eq!(render("http://tv/input?name={{value}}", "HDMI 1", true), "http://tv/input?name=HDMI%201");
+        assert_eq!(render(r#"{"on": {{value}}}"#, "false", false), r#"{"on": false}"#);
+    }
+
+    #[test]
+    fn test_extract_and_format() {
+        let state = json!({ "output": true, "apower": 12.5, "temperature": { "tC": "41.2" }, "mode": "eco" });
+
+        assert_eq!(ValueType::Bool.format_value(state.pointer("/output").unwrap()).as_deref(), Some("on"));
+        assert_eq!(ValueType::Int.format_value(state.pointer("/apower").unwrap()).as_deref(), Some("13"));
+        assert_eq!(ValueType::Float.format_value(state.pointer("/temperature/tC").unwrap()).as_deref(), Some("41.2"));
+        assert_eq!(ValueType::String.format_value(state.pointer("/mode").unwrap()).as_deref(), Some("eco"));
+        assert_eq!(ValueType::Bool.format_value(&json!([1])), None);
+    }
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!(ValueType::Bool.parse_payload("on"), Ok("true".into()));
+        assert_eq!(ValueType::Int.parse_payload(" 42 "), Ok("42".into()));
+        assert!(ValueType::Float.parse_payload("warm").is_err());
+    }
+}
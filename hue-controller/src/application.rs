@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use domain_state::error;
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+use serde::Serialize;
+
+use crate::{converters, discovery, MQTT_DEVICES_PUBLISH_TOPIC, MQTT_ERROR_TOPIC, MQTT_PAIR_RESULT_TOPIC, MQTT_TOPIC_PREFIX};
+use crate::hue::{Bridge, Sensor};
+
+pub struct Application {
+    client: AsyncClient,
+    bridge: Bridge,
+    token_path: PathBuf,
+    /// Light ids by topic name.
+    lights: HashMap<String, String>,
+    listing: Vec<DeviceListing>,
+    /// Last published payload by topic.
+    last_values: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct DeviceFilters {
+    pub id: Option<String>,
+}
+
+impl DeviceFilters {
+    fn matches(&self, bridge: &discovery::DiscoveryResponse) -> bool {
+        self.id.iter().all(|id| bridge.id == id.to_lowercase())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct DeviceListing {
+    name: String,
+    kind: &'static str,
+}
+
+/// A `smart-home-system/hue/<device>/<attribute>/<action>` topic.
+struct DeviceTopic<'a> {
+    device: &'a str,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { device: parts.next()?, attribute: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+/// Sorts numeric hue ids in the order the bridge assigned them.
+fn sorted_ids<T>(items: &HashMap<String, T>) -> Vec<&String> {
+    let mut ids: Vec<&String> = items.keys().collect();
+    ids.sort_by_key(|id| (id.parse::<u32>().unwrap_or(u32::MAX), id.to_string()));
+    ids
+}
+
+impl Application {
+    pub async fn new(client: AsyncClient, filter: DeviceFilters, token_path: PathBuf) -> Self {
+        let username = std::fs::read_to_string(&token_path).ok()
+            .map(|username| username.trim().to_string())
+            .filter(|username| !username.is_empty());
+
+        if username.is_none() {
+            warn!("No hue username found at {:?}. Press the link button on the bridge and publish to the pair topic.", token_path);
+        }
+
+        let bridge = Self::find_bridge(filter, username).await;
+
+        Self { client, bridge, token_path, lights: HashMap::new(), listing: Vec::new(), last_values: HashMap::new() }
+    }
+
+    pub async fn find_bridge(filter: DeviceFilters, username: Option<String>) -> Bridge {
+        loop {
+            let result = discovery::discover(Duration::from_secs(3)).await;
+            match result {
+                Ok(discovery) => {
+                    let bridge = discovery.into_iter().find(|bridge| filter.matches(bridge));
+
+                    if let Some(bridge) = bridge {
+                        info!("Using hue bridge {} at {}...", bridge.id, bridge.address);
+                        return Bridge::new(bridge.address, username).unwrap();
+                    } else {
+                        warn!("No hue bridge found matching filter {filter:?}. Retrying in 30 seconds...");
+                    }
+                }
+                Err(e) => warn!("Hue discovery failed: {}. Retrying in 30 seconds...", e)
+            }
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+
+    pub async fn handle_mqtt_pair(&mut self, message: &Message) {
+        info!("[{}] Pairing with hue bridge", message.topic());
+
+        let result = match self.bridge.pair().await {
+            Ok(username) => match std::fs::write(&self.token_path, username) {
+                Ok(()) => {
+                    info!("Paired with hue bridge. Username stored at {:?}", self.token_path);
+                    "paired".to_string()
+                }
+                Err(e) => {
+                    error!("Paired with hue bridge but could not store the username: {}", e);
+                    format!("error: {}", e)
+                }
+            },
+            Err(e) => {
+                error!("Failed to pair with hue bridge: {}", e);
+                format!("error: {}", e)
+            }
+        };
+
+        self.client.publish(Message::new(MQTT_PAIR_RESULT_TOPIC, result, 1));
+        self.poll().await;
+    }
+
+    /// Reads the lights and sensors of the bridge and publishes every value that changed since the last poll.
+    pub async fn poll(&mut self) {
+        if !self.bridge.is_paired() {
+            return;
+        }
+
+        let (lights, sensors) = match tokio::try_join!(self.bridge.lights(), self.bridge.sensors()) {
+            Ok(result) => result,
+            Err(e) => {
+                warn!("Failed to read hue bridge state: {}", e);
+                return;
+            }
+        };
+
+        let mut listing = Vec::new();
+        self.lights.clear();
+
+        for id in sorted_ids(&lights) {
+            let light = &lights[id];
+            let name = converters::topic_name(&light.name);
+
+            for (attribute, payload) in converters::from_light(light) {
+                self.publish(&name, attribute, payload);
+            }
+
+            self.lights.insert(name.clone(), id.clone());
+            listing.push(DeviceListing { name, kind: "light" });
+        }
+
+        for (name, sensors) in group_sensors(&sensors) {
+            for (attribute, payload) in sensors.into_iter().flat_map(converters::from_sensor) {
+                self.publish(&name, attribute, payload);
+            }
+
+            listing.push(DeviceListing { name, kind: "sensor" });
+        }
+
+        if listing != self.listing {
+            self.publish_device_listing(&listing);
+            self.listing = listing;
+        }
+    }
+
+    fn publish_device_listing(&self, listing: &[DeviceListing]) {
+        info!("Found {} hue devices", listing.len());
+
+        match serde_json::to_string(listing) {
+            Ok(listing) => {
+                self.client.publish(Message::new_retained(MQTT_DEVICES_PUBLISH_TOPIC, listing, 1));
+            }
+            Err(e) => error!("Failed to serialize device listing: {}", e),
+        }
+    }
+
+    fn publish(&mut self, device: &str, attribute: &str, payload: String) {
+        let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, device, attribute);
+
+        if self.last_values.get(&topic) == Some(&payload) {
+            return;
+        }
+
+        info!("Hue device {} {} is: {}", device, attribute, payload);
+
+        self.client.publish(Message::new_retained(topic.clone(), payload.clone(), 1));
+        self.last_values.insert(topic, payload);
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        if !self.listing.iter().any(|device| device.name == topic.device) {
+            warn!("[{}] Unknown hue device {}", message.topic(), topic.device);
+            return;
+        }
+
+        match topic.action {
+            "get" => {
+                let prefix = format!("{}/{}/", MQTT_TOPIC_PREFIX, topic.device);
+                self.last_values.retain(|topic, _| !topic.starts_with(&prefix));
+                self.poll().await;
+            }
+            "set" => self.handle_set(&topic, message).await,
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    async fn handle_set(&mut self, topic: &DeviceTopic<'_>, message: &Message) {
+        let Some(id) = self.lights.get(topic.device) else {
+            error!("[{}] Hue device {} is not a light", message.topic(), topic.device);
+            return;
+        };
+
+        let payload = message.payload_str();
+        let update = match converters::to_light(topic.attribute, &payload) {
+            Ok(update) => update,
+            Err(e) => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string());
+                return;
+            }
+        };
+
+        info!("[{}] Setting hue light {} {} to: {}", message.topic(), topic.device, topic.attribute, payload);
+
+        if let Err(e) = self.bridge.set_light_state(id, &update).await {
+            error!("Failed to update hue light {}: {}", topic.device, e);
+            return;
+        }
+
+        self.poll().await;
+    }
+}
+
+/// Groups the sensors of each physical device, e.g. the presence, temperature and light level
+/// sensors of a motion sensor, under the name of its main sensor. Virtual sensors are skipped.
+fn group_sensors(sensors: &HashMap<String, Sensor>) -> Vec<(String, Vec<&Sensor>)> {
+    let mut groups: Vec<(&str, Vec<&Sensor>)> = Vec::new();
+
+    for id in sorted_ids(sensors) {
+        let sensor = &sensors[id];
+        let Some(device_id) = sensor.device_id() else { continue };
+
+        match groups.iter_mut().find(|(group_id, _)| *group_id == device_id) {
+            Some((_, group)) => group.push(sensor),
+            None => groups.push((device_id, vec![sensor])),
+        }
+    }
+
+    groups.into_iter()
+        .map(|(_, group)| {
+            let main = group.iter().find(|sensor| sensor.sensor_type.ends_with("Presence")).unwrap_or(&group[0]);
+            (converters::topic_name(&main.name), group)
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use domain_state::Power;
+
+use crate::hue::{Light, LightUpdate, Sensor};
+
+/// The color temperature range of hue lights, in mireds (6500K to 2000K).
+const MIN_MIREDS: u16 = 153;
+const MAX_MIREDS: u16 = 500;
+
+/// Turns a hue name into a topic segment, e.g. `Living Room` into `living-room`.
+pub fn topic_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Converts the state of a light into `(attribute, payload)` pairs published under the light topic.
+pub fn from_light(light: &Light) -> Vec<(&'static str, String)> {
+    let state = &light.state;
+    let mut values = vec![("power", on_off(state.on)), ("reachable", on_off(state.reachable))];
+
+    if let Some(bri) = state.bri {
+        values.push(("brightness", ((bri as f64 / 254.0 * 100.0).round() as u8).to_string()));
+    }
+
+    if let Some(ct) = state.ct.filter(|ct| *ct > 0) {
+        values.push(("color_temperature", (1_000_000 / ct as u32).to_string()));
+    }
+
+    values
+}
+
+/// Converts the state of a sensor into `(attribute, payload)` pairs. The sensors of one physical
+/// device are published under the same topic, so their attributes don't overlap.
+pub fn from_sensor(sensor: &Sensor) -> Vec<(&'static str, String)> {
+    let state = &sensor.state;
+
+    let mut values = match sensor.sensor_type.as_str() {
+        "ZLLPresence" | "ZHAPresence" => state.presence.map(|presence| ("occupancy", on_off(presence))).into_iter().collect(),
+        "ZLLTemperature" | "ZHATemperature" => state.temperature
+            .map(|temperature| ("temperature", format!("{:.1}", temperature as f64 / 100.0)))
+            .into_iter().collect(),
+        "ZLLLightLevel" | "ZHALightLevel" => state.lightlevel
+            .map(|level| ("illuminance", (10f64.powf((level as f64 - 1.0) / 10000.0).round() as u32).to_string()))
+            .into_iter().collect(),
+        _ => vec![],
+    };
+
+    if let Some(battery) = sensor.config.battery {
+        values.push(("battery", battery.to_string()));
+    }
+
+    values
+}
+
+fn on_off(value: bool) -> String {
+    Power::from(value).to_string()
+}
+
+/// Converts a payload published to `<light>/<attribute>/set` into the state update for the light.
+pub fn to_light(attribute: &str, payload: &str) -> Result<LightUpdate, String> {
+    let payload = payload.trim();
+
+    match attribute {
+        "power" => {
+            let power = Power::from_str(payload).map_err(|e| e.to_string())?;
+            Ok(LightUpdate { on: Some(power.is_on()), ..Default::default() })
+        }
+        "brightness" => {
+            let brightness = payload.parse::<u8>().ok().filter(|brightness| *brightness <= 100)
+                .ok_or_else(|| format!("Invalid brightness value: {}", payload))?;
+
+            if brightness == 0 {
+                return Ok(LightUpdate { on: Some(false), ..Default::default() });
+            }
+
+            let bri = ((brightness as f64 / 100.0 * 254.0).round() as u8).max(1);
+            Ok(LightUpdate { on: Some(true), bri: Some(bri), ..Default::default() })
+        }
+        "color_temperature" => {
+            let kelvin = payload.parse::<u32>().ok().filter(|kelvin| *kelvin > 0)
+                .ok_or_else(|| format!("Invalid color temperature value: {}", payload))?;
+
+            let mireds = (1_000_000 / kelvin).clamp(MIN_MIREDS as u32, MAX_MIREDS as u32) as u16;
+            Ok(LightUpdate { ct: Some(mireds), ..Default::default() })
+        }
+        _ => Err(format!("Attribute {} can't be set", attribute)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::converters::{from_light, from_sensor, to_light, topic_name};
+    use crate::hue::{Light, LightState, LightUpdate, Sensor, SensorConfig, SensorState};
+
+    #[test]
+    fn test_topic_name() {
+        assert_eq!(topic_name("Living Room"), "living-room");
+        assert_eq!(topic_name("Hue motion sensor 1"), "hue-motion-sensor-1");
+        assert_eq!(topic_name("  Desk / Lamp "), "desk-lamp");
+    }
+
+    #[test]
+    fn test_from_light_and_sensor() {
+        let light = Light {
+            name: "Desk".into(),
+            state: LightState { on: true, bri: Some(127), ct: Some(370), reachable: true },
+        };
+
+        assert_eq!(from_light(&light), [
+            ("power", "on".to_string()),
+            ("reachable", "on".to_string()),
+            ("brightness", "50".to_string()),
+            ("color_temperature", "2702".to_string()),
+        ]);
+
+        let sensor = Sensor {
+            name: "Hue ambient light sensor 1".into(),
+            sensor_type: "ZLLLightLevel".into(),
+            uniqueid: None,
+            state: SensorState { lightlevel: Some(20001), ..Default::default() },
+            config: SensorConfig { battery: Some(80) },
+        };
+
+        assert_eq!(from_sensor(&sensor), [("illuminance", "100".to_string()), ("battery", "80".to_string())]);
+    }
+
+    #[test]
+    fn test_to_light() {
+        assert_eq!(to_light("power", "on"), Ok(LightUpdate { on: Some(true), ..Default::default() }));
+        assert_eq!(to_light("power", "0"), Ok(LightUpdate { on: Some(false), ..Default::default() }));
+        assert!(to_light("power", "dim").is_err());
+        assert_eq!(to_light("brightness", "100"), Ok(LightUpdate { on: Some(true), bri: Some(254), ..Default::default() }));
+        assert_eq!(to_light("brightness", "0"), Ok(LightUpdate { on: Some(false), ..Default::default() }));
+        assert_eq!(to_light("color_temperature", "10000"), Ok(LightUpdate { ct: Some(153), ..Default::default() }));
+        assert!(to_light("brightness", "101").is_err());
+        assert!(to_light("reachable", "on").is_err());
+    }
+}
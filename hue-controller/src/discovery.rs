@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+const SERVICE_TYPE: &str = "_hue._tcp.local.";
+
+#[derive(Debug, PartialEq)]
+pub struct DiscoveryResponse {
+    pub id: String,
+    pub address: String,
+}
+
+fn parse(info: &ServiceInfo) -> anyhow::Result<DiscoveryResponse> {
+    let ip = info.get_addresses_v4().into_iter().next()
+        .context("No ipv4 address found in response")?;
+
+    Ok(DiscoveryResponse {
+        id: info.get_property_val_str("bridgeid").context("No bridge id found in response")?.to_lowercase(),
+        address: format!("{}:{}", ip, info.get_port()),
+    })
+}
+
+pub async fn discover(timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse>> {
+    let daemon = ServiceDaemon::new().context("Failed to start mdns daemon")?;
+    let receiver = daemon.browse(SERVICE_TYPE).context("Failed to browse for hue bridges")?;
+
+    info!("Discovering {SERVICE_TYPE} with timeout {timeout:?}");
+
+    let mut responses = Vec::new();
+
+    let discover = async {
+        while let Ok(event) = receiver.recv_async().await {
+            if let ServiceEvent::ServiceResolved(info) = event {
+                match parse(&info) {
+                    Ok(discovery) if !responses.contains(&discovery) => {
+                        info!("Found hue bridge: {:?}", discovery);
+                        responses.push(discovery);
+                    }
+                    Ok(_) => {}
+                    Err(err) => error!("Failed to parse discovery response from {}: {}", info.get_fullname(), err),
+                }
+            }
+        }
+    };
+
+    let _ = tokio::time::timeout(timeout, discover).await;
+    let _ = daemon.shutdown();
+
+    Ok(responses)
+}
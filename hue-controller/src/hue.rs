@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+const DEVICE_TYPE: &str = "smart-home-system#hue-controller";
+
+/// Error type the bridge answers with while its link button hasn't been pressed.
+const ERROR_LINK_BUTTON_NOT_PRESSED: u16 = 101;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LightState {
+    pub on: bool,
+    /// 1-254
+    pub bri: Option<u8>,
+    /// Color temperature in mireds.
+    pub ct: Option<u16>,
+    #[serde(default)]
+    pub reachable: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Light {
+    pub name: String,
+    pub state: LightState,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SensorState {
+    pub presence: Option<bool>,
+    /// Hundredths of a degree Celsius.
+    pub temperature: Option<i32>,
+    /// `10000 * log10(lux) + 1`
+    pub lightlevel: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SensorConfig {
+    pub battery: Option<u8>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Sensor {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub sensor_type: String,
+    /// `<mac>-<endpoint>-<cluster>`. The sensors of one physical device share the mac.
+    pub uniqueid: Option<String>,
+    #[serde(default)]
+    pub state: SensorState,
+    #[serde(default)]
+    pub config: SensorConfig,
+}
+
+impl Sensor {
+    /// Identifies the physical device the sensor belongs to.
+    pub fn device_id(&self) -> Option<&str> {
+        self.uniqueid.as_deref().and_then(|id| id.split('-').next())
+    }
+}
+
+/// Body of a `PUT /api/<username>/lights/<id>/state` request. Only the fields that are set get sent.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct LightUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bri: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ct: Option<u16>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ApiError {
+    #[serde(rename = "type")]
+    error_type: u16,
+    description: String,
+}
+
+/// An element of the array the bridge answers writes with.
+#[derive(Deserialize, Debug)]
+struct ApiResult {
+    success: Option<Value>,
+    error: Option<ApiError>,
+}
+
+/// Fails with the first error of a bridge response, if any. Errors come back with status 200.
+fn check_errors(response: &Value) -> anyhow::Result<()> {
+    let Some(results) = response.as_array() else { return Ok(()) };
+
+    for result in results {
+        if let Ok(ApiResult { error: Some(error), .. }) = ApiResult::deserialize(result) {
+            anyhow::bail!("Hue bridge returned error {}: {}", error.error_type, error.description);
+        }
+    }
+
+    Ok(())
+}
+
+pub struct Bridge {
+    client: reqwest::Client,
+    base_url: String,
+    username: Option<String>,
+}
+
+impl Bridge {
+    pub fn new(address: String, username: Option<String>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .context("Failed to create http client")?;
+
+        Ok(Self { client, base_url: format!("http://{}/api", address), username })
+    }
+
+    pub fn is_paired(&self) -> bool {
+        self.username.is_some()
+    }
+
+    /// Registers a new user. The bridge only accepts it within 30 seconds of its link button being pressed.
+    pub async fn pair(&mut self) -> anyhow::Result<String> {
+        let results: Vec<ApiResult> = self.client.post(&self.base_url)
+            .json(&json!({ "devicetype": DEVICE_TYPE }))
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        let result = results.into_iter().next().context("Empty response from hue bridge")?;
+
+        if let Some(error) = result.error {
+            if error.error_type == ERROR_LINK_BUTTON_NOT_PRESSED {
+                anyhow::bail!("Link button not pressed. Press the button on the hue bridge and try again within 30 seconds.");
+            }
+            anyhow::bail!("Hue bridge returned error {}: {}", error.error_type, error.description);
+        }
+
+        let username = result.success
+            .and_then(|success| success.get("username")?.as_str().map(str::to_string))
+            .context("No username in response from hue bridge")?;
+
+        self.username = Some(username.clone());
+
+        Ok(username)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        let response: Value = self.client.get(format!("{}/{}", self.authenticated_url()?, path))
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        check_errors(&response)?;
+
+        Ok(serde_json::from_value(response)?)
+    }
+
+    pub async fn lights(&self) -> anyhow::Result<HashMap<String, Light>> {
+        self.get("lights").await
+    }
+
+    pub async fn sensors(&self) -> anyhow::Result<HashMap<String, Sensor>> {
+        self.get("sensors").await
+    }
+
+    pub async fn set_light_state(&self, id: &str, update: &LightUpdate) -> anyhow::Result<()> {
+        let response: Value = self.client.put(format!("{}/lights/{}/state", self.authenticated_url()?, id))
+            .json(update)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        check_errors(&response)
+    }
+
+    fn authenticated_url(&self) -> anyhow::Result<String> {
+        let username = self.username.as_ref().context("Hue bridge is not paired yet")?;
+        Ok(format!("{}/{}", self.base_url, username))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::hue::{check_errors, Light, Sensor};
+
+    #[test]
+    fn test_parse_lights_and_sensors() {
+        let lights: HashMap<String, Light> = serde_json::from_value(json!({
+            "1": {
+                "name": "Living Room",
+                "type": "Color temperature light",
+                "state": { "on": true, "bri": 127, "ct": 366, "alert": "none", "reachable": true }
+            }
+        })).unwrap();
+
+        assert_eq!(lights["1"].name, "Living Room");
+        assert_eq!(lights["1"].state.bri, Some(127));
+        assert_eq!(lights["1"].state.ct, Some(366));
+
+        let sensor: Sensor = serde_json::from_value(json!({
+            "name": "Hue temperature sensor 1",
+            "type": "ZLLTemperature",
+            "uniqueid": "00:17:88:01:02:00:af:28-02-0402",
+            "state": { "temperature": 2150, "lastupdated": "2024-01-01T10:00:00" },
+            "config": { "on": true, "battery": 90, "reachable": true }
+        })).unwrap();
+
+        assert_eq!(sensor.device_id(), Some("00:17:88:01:02:00:af:28"));
+        assert_eq!(sensor.state.temperature, Some(2150));
+        assert_eq!(sensor.config.battery, Some(90));
+    }
+
+    #[test]
+    fn test_check_errors() {
+        assert!(check_errors(&json!([{ "success": { "/lights/1/state/on": true } }])).is_ok());
+        assert!(check_errors(&json!([{ "error": { "type": 1, "address": "/", "description": "unauthorized user" } }])).is_err());
+        assert!(check_errors(&json!({ "1": {} })).is_ok());
+    }
+}
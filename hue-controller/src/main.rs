@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::info;
+
+use crate::application::{Application, DeviceFilters};
+use crate::mqtt::connect_mqtt;
+
+mod hue;
+mod converters;
+mod application;
+mod mqtt;
+mod discovery;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/hue";
+const MQTT_SET_TOPIC: &str = "smart-home-system/hue/+/+/set";
+const MQTT_GET_TOPIC: &str = "smart-home-system/hue/+/+/get";
+const MQTT_DEVICES_PUBLISH_TOPIC: &str = "smart-home-system/hue/devices";
+const MQTT_PAIR_TOPIC: &str = "smart-home-system/hue/admin/pair";
+const MQTT_PAIR_RESULT_TOPIC: &str = "smart-home-system/hue/admin/pair/result";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/hue/error";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [MQTT_SET_TOPIC, MQTT_GET_TOPIC, MQTT_PAIR_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting hue controller");
+
+    let token_path = std::env::var("HUE_TOKEN_PATH").unwrap_or_else(|_| "hue-token".into());
+
+    let mut application = Application::new(client, DeviceFilters {
+        id: std::env::var("HUE_BRIDGE_ID").ok(),
+    }, token_path.into()).await;
+
+    info!("Found hue bridge.");
+
+    info!("Waiting for mqtt messages...");
+
+    let mut poll_interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    match message.topic() {
+                        MQTT_PAIR_TOPIC => application.handle_mqtt_pair(&message).await,
+                        _ => application.handle_mqtt_message(&message).await,
+                    }
+                }
+            }
+            _ = poll_interval.tick() => application.poll().await,
+        }
+    }
+
+    Ok(())
+}
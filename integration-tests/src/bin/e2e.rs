@@ -0,0 +1,129 @@
+//! Brings up the stack of `e2e/docker-compose.yml`, a broker, an emulated bulb, the yeelight controller and
+//! the bridge, pairs with the bridge as HomeKit does and checks a change goes all the way to the bulb and back.
+//! It's the release gate, run with `cargo run --bin e2e`, or `cargo run --bin e2e -- --keep` to leave the stack
+//! running to look into a failure.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use integration_tests::client::TestClient;
+use integration_tests::hap::{Accessory, Pairing};
+use serde_json::{json, Value};
+
+const PROJECT: &str = "smart-home-system-e2e";
+const MQTT_SERVER_URI: &str = "tcp://localhost:18830";
+const BRIDGE_ADDRESS: &str = "localhost:32000";
+/// The `HOMEKIT_PIN` of the bridge in the stack.
+const PIN: &str = "031-45-154";
+const TOPIC_PREFIX: &str = "smart-home-system/yeelight";
+
+/// The aid of the yeelight lightbulb in the bridge.
+const LIGHTBULB_AID: u64 = 2;
+const LIGHTBULB_SERVICE: &str = "43";
+const ON_CHARACTERISTIC: &str = "25";
+const BRIGHTNESS_CHARACTERISTIC: &str = "8";
+
+/// How long the bridge has to accept the pairing after its container started.
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the bridge has to show a change of the bulb in its characteristics.
+const CHARACTERISTIC_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn compose(args: &[&str]) -> anyhow::Result<()> {
+    let compose_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("e2e").join("docker-compose.yml");
+    let status = Command::new("docker")
+        .args(["compose", "-p", PROJECT, "-f"])
+        .arg(compose_file)
+        .args(args)
+        .status()
+        .context("Failed to run docker compose")?;
+    anyhow::ensure!(status.success(), "docker compose {} failed: {}", args.join(" "), status);
+    Ok(())
+}
+
+async fn pair() -> anyhow::Result<Pairing> {
+    let started = Instant::now();
+    loop {
+        match Pairing::pair(BRIDGE_ADDRESS, PIN).await {
+            Ok(pairing) => return Ok(pairing),
+            Err(e) if started.elapsed() < PAIRING_TIMEOUT => {
+                println!("The bridge isn't ready yet: {:#}", e);
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+            Err(e) => return Err(e).context(format!("Failed to pair with the bridge in {:?}", PAIRING_TIMEOUT)),
+        }
+    }
+}
+
+/// Waits for the characteristic to have the value, as the bridge updates it when the controller publishes.
+async fn expect_characteristic(accessory: &mut Accessory, iid: u64, expected: Value) -> anyhow::Result<()> {
+    let started = Instant::now();
+    loop {
+        let value = accessory.read(LIGHTBULB_AID, iid).await?;
+        if value == expected {
+            return Ok(());
+        }
+        anyhow::ensure!(started.elapsed() < CHARACTERISTIC_TIMEOUT, "Characteristic {} is {} instead of {}", iid, value, expected);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+async fn run() -> anyhow::Result<()> {
+    let client = TestClient::connect_to(MQTT_SERVER_URI, "e2e-runner", &format!("{}/#", TOPIC_PREFIX)).await?;
+    let topic = |topic: &str| format!("{}/{}", TOPIC_PREFIX, topic);
+
+    println!("Waiting for the controller to connect to the bulb");
+    client.expect(&topic("stale"), "false").await?;
+
+    println!("Pairing with the bridge");
+    let pairing = pair().await?;
+    let mut accessory = pairing.session().await?;
+    let on = accessory.find_characteristic(LIGHTBULB_AID, LIGHTBULB_SERVICE, ON_CHARACTERISTIC).await?;
+    let brightness = accessory.find_characteristic(LIGHTBULB_AID, LIGHTBULB_SERVICE, BRIGHTNESS_CHARACTERISTIC).await?;
+
+    println!("Turning the lightbulb off from HomeKit");
+    accessory.write(LIGHTBULB_AID, on, json!(false)).await?;
+    client.expect(&topic("power/set"), "off").await?;
+    client.expect(&topic("power"), "off").await?;
+    expect_characteristic(&mut accessory, on, json!(false)).await?;
+
+    println!("Dimming the lightbulb from HomeKit");
+    accessory.write(LIGHTBULB_AID, brightness, json!(40)).await?;
+    client.expect(&topic("brightness/set"), "40").await?;
+    client.expect(&topic("brightness"), "40").await?;
+    expect_characteristic(&mut accessory, brightness, json!(40)).await?;
+
+    println!("Turning the lightbulb on from mqtt");
+    client.publish(&topic("power/set"), "on").await?;
+    client.expect(&topic("power"), "on").await?;
+    expect_characteristic(&mut accessory, on, json!(true)).await?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let keep = std::env::args().any(|arg| arg == "--keep");
+
+    let result = match compose(&["up", "--detach", "--build", "--wait"]) {
+        Ok(()) => run().await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = &result {
+        eprintln!("The e2e test failed: {:#}", e);
+        let _ = compose(&["logs", "--no-color"]);
+    }
+
+    if !keep {
+        if let Err(e) = compose(&["down", "--volumes"]) {
+            eprintln!("{:#}", e);
+        }
+    }
+
+    if result.is_err() {
+        std::process::exit(1);
+    }
+    println!("The e2e test passed");
+}
@@ -0,0 +1,269 @@
+//! Floods the yeelight controller with brightness sets at one or more rates and reports what became of them,
+//! to see how it copes before trusting it with automations that publish in bursts. It runs against its own
+//! broker and emulated bulb, e.g. `cargo run --bin soak -- --rate 5 --rate 50 --quota-error-every 60`.
+//!
+//! Every set has a brightness different from the one before, so the controller publishes the brightness the
+//! bulb notifies for each of them. A set is answered by that brightness or by an error on the error topic,
+//! superseded when a later set was answered first, e.g. when the sets are coalesced, and dropped otherwise.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use clap::Parser;
+use integration_tests::broker::Broker;
+use integration_tests::client::TestClient;
+use integration_tests::controller::Controller;
+use integration_tests::free_port;
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::time::MissedTickBehavior;
+use yeelight_emulator::{discovery, Emulator, Faults, State};
+
+const TOPIC_PREFIX: &str = "smart-home-system/yeelight";
+const DEVICE_ID: &str = "0x00000000504b0000";
+
+/// How often the sets waiting for an answer are counted.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// The brightness sets published per second, once for every rate.
+    #[arg(long = "rate", value_name = "PER_SEC", default_values_t = [5, 20, 50])]
+    rates: Vec<u32>,
+
+    /// How long the sets are published for at each rate.
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    duration_secs: u64,
+
+    /// How long the sets still waiting for an answer have after the last one is published.
+    #[arg(long, value_name = "SECS", default_value_t = 5)]
+    settle_secs: u64,
+
+    /// Makes the bulb wait this long before answering every command.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    delay_ms: u64,
+
+    /// Makes the bulb answer every nth command with the `client quota exceeded` error.
+    #[arg(long, value_name = "N")]
+    quota_error_every: Option<u64>,
+}
+
+struct Sent {
+    brightness: u8,
+    at: Instant,
+}
+
+/// What became of the sets published at one rate.
+#[derive(Default)]
+struct Stage {
+    sent: usize,
+    waiting: VecDeque<Sent>,
+    latencies: Vec<Duration>,
+    superseded: usize,
+    errors: BTreeMap<String, usize>,
+    max_waiting: usize,
+    waiting_samples: Vec<usize>,
+}
+
+impl Stage {
+    fn sent(&mut self, brightness: u8) {
+        self.sent += 1;
+        self.waiting.push_back(Sent { brightness, at: Instant::now() });
+    }
+
+    /// Takes the oldest set of the brightness out of the waiting ones, with the ones before it as superseded.
+    fn take(&mut self, brightness: u8) -> Option<Sent> {
+        let position = self.waiting.iter().position(|sent| sent.brightness == brightness)?;
+        self.superseded += position;
+        self.waiting.drain(..position);
+        self.waiting.pop_front()
+    }
+
+    fn answered(&mut self, brightness: u8) {
+        if let Some(sent) = self.take(brightness) {
+            self.latencies.push(sent.at.elapsed());
+        }
+    }
+
+    fn failed(&mut self, brightness: u8, error: String) {
+        if self.take(brightness).is_some() {
+            *self.errors.entry(error).or_default() += 1;
+        }
+    }
+
+    fn sample(&mut self) {
+        self.max_waiting = self.max_waiting.max(self.waiting.len());
+        self.waiting_samples.push(self.waiting.len());
+    }
+
+    fn report(&self, rate: u32, elapsed: Duration) -> String {
+        let answered = self.latencies.len();
+        let errors: usize = self.errors.values().sum();
+        let mut report = format!(
+            "{}/s: sent {} ({:.1}/s), answered {}, superseded {}, errors {}, dropped {}\n",
+            rate, self.sent, self.sent as f64 / elapsed.as_secs_f64(), answered, self.superseded, errors, self.waiting.len(),
+        );
+
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        if let Some(max) = latencies.last() {
+            let percentile = |p: usize| latencies[(latencies.len() * p / 100).min(latencies.len() - 1)];
+            report += &format!(
+                "  latency p50 {:?} p95 {:?} p99 {:?} max {:?}\n",
+                percentile(50), percentile(95), percentile(99), max,
+            );
+        }
+
+        if !self.waiting_samples.is_empty() {
+            let mean = self.waiting_samples.iter().sum::<usize>() as f64 / self.waiting_samples.len() as f64;
+            report += &format!("  waiting for an answer max {} mean {:.1}\n", self.max_waiting, mean);
+        }
+
+        for (error, count) in &self.errors {
+            report += &format!("  {} x {}\n", count, error);
+        }
+        report
+    }
+}
+
+/// Matches the brightness and the errors published by the controller with the sets of the current stage.
+async fn receive(client: Arc<TestClient>, stage: Arc<Mutex<Stage>>) {
+    let brightness_topic = format!("{}/brightness", TOPIC_PREFIX);
+    let error_topic = format!("{}/error", TOPIC_PREFIX);
+    let set_topic = format!("{}/brightness/set", TOPIC_PREFIX);
+
+    while let Some(message) = client.recv().await {
+        if message.topic() == brightness_topic {
+            if let Ok(brightness) = message.payload_str().parse() {
+                stage.lock().unwrap().answered(brightness);
+            }
+        } else if message.topic() == error_topic {
+            let Ok(error) = serde_json::from_slice::<Value>(message.payload()) else { continue };
+            if error["topic"] != set_topic.as_str() {
+                continue;
+            }
+            let brightness = error["payload"].as_str().and_then(|payload| payload.parse().ok());
+            let message = error["error"].as_str().unwrap_or_default().to_string();
+            if let Some(brightness) = brightness {
+                stage.lock().unwrap().failed(brightness, message);
+            }
+        }
+    }
+}
+
+async fn run_stage(client: &TestClient, stage: &Mutex<Stage>, rate: u32, args: &Args) -> anyhow::Result<Duration> {
+    let set_topic = format!("{}/brightness/set", TOPIC_PREFIX);
+    let duration = Duration::from_secs(args.duration_secs);
+
+    let mut publish_interval = tokio::time::interval(Duration::from_secs(1) / rate.max(1));
+    publish_interval.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let mut sample_interval = tokio::time::interval(SAMPLE_INTERVAL);
+
+    let started = Instant::now();
+    let mut brightness = 1;
+    while started.elapsed() < duration {
+        tokio::select! {
+            _ = publish_interval.tick() => {
+                // Cycles through 1 to 100, so no set has the brightness of the one before
+                brightness = brightness % 100 + 1;
+                stage.lock().unwrap().sent(brightness);
+                client.publish(&set_topic, &brightness.to_string()).await?;
+            }
+            _ = sample_interval.tick() => stage.lock().unwrap().sample(),
+        }
+    }
+    let elapsed = started.elapsed();
+
+    let settle = Instant::now();
+    while settle.elapsed() < Duration::from_secs(args.settle_secs) && !stage.lock().unwrap().waiting.is_empty() {
+        tokio::time::sleep(SAMPLE_INTERVAL).await;
+    }
+
+    Ok(elapsed)
+}
+
+async fn run(args: &Args) -> anyhow::Result<()> {
+    let broker = Broker::start()?;
+    let client = Arc::new(TestClient::connect(&broker, &format!("{}/#", TOPIC_PREFIX)).await?);
+
+    let faults = Faults {
+        delay: Duration::from_millis(args.delay_ms),
+        quota_error_every: args.quota_error_every,
+        drop_every: None,
+    };
+    let emulator = Emulator::new(DEVICE_ID.into(), "color".into(), State::default(), faults);
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(emulator.clone().serve(listener));
+    tokio::spawn(emulator.clone().answer_discovery(discovery::bind()?, address));
+
+    let controller = Controller::start_yeelight(&format!(r#"
+mqtt:
+  server_uri: {}
+  client_id: yeelight-controller-soak
+device:
+  id: "{}"
+timeouts:
+  discovery_secs: 1
+  discovery_retry_secs: 1
+http_port: {}
+log_level: warn
+"#, broker.uri(), DEVICE_ID, free_port()))?;
+
+    let result = async {
+        client.expect(&format!("{}/stale", TOPIC_PREFIX), "false").await
+            .context("The controller didn't connect to the bulb")?;
+
+        let stage = Arc::new(Mutex::new(Stage::default()));
+        tokio::spawn(receive(client.clone(), stage.clone()));
+
+        for &rate in &args.rates {
+            println!("Publishing {} brightness sets per second for {}s", rate, args.duration_secs);
+            let elapsed = run_stage(&client, &stage, rate, args).await?;
+            let finished = std::mem::take(&mut *stage.lock().unwrap());
+            print!("{}", finished.report(rate, elapsed));
+        }
+        anyhow::Ok(())
+    }.await;
+
+    result.map_err(|e| e.context(format!("The controller logged:\n{}", controller.log())))
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    if let Err(e) = run(&args).await {
+        eprintln!("The soak test failed: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Stage;
+
+    #[test]
+    fn test_stage() {
+        let mut stage = Stage::default();
+        for brightness in 1..=5 {
+            stage.sent(brightness);
+        }
+
+        stage.answered(1);
+        stage.failed(2, "client quota exceeded".into());
+        // A set answered after a later one was, as when they're coalesced
+        stage.answered(4);
+        // Not a set of the stage, e.g. the brightness published on startup
+        stage.answered(42);
+
+        assert_eq!(stage.sent, 5);
+        assert_eq!(stage.latencies.len(), 2);
+        assert_eq!(stage.superseded, 1);
+        assert_eq!(stage.errors.get("client quota exceeded"), Some(&1));
+        assert_eq!(stage.waiting.iter().map(|sent| sent.brightness).collect::<Vec<_>>(), [5]);
+    }
+}
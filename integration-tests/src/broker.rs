@@ -0,0 +1,59 @@
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use serde_json::json;
+
+use crate::free_port;
+
+/// How long the broker has to start accepting connections.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An mqtt broker running in the tests, until they exit.
+pub struct Broker {
+    pub port: u16,
+}
+
+impl Broker {
+    /// Starts a broker on a free port, accepting mqtt 5 as the controllers connect with it.
+    pub fn start() -> anyhow::Result<Self> {
+        let port = free_port();
+        let config: rumqttd::Config = serde_json::from_value(json!({
+            "id": 0,
+            "router": {
+                "max_connections": 100,
+                "max_outgoing_packet_count": 200,
+                "max_segment_size": 104857600,
+                "max_segment_count": 10,
+            },
+            "v5": {
+                "1": {
+                    "name": "v5-1",
+                    "listen": format!("127.0.0.1:{}", port),
+                    "next_connection_delay_ms": 1,
+                    "connections": {
+                        "connection_timeout_ms": 60000,
+                        "max_payload_size": 20480,
+                        "max_inflight_count": 100,
+                        "dynamic_filters": true,
+                    },
+                },
+            },
+        })).context("Invalid broker config")?;
+
+        let mut broker = rumqttd::Broker::new(config);
+        std::thread::spawn(move || broker.start());
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            anyhow::ensure!(started.elapsed() < START_TIMEOUT, "The broker didn't start listening on {}", port);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(Self { port })
+    }
+
+    pub fn uri(&self) -> String {
+        format!("tcp://127.0.0.1:{}", self.port)
+    }
+}
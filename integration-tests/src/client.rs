@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use paho_mqtt::{AsyncClient, AsyncReceiver, Message};
+
+use crate::broker::Broker;
+
+/// How long to wait for a message, long enough for a controller to discover its device and connect to it.
+pub const MESSAGE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A client of the broker, publishing the commands and checking the messages of the controllers.
+pub struct TestClient {
+    client: AsyncClient,
+    stream: AsyncReceiver<Option<Message>>,
+}
+
+impl TestClient {
+    /// Connects to the broker and subscribes to the topics, before the controller is started so none of its
+    /// messages are missed.
+    pub async fn connect(broker: &Broker, topic_filter: &str) -> anyhow::Result<Self> {
+        Self::connect_to(&broker.uri(), &format!("integration-tests-{}", broker.port), topic_filter).await
+    }
+
+    /// Connects to a broker that isn't started by the tests, e.g. the one of the e2e stack.
+    pub async fn connect_to(server_uri: &str, client_id: &str, topic_filter: &str) -> anyhow::Result<Self> {
+        let create_options = paho_mqtt::CreateOptionsBuilder::new()
+            .server_uri(server_uri)
+            .client_id(client_id)
+            .mqtt_version(paho_mqtt::MQTT_VERSION_5)
+            .finalize();
+
+        let mut client = AsyncClient::new(create_options).context("Failed to create mqtt client")?;
+        let stream = client.get_stream(100);
+
+        client.connect(paho_mqtt::ConnectOptionsBuilder::new_v5().clean_start(true).finalize()).await
+            .context("Failed to connect to the broker")?;
+        client.subscribe(topic_filter, 1).await.context(format!("Failed to subscribe to {}", topic_filter))?;
+
+        Ok(Self { client, stream })
+    }
+
+    pub async fn publish(&self, topic: &str, payload: &str) -> anyhow::Result<()> {
+        self.client.publish(Message::new(topic, payload, 1)).await
+            .context(format!("Failed to publish to {}", topic))
+    }
+
+    /// The next message on any of the topics, or `None` once the connection to the broker is closed.
+    pub async fn recv(&self) -> Option<Message> {
+        loop {
+            match self.stream.recv().await {
+                Ok(Some(message)) => return Some(message),
+                Ok(None) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// The payload of the next message on the topic, skipping the messages on the others.
+    pub async fn next(&self, topic: &str) -> anyhow::Result<String> {
+        let receive = async {
+            loop {
+                match self.stream.recv().await {
+                    Ok(Some(message)) if message.topic() == topic => return Ok(message.payload_str().to_string()),
+                    Ok(_) => continue,
+                    Err(e) => anyhow::bail!("The connection to the broker was closed: {}", e),
+                }
+            }
+        };
+
+        tokio::time::timeout(MESSAGE_TIMEOUT, receive).await
+            .context(format!("No message on {} in {:?}", topic, MESSAGE_TIMEOUT))?
+    }
+
+    /// Waits for the payload on the topic, skipping the other payloads.
+    pub async fn expect(&self, topic: &str, payload: &str) -> anyhow::Result<()> {
+        let receive = async {
+            while self.next(topic).await? != payload {}
+            Ok(())
+        };
+
+        tokio::time::timeout(MESSAGE_TIMEOUT, receive).await
+            .context(format!("No {:?} on {} in {:?}", payload, topic, MESSAGE_TIMEOUT))?
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use tempfile::TempDir;
+
+/// Builds the binary of a controller, as `cargo test` only builds the ones of this crate. The binary at env
+/// `<NAME>_BIN`, e.g. `YEELIGHT_CONTROLLER_BIN`, is used instead if set.
+fn binary(name: &str) -> anyhow::Result<PathBuf> {
+    let env = format!("{}_BIN", name.to_uppercase().replace('-', "_"));
+    if let Ok(path) = std::env::var(&env) {
+        return Ok(path.into());
+    }
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir.join("target").join("controllers");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--bin", name, "--manifest-path"])
+        .arg(manifest_dir.join("..").join(name).join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .status()
+        .context(format!("Failed to build {}", name))?;
+    anyhow::ensure!(status.success(), "Failed to build {}: {}", name, status);
+
+    Ok(target_dir.join("debug").join(name))
+}
+
+fn yeelight_controller_binary() -> anyhow::Result<&'static Path> {
+    static BINARY: OnceLock<PathBuf> = OnceLock::new();
+    if let Some(binary) = BINARY.get() {
+        return Ok(binary);
+    }
+
+    let binary = binary("yeelight-controller")?;
+    Ok(BINARY.get_or_init(|| binary))
+}
+
+/// A controller running as its own process with a config written for the test, killed when dropped.
+pub struct Controller {
+    process: Child,
+    dir: TempDir,
+}
+
+impl Controller {
+    /// Starts the yeelight controller with the config, without the env vars of the tests so only the config
+    /// is used. The paths of the config can be relative to the directory of the controller.
+    pub fn start_yeelight(config: &str) -> anyhow::Result<Self> {
+        let binary = yeelight_controller_binary()?;
+
+        let dir = tempfile::tempdir().context("Failed to create the directory of the controller")?;
+        let config_path = dir.path().join("yeelight.yaml");
+        std::fs::write(&config_path, config).context("Failed to write the config")?;
+        let log = File::create(dir.path().join("controller.log")).context("Failed to create the log")?;
+
+        let process = Command::new(binary)
+            .env_clear()
+            .arg("--config")
+            .arg(&config_path)
+            .current_dir(dir.path())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .stdin(Stdio::null())
+            .spawn()
+            .context(format!("Failed to start {:?}", binary))?;
+
+        Ok(Self { process, dir })
+    }
+
+    /// What the controller logged so far, to show why a test failed.
+    pub fn log(&self) -> String {
+        std::fs::read_to_string(self.dir.path().join("controller.log")).unwrap_or_default()
+    }
+}
+
+impl Drop for Controller {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
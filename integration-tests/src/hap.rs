@@ -0,0 +1,433 @@
+//! A minimal HomeKit controller, as iOS is one, to pair with the bridge and read and write its characteristics
+//! over HAP. Only what the e2e runner needs is implemented: pair setup, pair verify and the json endpoints.
+
+use std::ops::BitXor;
+
+use anyhow::Context;
+use num_bigint::BigUint;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::agreement::{self, EphemeralPrivateKey, X25519};
+use ring::digest::{digest, SHA512};
+use ring::hkdf;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, Ed25519KeyPair, KeyPair, ED25519};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// The 3072-bit group of RFC 5054, the one HAP uses for SRP.
+const SRP_N: &str = "\
+    FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DD\
+    EF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+    EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF0598DA48361C55D39A69163FA8FD24CF5F\
+    83655D23DCA3AD961C62F356208552BB9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+    E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF6955817183995497CEA956AE515D2261898FA0510\
+    15728E5A8AAAC42DAD33170D04507A33A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7\
+    ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864D87602733EC86A64521F2B18177B200C\
+    BBE117577A615D6C770988C0BAD946E208E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF";
+const SRP_G: u32 = 5;
+const SRP_USERNAME: &[u8] = b"Pair-Setup";
+
+/// The id the controller pairs with, a uuid as the bridge expects.
+const CONTROLLER_ID: &str = "8f3c2e5a-0e2e-4c1a-9f6b-3d1e2e000001";
+
+/// The most bytes of a request or response in one encrypted frame.
+const MAX_FRAME_LENGTH: usize = 1024;
+
+mod tlv {
+    pub const METHOD: u8 = 0x00;
+    pub const IDENTIFIER: u8 = 0x01;
+    pub const SALT: u8 = 0x02;
+    pub const PUBLIC_KEY: u8 = 0x03;
+    pub const PROOF: u8 = 0x04;
+    pub const ENCRYPTED_DATA: u8 = 0x05;
+    pub const STATE: u8 = 0x06;
+    pub const ERROR: u8 = 0x07;
+    pub const SIGNATURE: u8 = 0x0A;
+
+    /// Encodes the items, splitting the values longer than 255 bytes in consecutive items of the same type.
+    pub fn encode(items: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for (kind, value) in items {
+            let mut chunks = value.chunks(255).peekable();
+            if chunks.peek().is_none() {
+                encoded.extend([*kind, 0]);
+            }
+            for chunk in chunks {
+                encoded.extend([*kind, chunk.len() as u8]);
+                encoded.extend_from_slice(chunk);
+            }
+        }
+        encoded
+    }
+
+    /// Decodes the items, joining the consecutive ones of the same type.
+    pub fn decode(mut data: &[u8]) -> anyhow::Result<Vec<(u8, Vec<u8>)>> {
+        let mut items: Vec<(u8, Vec<u8>)> = Vec::new();
+        while let [kind, length, rest @ ..] = data {
+            let length = *length as usize;
+            anyhow::ensure!(rest.len() >= length, "Truncated tlv item of type {}", kind);
+            match items.last_mut() {
+                Some((last, value)) if last == kind => value.extend_from_slice(&rest[..length]),
+                _ => items.push((*kind, rest[..length].to_vec())),
+            }
+            data = &rest[length..];
+        }
+        anyhow::ensure!(data.is_empty(), "Truncated tlv item");
+        Ok(items)
+    }
+
+    /// The value of the item of the type, or an error with the one the accessory answered with.
+    pub fn get(items: &[(u8, Vec<u8>)], kind: u8) -> anyhow::Result<&[u8]> {
+        if let Some((_, error)) = items.iter().find(|(kind, _)| *kind == ERROR) {
+            anyhow::bail!("The accessory answered with error {:?}", error);
+        }
+        items.iter()
+            .find(|(item, _)| *item == kind)
+            .map(|(_, value)| value.as_slice())
+            .ok_or(anyhow::anyhow!("No tlv item of type {}", kind))
+    }
+}
+
+fn sha512(parts: &[&[u8]]) -> Vec<u8> {
+    digest(&SHA512, &parts.concat()).as_ref().to_vec()
+}
+
+struct KeyLength(usize);
+
+impl hkdf::KeyType for KeyLength {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn hkdf_sha512(key: &[u8], salt: &str, info: &str) -> anyhow::Result<[u8; 32]> {
+    let mut output = [0; 32];
+    hkdf::Salt::new(hkdf::HKDF_SHA512, salt.as_bytes())
+        .extract(key)
+        .expand(&[info.as_bytes()], KeyLength(output.len()))
+        .and_then(|okm| okm.fill(&mut output))
+        .map_err(|_| anyhow::anyhow!("Failed to derive the key for {}", info))?;
+    Ok(output)
+}
+
+/// The nonce of the pairing messages, e.g. `PS-Msg05`, or of the frames, with the counter, padded to 12 bytes.
+fn nonce(suffix: &[u8]) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[12 - suffix.len()..].copy_from_slice(suffix);
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn cipher(key: &[u8; 32]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("The key has the length of chacha20"))
+}
+
+fn seal(key: &[u8; 32], nonce: Nonce, aad: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut sealed = data.to_vec();
+    cipher(key).seal_in_place_append_tag(nonce, Aad::from(aad), &mut sealed)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt"))?;
+    Ok(sealed)
+}
+
+fn open(key: &[u8; 32], nonce: Nonce, aad: &[u8], data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut opened = data.to_vec();
+    let length = cipher(key).open_in_place(nonce, Aad::from(aad), &mut opened)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt, the keys don't match"))?
+        .len();
+    opened.truncate(length);
+    Ok(opened)
+}
+
+fn random<const N: usize>(rng: &SystemRandom) -> [u8; N] {
+    let mut bytes = [0; N];
+    rng.fill(&mut bytes).expect("Failed to generate random bytes");
+    bytes
+}
+
+/// The keys of a verified session, every frame has its own nonce from the counters.
+struct Session {
+    write_key: [u8; 32],
+    read_key: [u8; 32],
+    write_count: u64,
+    read_count: u64,
+}
+
+/// A connection to the accessory, in plain http until it's verified and then with every request and response
+/// encrypted in frames.
+struct Connection {
+    stream: TcpStream,
+    session: Option<Session>,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    async fn connect(address: &str) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(address).await.context(format!("Failed to connect to {}", address))?;
+        Ok(Self { stream, session: None, buffer: Vec::new() })
+    }
+
+    async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        let Some(session) = &mut self.session else {
+            return Ok(self.stream.write_all(data).await?);
+        };
+
+        for chunk in data.chunks(MAX_FRAME_LENGTH) {
+            let length = (chunk.len() as u16).to_le_bytes();
+            let frame = seal(&session.write_key, nonce(&session.write_count.to_le_bytes()), &length, chunk)?;
+            session.write_count += 1;
+            self.stream.write_all(&length).await?;
+            self.stream.write_all(&frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads more of the response into the buffer.
+    async fn fill(&mut self) -> anyhow::Result<()> {
+        let Some(session) = &mut self.session else {
+            let mut data = [0; 4096];
+            let read = self.stream.read(&mut data).await?;
+            anyhow::ensure!(read > 0, "The accessory closed the connection");
+            self.buffer.extend_from_slice(&data[..read]);
+            return Ok(());
+        };
+
+        let mut length = [0; 2];
+        self.stream.read_exact(&mut length).await.context("The accessory closed the connection")?;
+        let mut frame = vec![0; u16::from_le_bytes(length) as usize + 16];
+        self.stream.read_exact(&mut frame).await.context("The accessory closed the connection")?;
+        let data = open(&session.read_key, nonce(&session.read_count.to_le_bytes()), &length, &frame)?;
+        session.read_count += 1;
+        self.buffer.extend_from_slice(&data);
+        Ok(())
+    }
+
+    /// Sends the request and reads the status and body of the response, which always has a content length.
+    async fn request(&mut self, method: &str, path: &str, content_type: &str, body: &[u8]) -> anyhow::Result<(u16, Vec<u8>)> {
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: bridge\r\n", method, path).into_bytes();
+        if !body.is_empty() {
+            request.extend(format!("Content-Type: {}\r\nContent-Length: {}\r\n", content_type, body.len()).bytes());
+        }
+        request.extend(b"\r\n");
+        request.extend_from_slice(body);
+        self.write(&request).await?;
+
+        let header_end = loop {
+            if let Some(position) = self.buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+                break position + 4;
+            }
+            self.fill().await?;
+        };
+        let header = String::from_utf8_lossy(&self.buffer[..header_end]).to_string();
+        let status = header.split_whitespace().nth(1)
+            .and_then(|status| status.parse().ok())
+            .context(format!("Invalid response: {}", header))?;
+        let content_length = header.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .map(|(_, value)| value.trim().parse::<usize>())
+            .transpose()
+            .context(format!("Invalid content length: {}", header))?
+            .unwrap_or(0);
+
+        while self.buffer.len() < header_end + content_length {
+            self.fill().await?;
+        }
+        let body = self.buffer[header_end..header_end + content_length].to_vec();
+        self.buffer.drain(..header_end + content_length);
+        Ok((status, body))
+    }
+
+    async fn pairing_request(&mut self, path: &str, items: &[(u8, &[u8])]) -> anyhow::Result<Vec<(u8, Vec<u8>)>> {
+        let (status, body) = self.request("POST", path, "application/pairing+tlv8", &tlv::encode(items)).await?;
+        anyhow::ensure!(status == 200, "POST {} answered with status {}", path, status);
+        tlv::decode(&body)
+    }
+}
+
+/// A pairing of the controller with an accessory, with the keys to verify the sessions.
+pub struct Pairing {
+    address: String,
+    keys: Ed25519KeyPair,
+    accessory_id: Vec<u8>,
+    accessory_public_key: Vec<u8>,
+}
+
+impl Pairing {
+    /// Pairs with the accessory at the address, e.g. `localhost:32000`, with its setup code, e.g. `111-22-333`.
+    pub async fn pair(address: &str, pin: &str) -> anyhow::Result<Self> {
+        let rng = SystemRandom::new();
+        let mut connection = Connection::connect(address).await?;
+
+        // M1 and M2: the accessory answers with the salt and its srp public key
+        let response = connection.pairing_request("/pair-setup", &[(tlv::STATE, &[1]), (tlv::METHOD, &[0])]).await?;
+        let salt = tlv::get(&response, tlv::SALT)?;
+        let b_public = BigUint::from_bytes_be(tlv::get(&response, tlv::PUBLIC_KEY)?);
+
+        let n = BigUint::parse_bytes(SRP_N.as_bytes(), 16).expect("The srp group is valid hex");
+        let g = BigUint::from(SRP_G);
+        let n_bytes = n.to_bytes_be();
+        let mut g_padded = vec![0; n_bytes.len()];
+        let g_bytes = g.to_bytes_be();
+        g_padded[n_bytes.len() - g_bytes.len()..].copy_from_slice(&g_bytes);
+
+        let a = BigUint::from_bytes_be(&random::<32>(&rng));
+        let a_public = g.modpow(&a, &n).to_bytes_be();
+        let b_bytes = b_public.to_bytes_be();
+        let k = BigUint::from_bytes_be(&sha512(&[&n_bytes, &g_padded]));
+        let u = BigUint::from_bytes_be(&sha512(&[&a_public, &b_bytes]));
+        let x = BigUint::from_bytes_be(&sha512(&[salt, &sha512(&[SRP_USERNAME, b":", pin.as_bytes()])]));
+        let base = (&b_public + &n - (&k * g.modpow(&x, &n)) % &n) % &n;
+        let session_key = sha512(&[&base.modpow(&(&a + &u * &x), &n).to_bytes_be()]);
+
+        let n_xor_g = BigUint::from_bytes_be(&sha512(&[&n_bytes])).bitxor(BigUint::from_bytes_be(&sha512(&[&g_bytes])));
+        let proof = sha512(&[&n_xor_g.to_bytes_be(), &sha512(&[SRP_USERNAME]), salt, &a_public, &b_bytes, &session_key]);
+
+        // M3 and M4: both prove they have the same key, so the pin was right
+        let response = connection.pairing_request("/pair-setup", &[
+            (tlv::STATE, &[3]),
+            (tlv::PUBLIC_KEY, &a_public),
+            (tlv::PROOF, &proof),
+        ]).await.context("Failed to pair, is the pin right?")?;
+        let accessory_proof = tlv::get(&response, tlv::PROOF).context("Failed to pair, is the pin right?")?;
+        anyhow::ensure!(accessory_proof == sha512(&[&a_public, &proof, &session_key]), "The accessory proof doesn't match");
+
+        // M5 and M6: the long term keys are exchanged
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).map_err(|_| anyhow::anyhow!("Failed to generate the keys"))?;
+        let keys = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| anyhow::anyhow!("Invalid generated keys"))?;
+        let encryption_key = hkdf_sha512(&session_key, "Pair-Setup-Encrypt-Salt", "Pair-Setup-Encrypt-Info")?;
+        let controller_x = hkdf_sha512(&session_key, "Pair-Setup-Controller-Sign-Salt", "Pair-Setup-Controller-Sign-Info")?;
+        let public_key = keys.public_key().as_ref();
+        let signature = keys.sign(&[&controller_x, CONTROLLER_ID.as_bytes(), public_key].concat());
+        let sub_tlv = tlv::encode(&[
+            (tlv::IDENTIFIER, CONTROLLER_ID.as_bytes()),
+            (tlv::PUBLIC_KEY, public_key),
+            (tlv::SIGNATURE, signature.as_ref()),
+        ]);
+        let encrypted = seal(&encryption_key, nonce(b"PS-Msg05"), &[], &sub_tlv)?;
+
+        let response = connection.pairing_request("/pair-setup", &[(tlv::STATE, &[5]), (tlv::ENCRYPTED_DATA, &encrypted)]).await?;
+        let sub_tlv = tlv::decode(&open(&encryption_key, nonce(b"PS-Msg06"), &[], tlv::get(&response, tlv::ENCRYPTED_DATA)?)?)?;
+        let accessory_id = tlv::get(&sub_tlv, tlv::IDENTIFIER)?.to_vec();
+        let accessory_public_key = tlv::get(&sub_tlv, tlv::PUBLIC_KEY)?.to_vec();
+        let accessory_x = hkdf_sha512(&session_key, "Pair-Setup-Accessory-Sign-Salt", "Pair-Setup-Accessory-Sign-Info")?;
+        signature::UnparsedPublicKey::new(&ED25519, &accessory_public_key)
+            .verify(&[&accessory_x[..], &accessory_id, &accessory_public_key].concat(), tlv::get(&sub_tlv, tlv::SIGNATURE)?)
+            .map_err(|_| anyhow::anyhow!("The signature of the accessory is invalid"))?;
+
+        Ok(Self { address: address.into(), keys, accessory_id, accessory_public_key })
+    }
+
+    /// Opens a verified session with the accessory, to send it the requests.
+    pub async fn session(&self) -> anyhow::Result<Accessory> {
+        let rng = SystemRandom::new();
+        let mut connection = Connection::connect(&self.address).await?;
+
+        // M1 and M2: the accessory proves it's the one paired with
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng).map_err(|_| anyhow::anyhow!("Failed to generate the keys"))?;
+        let public_key = private_key.compute_public_key().map_err(|_| anyhow::anyhow!("Invalid generated keys"))?;
+        let public_key = public_key.as_ref();
+        let response = connection.pairing_request("/pair-verify", &[(tlv::STATE, &[1]), (tlv::PUBLIC_KEY, public_key)]).await?;
+        let accessory_key = tlv::get(&response, tlv::PUBLIC_KEY)?.to_vec();
+        let shared_secret = agreement::agree_ephemeral(private_key, &agreement::UnparsedPublicKey::new(&X25519, &accessory_key), |secret| secret.to_vec())
+            .map_err(|_| anyhow::anyhow!("Invalid public key of the accessory"))?;
+
+        let encryption_key = hkdf_sha512(&shared_secret, "Pair-Verify-Encrypt-Salt", "Pair-Verify-Encrypt-Info")?;
+        let sub_tlv = tlv::decode(&open(&encryption_key, nonce(b"PV-Msg02"), &[], tlv::get(&response, tlv::ENCRYPTED_DATA)?)?)?;
+        anyhow::ensure!(tlv::get(&sub_tlv, tlv::IDENTIFIER)? == self.accessory_id, "The accessory isn't the one paired with");
+        signature::UnparsedPublicKey::new(&ED25519, &self.accessory_public_key)
+            .verify(&[&accessory_key, &self.accessory_id, public_key].concat(), tlv::get(&sub_tlv, tlv::SIGNATURE)?)
+            .map_err(|_| anyhow::anyhow!("The signature of the accessory is invalid"))?;
+
+        // M3 and M4: the controller proves it's the one paired
+        let signature = self.keys.sign(&[public_key, CONTROLLER_ID.as_bytes(), &accessory_key].concat());
+        let sub_tlv = tlv::encode(&[(tlv::IDENTIFIER, CONTROLLER_ID.as_bytes()), (tlv::SIGNATURE, signature.as_ref())]);
+        let encrypted = seal(&encryption_key, nonce(b"PV-Msg03"), &[], &sub_tlv)?;
+        let response = connection.pairing_request("/pair-verify", &[(tlv::STATE, &[3]), (tlv::ENCRYPTED_DATA, &encrypted)]).await?;
+        anyhow::ensure!(tlv::get(&response, tlv::STATE)? == [4], "The accessory didn't verify the session");
+
+        connection.session = Some(Session {
+            write_key: hkdf_sha512(&shared_secret, "Control-Salt", "Control-Write-Encryption-Key")?,
+            read_key: hkdf_sha512(&shared_secret, "Control-Salt", "Control-Read-Encryption-Key")?,
+            write_count: 0,
+            read_count: 0,
+        });
+        Ok(Accessory { connection })
+    }
+}
+
+/// The short form of a HAP type, e.g. `25` for `00000025-0000-1000-8000-0026BB765291`.
+fn short_type(kind: &str) -> String {
+    let kind = kind.strip_suffix("-0000-1000-8000-0026BB765291").unwrap_or(kind);
+    kind.trim_start_matches('0').to_uppercase()
+}
+
+/// A verified session with an accessory.
+pub struct Accessory {
+    connection: Connection,
+}
+
+impl Accessory {
+    /// The json of every accessory, service and characteristic, as HomeKit shows them.
+    pub async fn accessories(&mut self) -> anyhow::Result<Value> {
+        let (status, body) = self.connection.request("GET", "/accessories", "", &[]).await?;
+        anyhow::ensure!(status == 200, "GET /accessories answered with status {}", status);
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// The iid of the characteristic of the type, e.g. `25` for On, in the service of the type of the accessory.
+    pub async fn find_characteristic(&mut self, aid: u64, service: &str, characteristic: &str) -> anyhow::Result<u64> {
+        let accessories = self.accessories().await?;
+        accessories["accessories"].as_array().into_iter().flatten()
+            .filter(|accessory| accessory["aid"] == aid)
+            .flat_map(|accessory| accessory["services"].as_array().into_iter().flatten())
+            .filter(|found| found["type"].as_str().map(short_type) == Some(short_type(service)))
+            .flat_map(|service| service["characteristics"].as_array().into_iter().flatten())
+            .find(|found| found["type"].as_str().map(short_type) == Some(short_type(characteristic)))
+            .and_then(|characteristic| characteristic["iid"].as_u64())
+            .context(format!("Accessory {} has no characteristic {} in a service {}", aid, characteristic, service))
+    }
+
+    pub async fn read(&mut self, aid: u64, iid: u64) -> anyhow::Result<Value> {
+        let path = format!("/characteristics?id={}.{}", aid, iid);
+        let (status, body) = self.connection.request("GET", &path, "", &[]).await?;
+        anyhow::ensure!(status == 200, "GET {} answered with status {}", path, status);
+        let body: Value = serde_json::from_slice(&body)?;
+        Ok(body["characteristics"][0]["value"].clone())
+    }
+
+    pub async fn write(&mut self, aid: u64, iid: u64, value: Value) -> anyhow::Result<()> {
+        let body = json!({ "characteristics": [{ "aid": aid, "iid": iid, "value": value }] });
+        let (status, body) = self.connection.request("PUT", "/characteristics", "application/hap+json", body.to_string().as_bytes()).await?;
+        anyhow::ensure!(status == 204, "PUT /characteristics answered with status {}: {}", status, String::from_utf8_lossy(&body));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hap::{short_type, tlv};
+
+    #[test]
+    fn test_tlv_fragments() {
+        let long = vec![7; 300];
+        let encoded = tlv::encode(&[(tlv::STATE, &[1]), (tlv::PUBLIC_KEY, &long), (tlv::PROOF, &[])]);
+        assert_eq!(encoded.len(), 3 + 2 + 255 + 2 + 45 + 2);
+
+        let decoded = tlv::decode(&encoded).unwrap();
+        assert_eq!(decoded, [(tlv::STATE, vec![1]), (tlv::PUBLIC_KEY, long), (tlv::PROOF, vec![])]);
+        assert!(tlv::decode(&encoded[..10]).is_err());
+    }
+
+    #[test]
+    fn test_tlv_error() {
+        let decoded = tlv::decode(&tlv::encode(&[(tlv::STATE, &[2]), (tlv::ERROR, &[2])])).unwrap();
+        assert!(tlv::get(&decoded, tlv::STATE).is_err());
+    }
+
+    #[test]
+    fn test_short_type() {
+        assert_eq!(short_type("00000025-0000-1000-8000-0026BB765291"), "25");
+        assert_eq!(short_type("43"), "43");
+        assert_eq!(short_type("8"), "8");
+    }
+}
@@ -0,0 +1,14 @@
+//! Runs the controllers against an embedded mqtt broker and emulated devices, to test them end to end.
+
+pub mod broker;
+pub mod client;
+pub mod controller;
+pub mod hap;
+
+/// A port nothing is listening on, for the broker and the http endpoints of the controllers.
+pub fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .map(|address| address.port())
+        .expect("Failed to find a free port")
+}
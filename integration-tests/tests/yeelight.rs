@@ -0,0 +1,110 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use integration_tests::broker::Broker;
+use integration_tests::client::TestClient;
+use integration_tests::controller::Controller;
+use integration_tests::free_port;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use yeelight_emulator::{discovery, Emulator, Faults, State};
+
+const TOPIC_PREFIX: &str = "smart-home-system/yeelight";
+
+/// A yeelight controller connected to an emulated bulb, with a client subscribed to its topics. Every test has
+/// its own broker and bulb, found by its id as the bulbs of the other tests answer the discoveries too.
+struct Setup {
+    client: TestClient,
+    emulator: Emulator,
+    address: SocketAddr,
+    controller: Controller,
+}
+
+impl Setup {
+    async fn start(id: &str) -> Self {
+        let broker = Broker::start().unwrap();
+        let client = TestClient::connect(&broker, &format!("{}/#", TOPIC_PREFIX)).await.unwrap();
+
+        let emulator = Emulator::new(id.into(), "color".into(), State::default(), Faults::default());
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(emulator.clone().serve(listener));
+        tokio::spawn(emulator.clone().answer_discovery(discovery::bind().unwrap(), address));
+
+        let controller = Controller::start_yeelight(&format!(r#"
+mqtt:
+  server_uri: {}
+  client_id: yeelight-controller-{}
+device:
+  id: "{}"
+timeouts:
+  discovery_secs: 1
+  discovery_retry_secs: 1
+http_port: {}
+state_path: state.json
+log_level: debug
+"#, broker.uri(), broker.port, id, free_port())).unwrap();
+
+        let setup = Self { client, emulator, address, controller };
+        // The state read from the bulb is published when the controller is ready
+        setup.expect("stale", "false").await;
+        setup
+    }
+
+    async fn publish(&self, topic: &str, payload: &str) {
+        self.client.publish(&format!("{}/{}", TOPIC_PREFIX, topic), payload).await.unwrap();
+    }
+
+    async fn next(&self, topic: &str) -> String {
+        let result = self.client.next(&format!("{}/{}", TOPIC_PREFIX, topic)).await;
+        result.unwrap_or_else(|e| panic!("{:#}, the controller logged:\n{}", e, self.controller.log()))
+    }
+
+    async fn expect(&self, topic: &str, payload: &str) {
+        let result = self.client.expect(&format!("{}/{}", TOPIC_PREFIX, topic), payload).await;
+        result.unwrap_or_else(|e| panic!("{:#}, the controller logged:\n{}", e, self.controller.log()))
+    }
+}
+
+#[tokio::test]
+async fn test_set_commands_change_the_bulb() {
+    let setup = Setup::start("0x00000000e2e00001").await;
+
+    setup.publish("power/set", "off").await;
+    setup.expect("power", "off").await;
+    assert!(!setup.emulator.state().power);
+
+    setup.publish("brightness/set", "40").await;
+    setup.expect("brightness", "40").await;
+    assert_eq!(setup.emulator.state().bright, 40);
+
+    setup.publish("toggle", "").await;
+    setup.expect("power", "on").await;
+    assert!(setup.emulator.state().power);
+}
+
+#[tokio::test]
+async fn test_changes_of_the_bulb_are_published() {
+    let setup = Setup::start("0x00000000e2e00002").await;
+
+    // Changed by another client, e.g. the yeelight app
+    let mut stream = BufReader::new(TcpStream::connect(setup.address).await.unwrap());
+    stream.get_mut().write_all(b"{\"id\":1,\"method\":\"set_bright\",\"params\":[25]}\r\n").await.unwrap();
+    let mut answer = String::new();
+    stream.read_line(&mut answer).await.unwrap();
+    assert_eq!(serde_json::from_str::<Value>(&answer).unwrap(), json!({ "id": 1, "result": ["ok"] }));
+
+    setup.expect("brightness", "25").await;
+}
+
+#[tokio::test]
+async fn test_invalid_commands_publish_an_error() {
+    let setup = Setup::start("0x00000000e2e00003").await;
+
+    setup.publish("power/set", "dim").await;
+    let error: Value = serde_json::from_str(&setup.next("error").await).unwrap();
+    assert_eq!(error["topic"], json!(format!("{}/power/set", TOPIC_PREFIX)));
+    assert_eq!(error["payload"], json!("dim"));
+    assert_eq!(error["kind"], json!("invalid_payload"));
+    assert!(setup.emulator.state().power);
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use domain_state::error;
+use log::{debug, error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::config::{AttributeConfig, Config, DeviceConfig};
+use crate::knx::{GroupEvent, GroupTelegram};
+use crate::tunnel::Tunnel;
+use crate::{MQTT_ERROR_TOPIC, MQTT_TOPIC_PREFIX};
+
+struct ManagedDevice {
+    config: DeviceConfig,
+    last_values: HashMap<String, String>,
+}
+
+pub struct Application {
+    client: AsyncClient,
+    gateway: String,
+    tunnel: Tunnel,
+    devices: HashMap<String, ManagedDevice>,
+}
+
+/// A `smart-home-system/knx/<device>/<attribute>/<action>` topic.
+struct DeviceTopic<'a> {
+    device: &'a str,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { device: parts.next()?, attribute: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+impl Application {
+    pub async fn new(client: AsyncClient, config: Config) -> Self {
+        let tunnel = Self::connect(&config.gateway).await;
+
+        let devices = config.devices.into_iter()
+            .map(|device| {
+                info!("Loaded KNX device {} with {} attributes", device.name, device.attributes.len());
+                (device.name.clone(), ManagedDevice { config: device, last_values: HashMap::new() })
+            })
+            .collect();
+
+        let mut application = Self { client, gateway: config.gateway, tunnel, devices };
+        application.read_all().await;
+        application
+    }
+
+    async fn connect(gateway: &str) -> Tunnel {
+        loop {
+            match Tunnel::connect(gateway).await {
+                Ok(tunnel) => return tunnel,
+                Err(e) => warn!("Failed to connect to KNX gateway {}: {}. Retrying in 30 seconds...", gateway, e),
+            }
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+
+    pub async fn reconnect(&mut self) {
+        self.tunnel.disconnect().await;
+        self.tunnel = Self::connect(&self.gateway).await;
+        self.read_all().await;
+    }
+
+    pub async fn disconnect(&mut self) {
+        self.tunnel.disconnect().await;
+    }
+
+    /// Asks every mapped group address for its value, so the state is published after (re)connecting.
+    async fn read_all(&mut self) {
+        let attributes: Vec<AttributeConfig> = self.devices.values()
+            .flat_map(|managed| managed.config.attributes.iter().cloned())
+            .collect();
+
+        for attribute in attributes {
+            if let Err(e) = self.tunnel.send_group(attribute.read_address(), None).await {
+                warn!("Failed to read group address {}: {}", attribute.read_address(), e);
+                return;
+            }
+        }
+    }
+
+    /// Waits for the next group telegram on the bus. Fails once the tunnel is broken.
+    pub async fn next_telegram(&mut self) -> anyhow::Result<GroupTelegram> {
+        self.tunnel.recv().await
+    }
+
+    pub fn handle_telegram(&mut self, telegram: GroupTelegram) {
+        let (GroupEvent::Write(data) | GroupEvent::Response(data)) = &telegram.event else { return };
+
+        let mut known = false;
+
+        for managed in self.devices.values_mut() {
+            for attribute in managed.config.attributes.iter().filter(|attribute| attribute.listens_to(telegram.destination)) {
+                known = true;
+
+                let payload = match attribute.dpt.decode(data) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        warn!("Received invalid value {:02x?} on group address {}: {}", data, telegram.destination, e);
+                        continue;
+                    }
+                };
+
+                publish(&self.client, &mut managed.last_values, &managed.config.name, &attribute.name, payload);
+            }
+        }
+
+        if !known {
+            debug!("Ignoring telegram to unmapped group address {}", telegram.destination);
+        }
+    }
+
+    pub async fn heartbeat(&mut self) {
+        if let Err(e) = self.tunnel.heartbeat().await {
+            warn!("KNX gateway did not answer the heartbeat: {}. Reconnecting...", e);
+            self.reconnect().await;
+        }
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        let Some(managed) = self.devices.get_mut(topic.device) else {
+            warn!("[{}] Unknown KNX device {}", message.topic(), topic.device);
+            return;
+        };
+
+        let Some(attribute) = managed.config.attributes.iter().find(|attribute| attribute.name == topic.attribute).cloned() else {
+            error!("[{}] Unknown attribute '{}' for KNX device {}", message.topic(), topic.attribute, topic.device);
+            return;
+        };
+
+        let result = match topic.action {
+            "get" => {
+                // the response is published even if the value didn't change
+                managed.last_values.remove(&attribute.name);
+                self.tunnel.send_group(attribute.read_address(), None).await
+            }
+            "set" => self.handle_set(&topic, &attribute, message).await,
+            _ => {
+                error!("Received message for unknown topic: {}", message.topic());
+                return;
+            }
+        };
+
+        if let Err(e) = result {
+            error!("[{}] Failed to send telegram to group address {}: {}. Reconnecting...", message.topic(), attribute.address, e);
+            self.reconnect().await;
+        }
+    }
+
+    async fn handle_set(&mut self, topic: &DeviceTopic<'_>, attribute: &AttributeConfig, message: &Message) -> anyhow::Result<()> {
+        if !attribute.writable {
+            error!("[{}] Attribute '{}' is read-only", message.topic(), attribute.name);
+            return Ok(());
+        }
+
+        let payload = message.payload_str();
+        let value = match attribute.dpt.encode(&payload) {
+            Ok(value) => value,
+            Err(e) => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string());
+                return Ok(());
+            }
+        };
+
+        info!("[{}] Writing {} to group address {}", message.topic(), payload.trim(), attribute.address);
+
+        self.tunnel.send_group(attribute.address, Some(&value)).await?;
+
+        // the bus doesn't echo our own writes, so without a status address the written value is the state
+        if attribute.status_address.is_none() {
+            if let (Some(managed), Ok(payload)) = (self.devices.get_mut(topic.device), attribute.dpt.decode(&value.data)) {
+                publish(&self.client, &mut managed.last_values, topic.device, &attribute.name, payload);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Publishes the value of an attribute if it changed since it was last published.
+fn publish(client: &AsyncClient, last_values: &mut HashMap<String, String>, device: &str, attribute: &str, payload: String) {
+    if last_values.get(attribute) == Some(&payload) {
+        return;
+    }
+
+    info!("KNX device {} {} is: {}", device, attribute, payload);
+
+    let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, device, attribute);
+    client.publish(Message::new_retained(topic, payload.clone(), 1));
+    last_values.insert(attribute.to_string(), payload);
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::dpt::Dpt;
+use crate::knx::GroupAddress;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Address of the KNX IP interface, e.g. `192.168.1.80` or `192.168.1.80:3671`.
+    pub gateway: String,
+    pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeviceConfig {
+    /// Used as the topic segment, e.g. `smart-home-system/knx/<name>/power`.
+    pub name: String,
+    pub attributes: Vec<AttributeConfig>,
+}
+
+/// A group address mapped to `smart-home-system/knx/<device>/<attribute>`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct AttributeConfig {
+    pub name: String,
+    /// Written by `set` and listened to for changes.
+    pub address: GroupAddress,
+    /// Where the actuator reports its actual state, when it differs from `address`. Read by `get`.
+    pub status_address: Option<GroupAddress>,
+    #[serde(rename = "type")]
+    pub dpt: Dpt,
+    /// Sensors and status-only points can't be set.
+    #[serde(default = "default_writable")]
+    pub writable: bool,
+}
+
+fn default_writable() -> bool {
+    true
+}
+
+impl AttributeConfig {
+    pub fn read_address(&self) -> GroupAddress {
+        self.status_address.unwrap_or(self.address)
+    }
+
+    pub fn listens_to(&self, address: GroupAddress) -> bool {
+        self.address == address || self.status_address == Some(address)
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        let config: Config = serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))?;
+
+        let mut names = HashSet::new();
+        for device in &config.devices {
+            if !names.insert(&device.name) {
+                anyhow::bail!("Device {} is defined more than once", device.name);
+            }
+        }
+
+        Ok(config)
+    }
+}
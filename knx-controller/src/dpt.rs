@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::knx::GroupValue;
+
+/// The datapoint types a group address can carry. Configured as `<main>.<sub>`, e.g. `9.001`;
+/// only the main number matters except for `5.001`, which is scaled to 0-100.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Dpt {
+    /// 1.xxx: published as `on` / `off`.
+    Boolean,
+    /// 5.001: published as 0-100.
+    Percent,
+    /// 5.xxx
+    Unsigned8,
+    /// 7.xxx
+    Unsigned16,
+    /// 13.xxx
+    Signed32,
+    /// 9.xxx: 2-byte float, e.g. temperature, humidity, lux.
+    Float16,
+    /// 14.xxx
+    Float32,
+}
+
+impl FromStr for Dpt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (main, sub) = s.split_once('.').unwrap_or((s, ""));
+
+        match (main, sub) {
+            ("1", _) => Ok(Dpt::Boolean),
+            ("5", "001") => Ok(Dpt::Percent),
+            ("5", _) => Ok(Dpt::Unsigned8),
+            ("7", _) => Ok(Dpt::Unsigned16),
+            ("13", _) => Ok(Dpt::Signed32),
+            ("9", _) => Ok(Dpt::Float16),
+            ("14", _) => Ok(Dpt::Float32),
+            _ => Err(format!("Unsupported datapoint type: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Dpt {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Dpt {
+    /// Decodes the value of a group write or response into the published payload.
+    pub fn decode(&self, data: &[u8]) -> anyhow::Result<String> {
+        let payload = match self {
+            Dpt::Boolean => on_off(*data.first().context("Missing value")? & 0x01 != 0),
+            Dpt::Percent => ((*data.first().context("Missing value")? as f64 * 100.0 / 255.0).round() as u8).to_string(),
+            Dpt::Unsigned8 => data.first().context("Missing value")?.to_string(),
+            Dpt::Unsigned16 => u16::from_be_bytes(bytes(data)?).to_string(),
+            Dpt::Signed32 => i32::from_be_bytes(bytes(data)?).to_string(),
+            Dpt::Float16 => {
+                let raw = u16::from_be_bytes(bytes(data)?);
+                if raw == 0x7FFF {
+                    bail!("Invalid 2-byte float value");
+                }
+                format_number(decode_float16(raw))
+            }
+            Dpt::Float32 => format_number(f32::from_be_bytes(bytes(data)?) as f64),
+        };
+
+        Ok(payload)
+    }
+
+    /// Encodes a payload published to a `set` topic into the value of a group write.
+    pub fn encode(&self, payload: &str) -> Result<GroupValue, String> {
+        let payload = payload.trim();
+        let invalid = || format!("Invalid value for {:?}: {}", self, payload);
+
+        let data = match self {
+            Dpt::Boolean => {
+                let value = match payload {
+                    "on" | "true" | "1" => 1,
+                    "off" | "false" | "0" => 0,
+                    _ => return Err(invalid()),
+                };
+                return Ok(GroupValue { data: vec![value], small: true });
+            }
+            Dpt::Percent => {
+                let percent = payload.parse::<u8>().ok().filter(|percent| *percent <= 100).ok_or_else(invalid)?;
+                vec![(percent as f64 * 255.0 / 100.0).round() as u8]
+            }
+            Dpt::Unsigned8 => vec![payload.parse::<u8>().map_err(|_| invalid())?],
+            Dpt::Unsigned16 => payload.parse::<u16>().map_err(|_| invalid())?.to_be_bytes().to_vec(),
+            Dpt::Signed32 => payload.parse::<i32>().map_err(|_| invalid())?.to_be_bytes().to_vec(),
+            Dpt::Float16 => {
+                let value = payload.parse::<f64>().ok().filter(|value| value.is_finite()).ok_or_else(invalid)?;
+                encode_float16(value).ok_or_else(invalid)?.to_be_bytes().to_vec()
+            }
+            Dpt::Float32 => payload.parse::<f32>().map_err(|_| invalid())?.to_be_bytes().to_vec(),
+        };
+
+        Ok(GroupValue { data, small: false })
+    }
+}
+
+fn bytes<const N: usize>(data: &[u8]) -> anyhow::Result<[u8; N]> {
+    data.try_into().ok().with_context(|| format!("Expected {} bytes but got {:02x?}", N, data))
+}
+
+fn on_off(value: bool) -> String {
+    if value { "on" } else { "off" }.into()
+}
+
+/// Formats with at most two decimal places, without trailing zeros.
+fn format_number(value: f64) -> String {
+    let formatted = format!("{:.2}", value);
+    formatted.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// 2-byte float: `0.01 * mantissa * 2^exponent`, laid out as `MEEEEMMM MMMMMMMM` with a 12-bit
+/// two's complement mantissa whose sign is the top bit.
+fn decode_float16(raw: u16) -> f64 {
+    let exponent = (raw >> 11) & 0x0F;
+    let mut mantissa = (raw & 0x07FF) as i32;
+    if raw & 0x8000 != 0 {
+        mantissa -= 2048;
+    }
+
+    0.01 * mantissa as f64 * (1u32 << exponent) as f64
+}
+
+fn encode_float16(value: f64) -> Option<u16> {
+    let hundredths = value * 100.0;
+
+    (0..16u16).find_map(|exponent| {
+        let mantissa = (hundredths / (1u32 << exponent) as f64).round() as i32;
+        (-2048..=2047).contains(&mantissa).then(|| {
+            let sign = if mantissa < 0 { 0x8000 } else { 0 };
+            sign | exponent << 11 | (mantissa & 0x07FF) as u16
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dpt::Dpt;
+    use crate::knx::GroupValue;
+
+    #[test]
+    fn test_parse_dpt() {
+        assert_eq!("1.001".parse(), Ok(Dpt::Boolean));
+        assert_eq!("5.001".parse(), Ok(Dpt::Percent));
+        assert_eq!("5.010".parse(), Ok(Dpt::Unsigned8));
+        assert_eq!("9".parse(), Ok(Dpt::Float16));
+        assert!("16.000".parse::<Dpt>().is_err());
+    }
+
+    #[test]
+    fn test_boolean_and_percent() {
+        assert_eq!(Dpt::Boolean.decode(&[0x01]).unwrap(), "on");
+        assert_eq!(Dpt::Boolean.encode("off"), Ok(GroupValue { data: vec![0], small: true }));
+        assert_eq!(Dpt::Percent.decode(&[0xFF]).unwrap(), "100");
+        assert_eq!(Dpt::Percent.encode("50"), Ok(GroupValue { data: vec![128], small: false }));
+        assert!(Dpt::Percent.encode("101").is_err());
+    }
+
+    #[test]
+    fn test_float16() {
+        assert_eq!(Dpt::Float16.decode(&[0x0C, 0x1A]).unwrap(), "21");
+        assert_eq!(Dpt::Float16.decode(&[0x87, 0x9C]).unwrap(), "-1");
+        assert!(Dpt::Float16.decode(&[0x7F, 0xFF]).is_err());
+
+        assert_eq!(Dpt::Float16.encode("21.5"), Ok(GroupValue { data: vec![0x0C, 0x33], small: false }));
+        assert_eq!(Dpt::Float16.encode("-1"), Ok(GroupValue { data: vec![0x87, 0x9C], small: false }));
+
+        let encoded = Dpt::Float16.encode("1250.4").unwrap();
+        assert_eq!(Dpt::Float16.decode(&encoded.data).unwrap(), "1250.56");
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+pub const PORT: u16 = 3671;
+
+pub const CONNECT_REQUEST: u16 = 0x0205;
+pub const CONNECT_RESPONSE: u16 = 0x0206;
+pub const CONNECTIONSTATE_REQUEST: u16 = 0x0207;
+pub const CONNECTIONSTATE_RESPONSE: u16 = 0x0208;
+pub const DISCONNECT_REQUEST: u16 = 0x0209;
+pub const DISCONNECT_RESPONSE: u16 = 0x020A;
+pub const TUNNELING_REQUEST: u16 = 0x0420;
+pub const TUNNELING_ACK: u16 = 0x0421;
+
+const HEADER_LENGTH: u8 = 0x06;
+const PROTOCOL_VERSION: u8 = 0x10;
+
+/// Host protocol address information for "route back" mode: the gateway answers to the address
+/// the request came from, which works behind NAT and in containers.
+const HPAI_ROUTE_BACK: [u8; 8] = [0x08, 0x01, 0, 0, 0, 0, 0, 0];
+/// Connection request information for a link layer tunnel.
+const CRI_TUNNEL_LINK_LAYER: [u8; 4] = [0x04, 0x04, 0x02, 0x00];
+
+const CEMI_L_DATA_REQ: u8 = 0x11;
+const CEMI_L_DATA_CON: u8 = 0x2E;
+const CEMI_L_DATA_IND: u8 = 0x29;
+
+const APCI_GROUP_VALUE_READ: u16 = 0x0000;
+const APCI_GROUP_VALUE_RESPONSE: u16 = 0x0040;
+const APCI_GROUP_VALUE_WRITE: u16 = 0x0080;
+
+/// A three level group address, e.g. `1/2/3`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct GroupAddress(pub u16);
+
+impl FromStr for GroupAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u16> = s.split('/')
+            .map(|part| part.trim().parse::<u16>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid group address: {}", s))?;
+
+        match parts[..] {
+            [main, middle, sub] if main < 32 && middle < 8 && sub < 256 => Ok(GroupAddress(main << 11 | middle << 8 | sub)),
+            _ => Err(format!("Invalid group address: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for GroupAddress {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for GroupAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.0 >> 11, (self.0 >> 8) & 0x07, self.0 & 0xFF)
+    }
+}
+
+/// A KNXnet/IP frame: the header's service type and the body after it.
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub service: u16,
+    pub body: Vec<u8>,
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = vec![HEADER_LENGTH, PROTOCOL_VERSION];
+        buffer.extend_from_slice(&self.service.to_be_bytes());
+        buffer.extend_from_slice(&(self.body.len() as u16 + HEADER_LENGTH as u16).to_be_bytes());
+        buffer.extend_from_slice(&self.body);
+        buffer
+    }
+
+    pub fn decode(buffer: &[u8]) -> anyhow::Result<Self> {
+        if buffer.len() < HEADER_LENGTH as usize || buffer[0] != HEADER_LENGTH || buffer[1] != PROTOCOL_VERSION {
+            bail!("Invalid KNXnet/IP header: {:02x?}", buffer);
+        }
+
+        let length = u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+        if length != buffer.len() {
+            bail!("KNXnet/IP frame length mismatch: header says {} bytes but got {}", length, buffer.len());
+        }
+
+        Ok(Self {
+            service: u16::from_be_bytes([buffer[2], buffer[3]]),
+            body: buffer[HEADER_LENGTH as usize..].to_vec(),
+        })
+    }
+
+    pub fn connect_request() -> Self {
+        let mut body = HPAI_ROUTE_BACK.to_vec();
+        body.extend_from_slice(&HPAI_ROUTE_BACK);
+        body.extend_from_slice(&CRI_TUNNEL_LINK_LAYER);
+        Self { service: CONNECT_REQUEST, body }
+    }
+
+    pub fn connection_state_request(channel: u8) -> Self {
+        let mut body = vec![channel, 0x00];
+        body.extend_from_slice(&HPAI_ROUTE_BACK);
+        Self { service: CONNECTIONSTATE_REQUEST, body }
+    }
+
+    pub fn disconnect_request(channel: u8) -> Self {
+        let mut body = vec![channel, 0x00];
+        body.extend_from_slice(&HPAI_ROUTE_BACK);
+        Self { service: DISCONNECT_REQUEST, body }
+    }
+
+    pub fn disconnect_response(channel: u8) -> Self {
+        Self { service: DISCONNECT_RESPONSE, body: vec![channel, 0x00] }
+    }
+
+    pub fn tunneling_request(channel: u8, sequence: u8, cemi: &[u8]) -> Self {
+        let mut body = vec![0x04, channel, sequence, 0x00];
+        body.extend_from_slice(cemi);
+        Self { service: TUNNELING_REQUEST, body }
+    }
+
+    pub fn tunneling_ack(channel: u8, sequence: u8) -> Self {
+        Self { service: TUNNELING_ACK, body: vec![0x04, channel, sequence, 0x00] }
+    }
+
+    /// Channel id and status of connect and connection state responses.
+    pub fn channel_and_status(&self) -> anyhow::Result<(u8, u8)> {
+        match self.body[..] {
+            [channel, status, ..] => Ok((channel, status)),
+            _ => bail!("Response is too short"),
+        }
+    }
+
+    /// Channel id, sequence number and cEMI frame of a tunneling request (or status for an ack).
+    pub fn tunneling(&self) -> anyhow::Result<(u8, u8, &[u8])> {
+        let length = *self.body.first().context("Empty tunneling frame")? as usize;
+        if length < 4 || self.body.len() < length {
+            bail!("Invalid tunneling connection header");
+        }
+
+        Ok((self.body[1], self.body[2], &self.body[length..]))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupEvent {
+    Read,
+    Response(Vec<u8>),
+    Write(Vec<u8>),
+}
+
+/// A group telegram carried in a cEMI L_Data frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupTelegram {
+    pub destination: GroupAddress,
+    pub event: GroupEvent,
+    /// Whether the telegram is the gateway's confirmation of one we sent.
+    pub confirmation: bool,
+}
+
+/// The value of a group write or response. Values of up to 6 bits are packed into the APCI
+/// byte; `data` holds them as a single byte marked `small`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupValue {
+    pub data: Vec<u8>,
+    pub small: bool,
+}
+
+impl GroupTelegram {
+    pub fn parse_cemi(cemi: &[u8]) -> anyhow::Result<Option<Self>> {
+        let message_code = *cemi.first().context("Empty cEMI frame")?;
+        if message_code != CEMI_L_DATA_IND && message_code != CEMI_L_DATA_CON {
+            return Ok(None);
+        }
+
+        let additional_info_length = *cemi.get(1).context("cEMI frame is too short")? as usize;
+        let frame = cemi.get(2 + additional_info_length..).context("cEMI frame is too short")?;
+
+        if frame.len() < 9 {
+            bail!("cEMI L_Data frame is too short: {:02x?}", cemi);
+        }
+
+        // group destinations are flagged in the second control field
+        if frame[1] & 0x80 == 0 {
+            return Ok(None);
+        }
+
+        let destination = GroupAddress(u16::from_be_bytes([frame[4], frame[5]]));
+        let length = frame[6] as usize;
+        let apdu = frame.get(7..8 + length).context("cEMI APDU is truncated")?;
+
+        let apci = u16::from_be_bytes([apdu[0], apdu[1]]) & 0x03C0;
+        let value = if length == 1 { vec![apdu[1] & 0x3F] } else { apdu[2..].to_vec() };
+
+        let event = match apci {
+            APCI_GROUP_VALUE_READ => GroupEvent::Read,
+            APCI_GROUP_VALUE_RESPONSE => GroupEvent::Response(value),
+            APCI_GROUP_VALUE_WRITE => GroupEvent::Write(value),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(Self { destination, event, confirmation: message_code == CEMI_L_DATA_CON }))
+    }
+
+    /// Builds the cEMI L_Data.req frame for a group read (`None`) or write.
+    pub fn encode_request(destination: GroupAddress, value: Option<&GroupValue>) -> Vec<u8> {
+        let mut cemi = vec![
+            CEMI_L_DATA_REQ,
+            0x00, // no additional info
+            0xBC, // standard frame, no repeat, broadcast, low priority
+            0xE0, // group address, hop count 6
+            0x00, 0x00, // source address, filled in by the gateway
+        ];
+        cemi.extend_from_slice(&destination.0.to_be_bytes());
+
+        match value {
+            None => cemi.extend_from_slice(&[0x01, 0x00, APCI_GROUP_VALUE_READ as u8]),
+            Some(GroupValue { data, small: true }) => {
+                cemi.extend_from_slice(&[0x01, 0x00, APCI_GROUP_VALUE_WRITE as u8 | (data[0] & 0x3F)]);
+            }
+            Some(GroupValue { data, small: false }) => {
+                cemi.extend_from_slice(&[data.len() as u8 + 1, 0x00, APCI_GROUP_VALUE_WRITE as u8]);
+                cemi.extend_from_slice(data);
+            }
+        }
+
+        cemi
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::knx::{Frame, GroupAddress, GroupEvent, GroupTelegram, GroupValue, TUNNELING_REQUEST};
+
+    #[test]
+    fn test_group_address() {
+        let address = GroupAddress::from_str("1/2/3").unwrap();
+
+        assert_eq!(address, GroupAddress(0x0A03));
+        assert_eq!(address.to_string(), "1/2/3");
+        assert!(GroupAddress::from_str("32/0/0").is_err());
+        assert!(GroupAddress::from_str("1/2").is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_frame() {
+        let frame = Frame::tunneling_request(7, 1, &[0x11, 0x00]);
+        let encoded = frame.encode();
+
+        assert_eq!(encoded, [0x06, 0x10, 0x04, 0x20, 0x00, 0x0C, 0x04, 0x07, 0x01, 0x00, 0x11, 0x00]);
+
+        let decoded = Frame::decode(&encoded).unwrap();
+        assert_eq!(decoded.service, TUNNELING_REQUEST);
+        assert_eq!(decoded.tunneling().unwrap(), (7, 1, &[0x11, 0x00][..]));
+
+        assert!(Frame::decode(&encoded[..10]).is_err());
+    }
+
+    #[test]
+    fn test_parse_group_write() {
+        // L_Data.ind from 1.1.5 to 0/0/1: write on (small value)
+        let cemi = [0x29, 0x00, 0xBC, 0xE0, 0x11, 0x05, 0x00, 0x01, 0x01, 0x00, 0x81];
+
+        assert_eq!(GroupTelegram::parse_cemi(&cemi).unwrap(), Some(GroupTelegram {
+            destination: GroupAddress(0x0001),
+            event: GroupEvent::Write(vec![0x01]),
+            confirmation: false,
+        }));
+
+        // L_Data.ind to 3/1/1: response with a 2-byte float
+        let cemi = [0x29, 0x00, 0xBC, 0xE0, 0x11, 0x05, 0x19, 0x01, 0x03, 0x00, 0x40, 0x0C, 0x1A];
+
+        assert_eq!(GroupTelegram::parse_cemi(&cemi).unwrap().unwrap().event, GroupEvent::Response(vec![0x0C, 0x1A]));
+    }
+
+    #[test]
+    fn test_encode_request() {
+        let address = GroupAddress(0x0A03);
+
+        assert_eq!(GroupTelegram::encode_request(address, None),
+                   [0x11, 0x00, 0xBC, 0xE0, 0x00, 0x00, 0x0A, 0x03, 0x01, 0x00, 0x00]);
+        assert_eq!(GroupTelegram::encode_request(address, Some(&GroupValue { data: vec![1], small: true })),
+                   [0x11, 0x00, 0xBC, 0xE0, 0x00, 0x00, 0x0A, 0x03, 0x01, 0x00, 0x81]);
+        assert_eq!(GroupTelegram::encode_request(address, Some(&GroupValue { data: vec![0xFF], small: false })),
+                   [0x11, 0x00, 0xBC, 0xE0, 0x00, 0x00, 0x0A, 0x03, 0x02, 0x00, 0x80, 0xFF]);
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{info, warn};
+
+use crate::application::Application;
+use crate::config::Config;
+use crate::mqtt::connect_mqtt;
+
+mod knx;
+mod dpt;
+mod tunnel;
+mod config;
+mod application;
+mod mqtt;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/knx";
+const MQTT_SET_TOPIC: &str = "smart-home-system/knx/+/+/set";
+const MQTT_GET_TOPIC: &str = "smart-home-system/knx/+/+/get";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/knx/error";
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [MQTT_SET_TOPIC, MQTT_GET_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let config_path: PathBuf = std::env::var("KNX_CONFIG").unwrap_or_else(|_| "knx.yaml".into()).into();
+    let config = Config::load(&config_path)?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting KNX controller");
+
+    let mut application = Application::new(client, config).await;
+
+    info!("Waiting for mqtt messages...");
+
+    let mut heartbeat_interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    application.handle_mqtt_message(&message).await;
+                }
+            }
+            telegram = application.next_telegram() => match telegram {
+                Ok(telegram) => application.handle_telegram(telegram),
+                Err(e) => {
+                    warn!("Lost connection to KNX gateway: {}. Reconnecting...", e);
+                    application.reconnect().await;
+                }
+            },
+            _ = heartbeat_interval.tick() => application.heartbeat().await,
+        }
+    }
+
+    application.disconnect().await;
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use log::{debug, info, warn};
+use tokio::net::UdpSocket;
+
+use crate::knx::{self, Frame, GroupAddress, GroupTelegram, GroupValue};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECTIONSTATE_TIMEOUT: Duration = Duration::from_secs(10);
+const TUNNELING_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A KNXnet/IP tunnelling connection to a KNX IP interface or router.
+pub struct Tunnel {
+    socket: UdpSocket,
+    channel: u8,
+    send_sequence: u8,
+    receive_sequence: Option<u8>,
+    /// Telegrams received while waiting for the answer to a request.
+    pending: VecDeque<GroupTelegram>,
+}
+
+impl Tunnel {
+    pub async fn connect(gateway: &str) -> anyhow::Result<Self> {
+        let address = if gateway.contains(':') {
+            gateway.to_string()
+        } else {
+            format!("{}:{}", gateway, knx::PORT)
+        };
+
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(&address).await.with_context(|| format!("Failed to resolve gateway {}", address))?;
+
+        let mut tunnel = Self { socket, channel: 0, send_sequence: 0, receive_sequence: None, pending: VecDeque::new() };
+
+        let response = tunnel.exchange(Frame::connect_request(), CONNECT_TIMEOUT, |frame| frame.service == knx::CONNECT_RESPONSE)
+            .await
+            .context("Gateway did not answer the connect request")?;
+
+        match response.channel_and_status()? {
+            (channel, 0x00) => tunnel.channel = channel,
+            (_, 0x24) => bail!("Gateway has no free tunnelling connections"),
+            (_, status) => bail!("Gateway refused the connection with status {:#04x}", status),
+        }
+
+        info!("Opened tunnel to KNX gateway {} on channel {}", address, tunnel.channel);
+
+        Ok(tunnel)
+    }
+
+    async fn send(&self, frame: Frame) -> anyhow::Result<()> {
+        debug!("Sending: {:?}", frame);
+        self.socket.send(&frame.encode()).await?;
+        Ok(())
+    }
+
+    /// Sends a frame and waits for the response matching `predicate`, keeping the telegrams
+    /// that arrive in the meantime for [`Tunnel::recv`].
+    async fn exchange(&mut self, frame: Frame, timeout: Duration, predicate: impl Fn(&Frame) -> bool) -> anyhow::Result<Frame> {
+        self.send(frame).await?;
+
+        tokio::time::timeout(timeout, async {
+            loop {
+                let frame = self.receive_frame().await?;
+
+                if predicate(&frame) {
+                    return Ok(frame);
+                }
+
+                if let Some(telegram) = self.handle_frame(&frame).await? {
+                    self.pending.push_back(telegram);
+                }
+            }
+        }).await.context("Timed out")?
+    }
+
+    async fn receive_frame(&self) -> anyhow::Result<Frame> {
+        let mut buffer = [0u8; 512];
+
+        loop {
+            let length = self.socket.recv(&mut buffer).await?;
+
+            match Frame::decode(&buffer[..length]) {
+                Ok(frame) => {
+                    debug!("Received: {:?}", frame);
+                    return Ok(frame);
+                }
+                Err(e) => warn!("Received invalid frame: {}", e),
+            }
+        }
+    }
+
+    /// Acknowledges incoming tunnelling requests and returns the group telegram they carry.
+    async fn handle_frame(&mut self, frame: &Frame) -> anyhow::Result<Option<GroupTelegram>> {
+        match frame.service {
+            knx::TUNNELING_REQUEST => {
+                let (channel, sequence, cemi) = frame.tunneling()?;
+                if channel != self.channel {
+                    return Ok(None);
+                }
+
+                self.send(Frame::tunneling_ack(channel, sequence)).await?;
+
+                // the gateway repeats requests whose ack it missed
+                if self.receive_sequence == Some(sequence) {
+                    return Ok(None);
+                }
+                self.receive_sequence = Some(sequence);
+
+                Ok(GroupTelegram::parse_cemi(cemi)?.filter(|telegram| !telegram.confirmation))
+            }
+            knx::DISCONNECT_REQUEST => {
+                self.send(Frame::disconnect_response(self.channel)).await?;
+                bail!("Gateway closed the tunnel")
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Waits for the next group telegram on the bus. Fails once the tunnel is broken.
+    pub async fn recv(&mut self) -> anyhow::Result<GroupTelegram> {
+        if let Some(telegram) = self.pending.pop_front() {
+            return Ok(telegram);
+        }
+
+        loop {
+            let frame = self.receive_frame().await?;
+
+            if let Some(telegram) = self.handle_frame(&frame).await? {
+                return Ok(telegram);
+            }
+        }
+    }
+
+    /// Sends a group read (`None`) or write. The answer to a read arrives as a group response.
+    pub async fn send_group(&mut self, destination: GroupAddress, value: Option<&GroupValue>) -> anyhow::Result<()> {
+        let cemi = GroupTelegram::encode_request(destination, value);
+        let sequence = self.send_sequence;
+        let channel = self.channel;
+
+        let is_ack = |frame: &Frame| {
+            frame.service == knx::TUNNELING_ACK && frame.tunneling().is_ok_and(|(ack_channel, ack_sequence, _)| {
+                ack_channel == channel && ack_sequence == sequence
+            })
+        };
+
+        // a request whose ack doesn't arrive is repeated once before giving up on the tunnel
+        let ack = match self.exchange(Frame::tunneling_request(channel, sequence, &cemi), TUNNELING_ACK_TIMEOUT, is_ack).await {
+            Ok(ack) => ack,
+            Err(e) => {
+                debug!("No ack for telegram to {}: {}. Repeating...", destination, e);
+                self.exchange(Frame::tunneling_request(channel, sequence, &cemi), TUNNELING_ACK_TIMEOUT, is_ack).await
+                    .context("Gateway did not acknowledge the telegram")?
+            }
+        };
+
+        self.send_sequence = self.send_sequence.wrapping_add(1);
+
+        match ack.body.get(3) {
+            Some(0x00) => Ok(()),
+            status => bail!("Gateway rejected the telegram with status {:02x?}", status),
+        }
+    }
+
+    /// Checks that the gateway still knows the connection. Gateways drop connections that go two
+    /// minutes without one of these.
+    pub async fn heartbeat(&mut self) -> anyhow::Result<()> {
+        let response = self.exchange(Frame::connection_state_request(self.channel), CONNECTIONSTATE_TIMEOUT, |frame| {
+            frame.service == knx::CONNECTIONSTATE_RESPONSE
+        }).await?;
+
+        match response.channel_and_status()? {
+            (_, 0x00) => Ok(()),
+            (_, status) => bail!("Gateway reported connection status {:#04x}", status),
+        }
+    }
+
+    pub async fn disconnect(&mut self) {
+        let channel = self.channel;
+
+        if let Err(e) = self.exchange(Frame::disconnect_request(channel), CONNECT_TIMEOUT, |frame| frame.service == knx::DISCONNECT_RESPONSE).await {
+            warn!("Failed to close the tunnel: {}", e);
+        }
+    }
+}
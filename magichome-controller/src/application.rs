@@ -0,0 +1,148 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use domain_state::{error, Brightness, Power, Rgb};
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::{discovery, MQTT_BRIGHTNESS_PUBLISH_TOPIC, MQTT_COLOR_PUBLISH_TOPIC, MQTT_ERROR_TOPIC, MQTT_POWER_PUBLISH_TOPIC};
+use crate::magichome::{self, Command, Device, State};
+
+pub struct Application {
+    client: AsyncClient,
+    device: Device,
+    last_state: Option<State>,
+}
+
+#[derive(Debug)]
+pub struct DeviceFilters {
+    pub id: Option<String>,
+    pub model: Option<String>,
+}
+
+impl DeviceFilters {
+    fn matches(&self, device: &discovery::DiscoveryResponse) -> bool {
+        self.id.iter().all(|id| device.id.eq_ignore_ascii_case(id)) &&
+            self.model.iter().all(|model| device.model == *model)
+    }
+}
+
+impl Application {
+    pub async fn new(client: AsyncClient, filter: DeviceFilters) -> Self {
+        let device = Self::find_device(filter).await;
+        Self { client, device, last_state: None }
+    }
+
+    pub async fn find_device(filter: DeviceFilters) -> Device {
+        loop {
+            let result = discovery::discover(Duration::from_secs(3)).await;
+            match result {
+                Ok(discovery) => {
+                    let device = discovery.into_iter().find(|device| filter.matches(device));
+
+                    if let Some(device) = device {
+                        let address = format!("{}:{}", device.address, magichome::PORT);
+                        info!("Using magichome device at {}...", address);
+                        return Device::new(address);
+                    } else {
+                        warn!("No magichome device found matching filter {filter:?}. Retrying in 30 seconds...");
+                    }
+                }
+                Err(e) => warn!("Magichome discovery failed: {}. Retrying in 30 seconds...", e)
+            }
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+
+    pub async fn handle_mqtt_set_power(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        if let Ok(power) = Power::from_str(&payload) {
+            info!("[{}] Setting magichome device power to: {:?}", message.topic(), power);
+            self.send(Command::SetPower(power)).await;
+            return;
+        }
+
+        error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected on or off");
+    }
+
+    pub async fn handle_mqtt_set_color(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        match Rgb::from_str(&payload) {
+            Ok(color) => {
+                info!("[{}] Setting magichome device color to: {}", message.topic(), color);
+                self.send(Command::SetColor(color)).await;
+            }
+            Err(e) => error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string()),
+        }
+    }
+
+    pub async fn handle_mqtt_set_brightness(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        if let Ok(brightness) = payload.parse::<u8>() {
+            let brightness = Brightness::new(brightness.clamp(1, 100));
+
+            let color = match &self.last_state {
+                Some(state) => state.color,
+                None => Rgb::WHITE,
+            };
+
+            info!("[{}] Setting magichome device brightness to: {}", message.topic(), brightness);
+            self.send(Command::SetColor(color.with_brightness(brightness))).await;
+            return;
+        }
+
+        error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a brightness from 0 to 255");
+    }
+
+    pub async fn handle_mqtt_get_state(&mut self) {
+        self.last_state = None;
+        self.poll().await;
+    }
+
+    /// Queries the controller and publishes every value that changed since the last poll.
+    pub async fn poll(&mut self) {
+        let state = match self.device.query_state().await {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to query magichome device state: {}", e);
+                return;
+            }
+        };
+
+        let last = self.last_state.as_ref();
+
+        if last.map(|last| last.power) != Some(state.power) {
+            info!("Magichome device power is: {:?}", state.power);
+            mqtt_publish(&self.client, MQTT_POWER_PUBLISH_TOPIC, state.power.to_string());
+        }
+
+        if last.map(|last| last.color) != Some(state.color) {
+            info!("Magichome device color is: {}", state.color);
+            mqtt_publish(&self.client, MQTT_COLOR_PUBLISH_TOPIC, state.color.to_string());
+        }
+
+        if last.map(|last| last.color.brightness()) != Some(state.color.brightness()) {
+            info!("Magichome device brightness is: {}", state.color.brightness());
+            mqtt_publish(&self.client, MQTT_BRIGHTNESS_PUBLISH_TOPIC, state.color.brightness().to_string());
+        }
+
+        self.last_state = Some(state);
+    }
+
+    async fn send(&mut self, command: Command) {
+        if let Err(e) = self.device.send(command).await {
+            error!("Failed to send command to magichome device: {}", e);
+            return;
+        }
+
+        self.poll().await;
+    }
+}
+
+fn mqtt_publish(client: &AsyncClient, topic: &str, value: String) {
+    let message = Message::new_retained(topic, value, 1);
+    client.publish(message);
+}
@@ -0,0 +1,85 @@
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info};
+use tokio::net::UdpSocket;
+
+const DISCOVERY_PORT: u16 = 48899;
+const DISCOVERY_MESSAGE: &[u8] = b"HF-A11ASSISTHREAD";
+
+#[derive(Debug, PartialEq)]
+pub struct DiscoveryResponse {
+    pub address: Ipv4Addr,
+    pub id: String,
+    pub model: String,
+}
+
+/// Responses look like `192.168.1.20,ACCF23123456,AK001-ZJ200`.
+fn parse(response: &[u8]) -> anyhow::Result<DiscoveryResponse> {
+    let response = std::str::from_utf8(response)?.trim();
+    let mut fields = response.split(',');
+
+    Ok(DiscoveryResponse {
+        address: fields.next().context("No address found in response")?.parse()?,
+        id: fields.next().context("No id found in response")?.to_string(),
+        model: fields.next().context("No model found in response")?.to_string(),
+    })
+}
+
+pub async fn discover(timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse>> {
+    let socket = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+
+    socket.send_to(DISCOVERY_MESSAGE, SocketAddrV4::new(Ipv4Addr::BROADCAST, DISCOVERY_PORT)).await?;
+    info!("Discovering on {} with timeout {timeout:?}", socket.local_addr()?);
+
+    let mut buf = [0; 256];
+    let mut responses = Vec::new();
+
+    let discover = async {
+        loop {
+            if let Ok(len) = socket.recv(&mut buf).await {
+                // The devices echo our probe back on some networks.
+                if &buf[..len] == DISCOVERY_MESSAGE {
+                    continue;
+                }
+
+                match parse(&buf[..len]) {
+                    Ok(discovery) => {
+                        if responses.contains(&discovery) {
+                            continue;
+                        }
+
+                        info!("Found magichome device: {:?}", discovery);
+                        responses.push(discovery);
+                    }
+                    Err(err) => error!("Failed to parse discovery response: {}", err),
+                }
+            }
+        }
+    };
+
+    let _ = tokio::time::timeout(timeout, discover).await;
+
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::discovery::parse;
+
+    #[test]
+    fn test_parse_discovery_response() {
+        let response = parse(b"192.168.1.20,ACCF23123456,AK001-ZJ200").unwrap();
+
+        assert_eq!(response.address, Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(response.id, "ACCF23123456");
+        assert_eq!(response.model, "AK001-ZJ200");
+
+        assert!(parse(b"192.168.1.20,ACCF23123456").is_err());
+        assert!(parse(b"not-an-ip,ACCF23123456,AK001").is_err());
+    }
+}
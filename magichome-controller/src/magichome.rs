@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use domain_state::{Power, Rgb};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const PORT: u16 = 5577;
+
+const STATE_RESPONSE_LENGTH: usize = 14;
+const POWER_ON: u8 = 0x23;
+const POWER_OFF: u8 = 0x24;
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct State {
+    pub power: Power,
+    pub color: Rgb,
+    pub warm_white: u8,
+}
+
+impl State {
+    pub fn parse(response: &[u8]) -> anyhow::Result<State> {
+        if response.len() != STATE_RESPONSE_LENGTH || response[0] != 0x81 {
+            anyhow::bail!("Invalid state response: {:02x?}", response);
+        }
+
+        let (payload, checksum) = response.split_at(STATE_RESPONSE_LENGTH - 1);
+        if checksum_of(payload) != checksum[0] {
+            anyhow::bail!("Invalid checksum in state response: {:02x?}", response);
+        }
+
+        Ok(State {
+            power: if response[2] == POWER_ON { Power::On } else { Power::Off },
+            color: Rgb::new(response[6], response[7], response[8]),
+            warm_white: response[9],
+        })
+    }
+}
+
+pub enum Command {
+    QueryState,
+    SetPower(Power),
+    SetColor(Rgb),
+}
+
+impl Command {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = match self {
+            Command::QueryState => vec![0x81, 0x8A, 0x8B],
+            Command::SetPower(Power::On) => vec![0x71, POWER_ON, 0x0F],
+            Command::SetPower(Power::Off) => vec![0x71, POWER_OFF, 0x0F],
+            Command::SetColor(color) => vec![0x31, color.red, color.green, color.blue, 0x00, 0xF0, 0x0F],
+        };
+
+        bytes.push(checksum_of(&bytes));
+        bytes
+    }
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+pub struct Device {
+    address: String,
+    stream: Option<TcpStream>,
+}
+
+impl Device {
+    pub fn new(address: String) -> Self {
+        Self { address, stream: None }
+    }
+
+    pub async fn query_state(&mut self) -> anyhow::Result<State> {
+        // Some firmwares acknowledge set commands, drop those bytes so they aren't read as the state.
+        if let Some(stream) = &self.stream {
+            let mut discard = [0; 64];
+            while matches!(stream.try_read(&mut discard), Ok(length) if length > 0) {}
+        }
+
+        self.send(Command::QueryState).await?;
+
+        let stream = self.stream.as_mut().context("Not connected")?;
+        let mut response = [0; STATE_RESPONSE_LENGTH];
+
+        let read = tokio::time::timeout(RESPONSE_TIMEOUT, stream.read_exact(&mut response)).await;
+        match read {
+            Ok(Ok(_)) => State::parse(&response),
+            Ok(Err(e)) => {
+                self.stream = None;
+                Err(e.into())
+            }
+            Err(_) => {
+                self.stream = None;
+                anyhow::bail!("Timed out waiting for state response")
+            }
+        }
+    }
+
+    pub async fn send(&mut self, command: Command) -> anyhow::Result<()> {
+        if self.stream.is_none() {
+            let stream = tokio::time::timeout(RESPONSE_TIMEOUT, TcpStream::connect(&self.address)).await
+                .context("Timed out connecting to device")??;
+            self.stream = Some(stream);
+        }
+
+        let stream = self.stream.as_mut().unwrap();
+
+        if let Err(e) = stream.write_all(&command.encode()).await {
+            // The controllers close idle connections, so the next command reconnects.
+            self.stream = None;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain_state::{Power, Rgb};
+
+    use crate::magichome::{Command, State};
+
+    #[test]
+    fn test_command_encode() {
+        assert_eq!(Command::QueryState.encode(), vec![0x81, 0x8A, 0x8B, 0x96]);
+        assert_eq!(Command::SetPower(Power::On).encode(), vec![0x71, 0x23, 0x0F, 0xA3]);
+        assert_eq!(Command::SetPower(Power::Off).encode(), vec![0x71, 0x24, 0x0F, 0xA4]);
+        assert_eq!(Command::SetColor(Rgb::new(255, 0, 0)).encode(),
+                   vec![0x31, 0xFF, 0x00, 0x00, 0x00, 0xF0, 0x0F, 0x2F]);
+    }
+
+    #[test]
+    fn test_state_parse() {
+        let mut response = vec![0x81, 0x25, 0x23, 0x61, 0x21, 0x10, 0xFF, 0x80, 0x00, 0x00, 0x05, 0x00, 0xF0];
+        response.push(response.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+
+        let state = State::parse(&response).unwrap();
+
+        assert_eq!(state.power, Power::On);
+        assert_eq!(state.color, Rgb::new(255, 128, 0));
+
+        response[13] = response[13].wrapping_add(1);
+        assert!(State::parse(&response).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info};
+
+use crate::application::{Application, DeviceFilters};
+use crate::mqtt::connect_mqtt;
+
+mod magichome;
+mod application;
+mod mqtt;
+mod discovery;
+
+const MQTT_SET_BRIGHTNESS_TOPIC: &str = "smart-home-system/magichome/brightness/set";
+const MQTT_GET_BRIGHTNESS_TOPIC: &str = "smart-home-system/magichome/brightness/get";
+const MQTT_BRIGHTNESS_PUBLISH_TOPIC: &str = "smart-home-system/magichome/brightness";
+const MQTT_SET_POWER_TOPIC: &str = "smart-home-system/magichome/power/set";
+const MQTT_GET_POWER_TOPIC: &str = "smart-home-system/magichome/power/get";
+const MQTT_POWER_PUBLISH_TOPIC: &str = "smart-home-system/magichome/power";
+const MQTT_SET_COLOR_TOPIC: &str = "smart-home-system/magichome/color/set";
+const MQTT_GET_COLOR_TOPIC: &str = "smart-home-system/magichome/color/get";
+const MQTT_COLOR_PUBLISH_TOPIC: &str = "smart-home-system/magichome/color";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/magichome/error";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [
+        MQTT_SET_POWER_TOPIC,
+        MQTT_SET_BRIGHTNESS_TOPIC,
+        MQTT_SET_COLOR_TOPIC,
+        MQTT_GET_POWER_TOPIC,
+        MQTT_GET_BRIGHTNESS_TOPIC,
+        MQTT_GET_COLOR_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting magichome controller");
+
+    let mut application = Application::new(client, DeviceFilters {
+        id: std::env::var("MAGICHOME_ID").ok(),
+        model: std::env::var("MAGICHOME_MODEL").ok(),
+    }).await;
+
+    info!("Found magichome device.");
+
+    info!("Waiting for mqtt messages...");
+
+    let mut poll_interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    match message.topic() {
+                        MQTT_SET_POWER_TOPIC => application.handle_mqtt_set_power(&message).await,
+                        MQTT_SET_BRIGHTNESS_TOPIC => application.handle_mqtt_set_brightness(&message).await,
+                        MQTT_SET_COLOR_TOPIC => application.handle_mqtt_set_color(&message).await,
+                        MQTT_GET_POWER_TOPIC | MQTT_GET_BRIGHTNESS_TOPIC | MQTT_GET_COLOR_TOPIC =>
+                            application.handle_mqtt_get_state().await,
+                        _ => error!("Received message for unknown topic: {}", message.topic()),
+                    }
+                }
+            }
+            _ = poll_interval.tick() => application.poll().await,
+        }
+    }
+
+    Ok(())
+}
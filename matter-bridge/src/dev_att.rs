@@ -0,0 +1,49 @@
+use std::path::Path;
+
+use anyhow::Context;
+use rs_matter::data_model::sdm::dev_att::{DataType, DevAttDataFetcher};
+use rs_matter::error::{Error, ErrorCode};
+
+/// The device attestation credentials the Matter controllers check when the bridge is commissioned, read from
+/// the files of a directory, e.g. the development ones of `credentials/development` of connectedhomeip:
+/// `cd.der`, `pai.der`, `dac.der`, `dac-public-key.bin` and `dac-private-key.bin`.
+pub struct FileDevAtt {
+    certification_declaration: Vec<u8>,
+    pai: Vec<u8>,
+    dac: Vec<u8>,
+    dac_public_key: Vec<u8>,
+    dac_private_key: Vec<u8>,
+}
+
+impl FileDevAtt {
+    pub fn load(directory: &Path) -> anyhow::Result<Self> {
+        let read = |name: &str| {
+            let path = directory.join(name);
+            std::fs::read(&path).context(format!("Failed to read the Matter credentials from {:?}", path))
+        };
+
+        Ok(Self {
+            certification_declaration: read("cd.der")?,
+            pai: read("pai.der")?,
+            dac: read("dac.der")?,
+            dac_public_key: read("dac-public-key.bin")?,
+            dac_private_key: read("dac-private-key.bin")?,
+        })
+    }
+}
+
+impl DevAttDataFetcher for FileDevAtt {
+    fn get_devatt_data(&self, data_type: DataType, data: &mut [u8]) -> Result<usize, Error> {
+        let src = match data_type {
+            DataType::CertDeclaration => &self.certification_declaration,
+            DataType::PAI => &self.pai,
+            DataType::DAC => &self.dac,
+            DataType::DACPubKey => &self.dac_public_key,
+            DataType::DACPrivKey => &self.dac_private_key,
+        };
+
+        let dst = data.get_mut(..src.len()).ok_or(ErrorCode::NoSpace)?;
+        dst.copy_from_slice(src);
+        Ok(src.len())
+    }
+}
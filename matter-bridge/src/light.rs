@@ -0,0 +1,68 @@
+use domain_state::{Power, Topic};
+use paho_mqtt::Message;
+
+/// A light controlled by its controller on mqtt, exposed as an on/off light endpoint of the Matter node.
+pub struct Light {
+    pub name: &'static str,
+    pub endpoint: u16,
+    pub topic: Topic,
+}
+
+impl Light {
+    fn new(name: &'static str, endpoint: u16, device: &str) -> Self {
+        Self { name, endpoint, topic: Topic::device(device) }
+    }
+}
+
+/// The lights exposed over Matter, the same ones the homekit bridge exposes. The endpoints are the ones of the
+/// node of `matter.rs`.
+pub fn lights() -> Vec<Light> {
+    vec![
+        Light::new("yeelight", 1, "yeelight"),
+        Light::new("living-room-light", 2, "knx/living-room-light"),
+    ]
+}
+
+/// The endpoint and the power of the light a message on the power topic of its controller is about.
+pub fn state(lights: &[Light], message: &Message) -> Option<(u16, bool)> {
+    let light = lights.iter().find(|light| light.topic.clone().power() == *message.topic())?;
+    let power: Power = message.payload_str().parse().ok()?;
+    Some((light.endpoint, power.is_on()))
+}
+
+/// The command to the controller of the light of the endpoint, for the power set by a Matter controller.
+pub fn command(lights: &[Light], endpoint: u16, on: bool) -> Option<Message> {
+    let light = lights.iter().find(|light| light.endpoint == endpoint)?;
+    Some(Message::new(light.topic.clone().power().set().to_string(), Power::from(on).to_string(), 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use paho_mqtt::Message;
+
+    use crate::light::{command, lights, state};
+
+    #[test]
+    fn test_state() {
+        let lights = lights();
+
+        let message = Message::new("smart-home-system/knx/living-room-light/power", "on", 1);
+        assert_eq!(state(&lights, &message), Some((2, true)));
+        let message = Message::new("smart-home-system/yeelight/power", "off", 1);
+        assert_eq!(state(&lights, &message), Some((1, false)));
+
+        assert_eq!(state(&lights, &Message::new("smart-home-system/yeelight/power", "dim", 1)), None);
+        assert_eq!(state(&lights, &Message::new("smart-home-system/yeelight/brightness", "40", 1)), None);
+    }
+
+    #[test]
+    fn test_command() {
+        let lights = lights();
+
+        let message = command(&lights, 1, true).unwrap();
+        assert_eq!(message.topic(), "smart-home-system/yeelight/power/set");
+        assert_eq!(message.payload_str(), "on");
+
+        assert!(command(&lights, 3, true).is_none());
+    }
+}
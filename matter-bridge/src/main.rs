@@ -0,0 +1,103 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context};
+use log::{error, info, warn};
+use paho_mqtt::Message;
+
+use crate::dev_att::FileDevAtt;
+use crate::mqtt::connect_mqtt;
+
+mod dev_att;
+mod light;
+mod matter;
+mod mqtt;
+
+/// The stack of the thread running the Matter stack, which keeps its buffers on it.
+const MATTER_STACK_SIZE: usize = 512 * 1024;
+
+/// The number of the env var, or the default if it isn't set.
+fn env_number<T: FromStr>(name: &str, default: T) -> anyhow::Result<T> where T::Err: Display {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().map_err(|e| anyhow!("Invalid {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let lights = light::lights();
+    let subscribe_topics: Vec<String> = lights.iter().map(|light| light.topic.clone().power().to_string()).collect();
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let credentials_path = std::env::var("MATTER_CREDENTIALS_PATH")
+        .context("No Matter credentials provided. Set env MATTER_CREDENTIALS_PATH to the directory of the device attestation credentials.")?;
+
+    // The passcode and discriminator of the Matter examples if not set
+    let config = matter::Config {
+        passcode: env_number("MATTER_PASSCODE", 20202021)?,
+        discriminator: env_number("MATTER_DISCRIMINATOR", 3840)?,
+        credentials: FileDevAtt::load(&PathBuf::from(credentials_path))?,
+        storage_path: std::env::var("MATTER_STORAGE_PATH").unwrap_or_else(|_| "matter-bridge".into()).into(),
+    };
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting matter bridge");
+
+    let (states, state_receiver) = async_channel::bounded(16);
+    let (command_sender, commands) = async_channel::bounded(16);
+    std::thread::Builder::new()
+        .name("matter".into())
+        .stack_size(MATTER_STACK_SIZE)
+        .spawn(move || {
+            // The channels are closed when it returns, which stops the bridge
+            if let Err(e) = matter::run(config, state_receiver, command_sender) {
+                error!("The Matter stack failed: {:?}", e);
+            }
+        })
+        .context("Failed to start the Matter stack")?;
+
+    for light in &lights {
+        client.publish(Message::new(light.topic.clone().power().get().to_string(), "", 1));
+    }
+
+    info!("Waiting for mqtt messages...");
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    match light::state(&lights, &message) {
+                        Some(state) => if states.send(state).await.is_err() {
+                            bail!("The Matter stack stopped");
+                        },
+                        None => warn!("[{}] Received invalid payload: '{}'", message.topic(), message.payload_str()),
+                    }
+                }
+            }
+            command = commands.recv() => {
+                let Ok((endpoint, on)) = command else { bail!("The Matter stack stopped") };
+
+                if let Some(message) = light::command(&lights, endpoint, on) {
+                    info!("[{}] Setting power to {} for a Matter controller", message.topic(), message.payload_str());
+                    client.publish(message);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
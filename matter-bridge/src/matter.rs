@@ -0,0 +1,164 @@
+use core::borrow::Borrow;
+use core::pin::pin;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::path::PathBuf;
+
+use async_channel::{Receiver, Sender};
+use futures_lite::FutureExt;
+use log::{info, warn};
+use nix::ifaddrs::getifaddrs;
+use nix::net::if_::{if_nametoindex, InterfaceFlags};
+use rs_matter::core::{CommissioningData, Matter};
+use rs_matter::data_model::cluster_basic_information::BasicInfoConfig;
+use rs_matter::data_model::cluster_on_off::{self, OnOffCluster};
+use rs_matter::data_model::device_types::DEV_TYPE_ON_OFF_LIGHT;
+use rs_matter::data_model::objects::*;
+use rs_matter::data_model::root_endpoint;
+use rs_matter::data_model::system_model::descriptor::{self, DescriptorCluster};
+use rs_matter::error::{Error, ErrorCode};
+use rs_matter::interaction_model::core::Transaction;
+use rs_matter::mdns::{MdnsRunBuffers, MdnsService};
+use rs_matter::persist::Psm;
+use rs_matter::secure_channel::spake2p::VerifierData;
+use rs_matter::tlv::TLVElement;
+use rs_matter::transport::core::RunBuffers;
+use rs_matter::transport::network::NetworkStack;
+use rs_matter::utils::rand::Rand;
+
+use crate::dev_att::FileDevAtt;
+
+/// The root endpoint and an on/off light endpoint for each light of `light.rs`.
+const NODE: Node<'static> = Node {
+    id: 0,
+    endpoints: &[root_endpoint::endpoint(0), light_endpoint(1), light_endpoint(2)],
+};
+
+const fn light_endpoint(id: EndptId) -> Endpoint<'static> {
+    Endpoint { id, device_type: DEV_TYPE_ON_OFF_LIGHT, clusters: &[descriptor::CLUSTER, cluster_on_off::CLUSTER] }
+}
+
+/// How the bridge is commissioned by the Matter controllers, e.g. with the pairing code asked by Google Home.
+pub struct Config {
+    pub passcode: u32,
+    pub discriminator: u16,
+    pub credentials: FileDevAtt,
+    /// Where the fabrics the bridge was commissioned to are kept.
+    pub storage_path: PathBuf,
+}
+
+/// The on/off cluster of a light, sending the power set by the Matter controllers to its controller. The state
+/// of the cluster is then set from the one the controller publishes, like the other changes of the light.
+struct LightCluster {
+    endpoint: EndptId,
+    cluster: OnOffCluster,
+    commands: Sender<(EndptId, bool)>,
+}
+
+impl LightCluster {
+    fn new(endpoint: EndptId, rand: Rand, commands: Sender<(EndptId, bool)>) -> Self {
+        Self { endpoint, cluster: OnOffCluster::new(rand), commands }
+    }
+}
+
+impl Handler for LightCluster {
+    fn read(&self, attr: &AttrDetails, encoder: AttrDataEncoder) -> Result<(), Error> {
+        self.cluster.read(attr, encoder)
+    }
+
+    fn write(&self, attr: &AttrDetails, data: AttrData) -> Result<(), Error> {
+        self.cluster.write(attr, data)
+    }
+
+    fn invoke(&self, transaction: &mut Transaction, cmd: &CmdDetails, data: &TLVElement, encoder: CmdDataEncoder) -> Result<(), Error> {
+        self.cluster.invoke(transaction, cmd, data, encoder)?;
+
+        if self.commands.try_send((self.endpoint, self.cluster.get())).is_err() {
+            warn!("Dropped the power set on endpoint {} by a Matter controller, mqtt is too slow", self.endpoint);
+        }
+        Ok(())
+    }
+}
+
+impl NonBlockingHandler for LightCluster {}
+
+/// The addresses announced by mdns, the ones of the first interface that's up with an ipv4 and an ipv6 address.
+fn network() -> Result<(Ipv4Addr, Ipv6Addr, u32), Error> {
+    let interfaces: Vec<_> = getifaddrs().map_err(|_| ErrorCode::NoNetworkInterface)?
+        .filter(|interface| {
+            interface.flags.contains(InterfaceFlags::IFF_UP)
+                && !interface.flags.intersects(InterfaceFlags::IFF_LOOPBACK | InterfaceFlags::IFF_POINTOPOINT)
+        })
+        .collect();
+
+    for interface in &interfaces {
+        let addresses = || interfaces.iter()
+            .filter(|other| other.interface_name == interface.interface_name)
+            .filter_map(|other| other.address);
+
+        let ipv4 = addresses().find_map(|address| address.as_sockaddr_in().map(|address| *SocketAddrV4::from(*address).ip()));
+        let ipv6 = addresses().find_map(|address| address.as_sockaddr_in6().map(|address| *SocketAddrV6::from(*address).ip()));
+
+        if let (Some(ipv4), Some(ipv6)) = (ipv4, ipv6) {
+            let index = if_nametoindex(interface.interface_name.as_str()).map_err(|_| ErrorCode::NoNetworkInterface)?;
+            info!("Announcing the Matter bridge on {} with {} and {}", interface.interface_name, ipv4, ipv6);
+            return Ok((ipv4, ipv6, index));
+        }
+    }
+
+    Err(ErrorCode::NoNetworkInterface.into())
+}
+
+/// Runs the Matter stack until it fails, setting the power of the lights received on `states` and sending the
+/// one set by the Matter controllers on `commands`, by endpoint. Blocks the thread, the stack isn't `Send`.
+pub fn run(config: Config, states: Receiver<(EndptId, bool)>, commands: Sender<(EndptId, bool)>) -> Result<(), Error> {
+    // The test vendor and product, the ones of the development credentials
+    let info = BasicInfoConfig {
+        vid: 0xFFF1,
+        pid: 0x8000,
+        hw_ver: 1,
+        sw_ver: 1,
+        sw_ver_str: "1",
+        serial_no: "smart-home-system",
+        device_name: "smart-home-system bridge",
+        product_name: "smart-home-system bridge",
+        vendor_name: "smart-home-system",
+    };
+
+    let (ipv4, ipv6, interface) = network()?;
+    let mdns = MdnsService::new(0, "smart-home-system", ipv4.octets(), Some((ipv6.octets(), interface)), &info, rs_matter::MATTER_PORT);
+    let matter = Matter::new(&info, &config.credentials, &mdns, rs_matter::utils::epoch::sys_epoch, rs_matter::utils::rand::sys_rand, rs_matter::MATTER_PORT);
+    let psm = Psm::new(&matter, config.storage_path)?;
+
+    let lights = [LightCluster::new(1, *matter.borrow(), commands.clone()), LightCluster::new(2, *matter.borrow(), commands)];
+    let handler = HandlerCompat((
+        NODE,
+        root_endpoint::handler(0, &matter)
+            .chain(1, descriptor::ID, DescriptorCluster::new(*matter.borrow()))
+            .chain(1, cluster_on_off::ID, &lights[0])
+            .chain(2, descriptor::ID, DescriptorCluster::new(*matter.borrow()))
+            .chain(2, cluster_on_off::ID, &lights[1]),
+    ));
+
+    let stack = NetworkStack::new();
+    let mut mdns_buffers = MdnsRunBuffers::new();
+    let mdns_runner = pin!(mdns.run(&stack, &mut mdns_buffers));
+
+    let mut buffers = RunBuffers::new();
+    let commissioning = CommissioningData {
+        verifier: VerifierData::new_with_pw(config.passcode, *matter.borrow()),
+        discriminator: config.discriminator,
+    };
+    let runner = pin!(matter.run(&stack, &mut buffers, commissioning, &handler));
+    let psm_runner = pin!(psm.run());
+
+    let update = pin!(async {
+        while let Ok((endpoint, on)) = states.recv().await {
+            if let Some(light) = lights.iter().find(|light| light.endpoint == endpoint) {
+                light.cluster.set(on);
+            }
+        }
+        Ok(())
+    });
+
+    futures_lite::future::block_on(runner.or(mdns_runner).or(psm_runner).or(update))
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use domain_state::error;
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::config::{Config, DeviceConfig};
+use crate::miio::{self, Device, Token};
+use crate::{MQTT_ERROR_TOPIC, MQTT_TOPIC_PREFIX};
+use crate::profile::Profile;
+
+struct ManagedDevice {
+    config: DeviceConfig,
+    token: Token,
+    profile: Profile,
+    device: Option<Device>,
+    last_values: HashMap<String, String>,
+}
+
+impl ManagedDevice {
+    async fn connection(&mut self) -> anyhow::Result<&mut Device> {
+        if self.device.is_none() {
+            let address = if self.config.address.contains(':') {
+                self.config.address.clone()
+            } else {
+                format!("{}:{}", self.config.address, miio::PORT)
+            };
+
+            info!("Connecting to miio device {} at {}...", self.config.name, address);
+            self.device = Some(Device::connect(&address, self.token.clone()).await?);
+        }
+
+        Ok(self.device.as_mut().unwrap())
+    }
+}
+
+pub struct Application {
+    client: AsyncClient,
+    devices: HashMap<String, ManagedDevice>,
+}
+
+/// A `smart-home-system/miio/<device>/<attribute>/<action>` topic.
+struct DeviceTopic<'a> {
+    device: &'a str,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { device: parts.next()?, attribute: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, config: Config, profiles_dir: &Path) -> anyhow::Result<Self> {
+        let mut devices = HashMap::new();
+
+        for device in config.devices {
+            let profile = Profile::load(&profiles_dir.join(format!("{}.yaml", device.model)))?;
+            let token = Token::from_hex(&device.token)
+                .map_err(|e| anyhow::anyhow!("Invalid token for device {}: {}", device.name, e))?;
+
+            info!("Loaded miio device {} ({}) with {} properties and {} actions",
+                device.name, profile.model, profile.properties.len(), profile.actions.len());
+
+            devices.insert(device.name.clone(), ManagedDevice {
+                config: device,
+                token,
+                profile,
+                device: None,
+                last_values: HashMap::new(),
+            });
+        }
+
+        Ok(Self { client, devices })
+    }
+
+    /// Reads every property of every device and publishes the values that changed since the last poll.
+    pub async fn poll(&mut self) {
+        let names: Vec<String> = self.devices.keys().cloned().collect();
+
+        for name in names {
+            self.poll_device(&name, false).await;
+        }
+    }
+
+    async fn poll_device(&mut self, name: &str, force: bool) {
+        let Some(managed) = self.devices.get_mut(name) else { return };
+
+        let properties: Vec<(String, u32, u32)> = managed.profile.properties.iter()
+            .map(|property| (property.name.clone(), property.siid, property.piid))
+            .collect();
+
+        if properties.is_empty() {
+            return;
+        }
+
+        let results = match managed.connection().await {
+            Ok(device) => device.get_properties(&properties).await,
+            Err(e) => Err(e),
+        };
+
+        let results = match results {
+            Ok(results) => results,
+            Err(e) => {
+                warn!("Failed to read properties of miio device {}: {}", name, e);
+                managed.device = None;
+                return;
+            }
+        };
+
+        for result in results {
+            let Some(property) = managed.profile.property(&result.did) else { continue };
+
+            if result.code != 0 {
+                warn!("miio device {} returned code {} for property {}", name, result.code, property.name);
+                continue;
+            }
+
+            let Some(value) = result.value else { continue };
+            let payload = property.value_type.format_value(&value);
+
+            if !force && managed.last_values.get(&property.name) == Some(&payload) {
+                continue;
+            }
+
+            info!("miio device {} {} is: {}", name, property.name, payload);
+
+            let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, name, property.name);
+            self.client.publish(Message::new_retained(topic, payload.clone(), 1));
+            managed.last_values.insert(property.name.clone(), payload);
+        }
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        if !self.devices.contains_key(topic.device) {
+            warn!("[{}] Unknown miio device {}", message.topic(), topic.device);
+            return;
+        }
+
+        match topic.action {
+            "get" => self.poll_device(topic.device, true).await,
+            "set" => self.handle_set(&topic, message).await,
+            "run" => self.handle_action(&topic, message).await,
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    async fn handle_set(&mut self, topic: &DeviceTopic<'_>, message: &Message) {
+        let managed = self.devices.get_mut(topic.device).unwrap();
+
+        let Some(property) = managed.profile.property(topic.attribute).cloned() else {
+            error!("[{}] Unknown property '{}' for model {}", message.topic(), topic.attribute, managed.profile.model);
+            return;
+        };
+
+        if !property.writable {
+            error!("[{}] Property '{}' is read-only", message.topic(), property.name);
+            return;
+        }
+
+        let payload = message.payload_str();
+        let value = match property.value_type.parse_payload(&payload) {
+            Ok(value) => value,
+            Err(e) => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string());
+                return;
+            }
+        };
+
+        info!("[{}] Setting miio device {} {} to: {}", message.topic(), topic.device, property.name, value);
+
+        let result = match managed.connection().await {
+            Ok(device) => device.set_property(&property.name, property.siid, property.piid, value).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to set {} on miio device {}: {}", property.name, topic.device, e);
+            managed.device = None;
+            return;
+        }
+
+        self.poll_device(topic.device, false).await;
+    }
+
+    async fn handle_action(&mut self, topic: &DeviceTopic<'_>, message: &Message) {
+        let managed = self.devices.get_mut(topic.device).unwrap();
+
+        let Some(action) = managed.profile.action(topic.attribute).cloned() else {
+            error!("[{}] Unknown action '{}' for model {}", message.topic(), topic.attribute, managed.profile.model);
+            return;
+        };
+
+        info!("[{}] Running action {} on miio device {}", message.topic(), action.name, topic.device);
+
+        let result = match managed.connection().await {
+            Ok(device) => device.action(&action.name, action.siid, action.aiid).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to run {} on miio device {}: {}", action.name, topic.device, e);
+            managed.device = None;
+            return;
+        }
+
+        self.poll_device(topic.device, false).await;
+    }
+}
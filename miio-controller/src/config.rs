@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u64,
+    pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeviceConfig {
+    /// Used as the topic segment, e.g. `smart-home-system/miio/<name>/power`.
+    pub name: String,
+    pub address: String,
+    pub token: String,
+    /// Selects the profile file `<profiles dir>/<model>.yaml`.
+    pub model: String,
+}
+
+fn default_poll_interval() -> u64 {
+    30
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        let config: Config = serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))?;
+
+        if config.poll_interval_secs == 0 {
+            anyhow::bail!("Invalid config {:?}: poll_interval_secs must be greater than 0", path);
+        }
+
+        Ok(config)
+    }
+}
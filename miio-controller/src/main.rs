@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::info;
+
+use crate::application::Application;
+use crate::config::Config;
+use crate::mqtt::connect_mqtt;
+
+mod miio;
+mod profile;
+mod config;
+mod application;
+mod mqtt;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/miio";
+const MQTT_SET_TOPIC: &str = "smart-home-system/miio/+/+/set";
+const MQTT_GET_TOPIC: &str = "smart-home-system/miio/+/+/get";
+const MQTT_RUN_TOPIC: &str = "smart-home-system/miio/+/+/run";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/miio/error";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [MQTT_SET_TOPIC, MQTT_GET_TOPIC, MQTT_RUN_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let config_path: PathBuf = std::env::var("MIIO_CONFIG").unwrap_or_else(|_| "miio.yaml".into()).into();
+    let profiles_dir: PathBuf = std::env::var("MIIO_PROFILES_DIR").unwrap_or_else(|_| "profiles".into()).into();
+
+    let config = Config::load(&config_path)?;
+    let poll_interval = Duration::from_secs(config.poll_interval_secs);
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting miio controller");
+
+    let mut application = Application::new(client, config, &profiles_dir)?;
+
+    info!("Waiting for mqtt messages...");
+
+    let mut poll_interval = tokio::time::interval(poll_interval);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    application.handle_mqtt_message(&message).await;
+                }
+            }
+            _ = poll_interval.tick() => application.poll().await,
+        }
+    }
+
+    Ok(())
+}
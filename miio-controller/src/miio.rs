@@ -0,0 +1,310 @@
+use std::time::{Duration, Instant};
+
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::cipher::block_padding::Pkcs7;
+use anyhow::Context;
+use log::{debug, warn};
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+
+pub const PORT: u16 = 54321;
+
+const MAGIC: u16 = 0x2131;
+const HEADER_LENGTH: usize = 32;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRIES: usize = 3;
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub unknown: u32,
+    pub device_id: u32,
+    pub stamp: u32,
+    pub checksum: [u8; 16],
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// The packet that starts every session. The reply carries the device id and its current stamp.
+    pub fn hello() -> Self {
+        Self { unknown: 0xFFFFFFFF, device_id: 0xFFFFFFFF, stamp: 0xFFFFFFFF, checksum: [0xFF; 16], data: Vec::new() }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(HEADER_LENGTH + self.data.len());
+        buffer.extend_from_slice(&MAGIC.to_be_bytes());
+        buffer.extend_from_slice(&((HEADER_LENGTH + self.data.len()) as u16).to_be_bytes());
+        buffer.extend_from_slice(&self.unknown.to_be_bytes());
+        buffer.extend_from_slice(&self.device_id.to_be_bytes());
+        buffer.extend_from_slice(&self.stamp.to_be_bytes());
+        buffer.extend_from_slice(&self.checksum);
+        buffer.extend_from_slice(&self.data);
+        buffer
+    }
+
+    pub fn decode(buffer: &[u8]) -> anyhow::Result<Self> {
+        if buffer.len() < HEADER_LENGTH {
+            anyhow::bail!("Packet shorter than header");
+        }
+
+        let magic = u16::from_be_bytes([buffer[0], buffer[1]]);
+        if magic != MAGIC {
+            anyhow::bail!("Invalid magic {:#06x}", magic);
+        }
+
+        let length = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+        let data = buffer.get(HEADER_LENGTH..length).context("Packet shorter than its declared length")?;
+
+        Ok(Self {
+            unknown: u32::from_be_bytes(buffer[4..8].try_into()?),
+            device_id: u32::from_be_bytes(buffer[8..12].try_into()?),
+            stamp: u32::from_be_bytes(buffer[12..16].try_into()?),
+            checksum: buffer[16..32].try_into()?,
+            data: data.to_vec(),
+        })
+    }
+
+    fn compute_checksum(&self, token: &[u8; 16]) -> [u8; 16] {
+        let mut packet = self.clone();
+        packet.checksum = *token;
+        Md5::digest(packet.encode()).into()
+    }
+}
+
+/// Key material derived from the 16-byte device token.
+#[derive(Clone)]
+pub struct Token {
+    token: [u8; 16],
+    key: [u8; 16],
+    iv: [u8; 16],
+}
+
+impl Token {
+    pub fn from_hex(token: &str) -> anyhow::Result<Self> {
+        let token: [u8; 16] = hex::decode(token.trim())
+            .context("Token is not valid hex")?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Token must be 16 bytes long"))?;
+
+        let key: [u8; 16] = Md5::digest(token).into();
+        let iv: [u8; 16] = Md5::new().chain_update(key).chain_update(token).finalize().into();
+
+        Ok(Self { token, key, iv })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        Aes128CbcEnc::new(&self.key.into(), &self.iv.into()).encrypt_padded_vec_mut::<Pkcs7>(plaintext)
+    }
+
+    pub fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Aes128CbcDec::new(&self.key.into(), &self.iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| anyhow::anyhow!("Invalid padding in decrypted payload"))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Response {
+    id: u64,
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<Value>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PropertyResult {
+    pub did: String,
+    pub siid: u32,
+    pub piid: u32,
+    pub code: i32,
+    #[serde(default)]
+    pub value: Option<Value>,
+}
+
+pub struct Device {
+    socket: UdpSocket,
+    token: Token,
+    device_id: u32,
+    stamp: u32,
+    stamp_received_at: Instant,
+    next_id: u64,
+}
+
+impl Device {
+    pub async fn connect(address: &str, token: Token) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(address).await?;
+
+        let mut device = Self { socket, token, device_id: 0, stamp: 0, stamp_received_at: Instant::now(), next_id: 1 };
+        device.handshake().await?;
+
+        Ok(device)
+    }
+
+    async fn handshake(&mut self) -> anyhow::Result<()> {
+        self.socket.send(&Packet::hello().encode()).await?;
+
+        let mut buffer = [0; 1024];
+        let length = tokio::time::timeout(REQUEST_TIMEOUT, self.socket.recv(&mut buffer)).await
+            .context("Timed out waiting for hello response")??;
+
+        let hello = Packet::decode(&buffer[..length])?;
+        self.device_id = hello.device_id;
+        self.stamp = hello.stamp;
+        self.stamp_received_at = Instant::now();
+
+        debug!("Handshake with device {:#x} done, stamp {}", self.device_id, self.stamp);
+
+        Ok(())
+    }
+
+    pub async fn send(&mut self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let mut last_error = None;
+
+        for attempt in 0..RETRIES {
+            if attempt > 0 {
+                // The device may have restarted and reset its stamp.
+                if let Err(e) = self.handshake().await {
+                    last_error = Some(e);
+                    continue;
+                }
+            }
+
+            match self.send_once(method, &params).await {
+                Ok(result) => return Ok(result),
+                Err(e) => {
+                    warn!("miIO request {} failed (attempt {}/{}): {}", method, attempt + 1, RETRIES, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("miIO request {} failed", method)))
+    }
+
+    async fn send_once(&mut self, method: &str, params: &Value) -> anyhow::Result<Value> {
+        let id = self.next_id;
+        // Devices ignore requests whose id they have already seen, so keep ids increasing across retries.
+        self.next_id += 1;
+
+        let request = json!({ "id": id, "method": method, "params": params });
+
+        let mut packet = Packet {
+            unknown: 0,
+            device_id: self.device_id,
+            stamp: self.stamp.wrapping_add(self.stamp_received_at.elapsed().as_secs() as u32),
+            checksum: [0; 16],
+            data: self.token.encrypt(&serde_json::to_vec(&request)?),
+        };
+        packet.checksum = packet.compute_checksum(&self.token.token);
+
+        self.socket.send(&packet.encode()).await?;
+
+        let mut buffer = [0; 4096];
+
+        loop {
+            let length = tokio::time::timeout(REQUEST_TIMEOUT, self.socket.recv(&mut buffer)).await
+                .context("Timed out waiting for response")??;
+
+            let packet = Packet::decode(&buffer[..length])?;
+
+            if packet.checksum != packet.compute_checksum(&self.token.token) {
+                anyhow::bail!("Invalid checksum in response. Is the token correct?");
+            }
+
+            let data = self.token.decrypt(&packet.data)?;
+            // Some firmwares terminate the payload with a null byte.
+            let data = data.strip_suffix(&[0]).unwrap_or(&data);
+            let response: Response = serde_json::from_slice(data)?;
+
+            if response.id != id {
+                debug!("Ignoring response for stale request {}", response.id);
+                continue;
+            }
+
+            if let Some(error) = response.error {
+                anyhow::bail!("Device returned error: {}", error);
+            }
+
+            return response.result.context("Response without result");
+        }
+    }
+
+    pub async fn get_properties(&mut self, properties: &[(String, u32, u32)]) -> anyhow::Result<Vec<PropertyResult>> {
+        let params: Vec<Value> = properties.iter()
+            .map(|(did, siid, piid)| json!({ "did": did, "siid": siid, "piid": piid }))
+            .collect();
+
+        let result = self.send("get_properties", Value::Array(params)).await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    pub async fn set_property(&mut self, did: &str, siid: u32, piid: u32, value: Value) -> anyhow::Result<()> {
+        let params = json!([{ "did": did, "siid": siid, "piid": piid, "value": value }]);
+        let result: Vec<PropertyResult> = serde_json::from_value(self.send("set_properties", params).await?)?;
+
+        match result.first() {
+            Some(result) if result.code == 0 => Ok(()),
+            Some(result) => anyhow::bail!("Device rejected set_properties with code {}", result.code),
+            None => anyhow::bail!("Empty set_properties response"),
+        }
+    }
+
+    pub async fn action(&mut self, did: &str, siid: u32, aiid: u32) -> anyhow::Result<()> {
+        let params = json!({ "did": did, "siid": siid, "aiid": aiid, "in": [] });
+        let result = self.send("action", params).await?;
+
+        match result.get("code").and_then(Value::as_i64) {
+            Some(0) | None => Ok(()),
+            Some(code) => anyhow::bail!("Device rejected action with code {}", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::miio::{Packet, Token};
+
+    const TOKEN: &str = "00112233445566778899aabbccddeeff";
+
+    #[test]
+    fn test_hello_packet() {
+        let hello = Packet::hello().encode();
+
+        assert_eq!(hello.len(), 32);
+        assert_eq!(&hello[..4], &[0x21, 0x31, 0x00, 0x20]);
+        assert!(hello[4..].iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let packet = Packet { unknown: 0, device_id: 0x0123abcd, stamp: 42, checksum: [7; 16], data: vec![1, 2, 3] };
+        let encoded = packet.encode();
+
+        assert_eq!(u16::from_be_bytes([encoded[2], encoded[3]]), 35);
+        assert_eq!(Packet::decode(&encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let token = Token::from_hex(TOKEN).unwrap();
+        let plaintext = b"{\"id\":1,\"method\":\"get_properties\",\"params\":[]}";
+
+        let ciphertext = token.encrypt(plaintext);
+
+        assert_eq!(ciphertext.len() % 16, 0);
+        assert_ne!(&ciphertext[..], &plaintext[..]);
+        assert_eq!(token.decrypt(&ciphertext).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        assert!(Token::from_hex("not hex").is_err());
+        assert!(Token::from_hex("0011").is_err());
+    }
+}
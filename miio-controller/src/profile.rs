@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Maps the MIoT properties (`siid`/`piid`) and actions (`siid`/`aiid`) of a device model onto topic names.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub model: String,
+    #[serde(default)]
+    pub properties: Vec<Property>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub siid: u32,
+    pub piid: u32,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Action {
+    pub name: String,
+    pub siid: u32,
+    pub aiid: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    String,
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> anyhow::Result<Profile> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read profile {:?}", path))?;
+
+        serde_yaml::from_str(&content).context(format!("Invalid profile {:?}", path))
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    pub fn action(&self, name: &str) -> Option<&Action> {
+        self.actions.iter().find(|action| action.name == name)
+    }
+}
+
+impl ValueType {
+    /// Parses an mqtt payload into the json value the device expects.
+    pub fn parse_payload(self, payload: &str) -> Result<Value, String> {
+        let payload = payload.trim();
+
+        match self {
+            ValueType::Bool => match payload.to_ascii_lowercase().as_str() {
+                "on" | "true" | "1" => Ok(Value::Bool(true)),
+                "off" | "false" | "0" => Ok(Value::Bool(false)),
+                _ => Err(format!("Invalid bool value: {}", payload)),
+            },
+            ValueType::Int => payload.parse::<i64>()
+                .map(Value::from)
+                .map_err(|_| format!("Invalid int value: {}", payload)),
+            ValueType::Float => payload.parse::<f64>()
+                .map(Value::from)
+                .map_err(|_| format!("Invalid float value: {}", payload)),
+            ValueType::String => Ok(Value::String(payload.to_string())),
+        }
+    }
+
+    /// Formats a value reported by the device as an mqtt payload.
+    pub fn format_value(self, value: &Value) -> String {
+        match (self, value) {
+            (ValueType::Bool, Value::Bool(true)) => "on".into(),
+            (ValueType::Bool, Value::Bool(false)) => "off".into(),
+            (_, Value::String(string)) => string.clone(),
+            (_, value) => value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::profile::{Profile, ValueType};
+
+    #[test]
+    fn test_profile_from_yaml() {
+        let profile = "
+model: zhimi.airpurifier.mb4
+properties:
+  - name: power
+    siid: 2
+    piid: 1
+    type: bool
+    writable: true
+  - name: pm25
+    siid: 3
+    piid: 4
+    type: int
+actions:
+  - name: reset-filter
+    siid: 4
+    aiid: 1
+";
+        let profile: Profile = serde_yaml::from_str(profile).unwrap();
+
+        assert_eq!(profile.model, "zhimi.airpurifier.mb4");
+        assert!(profile.property("power").unwrap().writable);
+        assert!(!profile.property("pm25").unwrap().writable);
+        assert_eq!(profile.property("pm25").unwrap().value_type, ValueType::Int);
+        assert_eq!(profile.action("reset-filter").unwrap().aiid, 1);
+        assert!(profile.property("mode").is_none());
+    }
+
+    #[test]
+    fn test_payload_conversion() {
+        assert_eq!(ValueType::Bool.parse_payload("ON"), Ok(Value::Bool(true)));
+        assert_eq!(ValueType::Int.parse_payload("42"), Ok(Value::from(42)));
+        assert!(ValueType::Int.parse_payload("4.2").is_err());
+        assert_eq!(ValueType::Float.parse_payload("4.5"), Ok(Value::from(4.5)));
+
+        assert_eq!(ValueType::Bool.format_value(&Value::Bool(false)), "off");
+        assert_eq!(ValueType::Int.format_value(&Value::from(12)), "12");
+        assert_eq!(ValueType::String.format_value(&Value::from("auto")), "auto");
+    }
+}
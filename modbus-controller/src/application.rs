@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use domain_state::error;
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::config::{Config, DeviceConfig, RegisterConfig};
+use crate::modbus::{self, Client};
+use crate::{MQTT_ERROR_TOPIC, MQTT_TOPIC_PREFIX};
+
+struct ManagedDevice {
+    config: DeviceConfig,
+    client: Option<Client>,
+    last_values: HashMap<String, String>,
+}
+
+impl ManagedDevice {
+    async fn connection(&mut self) -> anyhow::Result<&mut Client> {
+        if self.client.is_none() {
+            let address = if self.config.address.contains(':') {
+                self.config.address.clone()
+            } else {
+                format!("{}:{}", self.config.address, modbus::PORT)
+            };
+
+            info!("Connecting to modbus device {} at {}...", self.config.name, address);
+            self.client = Some(Client::connect(&address, self.config.unit_id).await?);
+        }
+
+        Ok(self.client.as_mut().unwrap())
+    }
+
+    async fn read(&mut self, register: &RegisterConfig) -> anyhow::Result<f64> {
+        let registers = self.connection().await?
+            .read_registers(register.kind.into(), register.address, register.data_type.register_count()).await?;
+
+        register.decode(&registers)
+    }
+}
+
+pub struct Application {
+    client: AsyncClient,
+    devices: HashMap<String, ManagedDevice>,
+}
+
+/// A `smart-home-system/modbus/<device>/<register>/<action>` topic.
+struct DeviceTopic<'a> {
+    device: &'a str,
+    register: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { device: parts.next()?, register: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, config: Config) -> Self {
+        let devices = config.devices.into_iter()
+            .map(|device| {
+                info!("Loaded modbus device {} with {} registers", device.name, device.registers.len());
+                (device.name.clone(), ManagedDevice { config: device, client: None, last_values: HashMap::new() })
+            })
+            .collect();
+
+        Self { client, devices }
+    }
+
+    /// Reads every register of every device and publishes the values that changed since the last poll.
+    pub async fn poll(&mut self) {
+        let names: Vec<String> = self.devices.keys().cloned().collect();
+
+        for name in names {
+            self.poll_device(&name, false).await;
+        }
+    }
+
+    async fn poll_device(&mut self, name: &str, force: bool) {
+        let Some(managed) = self.devices.get_mut(name) else { return };
+
+        for register in managed.config.registers.clone() {
+            let value = match managed.read(&register).await {
+                Ok(value) => value,
+                Err(e) => {
+                    warn!("Failed to read register {} of modbus device {}: {}", register.name, name, e);
+                    managed.client = None;
+                    return;
+                }
+            };
+
+            let payload = register.format(value);
+
+            if !force && managed.last_values.get(&register.name) == Some(&payload) {
+                continue;
+            }
+
+            info!("modbus device {} {} is: {}", name, register.name, payload);
+
+            let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, name, register.name);
+            self.client.publish(Message::new_retained(topic, payload.clone(), 1));
+            managed.last_values.insert(register.name.clone(), payload);
+        }
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        if !self.devices.contains_key(topic.device) {
+            warn!("[{}] Unknown modbus device {}", message.topic(), topic.device);
+            return;
+        }
+
+        match topic.action {
+            "get" => self.poll_device(topic.device, true).await,
+            "set" => self.handle_set(&topic, message).await,
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    async fn handle_set(&mut self, topic: &DeviceTopic<'_>, message: &Message) {
+        let managed = self.devices.get_mut(topic.device).unwrap();
+
+        let Some(register) = managed.config.registers.iter().find(|register| register.name == topic.register).cloned() else {
+            error!("[{}] Unknown register '{}' for modbus device {}", message.topic(), topic.register, topic.device);
+            return;
+        };
+
+        if !register.writable {
+            error!("[{}] Register '{}' is read-only", message.topic(), register.name);
+            return;
+        }
+
+        let payload = message.payload_str();
+        let values = match payload.trim().parse::<f64>().map_err(anyhow::Error::from).and_then(|value| register.encode(value)) {
+            Ok(values) => values,
+            Err(e) => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string());
+                return;
+            }
+        };
+
+        info!("[{}] Writing {:?} to register {} of modbus device {}", message.topic(), values, register.name, topic.device);
+
+        let result = match managed.connection().await {
+            Ok(client) => client.write_registers(register.address, &values).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to write register {} of modbus device {}: {}", register.name, topic.device, e);
+            managed.client = None;
+            return;
+        }
+
+        self.poll_device(topic.device, false).await;
+    }
+}
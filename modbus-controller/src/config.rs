@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::modbus::RegisterKind;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_secs: u64,
+    pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeviceConfig {
+    /// Used as the topic segment, e.g. `smart-home-system/modbus/<name>/power`.
+    pub name: String,
+    pub address: String,
+    #[serde(default = "default_unit_id")]
+    pub unit_id: u8,
+    pub registers: Vec<RegisterConfig>,
+}
+
+/// A value spread over one or more registers. The published value is `raw * scale + offset`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RegisterConfig {
+    pub name: String,
+    pub address: u16,
+    #[serde(default = "default_kind")]
+    pub kind: RegisterKindConfig,
+    #[serde(rename = "type", default = "default_data_type")]
+    pub data_type: DataType,
+    /// Order of the 16-bit words of 32 and 64-bit values. Most devices send the high word first.
+    #[serde(default = "default_word_order")]
+    pub word_order: WordOrder,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    /// Decimal places of the published value.
+    #[serde(default)]
+    pub precision: usize,
+    /// Only holding registers can be written.
+    #[serde(default)]
+    pub writable: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterKindConfig {
+    Holding,
+    Input,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DataType {
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    U64,
+    I64,
+    F64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WordOrder {
+    Big,
+    Little,
+}
+
+fn default_poll_interval() -> u64 {
+    10
+}
+
+fn default_unit_id() -> u8 {
+    1
+}
+
+fn default_kind() -> RegisterKindConfig {
+    RegisterKindConfig::Holding
+}
+
+fn default_data_type() -> DataType {
+    DataType::U16
+}
+
+fn default_word_order() -> WordOrder {
+    WordOrder::Big
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl From<RegisterKindConfig> for RegisterKind {
+    fn from(kind: RegisterKindConfig) -> Self {
+        match kind {
+            RegisterKindConfig::Holding => RegisterKind::Holding,
+            RegisterKindConfig::Input => RegisterKind::Input,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        let config: Config = serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))?;
+
+        if config.poll_interval_secs == 0 {
+            anyhow::bail!("Invalid config {:?}: poll_interval_secs must be greater than 0", path);
+        }
+
+        for device in &config.devices {
+            for register in &device.registers {
+                if register.writable && register.kind != RegisterKindConfig::Holding {
+                    anyhow::bail!("Register {} of device {} is writable but is not a holding register", register.name, device.name);
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+pub const PORT: u16 = 502;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const FUNCTION_READ_HOLDING_REGISTERS: u8 = 0x03;
+const FUNCTION_READ_INPUT_REGISTERS: u8 = 0x04;
+const FUNCTION_WRITE_SINGLE_REGISTER: u8 = 0x06;
+const FUNCTION_WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// A Modbus application data unit as sent over TCP: the MBAP header followed by the PDU.
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub transaction_id: u16,
+    pub unit_id: u8,
+    pub pdu: Vec<u8>,
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(7 + self.pdu.len());
+        buffer.extend_from_slice(&self.transaction_id.to_be_bytes());
+        buffer.extend_from_slice(&0u16.to_be_bytes()); // protocol id, always 0 for modbus
+        buffer.extend_from_slice(&(self.pdu.len() as u16 + 1).to_be_bytes());
+        buffer.push(self.unit_id);
+        buffer.extend_from_slice(&self.pdu);
+        buffer
+    }
+
+    pub fn decode(buffer: &[u8]) -> anyhow::Result<Self> {
+        if buffer.len() < 8 {
+            bail!("Frame is too short: {} bytes", buffer.len());
+        }
+
+        let length = u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+        if buffer.len() != 6 + length {
+            bail!("Frame length mismatch: header says {} bytes but got {}", length, buffer.len() - 6);
+        }
+
+        Ok(Self {
+            transaction_id: u16::from_be_bytes([buffer[0], buffer[1]]),
+            unit_id: buffer[6],
+            pdu: buffer[7..].to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegisterKind {
+    Holding,
+    Input,
+}
+
+pub fn read_registers_pdu(kind: RegisterKind, address: u16, count: u16) -> Vec<u8> {
+    let function = match kind {
+        RegisterKind::Holding => FUNCTION_READ_HOLDING_REGISTERS,
+        RegisterKind::Input => FUNCTION_READ_INPUT_REGISTERS,
+    };
+
+    let mut pdu = vec![function];
+    pdu.extend_from_slice(&address.to_be_bytes());
+    pdu.extend_from_slice(&count.to_be_bytes());
+    pdu
+}
+
+pub fn write_registers_pdu(address: u16, values: &[u16]) -> Vec<u8> {
+    let mut pdu = Vec::new();
+
+    if let [value] = values {
+        pdu.push(FUNCTION_WRITE_SINGLE_REGISTER);
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&value.to_be_bytes());
+    } else {
+        pdu.push(FUNCTION_WRITE_MULTIPLE_REGISTERS);
+        pdu.extend_from_slice(&address.to_be_bytes());
+        pdu.extend_from_slice(&(values.len() as u16).to_be_bytes());
+        pdu.push((values.len() * 2) as u8);
+        values.iter().for_each(|value| pdu.extend_from_slice(&value.to_be_bytes()));
+    }
+
+    pdu
+}
+
+/// Checks the response PDU for an exception and returns its payload after the function code.
+fn check_response(request: &[u8], response: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let function = *response.first().context("Empty response")?;
+
+    if function == request[0] | 0x80 {
+        let code = response.get(1).copied().unwrap_or_default();
+        let description = match code {
+            0x01 => "illegal function",
+            0x02 => "illegal data address",
+            0x03 => "illegal data value",
+            0x04 => "server device failure",
+            0x06 => "server device busy",
+            0x0B => "gateway target device failed to respond",
+            _ => "unknown exception",
+        };
+        bail!("Device returned exception {:#04x} ({})", code, description);
+    }
+
+    if function != request[0] {
+        bail!("Unexpected function code in response: {:#04x}", function);
+    }
+
+    Ok(response[1..].to_vec())
+}
+
+pub fn parse_read_response(request: &[u8], response: &[u8], count: u16) -> anyhow::Result<Vec<u16>> {
+    let data = check_response(request, response)?;
+
+    let byte_count = *data.first().context("Missing byte count")? as usize;
+    if byte_count != count as usize * 2 || data.len() != byte_count + 1 {
+        bail!("Expected {} registers but got {} bytes", count, data.len() - 1);
+    }
+
+    Ok(data[1..].chunks(2).map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]])).collect())
+}
+
+pub struct Client {
+    stream: TcpStream,
+    unit_id: u8,
+    transaction_id: u16,
+}
+
+impl Client {
+    pub async fn connect(address: &str, unit_id: u8) -> anyhow::Result<Self> {
+        let stream = tokio::time::timeout(REQUEST_TIMEOUT, TcpStream::connect(address)).await
+            .context("Timed out connecting")??;
+
+        Ok(Self { stream, unit_id, transaction_id: 0 })
+    }
+
+    async fn request(&mut self, pdu: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+
+        let frame = Frame { transaction_id: self.transaction_id, unit_id: self.unit_id, pdu };
+        self.stream.write_all(&frame.encode()).await?;
+
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, async {
+            let mut header = [0u8; 6];
+            self.stream.read_exact(&mut header).await?;
+
+            let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+            let mut buffer = header.to_vec();
+            buffer.resize(6 + length, 0);
+            self.stream.read_exact(&mut buffer[6..]).await?;
+
+            anyhow::Ok(buffer)
+        }).await.context("Timed out waiting for response")??;
+
+        let response = Frame::decode(&response)?;
+
+        if response.transaction_id != frame.transaction_id {
+            bail!("Response transaction id {} does not match request {}", response.transaction_id, frame.transaction_id);
+        }
+
+        check_response(&frame.pdu, &response.pdu)?;
+        Ok(response.pdu)
+    }
+
+    pub async fn read_registers(&mut self, kind: RegisterKind, address: u16, count: u16) -> anyhow::Result<Vec<u16>> {
+        let request = read_registers_pdu(kind, address, count);
+        let response = self.request(request.clone()).await?;
+
+        parse_read_response(&request, &response, count)
+    }
+
+    pub async fn write_registers(&mut self, address: u16, values: &[u16]) -> anyhow::Result<()> {
+        self.request(write_registers_pdu(address, values)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modbus::{Frame, parse_read_response, read_registers_pdu, RegisterKind, write_registers_pdu};
+
+    #[test]
+    fn test_encode_decode_frame() {
+        let frame = Frame { transaction_id: 1, unit_id: 17, pdu: read_registers_pdu(RegisterKind::Holding, 0x006B, 3) };
+
+        let encoded = frame.encode();
+        assert_eq!(encoded, [0x00, 0x01, 0x00, 0x00, 0x00, 0x06, 0x11, 0x03, 0x00, 0x6B, 0x00, 0x03]);
+        assert_eq!(Frame::decode(&encoded).unwrap(), frame);
+        assert!(Frame::decode(&encoded[..10]).is_err());
+    }
+
+    #[test]
+    fn test_parse_read_response() {
+        let request = read_registers_pdu(RegisterKind::Input, 8, 2);
+
+        assert_eq!(parse_read_response(&request, &[0x04, 0x04, 0x02, 0x2B, 0x00, 0x64], 2).unwrap(), [0x022B, 0x0064]);
+        assert!(parse_read_response(&request, &[0x04, 0x02, 0x02, 0x2B], 2).is_err());
+
+        let error = parse_read_response(&request, &[0x84, 0x02], 2).unwrap_err();
+        assert!(error.to_string().contains("illegal data address"));
+    }
+
+    #[test]
+    fn test_write_registers_pdu() {
+        assert_eq!(write_registers_pdu(1, &[0x0003]), [0x06, 0x00, 0x01, 0x00, 0x03]);
+        assert_eq!(write_registers_pdu(1, &[0x000A, 0x0102]), [0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02]);
+    }
+}
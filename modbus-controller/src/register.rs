@@ -0,0 +1,135 @@
+use anyhow::bail;
+
+use crate::config::{DataType, RegisterConfig, WordOrder};
+
+impl DataType {
+    /// Number of 16-bit registers the value spans.
+    pub fn register_count(self) -> u16 {
+        match self {
+            DataType::U16 | DataType::I16 => 1,
+            DataType::U32 | DataType::I32 | DataType::F32 => 2,
+            DataType::U64 | DataType::I64 | DataType::F64 => 4,
+        }
+    }
+}
+
+fn to_bytes(registers: &[u16], word_order: WordOrder) -> Vec<u8> {
+    let words: Vec<u16> = match word_order {
+        WordOrder::Big => registers.to_vec(),
+        WordOrder::Little => registers.iter().rev().copied().collect(),
+    };
+
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn from_bytes(bytes: &[u8], word_order: WordOrder) -> Vec<u16> {
+    let words = bytes.chunks(2).map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]));
+
+    match word_order {
+        WordOrder::Big => words.collect(),
+        WordOrder::Little => words.rev().collect(),
+    }
+}
+
+impl RegisterConfig {
+    /// Decodes the raw registers and applies scale and offset.
+    pub fn decode(&self, registers: &[u16]) -> anyhow::Result<f64> {
+        if registers.len() != self.data_type.register_count() as usize {
+            bail!("Expected {} registers but got {}", self.data_type.register_count(), registers.len());
+        }
+
+        let bytes = to_bytes(registers, self.word_order);
+
+        let raw = match self.data_type {
+            DataType::U16 => u16::from_be_bytes(bytes[..2].try_into()?) as f64,
+            DataType::I16 => i16::from_be_bytes(bytes[..2].try_into()?) as f64,
+            DataType::U32 => u32::from_be_bytes(bytes[..4].try_into()?) as f64,
+            DataType::I32 => i32::from_be_bytes(bytes[..4].try_into()?) as f64,
+            DataType::F32 => f32::from_be_bytes(bytes[..4].try_into()?) as f64,
+            DataType::U64 => u64::from_be_bytes(bytes[..8].try_into()?) as f64,
+            DataType::I64 => i64::from_be_bytes(bytes[..8].try_into()?) as f64,
+            DataType::F64 => f64::from_be_bytes(bytes[..8].try_into()?),
+        };
+
+        Ok(raw * self.scale + self.offset)
+    }
+
+    /// Reverses scale and offset and encodes the value into registers. Integer types are rounded
+    /// and must fit the type.
+    pub fn encode(&self, value: f64) -> anyhow::Result<Vec<u16>> {
+        let raw = (value - self.offset) / self.scale;
+
+        if !raw.is_finite() {
+            bail!("Value {} is not a finite number", value);
+        }
+
+        let rounded = raw.round();
+
+        let in_range = |min: f64, max: f64| {
+            if rounded < min || rounded > max {
+                bail!("Value {} is out of range for {:?}", value, self.data_type);
+            }
+            Ok(())
+        };
+
+        let bytes = match self.data_type {
+            DataType::U16 => { in_range(u16::MIN as f64, u16::MAX as f64)?; (rounded as u16).to_be_bytes().to_vec() }
+            DataType::I16 => { in_range(i16::MIN as f64, i16::MAX as f64)?; (rounded as i16).to_be_bytes().to_vec() }
+            DataType::U32 => { in_range(u32::MIN as f64, u32::MAX as f64)?; (rounded as u32).to_be_bytes().to_vec() }
+            DataType::I32 => { in_range(i32::MIN as f64, i32::MAX as f64)?; (rounded as i32).to_be_bytes().to_vec() }
+            DataType::F32 => (raw as f32).to_be_bytes().to_vec(),
+            DataType::U64 => { in_range(u64::MIN as f64, u64::MAX as f64)?; (rounded as u64).to_be_bytes().to_vec() }
+            DataType::I64 => { in_range(i64::MIN as f64, i64::MAX as f64)?; (rounded as i64).to_be_bytes().to_vec() }
+            DataType::F64 => raw.to_be_bytes().to_vec(),
+        };
+
+        Ok(from_bytes(&bytes, self.word_order))
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        format!("{:.*}", self.precision, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{DataType, RegisterConfig, RegisterKindConfig, WordOrder};
+
+    fn register(data_type: DataType, word_order: WordOrder, scale: f64, offset: f64) -> RegisterConfig {
+        RegisterConfig {
+            name: "test".into(),
+            address: 0,
+            kind: RegisterKindConfig::Holding,
+            data_type,
+            word_order,
+            scale,
+            offset,
+            precision: 1,
+            writable: true,
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(register(DataType::I16, WordOrder::Big, 0.1, 0.0).decode(&[0xFF9C]).unwrap(), -10.0);
+        assert_eq!(register(DataType::U32, WordOrder::Big, 1.0, 0.0).decode(&[0x0001, 0x0002]).unwrap(), 65538.0);
+        assert_eq!(register(DataType::U32, WordOrder::Little, 1.0, 0.0).decode(&[0x0002, 0x0001]).unwrap(), 65538.0);
+        assert_eq!(register(DataType::F32, WordOrder::Big, 1.0, 0.0).decode(&[0x41C8, 0x0000]).unwrap(), 25.0);
+        assert_eq!(register(DataType::U16, WordOrder::Big, 1.0, -40.0).decode(&[100]).unwrap(), 60.0);
+        assert!(register(DataType::U32, WordOrder::Big, 1.0, 0.0).decode(&[1]).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(register(DataType::I16, WordOrder::Big, 0.1, 0.0).encode(-10.0).unwrap(), [0xFF9C]);
+        assert_eq!(register(DataType::U32, WordOrder::Little, 1.0, 0.0).encode(65538.0).unwrap(), [0x0002, 0x0001]);
+        assert_eq!(register(DataType::F32, WordOrder::Big, 1.0, 0.0).encode(25.0).unwrap(), [0x41C8, 0x0000]);
+        assert!(register(DataType::U16, WordOrder::Big, 1.0, 0.0).encode(-1.0).is_err());
+        assert!(register(DataType::U16, WordOrder::Big, 0.0, 0.0).encode(1.0).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(register(DataType::U16, WordOrder::Big, 0.1, 0.0).format(21.549), "21.5");
+    }
+}
@@ -0,0 +1,74 @@
+//! The connection to the mqtt server of the homekit bridge and the yeelight controller, with the client of paho or
+//! of rumqttc, so the small builds don't need the C library of paho.
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+mod message;
+#[cfg(feature = "paho")]
+mod paho;
+#[cfg(feature = "rumqttc")]
+mod rumqttc;
+
+#[cfg(all(feature = "paho", feature = "rumqttc"))]
+compile_error!("The mqtt client is either the one of paho or the one of rumqttc, build with --no-default-features to use rumqttc");
+#[cfg(not(any(feature = "paho", feature = "rumqttc")))]
+compile_error!("No mqtt client, build with the paho or the rumqttc feature");
+
+pub use message::Message;
+#[cfg(feature = "paho")]
+pub use crate::paho::{connect, Client, SCHEMES};
+#[cfg(feature = "rumqttc")]
+pub use crate::rumqttc::{connect, Client, SCHEMES};
+
+/// The messages received from the server, with `None` when the connection is lost.
+pub type Messages = async_channel::Receiver<Option<Message>>;
+
+/// How many messages received are kept until they're read from [`Messages`].
+const MESSAGES_CAPACITY: usize = 10;
+
+/// How to connect to the mqtt server, always with mqtt v5 and a clean start.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// e.g. `tcp://localhost:1883`.
+    pub server_uri: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub keep_alive: Duration,
+    /// The shortest and the longest wait between the attempts to reconnect when the connection is lost, or `None`
+    /// to stay disconnected until the service is restarted.
+    pub reconnect: Option<(Duration, Duration)>,
+}
+
+impl ConnectOptions {
+    pub fn new(server_uri: impl Into<String>, client_id: impl Into<String>) -> Self {
+        Self {
+            server_uri: server_uri.into(),
+            client_id: client_id.into(),
+            username: None,
+            password: None,
+            keep_alive: Duration::from_secs(60),
+            reconnect: None,
+        }
+    }
+}
+
+/// Why [`connect`] failed.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The options are invalid, e.g. the uri of the server, connecting again fails the same way.
+    Options(anyhow::Error),
+    /// The server couldn't be reached or refused the connection. It may be back later.
+    Unavailable(anyhow::Error),
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Options(e) | Self::Unavailable(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+/// A message published to or received from the mqtt server, built as the one of paho.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Message {
+    topic: String,
+    payload: Vec<u8>,
+    qos: i32,
+    retained: bool,
+    /// The mqtt v5 user properties, e.g. the trace context of the sender.
+    user_properties: Vec<(String, String)>,
+}
+
+impl Message {
+    pub fn new<S: Into<String>, V: Into<Vec<u8>>>(topic: S, payload: V, qos: i32) -> Self {
+        Self { topic: topic.into(), payload: payload.into(), qos, ..Self::default() }
+    }
+
+    /// A message the server keeps for the clients subscribing to its topic later.
+    pub fn new_retained<S: Into<String>, V: Into<Vec<u8>>>(topic: S, payload: V, qos: i32) -> Self {
+        Self { retained: true, ..Self::new(topic, payload, qos) }
+    }
+
+    pub fn with_user_properties(self, user_properties: Vec<(String, String)>) -> Self {
+        Self { user_properties, ..self }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// The payload as text, with the invalid utf-8 replaced.
+    pub fn payload_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.payload)
+    }
+
+    pub fn qos(&self) -> i32 {
+        self.qos
+    }
+
+    pub fn retained(&self) -> bool {
+        self.retained
+    }
+
+    pub fn user_properties(&self) -> &[(String, String)] {
+        &self.user_properties
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Message;
+
+    #[test]
+    fn test_message() {
+        let message = Message::new_retained("smart-home-system/yeelight/power", "on", 1);
+        assert_eq!(message.topic(), "smart-home-system/yeelight/power");
+        assert_eq!(message.payload_str(), "on");
+        assert_eq!(message.qos(), 1);
+        assert!(message.retained());
+        assert!(message.user_properties().is_empty());
+
+        let message = Message::new("smart-home-system/yeelight/power/set", vec![0xff, b'o'], 0)
+            .with_user_properties(vec![("traceparent".into(), "00-1-2-01".into())]);
+        assert_eq!(message.payload_str(), "\u{fffd}o");
+        assert!(!message.retained());
+        assert_eq!(message.user_properties(), [("traceparent".to_string(), "00-1-2-01".to_string())]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use paho_mqtt::{AsyncClient, MessageBuilder, Properties, PropertyCode};
+
+use crate::{ConnectError, ConnectOptions, Message, Messages, MESSAGES_CAPACITY};
+
+/// The schemes of the server uris paho connects to.
+pub const SCHEMES: &[&str] = &["tcp", "ssl", "mqtt", "mqtts", "ws", "wss"];
+
+/// The client of paho, cloned to publish from every task.
+#[derive(Clone)]
+pub struct Client {
+    client: AsyncClient,
+    /// The topics subscribed to by every clone, see [`Client::subscriptions`].
+    subscriptions: Arc<Mutex<BTreeSet<String>>>,
+}
+
+/// Connects to the server, failing if it can't be reached.
+pub async fn connect(options: &ConnectOptions) -> Result<(Client, Messages), ConnectError> {
+    let create_options = paho_mqtt::CreateOptionsBuilder::new()
+        .server_uri(options.server_uri.clone())
+        .client_id(options.client_id.clone())
+        .mqtt_version(paho_mqtt::MQTT_VERSION_5)
+        .finalize();
+
+    // Only fails for an invalid server uri
+    let mut client = AsyncClient::new(create_options)
+        .context("Failed to create mqtt client")
+        .map_err(ConnectError::Options)?;
+
+    let mut connection_options = paho_mqtt::ConnectOptionsBuilder::new_v5();
+
+    if let Some(username) = options.username.clone() {
+        connection_options.user_name(username);
+    }
+
+    if let Some(password) = options.password.clone() {
+        connection_options.password(password);
+    }
+
+    if let Some((min, max)) = options.reconnect {
+        connection_options.automatic_reconnect(min, max);
+    }
+
+    let connection_options = connection_options
+        .keep_alive_interval(options.keep_alive)
+        .clean_start(true)
+        .finalize();
+
+    let stream = client.get_stream(MESSAGES_CAPACITY);
+
+    client.connect(connection_options).await
+        .context("Failed to connect to mqtt server")
+        .map_err(ConnectError::Unavailable)?;
+
+    let (sender, messages) = async_channel::bounded(MESSAGES_CAPACITY);
+    tokio::spawn(async move {
+        while let Ok(message) = stream.recv().await {
+            if sender.send(message.as_ref().map(from_paho)).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    Ok((Client { client, subscriptions: Arc::default() }, messages))
+}
+
+impl Client {
+    /// Publishes the message, resolving once the server acknowledged it.
+    pub fn publish(&self, message: Message) -> impl Future<Output = anyhow::Result<()>> + Send + 'static {
+        let token = self.client.publish(to_paho(&message));
+        async move { Ok(token.await?) }
+    }
+
+    /// Subscribes to the topic with qos 1, resolving once the server acknowledged it.
+    pub fn subscribe(&self, topic: &str) -> impl Future<Output = anyhow::Result<()>> + Send + 'static {
+        self.subscriptions.lock().unwrap().insert(topic.to_string());
+        let token = self.client.subscribe(topic, 1);
+        async move {
+            token.await?;
+            Ok(())
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_connected()
+    }
+
+    /// The topics subscribed to with any clone of the client, lost by the server when the client reconnects.
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Calls the callback whenever the client connects again after the connection was lost, instead of the one
+    /// set before.
+    pub fn on_reconnect(&self, callback: impl Fn(&Client) + Send + 'static) {
+        let subscriptions = self.subscriptions.clone();
+        self.client.set_connected_callback(move |client| {
+            callback(&Client { client: client.clone(), subscriptions: subscriptions.clone() })
+        });
+    }
+}
+
+fn to_paho(message: &Message) -> paho_mqtt::Message {
+    let mut properties = Properties::new();
+    for (key, value) in message.user_properties() {
+        let _ = properties.push_string_pair(PropertyCode::UserProperty, key, value);
+    }
+
+    MessageBuilder::new()
+        .topic(message.topic())
+        .payload(message.payload())
+        .qos(message.qos())
+        .retained(message.retained())
+        .properties(properties)
+        .finalize()
+}
+
+fn from_paho(message: &paho_mqtt::Message) -> Message {
+    let user_properties = message.properties().user_iter().collect();
+
+    match message.retained() {
+        true => Message::new_retained(message.topic(), message.payload(), message.qos()),
+        false => Message::new(message.topic(), message.payload(), message.qos()),
+    }.with_user_properties(user_properties)
+}
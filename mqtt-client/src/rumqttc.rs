@@ -0,0 +1,225 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context};
+use async_channel::Sender;
+use rumqttc::v5::mqttbytes::v5::{Packet, Publish, PublishProperties};
+use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::{AsyncClient, Event, EventLoop, MqttOptions};
+use tracing::warn;
+
+use crate::{ConnectError, ConnectOptions, Message, Messages, MESSAGES_CAPACITY};
+
+/// The schemes of the server uris rumqttc connects to, it's built without tls and websockets.
+pub const SCHEMES: &[&str] = &["tcp", "mqtt"];
+
+/// The port of the server uris without one.
+const DEFAULT_PORT: u16 = 1883;
+
+/// How many requests, like the messages to publish, are queued until they're sent to the server.
+const REQUESTS_CAPACITY: usize = 64;
+
+type Callback = Box<dyn Fn(&Client) + Send>;
+
+/// The client of rumqttc, cloned to publish from every task. Unlike with paho, the messages published and the
+/// subscriptions are only queued, the acknowledgements of the server aren't waited for.
+#[derive(Clone)]
+pub struct Client {
+    client: AsyncClient,
+    connected: Arc<AtomicBool>,
+    reconnected: Arc<Mutex<Option<Callback>>>,
+    /// The topics subscribed to by every clone, see [`Client::subscriptions`].
+    subscriptions: Arc<Mutex<BTreeSet<String>>>,
+    /// Kept by the client so the stream of the messages received stays open while it's in use, as with paho.
+    messages: Sender<Option<Message>>,
+}
+
+/// Connects to the server, failing if it can't be reached.
+pub async fn connect(options: &ConnectOptions) -> Result<(Client, Messages), ConnectError> {
+    let mqtt_options = mqtt_options(options)
+        .context("Failed to create mqtt client")
+        .map_err(ConnectError::Options)?;
+    let (client, mut event_loop) = AsyncClient::new(mqtt_options, REQUESTS_CAPACITY);
+
+    // The connection is only made when the event loop is polled
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => break,
+            Ok(_) => {}
+            Err(e) => return Err(ConnectError::Unavailable(anyhow!(e).context("Failed to connect to mqtt server"))),
+        }
+    }
+
+    let (sender, messages) = async_channel::bounded(MESSAGES_CAPACITY);
+    let client = Client {
+        client,
+        connected: Arc::new(AtomicBool::new(true)),
+        reconnected: Arc::default(),
+        subscriptions: Arc::default(),
+        messages: sender,
+    };
+    tokio::spawn(run(client.clone(), event_loop, options.reconnect));
+
+    Ok((client, messages))
+}
+
+/// The options of rumqttc for the uri of the server, e.g. `tcp://localhost:1883`.
+fn mqtt_options(options: &ConnectOptions) -> anyhow::Result<MqttOptions> {
+    let (scheme, address) = options.server_uri.split_once("://").unwrap_or(("tcp", &options.server_uri));
+    if !SCHEMES.contains(&scheme) {
+        bail!("{}:// needs the paho mqtt client, rumqttc is built without tls and websockets", scheme);
+    }
+
+    let address = address.trim_end_matches('/');
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => (host, port.parse().context(format!("Invalid port {:?}", port))?),
+        _ => (address, DEFAULT_PORT),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        bail!("No host in {:?}", options.server_uri);
+    }
+
+    let mut mqtt_options = MqttOptions::new(options.client_id.clone(), host, port);
+    mqtt_options
+        .set_keep_alive(options.keep_alive)
+        .set_clean_start(true);
+
+    if let Some(username) = options.username.clone() {
+        mqtt_options.set_credentials(username, options.password.clone().unwrap_or_default());
+    }
+
+    Ok(mqtt_options)
+}
+
+/// Polls the connection, handing the messages received to the stream of the client, and reconnects with a
+/// backoff when it's lost if the options ask for it.
+async fn run(client: Client, mut event_loop: EventLoop, reconnect: Option<(Duration, Duration)>) {
+    let mut delay = reconnect.map(|(min, _)| min).unwrap_or_default();
+
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                if client.messages.send(Some(message(publish))).await.is_err() {
+                    return;
+                }
+            }
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                client.connected.store(true, Ordering::Relaxed);
+                delay = reconnect.map(|(min, _)| min).unwrap_or_default();
+                if let Some(callback) = &*client.reconnected.lock().unwrap() {
+                    callback(&client);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if client.connected.swap(false, Ordering::Relaxed) {
+                    warn!("Lost the connection to the mqtt server: {}", e);
+                    let _ = client.messages.send(None).await;
+                }
+
+                let Some((_, max)) = reconnect else { return };
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(max);
+            }
+        }
+    }
+}
+
+fn qos(qos: i32) -> QoS {
+    match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+fn message(publish: Publish) -> Message {
+    let topic = String::from_utf8_lossy(&publish.topic).into_owned();
+    let user_properties = publish.properties.map(|properties| properties.user_properties).unwrap_or_default();
+
+    match publish.retain {
+        true => Message::new_retained(topic, publish.payload.to_vec(), publish.qos as i32),
+        false => Message::new(topic, publish.payload.to_vec(), publish.qos as i32),
+    }.with_user_properties(user_properties)
+}
+
+impl Client {
+    /// Queues the message to be published.
+    pub fn publish(&self, message: Message) -> impl Future<Output = anyhow::Result<()>> + Send + 'static {
+        let properties = PublishProperties { user_properties: message.user_properties().to_vec(), ..Default::default() };
+        let result = self.client.try_publish_with_properties(
+            message.topic(),
+            qos(message.qos()),
+            message.retained(),
+            message.payload().to_vec(),
+            properties,
+        );
+        async move { Ok(result?) }
+    }
+
+    /// Queues the subscription to the topic with qos 1.
+    pub fn subscribe(&self, topic: &str) -> impl Future<Output = anyhow::Result<()>> + Send + 'static {
+        self.subscriptions.lock().unwrap().insert(topic.to_string());
+        let result = self.client.try_subscribe(topic, QoS::AtLeastOnce);
+        async move { Ok(result?) }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// The topics subscribed to with any clone of the client, lost by the server when the client reconnects.
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Calls the callback whenever the client connects again after the connection was lost, instead of the one
+    /// set before.
+    pub fn on_reconnect(&self, callback: impl Fn(&Client) + Send + 'static) {
+        *self.reconnected.lock().unwrap() = Some(Box::new(callback));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rumqttc::v5::AsyncClient;
+
+    use crate::rumqttc::{mqtt_options, Client};
+    use crate::ConnectOptions;
+
+    #[test]
+    fn test_mqtt_options() {
+        let address = |uri: &str| mqtt_options(&ConnectOptions::new(uri, "yeelight-controller")).map(|options| options.broker_address());
+
+        assert_eq!(address("tcp://localhost:1883").unwrap(), ("localhost".to_string(), 1883));
+        assert_eq!(address("mqtt://192.168.1.10:1884/").unwrap(), ("192.168.1.10".to_string(), 1884));
+        assert_eq!(address("localhost").unwrap(), ("localhost".to_string(), 1883));
+        assert_eq!(address("tcp://[::1]:1883").unwrap(), ("::1".to_string(), 1883));
+
+        assert!(address("ssl://localhost:8883").is_err());
+        assert!(address("tcp://localhost:mqtt").is_err());
+        assert!(address("tcp://:1883").is_err());
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let (client, _event_loop) = AsyncClient::new(mqtt_options(&ConnectOptions::new("localhost", "launcher")).unwrap(), 10);
+        let client = Client {
+            client,
+            connected: Default::default(),
+            reconnected: Default::default(),
+            subscriptions: Default::default(),
+            messages: async_channel::bounded(1).0,
+        };
+
+        drop(client.subscribe("smart-home-system/yeelight/power/set"));
+        drop(client.clone().subscribe("smart-home-system/knx/living-room-light/power"));
+        drop(client.subscribe("smart-home-system/yeelight/power/set"));
+
+        assert_eq!(client.subscriptions(), ["smart-home-system/knx/living-room-light/power", "smart-home-system/yeelight/power/set"]);
+    }
+}
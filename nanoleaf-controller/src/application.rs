@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use domain_state::{error, Power};
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::{discovery, MQTT_BRIGHTNESS_PUBLISH_TOPIC, MQTT_COLOR_TEMPERATURE_PUBLISH_TOPIC, MQTT_ERROR_TOPIC, MQTT_PAIR_RESULT_TOPIC, MQTT_POWER_PUBLISH_TOPIC};
+use crate::nanoleaf::{Device, State, StateUpdate};
+
+pub struct Application {
+    client: AsyncClient,
+    device: Device,
+    token_path: PathBuf,
+    last_state: Option<State>,
+}
+
+#[derive(Debug)]
+pub struct DeviceFilters {
+    pub id: Option<String>,
+    pub model: Option<String>,
+}
+
+impl DeviceFilters {
+    fn matches(&self, device: &discovery::DiscoveryResponse) -> bool {
+        self.id.iter().all(|id| device.id == *id) &&
+            self.model.iter().all(|model| device.model == *model)
+    }
+}
+
+impl Application {
+    pub async fn new(client: AsyncClient, filter: DeviceFilters, token_path: PathBuf) -> Self {
+        let token = std::fs::read_to_string(&token_path).ok()
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+
+        if token.is_none() {
+            warn!("No nanoleaf auth token found at {:?}. Put the device in pairing mode and publish to the pair topic.", token_path);
+        }
+
+        let device = Self::find_device(filter, token).await;
+
+        Self { client, device, token_path, last_state: None }
+    }
+
+    pub async fn find_device(filter: DeviceFilters, token: Option<String>) -> Device {
+        loop {
+            let result = discovery::discover(Duration::from_secs(3)).await;
+            match result {
+                Ok(discovery) => {
+                    let device = discovery.into_iter().find(|device| filter.matches(device));
+
+                    if let Some(device) = device {
+                        info!("Using nanoleaf device at {}...", device.address);
+                        return Device::new(device.address, token).unwrap();
+                    } else {
+                        warn!("No nanoleaf device found matching filter {filter:?}. Retrying in 30 seconds...");
+                    }
+                }
+                Err(e) => warn!("Nanoleaf discovery failed: {}. Retrying in 30 seconds...", e)
+            }
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        }
+    }
+
+    pub async fn handle_mqtt_pair(&mut self, message: &Message) {
+        info!("[{}] Pairing with nanoleaf device", message.topic());
+
+        let result = match self.device.pair().await {
+            Ok(token) => match std::fs::write(&self.token_path, token) {
+                Ok(()) => {
+                    info!("Paired with nanoleaf device. Auth token stored at {:?}", self.token_path);
+                    "paired".to_string()
+                }
+                Err(e) => {
+                    error!("Paired with nanoleaf device but could not store the auth token: {}", e);
+                    format!("error: {}", e)
+                }
+            },
+            Err(e) => {
+                error!("Failed to pair with nanoleaf device: {}", e);
+                format!("error: {}", e)
+            }
+        };
+
+        self.client.publish(Message::new(MQTT_PAIR_RESULT_TOPIC, result, 1));
+        self.poll().await;
+    }
+
+    pub async fn handle_mqtt_set_power(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        if let Ok(power) = Power::from_str(&payload) {
+            info!("[{}] Setting nanoleaf device power to: {:?}", message.topic(), power);
+            self.update_state(StateUpdate::power(power)).await;
+            return;
+        }
+
+        error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected on or off");
+    }
+
+    pub async fn handle_mqtt_set_brightness(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        if let Ok(brightness) = payload.parse::<u8>() {
+            let brightness = brightness.min(100);
+
+            info!("[{}] Setting nanoleaf device brightness to: {:?}", message.topic(), brightness);
+            self.update_state(StateUpdate::brightness(brightness)).await;
+            return;
+        }
+
+        error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a brightness from 0 to 255");
+    }
+
+    pub async fn handle_mqtt_set_color_temperature(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        if let Ok(ct) = payload.parse::<u16>() {
+            let ct = match &self.last_state {
+                Some(state) => ct.clamp(state.ct.min, state.ct.max),
+                None => ct,
+            };
+
+            info!("[{}] Setting nanoleaf device color temperature to: {:?}", message.topic(), ct);
+            self.update_state(StateUpdate::color_temperature(ct)).await;
+            return;
+        }
+
+        error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a color temperature in kelvin");
+    }
+
+    pub async fn handle_mqtt_get_state(&mut self) {
+        self.last_state = None;
+        self.poll().await;
+    }
+
+    /// Reads the current state of the panels and publishes every value that changed since the last poll.
+    pub async fn poll(&mut self) {
+        if !self.device.is_paired() {
+            return;
+        }
+
+        let state = match self.device.get_state().await {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to read nanoleaf device state: {}", e);
+                return;
+            }
+        };
+
+        let last = self.last_state.as_ref();
+
+        if last.map(|last| &last.on) != Some(&state.on) {
+            info!("Nanoleaf device power is: {:?}", state.power());
+            mqtt_publish(&self.client, MQTT_POWER_PUBLISH_TOPIC, state.power().to_string());
+        }
+
+        if last.map(|last| last.brightness.value) != Some(state.brightness.value) {
+            info!("Nanoleaf device brightness is: {:?}", state.brightness.value);
+            mqtt_publish(&self.client, MQTT_BRIGHTNESS_PUBLISH_TOPIC, state.brightness.value.to_string());
+        }
+
+        if last.map(|last| last.ct.value) != Some(state.ct.value) {
+            info!("Nanoleaf device color temperature is: {:?}", state.ct.value);
+            mqtt_publish(&self.client, MQTT_COLOR_TEMPERATURE_PUBLISH_TOPIC, state.ct.value.to_string());
+        }
+
+        self.last_state = Some(state);
+    }
+
+    async fn update_state(&mut self, update: StateUpdate) {
+        if let Err(e) = self.device.set_state(update).await {
+            error!("Failed to update nanoleaf device state: {}", e);
+            return;
+        }
+
+        self.poll().await;
+    }
+}
+
+fn mqtt_publish(client: &AsyncClient, topic: &str, value: String) {
+    let message = Message::new_retained(topic, value, 1);
+    client.publish(message);
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+const SERVICE_TYPE: &str = "_nanoleafapi._tcp.local.";
+
+#[derive(Debug, PartialEq)]
+pub struct DiscoveryResponse {
+    pub model: String,
+    pub id: String,
+    pub address: String,
+}
+
+fn parse(info: &ServiceInfo) -> anyhow::Result<DiscoveryResponse> {
+    let ip = info.get_addresses_v4().into_iter().next()
+        .context("No ipv4 address found in response")?;
+
+    Ok(DiscoveryResponse {
+        model: info.get_property_val_str("md").context("No model found in response")?.to_string(),
+        id: info.get_property_val_str("id").context("No id found in response")?.to_string(),
+        address: format!("{}:{}", ip, info.get_port()),
+    })
+}
+
+pub async fn discover(timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse>> {
+    let daemon = ServiceDaemon::new().context("Failed to start mdns daemon")?;
+    let receiver = daemon.browse(SERVICE_TYPE).context("Failed to browse for nanoleaf devices")?;
+
+    info!("Discovering {SERVICE_TYPE} with timeout {timeout:?}");
+
+    let mut responses = Vec::new();
+
+    let discover = async {
+        while let Ok(event) = receiver.recv_async().await {
+            if let ServiceEvent::ServiceResolved(info) = event {
+                match parse(&info) {
+                    Ok(discovery) if !responses.contains(&discovery) => {
+                        info!("Found nanoleaf device: {:?}", discovery);
+                        responses.push(discovery);
+                    }
+                    Ok(_) => {}
+                    Err(err) => error!("Failed to parse discovery response from {}: {}", info.get_fullname(), err),
+                }
+            }
+        }
+    };
+
+    let _ = tokio::time::timeout(timeout, discover).await;
+    let _ = daemon.shutdown();
+
+    Ok(responses)
+}
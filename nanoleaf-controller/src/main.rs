@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info};
+
+use crate::application::{Application, DeviceFilters};
+use crate::mqtt::connect_mqtt;
+
+mod nanoleaf;
+mod application;
+mod mqtt;
+mod discovery;
+
+const MQTT_SET_BRIGHTNESS_TOPIC: &str = "smart-home-system/nanoleaf/brightness/set";
+const MQTT_GET_BRIGHTNESS_TOPIC: &str = "smart-home-system/nanoleaf/brightness/get";
+const MQTT_BRIGHTNESS_PUBLISH_TOPIC: &str = "smart-home-system/nanoleaf/brightness";
+const MQTT_SET_POWER_TOPIC: &str = "smart-home-system/nanoleaf/power/set";
+const MQTT_GET_POWER_TOPIC: &str = "smart-home-system/nanoleaf/power/get";
+const MQTT_POWER_PUBLISH_TOPIC: &str = "smart-home-system/nanoleaf/power";
+const MQTT_SET_COLOR_TEMPERATURE_TOPIC: &str = "smart-home-system/nanoleaf/color_temperature/set";
+const MQTT_GET_COLOR_TEMPERATURE_TOPIC: &str = "smart-home-system/nanoleaf/color_temperature/get";
+const MQTT_COLOR_TEMPERATURE_PUBLISH_TOPIC: &str = "smart-home-system/nanoleaf/color_temperature";
+const MQTT_PAIR_TOPIC: &str = "smart-home-system/nanoleaf/admin/pair";
+const MQTT_PAIR_RESULT_TOPIC: &str = "smart-home-system/nanoleaf/admin/pair/result";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/nanoleaf/error";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [
+        MQTT_SET_POWER_TOPIC,
+        MQTT_SET_BRIGHTNESS_TOPIC,
+        MQTT_SET_COLOR_TEMPERATURE_TOPIC,
+        MQTT_GET_POWER_TOPIC,
+        MQTT_GET_BRIGHTNESS_TOPIC,
+        MQTT_GET_COLOR_TEMPERATURE_TOPIC,
+        MQTT_PAIR_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting nanoleaf controller");
+
+    let token_path = std::env::var("NANOLEAF_TOKEN_PATH").unwrap_or_else(|_| "nanoleaf-token".into());
+
+    let mut application = Application::new(client, DeviceFilters {
+        id: std::env::var("NANOLEAF_ID").ok(),
+        model: std::env::var("NANOLEAF_MODEL").ok(),
+    }, token_path.into()).await;
+
+    info!("Found nanoleaf device.");
+
+    info!("Waiting for mqtt messages...");
+
+    let mut poll_interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    match message.topic() {
+                        MQTT_SET_POWER_TOPIC => application.handle_mqtt_set_power(&message).await,
+                        MQTT_SET_BRIGHTNESS_TOPIC => application.handle_mqtt_set_brightness(&message).await,
+                        MQTT_SET_COLOR_TEMPERATURE_TOPIC => application.handle_mqtt_set_color_temperature(&message).await,
+                        MQTT_GET_POWER_TOPIC | MQTT_GET_BRIGHTNESS_TOPIC | MQTT_GET_COLOR_TEMPERATURE_TOPIC =>
+                            application.handle_mqtt_get_state().await,
+                        MQTT_PAIR_TOPIC => application.handle_mqtt_pair(&message).await,
+                        _ => error!("Received message for unknown topic: {}", message.topic()),
+                    }
+                }
+            }
+            _ = poll_interval.tick() => application.poll().await,
+        }
+    }
+
+    Ok(())
+}
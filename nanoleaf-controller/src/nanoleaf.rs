@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use domain_state::Power;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Value<T> {
+    pub value: T,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RangedValue {
+    pub value: u16,
+    pub min: u16,
+    pub max: u16,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct State {
+    pub on: Value<bool>,
+    pub brightness: RangedValue,
+    pub ct: RangedValue,
+}
+
+impl State {
+    pub fn power(&self) -> Power {
+        self.on.value.into()
+    }
+}
+
+/// Body of a `PUT /api/v1/<token>/state` request. Only the fields that are set get sent.
+#[derive(Serialize, Default, Debug)]
+pub struct StateUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on: Option<Value<bool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<Value<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ct: Option<Value<u16>>,
+}
+
+impl StateUpdate {
+    pub fn power(power: Power) -> Self {
+        Self { on: Some(Value { value: power == Power::On }), ..Default::default() }
+    }
+
+    pub fn brightness(brightness: u8) -> Self {
+        Self { brightness: Some(Value { value: brightness }), ..Default::default() }
+    }
+
+    pub fn color_temperature(ct: u16) -> Self {
+        Self { ct: Some(Value { value: ct }), ..Default::default() }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct PairResponse {
+    auth_token: String,
+}
+
+pub struct Device {
+    client: reqwest::Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl Device {
+    pub fn new(address: String, token: Option<String>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .context("Failed to create http client")?;
+
+        Ok(Self { client, base_url: format!("http://{}/api/v1", address), token })
+    }
+
+    pub fn is_paired(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Requests a new auth token. The panel only hands one out while it is in pairing mode,
+    /// which is entered by holding its power button for 5-7 seconds.
+    pub async fn pair(&mut self) -> anyhow::Result<String> {
+        let response = self.client.post(format!("{}/new", self.base_url)).send().await?;
+
+        if response.status() == reqwest::StatusCode::FORBIDDEN {
+            anyhow::bail!("Nanoleaf device is not in pairing mode. Hold the power button for 5-7 seconds and try again.");
+        }
+
+        let response: PairResponse = response.error_for_status()?.json().await?;
+        self.token = Some(response.auth_token.clone());
+
+        Ok(response.auth_token)
+    }
+
+    pub async fn get_state(&self) -> anyhow::Result<State> {
+        let state = self.client.get(format!("{}/state", self.authenticated_url()?))
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        Ok(state)
+    }
+
+    pub async fn set_state(&self, update: StateUpdate) -> anyhow::Result<()> {
+        self.client.put(format!("{}/state", self.authenticated_url()?))
+            .json(&update)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    fn authenticated_url(&self) -> anyhow::Result<String> {
+        let token = self.token.as_ref().context("Nanoleaf device is not paired yet")?;
+        Ok(format!("{}/{}", self.base_url, token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain_state::Power;
+
+    use crate::nanoleaf::{State, StateUpdate};
+
+    #[test]
+    fn test_state_from_json() {
+        let state = "{\"on\":{\"value\":true},\"brightness\":{\"value\":42,\"max\":100,\"min\":0},\
+            \"hue\":{\"value\":0,\"max\":360,\"min\":0},\"sat\":{\"value\":0,\"max\":100,\"min\":0},\
+            \"ct\":{\"value\":4000,\"max\":6500,\"min\":1200},\"colorMode\":\"ct\"}";
+        let state: State = serde_json::from_str(state).unwrap();
+
+        assert_eq!(state.power(), Power::On);
+        assert_eq!(state.brightness.value, 42);
+        assert_eq!(state.ct.value, 4000);
+        assert_eq!(state.ct.min, 1200);
+    }
+
+    #[test]
+    fn test_state_update_to_json() {
+        assert_eq!(serde_json::to_string(&StateUpdate::power(Power::Off)).unwrap(),
+                   "{\"on\":{\"value\":false}}");
+        assert_eq!(serde_json::to_string(&StateUpdate::brightness(50)).unwrap(),
+                   "{\"brightness\":{\"value\":50}}");
+        assert_eq!(serde_json::to_string(&StateUpdate::color_temperature(2700)).unwrap(),
+                   "{\"ct\":{\"value\":2700}}");
+    }
+}
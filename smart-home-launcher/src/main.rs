@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+use mqtt_client::Messages;
+use tracing::{info, warn};
+
+/// Runs the HomeKit bridge and the yeelight controller in one process, for hosts too small for a container
+/// per service like a Raspberry Pi Zero. The services share the connection to the mqtt server of the config of
+/// the bridge, the mqtt server of the config of the controller isn't used. Each service keeps its own config
+/// otherwise, so the settings both read from the env vars, like `HTTP_PORT` and `ADMIN_SOCKET`, must be set in
+/// their config files instead.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The config file of the bridge, instead of env `HOMEKIT_BRIDGE_CONFIG` or `homekit-mqtt-bridge.yaml` if it exists.
+    #[arg(long, value_name = "PATH")]
+    bridge_config: Option<PathBuf>,
+
+    /// The config file of the yeelight controller, instead of env `YEELIGHT_CONFIG` or `yeelight.yaml` if it exists.
+    #[arg(long, value_name = "PATH")]
+    yeelight_config: Option<PathBuf>,
+
+    /// The profile of the configs to use, instead of env `CONFIG_PROFILE`.
+    #[arg(short, long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Which logs are written, as in `RUST_LOG`, instead of the log level of the config of the bridge.
+    #[arg(long, value_name = "FILTER")]
+    log_level: Option<String>,
+
+    /// Runs the yeelight controller against a simulated device, without ever connecting to the real one.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Returns the exit code of the first service that stopped, so the orchestrator restarts all of them.
+fn stopped(service: &str, exit_code: ExitCode) -> ExitCode {
+    warn!("The {} stopped, stopping the other services", service);
+    exit_code
+}
+
+/// Hands every message received on the shared connection to each service, which only handles the ones of the
+/// topics it subscribed to.
+fn fan_out(messages: Messages) -> (Messages, Messages) {
+    let (bridge_sender, bridge_messages) = async_channel::bounded(10);
+    let (yeelight_sender, yeelight_messages) = async_channel::bounded(10);
+
+    tokio::spawn(async move {
+        while let Ok(message) = messages.recv().await {
+            let _ = bridge_sender.send(message.clone()).await;
+            let _ = yeelight_sender.send(message).await;
+        }
+    });
+
+    (bridge_messages, yeelight_messages)
+}
+
+/// Subscribes to the topics of every service again when the shared connection reconnects, as the subscriptions are
+/// lost with the clean start. Only one callback is kept by the client, so it's the launcher's and not the services'.
+fn resubscribe_on_reconnect(client: &mqtt_client::Client) {
+    client.on_reconnect(|client| {
+        info!("Reconnected to the mqtt server, subscribing to the topics of the services again");
+        for topic in client.subscriptions() {
+            // Acknowledged in the background
+            drop(client.subscribe(&topic));
+        }
+    });
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // The logs are the ones of the bridge, the log level of its config applies to every service
+    let logs = match homekit_mqtt_bridge::logging::Logs::init() {
+        Ok(logs) => logs,
+        Err(e) => return homekit_mqtt_bridge::startup::exit(e),
+    };
+
+    let bridge_args = homekit_mqtt_bridge::args::Args {
+        config: args.bridge_config,
+        profile: args.profile.clone(),
+        log_level: args.log_level,
+        check: false,
+    };
+
+    let yeelight_args = yeelight_controller::args::Args {
+        config: args.yeelight_config,
+        profile: args.profile,
+        log_level: None,
+        discover_only: false,
+        check: false,
+        dry_run: args.dry_run,
+        command: None,
+    };
+
+    let connection = match homekit_mqtt_bridge::connect_mqtt(&bridge_args).await {
+        Ok(connection) => connection,
+        Err(e) => return homekit_mqtt_bridge::startup::exit(e),
+    };
+    resubscribe_on_reconnect(&connection.client);
+    let (bridge_messages, yeelight_messages) = fan_out(connection.messages);
+    let bridge_connection = homekit_mqtt_bridge::MqttConnection { client: connection.client.clone(), messages: bridge_messages };
+    let yeelight_connection = yeelight_controller::MqttConnection { client: connection.client, messages: yeelight_messages };
+
+    info!("Starting the homekit-mqtt-bridge and the yeelight-controller");
+
+    tokio::select! {
+        result = homekit_mqtt_bridge::run_shared(bridge_args, logs, bridge_connection) => {
+            stopped("homekit-mqtt-bridge", result.map_or_else(homekit_mqtt_bridge::startup::exit, |()| ExitCode::SUCCESS))
+        }
+        result = yeelight_controller::run_shared(yeelight_args, yeelight_controller::logging::Logs::external(), yeelight_connection) => {
+            stopped("yeelight-controller", result.map_or_else(yeelight_controller::startup::exit, |()| ExitCode::SUCCESS))
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use domain_state::{error, Power};
+use log::{error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+use serde::Serialize;
+
+use crate::{MQTT_DEVICES_PUBLISH_TOPIC, MQTT_ERROR_TOPIC, MQTT_TOPIC_PREFIX};
+use crate::tradfri::{dimmer_to_brightness, DeviceInfo, DeviceKind, DeviceUpdate, Gateway};
+
+pub struct Application {
+    client: AsyncClient,
+    gateway: Gateway,
+    devices: HashMap<u64, DeviceInfo>,
+}
+
+#[derive(Serialize)]
+struct DeviceListing<'a> {
+    id: u64,
+    name: &'a str,
+    kind: DeviceKind,
+}
+
+/// A `smart-home-system/tradfri/<id>/<attribute>/<action>` topic.
+struct DeviceTopic<'a> {
+    id: u64,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let id = parts.next()?.parse().ok()?;
+        let attribute = parts.next()?;
+        let action = parts.next()?;
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self { id, attribute, action })
+    }
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, gateway: Gateway) -> Self {
+        Self { client, gateway, devices: HashMap::new() }
+    }
+
+    /// Enumerates the devices on the gateway and publishes every state that changed since the last refresh.
+    pub async fn refresh(&mut self) {
+        let ids = match self.gateway.device_ids().await {
+            Ok(ids) => ids,
+            Err(e) => {
+                warn!("Failed to enumerate tradfri devices: {}", e);
+                return;
+            }
+        };
+
+        let mut devices = HashMap::new();
+
+        for id in ids {
+            match self.gateway.device(id).await {
+                Ok(device) if device.kind().is_some() => {
+                    self.publish_changes(self.devices.get(&id), &device);
+                    devices.insert(id, device);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to read tradfri device {}: {}", id, e),
+            }
+        }
+
+        let device_set_changed = devices.len() != self.devices.len() ||
+            devices.keys().any(|id| !self.devices.contains_key(id));
+
+        self.devices = devices;
+
+        if device_set_changed {
+            self.publish_device_listing();
+        }
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        let Some(device) = self.devices.get(&topic.id) else {
+            warn!("[{}] Unknown tradfri device {}", message.topic(), topic.id);
+            return;
+        };
+
+        match topic.action {
+            "get" => self.publish_changes(None, device),
+            "set" => self.handle_set(&topic, message).await,
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    async fn handle_set(&mut self, topic: &DeviceTopic<'_>, message: &Message) {
+        let payload = message.payload_str();
+
+        let update = match topic.attribute {
+            "power" => Power::from_str(&payload).ok().map(|power| DeviceUpdate::power(power.is_on())),
+            "brightness" => payload.parse::<u8>().ok().map(DeviceUpdate::brightness),
+            "position" => payload.parse::<u8>().ok().map(DeviceUpdate::position),
+            _ => {
+                error!("[{}] Unsupported attribute '{}'", message.topic(), topic.attribute);
+                return;
+            }
+        };
+
+        let Some(update) = update else {
+            error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &format!("Invalid value for {}", topic.attribute));
+            return;
+        };
+
+        info!("[{}] Setting tradfri device {} {} to: {}", message.topic(), topic.id, topic.attribute, payload);
+
+        if let Err(e) = self.gateway.update_device(topic.id, update).await {
+            error!("Failed to update tradfri device {}: {}", topic.id, e);
+            return;
+        }
+
+        match self.gateway.device(topic.id).await {
+            Ok(device) => {
+                self.publish_changes(self.devices.get(&topic.id), &device);
+                self.devices.insert(topic.id, device);
+            }
+            Err(e) => warn!("Failed to read tradfri device {}: {}", topic.id, e),
+        }
+    }
+
+    fn publish_changes(&self, last: Option<&DeviceInfo>, device: &DeviceInfo) {
+        match device.kind() {
+            Some(DeviceKind::Light) => {
+                let Some(light) = device.light.first() else { return };
+                let last = last.and_then(|last| last.light.first());
+
+                if let Some(on) = light.on.filter(|on| last.and_then(|last| last.on) != Some(*on)) {
+                    let power = Power::from(on == 1);
+                    info!("Tradfri light {} power changed to: {}", device.id, power);
+                    self.publish(device.id, "power", power.to_string());
+                }
+
+                if let Some(dimmer) = light.dimmer.filter(|dimmer| last.and_then(|last| last.dimmer) != Some(*dimmer)) {
+                    let brightness = dimmer_to_brightness(dimmer);
+                    info!("Tradfri light {} brightness changed to: {}", device.id, brightness);
+                    self.publish(device.id, "brightness", brightness.to_string());
+                }
+            }
+            Some(DeviceKind::Blind) => {
+                let Some(blind) = device.blind.first() else { return };
+                let last = last.and_then(|last| last.blind.first());
+
+                if last.map(|last| last.position) != Some(blind.position) {
+                    let position = blind.position.round() as u8;
+                    info!("Tradfri blind {} position changed to: {}", device.id, position);
+                    self.publish(device.id, "position", position.to_string());
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn publish_device_listing(&self) {
+        let listing: Vec<DeviceListing> = self.devices.values()
+            .filter_map(|device| Some(DeviceListing { id: device.id, name: &device.name, kind: device.kind()? }))
+            .collect();
+
+        info!("Found {} tradfri devices", listing.len());
+
+        match serde_json::to_string(&listing) {
+            Ok(listing) => {
+                self.client.publish(Message::new_retained(MQTT_DEVICES_PUBLISH_TOPIC, listing, 1));
+            }
+            Err(e) => error!("Failed to serialize device listing: {}", e),
+        }
+    }
+
+    fn publish(&self, id: u64, attribute: &str, value: String) {
+        let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, id, attribute);
+        self.client.publish(Message::new_retained(topic, value, 1));
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::Context;
+
+const VERSION: u8 = 1;
+const PAYLOAD_MARKER: u8 = 0xFF;
+
+pub const OPTION_URI_PATH: u16 = 11;
+pub const OPTION_CONTENT_FORMAT: u16 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageType {
+    Confirmable,
+    NonConfirmable,
+    Acknowledgement,
+    Reset,
+}
+
+impl MessageType {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Self::Confirmable,
+            1 => Self::NonConfirmable,
+            2 => Self::Acknowledgement,
+            _ => Self::Reset,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Self::Confirmable => 0,
+            Self::NonConfirmable => 1,
+            Self::Acknowledgement => 2,
+            Self::Reset => 3,
+        }
+    }
+}
+
+/// CoAP request/response code, encoded as `class.detail` (e.g. `2.05` is `Code(2, 5)`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Code(pub u8, pub u8);
+
+impl Code {
+    pub const EMPTY: Code = Code(0, 0);
+    pub const GET: Code = Code(0, 1);
+    pub const POST: Code = Code(0, 2);
+    pub const PUT: Code = Code(0, 3);
+
+    fn from_byte(byte: u8) -> Self {
+        Code(byte >> 5, byte & 0b11111)
+    }
+
+    fn byte(self) -> u8 {
+        (self.0 << 5) | (self.1 & 0b11111)
+    }
+
+    pub fn is_success(self) -> bool {
+        self.0 == 2
+    }
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:02}", self.0, self.1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub message_type: MessageType,
+    pub code: Code,
+    pub message_id: u16,
+    pub token: Vec<u8>,
+    /// Options sorted by number, as required by the wire format.
+    pub options: Vec<(u16, Vec<u8>)>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub fn request(code: Code, message_id: u16, token: Vec<u8>, path: &str, payload: Vec<u8>) -> Self {
+        let mut options: Vec<(u16, Vec<u8>)> = path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| (OPTION_URI_PATH, segment.as_bytes().to_vec()))
+            .collect();
+
+        if !payload.is_empty() {
+            // application/json
+            options.push((OPTION_CONTENT_FORMAT, vec![50]));
+        }
+
+        Self { message_type: MessageType::Confirmable, code, message_id, token, options, payload }
+    }
+
+    pub fn ack(message_id: u16) -> Self {
+        Self {
+            message_type: MessageType::Acknowledgement,
+            code: Code::EMPTY,
+            message_id,
+            token: Vec::new(),
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(4 + self.token.len() + self.payload.len() + 16);
+
+        buffer.push((VERSION << 6) | (self.message_type.bits() << 4) | (self.token.len() as u8 & 0x0F));
+        buffer.push(self.code.byte());
+        buffer.extend_from_slice(&self.message_id.to_be_bytes());
+        buffer.extend_from_slice(&self.token);
+
+        let mut last_number = 0;
+        for (number, value) in &self.options {
+            let (delta_nibble, delta_extended) = encode_option_value(number - last_number);
+            let (length_nibble, length_extended) = encode_option_value(value.len() as u16);
+
+            buffer.push((delta_nibble << 4) | length_nibble);
+            buffer.extend_from_slice(&delta_extended);
+            buffer.extend_from_slice(&length_extended);
+            buffer.extend_from_slice(value);
+
+            last_number = *number;
+        }
+
+        if !self.payload.is_empty() {
+            buffer.push(PAYLOAD_MARKER);
+            buffer.extend_from_slice(&self.payload);
+        }
+
+        buffer
+    }
+
+    pub fn decode(buffer: &[u8]) -> anyhow::Result<Self> {
+        let header = buffer.get(..4).context("Message shorter than header")?;
+
+        if header[0] >> 6 != VERSION {
+            anyhow::bail!("Unsupported coap version {}", header[0] >> 6);
+        }
+
+        let message_type = MessageType::from_bits(header[0] >> 4);
+        let token_length = (header[0] & 0x0F) as usize;
+        let code = Code::from_byte(header[1]);
+        let message_id = u16::from_be_bytes([header[2], header[3]]);
+
+        let token = buffer.get(4..4 + token_length).context("Message shorter than token")?.to_vec();
+
+        let mut position = 4 + token_length;
+        let mut options = Vec::new();
+        let mut last_number: u16 = 0;
+
+        while let Some(&byte) = buffer.get(position) {
+            position += 1;
+
+            if byte == PAYLOAD_MARKER {
+                break;
+            }
+
+            let delta = decode_option_value(byte >> 4, buffer, &mut position)?;
+            let length = decode_option_value(byte & 0x0F, buffer, &mut position)? as usize;
+
+            let value = buffer.get(position..position + length).context("Option value out of bounds")?;
+            position += length;
+
+            last_number = last_number.checked_add(delta).context("Option number out of range")?;
+            options.push((last_number, value.to_vec()));
+        }
+
+        let payload = buffer.get(position..).unwrap_or_default().to_vec();
+
+        Ok(Self { message_type, code, message_id, token, options, payload })
+    }
+}
+
+fn encode_option_value(value: u16) -> (u8, Vec<u8>) {
+    match value {
+        0..=12 => (value as u8, Vec::new()),
+        13..=268 => (13, vec![(value - 13) as u8]),
+        _ => (14, (value - 269).to_be_bytes().to_vec()),
+    }
+}
+
+fn decode_option_value(nibble: u8, buffer: &[u8], position: &mut usize) -> anyhow::Result<u16> {
+    match nibble {
+        0..=12 => Ok(nibble as u16),
+        13 => {
+            let byte = *buffer.get(*position).context("Option extension out of bounds")?;
+            *position += 1;
+            Ok(byte as u16 + 13)
+        }
+        14 => {
+            let bytes = buffer.get(*position..*position + 2).context("Option extension out of bounds")?;
+            *position += 2;
+            u16::from_be_bytes([bytes[0], bytes[1]]).checked_add(269).context("Option extension out of range")
+        }
+        _ => anyhow::bail!("Invalid option nibble 15"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coap::{Code, Message, MessageType, OPTION_CONTENT_FORMAT, OPTION_URI_PATH};
+
+    #[test]
+    fn test_request_encode() {
+        let message = Message::request(Code::GET, 0x1234, vec![0xAB], "15001/65537", Vec::new());
+
+        assert_eq!(message.encode(), vec![
+            0x41, 0x01, 0x12, 0x34, 0xAB,
+            0xB5, b'1', b'5', b'0', b'0', b'1',
+            0x05, b'6', b'5', b'5', b'3', b'7',
+        ]);
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let message = Message::request(Code::PUT, 7, vec![1, 2, 3, 4], "15001/65537", b"{\"3311\":[{\"5850\":1}]}".to_vec());
+        let decoded = Message::decode(&message.encode()).unwrap();
+
+        assert_eq!(decoded, message);
+        assert_eq!(decoded.options.last().unwrap(), &(OPTION_CONTENT_FORMAT, vec![50]));
+    }
+
+    #[test]
+    fn test_response_decode() {
+        let response = [0x61, 0x45, 0x00, 0x07, 0xAB, 0xC1, 0x32, 0xFF, b'[', b'1', b']'];
+        let response = Message::decode(&response).unwrap();
+
+        assert_eq!(response.message_type, MessageType::Acknowledgement);
+        assert_eq!(response.code, Code(2, 5));
+        assert_eq!(response.message_id, 7);
+        assert_eq!(response.token, vec![0xAB]);
+        assert_eq!(response.options, vec![(OPTION_CONTENT_FORMAT, vec![50])]);
+        assert_eq!(response.payload, b"[1]");
+        assert_ne!(response.options[0].0, OPTION_URI_PATH);
+    }
+
+    #[test]
+    fn test_option_out_of_range() {
+        // An extended option delta of 0xFFFF + 269, more than an option number can be
+        assert!(Message::decode(&[0x40, 0x01, 0x00, 0x01, 0xE0, 0xFF, 0xFF]).is_err());
+        // An extended option length too
+        assert!(Message::decode(&[0x40, 0x01, 0x00, 0x01, 0x0E, 0xFF, 0xFF]).is_err());
+        // Two deltas adding up to more than an option number can be
+        assert!(Message::decode(&[0x40, 0x01, 0x00, 0x01, 0xE0, 0xFE, 0xF0, 0xE0, 0x00, 0x10]).is_err());
+    }
+
+    #[test]
+    fn test_long_option_encoding() {
+        let path = "a".repeat(300);
+        let message = Message::request(Code::GET, 1, Vec::new(), &path, Vec::new());
+
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use log::info;
+
+use crate::application::Application;
+use crate::mqtt::connect_mqtt;
+use crate::storage::Credentials;
+use crate::tradfri::Gateway;
+
+mod coap;
+mod tradfri;
+mod application;
+mod mqtt;
+mod storage;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/tradfri";
+const MQTT_SET_TOPIC: &str = "smart-home-system/tradfri/+/+/set";
+const MQTT_GET_TOPIC: &str = "smart-home-system/tradfri/+/+/get";
+const MQTT_DEVICES_PUBLISH_TOPIC: &str = "smart-home-system/tradfri/devices";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/tradfri/error";
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+async fn load_credentials(address: &str, storage_path: &PathBuf) -> anyhow::Result<Credentials> {
+    if let Some(credentials) = Credentials::load(storage_path)? {
+        return Ok(credentials);
+    }
+
+    let security_code = std::env::var("TRADFRI_SECURITY_CODE")
+        .context("No stored tradfri credentials found. Set env TRADFRI_SECURITY_CODE to the code on the bottom of the gateway to onboard.")?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let identity = format!("smart-home-system-{}", timestamp);
+
+    info!("Onboarding with tradfri gateway as {}...", identity);
+
+    let psk = Gateway::onboard(address.to_string(), security_code, &identity).await
+        .context("Failed to onboard with the tradfri gateway")?;
+
+    let credentials = Credentials { identity, psk };
+    credentials.save(storage_path)?;
+
+    info!("Onboarded with tradfri gateway. Credentials stored at {:?}", storage_path);
+
+    Ok(credentials)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [MQTT_SET_TOPIC, MQTT_GET_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let mut gateway_address = std::env::var("TRADFRI_GATEWAY_ADDRESS")
+        .context("No tradfri gateway address provided. Set env TRADFRI_GATEWAY_ADDRESS to the address of the gateway.")?;
+
+    if !gateway_address.contains(':') {
+        gateway_address = format!("{}:{}", gateway_address, tradfri::DEFAULT_PORT);
+    }
+
+    let storage_path: PathBuf = std::env::var("TRADFRI_STORAGE_PATH")
+        .unwrap_or_else(|_| "tradfri.json".into())
+        .into();
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting tradfri controller");
+
+    let credentials = load_credentials(&gateway_address, &storage_path).await?;
+
+    let gateway = Gateway::connect(gateway_address, credentials.identity, credentials.psk).await
+        .context("Failed to connect to the tradfri gateway")?;
+
+    info!("Connected to tradfri gateway.");
+
+    let mut application = Application::new(client, gateway);
+
+    info!("Waiting for mqtt messages...");
+
+    let mut refresh_interval = tokio::time::interval(REFRESH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    application.handle_mqtt_message(&message).await;
+                }
+            }
+            _ = refresh_interval.tick() => application.refresh().await,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Credentials handed out by the gateway during onboarding. The security code printed on the
+/// gateway is only needed once to obtain them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Credentials {
+    pub identity: String,
+    pub psk: String,
+}
+
+impl Credentials {
+    pub fn load(path: &Path) -> anyhow::Result<Option<Credentials>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read credentials from {:?}", path))?;
+
+        let credentials = serde_json::from_str(&content)
+            .context(format!("Invalid credentials in {:?}", path))?;
+
+        Ok(Some(credentials))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .context(format!("Failed to write credentials to {:?}", path))
+    }
+}
@@ -0,0 +1,309 @@
+use std::io::{Read, Write};
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{info, warn};
+use openssl::ssl::{Ssl, SslContext, SslMethod, SslStream, SslVerifyMode};
+use serde::{Deserialize, Serialize};
+
+use crate::coap::{Code, Message, MessageType};
+
+pub const DEFAULT_PORT: u16 = 5684;
+pub const ONBOARDING_IDENTITY: &str = "Client_identity";
+
+const DEVICES_PATH: &str = "15001";
+const ONBOARDING_PATH: &str = "15011/9063";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LightControl {
+    #[serde(rename = "5850", default, skip_serializing_if = "Option::is_none")]
+    pub on: Option<u8>,
+    #[serde(rename = "5851", default, skip_serializing_if = "Option::is_none")]
+    pub dimmer: Option<u8>,
+    #[serde(rename = "5711", default, skip_serializing_if = "Option::is_none")]
+    pub color_temperature: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlindControl {
+    #[serde(rename = "5536")]
+    pub position: f32,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    #[serde(rename = "9003")]
+    pub id: u64,
+    #[serde(rename = "9001", default)]
+    pub name: String,
+    #[serde(rename = "5750", default)]
+    pub device_type: u8,
+    #[serde(rename = "9019", default)]
+    pub reachable: u8,
+    #[serde(rename = "3311", default)]
+    pub light: Vec<LightControl>,
+    #[serde(rename = "15015", default)]
+    pub blind: Vec<BlindControl>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Light,
+    Blind,
+}
+
+impl DeviceInfo {
+    pub fn kind(&self) -> Option<DeviceKind> {
+        match self.device_type {
+            2 => Some(DeviceKind::Light),
+            7 => Some(DeviceKind::Blind),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct DeviceUpdate {
+    #[serde(rename = "3311", skip_serializing_if = "Vec::is_empty")]
+    pub light: Vec<LightControl>,
+    #[serde(rename = "15015", skip_serializing_if = "Vec::is_empty")]
+    pub blind: Vec<BlindControl>,
+}
+
+impl DeviceUpdate {
+    pub fn power(on: bool) -> Self {
+        Self { light: vec![LightControl { on: Some(on as u8), dimmer: None, color_temperature: None }], ..Default::default() }
+    }
+
+    /// Takes a brightness in the 0-100 range used on the topics and maps it onto the 0-254 dimmer range.
+    pub fn brightness(brightness: u8) -> Self {
+        let dimmer = (brightness.min(100) as u16 * 254 / 100) as u8;
+        Self { light: vec![LightControl { on: None, dimmer: Some(dimmer), color_temperature: None }], ..Default::default() }
+    }
+
+    pub fn position(position: u8) -> Self {
+        Self { blind: vec![BlindControl { position: position.min(100) as f32 }], ..Default::default() }
+    }
+}
+
+pub fn dimmer_to_brightness(dimmer: u8) -> u8 {
+    ((dimmer as u16 * 100 + 127) / 254) as u8
+}
+
+#[derive(Deserialize, Debug)]
+struct OnboardingResponse {
+    #[serde(rename = "9091")]
+    psk: String,
+}
+
+/// A connected udp socket exposed as a byte stream so it can carry a DTLS session.
+#[derive(Debug)]
+struct UdpStream(UdpSocket);
+
+impl Read for UdpStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.recv(buf)
+    }
+}
+
+impl Write for UdpStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.send(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Gateway {
+    address: String,
+    identity: String,
+    psk: String,
+    stream: Arc<Mutex<Option<SslStream<UdpStream>>>>,
+    message_id: AtomicU16,
+}
+
+impl Gateway {
+    pub async fn connect(address: String, identity: String, psk: String) -> anyhow::Result<Self> {
+        let gateway = Self {
+            address,
+            identity,
+            psk,
+            stream: Arc::new(Mutex::new(None)),
+            message_id: AtomicU16::new(1),
+        };
+
+        let stream = gateway.handshake().await?;
+        *gateway.stream.lock().unwrap() = Some(stream);
+
+        Ok(gateway)
+    }
+
+    /// Exchanges the security code printed on the gateway for a pre-shared key bound to `identity`.
+    pub async fn onboard(address: String, security_code: String, identity: &str) -> anyhow::Result<String> {
+        let gateway = Self::connect(address, ONBOARDING_IDENTITY.into(), security_code).await
+            .context("Failed to connect to the gateway with the security code")?;
+
+        let payload = serde_json::json!({ "9090": identity }).to_string();
+        let response = gateway.request(Code::POST, ONBOARDING_PATH, payload.into_bytes()).await?;
+        let response: OnboardingResponse = serde_json::from_slice(&response)
+            .context("Invalid onboarding response")?;
+
+        Ok(response.psk)
+    }
+
+    pub async fn device_ids(&self) -> anyhow::Result<Vec<u64>> {
+        let response = self.request(Code::GET, DEVICES_PATH, Vec::new()).await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    pub async fn device(&self, id: u64) -> anyhow::Result<DeviceInfo> {
+        let response = self.request(Code::GET, &format!("{}/{}", DEVICES_PATH, id), Vec::new()).await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    pub async fn update_device(&self, id: u64, update: DeviceUpdate) -> anyhow::Result<()> {
+        let payload = serde_json::to_vec(&update)?;
+        self.request(Code::PUT, &format!("{}/{}", DEVICES_PATH, id), payload).await?;
+        Ok(())
+    }
+
+    async fn request(&self, code: Code, path: &str, payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let message_id = self.message_id.fetch_add(1, Ordering::Relaxed);
+        let request = Message::request(code, message_id, message_id.to_be_bytes().to_vec(), path, payload);
+
+        let stream = self.stream.clone();
+        let exchange = request.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut stream = stream.lock().unwrap();
+            let stream = stream.as_mut().context("Not connected to the gateway")?;
+            exchange_blocking(stream, &exchange)
+        }).await?;
+
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                // The gateway drops idle DTLS sessions, so retry once with a fresh handshake.
+                warn!("Request to {} failed: {}. Reconnecting to the gateway...", path, e);
+                let stream = self.handshake().await?;
+
+                let shared = self.stream.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut shared = shared.lock().unwrap();
+                    let stream = shared.insert(stream);
+                    exchange_blocking(stream, &request)
+                }).await??
+            }
+        };
+
+        if !response.code.is_success() {
+            anyhow::bail!("Gateway responded to {} with {}", path, response.code);
+        }
+
+        Ok(response.payload)
+    }
+
+    async fn handshake(&self) -> anyhow::Result<SslStream<UdpStream>> {
+        let address = self.address.clone();
+        let identity = self.identity.clone();
+        let psk = self.psk.clone();
+
+        info!("Establishing DTLS session with gateway at {}...", address);
+
+        tokio::task::spawn_blocking(move || {
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(&address)?;
+            socket.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+            let mut context = SslContext::builder(SslMethod::dtls())?;
+            // The gateway only speaks PSK-AES128-CCM8, which OpenSSL 3 hides behind security level 0.
+            context.set_cipher_list("PSK-AES128-CCM8:@SECLEVEL=0")?;
+            context.set_verify(SslVerifyMode::NONE);
+            context.set_psk_client_callback(move |_, _, identity_buffer, psk_buffer| {
+                let identity = identity.as_bytes();
+                let psk = psk.as_bytes();
+
+                if identity.len() >= identity_buffer.len() || psk.len() > psk_buffer.len() {
+                    return Ok(0);
+                }
+
+                identity_buffer[..identity.len()].copy_from_slice(identity);
+                identity_buffer[identity.len()] = 0;
+                psk_buffer[..psk.len()].copy_from_slice(psk);
+
+                Ok(psk.len())
+            });
+
+            let ssl = Ssl::new(&context.build())?;
+            ssl.connect(UdpStream(socket)).map_err(|e| anyhow::anyhow!("DTLS handshake failed: {}", e))
+        }).await?
+    }
+}
+
+fn exchange_blocking(stream: &mut SslStream<UdpStream>, request: &Message) -> anyhow::Result<Message> {
+    stream.write_all(&request.encode())?;
+
+    let mut buffer = [0; 4096];
+
+    loop {
+        let length = stream.read(&mut buffer)?;
+        let response = Message::decode(&buffer[..length])?;
+
+        match response.message_type {
+            // An empty acknowledgement means the response will follow in a separate message.
+            MessageType::Acknowledgement if response.message_id == request.message_id && response.code != Code::EMPTY => {
+                return Ok(response);
+            }
+            MessageType::Confirmable | MessageType::NonConfirmable if response.token == request.token => {
+                if response.message_type == MessageType::Confirmable {
+                    stream.write_all(&Message::ack(response.message_id).encode())?;
+                }
+                return Ok(response);
+            }
+            MessageType::Reset if response.message_id == request.message_id => {
+                anyhow::bail!("Gateway reset the request");
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tradfri::{dimmer_to_brightness, DeviceInfo, DeviceKind, DeviceUpdate};
+
+    #[test]
+    fn test_light_from_json() {
+        let light = "{\"9003\":65537,\"9001\":\"Kitchen\",\"5750\":2,\"9019\":1,\
+            \"3311\":[{\"5850\":1,\"5851\":127,\"5711\":370,\"9003\":0}]}";
+        let light: DeviceInfo = serde_json::from_str(light).unwrap();
+
+        assert_eq!(light.id, 65537);
+        assert_eq!(light.name, "Kitchen");
+        assert_eq!(light.kind(), Some(DeviceKind::Light));
+        assert_eq!(light.light[0].on, Some(1));
+        assert_eq!(dimmer_to_brightness(light.light[0].dimmer.unwrap()), 50);
+    }
+
+    #[test]
+    fn test_blind_from_json() {
+        let blind = "{\"9003\":65540,\"9001\":\"Bedroom blind\",\"5750\":7,\"9019\":1,\"15015\":[{\"5536\":35.0}]}";
+        let blind: DeviceInfo = serde_json::from_str(blind).unwrap();
+
+        assert_eq!(blind.kind(), Some(DeviceKind::Blind));
+        assert_eq!(blind.blind[0].position, 35.0);
+    }
+
+    #[test]
+    fn test_device_update_to_json() {
+        assert_eq!(serde_json::to_string(&DeviceUpdate::power(true)).unwrap(), "{\"3311\":[{\"5850\":1}]}");
+        assert_eq!(serde_json::to_string(&DeviceUpdate::brightness(100)).unwrap(), "{\"3311\":[{\"5851\":254}]}");
+        assert_eq!(serde_json::to_string(&DeviceUpdate::position(40)).unwrap(), "{\"15015\":[{\"5536\":40.0}]}");
+    }
+}
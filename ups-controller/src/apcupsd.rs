@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::ups::UpsStatus;
+
+pub const PORT: u16 = 3551;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends the `status` command to the apcupsd network information server and parses the report.
+pub async fn read_status(address: &str) -> anyhow::Result<UpsStatus> {
+    let report = tokio::time::timeout(TIMEOUT, request(address, "status")).await
+        .context("Timed out waiting for apcupsd")??;
+
+    parse_status(&report)
+}
+
+/// Every message, in both directions, is prefixed by its length as a big endian u16. The
+/// response is one message per line, terminated by an empty message.
+async fn request(address: &str, command: &str) -> anyhow::Result<String> {
+    let mut stream = TcpStream::connect(address).await
+        .with_context(|| format!("Failed to connect to apcupsd at {}", address))?;
+
+    stream.write_all(&(command.len() as u16).to_be_bytes()).await?;
+    stream.write_all(command.as_bytes()).await?;
+
+    let mut report = String::new();
+
+    loop {
+        let length = stream.read_u16().await? as usize;
+        if length == 0 {
+            return Ok(report);
+        }
+
+        let mut line = vec![0u8; length];
+        stream.read_exact(&mut line).await?;
+        report.push_str(&String::from_utf8_lossy(&line));
+    }
+}
+
+/// Parses a report of `KEY : value` lines, e.g. `BCHARGE  : 100.0 Percent`.
+pub fn parse_status(report: &str) -> anyhow::Result<UpsStatus> {
+    let fields: HashMap<&str, &str> = report.lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim(), value.trim()))
+        .collect();
+
+    let status = fields.get("STATUS").context("No STATUS in apcupsd report")?;
+    let flags: Vec<&str> = status.split_whitespace().collect();
+
+    let number = |key: &str| fields.get(key)
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse::<f64>().ok());
+
+    Ok(UpsStatus {
+        on_battery: flags.contains(&"ONBATT"),
+        low_battery: flags.contains(&"LOWBATT"),
+        battery_charge: number("BCHARGE"),
+        load: number("LOADPCT"),
+        runtime: number("TIMELEFT"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apcupsd::parse_status;
+    use crate::ups::UpsStatus;
+
+    #[test]
+    fn test_parse_status() {
+        let report = "APC      : 001,036,0879\n\
+            DATE     : 2024-01-01 10:00:00 +0000\n\
+            UPSNAME  : server\n\
+            STATUS   : ONBATT LOWBATT\n\
+            LOADPCT  : 23.0 Percent\n\
+            BCHARGE  : 8.0 Percent\n\
+            TIMELEFT : 2.5 Minutes\n";
+
+        assert_eq!(parse_status(report).unwrap(), UpsStatus {
+            on_battery: true,
+            low_battery: true,
+            battery_charge: Some(8.0),
+            load: Some(23.0),
+            runtime: Some(2.5),
+        });
+
+        let status = parse_status("STATUS   : ONLINE\n").unwrap();
+        assert!(!status.on_battery);
+        assert_eq!(status.battery_charge, None);
+
+        assert!(parse_status("UPSNAME  : server\n").is_err());
+    }
+}
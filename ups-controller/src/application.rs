@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+use paho_mqtt::{AsyncClient, Message};
+
+use crate::MQTT_TOPIC_PREFIX;
+use crate::ups::{Source, UpsStatus};
+
+pub struct Application {
+    client: AsyncClient,
+    source: Source,
+    /// Last published payload by attribute.
+    published: HashMap<&'static str, String>,
+}
+
+fn on_off(value: bool) -> String {
+    if value { "on" } else { "off" }.into()
+}
+
+/// Converts the status into `(attribute, payload)` pairs published under `smart-home-system/ups`.
+fn attributes(status: &UpsStatus) -> Vec<(&'static str, String)> {
+    let mut attributes = vec![("on_battery", on_off(status.on_battery)), ("low_battery", on_off(status.low_battery))];
+
+    if let Some(charge) = status.battery_charge {
+        attributes.push(("battery", format!("{:.0}", charge)));
+    }
+
+    if let Some(load) = status.load {
+        attributes.push(("load", format!("{:.0}", load)));
+    }
+
+    if let Some(runtime) = status.runtime {
+        attributes.push(("runtime", format!("{:.1}", runtime)));
+    }
+
+    attributes
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, source: Source) -> Self {
+        Self { client, source, published: HashMap::new() }
+    }
+
+    /// Reads the status of the UPS and publishes every value that changed since the last poll.
+    pub async fn poll(&mut self, force: bool) {
+        let status = match self.source.read().await {
+            Ok(status) => status,
+            Err(e) => {
+                warn!("Failed to read UPS status: {}", e);
+                return;
+            }
+        };
+
+        for (attribute, payload) in attributes(&status) {
+            if !force && self.published.get(attribute) == Some(&payload) {
+                continue;
+            }
+
+            if attribute == "on_battery" && self.published.contains_key(attribute) {
+                match status.on_battery {
+                    true => warn!("UPS switched to battery power"),
+                    false => info!("UPS power was restored"),
+                }
+            }
+
+            info!("UPS {} is: {}", attribute, payload);
+
+            let topic = format!("{}/{}", MQTT_TOPIC_PREFIX, attribute);
+            self.client.publish(Message::new_retained(topic, payload.clone(), 1));
+            self.published.insert(attribute, payload);
+        }
+    }
+
+    pub async fn handle_mqtt_get(&mut self) {
+        self.poll(true).await;
+    }
+}
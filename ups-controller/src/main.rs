@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info};
+
+use crate::application::Application;
+use crate::mqtt::connect_mqtt;
+use crate::ups::Source;
+
+mod ups;
+mod apcupsd;
+mod snmp;
+mod application;
+mod mqtt;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/ups";
+const MQTT_GET_TOPIC: &str = "smart-home-system/ups/+/get";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+fn with_default_port(address: String, port: u16) -> String {
+    if address.contains(':') {
+        address
+    } else {
+        format!("{}:{}", address, port)
+    }
+}
+
+async fn create_source() -> anyhow::Result<Source> {
+    let source = std::env::var("UPS_SOURCE").unwrap_or_else(|_| "apcupsd".into());
+    let address = std::env::var("UPS_ADDRESS").unwrap_or_else(|_| "localhost".into());
+
+    match source.as_str() {
+        "apcupsd" => Ok(Source::Apcupsd(with_default_port(address, apcupsd::PORT))),
+        "snmp" => {
+            let community = std::env::var("UPS_SNMP_COMMUNITY").unwrap_or_else(|_| "public".into());
+            let client = snmp::Client::connect(&with_default_port(address, snmp::PORT), community).await?;
+            Ok(Source::Snmp(client))
+        }
+        _ => anyhow::bail!("Unknown UPS source {}. Set env UPS_SOURCE to apcupsd or snmp.", source),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [MQTT_GET_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let source = create_source().await?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting UPS controller");
+
+    let mut application = Application::new(client, source);
+
+    info!("Waiting for mqtt messages...");
+
+    let mut poll_interval = tokio::time::interval(POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    if message.topic().starts_with(MQTT_TOPIC_PREFIX) && message.topic().ends_with("/get") {
+                        application.handle_mqtt_get().await;
+                    } else {
+                        error!("Received message for unknown topic: {}", message.topic());
+                    }
+                }
+            }
+            _ = poll_interval.tick() => application.poll(false).await,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,344 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use tokio::net::UdpSocket;
+
+use crate::ups::UpsStatus;
+
+pub const PORT: u16 = 161;
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_GET_REQUEST: u8 = 0xA0;
+const TAG_GET_RESPONSE: u8 = 0xA2;
+
+const VERSION_1: i64 = 0;
+
+// UPS-MIB (RFC 1628) objects
+const UPS_BATTERY_STATUS: &[u32] = &[1, 3, 6, 1, 2, 1, 33, 1, 2, 1, 0];
+const UPS_ESTIMATED_MINUTES_REMAINING: &[u32] = &[1, 3, 6, 1, 2, 1, 33, 1, 2, 3, 0];
+const UPS_ESTIMATED_CHARGE_REMAINING: &[u32] = &[1, 3, 6, 1, 2, 1, 33, 1, 2, 4, 0];
+const UPS_OUTPUT_SOURCE: &[u32] = &[1, 3, 6, 1, 2, 1, 33, 1, 4, 1, 0];
+const UPS_OUTPUT_PERCENT_LOAD: &[u32] = &[1, 3, 6, 1, 2, 1, 33, 1, 4, 4, 1, 5, 1];
+
+const BATTERY_STATUS_LOW: i64 = 3;
+const BATTERY_STATUS_DEPLETED: i64 = 4;
+const OUTPUT_SOURCE_BATTERY: i64 = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(i64),
+    Unsigned(u64),
+    /// Any other type, e.g. strings or the v2c `noSuchObject` exception.
+    Other,
+}
+
+/// An object id and its value.
+pub type VarBind = (Vec<u32>, Value);
+
+impl Value {
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            Value::Unsigned(value) => i64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+}
+
+fn encode_length(length: usize, buffer: &mut Vec<u8>) {
+    if length < 0x80 {
+        buffer.push(length as u8);
+    } else {
+        let bytes: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+        buffer.push(0x80 | bytes.len() as u8);
+        buffer.extend_from_slice(&bytes);
+    }
+}
+
+fn encode_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut buffer = vec![tag];
+    encode_length(content.len(), &mut buffer);
+    buffer.extend_from_slice(content);
+    buffer
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+
+    // drop leading bytes that only repeat the sign
+    let mut start = 0;
+    while start < 7 && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xFF && bytes[start + 1] & 0x80 != 0)) {
+        start += 1;
+    }
+
+    encode_tlv(TAG_INTEGER, &bytes[start..])
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut content = vec![(oid[0] * 40 + oid[1]) as u8];
+
+    for &component in &oid[2..] {
+        let mut encoded = vec![(component & 0x7F) as u8];
+        let mut rest = component >> 7;
+        while rest > 0 {
+            encoded.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(encoded.iter().rev());
+    }
+
+    encode_tlv(TAG_OID, &content)
+}
+
+/// Builds an SNMPv1 `GetRequest` for the given objects.
+pub fn encode_get_request(community: &str, request_id: i32, oids: &[&[u32]]) -> Vec<u8> {
+    let varbinds: Vec<u8> = oids.iter()
+        .flat_map(|oid| encode_tlv(TAG_SEQUENCE, &[encode_oid(oid), encode_tlv(TAG_NULL, &[])].concat()))
+        .collect();
+
+    let pdu = [
+        encode_integer(request_id as i64),
+        encode_integer(0), // error status
+        encode_integer(0), // error index
+        encode_tlv(TAG_SEQUENCE, &varbinds),
+    ].concat();
+
+    let message = [
+        encode_integer(VERSION_1),
+        encode_tlv(TAG_OCTET_STRING, community.as_bytes()),
+        encode_tlv(TAG_GET_REQUEST, &pdu),
+    ].concat();
+
+    encode_tlv(TAG_SEQUENCE, &message)
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn tlv(&mut self) -> anyhow::Result<(u8, &'a [u8])> {
+        let (&tag, rest) = self.data.split_first().context("Unexpected end of message")?;
+        let (&first, mut rest) = rest.split_first().context("Unexpected end of message")?;
+
+        let length = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let count = (first & 0x7F) as usize;
+            if count > 4 || rest.len() < count {
+                bail!("Invalid length");
+            }
+            let length = rest[..count].iter().fold(0usize, |length, byte| length << 8 | *byte as usize);
+            rest = &rest[count..];
+            length
+        };
+
+        if rest.len() < length {
+            bail!("Value is truncated");
+        }
+
+        self.data = &rest[length..];
+        Ok((tag, &rest[..length]))
+    }
+
+    fn expect(&mut self, expected: u8) -> anyhow::Result<&'a [u8]> {
+        match self.tlv()? {
+            (tag, content) if tag == expected => Ok(content),
+            (tag, _) => bail!("Expected tag {:#04x} but got {:#04x}", expected, tag),
+        }
+    }
+
+    fn integer(&mut self) -> anyhow::Result<i64> {
+        Ok(decode_integer(self.expect(TAG_INTEGER)?))
+    }
+}
+
+fn decode_integer(content: &[u8]) -> i64 {
+    let initial = if content.first().is_some_and(|byte| byte & 0x80 != 0) { -1 } else { 0 };
+    content.iter().fold(initial, |value, byte| value << 8 | *byte as i64)
+}
+
+fn decode_oid(content: &[u8]) -> Vec<u32> {
+    let Some((&first, rest)) = content.split_first() else { return vec![] };
+
+    let mut oid = vec![(first / 40) as u32, (first % 40) as u32];
+    let mut component = 0u32;
+
+    for byte in rest {
+        component = component << 7 | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            oid.push(component);
+            component = 0;
+        }
+    }
+
+    oid
+}
+
+/// Parses a `GetResponse` into its request id and variable bindings.
+pub fn parse_response(message: &[u8]) -> anyhow::Result<(i64, Vec<VarBind>)> {
+    let mut message = Decoder { data: Decoder { data: message }.expect(TAG_SEQUENCE)? };
+
+    let _version = message.integer()?;
+    let _community = message.expect(TAG_OCTET_STRING)?;
+
+    let mut pdu = Decoder { data: message.expect(TAG_GET_RESPONSE)? };
+
+    let request_id = pdu.integer()?;
+    let error_status = pdu.integer()?;
+    let error_index = pdu.integer()?;
+    if error_status != 0 {
+        bail!("Agent returned error status {} for object {}", error_status, error_index);
+    }
+
+    let mut varbinds = Decoder { data: pdu.expect(TAG_SEQUENCE)? };
+    let mut values = Vec::new();
+
+    while !varbinds.is_empty() {
+        let mut varbind = Decoder { data: varbinds.expect(TAG_SEQUENCE)? };
+        let oid = decode_oid(varbind.expect(TAG_OID)?);
+
+        let value = match varbind.tlv()? {
+            (TAG_INTEGER, content) => Value::Integer(decode_integer(content)),
+            (TAG_COUNTER32 | TAG_GAUGE32 | TAG_TIMETICKS, content) => {
+                Value::Unsigned(content.iter().fold(0u64, |value, byte| value << 8 | *byte as u64))
+            }
+            _ => Value::Other,
+        };
+
+        values.push((oid, value));
+    }
+
+    Ok((request_id, values))
+}
+
+pub struct Client {
+    socket: UdpSocket,
+    community: String,
+    request_id: i32,
+}
+
+impl Client {
+    pub async fn connect(address: &str, community: String) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(address).await.with_context(|| format!("Failed to resolve snmp agent {}", address))?;
+
+        Ok(Self { socket, community, request_id: 0 })
+    }
+
+    pub async fn get(&mut self, oids: &[&[u32]]) -> anyhow::Result<Vec<VarBind>> {
+        self.request_id = self.request_id.checked_add(1).unwrap_or(1);
+        let request_id = self.request_id;
+
+        self.socket.send(&encode_get_request(&self.community, request_id, oids)).await?;
+
+        tokio::time::timeout(TIMEOUT, async {
+            let mut buffer = [0u8; 1500];
+
+            loop {
+                let length = self.socket.recv(&mut buffer).await?;
+
+                let (response_id, values) = parse_response(&buffer[..length])?;
+
+                // skip late answers to requests that timed out
+                if response_id == request_id as i64 {
+                    return Ok(values);
+                }
+            }
+        }).await.context("Timed out waiting for the snmp agent")?
+    }
+}
+
+/// Reads the status of the UPS from the standard UPS-MIB objects.
+pub async fn read_status(client: &mut Client) -> anyhow::Result<UpsStatus> {
+    let values = client.get(&[
+        UPS_BATTERY_STATUS,
+        UPS_ESTIMATED_MINUTES_REMAINING,
+        UPS_ESTIMATED_CHARGE_REMAINING,
+        UPS_OUTPUT_SOURCE,
+        UPS_OUTPUT_PERCENT_LOAD,
+    ]).await?;
+
+    let value = |oid: &[u32]| values.iter()
+        .find(|(value_oid, _)| value_oid == oid)
+        .and_then(|(_, value)| value.as_i64());
+
+    let output_source = value(UPS_OUTPUT_SOURCE).context("UPS did not report its output source")?;
+    let battery_status = value(UPS_BATTERY_STATUS);
+
+    Ok(UpsStatus {
+        on_battery: output_source == OUTPUT_SOURCE_BATTERY,
+        low_battery: matches!(battery_status, Some(BATTERY_STATUS_LOW | BATTERY_STATUS_DEPLETED)),
+        battery_charge: value(UPS_ESTIMATED_CHARGE_REMAINING).map(|charge| charge as f64),
+        load: value(UPS_OUTPUT_PERCENT_LOAD).map(|load| load as f64),
+        runtime: value(UPS_ESTIMATED_MINUTES_REMAINING).map(|minutes| minutes as f64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snmp::{decode_oid, encode_get_request, encode_integer, encode_oid, parse_response, Value};
+
+    #[test]
+    fn test_encode_primitives() {
+        assert_eq!(encode_integer(0), [0x02, 0x01, 0x00]);
+        assert_eq!(encode_integer(128), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(encode_integer(-1), [0x02, 0x01, 0xFF]);
+        assert_eq!(encode_oid(&[1, 3, 6, 1, 2, 1, 33, 1, 2, 4, 0]),
+                   [0x06, 0x0A, 0x2B, 0x06, 0x01, 0x02, 0x01, 0x21, 0x01, 0x02, 0x04, 0x00]);
+        assert_eq!(decode_oid(&[0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37]), [1, 3, 6, 1, 4, 1, 311]);
+    }
+
+    #[test]
+    fn test_encode_get_request() {
+        let request = encode_get_request("public", 1, &[&[1, 3, 6, 1, 2, 1, 33, 1, 2, 4, 0]]);
+
+        assert_eq!(request, [
+            0x30, 0x28,
+            0x02, 0x01, 0x00,
+            0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+            0xA0, 0x1B,
+            0x02, 0x01, 0x01,
+            0x02, 0x01, 0x00,
+            0x02, 0x01, 0x00,
+            0x30, 0x10, 0x30, 0x0E,
+            0x06, 0x0A, 0x2B, 0x06, 0x01, 0x02, 0x01, 0x21, 0x01, 0x02, 0x04, 0x00,
+            0x05, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = [
+            0x30, 0x29,
+            0x02, 0x01, 0x00,
+            0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+            0xA2, 0x1C,
+            0x02, 0x01, 0x07,
+            0x02, 0x01, 0x00,
+            0x02, 0x01, 0x00,
+            0x30, 0x11, 0x30, 0x0F,
+            0x06, 0x0A, 0x2B, 0x06, 0x01, 0x02, 0x01, 0x21, 0x01, 0x02, 0x04, 0x00,
+            0x02, 0x01, 0x64,
+        ];
+
+        let (request_id, values) = parse_response(&response).unwrap();
+
+        assert_eq!(request_id, 7);
+        assert_eq!(values, [(vec![1, 3, 6, 1, 2, 1, 33, 1, 2, 4, 0], Value::Integer(100))]);
+        assert!(parse_response(&response[..20]).is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use crate::apcupsd;
+use crate::snmp;
+
+/// The state of the UPS as published to mqtt.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpsStatus {
+    pub on_battery: bool,
+    pub low_battery: bool,
+    /// Battery charge in percent.
+    pub battery_charge: Option<f64>,
+    /// Output load in percent of the UPS capacity.
+    pub load: Option<f64>,
+    /// Estimated runtime on battery in minutes.
+    pub runtime: Option<f64>,
+}
+
+/// Where the status of the UPS is read from.
+pub enum Source {
+    /// The network information server of apcupsd, for UPSs connected over usb or serial.
+    Apcupsd(String),
+    /// A UPS network card implementing the standard UPS-MIB (RFC 1628).
+    Snmp(snmp::Client),
+}
+
+impl Source {
+    pub async fn read(&mut self) -> anyhow::Result<UpsStatus> {
+        match self {
+            Source::Apcupsd(address) => apcupsd::read_status(address).await,
+            Source::Snmp(client) => snmp::read_status(client).await,
+        }
+    }
+}
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Included instead of depending on the controller, which links the mqtt client
+#[allow(dead_code)]
+#[path = "../../src/discovery.rs"]
+mod discovery;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(response) = discovery::parse(data) {
+        let _ = response.location.trim_start_matches("yeelight://");
+    }
+});
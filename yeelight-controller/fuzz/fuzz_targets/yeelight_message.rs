@@ -0,0 +1,34 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+
+// Included instead of depending on the controller, which links the mqtt client
+#[allow(dead_code)]
+#[path = "../../src/protocol.rs"]
+mod protocol;
+
+use protocol::{parse_brightness, parse_power, ResponseResult, YeelightMessage};
+
+// Parses a line as the connection to the device does, and then its values as the controller does
+fuzz_target!(|data: &[u8]| {
+    match YeelightMessage::parse(data) {
+        Ok(YeelightMessage::Response(response)) => match response.result {
+            ResponseResult::Success(result) => {
+                for value in result {
+                    let value = Value::from(value);
+                    let _ = parse_power(&value);
+                    let _ = parse_brightness(&value);
+                }
+            }
+            ResponseResult::Error { .. } => {}
+        },
+        Ok(YeelightMessage::Notification(notification)) => {
+            for value in notification.params.values() {
+                let _ = parse_power(value);
+                let _ = parse_brightness(value);
+            }
+        }
+        Err(_) => {}
+    }
+});
@@ -0,0 +1,158 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+
+use axum::extract::ws::{self, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use domain_state::Topic;
+use mqtt_client::Message;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info};
+
+use crate::application::{Command, Request};
+use crate::error::ControllerError;
+use crate::health::Health;
+use crate::state::StateFile;
+use crate::topic;
+
+#[derive(Clone)]
+pub struct Api {
+    /// Set once the device is found.
+    device_id: Arc<OnceLock<String>>,
+    health: Health,
+    state_file: StateFile,
+    requests: mpsc::Sender<Request>,
+}
+
+impl Api {
+    pub fn new(health: Health, state_file: StateFile, requests: mpsc::Sender<Request>) -> Self {
+        Self { device_id: Arc::new(OnceLock::new()), health, state_file, requests }
+    }
+
+    pub fn set_device_id(&self, id: &str) {
+        let _ = self.device_id.set(id.to_string());
+    }
+
+    fn has_device(&self, id: &str) -> bool {
+        self.device_id.get().is_some_and(|device_id| device_id == id)
+    }
+}
+
+fn not_found(id: &str) -> Response {
+    (StatusCode::NOT_FOUND, format!("No yeelight device with id {}", id)).into_response()
+}
+
+async fn devices(State(api): State<Api>) -> Response {
+    let devices: Vec<_> = api.device_id.get().into_iter()
+        .map(|id| serde_json::json!({ "id": id, "state": format!("{:?}", api.health.device_state()) }))
+        .collect();
+
+    Json(devices).into_response()
+}
+
+async fn device_state(State(api): State<Api>, Path(id): Path<String>) -> Response {
+    if !api.has_device(&id) {
+        return not_found(&id);
+    }
+
+    Json(api.state_file.state()).into_response()
+}
+
+/// Sends the command of a message received on the topic, answering once the device handled it.
+async fn send_command(api: &Api, topic: Topic, payload: &str) -> Response {
+    let message = Message::new(topic, payload, 0);
+    let command = match Command::from_message(&message) {
+        Ok(Some(command)) => command,
+        Ok(None) => return (StatusCode::NOT_FOUND, format!("No command on {}", message.topic())).into_response(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let (result, receiver) = oneshot::channel();
+    if api.requests.send(Request { command, message: Some(message), result: Some(result) }).await.is_err() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "The controller is shutting down").into_response();
+    }
+
+    match receiver.await {
+        Ok(Ok(())) => StatusCode::NO_CONTENT.into_response(),
+        Ok(Err(e @ ControllerError::InvalidPayload(_))) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        Ok(Err(e @ ControllerError::Disconnected)) => (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+        Ok(Err(e)) => (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "The controller is shutting down").into_response(),
+    }
+}
+
+/// Sets the power to the body, `on` or `off`.
+async fn set_power(State(api): State<Api>, Path(id): Path<String>, body: String) -> Response {
+    if !api.has_device(&id) {
+        return not_found(&id);
+    }
+
+    send_command(&api, topic().power().set(), body.trim()).await
+}
+
+/// Sets the brightness to the body, from 1 to 100.
+async fn set_brightness(State(api): State<Api>, Path(id): Path<String>, body: String) -> Response {
+    if !api.has_device(&id) {
+        return not_found(&id);
+    }
+
+    send_command(&api, topic().brightness().set(), body.trim()).await
+}
+
+/// Sends the state of the device as json when connected and whenever it changes.
+async fn events(State(api): State<Api>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(|mut socket| async move {
+        let mut changes = api.state_file.subscribe();
+
+        loop {
+            let state = changes.borrow_and_update().clone();
+            let event = serde_json::json!({ "device": api.device_id.get(), "state": state });
+
+            if socket.send(ws::Message::Text(event.to_string())).await.is_err() {
+                return;
+            }
+
+            // The messages received are ignored, they're only read to notice the socket was closed
+            loop {
+                tokio::select! {
+                    changed = changes.changed() => match changed {
+                        Ok(()) => break,
+                        Err(_) => return,
+                    },
+                    received = socket.recv() => if !matches!(received, Some(Ok(_))) { return },
+                }
+            }
+        }
+    })
+}
+
+async fn index() -> Html<&'static str> {
+    Html(include_str!("index.html"))
+}
+
+/// Serves `GET /devices`, `GET /devices/{id}/state`, `POST /devices/{id}/power` and `POST /devices/{id}/brightness`,
+/// for clients that can't use mqtt, the `/events` websocket and a web page to control the device at `/`.
+pub async fn serve(address: SocketAddr, api: Api) {
+    let router = Router::new()
+        .route("/devices", get(devices))
+        .route("/devices/:id/state", get(device_state))
+        .route("/devices/:id/power", post(set_power))
+        .route("/devices/:id/brightness", post(set_brightness))
+        .route("/events", get(events))
+        .route("/", get(index))
+        .with_state(api);
+
+    let server = match axum::Server::try_bind(&address) {
+        Ok(server) => server,
+        Err(e) => return error!("Failed to serve the api on {}: {}", address, e),
+    };
+
+    info!("Serving the api on http://{}", address);
+
+    if let Err(e) = server.serve(router.into_make_service()).await {
+        error!("Api server failed: {}", e);
+    }
+}
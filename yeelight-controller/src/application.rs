@@ -1,23 +1,55 @@
+use std::future::Future;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use log::{error, info, warn};
-use paho_mqtt::{AsyncClient, Message};
-use tokio::sync::mpsc;
+use anyhow::Context;
+use domain_state::topic::{Action, Attribute};
+use domain_state::{Brightness, ColorTemperature, ParseError, Power, Topic};
+use mqtt_client::Message;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, info_span, warn, Instrument};
 
-use crate::{discovery, MQTT_BRIGHTNESS_PUBLISH_TOPIC, MQTT_POWER_PUBLISH_TOPIC};
-use crate::yeelight::{Device, Method, Notification, Power, ResponseResult};
+use crate::{device_info, discovery, mqtt, telemetry, topic, DEVICE};
+use crate::config::TimeoutsConfig;
+use crate::device_info::{DeviceInfo, INFO_INTERVAL};
+use crate::error::ControllerError;
+use crate::fade::{Fade, FadeRequest, FadeStep, FADE_STEP};
+use crate::health::{DeviceState, Health};
+use crate::mqtt::{Client, Publish};
+use crate::state::StateFile;
+use crate::supervisor;
+use crate::protocol::{parse_brightness, parse_power, Method, Notification, ResponseResult};
+use crate::yeelight::Device;
+
+/// The color temperatures of the yeelight bulbs, in kelvin.
+const MIN_COLOR_TEMPERATURE: u16 = 1700;
+const MAX_COLOR_TEMPERATURE: u16 = 6500;
 
 pub struct Application {
-    client: AsyncClient,
+    client: Client,
     device: Device,
     handle: tokio::task::JoinHandle<()>,
+    /// Shared with the notification handler, which aborts the fade when the device is changed by someone else.
+    fade: Arc<Mutex<Option<Fade>>>,
+    state_file: StateFile,
+    /// To find the device again when the connection to it is lost.
+    filter: DeviceFilters,
+    timeouts: TimeoutsConfig,
+    notification_capacity: usize,
+    health: Health,
+    brightness_zero_turns_off: bool,
 }
 
-#[derive(Debug)]
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
 pub struct DeviceFilters {
     pub id: Option<String>,
     pub model: Option<String>,
+    /// Connected to without discovering the device, e.g. `192.168.1.10:55443` for a device with a static address.
+    pub address: Option<String>,
 }
 
 impl DeviceFilters {
@@ -27,6 +59,83 @@ impl DeviceFilters {
     }
 }
 
+/// What the application is asked to do, by mqtt, the api, the admin console or the controller itself.
+#[derive(Debug)]
+pub enum Command {
+    SetPower(Power),
+    /// Clamped to the brightness of the device, from 1 to 100, or turning it off for 0 if configured.
+    SetBrightness(u8),
+    /// The power, brightness and color temperature changed together, any of them left out.
+    Set { power: Option<Power>, brightness: Option<Brightness>, color_temperature: Option<ColorTemperature> },
+    Toggle,
+    Fade(FadeRequest),
+    /// Publishes the power read from the device.
+    GetPower,
+    /// Publishes the brightness read from the device.
+    GetBrightness,
+    /// Publishes the state read from the device, replacing the stale state.
+    Poll,
+    /// Connects to the device again, finding it again if it's not at its last address anymore.
+    Reconnect,
+    /// Answered with the ids of the commands sent to the device that weren't answered yet.
+    Pending(oneshot::Sender<Vec<u64>>),
+}
+
+impl Command {
+    /// The command of a message received on a topic of the controller, none for the topics it doesn't handle.
+    pub fn from_message(message: &Message) -> Result<Option<Self>, ControllerError> {
+        let payload = message.payload_str();
+        let invalid = |e: String| ControllerError::InvalidPayload(anyhow::Error::msg(e));
+        let invalid_state = |e: ParseError| ControllerError::InvalidPayload(e.into());
+
+        let Some(topic) = message.topic().parse::<Topic>().ok().filter(|topic| topic.device_name() == DEVICE) else {
+            return Ok(None);
+        };
+
+        let command = match (topic.attribute(), topic.action()) {
+            (Some(Attribute::Power), Some(Action::Set)) => Command::SetPower(payload.parse().map_err(invalid_state)?),
+            // Up to 255, the brightness applied instead of the one above 100 is published
+            (Some(Attribute::Brightness), Some(Action::Set)) => Command::SetBrightness(
+                payload.trim().parse().context("Invalid brightness").map_err(ControllerError::InvalidPayload)?
+            ),
+            (Some(Attribute::Brightness), Some(Action::Fade)) => Command::Fade(FadeRequest::from_str(&payload).map_err(invalid)?),
+            // A power, brightness and color temperature set together, e.g. by a homekit scene
+            (None, Some(Action::Set)) => {
+                let request: SetRequest = serde_json::from_slice(message.payload()).context("Invalid set command")
+                    .map_err(ControllerError::InvalidPayload)?;
+                if request.power.is_none() && request.brightness.is_none() && request.color_temperature.is_none() {
+                    return Err(invalid("The set command has no power, brightness or color temperature".into()));
+                }
+                Command::Set { power: request.power, brightness: request.brightness, color_temperature: request.color_temperature }
+            }
+            (None, Some(Action::Toggle)) => Command::Toggle,
+            (Some(Attribute::Power), Some(Action::Get)) => Command::GetPower,
+            (Some(Attribute::Brightness), Some(Action::Get)) => Command::GetBrightness,
+            _ => return Ok(None),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// A command sent to [`Application::run`], which handles them one at a time.
+pub struct Request {
+    pub command: Command,
+    /// The message the command was received as, published to the error topic with the error if it fails.
+    pub message: Option<Message>,
+    /// Answered once the command was handled, e.g. for the api.
+    pub result: Option<oneshot::Sender<Result<(), ControllerError>>>,
+}
+
+/// The payload of `smart-home-system/yeelight/set`, e.g. `{"power":"on","brightness":40,"color_temperature":2700}`
+/// with any of the fields left out, as published by the homekit bridge for the characteristics changed together.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SetRequest {
+    power: Option<Power>,
+    brightness: Option<Brightness>,
+    color_temperature: Option<ColorTemperature>,
+}
+
 impl Drop for Application {
     fn drop(&mut self) {
         self.handle.abort();
@@ -34,137 +143,784 @@ impl Drop for Application {
 }
 
 impl Application {
-    pub async fn new(client: AsyncClient, filter: DeviceFilters) -> Self {
-        let (device, mut notification_receiver) = Self::find_device(filter).await;
+    pub async fn new(
+        client: Client,
+        filter: DeviceFilters,
+        timeouts: &TimeoutsConfig,
+        notification_capacity: usize,
+        health: Health,
+        state_file: StateFile,
+        brightness_zero_turns_off: bool,
+    ) -> Self {
+        let (device, notification_receiver) = Self::find_device(filter.clone(), timeouts, notification_capacity, health.clone()).await;
 
-        let c = client.clone();
+        let fade = Arc::new(Mutex::new(None));
+        let handle = spawn_notification_handler(client.clone(), fade.clone(), state_file.clone(), notification_receiver);
 
-        let handle = tokio::spawn(async move {
-            while let Some(notification) = notification_receiver.recv().await {
-                handle_yeelight_notification(&c, notification);
+        Self {
+            client,
+            device,
+            handle,
+            fade,
+            state_file,
+            filter,
+            timeouts: timeouts.clone(),
+            notification_capacity,
+            health,
+            brightness_zero_turns_off,
+        }
+    }
+
+    /// Waits until the connection to the device is lost, with why.
+    async fn connection_lost(&self) -> String {
+        self.device.connection_lost().await
+    }
+
+    /// Marks the state as stale after the connection to the device was lost, the commands fail until it's
+    /// connected to again by the returned future and passed to `reconnected`. The device is connected to at its
+    /// last address first, and discovered again if it's not there anymore.
+    fn disconnected(&mut self) -> impl Future<Output = (Device, mpsc::Receiver<Notification>)> + Send + 'static {
+        mqtt_publish_stale(&self.client, true);
+        *self.fade.lock().unwrap() = None;
+        self.health.set_device_state(DeviceState::Discovering);
+
+        let (id, address, model) = (self.device.id().to_string(), self.device.address().to_string(), self.device.model().map(String::from));
+        let (filter, timeouts, health) = (self.filter.clone(), self.timeouts.clone(), self.health.clone());
+        let notification_capacity = self.notification_capacity;
+        async move {
+            let (sender, receiver) = mpsc::channel(notification_capacity);
+            info!("Connecting to yeelight device at {} again...", address);
+            match Device::new(id, address.clone(), sender, health.clone()).await {
+                Ok(device) => (device.with_model(model), receiver),
+                Err(e) => {
+                    warn!("Failed to connect to yeelight device at {} again: {}. Discovering it...", address, e);
+                    Self::find_device(filter, &timeouts, notification_capacity, health).await
+                }
             }
-        });
+        }
+    }
+
+    async fn reconnected(&mut self, device: Device, notification_receiver: mpsc::Receiver<Notification>) {
+        self.handle.abort();
+        self.handle = spawn_notification_handler(self.client.clone(), self.fade.clone(), self.state_file.clone(), notification_receiver);
+        self.device = device;
+
+        info!("Reconnected to yeelight device.");
+        self.publish_current_state().await;
+        self.publish_info();
+    }
+
+    #[cfg(feature = "api")]
+    pub fn device_id(&self) -> &str {
+        self.device.id()
+    }
+
+    pub fn device_model(&self) -> Option<&str> {
+        self.device.model()
+    }
+
+    /// Publishes the state read from the device, replacing the stale state published on startup.
+    pub async fn publish_current_state(&mut self) {
+        self.get_power().await;
+        self.get_brightness().await;
+        self.read_color_temperature().await;
+        mqtt_publish_stale(&self.client, false);
+    }
+
+    /// Publishes the info of the device in the background, as asking it for its firmware takes the discovery
+    /// timeout.
+    fn publish_info(&self) {
+        let info = DeviceInfo::new(self.device.id(), self.device.address(), self.device.model());
+        tokio::spawn(device_info::publish(self.client.clone(), info, self.timeouts.discovery()));
+    }
+
+    /// Remembers the color temperature of the device, for the scenes that keep it.
+    async fn read_color_temperature(&mut self) {
+        let Ok(properties) = self.send(Method::get_prop(vec!["ct".into()])).await else { return };
+
+        // Empty for the bulbs without one
+        if let Some(color_temperature) = properties.first().and_then(|ct| ct.parse::<u16>().ok()) {
+            self.state_file.update(|state| state.color_temperature = Some(color_temperature));
+        }
+    }
+
+    /// Discovers the device until it's found and connected to. Its notifications are coalesced once
+    /// `notification_capacity` of them wait to be handled.
+    pub async fn find_device(
+        filter: DeviceFilters,
+        timeouts: &TimeoutsConfig,
+        notification_capacity: usize,
+        health: Health,
+    ) -> (Device, mpsc::Receiver<Notification>) {
+        let (sender, receiver) = mpsc::channel(notification_capacity);
+
+        loop {
+            let found = match &filter.address {
+                Some(address) => Some((filter.id.clone().unwrap_or_else(|| address.clone()), address.clone(), filter.model.clone())),
+                None => Self::discover_device(&filter, timeouts).await,
+            };
+
+            if let Some((id, address, model)) = found {
+                info!("Connecting to yeelight device at {}...", address);
+                match Device::new(id, address.clone(), sender.clone(), health.clone()).await {
+                    Ok(device) => return (device.with_model(model), receiver),
+                    Err(e) => warn!("Failed to connect to yeelight device at {}: {}. Retrying in {:?}...", address, e, timeouts.discovery_retry()),
+                }
+            }
+            tokio::time::sleep(timeouts.discovery_retry()).await;
+        }
+    }
 
-        Self { client, device, handle }
+    /// The id, address and model of the first device discovered matching the filter, if any.
+    async fn discover_device(filter: &DeviceFilters, timeouts: &TimeoutsConfig) -> Option<(String, String, Option<String>)> {
+        match discovery::discover(timeouts.discovery()).await {
+            Ok(discovery) => {
+                let device = discovery.into_iter().find(|device| filter.matches(device));
+                if device.is_none() {
+                    warn!("No yeelight device found matching filter {filter:?}. Retrying in {:?}...", timeouts.discovery_retry());
+                }
+                device.map(|device| (device.id, device.location.trim_start_matches("yeelight://").to_string(), Some(device.model)))
+            }
+            Err(e) => {
+                warn!("Yeelight discovery failed: {}. Retring in {:?}...", e, timeouts.discovery_retry());
+                None
+            }
+        }
     }
 
-    pub async fn find_device(filter: DeviceFilters) -> (Device, mpsc::Receiver<Notification>) {
-        let (sender, receiver) = mpsc::channel(1);
+    /// Handles the requests one at a time, in the order they're sent, with the brightness fade, the periodic info
+    /// of the device and finding it again after losing the connection, until every sender of the requests is dropped.
+    pub async fn run(mut self, mut requests: mpsc::Receiver<Request>) {
+        let mut fade_interval = tokio::time::interval(FADE_STEP);
+        let mut info_interval = tokio::time::interval(INFO_INTERVAL);
+        // Finding the device again after losing the connection, while the commands keep failing without waiting for it
+        let mut reconnecting = None;
 
         loop {
-            let result = discovery::discover(Duration::from_secs(3)).await;
-            match result {
-                Ok(discovery) => {
-                    let device = discovery.into_iter().find(|device| filter.matches(device));
-
-                    if let Some(device) = device {
-                        let address = device.location.trim_start_matches("yeelight://").to_string();
-                        info!("Connecting to yeelight device at {}...", address);
-                        return (Device::new(address, sender).await.unwrap(), receiver);
-                    } else {
-                        warn!("No yeelight device found matching filter {filter:?}. Retrying in 30 seconds...");
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(Request { command: Command::Reconnect, result, .. }) => {
+                        if reconnecting.is_none() {
+                            warn!("Reconnecting to the yeelight device as requested");
+                            reconnecting = Some(Box::pin(self.disconnected()));
+                        }
+                        if let Some(result) = result {
+                            let _ = result.send(Ok(()));
+                        }
+                    }
+                    Some(request) => self.handle_request(request).await,
+                    None => return,
+                },
+                reason = self.connection_lost(), if reconnecting.is_none() => {
+                    warn!("Reconnecting to the yeelight device after losing the connection: {}", reason);
+                    reconnecting = Some(Box::pin(self.disconnected()));
+                }
+                (device, notification_receiver) = async { reconnecting.as_mut().unwrap().await }, if reconnecting.is_some() => {
+                    reconnecting = None;
+                    self.reconnected(device, notification_receiver).await;
+                }
+                _ = info_interval.tick(), if reconnecting.is_none() => self.publish_info(),
+                _ = fade_interval.tick() => {
+                    if let Err(e) = self.fade_step().await {
+                        error!("Yeelight brightness fade step failed: {:#}", e);
                     }
                 }
-                Err(e) => warn!("Yeelight discovery failed: {}. Retring in 30 seconds...", e)
             }
-            tokio::time::sleep(Duration::from_secs(30)).await;
         }
     }
 
-    pub async fn handle_mqtt_toggle(&mut self, message: &Message) {
-        info!("[{}] Toggling yeelight device",  message.topic());
-        self.device.send_method(Method::TOGGLE).await.unwrap();
+    async fn handle_request(&mut self, request: Request) {
+        let span = info_span!("command", topic = request.message.as_ref().map(Message::topic));
+        if let Some(message) = &request.message {
+            telemetry::set_parent(&span, message);
+        }
+
+        async {
+            let result = self.handle(request.command).await;
+            if let Err(e) = &result {
+                match &request.message {
+                    Some(message) => publish_error(&self.client, message, e),
+                    None => error!("Failed to handle the command: {}", e),
+                }
+            }
+            if let Some(sender) = request.result {
+                let _ = sender.send(result);
+            }
+        }.instrument(span).await
     }
 
-    pub async fn handle_mqtt_brightness_set(&mut self, message: &Message) {
-        let payload = message.payload_str();
+    pub async fn handle(&mut self, command: Command) -> Result<(), ControllerError> {
+        match command {
+            Command::SetPower(power) => self.set_power(power).await?,
+            Command::SetBrightness(brightness) => self.set_brightness(brightness).await?,
+            Command::Set { power, brightness, color_temperature } => self.set(power, brightness, color_temperature).await?,
+            Command::Toggle => self.toggle().await?,
+            Command::Fade(request) => self.fade(request).await?,
+            Command::GetPower => self.get_power().await,
+            Command::GetBrightness => self.get_brightness().await,
+            Command::Poll => self.publish_current_state().await,
+            // Handled by `run`, which keeps the connection being made
+            Command::Reconnect => {}
+            Command::Pending(ids) => {
+                let _ = ids.send(self.device.pending_requests().ids().await);
+            }
+        }
+        Ok(())
+    }
 
-        if let Ok(brightness) = message.payload_str().parse::<u8>() {
-            let brightness = brightness.max(1).min(100);
+    /// Sends the method, failing if the device answers with an error, e.g. when its command quota is exceeded.
+    async fn send(&self, method: Method) -> Result<Vec<String>, ControllerError> {
+        if !self.device.is_connected() {
+            return Err(ControllerError::Disconnected);
+        }
 
-            info!("[{}] Setting yeelight device brightness to: {:?}",  message.topic(), brightness);
-            self.device.send_method(Method::set_brightness(brightness)).await.expect("Could not send set_brightness method");
-            return;
+        match self.device.send_method(method).await.map_err(ControllerError::Device)?.result {
+            ResponseResult::Success(result) => Ok(result),
+            ResponseResult::Error { code, message } => Err(ControllerError::Rejected { code, message }),
         }
+    }
 
-        error!("[{}] Received invalid payload: '{}'", message.topic(), payload);
+    async fn toggle(&mut self) -> Result<(), ControllerError> {
+        self.abort_fade();
+        info!("Toggling yeelight device");
+        self.send(Method::TOGGLE).await?;
+        Ok(())
     }
 
-    pub async fn handle_mqtt_set_power(&mut self, message: &Message) {
-        let payload = message.payload_str();
+    async fn set_brightness(&mut self, requested: u8) -> Result<(), ControllerError> {
+        self.abort_fade();
 
-        if let Ok(power) = Power::from_str(&payload) {
-            info!("[{}] Setting yeelight device power to: {:?}", message.topic(), power);
-            self.device.send_method(Method::set_power(power)).await.expect("Could not send set_power method");
-            return;
+        if requested == 0 && self.brightness_zero_turns_off {
+            // The device keeps its brightness while it's off, so it's restored when it's turned on
+            info!("Turning yeelight device off for brightness 0");
+            self.send(Method::set_power(Power::Off)).await?;
+            return Ok(());
         }
 
-        error!("[{}] Received invalid payload: '{}'", message.topic(), payload);
+        let brightness = requested.clamp(1, 100);
+
+        info!("Setting yeelight device brightness to: {:?}", brightness);
+        self.send(Method::set_brightness(brightness)).await?;
+
+        // The device doesn't notify a brightness it already had, so the one applied instead of the requested
+        // one is published here
+        if brightness != requested {
+            mqtt_publish_brightness(&self.client, &self.state_file, brightness);
+        }
+        Ok(())
     }
 
-    pub async fn handle_mqtt_get_power(&mut self) {
-        let response = self.device.send_method(Method::get_prop(vec!("power".into()))).await.expect("Could not send get_prop method");
+    async fn set_power(&mut self, power: Power) -> Result<(), ControllerError> {
+        self.abort_fade();
+        info!("Setting yeelight device power to: {:?}", power);
+        self.send(Method::set_power(power)).await?;
+        Ok(())
+    }
+
+    /// Sets the power, brightness and color temperature changed together. The device is turned on with them by a
+    /// single `set_scene`, instead of going through the states in between, once its color temperature is known.
+    async fn set(
+        &mut self,
+        mut power: Option<Power>,
+        brightness: Option<Brightness>,
+        color_temperature: Option<ColorTemperature>,
+    ) -> Result<(), ControllerError> {
+        let requested = brightness.map(Brightness::percent);
+        if requested == Some(0) && self.brightness_zero_turns_off {
+            power = Some(Power::Off);
+        }
+        self.abort_fade();
+
+        if power == Some(Power::Off) {
+            info!("Setting yeelight device power to: {:?}", Power::Off);
+            self.send(Method::set_power(Power::Off)).await?;
+            return Ok(());
+        }
+
+        let brightness = requested.map(|brightness| brightness.clamp(1, 100));
+        let color_temperature = color_temperature
+            .map(|color_temperature| color_temperature.kelvin().clamp(MIN_COLOR_TEMPERATURE, MAX_COLOR_TEMPERATURE));
+        let state = self.state_file.state();
+
+        let scene = match (color_temperature, &power, brightness) {
+            (Some(color_temperature), _, brightness) => Some((color_temperature, brightness.or(state.brightness).unwrap_or(100).max(1))),
+            (None, Some(Power::On), Some(brightness)) => state.color_temperature.map(|color_temperature| (color_temperature, brightness)),
+            _ => None,
+        };
+
+        if let Some((color_temperature, brightness)) = scene {
+            info!("Turning yeelight device on at {}K and brightness {}", color_temperature, brightness);
+            self.send(Method::set_scene_color_temperature(color_temperature, brightness)).await?;
+        } else {
+            // The brightness can only be changed while the device is on, so it's turned on first
+            if let Some(power) = power {
+                info!("Setting yeelight device power to: {:?}", power);
+                self.send(Method::set_power(power)).await?;
+            }
+            if let Some(brightness) = brightness {
+                info!("Setting yeelight device brightness to: {:?}", brightness);
+                self.send(Method::set_brightness(brightness)).await?;
+            }
+        }
+
+        // As in `set_brightness`, the device doesn't notify a brightness it already had
+        if let (Some(requested), Some(brightness)) = (requested, brightness) {
+            if brightness != requested {
+                mqtt_publish_brightness(&self.client, &self.state_file, brightness);
+            }
+        }
+        Ok(())
+    }
+
+    fn abort_fade(&self) {
+        if self.fade.lock().unwrap().take().is_some() {
+            info!("Aborting yeelight brightness fade");
+        }
+    }
+
+    async fn fade(&mut self, request: FadeRequest) -> Result<(), ControllerError> {
+        let properties = self.send(Method::get_prop(vec!("power".into(), "bright".into()))).await?;
+
+        let is_on = properties.first().is_some_and(|power| power == "on");
+        let brightness = properties.get(1).and_then(|brightness| brightness.parse::<u8>().ok()).unwrap_or(1);
+
+        info!("Fading yeelight device brightness to {} over {:?}", request.target, request.duration);
+
+        if is_on {
+            *self.fade.lock().unwrap() = Some(Fade::new(brightness, &request, Instant::now()));
+        } else {
+            // The brightness can only be changed while the device is on, so it's turned on at the minimum brightness first
+            let mut fade = Fade::new(1, &request, Instant::now());
+            fade.expect(brightness);
+            *self.fade.lock().unwrap() = Some(fade);
+
+            self.send(Method::set_power(Power::On)).await?;
+            self.send(Method::set_brightness(1)).await?;
+        }
+
+        self.fade_step().await
+    }
+
+    /// Sends the next brightness change of the running fade, if any.
+    async fn fade_step(&mut self) -> Result<(), ControllerError> {
+        let step = match self.fade.lock().unwrap().as_mut() {
+            Some(fade) => fade.step(Instant::now()),
+            None => return Ok(()),
+        };
+
+        match step {
+            FadeStep::Brightness(brightness) => {
+                self.send(Method::set_brightness_smooth(brightness, FADE_STEP)).await?;
+            }
+            FadeStep::Off => {
+                *self.fade.lock().unwrap() = None;
+                info!("Yeelight brightness fade finished, turning the device off");
+                self.send(Method::set_power(Power::Off)).await?;
+            }
+            FadeStep::Done => {
+                *self.fade.lock().unwrap() = None;
+                info!("Yeelight brightness fade finished");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_power(&mut self) {
+        let response = match self.device.send_method(Method::get_prop(vec!("power".into()))).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Couldn't get the power of the yeelight device: {}", e);
+                return;
+            }
+        };
 
         info!("Getting yeelight device power: {:?}", response);
 
         match response.result {
             ResponseResult::Success(response) => {
-                if let Some(power) = response.first() {
-                    mqtt_publish_power(&self.client, Power::from_str(power).unwrap());
-                };
+                match response.first().and_then(|power| Power::from_str(power).ok()) {
+                    Some(power) => mqtt_publish_power(&self.client, &self.state_file, power),
+                    None => warn!("Couldn't parse power value from {:?} received from yeelight", response),
+                }
             }
             ResponseResult::Error { .. } => {}
         }
     }
 
-    pub async fn handle_mqtt_get_brightness(&mut self) {
-        let response = self.device.send_method(Method::get_prop(vec!("bright".into()))).await.expect("Could not send get_prop method");
+    async fn get_brightness(&mut self) {
+        let response = match self.device.send_method(Method::get_prop(vec!("bright".into()))).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Couldn't get the brightness of the yeelight device: {}", e);
+                return;
+            }
+        };
 
         info!("Getting yeelight device brightness: {:?}", response);
 
         match response.result {
             ResponseResult::Success(response) => {
-                if let Some(brightness) = response.first() {
-                    mqtt_publish_brightness(&self.client, brightness.parse().unwrap());
-                };
+                match response.first().and_then(|brightness| parse_brightness(&Value::from(brightness.as_str()))) {
+                    Some(brightness) => mqtt_publish_brightness(&self.client, &self.state_file, brightness),
+                    None => warn!("Couldn't parse brightness value from {:?} received from yeelight", response),
+                }
             }
             ResponseResult::Error { .. } => {}
         }
     }
 }
 
-fn handle_yeelight_notification(client: &AsyncClient, notification: Notification) {
+/// Restarted if handling a notification panics, so the next ones are still published.
+fn spawn_notification_handler(
+    client: Client,
+    fade: Arc<Mutex<Option<Fade>>>,
+    state_file: StateFile,
+    notification_receiver: mpsc::Receiver<Notification>,
+) -> tokio::task::JoinHandle<()> {
+    let notification_receiver = Arc::new(tokio::sync::Mutex::new(notification_receiver));
+    supervisor::supervise("yeelight notification handler", move || {
+        let (client, fade, state_file) = (client.clone(), fade.clone(), state_file.clone());
+        let notification_receiver = notification_receiver.clone();
+        async move {
+            let mut notification_receiver = notification_receiver.lock().await;
+            while let Some(notification) = notification_receiver.recv().await {
+                handle_yeelight_notification(&client, &fade, &state_file, notification);
+            }
+        }
+    })
+}
+
+fn handle_yeelight_notification(client: &dyn Publish, fade: &Mutex<Option<Fade>>, state_file: &StateFile, notification: Notification) {
     info!("Received notification: {:?}", notification);
 
+    abort_fade_on_change(fade, &notification);
+
     notification.params.iter().for_each(|(key, value)| {
         match key.as_ref() {
             "power" => {
-                if let Ok(power) = Power::from_str(value.as_str().unwrap()) {
+                if let Some(power) = parse_power(value) {
                     info!("Yeelight device power changed to: {:?}", power);
-                    mqtt_publish_power(client, power);
+                    mqtt_publish_power(client, state_file, power);
                 } else {
                     warn!("Couldn't parse power value from '{:?}' received from yeelight", value);
                 }
             }
             "bright" => {
-                if let Some(value) = value.as_u64() {
+                if let Some(value) = parse_brightness(value) {
                     info!("Yeelight device brightness changed to: {:?}", value);
-                    mqtt_publish_brightness(client, value as u8);
+                    mqtt_publish_brightness(client, state_file, value);
                 } else {
                     warn!("Couldn't parse brighness value from '{:?}' received from yeelight", value);
                 }
             }
+            "ct" => {
+                if let Some(value) = value.as_u64().and_then(|ct| u16::try_from(ct).ok()) {
+                    info!("Yeelight device color temperature changed to: {:?}", value);
+                    state_file.update(|state| state.color_temperature = Some(value));
+                } else {
+                    warn!("Couldn't parse color temperature value from '{:?}' received from yeelight", value);
+                }
+            }
             _ => {}
         }
     });
 }
 
-fn mqtt_publish_power(client: &AsyncClient, power: Power) {
-    let message = Message::new_retained(MQTT_POWER_PUBLISH_TOPIC, power.to_string(), 1);
-    client.publish(message);
+/// Aborts the running fade when the device is turned off or its brightness is changed by someone else.
+fn abort_fade_on_change(fade: &Mutex<Option<Fade>>, notification: &Notification) {
+    let mut fade = fade.lock().unwrap();
+    let Some(running) = fade.as_ref() else { return };
+
+    let turned_off = notification.params.get("power").and_then(|power| power.as_str()) == Some("off");
+    let changed = notification.params.get("bright")
+        .and_then(parse_brightness)
+        .is_some_and(|brightness| !running.is_expected(brightness));
+
+    if turned_off || changed {
+        info!("Yeelight device was changed during a brightness fade, aborting it");
+        *fade = None;
+    }
+}
+
+/// Publishes a command that failed to `smart-home-system/yeelight/error`, so it can be surfaced to the user.
+pub fn publish_error(client: &dyn Publish, message: &Message, error: &ControllerError) {
+    error!("[{}] Failed to handle '{}': {}", message.topic(), message.payload_str(), error);
+
+    let payload = serde_json::json!({
+        "topic": message.topic(),
+        "payload": message.payload_str(),
+        "kind": error.kind(),
+        "error": error.to_string(),
+    });
+    mqtt::publish(client, Message::new(topic().error(), payload.to_string(), 1));
+}
+
+/// Publishes the state from before the restart, marked as stale until the device is reachable.
+pub fn publish_last_state(client: &dyn Publish, state_file: &StateFile) {
+    let state = state_file.state();
+    if state.power.is_none() && state.brightness.is_none() {
+        return;
+    }
+
+    info!("Publishing the last known state of the yeelight device: {:?}", state);
+    mqtt_publish_stale(client, true);
+
+    if let Some(power) = state.power {
+        mqtt::publish(client, Message::new_retained(topic().power(), power, 1));
+    }
+
+    if let Some(brightness) = state.brightness {
+        mqtt::publish(client, Message::new_retained(topic().brightness(), brightness.to_string(), 1));
+    }
+}
+
+fn mqtt_publish_stale(client: &dyn Publish, stale: bool) {
+    let message = Message::new_retained(topic().stale(), stale.to_string(), 1);
+    mqtt::publish(client, message);
+}
+
+fn mqtt_publish_power(client: &dyn Publish, state_file: &StateFile, power: Power) {
+    state_file.update(|state| state.power = Some(power.to_string()));
+    let message = Message::new_retained(topic().power(), power.to_string(), 1);
+    mqtt::publish(client, message);
 }
 
-fn mqtt_publish_brightness(client: &AsyncClient, brightness: u8) {
-    let message = Message::new_retained(MQTT_BRIGHTNESS_PUBLISH_TOPIC, brightness.to_string(), 1);
-    client.publish(message);
-}
\ No newline at end of file
+fn mqtt_publish_brightness(client: &dyn Publish, state_file: &StateFile, brightness: u8) {
+    state_file.update(|state| state.brightness = Some(brightness));
+    let message = Message::new_retained(topic().brightness(), brightness.to_string(), 1);
+    mqtt::publish(client, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use domain_state::Power;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use mqtt_client::Message;
+    use tokio::sync::{mpsc, oneshot};
+
+    use crate::application::{
+        handle_yeelight_notification, publish_error, publish_last_state, Application, Command, DeviceFilters, Request,
+    };
+    use crate::config::TimeoutsConfig;
+    #[cfg(feature = "discovery")]
+    use crate::discovery::{discover_at, FakeResponder};
+    use crate::error::ControllerError;
+    use crate::health::Health;
+    use crate::mqtt::FakeClient;
+    use crate::protocol::Notification;
+    use crate::state::{LastState, StateFile};
+    use crate::yeelight::Device;
+
+    fn state_file(name: &str) -> StateFile {
+        let path = std::env::temp_dir().join(format!("yeelight-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        StateFile::load(path).unwrap()
+    }
+
+    fn published(client: &FakeClient) -> Vec<(String, String)> {
+        let mut published = client.take_published();
+        published.sort();
+        published
+    }
+
+    fn message(topic: &str, payload: &str) -> (String, String) {
+        (topic.to_string(), payload.to_string())
+    }
+
+    #[cfg(feature = "discovery")]
+    #[tokio::test]
+    async fn test_device_filters() {
+        let responder = FakeResponder::start(vec![
+            FakeResponder::response("0x1", "color", "yeelight://192.168.1.10:55443"),
+            FakeResponder::response("0x2", "mono", "yeelight://192.168.1.11:55443"),
+            FakeResponder::response("0x3", "mono", "yeelight://192.168.1.12:55443"),
+        ]).await;
+        let devices = discover_at(responder.address, Duration::from_millis(200)).await.unwrap();
+
+        let find = |id: Option<&str>, model: Option<&str>| {
+            let filter = DeviceFilters { id: id.map(String::from), model: model.map(String::from), address: None };
+            devices.iter().find(|device| filter.matches(device)).map(|device| device.id.as_str())
+        };
+
+        assert_eq!(find(None, None), Some("0x1"));
+        assert_eq!(find(None, Some("mono")), Some("0x2"));
+        assert_eq!(find(Some("0x3"), None), Some("0x3"));
+        assert_eq!(find(Some("0x3"), Some("mono")), Some("0x3"));
+        assert_eq!(find(Some("0x3"), Some("color")), None);
+        assert_eq!(find(Some("0x4"), None), None);
+    }
+
+    #[test]
+    fn test_notification_publishes_state() {
+        let client = FakeClient::default();
+        let state_file = state_file("notification");
+
+        let notification = Notification::from_str(r#"{"method":"props","params":{"power":"off","bright":30,"ct":2700}}"#).unwrap();
+        handle_yeelight_notification(&client, &Mutex::new(None), &state_file, notification);
+
+        assert_eq!(published(&client), [
+            message("smart-home-system/yeelight/brightness", "30"),
+            message("smart-home-system/yeelight/power", "off"),
+        ]);
+        assert_eq!(state_file.state(), LastState { power: Some("off".into()), brightness: Some(30), color_temperature: Some(2700) });
+
+        publish_last_state(&client, &state_file);
+        assert_eq!(published(&client), [
+            message("smart-home-system/yeelight/brightness", "30"),
+            message("smart-home-system/yeelight/power", "off"),
+            message("smart-home-system/yeelight/stale", "true"),
+        ]);
+    }
+
+    /// An application connected to a device answering the first command with the line, with the command.
+    async fn application(name: &str, answer: &'static [u8]) -> (Application, Arc<FakeClient>, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (command_sender, command_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut command = String::new();
+            stream.read_line(&mut command).await.unwrap();
+            let _ = command_sender.send(command.clone());
+            stream.get_mut().write_all(answer).await.unwrap();
+            // Keeps the connection open until the test is done
+            let _ = stream.read_line(&mut command).await;
+        });
+
+        let client = Arc::new(FakeClient::default());
+        let (sender, _receiver) = mpsc::channel(1);
+        let application = Application {
+            client: client.clone(),
+            device: Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap(),
+            handle: tokio::spawn(async {}),
+            fade: Arc::new(Mutex::new(None)),
+            state_file: state_file(name),
+            filter: DeviceFilters::default(),
+            timeouts: TimeoutsConfig::default(),
+            notification_capacity: 1,
+            health: Health::without_mqtt(),
+            brightness_zero_turns_off: false,
+        };
+        (application, client, command_receiver)
+    }
+
+    #[tokio::test]
+    async fn test_get_power_publishes_state() {
+        let (mut application, client, _) = application("get-power", b"{\"id\":1,\"result\":[\"on\"]}\r\n").await;
+
+        application.get_power().await;
+        assert_eq!(published(&client), [message("smart-home-system/yeelight/power", "on")]);
+    }
+
+    #[tokio::test]
+    async fn test_command_errors() {
+        let answer = b"{\"id\":1,\"error\":{\"code\":-1,\"message\":\"client quota exceeded\"}}\r\n";
+        let (mut application, client, _) = application("command-errors", answer).await;
+
+        let invalid = Message::new("smart-home-system/yeelight/power/set", "dim", 1);
+        let error = Command::from_message(&invalid).unwrap_err();
+        assert!(matches!(error, ControllerError::InvalidPayload(_)));
+
+        let set = Message::new("smart-home-system/yeelight/power/set", "off", 1);
+        let command = Command::from_message(&set).unwrap().unwrap();
+        let error = application.handle(command).await.unwrap_err();
+        assert!(matches!(&error, ControllerError::Rejected { code: -1, message } if message == "client quota exceeded"));
+
+        publish_error(&*client, &set, &error);
+        let published = client.take_published();
+        assert_eq!(published.len(), 1);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&published[0].1).unwrap(), serde_json::json!({
+            "topic": "smart-home-system/yeelight/power/set",
+            "payload": "off",
+            "kind": "rejected",
+            "error": "The yeelight device answered with error -1: client quota exceeded",
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_brightness_zero_turns_off() {
+        let (mut application, _, command) = application("brightness-zero", b"{\"id\":1,\"result\":[\"ok\"]}\r\n").await;
+        application.brightness_zero_turns_off = true;
+
+        application.handle(Command::SetBrightness(0)).await.unwrap();
+        assert_eq!(command.await.unwrap(), "{\"id\":1,\"method\":\"set_power\",\"params\":[\"off\"]}\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_clamped_brightness_is_published() {
+        let (mut application, client, command) = application("clamped-brightness", b"{\"id\":1,\"result\":[\"ok\"]}\r\n").await;
+
+        let set = Message::new("smart-home-system/yeelight/brightness/set", "150", 1);
+        application.handle(Command::from_message(&set).unwrap().unwrap()).await.unwrap();
+        assert_eq!(command.await.unwrap(), "{\"id\":1,\"method\":\"set_bright\",\"params\":[100]}\r\n");
+        assert_eq!(published(&client), [message("smart-home-system/yeelight/brightness", "100")]);
+    }
+
+    #[tokio::test]
+    async fn test_set_clamped_brightness_is_published() {
+        let (mut application, client, command) = application("set-clamped-brightness", b"{\"id\":1,\"result\":[\"ok\"]}\r\n").await;
+
+        let set = Message::new("smart-home-system/yeelight/set", r#"{"brightness":0}"#, 1);
+        application.handle(Command::from_message(&set).unwrap().unwrap()).await.unwrap();
+        assert_eq!(command.await.unwrap(), "{\"id\":1,\"method\":\"set_bright\",\"params\":[1]}\r\n");
+        assert_eq!(published(&client), [message("smart-home-system/yeelight/brightness", "1")]);
+    }
+
+    #[tokio::test]
+    async fn test_set_turns_on_with_a_scene() {
+        let (mut application, _, command) = application("set-scene", b"{\"id\":1,\"result\":[\"ok\"]}\r\n").await;
+        application.state_file.update(|state| state.color_temperature = Some(2700));
+
+        let invalid = Message::new("smart-home-system/yeelight/set", "{}", 1);
+        let error = Command::from_message(&invalid).unwrap_err();
+        assert!(matches!(error, ControllerError::InvalidPayload(_)));
+
+        // Keeps the color temperature the device had
+        let set = Message::new("smart-home-system/yeelight/set", r#"{"power":"on","brightness":40}"#, 1);
+        application.handle(Command::from_message(&set).unwrap().unwrap()).await.unwrap();
+        assert_eq!(command.await.unwrap(), "{\"id\":1,\"method\":\"set_scene\",\"params\":[\"ct\",2700,40]}\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_commands_fail_while_disconnected() {
+        let (mut application, client, _) = application("disconnected", b"").await;
+
+        // A device closing the connection as soon as it's connected to
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { drop(listener.accept().await.unwrap()) });
+        let (sender, _receiver) = mpsc::channel(1);
+        application.device = Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), application.connection_lost()).await.unwrap();
+        let _reconnecting = application.disconnected();
+        assert_eq!(published(&client), [message("smart-home-system/yeelight/stale", "true")]);
+
+        let error = application.handle(Command::SetPower(Power::On)).await.unwrap_err();
+        assert!(matches!(error, ControllerError::Disconnected));
+        assert_eq!(error.kind(), "disconnected");
+    }
+
+    #[tokio::test]
+    async fn test_run_answers_the_requests() {
+        let (application, _, command) = application("run", b"{\"id\":1,\"result\":[\"ok\"]}\r\n").await;
+        let (requests, receiver) = mpsc::channel(1);
+        let run = tokio::spawn(application.run(receiver));
+
+        let (result, answer) = oneshot::channel();
+        requests.send(Request { command: Command::Toggle, message: None, result: Some(result) }).await.unwrap();
+        answer.await.unwrap().unwrap();
+        assert_eq!(command.await.unwrap(), "{\"id\":1,\"method\":\"toggle\",\"params\":[]}\r\n");
+
+        // Returns once every sender of requests is dropped
+        drop(requests);
+        tokio::time::timeout(Duration::from_secs(1), run).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::config::Config;
+
+/// Controls a yeelight device with the messages of the mqtt server.
+#[derive(Parser, Debug, Clone)]
+#[command(version)]
+pub struct Args {
+    /// The config file, instead of env `YEELIGHT_CONFIG` or `yeelight.yaml` if it exists.
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// The profile of the config to use, instead of env `CONFIG_PROFILE`.
+    #[arg(short, long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Which logs are written, as in `RUST_LOG`, instead of the log level of the config.
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// Prints the devices found on the network and exits, without connecting to the mqtt server.
+    #[arg(long, conflicts_with = "check")]
+    pub discover_only: bool,
+
+    /// Checks every setting of the config, prints a report and exits, without connecting to anything.
+    /// Exits with an error if the config has errors.
+    #[arg(long, visible_alias = "validate-config")]
+    pub check: bool,
+
+    /// Handles the commands and publishes the state changes of a simulated device, without ever connecting to
+    /// the real one, e.g. to test automations against the topics of the real device.
+    #[arg(long, conflicts_with_all = ["discover_only", "check"])]
+    pub dry_run: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Prints a commented example config, or writes it to a file.
+    GenerateConfig {
+        /// The file to write the config to, which can't exist yet.
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Fills in the device with the ones found on the network.
+        #[arg(long)]
+        discover: bool,
+    },
+}
+
+impl Args {
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config.clone().or_else(Config::path)
+    }
+
+    pub fn profile(&self) -> Option<String> {
+        self.profile.clone().or_else(|| std::env::var("CONFIG_PROFILE").ok())
+    }
+
+    /// Loads the config, with the arguments taking precedence over the config file and the env vars.
+    pub fn read_config(&self) -> anyhow::Result<Config> {
+        let mut config = Config::load(self.config_path().as_deref(), self.profile().as_deref())?;
+
+        if let Some(log_level) = &self.log_level {
+            config.log_level = log_level.clone();
+        }
+
+        Ok(config)
+    }
+
+    /// Loads the config and validates it.
+    pub fn load_config(&self) -> anyhow::Result<Config> {
+        let config = self.read_config()?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use crate::args::{Args, Command};
+
+    #[test]
+    fn test_parse() {
+        let args = Args::parse_from(["yeelight-controller", "-c", "/etc/yeelight.yaml", "--log-level", "debug"]);
+        assert_eq!(args.config.as_deref(), Some("/etc/yeelight.yaml".as_ref()));
+        assert_eq!(args.log_level.as_deref(), Some("debug"));
+        assert!(!args.discover_only);
+        assert!(!args.dry_run);
+
+        assert!(Args::parse_from(["yeelight-controller", "--validate-config"]).check);
+        assert!(Args::try_parse_from(["yeelight-controller", "--discover-only", "--check"]).is_err());
+        assert!(Args::try_parse_from(["yeelight-controller", "--brightness"]).is_err());
+        assert!(Args::parse_from(["yeelight-controller", "--dry-run"]).dry_run);
+        assert!(Args::try_parse_from(["yeelight-controller", "--dry-run", "--check"]).is_err());
+
+        let args = Args::parse_from(["yeelight-controller", "generate-config", "--discover", "-o", "yeelight.yaml"]);
+        assert!(matches!(args.command, Some(Command::GenerateConfig { output: Some(_), discover: true })));
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use tracing_subscriber::EnvFilter;
+
+use crate::args::Args;
+use crate::config::Config;
+use crate::remap::{Remap, RemapRule};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub setting: &'static str,
+    pub message: String,
+}
+
+/// What's right and wrong with every setting of a config, see [`check`].
+#[derive(Debug, Default)]
+pub struct Report(pub Vec<Finding>);
+
+impl Report {
+    fn add(&mut self, severity: Severity, setting: &'static str, message: impl Into<String>) {
+        self.0.push(Finding { severity, setting, message: message.into() });
+    }
+
+    fn check(&mut self, setting: &'static str, result: Result<String, String>) {
+        match result {
+            Ok(message) => self.add(Severity::Ok, setting, message),
+            Err(message) => self.add(Severity::Error, setting, message),
+        }
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.0.iter().filter(|finding| finding.severity == severity).count()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Finding> {
+        self.0.iter().filter(|finding| finding.severity == Severity::Error)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{:<8}{}: {}", severity, self.setting, self.message)
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for finding in &self.0 {
+            writeln!(f, "{}", finding)?;
+        }
+        write!(f, "errors: {}, warnings: {}", self.count(Severity::Error), self.count(Severity::Warning))
+    }
+}
+
+/// Whether the uri is one the mqtt client can connect to, e.g. `tcp://localhost:1883`.
+fn check_broker_uri(uri: Option<&str>) -> Result<String, String> {
+    let uri = uri.ok_or("not set, set mqtt.server_uri in the config or env MQTT_SERVER_URI")?;
+
+    // The mqtt client connects with tcp without a scheme
+    let (scheme, address) = uri.split_once("://").unwrap_or(("tcp", uri));
+    if !mqtt_client::SCHEMES.contains(&scheme) {
+        return Err(format!("{:?} has an unknown scheme {:?}, should be one of {}", uri, scheme, mqtt_client::SCHEMES.join(", ")));
+    }
+
+    let address = address.split('/').next().unwrap_or_default();
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port)),
+        None => (address, None),
+    };
+
+    if host.is_empty() {
+        return Err(format!("{:?} has no host", uri));
+    }
+    if let Some(port) = port {
+        port.parse::<u16>().map_err(|_| format!("{:?} has an invalid port {:?}", uri, port))?;
+    }
+
+    Ok(uri.to_string())
+}
+
+/// Whether the topic can be published to, so it has no wildcards and no empty levels.
+fn check_topic(topic: &str) -> Result<String, String> {
+    if topic.is_empty() {
+        return Err("is empty".into());
+    }
+    if topic.contains(['+', '#']) {
+        return Err(format!("{:?} has the wildcards + or #", topic));
+    }
+    if topic.contains('\0') {
+        return Err(format!("{:?} has a null character", topic));
+    }
+    if topic.split('/').any(str::is_empty) {
+        return Err(format!("{:?} has an empty level, e.g. a leading, trailing or double /", topic));
+    }
+
+    Ok(topic.to_string())
+}
+
+fn check_directory(report: &mut Report, setting: &'static str, path: &Path) {
+    match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) if !parent.is_dir() => report.add(Severity::Warning, setting, format!("the directory {:?} doesn't exist", parent)),
+        _ => report.add(Severity::Ok, setting, format!("{:?}", path)),
+    }
+}
+
+/// Checks every setting of the config, without connecting to anything.
+pub fn check_config(config: &Config) -> Report {
+    let mut report = Report::default();
+
+    if let Some(profile) = &config.profile {
+        report.add(Severity::Ok, "profile", profile.clone());
+    }
+    // The profiles that aren't selected, which would only fail when they are
+    for (name, profile) in &config.profiles {
+        if let Some(Err(e)) = profile.mqtt.server_uri.as_deref().map(|uri| check_broker_uri(Some(uri))) {
+            report.add(Severity::Error, "profiles", format!("{}.mqtt.server_uri {}", name, e));
+        }
+        if let Some(Err(e)) = profile.mqtt.topic_prefix.as_deref().map(check_topic) {
+            report.add(Severity::Error, "profiles", format!("{}.mqtt.topic_prefix {}", name, e));
+        }
+    }
+
+    for error in &config.env_errors {
+        report.add(Severity::Error, "env", error.clone());
+    }
+
+    report.check("mqtt.server_uri", check_broker_uri(config.mqtt.server_uri.as_deref()));
+    report.check("mqtt.topic_prefix", check_topic(&config.mqtt.topic_prefix));
+    report.add(Severity::Ok, "mqtt.client_id", config.mqtt.client_id.clone());
+
+    for rule in &config.mqtt.remap {
+        let result = match rule {
+            RemapRule::Prefix { prefix, to } => check_topic(prefix).and(check_topic(to)).map(|_| format!("{} -> {}", prefix, to)),
+            RemapRule::Regex { regex, to } => match Remap::new(std::slice::from_ref(rule)) {
+                Ok(_) => Ok(format!("{} -> {}", regex, to)),
+                Err(e) => Err(format!("{:#}", e)),
+            },
+        };
+        report.check("mqtt.remap", result);
+    }
+    if let Some(prefix) = &config.mqttthing_topic_prefix {
+        report.check("mqttthing_topic_prefix", check_topic(prefix));
+    }
+
+    match &config.device.id {
+        Some(id) if !id.starts_with("0x") => report.add(Severity::Warning, "device.id", format!("{:?} doesn't look like a yeelight id, e.g. 0x0000000012345678", id)),
+        Some(id) => report.add(Severity::Ok, "device.id", id.clone()),
+        None => report.add(Severity::Ok, "device.id", "not set, the first device discovered is used"),
+    }
+    if let Some(model) = &config.device.model {
+        report.check("device.model", if model.is_empty() { Err("is empty".into()) } else { Ok(model.clone()) });
+    }
+    match &config.device.address {
+        Some(address) => report.check("device.address", match address.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(address.clone()),
+            _ => Err(format!("{:?} should be a host and a port, e.g. 192.168.1.10:55443", address)),
+        }),
+        None if !cfg!(feature = "discovery") => {
+            report.add(Severity::Error, "device.address", "not set, the controller is built without the discovery feature")
+        }
+        None => {}
+    }
+
+    if config.timeouts.discovery_secs == 0 {
+        report.add(Severity::Error, "timeouts.discovery_secs", "should be more than 0, the devices wouldn't have time to answer");
+    } else {
+        report.add(Severity::Ok, "timeouts.discovery_secs", config.timeouts.discovery_secs.to_string());
+    }
+
+    if config.notification_capacity == 0 {
+        report.add(Severity::Error, "notification_capacity", "should be more than 0, no notification could be handled");
+    } else {
+        report.add(Severity::Ok, "notification_capacity", config.notification_capacity.to_string());
+    }
+
+    if config.api_port.is_some() && !cfg!(feature = "api") {
+        report.add(Severity::Warning, "api_port", "is set but not served, the controller is built without the api feature");
+    }
+    if config.api_port == Some(config.http_port) {
+        report.add(Severity::Error, "api_port", format!("is the same as http_port {}", config.http_port));
+    } else {
+        report.add(Severity::Ok, "http_port", config.http_port.to_string());
+    }
+
+    check_directory(&mut report, "state_path", &config.state_path);
+    check_directory(&mut report, "usage_path", &config.usage_path);
+    if let Some(record_path) = &config.record_path {
+        check_directory(&mut report, "record_path", record_path);
+    }
+    if let Some(admin_socket) = &config.admin_socket {
+        check_directory(&mut report, "admin_socket", admin_socket);
+    }
+
+    report.check("log_level", EnvFilter::try_new(&config.log_level)
+        .map(|_| config.log_level.clone())
+        .map_err(|e| format!("{:?} is invalid: {}", config.log_level, e)));
+
+    report
+}
+
+/// Loads the config as the controller would and checks it, see `--check`.
+pub fn check(args: &Args) -> Report {
+    match args.read_config() {
+        Ok(config) => check_config(&config),
+        Err(e) => {
+            let mut report = Report::default();
+            report.add(Severity::Error, "config", format!("{:#}", e));
+            report
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check::{check_broker_uri, check_config, check_topic, Severity};
+    use crate::config::Config;
+
+    #[test]
+    fn test_check_broker_uri() {
+        assert!(check_broker_uri(Some("tcp://localhost:1883")).is_ok());
+        // Only the client of paho connects over tls and websockets
+        assert_eq!(check_broker_uri(Some("ssl://broker.local")).is_ok(), cfg!(feature = "mqtt-paho"));
+        assert_eq!(check_broker_uri(Some("ws://broker.local:9001/mqtt")).is_ok(), cfg!(feature = "mqtt-paho"));
+        assert!(check_broker_uri(None).is_err());
+        assert!(check_broker_uri(Some("localhost:1883")).is_ok());
+        assert!(check_broker_uri(Some("http://localhost:1883")).is_err());
+        assert!(check_broker_uri(Some("tcp://:1883")).is_err());
+        assert!(check_broker_uri(Some("tcp://localhost:mqtt")).is_err());
+    }
+
+    #[test]
+    fn test_check_topic() {
+        assert!(check_topic("smart-home-system/yeelight").is_ok());
+        assert!(check_topic("").is_err());
+        assert!(check_topic("home/+/light").is_err());
+        assert!(check_topic("home/light/").is_err());
+        assert!(check_topic("/home/light").is_err());
+        assert!(check_topic("home//light").is_err());
+    }
+
+    #[test]
+    fn test_check_config() {
+        let mut config = Config::default();
+        config.mqtt.server_uri = Some("tcp://localhost:1883".into());
+        assert!(!check_config(&config).has_errors());
+
+        config.mqtt.topic_prefix = "home/#".into();
+        config.api_port = Some(config.http_port);
+        config.device.id = Some("bedroom".into());
+
+        let report = check_config(&config);
+        let errors: Vec<&str> = report.errors().map(|finding| finding.setting).collect();
+        assert_eq!(errors, ["mqtt.topic_prefix", "api_port"]);
+        assert!(report.0.iter().any(|finding| finding.setting == "device.id" && finding.severity == Severity::Warning));
+        assert!(report.to_string().ends_with("errors: 2, warnings: 1"), "{}", report);
+    }
+
+    #[test]
+    fn test_check_device_address() {
+        let mut config = Config::default();
+        config.mqtt.server_uri = Some("tcp://localhost:1883".into());
+
+        config.device.address = Some("192.168.1.10:55443".into());
+        assert!(!check_config(&config).has_errors());
+
+        for address in ["192.168.1.10", ":55443", "192.168.1.10:yeelight"] {
+            config.device.address = Some(address.into());
+            let errors: Vec<&str> = check_config(&config).errors().map(|finding| finding.setting).collect();
+            assert_eq!(errors, ["device.address"], "{}", address);
+        }
+    }
+}
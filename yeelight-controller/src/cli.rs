@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
+use domain_state::Power;
+use tokio::sync::mpsc;
+use tracing_subscriber::EnvFilter;
+
+use yeelight_controller::discovery::{self, DiscoveryResponse};
+use yeelight_controller::health::Health;
+use yeelight_controller::protocol::{Method, ResponseResult};
+use yeelight_controller::recording;
+use yeelight_controller::yeelight::Device;
+
+/// Talks to yeelight devices directly, without the mqtt server and the controller.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// The id of the device, the first one found if not set.
+    #[arg(long, global = true)]
+    id: Option<String>,
+
+    /// Appends every line exchanged with the device to the file, to report the quirks of its firmware.
+    #[arg(long, global = true, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the id, model and address of the devices found on the network.
+    Discover,
+    /// Turns the device on or off.
+    SetPower {
+        /// on or off
+        power: Power,
+    },
+    /// Prints the values of the properties of the device, e.g. power or bright.
+    GetProp {
+        #[arg(required = true)]
+        properties: Vec<String>,
+    },
+    /// Parses the lines received in a recording, as the controller would, failing if any is invalid.
+    Replay {
+        recording: PathBuf,
+    },
+}
+
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+async fn discover() -> anyhow::Result<Vec<DiscoveryResponse>> {
+    discovery::discover(DISCOVERY_TIMEOUT).await.context("Yeelight discovery failed")
+}
+
+async fn connect(id: Option<&str>) -> anyhow::Result<Device> {
+    let device = discover().await?.into_iter()
+        .find(|device| id.is_none() || id == Some(device.id.as_str()))
+        .context("No yeelight device found")?;
+
+    let address = device.location.trim_start_matches("yeelight://").to_string();
+
+    // The notifications aren't shown, as they're sent for changes made by anyone
+    let (sender, mut notifications) = mpsc::channel(1);
+    tokio::spawn(async move { while notifications.recv().await.is_some() {} });
+
+    Device::new(device.id, address.clone(), sender, Health::without_mqtt()).await
+        .context(format!("Failed to connect to the yeelight device at {}", address))
+}
+
+async fn send(id: Option<&str>, method: Method) -> anyhow::Result<Vec<String>> {
+    let device = connect(id).await?;
+
+    match device.send_method(method).await?.result {
+        ResponseResult::Success(result) => Ok(result),
+        ResponseResult::Error { code, message } => bail!("The yeelight device answered with error {}: {}", code, message),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn")))
+        .with_writer(std::io::stderr)
+        .init();
+
+    let id = cli.id.as_deref();
+    if let Some(path) = &cli.record {
+        recording::enable(path)?;
+    }
+
+    match cli.command {
+        Command::Discover => {
+            for device in discover().await? {
+                println!("{}\t{}\t{}", device.id, device.model, device.location);
+            }
+        }
+        Command::SetPower { power } => {
+            send(id, Method::set_power(power)).await?;
+        }
+        Command::GetProp { properties } => {
+            let values = send(id, Method::get_prop(properties.clone())).await?;
+            for (property, value) in properties.iter().zip(values) {
+                println!("{}: {}", property, value);
+            }
+        }
+        Command::Replay { recording } => {
+            let entries = recording::read(&recording)?;
+            let mut invalid = 0;
+            for (entry, message) in recording::replay(&entries) {
+                match message {
+                    Ok(message) => println!("{}\t{:?}", entry.time_ms, message),
+                    Err(e) => {
+                        invalid += 1;
+                        println!("{}\tinvalid: {}: {:?}", entry.time_ms, e, String::from_utf8_lossy(entry.line()));
+                    }
+                }
+            }
+            if invalid > 0 {
+                bail!("{} of the lines received failed to parse", invalid);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,455 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::application::DeviceFilters;
+use crate::check;
+use crate::remap::RemapRule;
+
+/// Where the config is read from, unless set with env `YEELIGHT_CONFIG`. It's optional, the controller
+/// can be configured with env vars only.
+const DEFAULT_CONFIG_PATH: &str = "yeelight.yaml";
+
+/// Reloaded while the controller runs when the file changes, see [`crate::reload`]. The log settings and
+/// the diagnostics interval are applied right away, the others on the next restart.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mqtt: MqttConfig,
+    /// Which device to control, the first one discovered if empty.
+    pub device: DeviceFilters,
+    pub timeouts: TimeoutsConfig,
+    /// Port of the metrics and health check endpoints.
+    pub http_port: u16,
+    /// Port of the api and web page, only served if set.
+    pub api_port: Option<u16>,
+    /// Path of the unix socket of the admin console, only served if set.
+    pub admin_socket: Option<PathBuf>,
+    /// Where the last state of the device is kept between restarts.
+    pub state_path: PathBuf,
+    /// Where the on-time and switch cycles of the device are counted.
+    pub usage_path: PathBuf,
+    /// Appends every line exchanged with the device to this file, if set, to reproduce its quirks.
+    pub record_path: Option<PathBuf>,
+    /// How many notifications of the device wait to be published. The ones after them are coalesced into one
+    /// with the latest value of each property, instead of blocking the reads from the device.
+    pub notification_capacity: usize,
+    /// How often the command latencies are published to mqtt, only published if set.
+    pub diagnostics_interval_secs: Option<u64>,
+    /// Also uses the homebridge-mqttthing topics under this prefix, if set.
+    pub mqttthing_topic_prefix: Option<String>,
+    /// Turns the device off when the brightness is set to 0, as HomeKit and some dashboards mean, instead of
+    /// setting the lowest brightness the device accepts.
+    pub brightness_zero_turns_off: bool,
+    /// Which logs are written, as in `RUST_LOG`, e.g. `info,yeelight_controller::yeelight=debug`.
+    pub log_level: String,
+    /// How long repeats of a warning or error are dropped after it's logged.
+    pub log_throttle_secs: u64,
+    /// Settings that replace the ones above when the profile is selected, e.g. to run a test instance against
+    /// the same broker without touching the topics of the real one.
+    pub profiles: BTreeMap<String, Profile>,
+    /// The name of the profile that was applied.
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// The env vars set to invalid values, reported with the other errors of the config.
+    #[serde(skip)]
+    pub env_errors: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub server_uri: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Replaces `smart-home-system/yeelight` in every topic.
+    pub topic_prefix: String,
+    /// Only one client with an id can be connected to the broker.
+    pub client_id: String,
+    /// Rewrites the topics of the controller to the ones of an existing topic scheme on the broker. The first
+    /// rule that matches a topic is used, the topics no rule matches get the topic prefix.
+    pub remap: Vec<RemapRule>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub mqtt: ProfileMqttConfig,
+}
+
+/// The mqtt settings of a profile, the ones that aren't set are kept. The client id is suffixed with the
+/// name of the profile if it isn't set, so the instances don't disconnect each other.
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileMqttConfig {
+    pub server_uri: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub topic_prefix: Option<String>,
+    pub client_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    /// How long to wait for the devices to answer the discovery.
+    pub discovery_secs: u64,
+    /// How long to wait before discovering again when no device was found.
+    pub discovery_retry_secs: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mqtt: MqttConfig::default(),
+            device: DeviceFilters::default(),
+            timeouts: TimeoutsConfig::default(),
+            http_port: 9103,
+            api_port: None,
+            admin_socket: None,
+            state_path: "yeelight-state.json".into(),
+            usage_path: "yeelight-usage.json".into(),
+            record_path: None,
+            notification_capacity: 16,
+            diagnostics_interval_secs: None,
+            mqttthing_topic_prefix: None,
+            brightness_zero_turns_off: false,
+            log_level: "info".into(),
+            log_throttle_secs: crate::throttle::DEFAULT_WINDOW.as_secs(),
+            profiles: BTreeMap::new(),
+            profile: None,
+            env_errors: Vec::new(),
+        }
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            server_uri: None,
+            username: None,
+            password: None,
+            topic_prefix: crate::mqtt::DEFAULT_TOPIC_PREFIX.into(),
+            client_id: "yeelight-controller".into(),
+            remap: Vec::new(),
+        }
+    }
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        Self { discovery_secs: 3, discovery_retry_secs: 30 }
+    }
+}
+
+impl TimeoutsConfig {
+    pub fn discovery(&self) -> Duration {
+        Duration::from_secs(self.discovery_secs)
+    }
+
+    pub fn discovery_retry(&self) -> Duration {
+        Duration::from_secs(self.discovery_retry_secs)
+    }
+}
+
+/// The content of the file at env `<name>_FILE`, e.g. a docker or kubernetes secret, or else the value of
+/// the env var, if either is set.
+fn env<T: FromStr>(name: &str) -> anyhow::Result<Option<T>>
+    where T::Err: std::error::Error + Send + Sync + 'static {
+    let file = format!("{}_FILE", name);
+
+    let value = match std::env::var(&file) {
+        Ok(path) => std::fs::read_to_string(&path)
+            .context(format!("Failed to read env {}: {}", file, path))?
+            .trim_end_matches(['\n', '\r'])
+            .to_string(),
+        Err(_) => match std::env::var(name) {
+            Ok(value) => value,
+            Err(_) => return Ok(None),
+        },
+    };
+
+    value.parse().map(Some).context(format!("Invalid env {}: {}", name, value))
+}
+
+impl Config {
+    /// The path of the config file, if there's one.
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var("YEELIGHT_CONFIG") {
+            Ok(path) => Some(path.into()),
+            Err(_) => Path::new(DEFAULT_CONFIG_PATH).exists().then(|| DEFAULT_CONFIG_PATH.into()),
+        }
+    }
+
+    /// Reads the config file, if there's one, and overrides it with the profile, if one is selected, and
+    /// then with the env vars that are set. It isn't validated yet, see [`Config::validate`], which also reports
+    /// the invalid env vars.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> anyhow::Result<Config> {
+        let mut config = match path {
+            Some(path) => Self::read(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(profile) = profile {
+            config.apply_profile(profile)?;
+        }
+        config.override_with_env();
+        Ok(config)
+    }
+
+    fn apply_profile(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            anyhow::bail!("Unknown profile {:?}, the config has the profiles {:?}", name, self.profiles.keys().collect::<Vec<_>>());
+        };
+
+        let mqtt = profile.mqtt;
+        if let Some(server_uri) = mqtt.server_uri {
+            self.mqtt.server_uri = Some(server_uri);
+        }
+        if let Some(username) = mqtt.username {
+            self.mqtt.username = Some(username);
+        }
+        if let Some(password) = mqtt.password {
+            self.mqtt.password = Some(password);
+        }
+        if let Some(topic_prefix) = mqtt.topic_prefix {
+            self.mqtt.topic_prefix = topic_prefix;
+        }
+        self.mqtt.client_id = mqtt.client_id.unwrap_or_else(|| format!("{}-{}", self.mqtt.client_id, name));
+
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    fn read(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        Self::parse(&content).context(format!("Invalid config {:?}", path))
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Config> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+
+    /// Fails with the errors found by [`check::check_config`].
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let errors: Vec<String> = check::check_config(self).errors()
+            .map(|finding| format!("{}: {}", finding.setting, finding.message))
+            .collect();
+
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid config, {}", errors.join("; "));
+        }
+
+        Ok(())
+    }
+
+    /// The settings that changed in the other config and are only applied on a restart.
+    pub fn restart_required(&self, other: &Config) -> Vec<&'static str> {
+        [
+            ("mqtt", self.mqtt != other.mqtt),
+            ("device", self.device != other.device),
+            ("timeouts", self.timeouts != other.timeouts),
+            ("http_port", self.http_port != other.http_port),
+            ("api_port", self.api_port != other.api_port),
+            ("admin_socket", self.admin_socket != other.admin_socket),
+            ("state_path", self.state_path != other.state_path),
+            ("usage_path", self.usage_path != other.usage_path),
+            ("record_path", self.record_path != other.record_path),
+            ("notification_capacity", self.notification_capacity != other.notification_capacity),
+            ("mqttthing_topic_prefix", self.mqttthing_topic_prefix != other.mqttthing_topic_prefix),
+            ("brightness_zero_turns_off", self.brightness_zero_turns_off != other.brightness_zero_turns_off),
+        ].into_iter()
+            .filter(|(_, changed)| *changed)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// The env var, or `None` if it's invalid, with the error kept so every invalid env var is reported at once.
+    fn env<T: FromStr>(&mut self, name: &str) -> Option<T>
+        where T::Err: std::error::Error + Send + Sync + 'static {
+        env(name).unwrap_or_else(|e| {
+            self.env_errors.push(format!("{:#}, fix or unset it", e));
+            None
+        })
+    }
+
+    fn override_with_env(&mut self) {
+        if let Some(server_uri) = self.env("MQTT_SERVER_URI") {
+            self.mqtt.server_uri = Some(server_uri);
+        }
+        if let Some(username) = self.env("MQTT_USERNAME") {
+            self.mqtt.username = Some(username);
+        }
+        if let Some(password) = self.env("MQTT_PASSWORD") {
+            self.mqtt.password = Some(password);
+        }
+        if let Some(topic_prefix) = self.env("MQTT_TOPIC_PREFIX") {
+            self.mqtt.topic_prefix = topic_prefix;
+        }
+        if let Some(client_id) = self.env("MQTT_CLIENT_ID") {
+            self.mqtt.client_id = client_id;
+        }
+        if let Some(id) = self.env("YEELIGHT_ID") {
+            self.device.id = Some(id);
+        }
+        if let Some(model) = self.env("YEELIGHT_MODEL") {
+            self.device.model = Some(model);
+        }
+        if let Some(address) = self.env("YEELIGHT_ADDRESS") {
+            self.device.address = Some(address);
+        }
+        if let Some(http_port) = self.env("HTTP_PORT") {
+            self.http_port = http_port;
+        }
+        if let Some(api_port) = self.env("API_PORT") {
+            self.api_port = Some(api_port);
+        }
+        if let Some(admin_socket) = self.env("ADMIN_SOCKET") {
+            self.admin_socket = Some(admin_socket);
+        }
+        if let Some(state_path) = self.env("YEELIGHT_STATE_PATH") {
+            self.state_path = state_path;
+        }
+        if let Some(usage_path) = self.env("YEELIGHT_USAGE_PATH") {
+            self.usage_path = usage_path;
+        }
+        if let Some(record_path) = self.env("YEELIGHT_RECORD_PATH") {
+            self.record_path = Some(record_path);
+        }
+        if let Some(capacity) = self.env("YEELIGHT_NOTIFICATION_CAPACITY") {
+            self.notification_capacity = capacity;
+        }
+        if let Some(secs) = self.env("DIAGNOSTICS_INTERVAL_SECS") {
+            self.diagnostics_interval_secs = Some(secs);
+        }
+        if let Some(prefix) = self.env("MQTTTHING_TOPIC_PREFIX") {
+            self.mqttthing_topic_prefix = Some(prefix);
+        }
+        if let Some(brightness_zero_turns_off) = self.env("BRIGHTNESS_ZERO_TURNS_OFF") {
+            self.brightness_zero_turns_off = brightness_zero_turns_off;
+        }
+        if let Some(log_level) = self.env("RUST_LOG") {
+            self.log_level = log_level;
+        }
+        if let Some(secs) = self.env("LOG_THROTTLE_SECS") {
+            self.log_throttle_secs = secs;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{env, Config};
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse("
+mqtt:
+  server_uri: tcp://localhost:1883
+  topic_prefix: home/bedroom-light
+device:
+  model: color
+timeouts:
+  discovery_secs: 5
+api_port: 8080
+brightness_zero_turns_off: true
+").unwrap();
+
+        assert_eq!(config.mqtt.server_uri.as_deref(), Some("tcp://localhost:1883"));
+        assert_eq!(config.mqtt.topic_prefix, "home/bedroom-light");
+        assert_eq!(config.device.model.as_deref(), Some("color"));
+        assert_eq!(config.timeouts.discovery_secs, 5);
+        assert_eq!(config.timeouts.discovery_retry_secs, 30);
+        assert_eq!(config.api_port, Some(8080));
+        assert_eq!(config.http_port, 9103);
+        assert!(config.brightness_zero_turns_off);
+        assert!(config.validate().is_ok());
+
+        let error = Config::parse("mqtt:\n  server: tcp://localhost:1883\n").unwrap_err();
+        assert!(error.to_string().contains("unknown field `server`"), "{}", error);
+
+        assert!(Config::parse("").unwrap().validate().is_err());
+        assert!(Config::parse("mqtt:\n  server_uri: tcp://localhost:1883\n  topic_prefix: home/\n").unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut config = Config::parse("
+mqtt:
+  server_uri: tcp://broker:1883
+profiles:
+  dev:
+    mqtt:
+      topic_prefix: dev/yeelight
+  staging:
+    mqtt:
+      server_uri: tcp://staging-broker:1883
+      client_id: yeelight-staging
+").unwrap();
+
+        let mut dev = config.clone();
+        dev.apply_profile("dev").unwrap();
+        assert_eq!(dev.mqtt.server_uri.as_deref(), Some("tcp://broker:1883"));
+        assert_eq!(dev.mqtt.topic_prefix, "dev/yeelight");
+        assert_eq!(dev.mqtt.client_id, "yeelight-controller-dev");
+        assert_eq!(dev.profile.as_deref(), Some("dev"));
+
+        config.apply_profile("staging").unwrap();
+        assert_eq!(config.mqtt.server_uri.as_deref(), Some("tcp://staging-broker:1883"));
+        assert_eq!(config.mqtt.topic_prefix, "smart-home-system/yeelight");
+        assert_eq!(config.mqtt.client_id, "yeelight-staging");
+
+        let error = config.apply_profile("prod").unwrap_err();
+        assert!(error.to_string().contains("Unknown profile \"prod\""), "{}", error);
+    }
+
+    #[test]
+    fn test_restart_required() {
+        let config = Config::default();
+
+        let reloaded = Config { log_level: "debug".into(), diagnostics_interval_secs: Some(60), ..Config::default() };
+        assert!(config.restart_required(&reloaded).is_empty());
+
+        let reloaded = Config { http_port: 9200, api_port: Some(8080), log_level: "debug".into(), ..Config::default() };
+        assert_eq!(config.restart_required(&reloaded), ["http_port", "api_port"]);
+    }
+
+    #[test]
+    fn test_env_file() {
+        let path = std::env::temp_dir().join("yeelight-controller-test-secret");
+        std::fs::write(&path, "hunter2\n").unwrap();
+
+        std::env::set_var("YEELIGHT_TEST_SECRET", "plain");
+        assert_eq!(env::<String>("YEELIGHT_TEST_SECRET").unwrap().as_deref(), Some("plain"));
+
+        std::env::set_var("YEELIGHT_TEST_SECRET_FILE", &path);
+        assert_eq!(env::<String>("YEELIGHT_TEST_SECRET").unwrap().as_deref(), Some("hunter2"));
+
+        std::env::set_var("YEELIGHT_TEST_SECRET_FILE", path.with_extension("missing"));
+        assert!(env::<String>("YEELIGHT_TEST_SECRET").is_err());
+
+        assert_eq!(env::<String>("YEELIGHT_TEST_UNSET").unwrap(), None);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_invalid_env_vars_are_reported_together() {
+        std::env::set_var("YEELIGHT_TEST_PORT", "http");
+        std::env::set_var("YEELIGHT_TEST_SECS", "-1");
+
+        let mut config = Config::default();
+        assert_eq!(config.env::<u16>("YEELIGHT_TEST_PORT"), None);
+        assert_eq!(config.env::<u64>("YEELIGHT_TEST_SECS"), None);
+
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("mqtt.server_uri: not set"), "{}", error);
+        assert!(error.contains("env: Invalid env YEELIGHT_TEST_PORT: http"), "{}", error);
+        assert!(error.contains("env: Invalid env YEELIGHT_TEST_SECS: -1"), "{}", error);
+    }
+}
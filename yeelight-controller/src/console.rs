@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use mqtt_client::Message;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+
+use crate::application::{Command, Request};
+use crate::health::Health;
+use crate::state::StateFile;
+
+const HELP: &str = "Commands:
+    state                       the health and the last state of the device
+    pending                     the ids of the commands sent to the device that weren't answered yet
+    poll                        reads the state of the device and publishes it
+    reconnect                   connects to the device again
+    inject <topic> [payload]    handles the message as if it was received from mqtt
+    quit";
+
+#[derive(Debug, PartialEq)]
+enum ConsoleCommand {
+    Help,
+    State,
+    Pending,
+    Poll,
+    Reconnect,
+    Inject { topic: String, payload: String },
+    Quit,
+}
+
+fn parse(line: &str) -> Result<ConsoleCommand, String> {
+    let line = line.trim();
+    let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+
+    match command {
+        "help" | "" => Ok(ConsoleCommand::Help),
+        "state" => Ok(ConsoleCommand::State),
+        "pending" => Ok(ConsoleCommand::Pending),
+        "poll" => Ok(ConsoleCommand::Poll),
+        "reconnect" => Ok(ConsoleCommand::Reconnect),
+        "inject" => {
+            let arguments = arguments.trim();
+            let (topic, payload) = arguments.split_once(' ').unwrap_or((arguments, ""));
+            if topic.is_empty() {
+                return Err("Usage: inject <topic> [payload]".into());
+            }
+            Ok(ConsoleCommand::Inject { topic: topic.to_string(), payload: payload.trim().to_string() })
+        }
+        "quit" | "exit" => Ok(ConsoleCommand::Quit),
+        _ => Err(format!("Unknown command: {}. Type help for the commands.", command)),
+    }
+}
+
+/// A console on a unix socket to inspect the controller and inject messages while debugging,
+/// e.g. with `socat - UNIX-CONNECT:<path>`.
+#[derive(Clone)]
+pub struct Console {
+    health: Health,
+    state_file: StateFile,
+    requests: mpsc::Sender<Request>,
+}
+
+impl Console {
+    pub fn new(health: Health, state_file: StateFile, requests: mpsc::Sender<Request>) -> Self {
+        Self { health, state_file, requests }
+    }
+
+    /// Sends the command to the application, answering once it was handled.
+    async fn send(&self, command: Command, message: Option<Message>) -> String {
+        let (result, receiver) = oneshot::channel();
+        if self.requests.send(Request { command, message, result: Some(result) }).await.is_err() {
+            return "The controller is shutting down".into();
+        }
+
+        match receiver.await {
+            Ok(Ok(())) => "Ok".into(),
+            Ok(Err(e)) => format!("Failed: {}", e),
+            Err(_) => "The controller is shutting down".into(),
+        }
+    }
+
+    async fn execute(&self, command: ConsoleCommand) -> String {
+        match command {
+            ConsoleCommand::Help => HELP.into(),
+            ConsoleCommand::State => format!("{}state: {:?}", self.health.report(), self.state_file.state()),
+            ConsoleCommand::Pending => {
+                let (ids, receiver) = oneshot::channel();
+                let sent = self.send(Command::Pending(ids), None).await;
+                match receiver.await {
+                    Ok(ids) => format!("{:?}", ids),
+                    Err(_) => sent,
+                }
+            }
+            ConsoleCommand::Poll => self.send(Command::Poll, None).await,
+            ConsoleCommand::Reconnect => self.send(Command::Reconnect, None).await,
+            ConsoleCommand::Inject { topic, payload } => {
+                let message = Message::new(topic, payload, 0);
+                match Command::from_message(&message) {
+                    Ok(Some(command)) => self.send(command, Some(message)).await,
+                    Ok(None) => format!("Unknown topic: {}", message.topic()),
+                    Err(e) => format!("Failed: {}", e),
+                }
+            }
+            ConsoleCommand::Quit => String::new(),
+        }
+    }
+
+    async fn handle_connection(self, stream: UnixStream) -> std::io::Result<()> {
+        let (read_half, mut write_half) = stream.into_split();
+        let mut lines = BufReader::new(read_half).lines();
+
+        write_half.write_all(b"> ").await?;
+        while let Some(line) = lines.next_line().await? {
+            let output = match parse(&line) {
+                Ok(ConsoleCommand::Quit) => return Ok(()),
+                Ok(command) => self.execute(command).await,
+                Err(e) => e,
+            };
+
+            write_half.write_all(format!("{}\n> ", output.trim_end()).as_bytes()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn serve(path: PathBuf, console: Console) {
+    // Left behind by a previous run
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(e) => return error!("Failed to serve the admin console on {:?}: {}", path, e),
+    };
+
+    info!("Serving the admin console on {:?}", path);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let console = console.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = console.handle_connection(stream).await {
+                        warn!("Admin console connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => error!("Failed to accept an admin console connection: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::console::{parse, ConsoleCommand};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), Ok(ConsoleCommand::Help));
+        assert_eq!(parse("state\n"), Ok(ConsoleCommand::State));
+        assert_eq!(parse("inject smart-home-system/yeelight/power/set on"),
+                   Ok(ConsoleCommand::Inject { topic: "smart-home-system/yeelight/power/set".into(), payload: "on".into() }));
+        assert_eq!(parse("inject smart-home-system/yeelight/fade {\"target\": 10}"),
+                   Ok(ConsoleCommand::Inject { topic: "smart-home-system/yeelight/fade".into(), payload: "{\"target\": 10}".into() }));
+        assert_eq!(parse("inject smart-home-system/yeelight/toggle"),
+                   Ok(ConsoleCommand::Inject { topic: "smart-home-system/yeelight/toggle".into(), payload: "".into() }));
+        assert_eq!(parse("poll"), Ok(ConsoleCommand::Poll));
+        assert_eq!(parse("reconnect"), Ok(ConsoleCommand::Reconnect));
+        assert!(parse("inject").is_err());
+        assert!(parse("reboot").is_err());
+    }
+}
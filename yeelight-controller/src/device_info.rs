@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use mqtt_client::Message;
+use serde::Serialize;
+use tracing::warn;
+
+#[cfg(feature = "discovery")]
+use crate::discovery;
+use crate::mqtt::{self, Client};
+use crate::topic;
+
+/// How often the info of the device is published again, e.g. to pick up a firmware update.
+pub const INFO_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The port the yeelight devices answer the discovery on.
+const DISCOVERY_PORT: u16 = 1982;
+
+/// What's known of the device, published retained to `smart-home-system/yeelight/info` for the accessory
+/// information of the homekit bridge. The wifi signal isn't part of it, the yeelight protocol doesn't report it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub manufacturer: &'static str,
+    pub id: String,
+    pub model: Option<String>,
+    pub firmware: Option<String>,
+    pub address: String,
+}
+
+impl DeviceInfo {
+    pub fn new(id: &str, address: &str, model: Option<&str>) -> Self {
+        Self { manufacturer: "Yeelight", id: id.into(), model: model.map(String::from), firmware: None, address: address.into() }
+    }
+
+    /// Completes the info with the answer of the device to a discovery sent only to it, the only way it tells
+    /// its firmware version.
+    #[cfg(feature = "discovery")]
+    async fn probe(&mut self, address: SocketAddr, timeout: Duration) {
+        match discovery::discover_at(address, timeout).await {
+            Ok(responses) => {
+                if let Some(response) = responses.into_iter().next() {
+                    self.model = Some(response.model);
+                    self.firmware = response.firmware;
+                }
+            }
+            Err(e) => warn!("Failed to ask the yeelight device at {} for its firmware: {:#}", address, e),
+        }
+    }
+
+    /// Built without the discovery feature, the firmware isn't known.
+    #[cfg(not(feature = "discovery"))]
+    async fn probe(&mut self, _address: SocketAddr, _timeout: Duration) {}
+}
+
+/// Asks the device for its firmware, waiting up to the timeout for it to answer, then publishes the info.
+pub async fn publish(client: Client, mut info: DeviceInfo, timeout: Duration) {
+    // A device configured by its host name isn't asked
+    if let Ok(mut address) = info.address.parse::<SocketAddr>() {
+        address.set_port(DISCOVERY_PORT);
+        info.probe(address, timeout).await;
+    }
+
+    match serde_json::to_string(&info) {
+        Ok(payload) => mqtt::publish(&client, Message::new_retained(topic().info(), payload, 1)),
+        Err(e) => warn!("Failed to serialize the info of the yeelight device: {}", e),
+    }
+}
+
+#[cfg(all(test, feature = "discovery"))]
+mod tests {
+    use std::time::Duration;
+
+    use crate::device_info::DeviceInfo;
+    use crate::discovery::FakeResponder;
+
+    #[tokio::test]
+    async fn test_probe() {
+        let responder = FakeResponder::start(vec![FakeResponder::response("0x1", "color", "yeelight://127.0.0.1:55443")]).await;
+
+        let mut info = DeviceInfo::new("0x1", "127.0.0.1:55443", None);
+        info.probe(responder.address, Duration::from_millis(200)).await;
+        assert_eq!(info.model.as_deref(), Some("color"));
+        assert_eq!(info.firmware.as_deref(), Some("18"));
+
+        let payload = serde_json::to_value(&info).unwrap();
+        assert_eq!(payload["manufacturer"], "Yeelight");
+        assert_eq!(payload["id"], "0x1");
+    }
+
+    #[tokio::test]
+    async fn test_probe_without_answer() {
+        let responder = FakeResponder::start(Vec::new()).await;
+
+        let mut info = DeviceInfo::new("0x1", "127.0.0.1:55443", Some("mono"));
+        info.probe(responder.address, Duration::from_millis(100)).await;
+        assert_eq!(info, DeviceInfo::new("0x1", "127.0.0.1:55443", Some("mono")));
+    }
+}
@@ -1,10 +1,14 @@
+// Only the responses are parsed without the discovery feature, e.g. by the fuzz target
+#![cfg_attr(not(feature = "discovery"), allow(dead_code, unused_imports))]
+
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::Context;
+#[cfg(feature = "discovery")]
 use local_ip_address::local_ip;
-use log::{error, info};
+use tracing::{error, info};
 use tokio::net::UdpSocket;
 
 const SOCKET_CAST_ADDR: SocketAddrV4 = SocketAddrV4::new(MULTI_CAST_ADDR, 1982);
@@ -16,13 +20,17 @@ pub struct DiscoveryResponse {
     pub model: String,
     pub id: String,
     pub location: String,
+    /// The firmware version, which the devices only tell in the discovery.
+    pub firmware: Option<String>,
 }
 
-fn parse(response: &[u8]) -> anyhow::Result<DiscoveryResponse> {
+/// Parses an answer to the discovery, which can be anything another host on the LAN sends.
+pub fn parse(response: &[u8]) -> anyhow::Result<DiscoveryResponse> {
     let response = std::str::from_utf8(response)?;
     let mut model = None;
     let mut id = None;
     let mut location = None;
+    let mut firmware = None;
 
     for line in response.lines() {
         if let Some((key, value)) = line.split_once(": ") {
@@ -30,6 +38,7 @@ fn parse(response: &[u8]) -> anyhow::Result<DiscoveryResponse> {
                 "model" => model = Some(value.to_string()),
                 "id" => id = Some(value.to_string()),
                 "Location" => location = Some(value.to_string()),
+                "fw_ver" => firmware = Some(value.to_string()),
                 _ => {}
             }
         }
@@ -39,14 +48,28 @@ fn parse(response: &[u8]) -> anyhow::Result<DiscoveryResponse> {
         model: model.context("No model found in response")?,
         id: id.context("No id found in response")?,
         location: location.context("No location found in response")?,
+        firmware,
     })
 }
 
+#[cfg(feature = "discovery")]
 pub async fn discover(timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse>> {
+    discover_at(SOCKET_CAST_ADDR.into(), timeout).await
+}
+
+/// Built without the discovery feature, the device is only connected to at its configured address.
+#[cfg(not(feature = "discovery"))]
+pub async fn discover(_timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse>> {
+    anyhow::bail!("The controller is built without the discovery feature, set the address of the device")
+}
+
+/// Discovers the devices answering the probe sent to the address, e.g. a single host instead of every device.
+#[cfg(feature = "discovery")]
+pub async fn discover_at(address: SocketAddr, timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse>> {
     let my_local_ip = local_ip().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
     let socket = UdpSocket::bind(SocketAddr::new(my_local_ip, 0)).await?;
 
-    socket.send_to(DISCOVERY_MESSAGE, SOCKET_CAST_ADDR).await?;
+    socket.send_to(DISCOVERY_MESSAGE, address).await?;
     info!("Discovering on {} with timeout {timeout:?}", socket.local_addr()?);
 
     let mut buf = [0; 2048];
@@ -76,4 +99,96 @@ pub async fn discover(timeout: Duration) -> anyhow::Result<Vec<DiscoveryResponse
     let _ = tokio::time::timeout(timeout, discover).await;
 
     Ok(Arc::try_unwrap(responses).unwrap().into_inner().unwrap())
-}
\ No newline at end of file
+}
+/// Answers the discovery probes with the configured responses, so the discovery can be tested without devices.
+#[cfg(test)]
+pub struct FakeResponder {
+    pub address: SocketAddr,
+    handle: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(test)]
+impl FakeResponder {
+    /// Answers every probe with each of the responses, in order, which can be duplicated or malformed.
+    pub async fn start(responses: Vec<Vec<u8>>) -> Self {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = socket.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            while let Ok((len, sender)) = socket.recv_from(&mut buf).await {
+                if buf[..len] != *DISCOVERY_MESSAGE {
+                    continue;
+                }
+                for response in &responses {
+                    let _ = socket.send_to(response, sender).await;
+                }
+            }
+        });
+
+        Self { address, handle }
+    }
+
+    /// The response of a bulb, as sent by the real ones.
+    pub fn response(id: &str, model: &str, location: &str) -> Vec<u8> {
+        format!("HTTP/1.1 200 OK\r\nCache-Control: max-age=3600\r\nLocation: {}\r\nServer: POSIX UPnP/1.0 YGLC/1\r\n\
+                 id: {}\r\nmodel: {}\r\nfw_ver: 18\r\npower: on\r\nbright: 100\r\n", location, id, model).into_bytes()
+    }
+}
+
+#[cfg(test)]
+impl Drop for FakeResponder {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    #[cfg(feature = "discovery")]
+    use crate::discovery::discover_at;
+    use crate::discovery::{parse, DiscoveryResponse, FakeResponder};
+
+    fn device(id: &str, model: &str, location: &str) -> DiscoveryResponse {
+        DiscoveryResponse { id: id.into(), model: model.into(), location: location.into(), firmware: Some("18".into()) }
+    }
+
+    #[test]
+    fn test_parse() {
+        let response = FakeResponder::response("0x1", "color", "yeelight://192.168.1.10:55443");
+        assert_eq!(parse(&response).unwrap(), device("0x1", "color", "yeelight://192.168.1.10:55443"));
+        let without_firmware = parse(b"HTTP/1.1 200 OK\r\nLocation: yeelight://192.168.1.10:55443\r\nid: 0x1\r\nmodel: color\r\n");
+        assert_eq!(without_firmware.unwrap().firmware, None);
+
+        assert!(parse(b"HTTP/1.1 200 OK\r\nLocation: yeelight://192.168.1.10:55443\r\nmodel: color\r\n").is_err());
+        assert!(parse(&[0xff, 0xfe]).is_err());
+    }
+
+    #[cfg(feature = "discovery")]
+    #[tokio::test]
+    async fn test_discover() {
+        let responder = FakeResponder::start(vec![
+            FakeResponder::response("0x1", "color", "yeelight://192.168.1.10:55443"),
+            // Sent again by the same device
+            FakeResponder::response("0x1", "color", "yeelight://192.168.1.10:55443"),
+            b"HTTP/1.1 200 OK\r\nid: 0x3\r\n".to_vec(),
+            b"NOTIFY * HTTP/1.1\r\nmodel color\r\n".to_vec(),
+            FakeResponder::response("0x2", "mono", "yeelight://192.168.1.11:55443"),
+        ]).await;
+
+        let devices = discover_at(responder.address, Duration::from_millis(200)).await.unwrap();
+        assert_eq!(devices, [
+            device("0x1", "color", "yeelight://192.168.1.10:55443"),
+            device("0x2", "mono", "yeelight://192.168.1.11:55443"),
+        ]);
+    }
+
+    #[cfg(feature = "discovery")]
+    #[tokio::test]
+    async fn test_discover_without_answers() {
+        let responder = FakeResponder::start(Vec::new()).await;
+        assert!(discover_at(responder.address, Duration::from_millis(100)).await.unwrap().is_empty());
+    }
+}
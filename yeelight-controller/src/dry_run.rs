@@ -0,0 +1,97 @@
+use anyhow::Context;
+use tokio::net::TcpListener;
+use tracing::{error, warn};
+use yeelight_emulator::{Emulator, Faults, State};
+
+use crate::application::DeviceFilters;
+use crate::config::Config;
+use crate::state::{LastState, StateFile};
+
+/// Changes the config to control a simulated device served on a local port instead of the real one, which is
+/// never connected to. The simulated device starts from the last state of the real one, and its state and usage
+/// are kept next to the ones of the real device, e.g. in `yeelight-state.dry-run.json`, so they aren't touched.
+pub async fn start(config: &mut Config) -> anyhow::Result<()> {
+    let last_state = StateFile::load(config.state_path.clone())?.state();
+
+    config.state_path = config.state_path.with_extension("dry-run.json");
+    config.usage_path = config.usage_path.with_extension("dry-run.json");
+    let content = serde_json::to_string_pretty(&last_state).context("Failed to serialize the last state")?;
+    std::fs::write(&config.state_path, content)
+        .context(format!("Failed to write the last state to {:?}", config.state_path))?;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.context("Failed to listen for the simulated yeelight device")?;
+    let address = listener.local_addr()?.to_string();
+
+    let id = config.device.id.clone().unwrap_or_else(|| "dry-run".into());
+    let model = config.device.model.clone().unwrap_or_else(|| "color".into());
+    let emulator = Emulator::new(id.clone(), model.clone(), simulated_state(&last_state), Faults::default());
+    tokio::spawn(async move {
+        if let Err(e) = emulator.serve(listener).await {
+            error!("The simulated yeelight device stopped: {:#}", e);
+        }
+    });
+
+    warn!("Dry run, the commands are applied to a simulated yeelight device at {} instead of the real one", address);
+    config.device = DeviceFilters { id: Some(id), model: Some(model), address: Some(address) };
+    Ok(())
+}
+
+/// The state of the simulated device, the one of a new bulb for what isn't known of the real one.
+fn simulated_state(state: &LastState) -> State {
+    let default = State::default();
+    State {
+        power: state.power.as_deref().map_or(default.power, |power| power == "on"),
+        bright: state.brightness.filter(|brightness| (1..=100).contains(brightness)).unwrap_or(default.bright),
+        ct: state.color_temperature.unwrap_or(default.ct),
+        ..default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::mpsc;
+
+    use crate::config::Config;
+    use crate::dry_run::{simulated_state, start};
+    use crate::health::Health;
+    use crate::protocol::Method;
+    use crate::state::{LastState, StateFile};
+    use crate::yeelight::Device;
+
+    #[test]
+    fn test_simulated_state() {
+        let state = simulated_state(&LastState { power: Some("off".into()), brightness: Some(0), color_temperature: Some(2700) });
+        assert!(!state.power);
+        assert_eq!(state.bright, 100);
+        assert_eq!(state.ct, 2700);
+    }
+
+    #[tokio::test]
+    async fn test_controls_the_simulated_device() {
+        let directory = std::env::temp_dir().join(format!("yeelight-dry-run-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let state_path = directory.join("yeelight-state.json");
+        StateFile::load(state_path.clone()).unwrap().update(|state| state.power = Some("off".into()));
+
+        let mut config = Config { state_path: state_path.clone(), usage_path: directory.join("yeelight-usage.json"), ..Config::default() };
+        start(&mut config).await.unwrap();
+        assert_eq!(config.state_path, directory.join("yeelight-state.dry-run.json"));
+        assert_eq!(config.usage_path, directory.join("yeelight-usage.dry-run.json"));
+        assert_eq!(StateFile::load(config.state_path.clone()).unwrap().state().power.as_deref(), Some("off"));
+
+        let (sender, mut receiver) = mpsc::channel(1);
+        let address = config.device.address.clone().unwrap();
+        let device = Device::new("dry-run".into(), address, sender, Health::without_mqtt()).await.unwrap();
+
+        device.send_method(Method::TOGGLE).await.unwrap();
+        let notification = tokio::time::timeout(Duration::from_secs(1), receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(notification.params["power"], "on");
+
+        // The state of the real device is kept
+        assert_eq!(StateFile::load(state_path).unwrap().state().power.as_deref(), Some("off"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
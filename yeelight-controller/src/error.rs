@@ -0,0 +1,40 @@
+use std::fmt::{Display, Formatter};
+
+/// Why a command received from mqtt, the api or the admin console couldn't be handled. The controller keeps
+/// running after any of them, they're only logged and published to the error topic.
+#[derive(Debug)]
+pub enum ControllerError {
+    /// The payload isn't valid for the topic.
+    InvalidPayload(anyhow::Error),
+    /// The command couldn't be sent to the device, or it didn't answer in time.
+    Device(anyhow::Error),
+    /// The device answered with an error, e.g. when its command quota is exceeded.
+    Rejected { code: i64, message: String },
+    /// The connection to the device is lost, the command isn't sent while it's found again.
+    Disconnected,
+}
+
+impl ControllerError {
+    /// Published with the error, so the errors of the user can be told apart from the ones of the device.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidPayload(_) => "invalid_payload",
+            Self::Device(_) => "device",
+            Self::Rejected { .. } => "rejected",
+            Self::Disconnected => "disconnected",
+        }
+    }
+}
+
+impl Display for ControllerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPayload(e) => write!(f, "{:#}", e),
+            Self::Device(e) => write!(f, "{:#}", e),
+            Self::Rejected { code, message } => write!(f, "The yeelight device answered with error {}: {}", code, message),
+            Self::Disconnected => write!(f, "The yeelight device is disconnected, reconnecting to it"),
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {}
@@ -0,0 +1,134 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Time between two brightness changes of a fade. Each change is a smooth transition of this
+/// duration, so the brightness changes continuously. Yeelight devices accept at most 60 commands per minute.
+pub const FADE_STEP: Duration = Duration::from_secs(2);
+
+/// A `target,duration` payload, e.g. `80,600` to fade to 80% over 10 minutes.
+/// A target of 0 fades to the minimum brightness and turns the device off.
+#[derive(Debug, PartialEq)]
+pub struct FadeRequest {
+    pub target: u8,
+    pub duration: Duration,
+}
+
+impl FromStr for FadeRequest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, duration) = s.trim().split_once(',').ok_or_else(|| format!("Expected target,duration: {}", s))?;
+
+        let target = target.trim().parse::<u8>().ok().filter(|target| *target <= 100)
+            .ok_or_else(|| format!("Invalid target brightness: {}", target))?;
+        let duration = duration.trim().parse::<u64>().map_err(|_| format!("Invalid duration in seconds: {}", duration))?;
+
+        Ok(Self { target, duration: Duration::from_secs(duration) })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FadeStep {
+    /// Transition to this brightness during the next step.
+    Brightness(u8),
+    /// The fade finished, turn the device off.
+    Off,
+    /// The fade finished.
+    Done,
+}
+
+#[derive(Debug)]
+pub struct Fade {
+    from: u8,
+    to: u8,
+    turn_off: bool,
+    started: Instant,
+    duration: Duration,
+    /// Every brightness sent, to tell them apart from changes made by someone else.
+    sent: Vec<u8>,
+}
+
+impl Fade {
+    pub fn new(from: u8, request: &FadeRequest, now: Instant) -> Self {
+        Self {
+            from: from.clamp(1, 100),
+            to: request.target.max(1),
+            turn_off: request.target == 0,
+            started: now,
+            duration: request.duration,
+            sent: vec![],
+        }
+    }
+
+    fn brightness_at(&self, time: Instant) -> u8 {
+        let elapsed = time.duration_since(self.started);
+        if elapsed >= self.duration {
+            return self.to;
+        }
+
+        let progress = elapsed.as_secs_f64() / self.duration.as_secs_f64();
+        (self.from as f64 + (self.to as f64 - self.from as f64) * progress).round() as u8
+    }
+
+    /// The next change, which should be reached by the end of the step starting at `now`.
+    pub fn step(&mut self, now: Instant) -> FadeStep {
+        if self.sent.last() == Some(&self.to) && now.duration_since(self.started) >= self.duration {
+            return if self.turn_off { FadeStep::Off } else { FadeStep::Done };
+        }
+
+        let brightness = self.brightness_at(now + FADE_STEP);
+        self.sent.push(brightness);
+
+        FadeStep::Brightness(brightness)
+    }
+
+    /// Treats the device reporting `brightness` as caused by the fade, e.g. the brightness it had
+    /// when it was turned on to start the fade.
+    pub fn expect(&mut self, brightness: u8) {
+        self.sent.push(brightness);
+    }
+
+    /// Whether the device reporting `brightness` is caused by the fade.
+    pub fn is_expected(&self, brightness: u8) -> bool {
+        brightness == self.from || self.sent.contains(&brightness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::fade::{Fade, FadeRequest, FadeStep, FADE_STEP};
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!("80,600".parse(), Ok(FadeRequest { target: 80, duration: Duration::from_secs(600) }));
+        assert_eq!(" 0, 30 ".parse(), Ok(FadeRequest { target: 0, duration: Duration::from_secs(30) }));
+        assert!("101,600".parse::<FadeRequest>().is_err());
+        assert!("80".parse::<FadeRequest>().is_err());
+    }
+
+    #[test]
+    fn test_steps() {
+        let start = Instant::now();
+        let mut fade = Fade::new(10, &"50,8".parse().unwrap(), start);
+
+        assert_eq!(fade.step(start), FadeStep::Brightness(20));
+        assert_eq!(fade.step(start + FADE_STEP), FadeStep::Brightness(30));
+        assert_eq!(fade.step(start + FADE_STEP * 2), FadeStep::Brightness(40));
+        assert_eq!(fade.step(start + FADE_STEP * 3), FadeStep::Brightness(50));
+        assert_eq!(fade.step(start + FADE_STEP * 4), FadeStep::Done);
+
+        assert!(fade.is_expected(30));
+        assert!(!fade.is_expected(35));
+    }
+
+    #[test]
+    fn test_fade_out() {
+        let start = Instant::now();
+        let mut fade = Fade::new(40, &"0,0".parse().unwrap(), start);
+
+        assert_eq!(fade.step(start), FadeStep::Brightness(1));
+        assert_eq!(fade.step(start + FADE_STEP), FadeStep::Off);
+    }
+}
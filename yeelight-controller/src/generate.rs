@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::config::TimeoutsConfig;
+use crate::discovery::{self, DiscoveryResponse};
+
+const TEMPLATE: &str = include_str!("yeelight.example.yaml");
+
+/// The commented out device section of the template, replaced by the devices discovered.
+const DEVICE_SECTION: &str = "# device:\n#   id: \"0x0000000012345678\"\n#   model: color\n";
+
+/// The commented example config, with the first device discovered as the one to control and the others
+/// commented out.
+pub fn example_config(devices: &[DiscoveryResponse]) -> String {
+    let Some((first, others)) = devices.split_first() else {
+        return TEMPLATE.to_string();
+    };
+
+    let mut section = format!("device:\n  # {} at {}\n  id: \"{}\"\n", first.model, first.location, first.id);
+    for device in others {
+        section += &format!("  # Also found, {} at {}\n  # id: \"{}\"\n", device.model, device.location, device.id);
+    }
+
+    TEMPLATE.replacen(DEVICE_SECTION, &section, 1)
+}
+
+/// Writes the example config to the file, which can't exist yet, or prints it if there's no file.
+pub async fn generate_config(output: Option<&Path>, discover: bool) -> anyhow::Result<()> {
+    let devices = match discover {
+        true => discovery::discover(TimeoutsConfig::default().discovery()).await.context("Yeelight discovery failed")?,
+        false => vec![],
+    };
+
+    let config = example_config(&devices);
+
+    match output {
+        Some(path) => {
+            let mut file = std::fs::OpenOptions::new().write(true).create_new(true).open(path)
+                .context(format!("Failed to create {:?}", path))?;
+            file.write_all(config.as_bytes()).context(format!("Failed to write {:?}", path))?;
+            eprintln!("Wrote the config to {:?}, with {} devices found", path, devices.len());
+        }
+        None => print!("{}", config),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::discovery::DiscoveryResponse;
+    use crate::generate::{example_config, DEVICE_SECTION, TEMPLATE};
+
+    fn device(id: &str, model: &str) -> DiscoveryResponse {
+        DiscoveryResponse { id: id.into(), model: model.into(), location: "yeelight://192.168.1.10:55443".into(), firmware: None }
+    }
+
+    #[test]
+    fn test_example_config() {
+        assert!(TEMPLATE.contains(DEVICE_SECTION));
+
+        let config: Config = serde_yaml::from_str(&example_config(&[])).unwrap();
+        assert_eq!(config.mqtt.server_uri.as_deref(), Some("tcp://localhost:1883"));
+        assert_eq!(config.device.id, None);
+        assert!(config.validate().is_ok());
+
+        let generated = example_config(&[device("0x0000000012345678", "color"), device("0x000000001234abcd", "mono")]);
+        let config: Config = serde_yaml::from_str(&generated).unwrap();
+        assert_eq!(config.device.id.as_deref(), Some("0x0000000012345678"));
+        assert!(generated.contains("# id: \"0x000000001234abcd\""));
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use mqtt_client::Client;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceState {
+    Discovering,
+    Connected,
+    /// The connection to the device was lost, until the controller finds it again.
+    Disconnected,
+}
+
+struct DeviceHealth {
+    state: DeviceState,
+    last_command: Option<Instant>,
+}
+
+/// What the `/healthz` and `/readyz` endpoints report.
+#[derive(Clone)]
+pub struct Health {
+    /// None in the cli.
+    client: Option<Client>,
+    device: Arc<Mutex<DeviceHealth>>,
+}
+
+impl Health {
+    pub fn new(client: Client) -> Self {
+        Self { client: Some(client), device: Self::discovering() }
+    }
+
+    /// For the cli, which talks to the device without mqtt.
+    #[allow(dead_code)]
+    pub fn without_mqtt() -> Self {
+        Self { client: None, device: Self::discovering() }
+    }
+
+    fn discovering() -> Arc<Mutex<DeviceHealth>> {
+        Arc::new(Mutex::new(DeviceHealth { state: DeviceState::Discovering, last_command: None }))
+    }
+
+    fn mqtt_connected(&self) -> bool {
+        match &self.client {
+            Some(client) => client.is_connected(),
+            None => true,
+        }
+    }
+
+    pub fn set_device_state(&self, state: DeviceState) {
+        self.device.lock().unwrap().state = state;
+    }
+
+    pub fn command_succeeded(&self) {
+        self.device.lock().unwrap().last_command = Some(Instant::now());
+    }
+
+    pub fn device_state(&self) -> DeviceState {
+        self.device.lock().unwrap().state
+    }
+
+    /// Whether the controller works or is still looking for the device.
+    pub fn is_healthy(&self) -> bool {
+        self.mqtt_connected() && self.device_state() != DeviceState::Disconnected
+    }
+
+    /// Whether the controller is connected to the device and can handle commands.
+    #[cfg(feature = "metrics-server")]
+    pub fn is_ready(&self) -> bool {
+        self.mqtt_connected() && self.device_state() == DeviceState::Connected
+    }
+
+    pub fn report(&self) -> String {
+        let device = self.device.lock().unwrap();
+        let last_command = match device.last_command {
+            Some(instant) => format!("{}s ago", instant.elapsed().as_secs()),
+            None => "never".to_string(),
+        };
+
+        let mut report = String::new();
+        let _ = writeln!(report, "mqtt: {}", if self.mqtt_connected() { "connected" } else { "disconnected" });
+        let _ = writeln!(report, "device: {:?}", device.state);
+        let _ = writeln!(report, "last command: {}", last_command);
+        report
+    }
+}
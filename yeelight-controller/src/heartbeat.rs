@@ -0,0 +1,87 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Remembers the last error logged, to report it in the heartbeat.
+#[derive(Clone, Default)]
+pub struct LastError(Arc<Mutex<Option<String>>>);
+
+impl LastError {
+    pub fn get(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+struct MessageVisitor(Option<String>);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LastError {
+    fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+
+        let mut visitor = MessageVisitor(None);
+        event.record(&mut visitor);
+
+        if let Some(message) = visitor.0 {
+            *self.0.lock().unwrap() = Some(message);
+        }
+    }
+}
+
+/// The json published periodically to `smart-home-system/yeelight/heartbeat`, so a watchdog can tell the
+/// controller still runs even when it holds its mqtt connection.
+pub fn payload(started: Instant, connected_devices: usize, last_error: Option<String>) -> String {
+    json!({
+        "uptime_secs": started.elapsed().as_secs(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "connected_devices": connected_devices,
+        "last_error": last_error,
+    }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tracing::{error, info};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::heartbeat::{payload, LastError};
+
+    #[test]
+    fn test_last_error() {
+        let last_error = LastError::default();
+        let subscriber = tracing_subscriber::registry().with(last_error.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!("Connected to yeelight device");
+            assert_eq!(last_error.get(), None);
+
+            error!("Failed to read from yeelight device: {}", "connection reset");
+            info!("Retrying");
+        });
+
+        assert_eq!(last_error.get().as_deref(), Some("Failed to read from yeelight device: connection reset"));
+
+        let payload: serde_json::Value = serde_json::from_str(&payload(Instant::now(), 1, last_error.get())).unwrap();
+        assert_eq!(payload["connected_devices"], 1);
+        assert_eq!(payload["last_error"], "Failed to read from yeelight device: connection reset");
+        assert_eq!(payload["version"], env!("CARGO_PKG_VERSION"));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use domain_state::topic::Attribute;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::watch;
+
+use crate::error::ControllerError;
+use crate::state::LastState;
+
+/// How many transitions are kept, the oldest ones are dropped.
+pub const CAPACITY: usize = 256;
+
+/// A change of one attribute of the device.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Transition {
+    /// Milliseconds since the unix epoch.
+    pub time_ms: u64,
+    /// e.g. `power`, as in the topics.
+    pub attribute: &'static str,
+    pub value: Value,
+}
+
+/// The last transitions of the device, only kept in memory.
+#[derive(Clone, Default)]
+pub struct History(Arc<Mutex<VecDeque<Transition>>>);
+
+impl History {
+    fn push(&self, transition: Transition) {
+        let mut transitions = self.0.lock().unwrap();
+        if transitions.len() == CAPACITY {
+            transitions.pop_front();
+        }
+        transitions.push_back(transition);
+    }
+
+    /// The last transitions, the newest first.
+    pub fn last(&self, count: usize) -> Vec<Transition> {
+        self.0.lock().unwrap().iter().rev().take(count).cloned().collect()
+    }
+
+    /// The json answered on `smart-home-system/yeelight/history` to a `history/get` with the number of
+    /// transitions as the payload, or every one kept if it's empty.
+    pub fn payload(&self, request: &str) -> Result<String, ControllerError> {
+        let count = match request.trim() {
+            "" => CAPACITY,
+            count => count.parse().context("Invalid number of transitions").map_err(ControllerError::InvalidPayload)?,
+        };
+        Ok(json!(self.last(count)).to_string())
+    }
+}
+
+/// The attributes that changed between the states.
+fn transitions(previous: &LastState, state: &LastState, time_ms: u64) -> Vec<Transition> {
+    let mut transitions = Vec::new();
+    let mut changed = |attribute: Attribute, value: Option<Value>| {
+        if let Some(value) = value {
+            transitions.push(Transition { time_ms, attribute: attribute.name(), value });
+        }
+    };
+
+    if state.power != previous.power {
+        changed(Attribute::Power, state.power.clone().map(Value::from));
+    }
+    if state.brightness != previous.brightness {
+        changed(Attribute::Brightness, state.brightness.map(Value::from));
+    }
+    if state.color_temperature != previous.color_temperature {
+        changed(Attribute::ColorTemperature, state.color_temperature.map(Value::from));
+    }
+    transitions
+}
+
+/// Adds the changes of the state to the history, starting from the state before the restart.
+pub async fn record(history: History, mut changes: watch::Receiver<LastState>) {
+    let mut previous = changes.borrow_and_update().clone();
+
+    while changes.changed().await.is_ok() {
+        let state = changes.borrow_and_update().clone();
+        let time_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        for transition in transitions(&previous, &state, time_ms) {
+            history.push(transition);
+        }
+        previous = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::history::{record, transitions, History, Transition, CAPACITY};
+    use crate::state::{LastState, StateFile};
+
+    #[test]
+    fn test_transitions() {
+        let previous = LastState { power: Some("off".into()), brightness: Some(40), color_temperature: None };
+        let state = LastState { power: Some("on".into()), brightness: Some(40), color_temperature: Some(2700) };
+
+        assert_eq!(transitions(&previous, &state, 1000), [
+            Transition { time_ms: 1000, attribute: "power", value: json!("on") },
+            Transition { time_ms: 1000, attribute: "color_temperature", value: json!(2700) },
+        ]);
+        assert!(transitions(&state, &state, 1000).is_empty());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = History::default();
+        for brightness in 0..CAPACITY + 10 {
+            history.push(Transition { time_ms: brightness as u64, attribute: "brightness", value: json!(brightness) });
+        }
+
+        let last = history.last(usize::MAX);
+        assert_eq!(last.len(), CAPACITY);
+        assert_eq!(last[0].value, json!(CAPACITY + 9));
+        assert_eq!(last[CAPACITY - 1].value, json!(10));
+    }
+
+    #[tokio::test]
+    async fn test_records_the_changes() {
+        let path = std::env::temp_dir().join(format!("yeelight-history-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let state_file = StateFile::load(path.clone()).unwrap();
+
+        let history = History::default();
+        tokio::spawn(record(history.clone(), state_file.subscribe()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        state_file.update(|state| state.power = Some("on".into()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        state_file.update(|state| state.brightness = Some(70));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let payload: serde_json::Value = serde_json::from_str(&history.payload("1").unwrap()).unwrap();
+        assert_eq!(payload.as_array().unwrap().len(), 1);
+        assert_eq!(payload[0]["attribute"], "brightness");
+        assert_eq!(payload[0]["value"], 70);
+
+        let payload: serde_json::Value = serde_json::from_str(&history.payload("").unwrap()).unwrap();
+        assert_eq!(payload[1]["attribute"], "power");
+        assert_eq!(payload[1]["value"], "on");
+
+        assert_eq!(history.payload("last").unwrap_err().to_string(), "Invalid number of transitions: invalid digit found in string");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,55 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::{Body, Request, Response, Server, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use tracing::{error, info};
+
+use crate::health::Health;
+use crate::metrics;
+
+fn response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(body.into());
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle_request(request: Request<Body>, health: Health) -> Result<Response<Body>, Infallible> {
+    let response = match request.uri().path() {
+        "/metrics" => {
+            let mut response = response(StatusCode::OK, metrics::encode());
+            response.headers_mut().insert(hyper::header::CONTENT_TYPE, metrics::CONTENT_TYPE.parse().unwrap());
+            response
+        }
+        "/healthz" => match health.is_healthy() {
+            true => response(StatusCode::OK, health.report()),
+            false => response(StatusCode::SERVICE_UNAVAILABLE, health.report()),
+        },
+        "/readyz" => match health.is_ready() {
+            true => response(StatusCode::OK, health.report()),
+            false => response(StatusCode::SERVICE_UNAVAILABLE, health.report()),
+        },
+        _ => response(StatusCode::NOT_FOUND, "Not found"),
+    };
+
+    Ok(response)
+}
+
+/// Serves `/metrics`, `/healthz` and `/readyz`.
+pub async fn serve(address: SocketAddr, health: Health) {
+    let make_service = make_service_fn(move |_| {
+        let health = health.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle_request(request, health.clone()))) }
+    });
+
+    let server = match Server::try_bind(&address) {
+        Ok(server) => server,
+        Err(e) => return error!("Failed to serve http on {}: {}", address, e),
+    };
+
+    info!("Serving metrics and health checks on http://{}", address);
+
+    if let Err(e) = server.serve(make_service).await {
+        error!("Http server failed: {}", e);
+    }
+}
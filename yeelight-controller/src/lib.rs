@@ -0,0 +1,349 @@
+//! The yeelight controller, run by its own binary or next to the other services by the launcher.
+
+#[cfg(any(feature = "metrics-server", feature = "api"))]
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use domain_state::Topic;
+use tracing::{error, info};
+use mqtt_client::{Message, Messages};
+use tokio::sync::{mpsc, watch};
+
+#[cfg(feature = "api")]
+use crate::api::Api;
+use crate::args::{Args, Command};
+use crate::application::{publish_error, publish_last_state, Application, Request};
+use crate::config::TimeoutsConfig;
+use crate::console::Console;
+use crate::health::{DeviceState, Health};
+use crate::heartbeat::HEARTBEAT_INTERVAL;
+use crate::history::History;
+use crate::logging::Logs;
+use crate::reload::Reloadable;
+use crate::mqtt::{connect_mqtt, Client, Deduplicated};
+use crate::power_consumption::PowerCurve;
+use crate::startup::StartupError;
+use crate::state::StateFile;
+use crate::usage::UsageFile;
+
+pub mod yeelight;
+pub mod protocol;
+pub mod recording;
+mod application;
+pub mod args;
+mod check;
+mod config;
+mod error;
+mod mqtt;
+mod mqttthing;
+mod remap;
+mod device_info;
+pub mod discovery;
+#[cfg(feature = "dry-run")]
+mod dry_run;
+mod fade;
+mod generate;
+pub mod health;
+mod heartbeat;
+mod history;
+#[cfg(feature = "api")]
+mod api;
+mod console;
+#[cfg(feature = "metrics-server")]
+mod http;
+pub mod logging;
+mod metrics;
+mod power_consumption;
+mod reload;
+pub mod startup;
+mod state;
+mod supervisor;
+mod systemd;
+mod throttle;
+mod telemetry;
+mod usage;
+
+/// The device the topics of the controller are under, e.g. `smart-home-system/yeelight/power`.
+const DEVICE: &str = "yeelight";
+
+fn topic() -> Topic {
+    Topic::device(DEVICE)
+}
+
+/// The connection to the mqtt server with the messages received on it, shared by the launcher with the bridge.
+pub struct MqttConnection {
+    pub client: mqtt_client::Client,
+    pub messages: Messages,
+}
+
+/// Runs the controller until the connection to the broker is closed, or runs the command of the arguments. The
+/// config check fails with [`StartupError::Config`] if a setting is invalid.
+pub async fn run(args: Args, logs: Logs) -> Result<(), StartupError> {
+    run_on(args, logs, None).await
+}
+
+/// As [`run`], on the connection to the mqtt server of the launcher instead of its own. The launcher subscribes to
+/// the topics of every service again when it reconnects, the controller would only know its own.
+pub async fn run_shared(args: Args, logs: Logs, connection: MqttConnection) -> Result<(), StartupError> {
+    run_on(args, logs, Some(connection)).await
+}
+
+async fn run_on(args: Args, logs: Logs, connection: Option<MqttConnection>) -> Result<(), StartupError> {
+    let started = Instant::now();
+
+    if let Some(Command::GenerateConfig { output, discover }) = &args.command {
+        return generate::generate_config(output.as_deref(), *discover).await.map_err(StartupError::Other);
+    }
+
+    if args.discover_only {
+        return print_devices().await.map_err(StartupError::Other);
+    }
+
+    if args.check {
+        match args.config_path() {
+            Some(path) => println!("Checking the config {:?} with the env vars", path),
+            None => println!("Checking the config of the env vars, there's no config file"),
+        }
+        let report = check::check(&args);
+        println!("{}", report);
+        return match report.has_errors() {
+            true => Err(StartupError::Config(anyhow::anyhow!("{} settings are invalid, see the report above", report.errors().count()))),
+            false => Ok(()),
+        };
+    }
+
+    let config_path = args.config_path();
+    #[cfg_attr(not(feature = "dry-run"), allow(unused_mut))]
+    let mut config = args.load_config().map_err(StartupError::Config)?;
+    // Reloaded against the config as it's loaded, without the changes of the dry run
+    let loaded_config = config.clone();
+
+    let (diagnostics_interval, diagnostics_interval_receiver) = watch::channel(None);
+    let reloadable = Reloadable { log_filter: logs.filter, throttle: logs.throttle, diagnostics_interval };
+    reloadable.apply(&config).map_err(StartupError::Config)?;
+    mqtt::set_topic_prefix(config.mqtt.topic_prefix.clone());
+    remap::enable(&config.mqtt.remap).map_err(StartupError::Config)?;
+    if let Some(path) = &config.record_path {
+        info!("Recording the traffic of the yeelight device to {:?}", path);
+        recording::enable(path).map_err(StartupError::Storage)?;
+    }
+
+    // The homebridge-mqttthing topics are only used if their prefix is configured
+    if let Some(prefix) = config.mqttthing_topic_prefix.clone() {
+        info!("Using the homebridge-mqttthing topics under {}", prefix);
+        mqttthing::enable(prefix);
+    }
+    let mqttthing_topics = mqttthing::get().map(|mqttthing| mqttthing.subscribe_topics().to_vec()).unwrap_or_default();
+
+    let subscribe_topics: Vec<String> = [
+        topic().power().set(),
+        topic().brightness().set(),
+        topic().brightness().fade(),
+        topic().set(),
+        topic().toggle(),
+        topic().power().get(),
+        topic().brightness().get(),
+        topic().diagnostics().get(),
+        topic().history().get(),
+        topic().usage_counter().reset()].into_iter()
+        .map(String::from)
+        .chain(mqttthing_topics)
+        .collect();
+    let subscribe_topics: Vec<&str> = subscribe_topics.iter().map(String::as_str).collect();
+
+    let (mqtt_client, stream) = match connection {
+        Some(MqttConnection { client, messages }) => {
+            mqtt::subscribe(&client, &subscribe_topics).await.map_err(StartupError::Unavailable)?;
+            (client, messages)
+        }
+        None => connect_mqtt(
+            &subscribe_topics,
+            config.mqtt.server_uri.clone().unwrap_or_default(),
+            config.mqtt.client_id.clone(),
+            config.mqtt.username.clone(),
+            config.mqtt.password.clone(),
+        ).await?,
+    };
+    let subscribed = mqtt::broker_topics(&subscribe_topics);
+    let client: Client = Arc::new(Deduplicated::new(mqtt_client.clone()));
+
+    match &config.profile {
+        Some(profile) => info!("Starting yeelight controller with the {} profile", profile),
+        None => info!("Starting yeelight controller"),
+    }
+
+    if args.dry_run {
+        #[cfg(feature = "dry-run")]
+        dry_run::start(&mut config).await.map_err(StartupError::Storage)?;
+        #[cfg(not(feature = "dry-run"))]
+        return Err(StartupError::Config(anyhow::anyhow!("--dry-run needs the controller built with the dry-run feature")));
+    }
+
+    let state_file = StateFile::load(config.state_path.clone()).map_err(StartupError::Storage)?;
+    publish_last_state(&client, &state_file);
+    let usage_file = UsageFile::load(config.usage_path.clone()).map_err(StartupError::Storage)?;
+
+    let health = Health::new(mqtt_client);
+
+    #[cfg(feature = "metrics-server")]
+    tokio::spawn(http::serve(SocketAddr::from(([0, 0, 0, 0], config.http_port)), health.clone()));
+    tokio::spawn(systemd::run_watchdog(health.clone()));
+
+    // The commands of mqtt, the api and the admin console, handled one at a time by the application
+    let (requests, request_receiver) = mpsc::channel(8);
+
+    #[cfg(feature = "api")]
+    let api = Api::new(health.clone(), state_file.clone(), requests.clone());
+    #[cfg(feature = "api")]
+    if let Some(port) = config.api_port {
+        tokio::spawn(api::serve(SocketAddr::from(([0, 0, 0, 0], port)), api.clone()));
+    }
+
+    if let Some(path) = config.admin_socket.clone() {
+        tokio::spawn(console::serve(path, Console::new(health.clone(), state_file.clone(), requests.clone())));
+    }
+
+    let heartbeat_client = client.clone();
+    let heartbeat_health = health.clone();
+    let last_error = logs.last_error;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let connected_devices = usize::from(heartbeat_health.device_state() == DeviceState::Connected);
+            let payload = heartbeat::payload(started, connected_devices, last_error.get());
+            mqtt::publish(&heartbeat_client, Message::new(topic().heartbeat(), payload, 0));
+        }
+    });
+
+    tokio::spawn(publish_diagnostics(client.clone(), diagnostics_interval_receiver));
+
+    if let Some(path) = config_path {
+        tokio::spawn(reload::watch(path, args, loaded_config, reloadable, client.clone()));
+    }
+
+    let state_changes = state_file.subscribe();
+    tokio::spawn(usage::track(client.clone(), usage_file.clone(), state_file.subscribe()));
+    let history = History::default();
+    tokio::spawn(history::record(history.clone(), state_file.subscribe()));
+    let mut application = Application::new(
+        client.clone(),
+        config.device.clone(),
+        &config.timeouts,
+        config.notification_capacity,
+        health,
+        state_file,
+        config.brightness_zero_turns_off,
+    ).await;
+
+    info!("Connected to yeelight device.");
+    #[cfg(feature = "api")]
+    api.set_device_id(application.device_id());
+    tokio::spawn(power_consumption::publish(client.clone(), PowerCurve::for_model(application.device_model()), state_changes));
+
+    application.publish_current_state().await;
+    systemd::notify_ready();
+
+    info!("Waiting for mqtt messages...");
+
+    // Returns when the connection to the broker is closed
+    tokio::select! {
+        _ = application.run(request_receiver) => {}
+        _ = receive_mqtt_messages(&stream, &subscribed, &client, requests, &usage_file, &history) => {}
+    }
+
+    Ok(())
+}
+
+/// Sends the commands received from mqtt to the application, publishing the ones that are invalid as errors.
+async fn receive_mqtt_messages(
+    stream: &Messages,
+    subscribed: &[String],
+    client: &Client,
+    requests: mpsc::Sender<Request>,
+    usage_file: &UsageFile,
+    history: &History,
+) {
+    while let Ok(message) = stream.recv().await {
+        let Some(message) = message else { continue };
+        // e.g. the states the bridge subscribed to, on the connection the launcher shares with it
+        if !subscribed.iter().any(|topic| topic == message.topic()) {
+            continue;
+        }
+
+        metrics::mqtt_received(message.topic());
+        let message = mqtt::controller_message(message);
+
+        // The commands on the mqttthing topics are handled as the commands of the controller
+        let inbound = mqttthing::get().and_then(|mqttthing| mqttthing.inbound(message.topic(), &message.payload_str()));
+        let message = match inbound {
+            Some((topic, payload)) => Message::new(topic, payload, message.qos()),
+            None => message,
+        };
+
+        if topic().diagnostics().get() == *message.topic() {
+            mqtt::publish(client, Message::new(topic().diagnostics(), metrics::diagnostics(), 0));
+            continue;
+        }
+
+        if topic().history().get() == *message.topic() {
+            match history.payload(&message.payload_str()) {
+                Ok(payload) => mqtt::publish(client, Message::new(topic().history(), payload, 0)),
+                Err(e) => publish_error(client, &message, &e),
+            }
+            continue;
+        }
+
+        if topic().usage_counter().reset() == *message.topic() {
+            usage_file.reset();
+            usage::publish_counter(client, usage_file);
+            continue;
+        }
+
+        match application::Command::from_message(&message) {
+            Ok(Some(command)) => {
+                if requests.send(Request { command, message: Some(message), result: None }).await.is_err() {
+                    return;
+                }
+            }
+            Ok(None) => error!("Received message for unknown topic: {}", message.topic()),
+            Err(e) => publish_error(client, &message, &e),
+        }
+    }
+}
+
+/// Prints the id, model and address of the devices found on the network.
+async fn print_devices() -> anyhow::Result<()> {
+    let devices = discovery::discover(TimeoutsConfig::default().discovery()).await
+        .context("Yeelight discovery failed")?;
+
+    for device in devices {
+        println!("{}\t{}\t{}", device.id, device.model, device.location);
+    }
+
+    Ok(())
+}
+
+/// Publishes the command latencies every interval, only while the interval is set.
+async fn publish_diagnostics(client: Client, mut interval_secs: watch::Receiver<Option<u64>>) {
+    loop {
+        let secs = *interval_secs.borrow_and_update();
+
+        if let Some(secs) = secs {
+            let mut interval = tokio::time::interval(Duration::from_secs(secs.max(1)));
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => mqtt::publish(&client, Message::new(topic().diagnostics(), metrics::diagnostics(), 0)),
+                    changed = interval_secs.changed() => match changed {
+                        Ok(()) => break,
+                        Err(_) => return,
+                    },
+                }
+            }
+        } else if interval_secs.changed().await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt::{self, Debug};
+
+use anyhow::Context;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::heartbeat::LastError;
+use crate::reload::LogFilter;
+use crate::startup::StartupError;
+use crate::telemetry;
+use crate::throttle::Throttle;
+
+/// The logs of the controller, with the handles changing them when the config is reloaded.
+pub struct Logs {
+    pub(crate) filter: Option<LogFilter>,
+    pub(crate) throttle: Throttle,
+    pub(crate) last_error: LastError,
+}
+
+impl Logs {
+    /// Sets up the logs of the process, which can only be done once.
+    pub fn init() -> Result<Self, StartupError> {
+        let last_error = LastError::default();
+        let throttle = Throttle::default();
+        let json_logs = json_enabled();
+
+        let (filter, filter_handle) = tracing_subscriber::reload::Layer::new(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")));
+
+        tracing_subscriber::registry()
+            .with(filter)
+            .with((!json_logs).then(|| tracing_subscriber::fmt::layer().with_filter(throttle.clone())))
+            .with(json_logs.then(|| tracing_subscriber::fmt::layer().fmt_fields(JsonFields::new()).event_format(JsonFormat).with_filter(throttle.clone())))
+            .with(last_error.clone())
+            .with(telemetry::layer().context("Failed to set up the OTLP exporter").map_err(StartupError::Config)?)
+            .init();
+
+        tokio::spawn(throttle.clone().run());
+
+        Ok(Self { filter: Some(filter_handle), throttle, last_error })
+    }
+
+    /// For a process whose logs are already set up, e.g. the launcher running the controller next to the bridge.
+    /// The log level and the throttle window of the config aren't applied, and the heartbeat has no last error.
+    pub fn external() -> Self {
+        Self { filter: None, throttle: Throttle::default(), last_error: LastError::default() }
+    }
+}
+
+/// Whether env `LOG_FORMAT` asks for json logs instead of text.
+pub fn json_enabled() -> bool {
+    std::env::var("LOG_FORMAT").is_ok_and(|format| format.eq_ignore_ascii_case("json"))
+}
+
+/// Formats an event as a json object on a single line, with the fields of the spans it's in next to its
+/// own, e.g. `{"timestamp":"...","level":"INFO","component":"yeelight-controller","topic":"...","message":"..."}`.
+/// The span fields must be recorded with [`tracing_subscriber::fmt::format::JsonFields`].
+pub struct JsonFormat;
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().into(), format!("{:?}", value).into());
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+        N: for<'a> FormatFields<'a> + 'static {
+    fn format_event(&self, context: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut object = Map::new();
+        object.insert("timestamp".into(), timestamp.into());
+        object.insert("level".into(), event.metadata().level().as_str().into());
+        object.insert("component".into(), env!("CARGO_PKG_NAME").into());
+        object.insert("target".into(), event.metadata().target().into());
+
+        for span in context.event_scope().into_iter().flat_map(|scope| scope.from_root()) {
+            if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                if let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(fields) {
+                    object.extend(fields);
+                }
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::Value;
+    use tracing::{info, info_span};
+    use tracing_subscriber::fmt::format::JsonFields;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::logging::JsonFormat;
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_format() {
+        let output = Output::default();
+        let writer = output.clone();
+        let layer = tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonFormat)
+            .with_writer(move || writer.clone());
+
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            let _message = info_span!("mqtt_message", topic = "smart-home-system/yeelight/power/set").entered();
+            let _command = info_span!("yeelight_command", device = "0x1234", command_id = 7).entered();
+            info!(power = "on", "Setting power");
+        });
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["component"], "yeelight-controller");
+        assert_eq!(line["topic"], "smart-home-system/yeelight/power/set");
+        assert_eq!(line["device"], "0x1234");
+        assert_eq!(line["command_id"], 7);
+        assert_eq!(line["power"], "on");
+        assert_eq!(line["message"], "Setting power");
+    }
+}
@@ -0,0 +1,256 @@
+#[cfg(feature = "metrics")]
+use std::collections::{BTreeMap, VecDeque};
+#[cfg(feature = "metrics")]
+use std::sync::{Mutex, OnceLock};
+#[cfg(feature = "metrics")]
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+use prometheus::core::Collector;
+#[cfg(feature = "metrics")]
+use prometheus::{GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry};
+#[cfg(feature = "metrics-server")]
+use prometheus::{Encoder, TextEncoder};
+#[cfg(feature = "metrics-server")]
+use tracing::error;
+
+/// How many of the last commands the latency percentiles are computed from.
+#[cfg(feature = "metrics")]
+const LATENCY_WINDOW: usize = 1000;
+
+// The registry and the quantiles are only read when encoded for the metrics server
+#[cfg(feature = "metrics")]
+#[cfg_attr(not(feature = "metrics-server"), allow(dead_code))]
+struct Metrics {
+    registry: Registry,
+    command_duration: HistogramVec,
+    command_failures: IntCounter,
+    command_duration_quantiles: GaugeVec,
+    mqtt_received: IntCounterVec,
+    mqtt_published: IntCounterVec,
+    mqtt_publish_failures: IntCounterVec,
+    latencies: Mutex<VecDeque<Duration>>,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("yeelight_controller".into()), None)
+            .expect("The metrics registry should be created successfully.");
+
+        let command_duration = HistogramVec::new(
+            HistogramOpts::new("command_duration_seconds", "Round trip time of the commands sent to the yeelight device")
+                .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]),
+            &["method"],
+        ).expect("The metric should be valid.");
+        registry.register(Box::new(command_duration.clone())).expect("The metric should be registered once.");
+
+        let command_failures = IntCounter::new("command_failures_total", "Commands that failed or timed out")
+            .expect("The metric should be valid.");
+        registry.register(Box::new(command_failures.clone())).expect("The metric should be registered once.");
+
+        let command_duration_quantiles = GaugeVec::new(
+            Opts::new("command_duration_quantile_seconds", "Round trip time percentiles of the last commands"),
+            &["quantile"],
+        ).expect("The metric should be valid.");
+        registry.register(Box::new(command_duration_quantiles.clone())).expect("The metric should be registered once.");
+
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &["topic"]).expect("The metric should be valid.");
+            registry.register(Box::new(counter.clone())).expect("The metric should be registered once.");
+            counter
+        };
+
+        let mqtt_received = counter("mqtt_messages_received_total", "Mqtt messages received");
+        let mqtt_published = counter("mqtt_messages_published_total", "Mqtt messages published");
+        let mqtt_publish_failures = counter("mqtt_publish_failures_total", "Mqtt messages the broker didn't acknowledge");
+
+        Self {
+            registry,
+            command_duration,
+            command_failures,
+            command_duration_quantiles,
+            mqtt_received,
+            mqtt_published,
+            mqtt_publish_failures,
+            latencies: Mutex::new(VecDeque::new()),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[cfg(feature = "metrics")]
+pub fn command_succeeded(method: &str, duration: Duration) {
+    metrics().command_duration.with_label_values(&[method]).observe(duration.as_secs_f64());
+
+    let mut latencies = metrics().latencies.lock().unwrap();
+    if latencies.len() == LATENCY_WINDOW {
+        latencies.pop_front();
+    }
+    latencies.push_back(duration);
+}
+
+#[cfg(feature = "metrics")]
+pub fn command_failed() {
+    metrics().command_failures.inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn command_failures() -> u64 {
+    metrics().command_failures.get()
+}
+
+#[cfg(feature = "metrics")]
+pub fn mqtt_received(topic: &str) {
+    metrics().mqtt_received.with_label_values(&[topic]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn mqtt_published(topic: &str) {
+    metrics().mqtt_published.with_label_values(&[topic]).inc();
+}
+
+#[cfg(feature = "metrics")]
+pub fn mqtt_publish_failed(topic: &str) {
+    metrics().mqtt_publish_failures.with_label_values(&[topic]).inc();
+}
+
+/// The value of a counter for each topic it was incremented for.
+#[cfg(feature = "metrics")]
+fn per_topic(counter: &IntCounterVec) -> BTreeMap<String, u64> {
+    counter.collect().iter()
+        .flat_map(|family| family.get_metric())
+        .filter_map(|metric| {
+            let topic = metric.get_label().iter().find(|label| label.get_name() == "topic")?;
+            Some((topic.get_value().to_string(), metric.get_counter().get_value() as u64))
+        })
+        .collect()
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Debug, PartialEq)]
+pub struct Latencies {
+    pub samples: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+/// The round trip time percentiles of the last commands, if any was sent.
+#[cfg(feature = "metrics")]
+pub fn latencies() -> Option<Latencies> {
+    percentiles(metrics().latencies.lock().unwrap().iter().copied().collect())
+}
+
+#[cfg(feature = "metrics")]
+fn percentiles(mut samples: Vec<Duration>) -> Option<Latencies> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    samples.sort();
+    // Nearest rank
+    let percentile = |quantile: f64| samples[((quantile * samples.len() as f64).ceil() as usize).max(1) - 1];
+
+    Some(Latencies { samples: samples.len(), p50: percentile(0.5), p95: percentile(0.95), p99: percentile(0.99) })
+}
+
+/// The command latency percentiles and failures and the mqtt messages and publish failures per topic as json,
+/// published to mqtt for diagnostics.
+#[cfg(feature = "metrics")]
+pub fn diagnostics() -> String {
+    let latencies = latencies();
+    let millis = |percentile: fn(&Latencies) -> Duration| latencies.as_ref().map(|latencies| percentile(latencies).as_secs_f64() * 1000.0);
+
+    serde_json::json!({
+        "samples": latencies.as_ref().map_or(0, |latencies| latencies.samples),
+        "p50_ms": millis(|latencies| latencies.p50),
+        "p95_ms": millis(|latencies| latencies.p95),
+        "p99_ms": millis(|latencies| latencies.p99),
+        "failures": command_failures(),
+        "received": per_topic(&metrics().mqtt_received),
+        "published": per_topic(&metrics().mqtt_published),
+        "publish_failures": per_topic(&metrics().mqtt_publish_failures),
+    }).to_string()
+}
+
+/// Built without the metrics feature, nothing is counted and the diagnostics are empty.
+#[cfg(not(feature = "metrics"))]
+mod disabled {
+    use std::time::Duration;
+
+    pub fn command_succeeded(_method: &str, _duration: Duration) {}
+
+    pub fn command_failed() {}
+
+    pub fn mqtt_received(_topic: &str) {}
+
+    pub fn mqtt_published(_topic: &str) {}
+
+    pub fn mqtt_publish_failed(_topic: &str) {}
+
+    pub fn diagnostics() -> String {
+        "{}".into()
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub use disabled::*;
+
+#[cfg(feature = "metrics-server")]
+pub const CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+/// Encodes every metric in the prometheus text format.
+#[cfg(feature = "metrics-server")]
+pub fn encode() -> Vec<u8> {
+    if let Some(latencies) = latencies() {
+        for (quantile, value) in [("0.5", latencies.p50), ("0.95", latencies.p95), ("0.99", latencies.p99)] {
+            metrics().command_duration_quantiles.with_label_values(&[quantile]).set(value.as_secs_f64());
+        }
+    }
+
+    let mut buffer = vec![];
+    if let Err(e) = TextEncoder::new().encode(&metrics().registry.gather(), &mut buffer) {
+        error!("Failed to encode metrics: {}", e);
+    }
+    buffer
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::time::Duration;
+
+    use crate::metrics::{diagnostics, mqtt_published, mqtt_received, percentiles, Latencies};
+
+    #[test]
+    fn test_percentiles() {
+        assert_eq!(percentiles(vec![]), None);
+
+        let samples = (1..=100).rev().map(Duration::from_millis).collect();
+        assert_eq!(percentiles(samples), Some(Latencies {
+            samples: 100,
+            p50: Duration::from_millis(50),
+            p95: Duration::from_millis(95),
+            p99: Duration::from_millis(99),
+        }));
+
+        assert_eq!(percentiles(vec![Duration::from_millis(7)]).unwrap().p99, Duration::from_millis(7));
+    }
+
+    #[test]
+    fn test_diagnostics_topics() {
+        for _ in 0..3 {
+            mqtt_received("test/diagnostics/set");
+        }
+        mqtt_published("test/diagnostics");
+
+        let diagnostics: serde_json::Value = serde_json::from_str(&diagnostics()).unwrap();
+        assert_eq!(diagnostics["received"]["test/diagnostics/set"], 3);
+        assert_eq!(diagnostics["published"]["test/diagnostics"], 1);
+    }
+}
@@ -1,44 +1,298 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
 
 use anyhow::Context;
-use paho_mqtt::{AsyncClient, AsyncReceiver, Message};
+use async_trait::async_trait;
+use mqtt_client::{ConnectError, ConnectOptions, Message, Messages};
+use tracing::{info, warn};
+
+use crate::startup::StartupError;
+use crate::{metrics, mqttthing, remap};
+
+/// The prefix of the topics of the controller, replaced by the configured prefix on the broker.
+pub const DEFAULT_TOPIC_PREFIX: &str = "smart-home-system/yeelight";
+
+static TOPIC_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// How long the broker has to acknowledge a published message before it's counted as failed.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Replaces `smart-home-system/yeelight` in the topics published and subscribed to.
+pub fn set_topic_prefix(prefix: String) {
+    if prefix != DEFAULT_TOPIC_PREFIX {
+        let _ = TOPIC_PREFIX.set(prefix);
+    }
+}
+
+/// Publishes messages to the broker, implemented by the mqtt client and by [`FakeClient`] in the tests.
+pub trait Publish: Send + Sync {
+    fn publish(&self, message: Message);
+}
+
+/// Subscribes to topics on the broker, implemented by the mqtt client and by [`FakeClient`] in the tests.
+#[async_trait]
+pub trait Subscribe: Send + Sync {
+    async fn subscribe(&self, topic: &str) -> anyhow::Result<()>;
+}
+
+/// The client the controller publishes with, shared by its tasks.
+pub type Client = Arc<dyn Publish>;
+
+impl Publish for mqtt_client::Client {
+    fn publish(&self, message: Message) {
+        let topic = message.topic().to_string();
+        let token = mqtt_client::Client::publish(self, message);
+
+        // The broker acknowledges the message in the background, a failure would otherwise lose it silently
+        tokio::spawn(async move {
+            let error = match tokio::time::timeout(DELIVERY_TIMEOUT, token).await {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => format!("not acknowledged in {:?}", DELIVERY_TIMEOUT),
+            };
+            warn!("Failed to publish to {}: {}", topic, error);
+            metrics::mqtt_publish_failed(&topic);
+        });
+    }
+}
+
+impl<P: Publish + ?Sized> Publish for Arc<P> {
+    fn publish(&self, message: Message) {
+        (**self).publish(message);
+    }
+}
+
+/// Skips the retained messages with the payload last published to their topic, e.g. the state notified by the
+/// device when it echoes a command, so the subscribers aren't told about changes that didn't happen.
+pub struct Deduplicated<P> {
+    client: P,
+    last_published: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl<P> Deduplicated<P> {
+    pub fn new(client: P) -> Self {
+        Self { client, last_published: Mutex::default() }
+    }
+}
+
+impl<P: Publish> Publish for Deduplicated<P> {
+    fn publish(&self, message: Message) {
+        if message.retained() {
+            let mut last_published = self.last_published.lock().unwrap();
+            if last_published.get(message.topic()).is_some_and(|payload| payload == message.payload()) {
+                return;
+            }
+            last_published.insert(message.topic().to_string(), message.payload().to_vec());
+        }
+
+        self.client.publish(message);
+    }
+}
+
+#[async_trait]
+impl Subscribe for mqtt_client::Client {
+    async fn subscribe(&self, topic: &str) -> anyhow::Result<()> {
+        mqtt_client::Client::subscribe(self, topic).await
+    }
+}
+
+fn replace_prefix(topic: &str, from: &str, to: &str) -> Option<String> {
+    match topic.strip_prefix(from) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(format!("{}{}", to, rest)),
+        _ => None,
+    }
+}
+
+fn replace_topic_prefix(topic: &str) -> Option<String> {
+    replace_prefix(topic, DEFAULT_TOPIC_PREFIX, TOPIC_PREFIX.get()?)
+}
+
+/// The topic on the broker of a topic of the controller, if it was remapped or the prefix was replaced.
+fn broker_topic(topic: &str) -> Option<String> {
+    remap::get().and_then(|remap| remap.outbound(topic)).or_else(|| replace_topic_prefix(topic))
+}
+
+/// As [`broker_topic`], remembering the remapped topics to rewrite the messages received on them.
+fn subscribe_topic(topic: &str) -> Option<String> {
+    remap::get().and_then(|remap| remap.subscribe(topic)).or_else(|| replace_topic_prefix(topic))
+}
+
+/// The message with the topic of the controller of a message received from the broker.
+pub fn controller_message(message: Message) -> Message {
+    let topic = remap::get().and_then(|remap| remap.inbound(message.topic()))
+        .or_else(|| TOPIC_PREFIX.get().and_then(|prefix| replace_prefix(message.topic(), prefix, DEFAULT_TOPIC_PREFIX)));
+
+    match topic {
+        Some(topic) => Message::new(topic, message.payload(), message.qos()),
+        None => message,
+    }
+}
 
 pub async fn connect_mqtt(
     subscribe_topics: &[&str],
     server_uri: String,
+    client_id: String,
     username: Option<String>,
     password: Option<String>,
-) -> anyhow::Result<(AsyncClient, AsyncReceiver<Option<Message>>)> {
-    let create_options = paho_mqtt::CreateOptionsBuilder::new()
-        .server_uri(server_uri)
-        .client_id("yeelight-controller")
-        .finalize();
+) -> Result<(mqtt_client::Client, Messages), StartupError> {
+    let options = ConnectOptions {
+        username,
+        password,
+        reconnect: Some((Duration::from_secs(1), Duration::from_secs(30))),
+        ..ConnectOptions::new(server_uri, client_id)
+    };
 
-    let mut client = AsyncClient::new(create_options)
-        .context("Failed to create mqtt client")?;
+    let (client, stream) = mqtt_client::connect(&options).await.map_err(|e| match e {
+        ConnectError::Options(e) => StartupError::Config(e),
+        ConnectError::Unavailable(e) => StartupError::Unavailable(e),
+    })?;
+    subscribe(&client, subscribe_topics).await.map_err(StartupError::Unavailable)?;
+    resubscribe_on_reconnect(&client, subscribe_topics);
+
+    Ok((client, stream))
+}
 
-    let mut connection_options = paho_mqtt::ConnectOptionsBuilder::new();
+/// Subscribes to the topics again whenever the client reconnects, as the subscriptions are lost with the clean
+/// session it starts.
+fn resubscribe_on_reconnect(client: &mqtt_client::Client, topics: &[&str]) {
+    let topics = broker_topics(topics);
 
-    if let Some(username) = username {
-        connection_options.user_name(username);
+    client.on_reconnect(move |client| {
+        info!("Reconnected to the mqtt server, subscribing to the topics again");
+        for topic in &topics {
+            // Acknowledged in the background
+            drop(client.subscribe(topic));
+        }
+    });
+}
+
+/// The topics subscribed to on the broker for the topics of the controller, remapped or with the prefix replaced.
+pub fn broker_topics(topics: &[&str]) -> Vec<String> {
+    topics.iter()
+        .map(|&topic| subscribe_topic(topic).unwrap_or_else(|| topic.to_string()))
+        .collect()
+}
+
+/// Subscribes to the topics on the broker, remapped or with the prefix replaced.
+pub async fn subscribe(client: &impl Subscribe, topics: &[&str]) -> anyhow::Result<()> {
+    for &topic in topics {
+        let topic = subscribe_topic(topic).unwrap_or_else(|| topic.to_string());
+        client.subscribe(&topic).await.context(format!("Failed to subscribe to topic: {}", topic))?;
     }
 
-    if let Some(password) = password {
-        connection_options.password(password);
+    Ok(())
+}
+
+/// Publishes the message, counting it in the diagnostics, and its mqttthing counterpart if enabled.
+pub fn publish(client: &dyn Publish, message: Message) {
+    let mqttthing = mqttthing::get().and_then(|mqttthing| mqttthing.outbound(message.topic(), &message.payload_str()));
+
+    if let Some((topic, payload)) = mqttthing {
+        let message = match message.retained() {
+            true => Message::new_retained(topic, payload, message.qos()),
+            false => Message::new(topic, payload, message.qos()),
+        };
+        metrics::mqtt_published(message.topic());
+        client.publish(message);
     }
 
-    let connection_options = connection_options
-        .clean_session(true)
-        .automatic_reconnect(Duration::from_secs(1), Duration::from_secs(30))
-        .finalize();
+    let message = match broker_topic(message.topic()) {
+        Some(topic) if message.retained() => Message::new_retained(topic, message.payload(), message.qos()),
+        Some(topic) => Message::new(topic, message.payload(), message.qos()),
+        None => message,
+    };
 
-    let stream = client.get_stream(10);
+    metrics::mqtt_published(message.topic());
+    client.publish(message);
+}
 
-    client.connect(connection_options).await.context("Failed to connect to mqtt server")?;
+/// Keeps the messages published and the topics subscribed to in memory, instead of sending them to a broker.
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeClient {
+    published: std::sync::Mutex<Vec<Message>>,
+    subscribed: std::sync::Mutex<Vec<String>>,
+}
 
-    for &topic in subscribe_topics {
-        client.subscribe(topic, 1).await.context(format!("Failed to subscribe to topic: {}", topic))?;
+#[cfg(test)]
+impl FakeClient {
+    /// The topics and payloads of the messages published since the last call.
+    pub fn take_published(&self) -> Vec<(String, String)> {
+        self.published.lock().unwrap().drain(..)
+            .map(|message| (message.topic().to_string(), message.payload_str().to_string()))
+            .collect()
     }
 
-    Ok((client, stream))
-}
\ No newline at end of file
+    pub fn subscribed(&self) -> Vec<String> {
+        self.subscribed.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Publish for FakeClient {
+    fn publish(&self, message: Message) {
+        self.published.lock().unwrap().push(message);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Subscribe for FakeClient {
+    async fn subscribe(&self, topic: &str) -> anyhow::Result<()> {
+        self.subscribed.lock().unwrap().push(topic.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mqtt_client::Message;
+
+    use crate::mqtt::{publish, replace_prefix, subscribe, Deduplicated, FakeClient, Publish};
+
+    #[test]
+    fn test_replace_prefix() {
+        let replace = |topic| replace_prefix(topic, "smart-home-system/yeelight", "home/bedroom-light");
+
+        assert_eq!(replace("smart-home-system/yeelight/power/set").as_deref(), Some("home/bedroom-light/power/set"));
+        assert_eq!(replace("smart-home-system/yeelight").as_deref(), Some("home/bedroom-light"));
+        assert_eq!(replace("smart-home-system/yeelight-2/power"), None);
+        assert_eq!(replace("homebridge/yeelight/setOn"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fake_client() {
+        let client = FakeClient::default();
+
+        subscribe(&client, &["smart-home-system/yeelight/power/set", "smart-home-system/yeelight/toggle"]).await.unwrap();
+        assert_eq!(client.subscribed(), ["smart-home-system/yeelight/power/set", "smart-home-system/yeelight/toggle"]);
+
+        publish(&client, Message::new_retained("smart-home-system/yeelight/power", "on", 1));
+        assert_eq!(client.take_published(), [("smart-home-system/yeelight/power".to_string(), "on".to_string())]);
+        assert!(client.take_published().is_empty());
+    }
+
+    #[test]
+    fn test_deduplicated() {
+        let client = Deduplicated::new(FakeClient::default());
+
+        client.publish(Message::new_retained("smart-home-system/yeelight/power", "on", 1));
+        client.publish(Message::new_retained("smart-home-system/yeelight/power", "on", 1));
+        client.publish(Message::new_retained("smart-home-system/yeelight/brightness", "30", 1));
+        client.publish(Message::new_retained("smart-home-system/yeelight/power", "off", 1));
+        client.publish(Message::new_retained("smart-home-system/yeelight/power", "on", 1));
+        // Only the state is deduplicated, not the events
+        client.publish(Message::new("smart-home-system/yeelight/error", "{}", 1));
+        client.publish(Message::new("smart-home-system/yeelight/error", "{}", 1));
+
+        assert_eq!(client.client.take_published(), [
+            ("smart-home-system/yeelight/power".to_string(), "on".to_string()),
+            ("smart-home-system/yeelight/brightness".to_string(), "30".to_string()),
+            ("smart-home-system/yeelight/power".to_string(), "off".to_string()),
+            ("smart-home-system/yeelight/power".to_string(), "on".to_string()),
+            ("smart-home-system/yeelight/error".to_string(), "{}".to_string()),
+            ("smart-home-system/yeelight/error".to_string(), "{}".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::OnceLock;
+
+use domain_state::topic::Attribute;
+use domain_state::Topic;
+
+use crate::DEVICE;
+
+/// The topics of a homebridge-mqttthing lightbulb, under a prefix, e.g. `<prefix>/setOn` with `true` or `false`.
+/// Its `topics` should be configured as `getOn`, `setOn`, `getBrightness`, `setBrightness` and `getOnline`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mqttthing {
+    prefix: String,
+}
+
+static MQTTTHING: OnceLock<Mqttthing> = OnceLock::new();
+
+/// Also publishes and subscribes to the mqttthing topics under the prefix.
+pub fn enable(prefix: String) {
+    let _ = MQTTTHING.set(Mqttthing::new(prefix));
+}
+
+pub fn get() -> Option<&'static Mqttthing> {
+    MQTTTHING.get()
+}
+
+fn power_to_bool(payload: &str) -> &'static str {
+    if payload.trim().eq_ignore_ascii_case("on") { "true" } else { "false" }
+}
+
+fn bool_to_power(payload: &str) -> &'static str {
+    if payload.trim().eq_ignore_ascii_case("true") { "on" } else { "off" }
+}
+
+impl Mqttthing {
+    fn new(prefix: String) -> Self {
+        Self { prefix: prefix.trim_end_matches('/').to_string() }
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+
+    pub fn subscribe_topics(&self) -> [String; 2] {
+        [self.topic("setOn"), self.topic("setBrightness")]
+    }
+
+    /// The topic and payload of the command of the controller for a message received on a mqttthing topic.
+    pub fn inbound(&self, topic: &str, payload: &str) -> Option<(Topic, String)> {
+        if topic == self.topic("setOn") {
+            Some((crate::topic().power().set(), bool_to_power(payload).to_string()))
+        } else if topic == self.topic("setBrightness") {
+            Some((crate::topic().brightness().set(), payload.trim().to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// The mqttthing topic and payload for a message published by the controller, if mqttthing has a topic for it.
+    pub fn outbound(&self, topic: &str, payload: &str) -> Option<(String, String)> {
+        let topic = topic.parse::<Topic>().ok().filter(|topic| topic.device_name() == DEVICE && topic.action().is_none())?;
+        match topic.attribute()? {
+            Attribute::Power => Some((self.topic("getOn"), power_to_bool(payload).to_string())),
+            Attribute::Brightness => Some((self.topic("getBrightness"), payload.trim().to_string())),
+            // Stale while the device isn't reachable
+            Attribute::Stale => Some((self.topic("getOnline"), (payload.trim() != "true").to_string())),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mqttthing::Mqttthing;
+    use crate::topic;
+
+    #[test]
+    fn test_translation() {
+        let mqttthing = Mqttthing::new("homebridge/bedroom-light/".into());
+        let pair = |(topic, payload): (&str, &str)| (topic.to_string(), payload.to_string());
+
+        assert_eq!(mqttthing.subscribe_topics(), ["homebridge/bedroom-light/setOn", "homebridge/bedroom-light/setBrightness"]);
+
+        assert_eq!(mqttthing.inbound("homebridge/bedroom-light/setOn", "true"), Some((topic().power().set(), "on".into())));
+        assert_eq!(mqttthing.inbound("homebridge/bedroom-light/setBrightness", "40"), Some((topic().brightness().set(), "40".into())));
+        assert_eq!(mqttthing.inbound("homebridge/bedroom-light/getOn", "true"), None);
+
+        assert_eq!(mqttthing.outbound("smart-home-system/yeelight/power", "off"), Some(pair(("homebridge/bedroom-light/getOn", "false"))));
+        assert_eq!(mqttthing.outbound("smart-home-system/yeelight/brightness", "40"), Some(pair(("homebridge/bedroom-light/getBrightness", "40"))));
+        assert_eq!(mqttthing.outbound("smart-home-system/yeelight/stale", "true"), Some(pair(("homebridge/bedroom-light/getOnline", "false"))));
+        assert_eq!(mqttthing.outbound("smart-home-system/yeelight/heartbeat", "{}"), None);
+    }
+}
@@ -0,0 +1,125 @@
+use domain_state::Power;
+use mqtt_client::Message;
+use tokio::sync::watch;
+
+use crate::mqtt::{self, Client};
+use crate::state::LastState;
+use crate::topic;
+
+/// The watts drawn by a model of device in standby and at its lowest and highest brightness, from their rated
+/// power. The LED drivers are close enough to linear in between for an energy dashboard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerCurve {
+    standby: f64,
+    min: f64,
+    max: f64,
+}
+
+impl PowerCurve {
+    /// The curve of the model, or the one of the color bulb for the unknown ones.
+    pub fn for_model(model: Option<&str>) -> Self {
+        let (standby, min, max) = match model {
+            Some("mono") | Some("ct_bulb") => (0.3, 0.8, 8.0),
+            Some("stripe") => (0.4, 0.7, 7.5),
+            Some("bslamp") => (0.4, 0.6, 6.5),
+            Some("desklamp") => (0.3, 0.5, 5.0),
+            Some("ceiling") => (0.5, 2.0, 28.0),
+            _ => (0.4, 1.0, 9.0),
+        };
+        Self { standby, min, max }
+    }
+
+    fn watts(&self, power: Power, brightness: u8) -> f64 {
+        match power {
+            Power::Off => self.standby,
+            Power::On => {
+                let t = f64::from(brightness.clamp(1, 100) - 1) / 99.0;
+                self.min * (1.0 - t) + self.max * t
+            }
+        }
+    }
+
+    /// The watts of the state, unless its power isn't known yet, or its brightness while it's on.
+    fn estimate(&self, state: &LastState) -> Option<f64> {
+        match state.power.as_deref()?.parse().ok()? {
+            Power::Off => Some(self.standby),
+            Power::On => Some(self.watts(Power::On, state.brightness?)),
+        }
+    }
+}
+
+/// Publishes the estimated watts to `smart-home-system/yeelight/power_consumption` whenever the power or the
+/// brightness changes, so energy dashboards have the lights without smart plugs.
+pub async fn publish(client: Client, curve: PowerCurve, mut changes: watch::Receiver<LastState>) {
+    loop {
+        let watts = curve.estimate(&changes.borrow_and_update());
+        if let Some(watts) = watts {
+            mqtt::publish(&client, Message::new_retained(topic().power_consumption(), format!("{:.1}", watts), 1));
+        }
+
+        if changes.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use domain_state::Power;
+
+    use crate::mqtt::FakeClient;
+    use crate::power_consumption::{publish, PowerCurve};
+    use crate::state::{LastState, StateFile};
+
+    #[test]
+    fn test_watts() {
+        let curve = PowerCurve::for_model(Some("color"));
+        assert_eq!(curve.watts(Power::Off, 100), 0.4);
+        assert_eq!(curve.watts(Power::On, 1), 1.0);
+        assert_eq!(curve.watts(Power::On, 100), 9.0);
+        assert_eq!(curve.watts(Power::On, 0), 1.0);
+        assert!((curve.watts(Power::On, 50) - 4.96).abs() < 0.01);
+
+        assert_eq!(PowerCurve::for_model(Some("ceiling")).watts(Power::On, 100), 28.0);
+        assert_eq!(PowerCurve::for_model(None), curve);
+    }
+
+    #[test]
+    fn test_estimate() {
+        let curve = PowerCurve::for_model(Some("mono"));
+        let state = |power: Option<&str>, brightness: Option<u8>| LastState {
+            power: power.map(String::from),
+            brightness,
+            color_temperature: None,
+        };
+
+        assert_eq!(curve.estimate(&state(None, Some(100))), None);
+        assert_eq!(curve.estimate(&state(Some("on"), None)), None);
+        assert_eq!(curve.estimate(&state(Some("off"), None)), Some(0.3));
+        assert_eq!(curve.estimate(&state(Some("on"), Some(100))), Some(8.0));
+    }
+
+    #[tokio::test]
+    async fn test_publishes_the_changes() {
+        let path = std::env::temp_dir().join(format!("yeelight-power-consumption-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let state_file = StateFile::load(path).unwrap();
+
+        let client = Arc::new(FakeClient::default());
+        tokio::spawn(publish(client.clone(), PowerCurve::for_model(Some("color")), state_file.subscribe()));
+
+        state_file.update(|state| state.power = Some("on".into()));
+        state_file.update(|state| state.brightness = Some(100));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(client.take_published(), [("smart-home-system/yeelight/power_consumption".to_string(), "9.0".to_string())]);
+
+        state_file.update(|state| state.power = Some("off".into()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(client.take_published(), [("smart-home-system/yeelight/power_consumption".to_string(), "0.4".to_string())]);
+    }
+}
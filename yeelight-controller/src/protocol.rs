@@ -0,0 +1,391 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use domain_state::Power;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+#[derive(Serialize, Debug)]
+pub struct Command {
+    pub id: u64,
+    #[serde(flatten)]
+    pub method: Method,
+}
+
+impl Command {
+    pub const fn new(id: u64, method: Method) -> Self {
+        Self { id, method }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Method {
+    GetProp { params: Vec<String> },
+    SetBright { params: (u8, ) },
+    #[serde(rename = "set_bright")]
+    SetBrightSmooth { params: (u8, &'static str, u64) },
+    SetPower { params: (Power, ) },
+    SetScene { params: (&'static str, u16, u8) },
+    Toggle { params: [(); 0] },
+}
+
+impl Method {
+    pub const fn get_prop(params: Vec<String>) -> Method {
+        Method::GetProp { params }
+    }
+
+    pub const fn set_brightness(brightness: u8) -> Method {
+        Method::SetBright { params: (brightness, ) }
+    }
+
+    /// Changes the brightness gradually over `duration`.
+    pub fn set_brightness_smooth(brightness: u8, duration: Duration) -> Method {
+        Method::SetBrightSmooth { params: (brightness, "smooth", duration.as_millis() as u64) }
+    }
+
+    pub const fn set_power(power: Power) -> Method {
+        Method::SetPower { params: (power, ) }
+    }
+
+    /// Turns the device on with the color temperature, in kelvin, and the brightness at once.
+    pub const fn set_scene_color_temperature(color_temperature: u16, brightness: u8) -> Method {
+        Method::SetScene { params: ("ct", color_temperature, brightness) }
+    }
+
+    pub const TOGGLE: Method = Method::Toggle { params: [] };
+
+    /// The name of the method in the yeelight protocol.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Method::GetProp { .. } => "get_prop",
+            Method::SetBright { .. } | Method::SetBrightSmooth { .. } => "set_bright",
+            Method::SetPower { .. } => "set_power",
+            Method::SetScene { .. } => "set_scene",
+            Method::Toggle { .. } => "toggle",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum YeelightMessage {
+    Response(Response),
+    Notification(Notification),
+}
+
+/// The fields of every message, parsed in one pass. `#[serde(untagged)]` would buffer the whole message before
+/// trying each variant, which shows at the rate a bulb in music mode notifies.
+#[derive(Deserialize)]
+struct RawMessage<'a> {
+    id: Option<u64>,
+    result: Option<Vec<String>>,
+    error: Option<RawError>,
+    #[serde(borrow)]
+    method: Option<Cow<'a, str>>,
+    params: Option<HashMap<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct RawError {
+    code: i64,
+    message: String,
+}
+
+impl<'de> Deserialize<'de> for YeelightMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match RawMessage::deserialize(deserializer)? {
+            RawMessage { id: Some(id), result: Some(result), error: None, .. } => {
+                Ok(Self::Response(Response { id, result: ResponseResult::Success(result) }))
+            }
+            RawMessage { id: Some(id), result: None, error: Some(RawError { code, message }), .. } => {
+                Ok(Self::Response(Response { id, result: ResponseResult::Error { code, message } }))
+            }
+            RawMessage { method: Some(method), params: Some(params), .. } => {
+                Ok(Self::Notification(Notification { method: method.into_owned(), params }))
+            }
+            _ => Err(D::Error::custom("expected a response with an id and a result or an error, or a notification with a method and params")),
+        }
+    }
+}
+
+impl YeelightMessage {
+    /// Parses a line sent by the device, which can be anything another host on the LAN sends.
+    pub fn parse(line: &[u8]) -> serde_json::Result<Self> {
+        serde_json::from_slice(line)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Response {
+    pub id: u64,
+    #[serde(flatten)]
+    pub result: ResponseResult,
+}
+
+#[derive(Deserialize, PartialEq, Debug, Clone)]
+pub enum ResponseResult {
+    #[serde(rename = "result")]
+    Success(Vec<String>),
+
+    #[serde(rename = "error")]
+    Error { code: i64, message: String },
+}
+
+impl FromStr for Response {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Notification {
+    pub method: String,
+    pub params: HashMap<String, Value>,
+}
+
+impl FromStr for Notification {
+    type Err = serde_json::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_str(s)
+    }
+}
+
+/// The power in a notification or a `get_prop` result, if it's valid.
+pub fn parse_power(value: &Value) -> Option<Power> {
+    value.as_str().and_then(|power| Power::from_str(power).ok())
+}
+
+/// The brightness in a notification, a number, or in a `get_prop` result, a string, if it's between 1 and 100.
+pub fn parse_brightness(value: &Value) -> Option<u8> {
+    let brightness = match value {
+        Value::Number(number) => number.as_u64()?,
+        Value::String(string) => string.parse().ok()?,
+        _ => return None,
+    };
+    (1..=100).contains(&brightness).then_some(brightness as u8)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::fmt::Display;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use domain_state::Power;
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+
+    use crate::protocol::{parse_brightness, parse_power, Command, Method, Notification, Response, ResponseResult, YeelightMessage};
+
+    impl Display for Command {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", serde_json::to_string(self).unwrap())
+        }
+    }
+
+    /// The number of variants of [`Method`], checked by the exhaustive match of [`variant`].
+    const METHOD_VARIANTS: usize = 6;
+
+    fn variant(method: &Method) -> usize {
+        match method {
+            Method::GetProp { .. } => 0,
+            Method::SetBright { .. } => 1,
+            Method::SetBrightSmooth { .. } => 2,
+            Method::SetPower { .. } => 3,
+            Method::SetScene { .. } => 4,
+            Method::Toggle { .. } => 5,
+        }
+    }
+
+    /// A command of every variant of [`Method`], in the order of the golden file.
+    fn example_methods() -> Vec<Method> {
+        vec![
+            Method::get_prop(vec!["power".into(), "bright".into()]),
+            Method::set_brightness(50),
+            Method::set_brightness_smooth(50, Duration::from_secs(2)),
+            Method::set_power(Power::On),
+            Method::set_power(Power::Off),
+            Method::set_scene_color_temperature(2700, 40),
+            Method::TOGGLE,
+        ]
+    }
+
+    fn method() -> impl Strategy<Value = Method> {
+        prop_oneof![
+            prop::collection::vec("[a-z_]{1,12}", 0..6).prop_map(Method::get_prop),
+            any::<u8>().prop_map(Method::set_brightness),
+            (any::<u8>(), 0..60_000u64).prop_map(|(brightness, ms)| Method::set_brightness_smooth(brightness, Duration::from_millis(ms))),
+            prop_oneof![Just(Power::On), Just(Power::Off)].prop_map(Method::set_power),
+            (any::<u16>(), any::<u8>()).prop_map(|(color_temperature, brightness)| Method::set_scene_color_temperature(color_temperature, brightness)),
+            Just(Method::TOGGLE),
+        ]
+    }
+
+    /// The method of a command as the bulb reads it, the inverse of its serialization.
+    fn decode(command: &Value) -> Option<(u64, Method)> {
+        let id = command["id"].as_u64()?;
+        let params = command["params"].as_array()?;
+        let brightness = || params.first()?.as_u64().and_then(|brightness| u8::try_from(brightness).ok());
+
+        let method = match (command["method"].as_str()?, params.len()) {
+            ("get_prop", _) => Method::get_prop(params.iter().map(|param| param.as_str().map(String::from)).collect::<Option<_>>()?),
+            ("set_bright", 1) => Method::set_brightness(brightness()?),
+            ("set_bright", 3) if params[1] == "smooth" => Method::set_brightness_smooth(brightness()?, Duration::from_millis(params[2].as_u64()?)),
+            ("set_power", 1) => Method::set_power(Power::from_str(params[0].as_str()?).ok()?),
+            ("set_scene", 3) if params[0] == "ct" => Method::set_scene_color_temperature(
+                u16::try_from(params[1].as_u64()?).ok()?,
+                u8::try_from(params[2].as_u64()?).ok()?,
+            ),
+            ("toggle", 0) => Method::TOGGLE,
+            _ => return None,
+        };
+
+        Some((id, method))
+    }
+
+    fn golden_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("yeelight").join(name)
+    }
+
+    /// Compares the output with the golden file, or writes it when env `UPDATE_GOLDEN` is set.
+    pub(crate) fn assert_golden(path: &Path, output: &str) {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(path, output).unwrap();
+            return;
+        }
+
+        let golden = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {:?}: {}", path, e));
+        assert_eq!(output, golden, "The output differs from {:?}, run with UPDATE_GOLDEN=1 if the change is intended", path);
+    }
+
+    /// A description of a message that doesn't depend on the order of the notification params.
+    pub(crate) fn describe(message: YeelightMessage) -> String {
+        match message {
+            YeelightMessage::Response(Response { id, result: ResponseResult::Success(result) }) => format!("response {} {:?}", id, result),
+            YeelightMessage::Response(Response { id, result: ResponseResult::Error { code, message } }) => format!("error {} {} {:?}", id, code, message),
+            YeelightMessage::Notification(notification) => {
+                let params: BTreeMap<_, _> = notification.params.into_iter().collect();
+                format!("notification {} {}", notification.method, serde_json::to_string(&params).unwrap())
+            }
+        }
+    }
+
+    #[test]
+    fn test_commands_golden() {
+        let methods = example_methods();
+        let variants: BTreeSet<usize> = methods.iter().map(variant).collect();
+        assert_eq!(variants.len(), METHOD_VARIANTS, "Every method should have an example");
+
+        let output: String = methods.into_iter()
+            .enumerate()
+            .map(|(id, method)| format!("{}\n", Command::new(id as u64 + 1, method)))
+            .collect();
+        assert_golden(&golden_path("commands.golden"), &output);
+    }
+
+    /// The messages in `messages.jsonl` were sent by real bulbs.
+    #[test]
+    fn test_messages_golden() {
+        let corpus = std::fs::read_to_string(golden_path("messages.jsonl")).unwrap();
+
+        let output: String = corpus.lines()
+            .map(|line| match serde_json::from_str::<YeelightMessage>(line) {
+                Ok(message) => format!("{}\n", describe(message)),
+                Err(e) => format!("invalid {}\n", e),
+            })
+            .collect();
+        assert_golden(&golden_path("messages.golden"), &output);
+    }
+
+    proptest! {
+        #[test]
+        fn test_command_round_trip(id in any::<u64>(), method in method()) {
+            let command = serde_json::to_value(Command::new(id, method.clone())).unwrap();
+            prop_assert_eq!(command["method"].as_str(), Some(method.name()));
+            prop_assert_eq!(decode(&command), Some((id, method)));
+        }
+
+        #[test]
+        fn test_response_round_trip(id in any::<u64>(), result in prop::collection::vec(".*", 0..4)) {
+            let response = Response::from_str(&json!({ "id": id, "result": result }).to_string()).unwrap();
+            prop_assert_eq!(response.id, id);
+            prop_assert_eq!(response.result, ResponseResult::Success(result));
+        }
+
+        #[test]
+        fn test_error_round_trip(id in any::<u64>(), code in any::<i64>(), message in ".*") {
+            let response = Response::from_str(&json!({ "id": id, "error": { "code": code, "message": message } }).to_string()).unwrap();
+            prop_assert_eq!(response.result, ResponseResult::Error { code, message });
+        }
+
+        #[test]
+        fn test_notification_round_trip(params in prop::collection::hash_map("[a-z_]{1,12}", prop_oneof![any::<u8>().prop_map(Value::from), "[a-z]*".prop_map(Value::from)], 0..6)) {
+            let notification = json!({ "method": "props", "params": params });
+            let notification = Notification::from_str(&notification.to_string()).unwrap();
+            prop_assert_eq!(notification.method, "props");
+            prop_assert_eq!(notification.params, params);
+        }
+    }
+
+    #[test]
+    fn test_response_from_json() {
+        let ok_response = Response::from_str("{\"id\":1,\"result\":[\"on\"]}").unwrap();
+        assert_eq!(ok_response.id, 1);
+        assert_eq!(ok_response.result, ResponseResult::Success(vec!("on".to_string())));
+
+        let error_response = "{\"id\":2, \"error\":{\"code\":-1, \"message\":\"unsupported method\"}}";
+        let error_response = Response::from_str(error_response).unwrap();
+
+        assert_eq!(error_response.id, 2);
+        dbg!(error_response.result);
+    }
+
+    #[test]
+    fn test_notification_from_json() {
+        let notification = "{\"method\":\"props\",\"params\":{\"power\":\"on\", \"bright\": \"10\"}}";
+        let notification: Notification = serde_json::from_str(notification).unwrap();
+
+        assert_eq!(notification.method, "props");
+        assert_eq!(notification.params.get("power").unwrap(), "on");
+        assert_eq!(notification.params.get("bright").unwrap(), "10");
+    }
+
+    #[test]
+    fn test_parse_message() {
+        // The method is only copied out of the line when it has escapes
+        let message = YeelightMessage::parse(br#"{"method":"pr\u006fps","params":{"bright":10}}"#).unwrap();
+        assert_eq!(describe(message), r#"notification props {"bright":10}"#);
+
+        let message = YeelightMessage::parse(br#"{"id":1,"result":["ok"],"method":"props","params":{}}"#).unwrap();
+        assert_eq!(describe(message), r#"response 1 ["ok"]"#);
+
+        assert!(YeelightMessage::parse(br#"{"id":1,"result":["ok"],"error":{"code":-1,"message":"general error"}}"#).is_err());
+        assert!(YeelightMessage::parse(br#"{"id":1}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_found_by_fuzzing() {
+        assert!(YeelightMessage::parse(b"{\"id\":1,\"result\":[\"\xff\"]}").is_err());
+        assert!(YeelightMessage::parse(&[b'['; 4096]).is_err());
+
+        assert_eq!(parse_power(&json!("OFF")), Some(Power::Off));
+        assert_eq!(parse_power(&json!(1)), None);
+        assert_eq!(parse_power(&json!(null)), None);
+
+        assert_eq!(parse_brightness(&json!(30)), Some(30));
+        assert_eq!(parse_brightness(&json!("100")), Some(100));
+        assert_eq!(parse_brightness(&json!(300)), None);
+        assert_eq!(parse_brightness(&json!(0)), None);
+        assert_eq!(parse_brightness(&json!(-1)), None);
+        assert_eq!(parse_brightness(&json!("bright")), None);
+        assert_eq!(parse_brightness(&json!([30])), None);
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::protocol::YeelightMessage;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A line exchanged with the device, one per line of the recording.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Entry {
+    /// Milliseconds since the unix epoch.
+    pub time_ms: u64,
+    pub direction: Direction,
+    /// The line with its line break, if it's valid utf-8.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// The bytes of the line otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<Vec<u8>>,
+}
+
+impl Entry {
+    pub fn new(direction: Direction, line: &[u8]) -> Self {
+        let time_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        match std::str::from_utf8(line) {
+            Ok(data) => Self { time_ms, direction, data: Some(data.to_string()), bytes: None },
+            Err(_) => Self { time_ms, direction, data: None, bytes: Some(line.to_vec()) },
+        }
+    }
+
+    pub fn line(&self) -> &[u8] {
+        match (&self.data, &self.bytes) {
+            (Some(data), _) => data.as_bytes(),
+            (None, Some(bytes)) => bytes,
+            (None, None) => &[],
+        }
+    }
+}
+
+/// Appends every line exchanged with the device to a file, to reproduce the quirks of its firmware.
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+static RECORDER: OnceLock<Recorder> = OnceLock::new();
+
+/// Records the traffic of the devices connected from now on to the file, appending to it if it exists.
+pub fn enable(path: &Path) -> anyhow::Result<()> {
+    let file = File::options().create(true).append(true).open(path)
+        .context(format!("Failed to open the recording {:?}", path))?;
+    let _ = RECORDER.set(Recorder { file: Mutex::new(file) });
+    Ok(())
+}
+
+pub fn get() -> Option<&'static Recorder> {
+    RECORDER.get()
+}
+
+impl Recorder {
+    pub fn record(&self, direction: Direction, line: &[u8]) {
+        let mut entry = serde_json::to_vec(&Entry::new(direction, line)).expect("The entry is serializable");
+        entry.push(b'\n');
+
+        if let Err(e) = self.file.lock().unwrap().write_all(&entry) {
+            warn!("Failed to write to the recording: {}", e);
+        }
+    }
+}
+
+/// Reads the entries of a recording.
+pub fn read(path: &Path) -> anyhow::Result<Vec<Entry>> {
+    let file = File::open(path).context(format!("Failed to open the recording {:?}", path))?;
+    BufReader::new(file).lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(number, line)| {
+            serde_json::from_str(&line?).context(format!("Invalid entry at line {} of {:?}", number + 1, path))
+        })
+        .collect()
+}
+
+/// Feeds the lines received in the recording to the parser, as the connection to the device does.
+pub fn replay(entries: &[Entry]) -> impl Iterator<Item = (&Entry, serde_json::Result<YeelightMessage>)> {
+    entries.iter()
+        .filter(|entry| entry.direction == Direction::Received)
+        .map(|entry| (entry, YeelightMessage::parse(entry.line())))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use crate::protocol::tests::{assert_golden, describe};
+    use crate::recording::{read, replay, Direction, Entry, Recorder};
+
+    #[test]
+    fn test_record_and_read() {
+        let path = std::env::temp_dir().join(format!("yeelight-recording-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = Recorder { file: Mutex::new(std::fs::File::create(&path).unwrap()) };
+        recorder.record(Direction::Sent, b"{\"id\":1,\"method\":\"toggle\",\"params\":[]}\r\n");
+        recorder.record(Direction::Received, b"{\"id\":1,\"result\":[\"\xff\"]}\r\n");
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, Direction::Sent);
+        assert_eq!(entries[0].data.as_deref(), Some("{\"id\":1,\"method\":\"toggle\",\"params\":[]}\r\n"));
+        assert_eq!(entries[1].line(), b"{\"id\":1,\"result\":[\"\xff\"]}\r\n");
+
+        let replayed: Vec<_> = replay(&entries).collect();
+        assert_eq!(replayed.len(), 1);
+        assert!(replayed[0].1.is_err());
+    }
+
+    /// Replays every recording of `testdata/recordings`, e.g. one reported for a firmware, against its golden
+    /// file. A recording is added with `yeelight-cli --record` and its golden file with `UPDATE_GOLDEN=1`.
+    #[test]
+    fn test_recordings_golden() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("recordings");
+        let mut recordings: Vec<_> = std::fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "jsonl"))
+            .collect();
+        recordings.sort();
+        assert!(!recordings.is_empty(), "No recordings in {:?}", directory);
+
+        for recording in recordings {
+            let entries: Vec<Entry> = read(&recording).unwrap();
+            let output: String = replay(&entries)
+                .map(|(_, message)| match message {
+                    Ok(message) => describe(message) + "\n",
+                    Err(e) => format!("invalid {}\n", e),
+                })
+                .collect();
+            assert_golden(&recording.with_extension("golden"), &output);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::OnceLock;
+
+#[cfg(not(feature = "remap-regex"))]
+use anyhow::bail;
+#[cfg(feature = "remap-regex")]
+use anyhow::Context;
+use dashmap::DashMap;
+#[cfg(feature = "remap-regex")]
+use regex::Regex;
+use serde::Deserialize;
+
+/// A rule of the topic remapping table, rewriting a topic of the controller to the topic used on the broker.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum RemapRule {
+    /// Replaces the start of the topics, e.g. `smart-home-system/yeelight/power` to `stat/bedroom-light/power`.
+    Prefix { prefix: String, to: String },
+    /// Replaces the topics matching the regex, with `$1` for its first group, e.g.
+    /// `^smart-home-system/yeelight/(.+)/set$` to `cmnd/bedroom-light/$1`. Needs the remap-regex feature.
+    Regex { regex: String, to: String },
+}
+
+enum Rule {
+    Prefix { prefix: String, to: String },
+    #[cfg(feature = "remap-regex")]
+    Regex { regex: Regex, to: String },
+}
+
+impl Rule {
+    fn new(rule: &RemapRule) -> anyhow::Result<Self> {
+        match rule {
+            RemapRule::Prefix { prefix, to } => Ok(Rule::Prefix { prefix: prefix.clone(), to: to.clone() }),
+            #[cfg(feature = "remap-regex")]
+            RemapRule::Regex { regex, to } => {
+                let regex = Regex::new(regex).with_context(|| format!("{:?} is an invalid regex", regex))?;
+                Ok(Rule::Regex { regex, to: to.clone() })
+            }
+            #[cfg(not(feature = "remap-regex"))]
+            RemapRule::Regex { regex, .. } => bail!("{:?} needs the controller to be built with the remap-regex feature", regex),
+        }
+    }
+
+    fn apply(&self, topic: &str) -> Option<String> {
+        match self {
+            Rule::Prefix { prefix, to } => match topic.strip_prefix(prefix.as_str()) {
+                Some(rest) if rest.is_empty() || rest.starts_with('/') => Some(format!("{}{}", to, rest)),
+                _ => None,
+            },
+            #[cfg(feature = "remap-regex")]
+            Rule::Regex { regex, to } => regex.is_match(topic).then(|| regex.replace(topic, to.as_str()).into_owned()),
+        }
+    }
+}
+
+/// Rewrites the topics of the controller with the first rule that matches them. The messages received are
+/// rewritten back with the topics that were subscribed to, as the regex rules can't be inverted.
+pub struct Remap {
+    rules: Vec<Rule>,
+    /// The topic of the controller of every topic subscribed to on the broker.
+    subscribed: DashMap<String, String>,
+}
+
+static REMAP: OnceLock<Remap> = OnceLock::new();
+
+/// Rewrites the topics published and subscribed to with the rules.
+pub fn enable(rules: &[RemapRule]) -> anyhow::Result<()> {
+    if !rules.is_empty() {
+        let _ = REMAP.set(Remap::new(rules)?);
+    }
+    Ok(())
+}
+
+pub fn get() -> Option<&'static Remap> {
+    REMAP.get()
+}
+
+impl Remap {
+    pub fn new(rules: &[RemapRule]) -> anyhow::Result<Self> {
+        let rules = rules.iter().map(Rule::new).collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { rules, subscribed: DashMap::new() })
+    }
+
+    /// The topic on the broker of a topic of the controller, if a rule matches it.
+    pub fn outbound(&self, topic: &str) -> Option<String> {
+        self.rules.iter().find_map(|rule| rule.apply(topic))
+    }
+
+    /// The topic to subscribe to on the broker, if a rule matches it, remembered to rewrite the messages
+    /// received on it.
+    pub fn subscribe(&self, topic: &str) -> Option<String> {
+        let broker_topic = self.outbound(topic)?;
+        self.subscribed.insert(broker_topic.clone(), topic.to_string());
+        Some(broker_topic)
+    }
+
+    /// The topic of the controller of a topic received from the broker, if it was remapped.
+    pub fn inbound(&self, topic: &str) -> Option<String> {
+        self.subscribed.get(topic).map(|entry| entry.value().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remap::{Remap, RemapRule};
+
+    #[test]
+    #[cfg(feature = "remap-regex")]
+    fn test_remap() {
+        let rules: Vec<RemapRule> = serde_yaml::from_str(r"
+- prefix: smart-home-system/yeelight/power
+  to: stat/bedroom-light/POWER
+- regex: ^smart-home-system/yeelight/(.+)/set$
+  to: cmnd/bedroom-light/$1
+").unwrap();
+        let remap = Remap::new(&rules).unwrap();
+
+        assert_eq!(remap.outbound("smart-home-system/yeelight/power").as_deref(), Some("stat/bedroom-light/POWER"));
+        assert_eq!(remap.outbound("smart-home-system/yeelight/powerful"), None);
+        assert_eq!(remap.outbound("smart-home-system/yeelight/brightness/set").as_deref(), Some("cmnd/bedroom-light/brightness"));
+        assert_eq!(remap.outbound("smart-home-system/yeelight/heartbeat"), None);
+
+        // The first rule that matches is used
+        assert_eq!(remap.subscribe("smart-home-system/yeelight/power/set").as_deref(), Some("stat/bedroom-light/POWER/set"));
+        assert_eq!(remap.subscribe("smart-home-system/yeelight/toggle"), None);
+        assert_eq!(remap.inbound("stat/bedroom-light/POWER/set").as_deref(), Some("smart-home-system/yeelight/power/set"));
+        assert_eq!(remap.inbound("cmnd/bedroom-light/brightness"), None);
+
+        assert!(serde_yaml::from_str::<Vec<RemapRule>>("- regex: (\n  to: a\n").map(|rules| Remap::new(&rules).is_err()).unwrap());
+        assert!(serde_yaml::from_str::<Vec<RemapRule>>("- prefix: a\n  regex: b\n  to: c\n").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "remap-regex"))]
+    fn test_regex_needs_feature() {
+        let rules: Vec<RemapRule> = serde_yaml::from_str("- regex: ^smart-home-system/yeelight/(.+)$\n  to: cmnd/bedroom-light/$1\n").unwrap();
+        let error = Remap::new(&rules).err().unwrap();
+        assert!(error.to_string().contains("remap-regex"), "{}", error);
+    }
+}
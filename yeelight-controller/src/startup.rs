@@ -0,0 +1,76 @@
+use std::fmt::{Display, Formatter};
+use std::process::ExitCode;
+
+/// Why the controller stopped, telling the orchestrator running it whether restarting it can help.
+#[derive(Debug)]
+pub enum StartupError {
+    /// The config file or the env vars are invalid, the controller fails the same way until they're fixed.
+    Config(anyhow::Error),
+    /// A file the controller keeps its state in couldn't be read or written.
+    Storage(anyhow::Error),
+    /// A service the controller depends on, like the broker, couldn't be reached. It may be back on a restart.
+    Unavailable(anyhow::Error),
+    /// Anything else, e.g. a command like `--discover-only` that failed.
+    Other(anyhow::Error),
+}
+
+impl StartupError {
+    /// Whether restarting the controller without changing anything may get it running.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Unavailable(_))
+    }
+
+    /// The exit codes of sysexits.h, e.g. for `RestartPreventExitStatus=78 74` in a systemd unit.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Self::Config(_) => 78,
+            Self::Storage(_) => 74,
+            Self::Unavailable(_) => 75,
+            Self::Other(_) => 1,
+        })
+    }
+}
+
+impl Display for StartupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Config(e) => write!(f, "{:#}. Run with --check for a report of every setting.", e),
+            Self::Storage(e) | Self::Unavailable(e) | Self::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for StartupError {}
+
+/// Logs why the controller stopped and returns its exit code, printing to stderr if the logs aren't set up yet.
+pub fn exit(error: StartupError) -> ExitCode {
+    if tracing::dispatcher::has_been_set() {
+        tracing::error!(retryable = error.is_retryable(), "{}", error);
+    } else {
+        eprintln!("{}", error);
+    }
+    error.exit_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use crate::startup::StartupError;
+
+    #[test]
+    fn test_only_unavailable_is_retryable() {
+        assert!(StartupError::Unavailable(anyhow!("Failed to connect to mqtt server")).is_retryable());
+        assert!(!StartupError::Config(anyhow!("Invalid config")).is_retryable());
+        assert!(!StartupError::Storage(anyhow!("Failed to read the last state")).is_retryable());
+    }
+
+    #[test]
+    fn test_config_error_points_to_check() {
+        let error = StartupError::Config(anyhow!("expected a number").context("Invalid YEELIGHT_HTTP_PORT"));
+        assert_eq!(
+            error.to_string(),
+            "Invalid YEELIGHT_HTTP_PORT: expected a number. Run with --check for a report of every setting.",
+        );
+    }
+}
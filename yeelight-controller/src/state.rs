@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tracing::warn;
+
+/// The last power and brightness published for the device, and its color temperature.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct LastState {
+    pub power: Option<String>,
+    pub brightness: Option<u8>,
+    /// In kelvin, kept by the scenes the device is turned on with.
+    pub color_temperature: Option<u16>,
+}
+
+/// Keeps the last state in a file, so it can be published on startup before the device is reachable.
+#[derive(Clone)]
+pub struct StateFile {
+    path: PathBuf,
+    state: Arc<Mutex<LastState>>,
+    changes: Arc<watch::Sender<LastState>>,
+}
+
+impl StateFile {
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let state = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .context(format!("Failed to read the last state from {:?}", path))?;
+
+            serde_json::from_str(&content)
+                .context(format!("Invalid last state in {:?}", path))?
+        } else {
+            LastState::default()
+        };
+
+        let (changes, _) = watch::channel(state.clone());
+        Ok(Self { path, state: Arc::new(Mutex::new(state)), changes: Arc::new(changes) })
+    }
+
+    pub fn state(&self) -> LastState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Receives the state whenever it changes.
+    pub fn subscribe(&self) -> watch::Receiver<LastState> {
+        self.changes.subscribe()
+    }
+
+    /// Changes the state, writing the file and notifying the subscribers if it changed.
+    pub fn update(&self, update: impl FnOnce(&mut LastState)) {
+        let mut state = self.state.lock().unwrap();
+        let previous = state.clone();
+        update(&mut state);
+
+        if *state == previous {
+            return;
+        }
+
+        self.changes.send_replace(state.clone());
+
+        let result = serde_json::to_string_pretty(&*state)
+            .context("Failed to serialize the last state")
+            .and_then(|content| std::fs::write(&self.path, content).context(format!("Failed to write the last state to {:?}", self.path)));
+
+        if let Err(e) = result {
+            warn!("{:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{LastState, StateFile};
+
+    #[test]
+    fn test_state_file() {
+        let path = std::env::temp_dir().join(format!("yeelight-state-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let file = StateFile::load(path.clone()).unwrap();
+        assert_eq!(file.state(), LastState::default());
+
+        file.update(|state| state.power = Some("on".into()));
+        file.update(|state| state.brightness = Some(40));
+
+        let file = StateFile::load(path.clone()).unwrap();
+        assert_eq!(file.state(), LastState { power: Some("on".into()), brightness: Some(40), color_temperature: None });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
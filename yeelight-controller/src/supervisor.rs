@@ -0,0 +1,104 @@
+use std::any::Any;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// How long to wait before restarting a task that panicked, doubled after every panic in a row.
+const MIN_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long a task has to run before a panic is no longer counted as one in a row.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Aborts the task when dropped, so aborting the supervisor stops the task it runs.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message,
+        _ => "unknown cause",
+    }
+}
+
+/// Runs the task started by `start`, starting it again with backoff whenever it panics, so a bug hit by one
+/// message doesn't leave the process running without it. A task that returns is done, e.g. its channel was
+/// closed, and isn't restarted. Aborting the handle stops the task.
+pub fn supervise<F, Fut>(name: &'static str, mut start: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let mut task = AbortOnDrop(tokio::spawn(start()));
+
+            let panic = match (&mut task.0).await {
+                Err(e) if e.is_panic() => e.into_panic(),
+                _ => return,
+            };
+
+            if started.elapsed() >= STABLE_AFTER {
+                backoff = MIN_BACKOFF;
+            }
+            error!("The {} task panicked: {}. Restarting it in {:?}...", name, panic_message(panic.as_ref()), backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::supervisor::supervise;
+
+    #[tokio::test]
+    async fn test_restarts_after_panic() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let r = runs.clone();
+
+        let handle = supervise("test", move || {
+            let run = r.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if run < 2 {
+                    panic!("run {}", run);
+                }
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_abort_stops_the_task() {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<()>(1);
+
+        let handle = supervise("test", move || {
+            let sender = sender.clone();
+            async move {
+                let _sender = sender;
+                std::future::pending::<()>().await;
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.abort();
+
+        // The channel is closed once the task holding the last sender is dropped
+        tokio::time::timeout(Duration::from_secs(1), receiver.recv()).await.unwrap();
+    }
+}
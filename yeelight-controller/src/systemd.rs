@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use sd_notify::NotifyState;
+use tracing::warn;
+
+use crate::health::Health;
+
+/// Tells systemd the service started, for units with `Type=notify`. Does nothing when not run by systemd.
+pub fn notify_ready() {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+/// Pings the systemd watchdog at half its timeout while the service is healthy, so systemd restarts it
+/// when it hangs or loses its connections. Returns right away if `WatchdogSec` isn't set.
+pub async fn run_watchdog(health: Health) {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_micros(usec) / 2);
+    loop {
+        interval.tick().await;
+
+        if !health.is_healthy() {
+            warn!("Not pinging the systemd watchdog while unhealthy: {}", health.report());
+            continue;
+        }
+
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+            warn!("Failed to ping the systemd watchdog: {}", e);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::callsite::Identifier;
+use tracing::field::{Field, Visit};
+use tracing::{warn, Event, Level, Metadata};
+use tracing_subscriber::layer::{Context, Filter};
+
+/// How long repeats of a warning or error are dropped after it's logged, unless changed by the config.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// How often the dropped repeats are reported.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+const TARGET: &str = "log_throttle";
+
+struct Repeats {
+    since: Instant,
+    count: usize,
+}
+
+/// Drops the warnings and errors that repeat one logged less than a window ago, so a flapping device
+/// doesn't flood the log. The repeats are counted and reported by [`Throttle::flush`].
+#[derive(Clone)]
+pub struct Throttle {
+    messages: Arc<Mutex<HashMap<(Identifier, String), Repeats>>>,
+    /// In milliseconds, so it can be changed when the config is reloaded.
+    window: Arc<AtomicU64>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self { messages: Arc::default(), window: Arc::new(AtomicU64::new(DEFAULT_WINDOW.as_millis() as u64)) }
+    }
+}
+
+impl Throttle {
+    pub fn set_window(&self, window: Duration) {
+        self.window.store(window.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_millis(self.window.load(Ordering::Relaxed))
+    }
+
+    /// Logs how many times the messages whose window ended were repeated.
+    pub fn flush(&self, now: Instant) {
+        let mut repeated = vec![];
+        let window = self.window();
+
+        self.messages.lock().unwrap().retain(|(_, message), repeats| {
+            if now.duration_since(repeats.since) < window {
+                return true;
+            }
+            if repeats.count > 0 {
+                repeated.push((message.clone(), repeats.count));
+            }
+            false
+        });
+
+        for (message, count) in repeated {
+            warn!(target: TARGET, "{} (message repeated {} times)", message, count);
+        }
+    }
+
+    /// Reports the dropped repeats every [`FLUSH_INTERVAL`].
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.flush(Instant::now());
+        }
+    }
+}
+
+/// Writes the message and the other fields of an event, e.g. `Request failed device="desk"`.
+struct FieldsVisitor(String);
+
+impl Visit for FieldsVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl<S> Filter<S> for Throttle {
+    fn enabled(&self, _metadata: &Metadata<'_>, _context: &Context<'_, S>) -> bool {
+        true
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, _context: &Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::WARN || metadata.target() == TARGET {
+            return true;
+        }
+
+        let mut visitor = FieldsVisitor(String::new());
+        event.record(&mut visitor);
+
+        let window = self.window();
+        let mut messages = self.messages.lock().unwrap();
+        match messages.get_mut(&(metadata.callsite(), visitor.0.clone())) {
+            Some(repeats) if repeats.since.elapsed() < window => {
+                repeats.count += 1;
+                false
+            }
+            _ => {
+                messages.insert((metadata.callsite(), visitor.0), Repeats { since: Instant::now(), count: 0 });
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use tracing::{error, info};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    use crate::throttle::{Throttle, DEFAULT_WINDOW};
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_throttle() {
+        let output = Output::default();
+        let throttle = Throttle::default();
+
+        let writer = output.clone();
+        let layer = tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .without_time()
+            .with_writer(move || writer.clone())
+            .with_filter(throttle.clone());
+
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            for _ in 0..5 {
+                error!("Failed to read from yeelight device: {}", "timed out");
+                info!("Reconnecting");
+            }
+            error!("Failed to read from yeelight device: {}", "connection reset");
+
+            throttle.flush(Instant::now());
+            throttle.flush(Instant::now() + DEFAULT_WINDOW);
+        });
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().map(str::trim).collect();
+
+        assert_eq!(lines.iter().filter(|line| line.contains("timed out")).count(), 2, "{}", output);
+        assert_eq!(lines.iter().filter(|line| line.contains("Reconnecting")).count(), 5);
+        assert!(lines.contains(&"ERROR yeelight_controller::throttle::tests: Failed to read from yeelight device: connection reset"));
+        assert_eq!(lines.last(), Some(&"WARN log_throttle: Failed to read from yeelight device: timed out (message repeated 4 times)"));
+    }
+}
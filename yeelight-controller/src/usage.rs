@@ -0,0 +1,290 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use domain_state::Power;
+use mqtt_client::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::mqtt::{self, Client};
+use crate::state::LastState;
+use crate::topic;
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How long the device was on and how many times it was turned on.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+pub struct Counters {
+    pub on_secs: u64,
+    pub switch_cycles: u64,
+}
+
+/// The counters of the current day and the ones since the counter topic was last reset. The days are in UTC.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+struct Usage {
+    /// The day of `today`, in days since the unix epoch.
+    day: u64,
+    today: Counters,
+    total: Counters,
+    /// When the total was last reset, in seconds since the unix epoch.
+    reset_at: u64,
+    /// Whether the device was last seen on, so a restart while it's on isn't counted as a switch cycle.
+    on: bool,
+    /// When the on-time was last counted while the device is on. Not kept, the time the controller doesn't run
+    /// isn't counted.
+    #[serde(skip)]
+    on_since: Option<u64>,
+}
+
+impl Usage {
+    fn new(now: u64) -> Self {
+        Self { day: now / SECS_PER_DAY, reset_at: now, ..Self::default() }
+    }
+
+    fn count_on_time(&mut self, now: u64) {
+        if let Some(since) = self.on_since {
+            let secs = now.saturating_sub(since);
+            self.today.on_secs += secs;
+            self.total.on_secs += secs;
+            self.on_since = Some(since.max(now));
+        }
+    }
+
+    fn power_changed(&mut self, power: Power, now: u64) {
+        match power {
+            Power::On => {
+                if !self.on {
+                    self.today.switch_cycles += 1;
+                    self.total.switch_cycles += 1;
+                }
+                self.on = true;
+                self.on_since.get_or_insert(now);
+            }
+            Power::Off => {
+                self.count_on_time(now);
+                self.on = false;
+                self.on_since = None;
+            }
+        }
+    }
+
+    /// Starts the day of `now` if it's a new one, returning the day that ended and its counters.
+    fn roll(&mut self, now: u64) -> Option<(u64, Counters)> {
+        let day = now / SECS_PER_DAY;
+        if day <= self.day {
+            return None;
+        }
+
+        self.count_on_time(day * SECS_PER_DAY);
+        let ended = (self.day, std::mem::take(&mut self.today));
+        self.day = day;
+        Some(ended)
+    }
+
+    fn reset(&mut self, now: u64) {
+        self.count_on_time(now);
+        self.total = Counters::default();
+        self.reset_at = now;
+    }
+
+    /// The counters since the last reset, with the on-time up to `now`.
+    fn counter(&self, now: u64) -> Counters {
+        let running = self.on_since.map_or(0, |since| now.saturating_sub(since));
+        Counters { on_secs: self.total.on_secs + running, ..self.total }
+    }
+}
+
+/// Keeps the usage of the device in a file, so the counters survive restarts.
+#[derive(Clone)]
+pub struct UsageFile {
+    path: PathBuf,
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl UsageFile {
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let usage = if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .context(format!("Failed to read the usage from {:?}", path))?;
+
+            serde_json::from_str(&content)
+                .context(format!("Invalid usage in {:?}", path))?
+        } else {
+            Usage::new(now())
+        };
+
+        Ok(Self { path, usage: Arc::new(Mutex::new(usage)) })
+    }
+
+    /// Resets the counters of the counter topic, not the ones of the current day.
+    pub fn reset(&self) {
+        self.update(|usage| usage.reset(now()));
+    }
+
+    /// Changes the usage, writing the file if it changed.
+    fn update<T>(&self, update: impl FnOnce(&mut Usage) -> T) -> T {
+        let mut usage = self.usage.lock().unwrap();
+        let previous = usage.clone();
+        let result = update(&mut usage);
+
+        if *usage != previous {
+            self.write(&usage);
+        }
+        result
+    }
+
+    fn write(&self, usage: &Usage) {
+        let result = serde_json::to_string_pretty(usage)
+            .context("Failed to serialize the usage")
+            .and_then(|content| std::fs::write(&self.path, content).context(format!("Failed to write the usage to {:?}", self.path)));
+
+        if let Err(e) = result {
+            warn!("{:#}", e);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// The day as `2026-10-16`, from the days since the unix epoch.
+fn date(day: u64) -> String {
+    // The civil_from_days algorithm of http://howardhinnant.github.io/date_algorithms.html
+    let days = day + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+/// Publishes the counters since the last reset to `smart-home-system/yeelight/usage_counter`.
+pub fn publish_counter(client: &Client, usage: &UsageFile) {
+    let usage = usage.usage.lock().unwrap();
+    let counter = usage.counter(now());
+    let payload = json!({
+        "on_secs": counter.on_secs,
+        "switch_cycles": counter.switch_cycles,
+        "since": date(usage.reset_at / SECS_PER_DAY),
+    });
+    mqtt::publish(client, Message::new_retained(topic().usage_counter(), payload.to_string(), 1));
+}
+
+/// Counts the on-time and the switch cycles of the device, publishing the counter whenever the power or the
+/// brightness changes, and the summary of each day to `smart-home-system/yeelight/usage` when it ends.
+pub async fn track(client: Client, usage: UsageFile, mut changes: watch::Receiver<LastState>) {
+    loop {
+        let power = changes.borrow_and_update().power.as_deref().and_then(|power| power.parse().ok());
+        let now = now();
+
+        let ended = usage.update(|usage| {
+            let ended = usage.roll(now);
+            if let Some(power) = power {
+                usage.power_changed(power, now);
+            }
+            ended
+        });
+
+        if let Some((day, counters)) = ended {
+            let payload = json!({
+                "date": date(day),
+                "on_secs": counters.on_secs,
+                "switch_cycles": counters.switch_cycles,
+            });
+            mqtt::publish(&client, Message::new_retained(topic().usage(), payload.to_string(), 1));
+        }
+        publish_counter(&client, &usage);
+
+        let next_day = Duration::from_secs(SECS_PER_DAY - now % SECS_PER_DAY);
+        tokio::select! {
+            changed = changes.changed() => if changed.is_err() {
+                return;
+            },
+            _ = tokio::time::sleep(next_day) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use domain_state::Power;
+
+    use crate::usage::{date, Counters, Usage, SECS_PER_DAY};
+
+    /// 2026-10-16 at midnight, in UTC.
+    const MIDNIGHT: u64 = 20_742 * SECS_PER_DAY;
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(MIDNIGHT / SECS_PER_DAY), "2026-10-16");
+        assert_eq!(date(19_782), "2024-02-29");
+        assert_eq!(date(19_783), "2024-03-01");
+    }
+
+    #[test]
+    fn test_counts_on_time_and_switch_cycles() {
+        let mut usage = Usage::new(MIDNIGHT);
+
+        usage.power_changed(Power::On, MIDNIGHT + 10);
+        // Repeated by the notifications of the brightness
+        usage.power_changed(Power::On, MIDNIGHT + 20);
+        assert_eq!(usage.counter(MIDNIGHT + 40), Counters { on_secs: 30, switch_cycles: 1 });
+
+        usage.power_changed(Power::Off, MIDNIGHT + 70);
+        usage.power_changed(Power::Off, MIDNIGHT + 80);
+        usage.power_changed(Power::On, MIDNIGHT + 100);
+        usage.power_changed(Power::Off, MIDNIGHT + 105);
+        assert_eq!(usage.today, Counters { on_secs: 65, switch_cycles: 2 });
+        assert_eq!(usage.counter(MIDNIGHT + 200), usage.today);
+    }
+
+    #[test]
+    fn test_restart_while_on_is_not_a_switch_cycle() {
+        let mut usage = Usage::new(MIDNIGHT);
+        usage.power_changed(Power::On, MIDNIGHT);
+
+        let mut restarted: Usage = serde_json::from_str(&serde_json::to_string(&usage).unwrap()).unwrap();
+        restarted.power_changed(Power::On, MIDNIGHT + 600);
+        restarted.power_changed(Power::Off, MIDNIGHT + 660);
+
+        // Only the time the controller ran is counted
+        assert_eq!(restarted.today, Counters { on_secs: 60, switch_cycles: 1 });
+    }
+
+    #[test]
+    fn test_daily_summary() {
+        let mut usage = Usage::new(MIDNIGHT);
+        usage.power_changed(Power::On, MIDNIGHT + SECS_PER_DAY - 100);
+        assert_eq!(usage.roll(MIDNIGHT + SECS_PER_DAY - 1), None);
+
+        // Left on through the night, the on-time is split at midnight
+        let ended = usage.roll(MIDNIGHT + SECS_PER_DAY + 50);
+        assert_eq!(ended, Some((MIDNIGHT / SECS_PER_DAY, Counters { on_secs: 100, switch_cycles: 1 })));
+
+        usage.power_changed(Power::Off, MIDNIGHT + SECS_PER_DAY + 50);
+        assert_eq!(usage.today, Counters { on_secs: 50, switch_cycles: 0 });
+        assert_eq!(usage.total, Counters { on_secs: 150, switch_cycles: 1 });
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut usage = Usage::new(MIDNIGHT);
+        usage.power_changed(Power::On, MIDNIGHT);
+
+        usage.reset(MIDNIGHT + 30);
+        assert_eq!(usage.counter(MIDNIGHT + 40), Counters { on_secs: 10, switch_cycles: 0 });
+        assert_eq!(usage.reset_at, MIDNIGHT + 30);
+        // The day isn't reset
+        assert_eq!(usage.today, Counters { on_secs: 30, switch_cycles: 1 });
+    }
+}
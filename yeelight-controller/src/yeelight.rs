@@ -1,283 +1,704 @@
-use std::collections::HashMap;
-use std::fmt::Display;
-use std::str::FromStr;
+use std::ops::ControlFlow;
 use std::sync::Arc;
-use std::sync::atomic::AtomicU64;
 use std::time::Duration;
 
-use dashmap::DashMap;
-use log::error;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::tcp::OwnedWriteHalf;
+use anyhow::anyhow;
+use slab::Slab;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, error, info, info_span, Instrument};
 
-#[derive(Serialize)]
-pub struct Command {
+use crate::health::{DeviceState, Health};
+use crate::metrics;
+use crate::protocol::{Command, Method, Notification, Response, YeelightMessage};
+use crate::recording::{self, Direction};
+use crate::supervisor;
+
+/// The longest line accepted from a device, the messages of a bulb are a few hundred bytes.
+const MAX_MESSAGE_LENGTH: u64 = 16 * 1024;
+
+/// How long a device has to answer a command.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The most commands waiting for an answer at once, the commands after them fail until some are answered.
+const MAX_PENDING_REQUESTS: usize = 64;
+
+/// How many commands in a row the device can leave unanswered before the connection is taken as lost. The socket
+/// of a device that lost power or its network looks connected until the OS gives up on it, minutes later.
+const MAX_CONSECUTIVE_TIMEOUTS: usize = 3;
+
+/// A command sent to a device, waiting for its answer.
+struct PendingRequest {
     id: u64,
-    #[serde(flatten)]
-    method: Method,
+    method: &'static str,
+    answer: oneshot::Sender<anyhow::Result<Response>>,
+    sent: Instant,
 }
 
-impl Command {
-    pub const fn new(id: u64, method: Method) -> Self {
-        Self { id, method }
+impl PendingRequest {
+    fn deadline(&self) -> Instant {
+        self.sent + RESPONSE_TIMEOUT
+    }
+
+    fn fail(self, error: anyhow::Error) {
+        metrics::command_failed();
+        // Nothing waits for the answer anymore if the command was cancelled
+        let _ = self.answer.send(Err(error));
     }
 }
 
-#[derive(Serialize)]
-#[serde(tag = "method", rename_all = "snake_case")]
-pub enum Method {
-    GetProp { params: Vec<String> },
-    SetBright { params: (u8, ) },
-    SetPower { params: (Power, ) },
-    Toggle { params: [(); 0] },
+/// The commands sent to a device that weren't answered yet, owned by its connection task. There are few enough of
+/// them that an answer is matched by going through them all.
+#[derive(Default)]
+struct Requests {
+    pending: Slab<PendingRequest>,
+    last_id: u64,
 }
 
-impl Method {
-    pub const fn get_prop(params: Vec<String>) -> Method {
-        Method::GetProp { params }
+impl Requests {
+    /// The id of the next command, wrapping around instead of overflowing and skipping 0 and the ids of the
+    /// commands still waiting for an answer.
+    fn next_id(&mut self) -> u64 {
+        loop {
+            self.last_id = self.last_id.wrapping_add(1);
+            if self.last_id != 0 && !self.pending.iter().any(|(_, pending)| pending.id == self.last_id) {
+                return self.last_id;
+            }
+        }
     }
 
-    pub const fn set_brightness(brightness: u8) -> Method {
-        Method::SetBright { params: (brightness, ) }
+    fn insert(&mut self, pending: PendingRequest) {
+        self.pending.insert(pending);
     }
 
-    pub const fn set_power(power: Power) -> Method {
-        Method::SetPower { params: (power, ) }
+    fn is_full(&self) -> bool {
+        self.pending.len() >= MAX_PENDING_REQUESTS
     }
 
-    pub const TOGGLE: Method = Method::Toggle { params: [] };
-}
+    /// Takes the command with the id out of the ones waiting for an answer.
+    fn take(&mut self, id: u64) -> Option<PendingRequest> {
+        let key = self.pending.iter().find(|(_, pending)| pending.id == id).map(|(key, _)| key)?;
+        Some(self.pending.remove(key))
+    }
 
-#[derive(Serialize, Debug)]
-#[serde(rename_all = "lowercase")]
-pub enum Power {
-    On,
-    Off,
-}
+    /// Removes the commands nothing waits for anymore, e.g. when the task sending them was cancelled.
+    fn evict_cancelled(&mut self) {
+        self.pending.retain(|_, pending| {
+            let waiting = !pending.answer.is_closed();
+            if !waiting {
+                debug!("Evicting command {}, it's no longer waiting for an answer", pending.id);
+            }
+            waiting
+        });
+    }
 
-impl FromStr for Power {
-    type Err = String;
+    fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|(_, pending)| pending.deadline()).min()
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "on" => Ok(Self::On),
-            "off" => Ok(Self::Off),
-            _ => Err(format!("Invalid power value: {}", s)),
-        }
+    /// Takes the commands that weren't answered in time out of the ones waiting for an answer.
+    fn take_expired(&mut self, now: Instant) -> Vec<PendingRequest> {
+        let expired: Vec<usize> = self.pending.iter()
+            .filter(|(_, pending)| pending.deadline() <= now)
+            .map(|(key, _)| key)
+            .collect();
+        expired.into_iter().map(|key| self.pending.remove(key)).collect()
     }
-}
 
-impl Display for Power {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", match self {
-            Self::On => "on",
-            Self::Off => "off",
-        })
+    fn take_all(&mut self) -> Vec<PendingRequest> {
+        self.pending.drain().collect()
     }
-}
 
-#[derive(Deserialize)]
-#[serde(untagged)]
-pub enum YeelightMessage {
-    Response(Response),
-    Notification(Notification),
+    fn ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.pending.iter().map(|(_, pending)| pending.id).collect();
+        ids.sort();
+        ids
+    }
 }
 
-#[derive(Deserialize, Debug, Clone)]
-pub struct Response {
-    pub id: u64,
-    #[serde(flatten)]
-    pub result: ResponseResult,
+/// What the device asks its connection task.
+enum Request {
+    /// Sends the method, answered with the response of the device or why there's none.
+    Send { method: Method, answer: oneshot::Sender<anyhow::Result<Response>> },
+    /// Answered with the ids of the commands waiting for an answer.
+    Pending(oneshot::Sender<Vec<u64>>),
 }
 
-#[derive(Deserialize, PartialEq, Debug, Clone)]
-pub enum ResponseResult {
-    #[serde(rename = "result")]
-    Success(Vec<String>),
+/// The commands sent to a device that weren't answered yet.
+#[derive(Clone)]
+pub struct PendingRequests(mpsc::Sender<Request>);
 
-    #[serde(rename = "error")]
-    Error { code: i64, message: String },
+impl PendingRequests {
+    /// The ids of the commands, none once the connection is closed.
+    pub async fn ids(&self) -> Vec<u64> {
+        let (answer, ids) = oneshot::channel();
+        if self.0.send(Request::Pending(answer)).await.is_err() {
+            return Vec::new();
+        }
+        ids.await.unwrap_or_default()
+    }
 }
 
-impl FromStr for Response {
-    type Err = serde_json::Error;
+/// What the connection task was woken up by.
+enum Event {
+    Read(std::io::Result<usize>),
+    /// The coalesced notifications were sent.
+    Notified,
+    /// Nothing handles the notifications anymore.
+    NotHandled,
+    /// A request of the device, none once it was dropped.
+    Request(Option<Request>),
+    /// A command waiting for an answer timed out.
+    Deadline,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        serde_json::from_str(s)
-    }
+/// The state of the connection to a device, reported by its connection task.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    Connected,
+    /// The device closed the connection, reading from it failed or it stopped answering, with why.
+    Lost(String),
+    /// The connection was closed as nothing handles the notifications of the device anymore.
+    Closed,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Notification {
-    pub method: String,
-    pub params: HashMap<String, Value>,
+/// The connection to a device, owned by a single task the device sends its commands to, so the commands waiting
+/// for an answer need no lock. It's kept between the restarts of the task.
+struct Connection {
+    device_id: String,
+    read_half: BufReader<OwnedReadHalf>,
+    write_half: OwnedWriteHalf,
+    requests: mpsc::Receiver<Request>,
+    pending: Requests,
+    consecutive_timeouts: usize,
+    notification_handler: mpsc::Sender<Notification>,
+    /// The notifications received while the channel was full, coalesced into one with the latest value of each
+    /// property, so a burst of notifications doesn't block reading the answers of the device.
+    overflow: Option<Notification>,
+    status: watch::Sender<ConnectionStatus>,
+    health: Health,
 }
 
-impl FromStr for Notification {
-    type Err = serde_json::Error;
+impl Connection {
+    /// Handles the messages of the device and the commands sent to it until the connection is lost or nothing
+    /// handles the notifications anymore.
+    async fn run(&mut self) {
+        let mut buffer = Vec::new();
+        let status = loop {
+            let deadline = self.pending.next_deadline();
+            // A line longer than the limit is read in parts, which fail to parse and are dropped. The part read
+            // before another branch is taken is kept in the buffer and counts towards the limit.
+            let limit = MAX_MESSAGE_LENGTH - buffer.len() as u64;
+
+            let event = tokio::select! {
+                read = async { (&mut self.read_half).take(limit).read_until(b'\n', &mut buffer).await } => Event::Read(read),
+                permit = self.notification_handler.reserve(), if self.overflow.is_some() => match permit {
+                    Ok(permit) => {
+                        permit.send(self.overflow.take().expect("The overflow was checked"));
+                        Event::Notified
+                    }
+                    Err(_) => Event::NotHandled,
+                },
+                request = self.requests.recv() => Event::Request(request),
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => Event::Deadline,
+            };
+
+            let flow = match event {
+                Event::Read(Ok(0)) => ControlFlow::Break(ConnectionStatus::Lost("The yeelight device closed the connection".into())),
+                Event::Read(Ok(_)) => {
+                    if let Some(recorder) = recording::get() {
+                        recorder.record(Direction::Received, &buffer);
+                    }
+                    let flow = self.process_incoming_message(&buffer);
+                    buffer.clear();
+                    flow
+                }
+                Event::Read(Err(e)) => ControlFlow::Break(ConnectionStatus::Lost(format!("Failed to read from yeelight device: {}", e))),
+                Event::Notified => ControlFlow::Continue(()),
+                Event::NotHandled => Self::not_handled(),
+                Event::Request(Some(Request::Send { method, answer })) => {
+                    self.send(method, answer).await;
+                    ControlFlow::Continue(())
+                }
+                Event::Request(Some(Request::Pending(answer))) => {
+                    let _ = answer.send(self.pending.ids());
+                    ControlFlow::Continue(())
+                }
+                // The device was dropped
+                Event::Request(None) => ControlFlow::Break(ConnectionStatus::Closed),
+                Event::Deadline => self.timed_out(),
+            };
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        serde_json::from_str(s)
+            if let ControlFlow::Break(status) = flow {
+                break status;
+            }
+        };
+
+        if let ConnectionStatus::Lost(reason) = &status {
+            error!("Lost the connection to the yeelight device: {}", reason);
+        }
+        self.health.set_device_state(DeviceState::Disconnected);
+        // The commands waiting for an answer fail now instead of timing out
+        for pending in self.pending.take_all() {
+            let id = pending.id;
+            pending.fail(anyhow!("The connection to the yeelight device was closed before it answered command {}", id));
+        }
+        let _ = self.status.send(status);
     }
-}
 
-pub struct Device {
-    current_id: AtomicU64,
-    write_half: OwnedWriteHalf,
-    responses: Arc<DashMap<u64, oneshot::Sender<Response>>>,
-    read_handle: JoinHandle<()>,
-}
+    async fn send(&mut self, method: Method, answer: oneshot::Sender<anyhow::Result<Response>>) {
+        self.pending.evict_cancelled();
+        if self.pending.is_full() {
+            metrics::command_failed();
+            let _ = answer.send(Err(anyhow!("Too many commands are waiting for an answer from the yeelight device")));
+            return;
+        }
 
-impl Device {
-    pub async fn new(address: String, mut notification_handler: mpsc::Sender<Notification>) -> anyhow::Result<Self> {
-        let (read_half, write_half) = TcpStream::connect(address).await?.into_split();
+        let command = Command::new(self.pending.next_id(), method);
+        let span = info_span!("yeelight_command", device = %self.device_id, command_id = command.id);
+        let write_half = &mut self.write_half;
 
-        let responses: Arc<DashMap<u64, oneshot::Sender<Response>>> = Arc::new(DashMap::new());
+        let written = async {
+            let mut content = serde_json::to_vec(&command)?;
+            debug!("Sending command {}", String::from_utf8_lossy(&content));
+            content.extend_from_slice(b"\r\n");
 
-        let arc = responses.clone();
+            // A device that stopped reading would otherwise block the connection
+            let write = async {
+                write_half.write_all(&content).await?;
+                write_half.flush().await
+            };
+            match tokio::time::timeout(RESPONSE_TIMEOUT, write).await {
+                Ok(result) => result?,
+                Err(_) => anyhow::bail!("The yeelight device didn't read command {} in {:?}", command.id, RESPONSE_TIMEOUT),
+            }
 
-        let read_handle = tokio::spawn(async move {
-            let mut read_half = BufReader::new(read_half);
-            let mut buffer = String::new();
-            while read_half.read_line(&mut buffer).await.unwrap() > 0 {
-                if !buffer.is_empty() {
-                    Self::process_incoming_message(&arc, &mut buffer, &mut notification_handler).await;
-                }
-                buffer.clear();
+            if let Some(recorder) = recording::get() {
+                recorder.record(Direction::Sent, &content);
             }
-        });
+            anyhow::Ok(())
+        }.instrument(span).await;
 
-        Ok(Self { write_half, current_id: AtomicU64::new(0), responses: responses.clone(), read_handle })
+        let pending = PendingRequest { id: command.id, method: command.method.name(), answer, sent: Instant::now() };
+        match written {
+            Ok(()) => self.pending.insert(pending),
+            Err(e) => pending.fail(e),
+        }
     }
 
-    async fn process_incoming_message(
-        wait_map: &Arc<DashMap<u64, oneshot::Sender<Response>>>,
-        content: &mut str, notification_sender:
-        &mut mpsc::Sender<Notification>,
-    ) {
-        let message: YeelightMessage = match serde_json::from_str(content) {
+    fn process_incoming_message(&mut self, content: &[u8]) -> ControlFlow<ConnectionStatus> {
+        let message = match YeelightMessage::parse(content) {
             Ok(message) => message,
             Err(error) => {
-                error!("Failed to parse incoming message: {}: {}", error, content);
-                return;
+                error!("Failed to parse incoming message: {}: {}", error, String::from_utf8_lossy(content));
+                return ControlFlow::Continue(());
             }
         };
 
         match message {
             YeelightMessage::Response(response) => {
-                if let Some((_, sender)) = wait_map.remove(&response.id) {
-                    sender.send(response).unwrap();
+                // The command timed out if it's not waiting for an answer anymore
+                if let Some(pending) = self.pending.take(response.id) {
+                    self.consecutive_timeouts = 0;
+                    metrics::command_succeeded(pending.method, pending.sent.elapsed());
+                    self.health.command_succeeded();
+                    let _ = pending.answer.send(Ok(response));
                 }
+                ControlFlow::Continue(())
+            }
+            YeelightMessage::Notification(notification) => self.notify(notification),
+        }
+    }
+
+    /// Sends the notification, or keeps it with the ones waiting for room in the channel if it's full.
+    fn notify(&mut self, notification: Notification) -> ControlFlow<ConnectionStatus> {
+        let notification = match self.overflow.take() {
+            Some(mut overflow) => {
+                overflow.params.extend(notification.params);
+                overflow
             }
-            YeelightMessage::Notification(notification) => {
-                notification_sender.send(notification).await.unwrap();
+            None => notification,
+        };
+
+        match self.notification_handler.try_send(notification) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(TrySendError::Full(notification)) => {
+                debug!("The notifications of the yeelight device aren't handled fast enough, coalescing {:?}", notification.params);
+                self.overflow = Some(notification);
+                ControlFlow::Continue(())
             }
+            // The application stopped handling the notifications, e.g. it was dropped
+            Err(TrySendError::Closed(_)) => Self::not_handled(),
         }
     }
 
-    pub async fn send_method(&mut self, method: Method) -> anyhow::Result<Response> {
-        let command = self.new_command(method).await;
+    fn not_handled() -> ControlFlow<ConnectionStatus> {
+        info!("Nothing handles the notifications of the yeelight device anymore, closing the connection");
+        ControlFlow::Break(ConnectionStatus::Closed)
+    }
+
+    /// Fails the commands the device didn't answer in time, and takes the connection as lost once it left the
+    /// last ones unanswered.
+    fn timed_out(&mut self) -> ControlFlow<ConnectionStatus> {
+        for pending in self.pending.take_expired(Instant::now()) {
+            self.consecutive_timeouts += 1;
+            let id = pending.id;
+            pending.fail(anyhow!("The yeelight device didn't answer command {} in {:?}", id, RESPONSE_TIMEOUT));
+        }
+
+        if self.consecutive_timeouts >= MAX_CONSECUTIVE_TIMEOUTS {
+            let reason = format!("The yeelight device didn't answer {} commands in a row", self.consecutive_timeouts);
+            return ControlFlow::Break(ConnectionStatus::Lost(reason));
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+pub struct Device {
+    /// The id of the device from discovery.
+    id: String,
+    /// The address it was connected to, to connect to it again without discovering it.
+    address: String,
+    /// The model of the device from discovery or the config, e.g. `color`.
+    model: Option<String>,
+    requests: mpsc::Sender<Request>,
+    handle: JoinHandle<()>,
+    status: watch::Receiver<ConnectionStatus>,
+}
+
+impl Device {
+    pub async fn new(id: String, address: String, notification_handler: mpsc::Sender<Notification>, health: Health) -> anyhow::Result<Self> {
+        let (read_half, write_half) = TcpStream::connect(&address).await?.into_split();
+
+        let (requests, request_receiver) = mpsc::channel(MAX_PENDING_REQUESTS);
+        let (status_sender, status) = watch::channel(ConnectionStatus::Connected);
+
+        let connection = Arc::new(tokio::sync::Mutex::new(Connection {
+            device_id: id.clone(),
+            read_half: BufReader::new(read_half),
+            write_half,
+            requests: request_receiver,
+            pending: Requests::default(),
+            consecutive_timeouts: 0,
+            notification_handler,
+            overflow: None,
+            status: status_sender,
+            health: health.clone(),
+        }));
+
+        // Restarted if handling a message panics, to handle the rest of the messages of the connection
+        let handle = supervisor::supervise("yeelight connection", move || {
+            let connection = connection.clone();
+            async move { connection.lock().await.run().await }
+        });
+
+        health.set_device_state(DeviceState::Connected);
+
+        Ok(Self { id, address, model: None, requests, handle, status })
+    }
+
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
 
-        self.write_half.write_all(&serde_json::to_vec(&command)?).await?;
-        self.write_half.write_all(b"\r\n").await?;
-        self.write_half.flush().await?;
+    pub fn id(&self) -> &str {
+        &self.id
+    }
 
-        self.read_response(command.id).await
+    pub fn address(&self) -> &str {
+        &self.address
     }
 
-    async fn new_command(&mut self, method: Method) -> Command {
-        let current_id = self.current_id.get_mut();
-        *current_id += 1;
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
 
-        Command::new(*current_id, method)
+    pub fn pending_requests(&self) -> PendingRequests {
+        PendingRequests(self.requests.clone())
     }
 
-    async fn read_response(&mut self, id: u64) -> anyhow::Result<Response> {
-        let (sender, receiver) = oneshot::channel();
-        self.responses.insert(id, sender);
+    pub fn is_connected(&self) -> bool {
+        *self.status.borrow() == ConnectionStatus::Connected
+    }
 
-        let response = tokio::time::timeout(Duration::from_secs(5), receiver).await;
+    /// Waits until the connection to the device is lost, with why. Never returns if it was closed on purpose.
+    pub async fn connection_lost(&self) -> String {
+        let mut status = self.status.clone();
+        loop {
+            let current = status.borrow_and_update().clone();
+            match current {
+                ConnectionStatus::Lost(reason) => return reason,
+                ConnectionStatus::Closed => return std::future::pending().await,
+                // The connection task reports the status before it ends, so it can't end while connected
+                ConnectionStatus::Connected => if status.changed().await.is_err() {
+                    return std::future::pending().await;
+                },
+            }
+        }
+    }
 
-        if let Ok(Ok(response)) = response {
-            return Ok(response);
+    pub async fn send_method(&self, method: Method) -> anyhow::Result<Response> {
+        let (answer, response) = oneshot::channel();
+        if !self.is_connected() || self.requests.send(Request::Send { method, answer }).await.is_err() {
+            anyhow::bail!("The connection to the yeelight device is closed");
         }
 
-        anyhow::bail!("{} id timedout", id)
+        response.await.unwrap_or_else(|_| Err(anyhow!("The connection to the yeelight device is closed")))
     }
 }
 
 impl Drop for Device {
     fn drop(&mut self) {
-        self.read_handle.abort();
+        self.handle.abort();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fmt::Display;
-    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
 
-    use crate::yeelight::{Command, Method, Notification, Power, Response, ResponseResult};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::{mpsc, oneshot};
 
-    impl Display for Command {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", serde_json::to_string(self).unwrap())
-        }
+    use crate::health::{DeviceState, Health};
+    use crate::protocol::Method;
+    use crate::yeelight::{Device, PendingRequest, Requests, MAX_CONSECUTIVE_TIMEOUTS, MAX_PENDING_REQUESTS, RESPONSE_TIMEOUT};
+
+    fn pending_request(id: u64, sent: tokio::time::Instant) -> (PendingRequest, oneshot::Receiver<anyhow::Result<crate::protocol::Response>>) {
+        let (answer, receiver) = oneshot::channel();
+        (PendingRequest { id, method: "toggle", answer, sent }, receiver)
     }
 
-    #[test]
-    fn test_command_generate_json_packet() {
-        let mut list = Vec::new();
+    /// A device reading the commands without ever answering them.
+    async fn silent_device() -> Device {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let _ = stream.read_to_end(&mut received).await;
+        });
 
-        list.push((Command::new(1, Method::set_power(Power::On)),
-                   "{\"id\":1,\"method\":\"set_power\",\"params\":[\"on\"]}"));
+        // Never used, the device doesn't notify anything
+        let (sender, _receiver) = mpsc::channel(1);
+        Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap()
+    }
 
-        list.push((Command::new(1, Method::set_brightness(50)),
-                   "{\"id\":1,\"method\":\"set_bright\",\"params\":[50]}"));
+    #[tokio::test]
+    async fn test_pending_requests_are_evicted() {
+        // A device answering a command it wasn't sent, then the first one, then closing the connection
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut command = String::new();
+            stream.read_line(&mut command).await.unwrap();
+            stream.get_mut().write_all(b"{\"id\":42,\"result\":[\"ok\"]}\r\n{\"id\":1,\"result\":[\"ok\"]}\r\n").await.unwrap();
+            stream.read_line(&mut command).await.unwrap();
+        });
 
-        list.push((Command::new(1, Method::get_prop(vec!("power".to_string()))),
-                   "{\"id\":1,\"method\":\"get_prop\",\"params\":[\"power\"]}"));
+        let (sender, _receiver) = mpsc::channel(1);
+        let device = Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap();
 
-        list.push((Command::new(1, Method::TOGGLE),
-                   "{\"id\":1,\"method\":\"toggle\",\"params\":[]}"));
+        assert_eq!(device.send_method(Method::TOGGLE).await.unwrap().id, 1);
+        assert!(device.pending_requests().ids().await.is_empty());
 
-        // Need a better way to do this
+        let error = device.send_method(Method::TOGGLE).await.unwrap_err();
+        assert_eq!(error.to_string(), "The connection to the yeelight device was closed before it answered command 2");
+        assert!(device.pending_requests().ids().await.is_empty());
+    }
 
-        for (command, expected) in list {
-            match command.method {
-                Method::GetProp { .. } => assert_eq!(command.to_string(), expected),
-                Method::SetBright { .. } => assert_eq!(command.to_string(), expected),
-                Method::SetPower { .. } => assert_eq!(command.to_string(), expected),
-                Method::Toggle { .. } => assert_eq!(command.to_string(), expected),
-            };
+    #[tokio::test]
+    async fn test_concurrent_commands() {
+        // A device answering two commands in the reverse order
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut commands = String::new();
+            stream.read_line(&mut commands).await.unwrap();
+            stream.read_line(&mut commands).await.unwrap();
+            stream.get_mut().write_all(b"{\"id\":2,\"result\":[\"off\"]}\r\n{\"id\":1,\"result\":[\"ok\"]}\r\n").await.unwrap();
+            stream.read_line(&mut commands).await.unwrap();
+        });
+
+        let (sender, _receiver) = mpsc::channel(1);
+        let device = Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap();
+
+        let (toggle, power) = tokio::join!(
+            device.send_method(Method::TOGGLE),
+            device.send_method(Method::get_prop(vec!["power".into()])),
+        );
+        assert_eq!(toggle.unwrap().id, 1);
+        assert_eq!(power.unwrap().id, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_requests_are_evicted() {
+        let device = silent_device().await;
+
+        // As when the task sending the command is aborted while waiting for the answer
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), device.send_method(Method::TOGGLE)).await;
+        assert!(cancelled.is_err());
+        assert_eq!(device.pending_requests().ids().await, [1]);
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(50), device.send_method(Method::TOGGLE)).await;
+        assert!(cancelled.is_err());
+        assert_eq!(device.pending_requests().ids().await, [2]);
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests_are_bounded() {
+        let device = Arc::new(silent_device().await);
+
+        for _ in 0..MAX_PENDING_REQUESTS {
+            let device = device.clone();
+            tokio::spawn(async move { device.send_method(Method::TOGGLE).await });
+        }
+        while device.pending_requests().ids().await.len() < MAX_PENDING_REQUESTS {
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
+
+        let error = device.send_method(Method::TOGGLE).await.unwrap_err();
+        assert_eq!(error.to_string(), "Too many commands are waiting for an answer from the yeelight device");
+        assert_eq!(device.pending_requests().ids().await.len(), MAX_PENDING_REQUESTS);
     }
 
     #[test]
-    fn test_response_from_json() {
-        let ok_response = Response::from_str("{\"id\":1,\"result\":[\"on\"]}").unwrap();
-        assert_eq!(ok_response.id, 1);
-        assert_eq!(ok_response.result, ResponseResult::Success(vec!("on".to_string())));
+    fn test_command_ids_wrap_around() {
+        let mut requests = Requests { last_id: u64::MAX - 1, ..Requests::default() };
+        let (pending, _receiver) = pending_request(1, tokio::time::Instant::now());
+        requests.insert(pending);
+
+        assert_eq!(requests.next_id(), u64::MAX);
+        // Skips 0 and the id of the command still waiting for an answer
+        assert_eq!(requests.next_id(), 2);
+    }
 
-        let error_response = "{\"id\":2, \"error\":{\"code\":-1, \"message\":\"unsupported method\"}}";
-        let error_response = Response::from_str(error_response).unwrap();
+    #[test]
+    fn test_expired_requests() {
+        let now = tokio::time::Instant::now();
+        let mut requests = Requests::default();
+        let (expired, _expired_receiver) = pending_request(1, now - RESPONSE_TIMEOUT);
+        let (waiting, _waiting_receiver) = pending_request(2, now);
+        requests.insert(expired);
+        requests.insert(waiting);
+
+        assert_eq!(requests.next_deadline(), Some(now));
+        let expired: Vec<u64> = requests.take_expired(now).iter().map(|pending| pending.id).collect();
+        assert_eq!(expired, [1]);
+        assert_eq!(requests.ids(), [2]);
+        assert_eq!(requests.next_deadline(), Some(now + RESPONSE_TIMEOUT));
+    }
 
-        assert_eq!(error_response.id, 2);
-        dbg!(error_response.result);
+    #[tokio::test]
+    async fn test_reports_the_lost_connection_after_consecutive_timeouts() {
+        let device = silent_device().await;
+        tokio::time::pause();
+
+        for id in 1..MAX_CONSECUTIVE_TIMEOUTS as u64 {
+            let error = device.send_method(Method::TOGGLE).await.unwrap_err();
+            assert_eq!(error.to_string(), format!("The yeelight device didn't answer command {} in 5s", id));
+        }
+        assert!(device.is_connected());
+
+        device.send_method(Method::TOGGLE).await.unwrap_err();
+        assert!(!device.is_connected());
+        let reason = tokio::time::timeout(Duration::from_secs(1), device.connection_lost()).await.unwrap();
+        assert_eq!(reason, "The yeelight device didn't answer 3 commands in a row");
+
+        let error = device.send_method(Method::TOGGLE).await.unwrap_err();
+        assert_eq!(error.to_string(), "The connection to the yeelight device is closed");
     }
 
-    #[test]
-    fn test_notification_from_json() {
-        let notification = "{\"method\":\"props\",\"params\":{\"power\":\"on\", \"bright\": \"10\"}}";
-        let notification: Notification = serde_json::from_str(notification).unwrap();
+    #[tokio::test]
+    async fn test_reports_the_lost_connection() {
+        // A device closing the connection as soon as it's connected to
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { drop(listener.accept().await.unwrap()) });
+
+        let (sender, _receiver) = mpsc::channel(1);
+        let health = Health::without_mqtt();
+        let device = Device::new("0x1".into(), address, sender, health.clone()).await.unwrap();
+
+        let reason = tokio::time::timeout(Duration::from_secs(1), device.connection_lost()).await.unwrap();
+        assert_eq!(reason, "The yeelight device closed the connection");
+        assert_eq!(health.device_state(), DeviceState::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_coalesced_while_not_handled() {
+        // A device notifying three changes as soon as it's connected to, then answering a command
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            stream.get_mut().write_all(concat!(
+                "{\"method\":\"props\",\"params\":{\"bright\":10}}\r\n",
+                "{\"method\":\"props\",\"params\":{\"bright\":20}}\r\n",
+                "{\"method\":\"props\",\"params\":{\"power\":\"on\",\"bright\":30}}\r\n",
+            ).as_bytes()).await.unwrap();
+            let mut command = String::new();
+            stream.read_line(&mut command).await.unwrap();
+            stream.get_mut().write_all(b"{\"id\":1,\"result\":[\"ok\"]}\r\n").await.unwrap();
+            stream.read_line(&mut command).await.unwrap();
+        });
+
+        let (sender, mut receiver) = mpsc::channel(1);
+        let device = Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap();
 
-        assert_eq!(notification.method, "props");
-        assert_eq!(notification.params.get("power").unwrap(), "on");
-        assert_eq!(notification.params.get("bright").unwrap(), "10");
+        // Answered even though nothing handles the notifications yet
+        let response = tokio::time::timeout(Duration::from_secs(1), device.send_method(Method::TOGGLE)).await.unwrap();
+        assert_eq!(response.unwrap().id, 1);
+
+        let first = receiver.recv().await.unwrap();
+        assert_eq!(first.params.len(), 1);
+        assert_eq!(first.params["bright"], 10);
+
+        // The latest value of each property of the notifications that didn't fit in the channel
+        let coalesced = receiver.recv().await.unwrap();
+        assert_eq!(coalesced.params.len(), 2);
+        assert_eq!(coalesced.params["bright"], 30);
+        assert_eq!(coalesced.params["power"], "on");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_closes_when_notifications_are_not_handled() {
+        // A device notifying a change as soon as it's connected to, then reading until the connection is closed
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let device = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"{\"method\":\"props\",\"params\":{\"power\":\"off\"}}\r\n").await.unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).await.unwrap();
+        });
+
+        let (sender, receiver) = mpsc::channel(1);
+        drop(receiver);
+        let device_connection = Device::new("0x1".into(), address, sender, Health::without_mqtt()).await.unwrap();
+
+        while !device_connection.handle.is_finished() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let sent = Instant::now();
+        let error = device_connection.send_method(Method::TOGGLE).await.unwrap_err();
+        assert_eq!(error.to_string(), "The connection to the yeelight device is closed");
+        assert!(sent.elapsed() < Duration::from_secs(1));
+
+        tokio::time::timeout(Duration::from_secs(1), device).await.unwrap().unwrap();
+    }
+}
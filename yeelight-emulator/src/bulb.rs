@@ -0,0 +1,148 @@
+use serde_json::{Map, Value};
+
+/// The properties of the emulated bulb that can be read and changed with commands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub power: bool,
+    pub bright: u8,
+    /// The color temperature in kelvin.
+    pub ct: u16,
+    pub name: String,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self { power: true, bright: 100, ct: 4000, name: String::new() }
+    }
+}
+
+/// The answer to a command that was executed.
+#[derive(Debug, PartialEq)]
+pub struct Outcome {
+    pub result: Vec<String>,
+    /// The properties set by the command, sent to every connection as a `props` notification.
+    pub changed: Map<String, Value>,
+}
+
+impl Outcome {
+    fn ok(changed: Map<String, Value>) -> Self {
+        Self { result: vec!["ok".into()], changed }
+    }
+}
+
+/// An error answered to a command, as `{"code": -1, "message": ...}` as the real bulbs do.
+#[derive(Debug, PartialEq)]
+pub struct CommandError(pub &'static str);
+
+pub const METHOD_NOT_SUPPORTED: CommandError = CommandError("method not supported");
+pub const INVALID_PARAMS: CommandError = CommandError("invalid params");
+pub const QUOTA_EXCEEDED: CommandError = CommandError("client quota exceeded");
+
+/// The methods the bulb advertises in discovery.
+pub const SUPPORT: &str = "get_prop set_power toggle set_bright set_scene set_name";
+
+impl State {
+    /// The value of a property in a `get_prop` result, empty for the ones the bulb doesn't have.
+    pub fn prop(&self, name: &str) -> String {
+        match name {
+            "power" => self.power_name().to_string(),
+            "bright" => self.bright.to_string(),
+            "ct" => self.ct.to_string(),
+            "name" => self.name.clone(),
+            _ => String::new(),
+        }
+    }
+
+    pub fn power_name(&self) -> &'static str {
+        if self.power { "on" } else { "off" }
+    }
+
+    fn set_power(&mut self, power: bool) -> Outcome {
+        self.power = power;
+        Outcome::ok(Map::from_iter([("power".to_string(), Value::from(self.power_name()))]))
+    }
+
+    /// Executes a command. The transitions of `smooth` effects aren't emulated, the bulb changes right away.
+    pub fn execute(&mut self, method: &str, params: &[Value]) -> Result<Outcome, CommandError> {
+        match method {
+            "get_prop" => {
+                let result = params.iter()
+                    .map(|param| param.as_str().map(|name| self.prop(name)).ok_or(INVALID_PARAMS))
+                    .collect::<Result<_, _>>()?;
+                Ok(Outcome { result, changed: Map::new() })
+            }
+            "set_power" => match params.first().and_then(Value::as_str) {
+                Some("on") => Ok(self.set_power(true)),
+                Some("off") => Ok(self.set_power(false)),
+                _ => Err(INVALID_PARAMS),
+            },
+            "toggle" => Ok(self.set_power(!self.power)),
+            "set_bright" => {
+                let bright = params.first().and_then(Value::as_u64).filter(|bright| (1..=100).contains(bright)).ok_or(INVALID_PARAMS)?;
+                self.bright = bright as u8;
+                Ok(Outcome::ok(Map::from_iter([("bright".to_string(), Value::from(self.bright))])))
+            }
+            // Only the color temperature scene of the color bulbs, which turns the bulb on
+            "set_scene" => {
+                let ct = params.get(1).and_then(Value::as_u64).filter(|ct| (1700..=6500).contains(ct));
+                let bright = params.get(2).and_then(Value::as_u64).filter(|bright| (1..=100).contains(bright));
+                let (Some("ct"), Some(ct), Some(bright)) = (params.first().and_then(Value::as_str), ct, bright) else {
+                    return Err(INVALID_PARAMS);
+                };
+                self.power = true;
+                self.ct = ct as u16;
+                self.bright = bright as u8;
+                Ok(Outcome::ok(Map::from_iter([
+                    ("power".to_string(), Value::from(self.power_name())),
+                    ("bright".to_string(), Value::from(self.bright)),
+                    ("ct".to_string(), Value::from(self.ct)),
+                ])))
+            }
+            "set_name" => {
+                self.name = params.first().and_then(Value::as_str).ok_or(INVALID_PARAMS)?.to_string();
+                Ok(Outcome::ok(Map::from_iter([("name".to_string(), Value::from(self.name.clone()))])))
+            }
+            _ => Err(METHOD_NOT_SUPPORTED),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::bulb::{State, INVALID_PARAMS, METHOD_NOT_SUPPORTED};
+
+    #[test]
+    fn test_execute() {
+        let mut state = State::default();
+
+        let outcome = state.execute("set_bright", &[json!(50), json!("smooth"), json!(500)]).unwrap();
+        assert_eq!(outcome.result, ["ok"]);
+        assert_eq!(json!(outcome.changed), json!({ "bright": 50 }));
+
+        let outcome = state.execute("toggle", &[]).unwrap();
+        assert_eq!(json!(outcome.changed), json!({ "power": "off" }));
+
+        let outcome = state.execute("get_prop", &[json!("power"), json!("bright"), json!("hue")]).unwrap();
+        assert_eq!(outcome.result, ["off", "50", ""]);
+        assert!(outcome.changed.is_empty());
+
+        assert_eq!(state.execute("set_power", &[json!("dim")]), Err(INVALID_PARAMS));
+        assert_eq!(state.execute("set_bright", &[json!(0)]), Err(INVALID_PARAMS));
+        assert_eq!(state.execute("start_cf", &[]), Err(METHOD_NOT_SUPPORTED));
+        assert_eq!(state, State { power: false, bright: 50, ct: 4000, name: String::new() });
+    }
+
+    #[test]
+    fn test_set_scene() {
+        let mut state = State { power: false, ..State::default() };
+
+        let outcome = state.execute("set_scene", &[json!("ct"), json!(2700), json!(40)]).unwrap();
+        assert_eq!(json!(outcome.changed), json!({ "power": "on", "bright": 40, "ct": 2700 }));
+
+        assert_eq!(state.execute("set_scene", &[json!("ct"), json!(1000), json!(40)]), Err(INVALID_PARAMS));
+        assert_eq!(state.execute("set_scene", &[json!("color"), json!(65280), json!(40)]), Err(INVALID_PARAMS));
+        assert_eq!(state, State { power: true, bright: 40, ct: 2700, name: String::new() });
+    }
+}
@@ -0,0 +1,83 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tracing::{debug, info};
+
+use crate::bulb::SUPPORT;
+use crate::Emulator;
+
+pub const DISCOVERY_PORT: u16 = 1982;
+pub const MULTI_CAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+
+/// Whether the message is a discovery of yeelight bulbs, as sent by the controller.
+fn is_search(message: &[u8]) -> bool {
+    let message = String::from_utf8_lossy(message);
+    message.starts_with("M-SEARCH") && message.lines().any(|line| line.trim() == "ST: wifi_bulb")
+}
+
+impl Emulator {
+    /// The answer to a discovery, with the address the commands are accepted on.
+    pub fn discovery_response(&self, address: SocketAddr) -> String {
+        let state = self.state();
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+             Cache-Control: max-age=3600\r\n\
+             Location: yeelight://{}\r\n\
+             Server: POSIX UPnP/1.0 YGLC/1\r\n\
+             id: {}\r\n\
+             model: {}\r\n\
+             fw_ver: 18\r\n\
+             support: {}\r\n\
+             power: {}\r\n\
+             bright: {}\r\n\
+             name: {}\r\n",
+            address, self.id, self.model, SUPPORT, state.power_name(), state.bright, state.name,
+        )
+    }
+
+    /// Answers the discoveries received on the socket, e.g. one bound to [`DISCOVERY_PORT`] that joined
+    /// [`MULTI_CAST_ADDR`].
+    pub async fn answer_discovery(self, socket: UdpSocket, address: SocketAddr) -> anyhow::Result<()> {
+        info!("Answering discoveries on {} with {}", socket.local_addr()?, address);
+
+        let mut buf = [0; 2048];
+        loop {
+            let (len, sender) = socket.recv_from(&mut buf).await?;
+            if !is_search(&buf[..len]) {
+                continue;
+            }
+
+            debug!("Answering the discovery from {}", sender);
+            socket.send_to(self.discovery_response(address).as_bytes(), sender).await?;
+        }
+    }
+}
+
+/// Binds the socket the bulbs receive discoveries on, shared so several emulators can answer on the same host.
+pub fn bind() -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT)).into())?;
+    socket.join_multicast_v4(&MULTI_CAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    UdpSocket::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::discovery::is_search;
+    use crate::{Emulator, Faults, State};
+
+    #[test]
+    fn test_discovery_response() {
+        assert!(is_search(b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1982\r\nMAN: \"ssdp:discover\"\r\nST: wifi_bulb\r\n"));
+        assert!(!is_search(b"M-SEARCH * HTTP/1.1\r\nST: ssdp:all\r\n"));
+
+        let emulator = Emulator::new("0x0000000012345678".into(), "color".into(), State::default(), Faults::default());
+        let response = emulator.discovery_response("192.168.1.10:55443".parse().unwrap());
+        assert!(response.contains("\r\nLocation: yeelight://192.168.1.10:55443\r\n"));
+        assert!(response.contains("\r\nid: 0x0000000012345678\r\nmodel: color\r\n"));
+        assert!(response.contains("\r\npower: on\r\nbright: 100\r\n"));
+    }
+}
@@ -0,0 +1,84 @@
+//! Emulates a yeelight bulb on the network, to run the yeelight controller without one.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+
+pub mod bulb;
+pub mod discovery;
+pub mod server;
+
+pub use bulb::State;
+
+/// The faults injected in the answers to the commands, to test how the controller copes with a misbehaving
+/// bulb. The commands are counted across every connection.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    /// How long to wait before answering every command.
+    pub delay: Duration,
+    /// Answers every nth command with the `client quota exceeded` error, as the bulbs do after 60 commands
+    /// in a minute.
+    pub quota_error_every: Option<u64>,
+    /// Closes the connection instead of answering every nth command.
+    pub drop_every: Option<u64>,
+}
+
+/// What's done with a command, from the faults.
+#[derive(Debug, PartialEq)]
+pub enum Fault {
+    None,
+    QuotaError,
+    Drop,
+}
+
+/// The emulated bulb, shared by the discovery responder and every connection.
+#[derive(Clone)]
+pub struct Emulator {
+    pub id: String,
+    pub model: String,
+    pub faults: Faults,
+    state: Arc<Mutex<State>>,
+    commands: Arc<AtomicU64>,
+    notifications: broadcast::Sender<String>,
+}
+
+impl Emulator {
+    pub fn new(id: String, model: String, state: State, faults: Faults) -> Self {
+        let (notifications, _) = broadcast::channel(16);
+        Self { id, model, faults, state: Arc::new(Mutex::new(state)), commands: Arc::new(AtomicU64::new(0)), notifications }
+    }
+
+    pub fn state(&self) -> State {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Counts a command and picks the fault injected in its answer.
+    fn next_fault(&self) -> Fault {
+        let count = self.commands.fetch_add(1, Ordering::Relaxed) + 1;
+        let every = |n: Option<u64>| n.and_then(|n| count.checked_rem(n)) == Some(0);
+
+        if every(self.faults.drop_every) {
+            Fault::Drop
+        } else if every(self.faults.quota_error_every) {
+            Fault::QuotaError
+        } else {
+            Fault::None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Emulator, Fault, Faults, State};
+
+    #[test]
+    fn test_next_fault() {
+        let faults = Faults { quota_error_every: Some(2), drop_every: Some(3), ..Faults::default() };
+        let emulator = Emulator::new("0x1".into(), "color".into(), State::default(), faults);
+
+        let faults: Vec<Fault> = (0..6).map(|_| emulator.next_fault()).collect();
+        assert_eq!(faults, [Fault::None, Fault::QuotaError, Fault::Drop, Fault::QuotaError, Fault::None, Fault::Drop]);
+    }
+}
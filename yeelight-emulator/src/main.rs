@@ -0,0 +1,83 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use clap::Parser;
+use local_ip_address::local_ip;
+use tokio::net::TcpListener;
+use tracing::info;
+use tracing_subscriber::EnvFilter;
+
+use yeelight_emulator::{discovery, Emulator, Faults, State};
+
+/// Emulates a yeelight bulb: answers the discoveries, accepts the commands and notifies the changes.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// The id of the bulb in the discovery.
+    #[arg(long, default_value = "0x0000000012345678")]
+    id: String,
+
+    /// The model of the bulb in the discovery.
+    #[arg(long, default_value = "color")]
+    model: String,
+
+    /// The port the commands are accepted on.
+    #[arg(long, default_value_t = 55443)]
+    port: u16,
+
+    /// The address of the bulb in the discovery, instead of the local ip.
+    #[arg(long)]
+    address: Option<IpAddr>,
+
+    /// Doesn't answer the discoveries, so the bulb is only reachable at its address.
+    #[arg(long)]
+    no_discovery: bool,
+
+    /// Starts turned off.
+    #[arg(long)]
+    off: bool,
+
+    /// The brightness it starts with.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(1..=100))]
+    bright: u8,
+
+    /// Waits this long before answering every command.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    delay_ms: u64,
+
+    /// Answers every nth command with the `client quota exceeded` error.
+    #[arg(long, value_name = "N")]
+    quota_error_every: Option<u64>,
+
+    /// Closes the connection instead of answering every nth command.
+    #[arg(long, value_name = "N")]
+    drop_every: Option<u64>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
+    let args = Args::parse();
+
+    let state = State { power: !args.off, bright: args.bright, ..State::default() };
+    let faults = Faults {
+        delay: Duration::from_millis(args.delay_ms),
+        quota_error_every: args.quota_error_every,
+        drop_every: args.drop_every,
+    };
+    let emulator = Emulator::new(args.id, args.model, state, faults);
+    info!("Emulating yeelight {} {} with {:?}", emulator.model, emulator.id, emulator.faults);
+
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, args.port)).await?;
+
+    if !args.no_discovery {
+        let ip = args.address.unwrap_or_else(|| local_ip().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        let address = SocketAddr::new(ip, listener.local_addr()?.port());
+        tokio::spawn(emulator.clone().answer_discovery(discovery::bind()?, address));
+    }
+
+    emulator.serve(listener).await
+}
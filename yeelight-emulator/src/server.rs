@@ -0,0 +1,155 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, info, warn};
+
+use crate::bulb::QUOTA_EXCEEDED;
+use crate::{Emulator, Fault};
+
+#[derive(Deserialize, Debug)]
+struct Command {
+    id: u64,
+    method: String,
+    #[serde(default)]
+    params: Vec<Value>,
+}
+
+impl Emulator {
+    /// Answers the commands of every connection accepted by the listener.
+    pub async fn serve(self, listener: TcpListener) -> anyhow::Result<()> {
+        info!("Accepting commands on {}", listener.local_addr()?);
+
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let emulator = self.clone();
+            tokio::spawn(async move {
+                info!("Accepted connection from {}", address);
+                match emulator.handle_connection(stream).await {
+                    Ok(()) => info!("Closed connection from {}", address),
+                    Err(e) => warn!("Connection from {} failed: {}", address, e),
+                }
+            });
+        }
+    }
+
+    /// Answers the commands of a connection and sends it the notifications of every change.
+    async fn handle_connection(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let mut notifications = self.notifications.subscribe();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut lines = BufReader::new(read_half).lines();
+
+        loop {
+            let message = tokio::select! {
+                line = lines.next_line() => {
+                    let Some(line) = line? else { return Ok(()) };
+                    match self.answer(&line).await {
+                        Some(answer) => answer,
+                        None => return Ok(()),
+                    }
+                }
+                notification = notifications.recv() => match notification {
+                    Ok(notification) => notification,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            };
+
+            debug!("Sending {}", message);
+            write_half.write_all(message.as_bytes()).await?;
+            write_half.write_all(b"\r\n").await?;
+        }
+    }
+
+    /// The answer to a command line, or none if the connection is dropped.
+    async fn answer(&self, line: &str) -> Option<String> {
+        debug!("Received {}", line);
+
+        let command: Command = match serde_json::from_str(line.trim()) {
+            Ok(command) => command,
+            Err(e) => {
+                warn!("Received an invalid command {:?}: {}", line, e);
+                return Some(json!({ "id": 0, "error": { "code": -1, "message": "invalid command" } }).to_string());
+            }
+        };
+
+        let fault = self.next_fault();
+        if fault == Fault::Drop {
+            info!("Dropping the connection instead of answering command {}", command.id);
+            return None;
+        }
+
+        tokio::time::sleep(self.faults.delay).await;
+
+        let outcome = match fault {
+            Fault::QuotaError => Err(QUOTA_EXCEEDED),
+            _ => self.state.lock().unwrap().execute(&command.method, &command.params),
+        };
+
+        let answer = match outcome {
+            Ok(outcome) => {
+                if !outcome.changed.is_empty() {
+                    let notification = json!({ "method": "props", "params": outcome.changed });
+                    let _ = self.notifications.send(notification.to_string());
+                }
+                json!({ "id": command.id, "result": outcome.result })
+            }
+            Err(e) => json!({ "id": command.id, "error": { "code": -1, "message": e.0 } }),
+        };
+
+        Some(answer.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::{Emulator, Faults, State};
+
+    async fn connect(faults: Faults) -> (Emulator, BufReader<TcpStream>) {
+        let emulator = Emulator::new("0x1".into(), "color".into(), State::default(), faults);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(emulator.clone().serve(listener));
+        (emulator, BufReader::new(TcpStream::connect(address).await.unwrap()))
+    }
+
+    async fn send(stream: &mut BufReader<TcpStream>, command: Value) -> Option<Value> {
+        stream.get_mut().write_all(format!("{}\r\n", command).as_bytes()).await.unwrap();
+        read(stream).await
+    }
+
+    async fn read(stream: &mut BufReader<TcpStream>) -> Option<Value> {
+        let mut line = String::new();
+        match stream.read_line(&mut line).await.unwrap() {
+            0 => None,
+            _ => Some(serde_json::from_str(&line).unwrap()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commands() {
+        let (emulator, mut stream) = connect(Faults::default()).await;
+
+        let answer = send(&mut stream, json!({ "id": 1, "method": "set_power", "params": ["off"] })).await;
+        assert_eq!(answer, Some(json!({ "id": 1, "result": ["ok"] })));
+        assert_eq!(read(&mut stream).await, Some(json!({ "method": "props", "params": { "power": "off" } })));
+
+        let answer = send(&mut stream, json!({ "id": 2, "method": "get_prop", "params": ["power", "bright"] })).await;
+        assert_eq!(answer, Some(json!({ "id": 2, "result": ["off", "100"] })));
+        assert!(!emulator.state().power);
+    }
+
+    #[tokio::test]
+    async fn test_faults() {
+        let (_, mut stream) = connect(Faults { quota_error_every: Some(1), drop_every: Some(2), ..Faults::default() }).await;
+
+        let answer = send(&mut stream, json!({ "id": 1, "method": "toggle", "params": [] })).await;
+        assert_eq!(answer, Some(json!({ "id": 1, "error": { "code": -1, "message": "client quota exceeded" } })));
+        assert_eq!(send(&mut stream, json!({ "id": 2, "method": "toggle", "params": [] })).await, None);
+    }
+}
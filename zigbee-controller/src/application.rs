@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use domain_state::error;
+use log::{debug, error, info, warn};
+use paho_mqtt::{AsyncClient, Message};
+use serde_json::json;
+
+use crate::{converters, MQTT_DEVICES_PUBLISH_TOPIC, MQTT_ERROR_TOPIC, MQTT_TOPIC_PREFIX};
+use crate::coordinator::Coordinator;
+use crate::database::{Database, format_ieee_address};
+use crate::zcl::{self, ZclFrame};
+use crate::znp::{commands, CommandType, Frame, IncomingMessage, Reader};
+
+const DEFAULT_ENDPOINT: u8 = 1;
+
+pub struct Application {
+    client: AsyncClient,
+    coordinator: Coordinator,
+    database: Database,
+    /// Friendly names by IEEE address.
+    names: HashMap<String, String>,
+    zcl_sequence: u8,
+}
+
+/// A `smart-home-system/zigbee/<device>/<attribute>/<action>` topic.
+struct DeviceTopic<'a> {
+    device: &'a str,
+    attribute: &'a str,
+    action: &'a str,
+}
+
+impl<'a> DeviceTopic<'a> {
+    fn parse(topic: &'a str) -> Option<Self> {
+        let rest = topic.strip_prefix(MQTT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        let mut parts = rest.split('/');
+
+        let topic = Self { device: parts.next()?, attribute: parts.next()?, action: parts.next()? };
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(topic),
+        }
+    }
+}
+
+impl Application {
+    pub fn new(client: AsyncClient, coordinator: Coordinator, database: Database, names: HashMap<String, String>) -> Self {
+        let names = names.into_iter().map(|(ieee_address, name)| (ieee_address.to_ascii_lowercase(), name)).collect();
+
+        let application = Self { client, coordinator, database, names, zcl_sequence: 0 };
+        application.publish_devices();
+        application
+    }
+
+    fn topic_name<'a>(&'a self, ieee_address: &'a str) -> &'a str {
+        self.names.get(ieee_address).map(String::as_str).unwrap_or(ieee_address)
+    }
+
+    /// Resolves a topic segment, either a friendly name or an IEEE address, to the IEEE address.
+    fn resolve(&self, device: &str) -> Option<String> {
+        self.names.iter()
+            .find(|(_, name)| name.as_str() == device)
+            .map(|(ieee_address, _)| ieee_address.clone())
+            .or_else(|| self.database.get(device).map(|_| device.to_string()))
+    }
+
+    fn publish_devices(&self) {
+        let devices: Vec<_> = self.database.devices()
+            .map(|(ieee_address, device)| json!({
+                "ieee_address": ieee_address,
+                "name": self.topic_name(ieee_address),
+                "network_address": device.network_address,
+            }))
+            .collect();
+
+        self.client.publish(Message::new_retained(MQTT_DEVICES_PUBLISH_TOPIC, json!(devices).to_string(), 1));
+    }
+
+    fn save_database(&self) {
+        if let Err(e) = self.database.save() {
+            error!("Failed to save zigbee device database: {}", e);
+        }
+    }
+
+    fn device_joined(&mut self, ieee_address: u64, network_address: u16) {
+        let ieee_address = format_ieee_address(ieee_address);
+
+        if self.database.upsert(ieee_address.clone(), network_address) {
+            info!("Zigbee device {} joined with network address {:#06x}", self.topic_name(&ieee_address), network_address);
+            self.save_database();
+            self.publish_devices();
+        }
+    }
+
+    pub async fn handle_indication(&mut self, frame: Frame) {
+        if let Err(e) = self.try_handle_indication(&frame).await {
+            warn!("Failed to handle coordinator indication {:?}: {}", frame, e);
+        }
+    }
+
+    async fn try_handle_indication(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        let mut reader = Reader::new(&frame.data);
+
+        if frame.is(CommandType::Areq, commands::AF_INCOMING_MSG) {
+            return self.handle_incoming_message(IncomingMessage::parse(&frame.data)?).await;
+        } else if frame.is(CommandType::Areq, commands::ZDO_END_DEVICE_ANNCE_IND) {
+            let _source_address = reader.u16()?;
+            let network_address = reader.u16()?;
+            self.device_joined(reader.u64()?, network_address);
+        } else if frame.is(CommandType::Areq, commands::ZDO_TC_DEV_IND) {
+            let network_address = reader.u16()?;
+            self.device_joined(reader.u64()?, network_address);
+        } else if frame.is(CommandType::Areq, commands::ZDO_IEEE_ADDR_RSP) {
+            if reader.u8()? == 0 {
+                let ieee_address = reader.u64()?;
+                self.device_joined(ieee_address, reader.u16()?);
+            }
+        } else if frame.is(CommandType::Areq, commands::ZDO_LEAVE_IND) {
+            let _source_address = reader.u16()?;
+            let ieee_address = format_ieee_address(reader.u64()?);
+            let _request = reader.u8()?;
+            let _remove_children = reader.u8()?;
+            let rejoin = reader.u8()? != 0;
+
+            if !rejoin && self.database.remove(&ieee_address) {
+                info!("Zigbee device {} left the network", self.topic_name(&ieee_address));
+                self.save_database();
+                self.publish_devices();
+            }
+        } else if frame.is(CommandType::Areq, commands::AF_DATA_CONFIRM) {
+            if let Some(status) = frame.data.first().filter(|status| **status != 0) {
+                warn!("Zigbee message was not delivered: status {:#04x}", status);
+            }
+        } else {
+            debug!("Ignoring coordinator indication: {:?}", frame);
+        }
+
+        Ok(())
+    }
+
+    async fn handle_incoming_message(&mut self, message: IncomingMessage) -> anyhow::Result<()> {
+        let Some(ieee_address) = self.database.find_by_network_address(message.source_address).map(str::to_string) else {
+            info!("Received message from unknown device {:#06x}. Requesting its address...", message.source_address);
+            return self.coordinator.request_ieee_address(message.source_address).await;
+        };
+
+        if self.database.record_endpoint(&ieee_address, message.cluster_id, message.source_endpoint) {
+            self.save_database();
+        }
+
+        if !zcl::is_from_server(&message.data) {
+            return Ok(());
+        }
+
+        let frame = ZclFrame::parse(&message.data)?;
+        let values = converters::from_device(message.cluster_id, &frame)?;
+
+        if values.is_empty() {
+            debug!("Ignoring zcl frame from {} on cluster {:#06x}: {:?}", ieee_address, message.cluster_id, frame);
+            return Ok(());
+        }
+
+        let name = self.topic_name(&ieee_address).to_string();
+
+        for (attribute, payload) in values.into_iter().chain([("linkquality", message.link_quality.to_string())]) {
+            info!("Zigbee device {} {} is: {}", name, attribute, payload);
+
+            let topic = format!("{}/{}/{}", MQTT_TOPIC_PREFIX, name, attribute);
+            self.client.publish(Message::new_retained(topic, payload, 1));
+        }
+
+        Ok(())
+    }
+
+    fn next_zcl_sequence(&mut self) -> u8 {
+        self.zcl_sequence = self.zcl_sequence.wrapping_add(1);
+        self.zcl_sequence
+    }
+
+    pub async fn handle_mqtt_permit_join(&mut self, message: &Message) {
+        let payload = message.payload_str();
+
+        let Ok(seconds) = payload.trim().parse::<u8>() else {
+            error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, "Expected a number of seconds from 0 to 255");
+            return;
+        };
+
+        info!("[{}] Permitting devices to join for {} seconds", message.topic(), seconds);
+
+        if let Err(e) = self.coordinator.permit_join(seconds).await {
+            error!("[{}] Failed to permit joining: {}", message.topic(), e);
+        }
+    }
+
+    pub async fn handle_mqtt_message(&mut self, message: &Message) {
+        let Some(topic) = DeviceTopic::parse(message.topic()) else {
+            error!("Received message for unknown topic: {}", message.topic());
+            return;
+        };
+
+        let Some(ieee_address) = self.resolve(topic.device) else {
+            warn!("[{}] Unknown zigbee device {}", message.topic(), topic.device);
+            return;
+        };
+
+        match topic.action {
+            "get" => self.handle_get(&ieee_address, message).await,
+            "set" => self.handle_set(&ieee_address, &topic, message).await,
+            _ => error!("Received message for unknown topic: {}", message.topic()),
+        }
+    }
+
+    async fn handle_get(&mut self, ieee_address: &str, message: &Message) {
+        let Some(device) = self.database.get(ieee_address).cloned() else { return };
+
+        for (cluster, attributes) in converters::READABLE_ATTRIBUTES {
+            let Some(endpoint) = device.endpoints.get(cluster) else { continue };
+
+            let sequence = self.next_zcl_sequence();
+            let zcl = ZclFrame::encode_client_command(false, sequence, zcl::COMMAND_READ_ATTRIBUTES, &zcl::read_attributes_payload(attributes));
+
+            if let Err(e) = self.coordinator.send_zcl(device.network_address, *endpoint, *cluster, &zcl).await {
+                error!("[{}] Failed to read cluster {:#06x} of zigbee device {}: {}", message.topic(), cluster, ieee_address, e);
+            }
+        }
+    }
+
+    async fn handle_set(&mut self, ieee_address: &str, topic: &DeviceTopic<'_>, message: &Message) {
+        let Some(device) = self.database.get(ieee_address).cloned() else { return };
+
+        let payload = message.payload_str();
+        let command = match converters::to_device(topic.attribute, &payload) {
+            Ok(command) => command,
+            Err(e) => {
+                error::publish_invalid_payload(&self.client, MQTT_ERROR_TOPIC, message, &e.to_string());
+                return;
+            }
+        };
+
+        info!("[{}] Setting zigbee device {} {} to: {}", message.topic(), topic.device, topic.attribute, payload);
+
+        let endpoint = device.endpoints.get(&command.cluster).copied().unwrap_or(DEFAULT_ENDPOINT);
+        let sequence = self.next_zcl_sequence();
+        let zcl = ZclFrame::encode_client_command(true, sequence, command.command, &command.payload);
+
+        if let Err(e) = self.coordinator.send_zcl(device.network_address, endpoint, command.cluster, &zcl).await {
+            error!("Failed to set {} on zigbee device {}: {}", topic.attribute, topic.device, e);
+        }
+    }
+}
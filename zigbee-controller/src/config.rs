@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub serial_port: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    /// Only used when the coordinator has no network yet and one is formed.
+    #[serde(default = "default_channel")]
+    pub channel: u8,
+    #[serde(default = "default_pan_id")]
+    pub pan_id: u16,
+    #[serde(default = "default_database_path")]
+    pub database_path: String,
+    /// Friendly names by IEEE address, used as the topic segment instead of the address,
+    /// e.g. `smart-home-system/zigbee/<name>/temperature`.
+    #[serde(default)]
+    pub devices: HashMap<String, String>,
+}
+
+fn default_baud_rate() -> u32 {
+    115200
+}
+
+fn default_channel() -> u8 {
+    11
+}
+
+fn default_pan_id() -> u16 {
+    0x1A62
+}
+
+fn default_database_path() -> String {
+    "zigbee-devices.json".into()
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read config {:?}", path))?;
+
+        let config: Config = serde_yaml::from_str(&content).context(format!("Invalid config {:?}", path))?;
+
+        if !(11..=26).contains(&config.channel) {
+            anyhow::bail!("Invalid zigbee channel {}. Must be between 11 and 26", config.channel);
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::zcl::{self, AttributeValue, ZclFrame};
+
+/// Attributes read when a device's state is requested, by cluster.
+pub const READABLE_ATTRIBUTES: &[(u16, &[u16])] = &[
+    (zcl::CLUSTER_ON_OFF, &[0x0000]),
+    (zcl::CLUSTER_LEVEL_CONTROL, &[0x0000]),
+];
+
+/// Converts a message from a device into `(attribute, payload)` pairs published under the device topic.
+pub fn from_device(cluster: u16, frame: &ZclFrame) -> anyhow::Result<Vec<(&'static str, String)>> {
+    if frame.cluster_specific {
+        return Ok(match (cluster, frame.command) {
+            // zone status change notification: zone status, extended status, zone id, delay
+            (zcl::CLUSTER_IAS_ZONE, 0x00) if frame.payload.len() >= 2 => {
+                let status = u16::from_le_bytes([frame.payload[0], frame.payload[1]]);
+                vec![("alarm", on_off(status & 0x0001 != 0)), ("tamper", on_off(status & 0x0004 != 0))]
+            }
+            _ => vec![],
+        });
+    }
+
+    let attributes = match frame.command {
+        zcl::COMMAND_REPORT_ATTRIBUTES => zcl::parse_report(&frame.payload)?,
+        zcl::COMMAND_READ_ATTRIBUTES_RESPONSE => zcl::parse_read_response(&frame.payload)?,
+        _ => return Ok(vec![]),
+    };
+
+    Ok(attributes.iter().filter_map(|(attribute, value)| convert_attribute(cluster, *attribute, value)).collect())
+}
+
+fn convert_attribute(cluster: u16, attribute: u16, value: &AttributeValue) -> Option<(&'static str, String)> {
+    let number = value.as_f64()?;
+
+    match (cluster, attribute) {
+        (zcl::CLUSTER_ON_OFF, 0x0000) => Some(("power", on_off(number != 0.0))),
+        (zcl::CLUSTER_LEVEL_CONTROL, 0x0000) => Some(("brightness", ((number / 254.0 * 100.0).round() as u8).to_string())),
+        // invalid measurements are reported as 0x8000 / 0xFFFF
+        (zcl::CLUSTER_TEMPERATURE_MEASUREMENT, 0x0000) if number != -32768.0 => Some(("temperature", format!("{:.1}", number / 100.0))),
+        (zcl::CLUSTER_RELATIVE_HUMIDITY, 0x0000) if number != 65535.0 => Some(("humidity", format!("{:.1}", number / 100.0))),
+        (zcl::CLUSTER_OCCUPANCY_SENSING, 0x0000) => Some(("occupancy", on_off(number as u64 & 0x01 != 0))),
+        // reported in half percent
+        (zcl::CLUSTER_POWER_CONFIGURATION, 0x0021) if number != 255.0 => Some(("battery", ((number / 2.0).round() as u8).min(100).to_string())),
+        _ => None,
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "on" } else { "off" }.into()
+}
+
+/// A cluster-specific command that changes the state of a device.
+#[derive(Debug, PartialEq)]
+pub struct DeviceCommand {
+    pub cluster: u16,
+    pub command: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Converts a payload published to `<device>/<attribute>/set` into the command for the device.
+pub fn to_device(attribute: &str, payload: &str) -> Result<DeviceCommand, String> {
+    let payload = payload.trim();
+
+    match attribute {
+        "power" => {
+            let command = match payload {
+                "on" | "true" | "1" => 0x01,
+                "off" | "false" | "0" => 0x00,
+                "toggle" => 0x02,
+                _ => return Err(format!("Invalid power value: {}", payload)),
+            };
+
+            Ok(DeviceCommand { cluster: zcl::CLUSTER_ON_OFF, command, payload: vec![] })
+        }
+        "brightness" => {
+            let brightness = payload.parse::<u8>().ok().filter(|brightness| *brightness <= 100)
+                .ok_or_else(|| format!("Invalid brightness value: {}", payload))?;
+
+            let level = (brightness as f64 / 100.0 * 254.0).round() as u8;
+            let transition_time: u16 = 5; // tenths of a second
+
+            let mut payload = vec![level];
+            payload.extend_from_slice(&transition_time.to_le_bytes());
+
+            // move to level (with on/off), so setting a brightness turns the light on and 0 turns it off
+            Ok(DeviceCommand { cluster: zcl::CLUSTER_LEVEL_CONTROL, command: 0x04, payload })
+        }
+        _ => Err(format!("Attribute {} can't be set", attribute)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::converters::{DeviceCommand, from_device, to_device};
+    use crate::zcl::{self, ZclFrame};
+
+    #[test]
+    fn test_from_device() {
+        let frame = ZclFrame::parse(&[0x18, 0x01, 0x0A, 0x00, 0x00, 0x29, 0x66, 0x08]).unwrap();
+        assert_eq!(from_device(zcl::CLUSTER_TEMPERATURE_MEASUREMENT, &frame).unwrap(), [("temperature", "21.5".to_string())]);
+
+        let frame = ZclFrame::parse(&[0x18, 0x02, 0x01, 0x00, 0x00, 0x00, 0x20, 0xFE]).unwrap();
+        assert_eq!(from_device(zcl::CLUSTER_LEVEL_CONTROL, &frame).unwrap(), [("brightness", "100".to_string())]);
+
+        let frame = ZclFrame::parse(&[0x18, 0x03, 0x0A, 0x21, 0x00, 0x20, 0xB4]).unwrap();
+        assert_eq!(from_device(zcl::CLUSTER_POWER_CONFIGURATION, &frame).unwrap(), [("battery", "90".to_string())]);
+    }
+
+    #[test]
+    fn test_from_device_zone_status() {
+        let frame = ZclFrame::parse(&[0x19, 0x04, 0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00]).unwrap();
+
+        assert_eq!(from_device(zcl::CLUSTER_IAS_ZONE, &frame).unwrap(),
+                   [("alarm", "on".to_string()), ("tamper", "off".to_string())]);
+    }
+
+    #[test]
+    fn test_to_device() {
+        assert_eq!(to_device("power", "on"), Ok(DeviceCommand { cluster: zcl::CLUSTER_ON_OFF, command: 0x01, payload: vec![] }));
+        assert_eq!(to_device("brightness", "50"),
+                   Ok(DeviceCommand { cluster: zcl::CLUSTER_LEVEL_CONTROL, command: 0x04, payload: vec![127, 5, 0] }));
+        assert!(to_device("brightness", "101").is_err());
+        assert!(to_device("temperature", "20").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use log::{debug, info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::mpsc;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::znp::{commands, CommandType, Frame, FrameReader, Subsystem};
+
+const SRSP_TIMEOUT: Duration = Duration::from_secs(6);
+const RESET_TIMEOUT: Duration = Duration::from_secs(10);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(20);
+const FORMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+const ENDPOINT: u8 = 1;
+const PROFILE_HOME_AUTOMATION: u16 = 0x0104;
+const DEFAULT_RADIUS: u8 = 30;
+
+const NV_PANID: u16 = 0x0083;
+const NV_LOGICAL_TYPE: u16 = 0x0087;
+
+const DEVICE_STATE_COORDINATOR: u8 = 0x09;
+
+/// A Texas Instruments Z-Stack (ZNP) coordinator on a serial port.
+pub struct Coordinator {
+    writer: WriteHalf<SerialStream>,
+    responses: mpsc::Receiver<Frame>,
+    transaction_id: u8,
+}
+
+impl Coordinator {
+    /// Opens the serial port and returns the channel where the asynchronous indications of the
+    /// coordinator are delivered. The channel closes when the port can no longer be read.
+    pub fn open(path: &str, baud_rate: u32) -> anyhow::Result<(Self, mpsc::Receiver<Frame>)> {
+        let port = tokio_serial::new(path, baud_rate).open_native_async()
+            .with_context(|| format!("Failed to open serial port {}", path))?;
+
+        let (reader, writer) = tokio::io::split(port);
+        let (responses_sender, responses) = mpsc::channel(4);
+        let (indications_sender, indications) = mpsc::channel(64);
+
+        tokio::spawn(Self::read_frames(reader, responses_sender, indications_sender));
+
+        Ok((Self { writer, responses, transaction_id: 0 }, indications))
+    }
+
+    async fn read_frames(mut reader: ReadHalf<SerialStream>, responses: mpsc::Sender<Frame>, indications: mpsc::Sender<Frame>) {
+        let mut frames = FrameReader::default();
+        let mut buffer = [0u8; 256];
+
+        loop {
+            let length = match reader.read(&mut buffer).await {
+                Ok(0) => return,
+                Ok(length) => length,
+                Err(e) => {
+                    warn!("Failed to read from serial port: {}", e);
+                    return;
+                }
+            };
+
+            frames.push(&buffer[..length]);
+
+            while let Some(frame) = frames.next_frame() {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!("Received invalid frame: {}", e);
+                        continue;
+                    }
+                };
+
+                debug!("Received: {:?}", frame);
+
+                let sender = match frame.command_type {
+                    CommandType::Srsp => &responses,
+                    _ => &indications,
+                };
+
+                if sender.send(frame).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn write(&mut self, frame: Frame) -> anyhow::Result<()> {
+        debug!("Sending: {:?}", frame);
+        self.writer.write_all(&frame.encode()).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Sends a synchronous request and waits for its response. Z-Stack handles one at a time.
+    pub async fn request(&mut self, command: (Subsystem, u8), data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        // drop responses to requests that timed out
+        while self.responses.try_recv().is_ok() {}
+
+        self.write(Frame::new(CommandType::Sreq, command, data)).await?;
+
+        let response = tokio::time::timeout(SRSP_TIMEOUT, async {
+            while let Some(frame) = self.responses.recv().await {
+                if frame.is(CommandType::Srsp, command) {
+                    return Some(frame);
+                }
+            }
+            None
+        }).await;
+
+        match response {
+            Ok(Some(frame)) => Ok(frame.data),
+            Ok(None) => bail!("Serial port closed"),
+            Err(_) => bail!("Timed out waiting for response to {:?}", command),
+        }
+    }
+
+    /// Sends a synchronous request whose response is a single status byte.
+    pub async fn request_status(&mut self, command: (Subsystem, u8), data: Vec<u8>) -> anyhow::Result<()> {
+        let response = self.request(command, data).await?;
+
+        match response.first() {
+            Some(0) => Ok(()),
+            Some(status) => bail!("Request {:?} failed with status {:#04x}", command, status),
+            None => bail!("Empty response to {:?}", command),
+        }
+    }
+
+    /// Resets the coordinator, starts its network (forming one on the given channel if there is
+    /// none) and registers the endpoint used to talk to devices.
+    pub async fn start(&mut self, indications: &mut mpsc::Receiver<Frame>, channel: u8, pan_id: u16) -> anyhow::Result<()> {
+        self.reset(indications).await;
+
+        let version = self.request(commands::SYS_VERSION, vec![]).await.context("Coordinator is not responding")?;
+        info!("Coordinator firmware version: {:02x?}", version);
+
+        if let Err(e) = self.startup(indications).await {
+            warn!("Coordinator has no network ({}). Forming one on channel {} with pan id {:#06x}...", e, channel, pan_id);
+            self.form_network(indications, channel, pan_id).await?;
+        }
+
+        let mut register = vec![ENDPOINT];
+        register.extend_from_slice(&PROFILE_HOME_AUTOMATION.to_le_bytes());
+        register.extend_from_slice(&0x0005u16.to_le_bytes()); // configuration tool device id
+        register.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // version, latency, no input or output clusters
+
+        match self.request(commands::AF_REGISTER, register).await?.first() {
+            // 0xB8: the endpoint is already registered, which survives soft resets on some firmwares
+            Some(0x00) | Some(0xB8) => Ok(()),
+            status => bail!("Failed to register endpoint: status {:02x?}", status),
+        }
+    }
+
+    async fn reset(&mut self, indications: &mut mpsc::Receiver<Frame>) {
+        if let Err(e) = self.write(Frame::new(CommandType::Areq, commands::SYS_RESET_REQ, vec![0x01])).await {
+            warn!("Failed to reset coordinator: {}", e);
+            return;
+        }
+
+        if wait_for(indications, RESET_TIMEOUT, |frame| frame.is(CommandType::Areq, commands::SYS_RESET_IND)).await.is_err() {
+            warn!("Coordinator did not confirm the reset. Continuing anyway...");
+        }
+    }
+
+    async fn startup(&mut self, indications: &mut mpsc::Receiver<Frame>) -> anyhow::Result<()> {
+        self.request(commands::ZDO_STARTUP_FROM_APP, 100u16.to_le_bytes().to_vec()).await?;
+
+        wait_for(indications, STARTUP_TIMEOUT, |frame| {
+            frame.is(CommandType::Areq, commands::ZDO_STATE_CHANGE_IND) && frame.data.first() == Some(&DEVICE_STATE_COORDINATOR)
+        }).await.context("Coordinator did not start")
+    }
+
+    async fn write_nv(&mut self, id: u16, value: &[u8]) -> anyhow::Result<()> {
+        let mut data = id.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x00, value.len() as u8]); // offset, length
+        data.extend_from_slice(value);
+
+        self.request_status(commands::SYS_OSAL_NV_WRITE, data).await
+    }
+
+    async fn form_network(&mut self, indications: &mut mpsc::Receiver<Frame>, channel: u8, pan_id: u16) -> anyhow::Result<()> {
+        self.write_nv(NV_LOGICAL_TYPE, &[0x00]).await?;
+        self.write_nv(NV_PANID, &pan_id.to_le_bytes()).await?;
+
+        self.reset(indications).await;
+
+        let mut primary_channel = vec![0x01];
+        primary_channel.extend_from_slice(&(1u32 << channel).to_le_bytes());
+        self.request_status(commands::APP_CNF_BDB_SET_CHANNEL, primary_channel).await?;
+
+        let mut secondary_channel = vec![0x00];
+        secondary_channel.extend_from_slice(&0u32.to_le_bytes());
+        self.request_status(commands::APP_CNF_BDB_SET_CHANNEL, secondary_channel).await?;
+
+        // 0x04: network formation
+        self.request_status(commands::APP_CNF_BDB_START_COMMISSIONING, vec![0x04]).await?;
+
+        wait_for(indications, FORMATION_TIMEOUT, |frame| {
+            frame.is(CommandType::Areq, commands::ZDO_STATE_CHANGE_IND) && frame.data.first() == Some(&DEVICE_STATE_COORDINATOR)
+        }).await.context("Network formation did not complete")?;
+
+        info!("Formed network on channel {}", channel);
+        Ok(())
+    }
+
+    pub async fn permit_join(&mut self, seconds: u8) -> anyhow::Result<()> {
+        // broadcast to all routers and the coordinator itself
+        let mut data = vec![0x0F];
+        data.extend_from_slice(&0xFFFCu16.to_le_bytes());
+        data.extend_from_slice(&[seconds, 0x00]);
+
+        self.request_status(commands::ZDO_MGMT_PERMIT_JOIN_REQ, data).await
+    }
+
+    /// Asks a device for its IEEE address. The answer arrives as a `ZDO_IEEE_ADDR_RSP` indication.
+    pub async fn request_ieee_address(&mut self, network_address: u16) -> anyhow::Result<()> {
+        let mut data = network_address.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x00, 0x00]); // single device response, start index
+
+        self.request_status(commands::ZDO_IEEE_ADDR_REQ, data).await
+    }
+
+    pub async fn send_zcl(&mut self, network_address: u16, endpoint: u8, cluster: u16, zcl: &[u8]) -> anyhow::Result<()> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+
+        let mut data = network_address.to_le_bytes().to_vec();
+        data.extend_from_slice(&[endpoint, ENDPOINT]);
+        data.extend_from_slice(&cluster.to_le_bytes());
+        data.extend_from_slice(&[self.transaction_id, 0x00, DEFAULT_RADIUS, zcl.len() as u8]);
+        data.extend_from_slice(zcl);
+
+        self.request_status(commands::AF_DATA_REQUEST, data).await
+    }
+}
+
+async fn wait_for(indications: &mut mpsc::Receiver<Frame>, timeout: Duration, predicate: impl Fn(&Frame) -> bool) -> anyhow::Result<()> {
+    tokio::time::timeout(timeout, async {
+        while let Some(frame) = indications.recv().await {
+            if predicate(&frame) {
+                return Ok(());
+            }
+        }
+        bail!("Serial port closed")
+    }).await.context("Timed out")?
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// The devices that joined the network, keyed by IEEE address formatted as `0x00158d0001a2b3c4`.
+/// Persisted so that messages from devices can be attributed after a restart, since devices
+/// only announce themselves when they join or rejoin.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Database {
+    #[serde(skip)]
+    path: PathBuf,
+    devices: HashMap<String, DeviceRecord>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceRecord {
+    pub network_address: u16,
+    /// The endpoint implementing each cluster, learned from the messages of the device.
+    #[serde(default)]
+    pub endpoints: HashMap<u16, u8>,
+}
+
+pub fn format_ieee_address(address: u64) -> String {
+    format!("{:#018x}", address)
+}
+
+impl Database {
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let mut database: Database = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).context(format!("Invalid device database {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Database::default(),
+            Err(e) => return Err(e).context(format!("Failed to read device database {:?}", path)),
+        };
+
+        database.path = path;
+        Ok(database)
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(&self.path, content).context(format!("Failed to write device database {:?}", self.path))
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = (&String, &DeviceRecord)> {
+        self.devices.iter()
+    }
+
+    pub fn get(&self, ieee_address: &str) -> Option<&DeviceRecord> {
+        self.devices.get(ieee_address)
+    }
+
+    pub fn find_by_network_address(&self, network_address: u16) -> Option<&str> {
+        self.devices.iter()
+            .find(|(_, device)| device.network_address == network_address)
+            .map(|(ieee_address, _)| ieee_address.as_str())
+    }
+
+    /// Records a device joining or changing its network address. Returns whether anything changed.
+    pub fn upsert(&mut self, ieee_address: String, network_address: u16) -> bool {
+        match self.devices.get_mut(&ieee_address) {
+            Some(device) if device.network_address == network_address => false,
+            Some(device) => {
+                device.network_address = network_address;
+                true
+            }
+            None => {
+                self.devices.insert(ieee_address, DeviceRecord { network_address, endpoints: HashMap::new() });
+                true
+            }
+        }
+    }
+
+    /// Returns whether anything changed.
+    pub fn record_endpoint(&mut self, ieee_address: &str, cluster: u16, endpoint: u8) -> bool {
+        let Some(device) = self.devices.get_mut(ieee_address) else { return false };
+        device.endpoints.insert(cluster, endpoint) != Some(endpoint)
+    }
+
+    pub fn remove(&mut self, ieee_address: &str) -> bool {
+        self.devices.remove(ieee_address).is_some()
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use log::info;
+
+use crate::application::Application;
+use crate::config::Config;
+use crate::coordinator::Coordinator;
+use crate::database::Database;
+use crate::mqtt::connect_mqtt;
+
+mod znp;
+mod zcl;
+mod converters;
+mod coordinator;
+mod database;
+mod config;
+mod application;
+mod mqtt;
+
+const MQTT_TOPIC_PREFIX: &str = "smart-home-system/zigbee";
+const MQTT_SET_TOPIC: &str = "smart-home-system/zigbee/+/+/set";
+const MQTT_GET_TOPIC: &str = "smart-home-system/zigbee/+/+/get";
+const MQTT_PERMIT_JOIN_TOPIC: &str = "smart-home-system/zigbee/admin/permit_join";
+const MQTT_DEVICES_PUBLISH_TOPIC: &str = "smart-home-system/zigbee/devices";
+const MQTT_ERROR_TOPIC: &str = "smart-home-system/zigbee/error";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+
+    let subscribe_topics = [MQTT_SET_TOPIC, MQTT_GET_TOPIC, MQTT_PERMIT_JOIN_TOPIC];
+
+    let mqtt_server_uri = std::env::var("MQTT_SERVER_URI")
+        .context("No mqtt server uri provided. Set env MQTT_SERVER_URI to the uri of the mqtt server.")?;
+
+    let config_path: PathBuf = std::env::var("ZIGBEE_CONFIG").unwrap_or_else(|_| "zigbee.yaml".into()).into();
+    let config = Config::load(&config_path)?;
+
+    let database = Database::load(config.database_path.clone().into())?;
+
+    let (mut coordinator, mut indications) = Coordinator::open(&config.serial_port, config.baud_rate)?;
+
+    info!("Starting zigbee coordinator on {}...", config.serial_port);
+    coordinator.start(&mut indications, config.channel, config.pan_id).await
+        .context("Failed to start zigbee coordinator")?;
+
+    let (client, stream) = connect_mqtt(
+        &subscribe_topics,
+        mqtt_server_uri,
+        std::env::var("MQTT_USERNAME").ok(),
+        std::env::var("MQTT_PASSWORD").ok(),
+    ).await.context("Failed to connect to mqtt server")?;
+
+    info!("Starting zigbee controller");
+
+    let mut application = Application::new(client, coordinator, database, config.devices);
+
+    info!("Waiting for mqtt messages...");
+
+    loop {
+        tokio::select! {
+            message = stream.recv() => {
+                let Ok(message) = message else { break };
+
+                if let Some(message) = message {
+                    match message.topic() {
+                        MQTT_PERMIT_JOIN_TOPIC => application.handle_mqtt_permit_join(&message).await,
+                        _ => application.handle_mqtt_message(&message).await,
+                    }
+                }
+            }
+            indication = indications.recv() => {
+                let Some(indication) = indication else {
+                    anyhow::bail!("Lost connection to the zigbee coordinator");
+                };
+
+                application.handle_indication(indication).await;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use anyhow::bail;
+
+use crate::znp::Reader;
+
+pub const CLUSTER_POWER_CONFIGURATION: u16 = 0x0001;
+pub const CLUSTER_ON_OFF: u16 = 0x0006;
+pub const CLUSTER_LEVEL_CONTROL: u16 = 0x0008;
+pub const CLUSTER_TEMPERATURE_MEASUREMENT: u16 = 0x0402;
+pub const CLUSTER_RELATIVE_HUMIDITY: u16 = 0x0405;
+pub const CLUSTER_OCCUPANCY_SENSING: u16 = 0x0406;
+pub const CLUSTER_IAS_ZONE: u16 = 0x0500;
+
+pub const COMMAND_READ_ATTRIBUTES: u8 = 0x00;
+pub const COMMAND_READ_ATTRIBUTES_RESPONSE: u8 = 0x01;
+pub const COMMAND_REPORT_ATTRIBUTES: u8 = 0x0A;
+
+const FRAME_TYPE_CLUSTER_SPECIFIC: u8 = 0x01;
+const MANUFACTURER_SPECIFIC: u8 = 0x04;
+const DIRECTION_SERVER_TO_CLIENT: u8 = 0x08;
+const DISABLE_DEFAULT_RESPONSE: u8 = 0x10;
+
+#[derive(Debug, PartialEq)]
+pub struct ZclFrame {
+    pub cluster_specific: bool,
+    pub manufacturer_code: Option<u16>,
+    pub sequence: u8,
+    pub command: u8,
+    pub payload: Vec<u8>,
+}
+
+impl ZclFrame {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(data);
+
+        let frame_control = reader.u8()?;
+        let manufacturer_code = match frame_control & MANUFACTURER_SPECIFIC != 0 {
+            true => Some(reader.u16()?),
+            false => None,
+        };
+
+        Ok(Self {
+            cluster_specific: frame_control & 0x03 == FRAME_TYPE_CLUSTER_SPECIFIC,
+            manufacturer_code,
+            sequence: reader.u8()?,
+            command: reader.u8()?,
+            payload: reader.rest().to_vec(),
+        })
+    }
+
+    /// A command sent by the coordinator (client) to a device (server).
+    pub fn encode_client_command(cluster_specific: bool, sequence: u8, command: u8, payload: &[u8]) -> Vec<u8> {
+        let frame_type = if cluster_specific { FRAME_TYPE_CLUSTER_SPECIFIC } else { 0 };
+
+        let mut buffer = vec![frame_type | DISABLE_DEFAULT_RESPONSE, sequence, command];
+        buffer.extend_from_slice(payload);
+        buffer
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+    Float(f32),
+    String(String),
+}
+
+impl AttributeValue {
+    pub fn parse(data_type: u8, reader: &mut Reader) -> anyhow::Result<Self> {
+        let unsigned = |reader: &mut Reader, length: usize| -> anyhow::Result<u64> {
+            let mut bytes = [0u8; 8];
+            bytes[..length].copy_from_slice(reader.bytes(length)?);
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        let signed = |reader: &mut Reader, length: usize| -> anyhow::Result<i64> {
+            let value = unsigned(reader, length)?;
+            let shift = 64 - length * 8;
+            Ok(((value << shift) as i64) >> shift)
+        };
+
+        Ok(match data_type {
+            0x10 => AttributeValue::Bool(reader.u8()? != 0),
+            // data, bitmap, unsigned integer and enumeration types
+            0x08..=0x0F => AttributeValue::Unsigned(unsigned(reader, (data_type - 0x07) as usize)?),
+            0x18..=0x1F => AttributeValue::Unsigned(unsigned(reader, (data_type - 0x17) as usize)?),
+            0x20..=0x27 => AttributeValue::Unsigned(unsigned(reader, (data_type - 0x1F) as usize)?),
+            0x28..=0x2F => AttributeValue::Signed(signed(reader, (data_type - 0x27) as usize)?),
+            0x30 => AttributeValue::Unsigned(reader.u8()? as u64),
+            0x31 => AttributeValue::Unsigned(reader.u16()? as u64),
+            0x39 => AttributeValue::Float(f32::from_le_bytes(reader.bytes(4)?.try_into()?)),
+            0x41 | 0x42 => {
+                let length = reader.u8()? as usize;
+                AttributeValue::String(String::from_utf8_lossy(reader.bytes(length)?).into_owned())
+            }
+            _ => bail!("Unsupported attribute data type {:#04x}", data_type),
+        })
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            AttributeValue::Bool(value) => Some(*value as u8 as f64),
+            AttributeValue::Unsigned(value) => Some(*value as f64),
+            AttributeValue::Signed(value) => Some(*value as f64),
+            AttributeValue::Float(value) => Some(*value as f64),
+            AttributeValue::String(_) => None,
+        }
+    }
+}
+
+/// Parses the records of a report attributes command.
+pub fn parse_report(payload: &[u8]) -> anyhow::Result<Vec<(u16, AttributeValue)>> {
+    let mut reader = Reader::new(payload);
+    let mut attributes = Vec::new();
+
+    while !reader.is_empty() {
+        let attribute = reader.u16()?;
+        let data_type = reader.u8()?;
+        attributes.push((attribute, AttributeValue::parse(data_type, &mut reader)?));
+    }
+
+    Ok(attributes)
+}
+
+/// Parses the records of a read attributes response, skipping attributes the device doesn't support.
+pub fn parse_read_response(payload: &[u8]) -> anyhow::Result<Vec<(u16, AttributeValue)>> {
+    let mut reader = Reader::new(payload);
+    let mut attributes = Vec::new();
+
+    while !reader.is_empty() {
+        let attribute = reader.u16()?;
+        if reader.u8()? != 0 {
+            continue;
+        }
+
+        let data_type = reader.u8()?;
+        attributes.push((attribute, AttributeValue::parse(data_type, &mut reader)?));
+    }
+
+    Ok(attributes)
+}
+
+pub fn read_attributes_payload(attributes: &[u16]) -> Vec<u8> {
+    attributes.iter().flat_map(|attribute| attribute.to_le_bytes()).collect()
+}
+
+/// Whether a frame was sent by a device rather than by another client.
+pub fn is_from_server(data: &[u8]) -> bool {
+    data.first().is_some_and(|frame_control| frame_control & DIRECTION_SERVER_TO_CLIENT != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::zcl::{AttributeValue, parse_read_response, parse_report, ZclFrame};
+
+    #[test]
+    fn test_parse_frame() {
+        let frame = ZclFrame::parse(&[0x18, 0x05, 0x0A, 0x00, 0x00, 0x10, 0x01]).unwrap();
+
+        assert!(!frame.cluster_specific);
+        assert_eq!(frame.sequence, 5);
+        assert_eq!(frame.command, 0x0A);
+        assert_eq!(frame.payload, [0x00, 0x00, 0x10, 0x01]);
+
+        let frame = ZclFrame::parse(&[0x1D, 0x5F, 0x11, 0x01, 0x02]).unwrap();
+        assert!(frame.cluster_specific);
+        assert_eq!(frame.manufacturer_code, Some(0x115F));
+        assert_eq!(frame.command, 0x02);
+    }
+
+    #[test]
+    fn test_parse_report() {
+        // temperature 21.50 °C as int16 and an occupancy bitmap8
+        let attributes = parse_report(&[0x00, 0x00, 0x29, 0x66, 0x08, 0x01, 0x00, 0x18, 0x01]).unwrap();
+        assert_eq!(attributes, [(0x0000, AttributeValue::Signed(2150)), (0x0001, AttributeValue::Unsigned(1))]);
+
+        let attributes = parse_report(&[0x00, 0x00, 0x29, 0x38, 0xFE]).unwrap();
+        assert_eq!(attributes, [(0x0000, AttributeValue::Signed(-456))]);
+
+        assert!(parse_report(&[0x00, 0x00, 0x29, 0x66]).is_err());
+    }
+
+    #[test]
+    fn test_parse_read_response() {
+        // on/off is on, level is unsupported (status 0x86)
+        let attributes = parse_read_response(&[0x00, 0x00, 0x00, 0x10, 0x01, 0x00, 0x40, 0x86]).unwrap();
+        assert_eq!(attributes, [(0x0000, AttributeValue::Bool(true))]);
+    }
+
+    #[test]
+    fn test_encode_client_command() {
+        assert_eq!(ZclFrame::encode_client_command(true, 7, 0x01, &[]), [0x11, 0x07, 0x01]);
+    }
+}
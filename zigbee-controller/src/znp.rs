@@ -0,0 +1,270 @@
+use anyhow::bail;
+
+/// Start of frame of the Z-Stack monitor and test (MT) serial protocol.
+const SOF: u8 = 0xFE;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandType {
+    /// Synchronous request, answered by exactly one `Srsp`.
+    Sreq,
+    /// Asynchronous request or indication.
+    Areq,
+    Srsp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subsystem {
+    Sys = 0x01,
+    Af = 0x04,
+    Zdo = 0x05,
+    Util = 0x07,
+    AppConfig = 0x0F,
+}
+
+pub mod commands {
+    use crate::znp::Subsystem;
+
+    pub const SYS_RESET_REQ: (Subsystem, u8) = (Subsystem::Sys, 0x00);
+    pub const SYS_VERSION: (Subsystem, u8) = (Subsystem::Sys, 0x02);
+    pub const SYS_OSAL_NV_WRITE: (Subsystem, u8) = (Subsystem::Sys, 0x09);
+    pub const SYS_RESET_IND: (Subsystem, u8) = (Subsystem::Sys, 0x80);
+
+    pub const AF_REGISTER: (Subsystem, u8) = (Subsystem::Af, 0x00);
+    pub const AF_DATA_REQUEST: (Subsystem, u8) = (Subsystem::Af, 0x01);
+    pub const AF_DATA_CONFIRM: (Subsystem, u8) = (Subsystem::Af, 0x80);
+    pub const AF_INCOMING_MSG: (Subsystem, u8) = (Subsystem::Af, 0x81);
+
+    pub const ZDO_IEEE_ADDR_REQ: (Subsystem, u8) = (Subsystem::Zdo, 0x01);
+    pub const ZDO_MGMT_PERMIT_JOIN_REQ: (Subsystem, u8) = (Subsystem::Zdo, 0x36);
+    pub const ZDO_STARTUP_FROM_APP: (Subsystem, u8) = (Subsystem::Zdo, 0x40);
+    pub const ZDO_IEEE_ADDR_RSP: (Subsystem, u8) = (Subsystem::Zdo, 0x81);
+    pub const ZDO_STATE_CHANGE_IND: (Subsystem, u8) = (Subsystem::Zdo, 0xC0);
+    pub const ZDO_END_DEVICE_ANNCE_IND: (Subsystem, u8) = (Subsystem::Zdo, 0xC1);
+    pub const ZDO_LEAVE_IND: (Subsystem, u8) = (Subsystem::Zdo, 0xC9);
+    pub const ZDO_TC_DEV_IND: (Subsystem, u8) = (Subsystem::Zdo, 0xCA);
+
+    pub const APP_CNF_BDB_START_COMMISSIONING: (Subsystem, u8) = (Subsystem::AppConfig, 0x05);
+    pub const APP_CNF_BDB_SET_CHANNEL: (Subsystem, u8) = (Subsystem::AppConfig, 0x08);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub command_type: CommandType,
+    pub subsystem: Subsystem,
+    pub command: u8,
+    pub data: Vec<u8>,
+}
+
+fn fcs(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |fcs, byte| fcs ^ byte)
+}
+
+impl Frame {
+    pub fn new(command_type: CommandType, (subsystem, command): (Subsystem, u8), data: Vec<u8>) -> Self {
+        Self { command_type, subsystem, command, data }
+    }
+
+    pub fn is(&self, command_type: CommandType, (subsystem, command): (Subsystem, u8)) -> bool {
+        self.command_type == command_type && self.subsystem == subsystem && self.command == command
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let command_type = match self.command_type {
+            CommandType::Sreq => 0x20,
+            CommandType::Areq => 0x40,
+            CommandType::Srsp => 0x60,
+        };
+
+        let mut buffer = vec![SOF, self.data.len() as u8, command_type | self.subsystem as u8, self.command];
+        buffer.extend_from_slice(&self.data);
+        buffer.push(fcs(&buffer[1..]));
+        buffer
+    }
+
+    fn decode(cmd0: u8, cmd1: u8, data: &[u8]) -> anyhow::Result<Self> {
+        let command_type = match cmd0 & 0xE0 {
+            0x20 => CommandType::Sreq,
+            0x40 => CommandType::Areq,
+            0x60 => CommandType::Srsp,
+            other => bail!("Unknown command type {:#04x}", other),
+        };
+
+        let subsystem = match cmd0 & 0x1F {
+            0x01 => Subsystem::Sys,
+            0x04 => Subsystem::Af,
+            0x05 => Subsystem::Zdo,
+            0x07 => Subsystem::Util,
+            0x0F => Subsystem::AppConfig,
+            other => bail!("Unsupported subsystem {:#04x}", other),
+        };
+
+        Ok(Self { command_type, subsystem, command: cmd1, data: data.to_vec() })
+    }
+}
+
+/// Extracts frames from the bytes read from the serial port, skipping garbage and frames with a bad checksum.
+#[derive(Default)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn next_frame(&mut self) -> Option<anyhow::Result<Frame>> {
+        let Some(start) = self.buffer.iter().position(|byte| *byte == SOF) else {
+            self.buffer.clear();
+            return None;
+        };
+        self.buffer.drain(..start);
+
+        let length = *self.buffer.get(1)? as usize;
+        let frame_length = 5 + length;
+
+        if self.buffer.len() < frame_length {
+            return None;
+        }
+
+        let frame: Vec<u8> = self.buffer.drain(..frame_length).collect();
+
+        if fcs(&frame[1..frame_length - 1]) != frame[frame_length - 1] {
+            return Some(Err(anyhow::anyhow!("Frame checksum mismatch: {:02x?}", frame)));
+        }
+
+        Some(Frame::decode(frame[2], frame[3], &frame[4..frame_length - 1]))
+    }
+}
+
+/// Little-endian reader for the fields of frame payloads.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn bytes(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        let Some(bytes) = self.data.get(self.position..self.position + length) else {
+            bail!("Payload is too short: wanted {} bytes at {} of {}", length, self.position, self.data.len());
+        };
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> anyhow::Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    pub fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    pub fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    pub fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.data[self.position.min(self.data.len())..];
+        self.position = self.data.len();
+        rest
+    }
+}
+
+/// An incoming `AF_INCOMING_MSG` indication.
+#[derive(Debug, PartialEq)]
+pub struct IncomingMessage {
+    pub cluster_id: u16,
+    pub source_address: u16,
+    pub source_endpoint: u8,
+    pub link_quality: u8,
+    pub data: Vec<u8>,
+}
+
+impl IncomingMessage {
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(data);
+
+        let _group_id = reader.u16()?;
+        let cluster_id = reader.u16()?;
+        let source_address = reader.u16()?;
+        let source_endpoint = reader.u8()?;
+        let _destination_endpoint = reader.u8()?;
+        let _was_broadcast = reader.u8()?;
+        let link_quality = reader.u8()?;
+        let _security_use = reader.u8()?;
+        let _timestamp = reader.u32()?;
+        let _transaction_sequence = reader.u8()?;
+        let length = reader.u8()? as usize;
+        let data = reader.bytes(length)?.to_vec();
+
+        Ok(Self { cluster_id, source_address, source_endpoint, link_quality, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::znp::{commands, CommandType, Frame, FrameReader, IncomingMessage, Subsystem};
+
+    #[test]
+    fn test_encode_frame() {
+        let frame = Frame::new(CommandType::Sreq, (Subsystem::Sys, 0x01), vec![]);
+        assert_eq!(frame.encode(), [0xFE, 0x00, 0x21, 0x01, 0x20]);
+
+        let frame = Frame::new(CommandType::Areq, commands::SYS_RESET_REQ, vec![0x01]);
+        assert_eq!(frame.encode(), [0xFE, 0x01, 0x41, 0x00, 0x01, 0x41]);
+    }
+
+    #[test]
+    fn test_frame_reader() {
+        let mut reader = FrameReader::default();
+
+        // garbage, a ping response split in two reads, then a frame with a broken checksum
+        reader.push(&[0x00, 0x13, 0xFE, 0x02, 0x61]);
+        assert!(reader.next_frame().is_none());
+
+        reader.push(&[0x01, 0x79, 0x01, 0x1A, 0xFE, 0x00, 0x21, 0x01, 0x00]);
+
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert!(frame.is(CommandType::Srsp, (Subsystem::Sys, 0x01)));
+        assert_eq!(frame.data, [0x79, 0x01]);
+
+        assert!(reader.next_frame().unwrap().is_err());
+        assert!(reader.next_frame().is_none());
+    }
+
+    #[test]
+    fn test_parse_incoming_message() {
+        let data = [
+            0x00, 0x00, // group id
+            0x06, 0x00, // cluster id
+            0x34, 0x12, // source address
+            0x01, 0x01, 0x00, 0x73, 0x00, // endpoints, broadcast, link quality, security
+            0x00, 0x00, 0x00, 0x00, 0x05, // timestamp, sequence
+            0x03, 0x18, 0x01, 0x0A, // zcl payload
+            0x34, 0x12, 0x1E, // mac source address and radius
+        ];
+
+        assert_eq!(IncomingMessage::parse(&data).unwrap(), IncomingMessage {
+            cluster_id: 0x0006,
+            source_address: 0x1234,
+            source_endpoint: 1,
+            link_quality: 0x73,
+            data: vec![0x18, 0x01, 0x0A],
+        });
+
+        assert!(IncomingMessage::parse(&data[..18]).is_err());
+    }
+}